@@ -113,7 +113,9 @@ impl OrderManager {
                 match order.order_type {
                     OrdType::Limit => "Limit".to_string(),
                     OrdType::Market => "Market".to_string(),
-                    OrdType::Unsupported => return Err(BybitError::InvalidArg("order_type")),
+                    OrdType::StopMarket | OrdType::Unsupported => {
+                        return Err(BybitError::InvalidArg("order_type"));
+                    }
                 }
             }),
             qty: Some(format!("{:.5}", order.qty)),
@@ -320,11 +320,7 @@ pub fn build_asset(input: TokenStream) -> TokenStream {
                                 order_e2l,
                             ));
 
-                            Asset {
-                                local,
-                                exch,
-                                reader
-                            }
+                            Asset::from_boxed(local, exch, reader)
                         },
                     });
                     }
@@ -35,12 +35,12 @@ where
         let depth = hbt.depth(0);
         let position = hbt.position(0);
 
-        if depth.best_bid_tick() == INVALID_MIN || depth.best_ask_tick() == INVALID_MAX {
+        let (Some(best_bid), Some(best_ask)) = (depth.try_best_bid(), depth.try_best_ask()) else {
             // Market depth is incomplete.
             continue;
-        }
+        };
 
-        let mid_price = (depth.best_bid() + depth.best_ask()) as f64 / 2.0;
+        let mid_price = (best_bid + best_ask) / 2.0;
 
         let normalized_position = position / order_qty;
 
@@ -49,10 +49,8 @@ where
         let alpha = 0.0;
         let forecast_mid_price = mid_price + alpha;
 
-        let bid_price =
-            (forecast_mid_price * (1.0 - relative_bid_depth)).min(depth.best_bid() as f64);
-        let ask_price =
-            (forecast_mid_price * (1.0 + relative_ask_depth)).max(depth.best_ask() as f64);
+        let bid_price = (forecast_mid_price * (1.0 - relative_bid_depth)).min(best_bid);
+        let ask_price = (forecast_mid_price * (1.0 + relative_ask_depth)).max(best_ask);
 
         // min_grid_step enforces grid interval changes to be no less than min_grid_step, which
         // stabilizes the grid_interval and keeps the orders on the grid more stable.
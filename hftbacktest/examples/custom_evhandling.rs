@@ -17,11 +17,11 @@ use hftbacktest::{
             TradingValueFeeModel,
         },
         order::order_bus,
-        proc::{Local, LocalProcessor, NoPartialFillExchange, Processor},
-        state::State,
+        proc::{Local, LocalProcessor, NoPartialFillExchange, OrderOptions, Processor},
+        state::{PnlSummary, State},
     },
     depth::{HashMapMarketDepth, L2MarketDepth, MarketDepth},
-    prelude::{Bot, Event, OrdType, Order, OrderId, Side, StateValues, TimeInForce},
+    prelude::{AssetParams, Bot, Event, OrdType, Order, OrderId, Side, StateValues, TimeInForce},
 };
 
 /// Handling tick events and order response events through the event handler approach requires
@@ -53,6 +53,7 @@ where
         qty: f64,
         order_type: OrdType,
         time_in_force: TimeInForce,
+        options: OrderOptions,
         current_timestamp: i64,
     ) -> Result<(), BacktestError> {
         self.local.submit_order(
@@ -62,6 +63,7 @@ where
             qty,
             order_type,
             time_in_force,
+            options,
             current_timestamp,
         )
     }
@@ -92,6 +94,14 @@ where
         self.local.state_values()
     }
 
+    fn asset_params(&self) -> AssetParams {
+        self.local.asset_params()
+    }
+
+    fn pnl(&self, timestamp: i64) -> PnlSummary {
+        self.local.pnl(timestamp)
+    }
+
     fn depth(&self) -> &MD {
         self.local.depth()
     }
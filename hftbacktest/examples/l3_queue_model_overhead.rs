@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+use hftbacktest::{
+    backtest::models::{L3FIFOQueueModel, L3QueueModel, L3TouchQueueModel},
+    depth::{HashMapMarketDepth, L3MarketDepth, MarketDepth},
+    types::{
+        ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, Event, FILL_EVENT, OrdType, Order, OrderId,
+        SELL_EVENT, Side, TimeInForce,
+    },
+};
+
+const NUM_EVENTS: usize = 1_000_000;
+const NUM_LEVELS: u64 = 50;
+const MID_TICK: i64 = 10_000;
+
+/// A tiny linear congruential generator so that both queue models see the identical event stream
+/// without depending on an external crate.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+fn feed_event(ev: u64, order_id: OrderId, price_tick: i64) -> Event {
+    Event {
+        ev: EXCH_EVENT | ev,
+        exch_ts: 0,
+        local_ts: 0,
+        px: price_tick as f64,
+        qty: 1.0,
+        order_id,
+        ival: 0,
+        fval: 0.0,
+    }
+}
+
+/// Replays a synthetic Market-By-Order stream spanning `NUM_LEVELS` levels on each side while
+/// keeping a backtest order at the best bid and ask, and returns the elapsed time.
+fn run<QM>(mut qm: QM) -> Duration
+where
+    QM: L3QueueModel<HashMapMarketDepth>,
+{
+    let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+    let mut rng = Lcg(42);
+    let mut live: Vec<(OrderId, Side, i64)> = Vec::new();
+    let mut next_order_id: OrderId = 1;
+    let mut bt_bid: Option<OrderId> = None;
+    let mut bt_ask: Option<OrderId> = None;
+
+    let start = Instant::now();
+    for _ in 0..NUM_EVENTS {
+        let action = rng.next() % 10;
+        if action < 5 || live.is_empty() {
+            let offset = (rng.next() % NUM_LEVELS) as i64 + 1;
+            let order_id = next_order_id;
+            next_order_id += 1;
+            let (ev, side, price_tick) = if rng.next() % 2 == 0 {
+                (BUY_EVENT | ADD_ORDER_EVENT, Side::Buy, MID_TICK - offset)
+            } else {
+                (SELL_EVENT | ADD_ORDER_EVENT, Side::Sell, MID_TICK + offset)
+            };
+            let ev = feed_event(ev, order_id, price_tick);
+            match side {
+                Side::Buy => depth.add_buy_order(order_id, ev.px, ev.qty, 0).unwrap(),
+                _ => depth.add_sell_order(order_id, ev.px, ev.qty, 0).unwrap(),
+            };
+            qm.add_market_feed_order(&ev, &depth).unwrap();
+            live.push((order_id, side, price_tick));
+        } else {
+            let (order_id, side, price_tick) = live.swap_remove((rng.next() as usize) % live.len());
+            if action < 8 {
+                depth.delete_order(order_id, 0).unwrap();
+                qm.cancel_market_feed_order(order_id, &depth).unwrap();
+            } else {
+                let side_ev = if side == Side::Buy {
+                    BUY_EVENT
+                } else {
+                    SELL_EVENT
+                };
+                let ev = feed_event(side_ev | FILL_EVENT, order_id, price_tick);
+                for order in qm
+                    .fill_market_feed_order::<false>(order_id, &ev, &depth)
+                    .unwrap()
+                {
+                    if Some(order.order_id) == bt_bid {
+                        bt_bid = None;
+                    } else if Some(order.order_id) == bt_ask {
+                        bt_ask = None;
+                    }
+                }
+                depth.delete_order(order_id, 0).unwrap();
+                qm.cancel_market_feed_order(order_id, &depth).unwrap();
+            }
+        }
+
+        // Quotes at the touch.
+        if bt_bid.is_none() && depth.best_bid_tick() < MID_TICK {
+            let order_id = next_order_id;
+            next_order_id += 1;
+            let order = Order::new(
+                order_id,
+                depth.best_bid_tick(),
+                1.0,
+                1.0,
+                Side::Buy,
+                OrdType::Limit,
+                TimeInForce::GTX,
+            );
+            qm.add_backtest_order(order, &depth).unwrap();
+            bt_bid = Some(order_id);
+        }
+        if bt_ask.is_none() && depth.best_ask_tick() > MID_TICK {
+            let order_id = next_order_id;
+            next_order_id += 1;
+            let order = Order::new(
+                order_id,
+                depth.best_ask_tick(),
+                1.0,
+                1.0,
+                Side::Sell,
+                OrdType::Limit,
+                TimeInForce::GTX,
+            );
+            qm.add_backtest_order(order, &depth).unwrap();
+            bt_ask = Some(order_id);
+        }
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let fifo = run(L3FIFOQueueModel::new());
+    let touch = run(L3TouchQueueModel::new());
+    println!("{NUM_EVENTS} events over {NUM_LEVELS} levels on each side");
+    println!("L3FIFOQueueModel : {fifo:?}");
+    println!("L3TouchQueueModel: {touch:?}");
+}
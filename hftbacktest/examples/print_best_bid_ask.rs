@@ -1,4 +1,3 @@
-use std::cmp;
 use std::fmt::Debug;
 
 use hftbacktest::{
@@ -8,6 +7,7 @@ use hftbacktest::{
         models::{CommonFees, ConstantLatency, L3FIFOQueueModel, TradingQtyFeeModel},
     },
     depth::{MarketDepth, ROIVectorMarketDepth},
+    prelude::format_price,
     types::{Bot, ElapseResult, Event},
 };
 
@@ -21,10 +21,10 @@ where
         let depth = hbt.depth(0);
 
         println!(
-            "{} , best bid: {:.2} , best ask: {:.2}",
+            "{} , best bid: {} , best ask: {}",
             hbt.current_timestamp(),
-            depth.best_bid(),
-            depth.best_ask()
+            format_price(depth.tick_size(), depth.best_bid()),
+            format_price(depth.tick_size(), depth.best_ask())
         );
 
         // 输出到开盘
@@ -58,51 +58,29 @@ where
         println!("best bid {:?}", depth.best_bid());
 
         // 收集卖盘数据（卖三、卖二、卖一）
-        let mut asks = Vec::new();
-        let mut i = 0;
-        for tick_price in depth.best_ask_tick()..=(depth.best_ask_tick() as f64 * 1.1) as i64 {
-            let qty = depth.ask_qty_at_tick(tick_price);
-            if qty > 0.0 {
-                asks.push((tick_price, qty));
-                i += 1;
-                if i == 5 {
-                    break;
-                }
-            }
-        }
+        let mut asks = depth.ask_levels(5);
 
         // 倒序打印卖盘（从卖三到卖一）
         asks.reverse();
         for (idx, (tick_price, qty)) in asks.iter().enumerate() {
             println!(
-                "卖{} {:>10.2} @ {:>10.2}",
+                "卖{} {:>10.2} @ {:>10}",
                 5 - idx,
                 qty,
-                *tick_price as f64 * depth.tick_size()
+                format_price(depth.tick_size(), *tick_price as f64 * depth.tick_size())
             );
         }
 
         println!("----------------------------");
 
         // 打印买盘（买一、买二、买三）
-        let mut i = 0;
-        for tick_price in ((cmp::max((depth.best_bid_tick() as f64 * 0.9) as i64, 0))
-            ..=depth.best_bid_tick())
-            .rev()
-        {
-            let qty = depth.bid_qty_at_tick(tick_price);
-            if qty > 0.0 {
-                println!(
-                    "买{} {:>10.2} @ {:>10.2}",
-                    i + 1,
-                    qty,
-                    tick_price as f64 * depth.tick_size()
-                );
-                i += 1;
-                if i == 5 {
-                    break;
-                }
-            }
+        for (i, (tick_price, qty)) in depth.bid_levels(5).into_iter().enumerate() {
+            println!(
+                "买{} {:>10.2} @ {:>10}",
+                i + 1,
+                qty,
+                format_price(depth.tick_size(), tick_price as f64 * depth.tick_size())
+            );
         }
         println!("============================\n");
         // println!("{:?} @ 343.80", depth.bid_qty_at_tick(34380 as i64));
@@ -0,0 +1,300 @@
+use std::collections::VecDeque;
+
+use crate::{
+    backtest::opportunitycost::mid,
+    depth::MarketDepth,
+    types::{Order, OrderId, Side},
+};
+
+/// The mid price move over the horizon after a maker fill, measuring how much the market moves
+/// against the fill shortly after it.
+#[derive(Clone, Debug)]
+pub struct AdverseSelection {
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The executed price.
+    pub price: f64,
+    /// The executed quantity.
+    pub qty: f64,
+    /// The time at which the local receives the fill.
+    pub fill_ts: i64,
+    /// The mid price in the local's market depth when the fill is received. `NaN` if either side
+    /// of the market depth is empty.
+    pub fill_mid: f64,
+    /// The mid price in the local's market depth at the end of the horizon. `NaN` if either side
+    /// of the market depth is empty.
+    pub horizon_mid: f64,
+}
+
+impl AdverseSelection {
+    /// Returns the mid price move over the horizon in the fill's favor. A negative value means
+    /// that the mid price falls after a buy fill or rises after a sell fill, that is, the maker
+    /// was picked off.
+    pub fn mid_move(&self) -> f64 {
+        match self.side {
+            Side::Buy => self.horizon_mid - self.fill_mid,
+            Side::Sell => self.fill_mid - self.horizon_mid,
+            Side::None | Side::Unsupported => f64::NAN,
+        }
+    }
+}
+
+/// The aggregate of the [`AdverseSelection`]s over a run. The fills without a valid mid price at
+/// the fill or the end of the horizon are counted but excluded from the aggregate.
+#[derive(Clone, Debug, Default)]
+pub struct AdverseSelectionSummary {
+    pub num_fills: usize,
+    /// The total executed quantity of the aggregated fills.
+    pub qty: f64,
+    /// The average mid price move in the fills' favor, weighted by the executed quantity. A
+    /// negative value means that the maker fills are adversely selected on average. See
+    /// [`AdverseSelection::mid_move`].
+    pub vw_mid_move: f64,
+}
+
+impl AdverseSelectionSummary {
+    /// Aggregates the given adverse selection records.
+    pub fn new(records: &[AdverseSelection]) -> Self {
+        let mut summary = Self {
+            num_fills: records.len(),
+            ..Default::default()
+        };
+        let mut weighted_mid_move = 0.0;
+        for record in records {
+            if !record.mid_move().is_finite() {
+                continue;
+            }
+            summary.qty += record.qty;
+            weighted_mid_move += record.mid_move() * record.qty;
+        }
+        if summary.qty > 0.0 {
+            summary.vw_mid_move = weighted_mid_move / summary.qty;
+        }
+        summary
+    }
+}
+
+/// Tracks the mid price over the horizon after each maker fill received by the local. A fill is
+/// recorded once the local processes an event past the end of its horizon, so the fills whose
+/// horizon extends beyond the data are not recorded.
+#[derive(Default)]
+pub struct AdverseSelectionRecorder {
+    horizon: i64,
+    pending: VecDeque<AdverseSelection>,
+    records: Vec<AdverseSelection>,
+}
+
+impl AdverseSelectionRecorder {
+    /// Constructs an instance of `AdverseSelectionRecorder` tracking the mid price over `horizon`
+    /// nanoseconds after each maker fill. The recorder is enabled only if `horizon` is greater
+    /// than zero.
+    pub fn new(horizon: i64) -> Self {
+        Self {
+            horizon,
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if the recorder is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.horizon > 0
+    }
+
+    /// Starts the horizon of the fill if it is a maker fill.
+    ///
+    /// * `fill` - The fill applied to the state, whose `exec_qty` and `exec_price_tick` are of
+    ///   this fill only.
+    pub fn on_fill<MD: MarketDepth>(&mut self, fill: &Order, depth: &MD, timestamp: i64) {
+        if !self.enabled() || !fill.maker {
+            return;
+        }
+        self.pending.push_back(AdverseSelection {
+            order_id: fill.order_id,
+            side: fill.side,
+            price: fill.exec_price(),
+            qty: fill.exec_qty,
+            fill_ts: timestamp,
+            fill_mid: mid(depth),
+            horizon_mid: f64::NAN,
+        });
+    }
+
+    /// Records the fills whose horizon ends before `timestamp`, with the mid price of the market
+    /// depth as of the end of the horizon. This must be invoked before the market depth is updated
+    /// by the event at `timestamp`.
+    pub fn on_time<MD: MarketDepth>(&mut self, depth: &MD, timestamp: i64) {
+        while let Some(record) = self.pending.front() {
+            if record.fill_ts + self.horizon >= timestamp {
+                break;
+            }
+            let mut record = self.pending.pop_front().unwrap();
+            record.horizon_mid = mid(depth);
+            self.records.push(record);
+        }
+    }
+
+    /// Returns the fills whose horizon has ended.
+    pub fn records(&self) -> &[AdverseSelection] {
+        self.records.as_slice()
+    }
+
+    /// Clears the fills whose horizon has ended.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, Side, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, CANCEL_ORDER_EVENT, EXCH_EVENT, FILL_EVENT, LOCAL_EVENT,
+            SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn adverse_selection_of_maker_fills() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            // Join the queues behind the backtest orders.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                100,
+                100.0,
+                5.0,
+                3,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                100,
+                101.0,
+                5.0,
+                4,
+            ),
+            // Fills the backtest bid, after which the bid side falls.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                200,
+                100.0,
+                1.0,
+                3,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                300,
+                99.0,
+                1.0,
+                5,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | CANCEL_ORDER_EVENT,
+                300,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | CANCEL_ORDER_EVENT,
+                300,
+                100.0,
+                5.0,
+                3,
+            ),
+            // Fills the backtest ask, after which the ask side rises.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | FILL_EVENT,
+                400,
+                101.0,
+                2.0,
+                4,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                500,
+                103.0,
+                1.0,
+                6,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                500,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                500,
+                101.0,
+                5.0,
+                4,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_counterparty(false)
+                    .adverse_selection_horizon(150)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.submit_sell_order(0, 11, 101.0, 2.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        // The horizon of the buy fill received at 220 ends at 370.
+        hbt.elapse(359)?;
+        assert!(hbt.adverse_selections(0).is_empty());
+        hbt.elapse(1000)?;
+
+        let records = hbt.adverse_selections(0);
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].order_id, records[0].side), (10, Side::Buy));
+        assert_eq!((records[0].qty, records[0].fill_ts), (1.0, 220));
+        assert_eq!(records[0].fill_mid, 100.5);
+        assert_eq!(records[0].horizon_mid, 100.0);
+        assert_eq!(records[0].mid_move(), -0.5);
+        assert_eq!((records[1].order_id, records[1].side), (11, Side::Sell));
+        assert_eq!((records[1].qty, records[1].fill_ts), (2.0, 420));
+        assert_eq!(records[1].fill_mid, 100.0);
+        assert_eq!(records[1].horizon_mid, 101.0);
+        assert_eq!(records[1].mid_move(), -1.0);
+
+        // Both fills are picked off, weighted by their quantities.
+        let summary = hbt.adverse_selection_summary(0);
+        assert_eq!(summary.num_fills, 2);
+        assert_eq!(summary.qty, 3.0);
+        assert!(summary.vw_mid_move < 0.0);
+        assert!((summary.vw_mid_move - (-2.5 / 3.0)).abs() < 1e-9);
+
+        hbt.clear_adverse_selections(None);
+        assert!(hbt.adverse_selections(0).is_empty());
+        Ok(())
+    }
+}
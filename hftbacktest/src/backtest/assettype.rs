@@ -5,6 +5,9 @@ pub trait AssetType {
 
     /// Calculates the equity.
     fn equity(&self, price: f64, balance: f64, position: f64, fee: f64) -> f64;
+
+    /// Returns the contract size, by which the value amount is multiplied.
+    fn contract_size(&self) -> f64;
 }
 
 /// The common type of asset where the contract's notional value is linear to the quote currency.
@@ -28,6 +31,10 @@ impl AssetType for LinearAsset {
     fn equity(&self, price: f64, balance: f64, position: f64, fee: f64) -> f64 {
         balance + self.contract_size * position * price - fee
     }
+
+    fn contract_size(&self) -> f64 {
+        self.contract_size
+    }
 }
 
 /// The contract’s notional value is denominated in the quote currency.
@@ -51,4 +58,41 @@ impl AssetType for InverseAsset {
     fn equity(&self, price: f64, balance: f64, position: f64, fee: f64) -> f64 {
         -balance - self.contract_size * position / price - fee
     }
+
+    fn contract_size(&self) -> f64 {
+        self.contract_size
+    }
+}
+
+/// The contract’s notional value is linear to the price but settled in a currency other than the
+/// quote currency, converted at a fixed quanto rate, which is the value in the settlement currency
+/// of one unit of price change per contract size.
+#[derive(Clone)]
+pub struct QuantoAsset {
+    contract_size: f64,
+    quanto_rate: f64,
+}
+
+impl QuantoAsset {
+    /// Constructs an instance of `QuantoAsset`.
+    pub fn new(contract_size: f64, quanto_rate: f64) -> Self {
+        Self {
+            contract_size,
+            quanto_rate,
+        }
+    }
+}
+
+impl AssetType for QuantoAsset {
+    fn amount(&self, exec_price: f64, qty: f64) -> f64 {
+        self.contract_size * self.quanto_rate * exec_price * qty
+    }
+
+    fn equity(&self, price: f64, balance: f64, position: f64, fee: f64) -> f64 {
+        balance + self.contract_size * self.quanto_rate * position * price - fee
+    }
+
+    fn contract_size(&self) -> f64 {
+        self.contract_size
+    }
 }
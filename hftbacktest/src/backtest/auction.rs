@@ -0,0 +1,44 @@
+use crate::types::{OrderId, Side, Status};
+
+/// The matched quantity of a backtest order in an auction.
+#[derive(Clone, Debug)]
+pub struct AuctionFill {
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The quantity matched at the auction price.
+    pub matched_qty: f64,
+    /// The remaining quantity of the order after the auction.
+    pub leaves_qty: f64,
+    /// [`Status::Filled`] if the order is fully filled, otherwise [`Status::PartiallyFilled`].
+    pub status: Status,
+}
+
+impl AuctionFill {
+    /// Returns `true` if the order is fully filled in the auction.
+    pub fn is_fully_filled(&self) -> bool {
+        self.status == Status::Filled
+    }
+}
+
+/// The result of an auction uncross, with the attribution of the backtest orders touched by it.
+#[derive(Clone, Debug)]
+pub struct AuctionResult {
+    /// The uncross price.
+    pub price: f64,
+    /// The time at which the auction is uncrossed on the exchange.
+    pub exch_ts: i64,
+    /// The total quantity matched in the uncross, including the market feed orders. It is `0` if
+    /// only one side has orders that can be matched at the uncross price, in which case no order
+    /// is filled and the order book is left intact.
+    pub matched_qty: f64,
+    /// The backtest orders matched in the auction, in the order they are matched.
+    pub fills: Vec<AuctionFill>,
+}
+
+impl AuctionResult {
+    /// Returns the attribution of the given backtest order, or `None` if the order isn't matched
+    /// in the auction.
+    pub fn fill(&self, order_id: OrderId) -> Option<&AuctionFill> {
+        self.fills.iter().find(|fill| fill.order_id == order_id)
+    }
+}
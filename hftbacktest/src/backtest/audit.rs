@@ -0,0 +1,345 @@
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Error, Write},
+    path::PathBuf,
+};
+
+use crate::types::{CancelReason, Order, OrderId, RejectReason, Side, Status};
+
+/// Action of an [`AuditRecord`].
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum AuditAction {
+    /// The local submitted the order.
+    Submitted,
+    /// The local requested to modify the order.
+    ModifyRequested,
+    /// The local requested to cancel the order.
+    CancelRequested,
+    /// The exchange accepted the order.
+    Acked,
+    /// The exchange accepted the modification of the order.
+    Modified,
+    /// The order was executed, either fully or partially.
+    Fill,
+    /// The order was canceled, either by the request or by the exchange, in which case
+    /// [`AuditRecord::cancel_reason`] gives the reason.
+    Canceled,
+    /// The order expired.
+    Expired,
+    /// The exchange rejected the request for the reason given by
+    /// [`AuditRecord::reject_reason`].
+    Rejected,
+}
+
+/// An entry of the audit trail.
+#[derive(Clone, Debug)]
+pub struct AuditRecord {
+    pub action: AuditAction,
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The executed price for [`AuditAction::Fill`], or the order price otherwise.
+    pub price: f64,
+    /// The executed quantity for [`AuditAction::Fill`], the requested quantity for the requests
+    /// made by the local, or the order's leaves quantity otherwise.
+    pub qty: f64,
+    /// The status of the local order after the action is applied.
+    pub status: Status,
+    pub reject_reason: RejectReason,
+    pub cancel_reason: CancelReason,
+    /// The time at which the exchange processes the action. `0` for the requests made by the
+    /// local.
+    pub exch_ts: i64,
+    /// The time at which the local makes the request or receives the response.
+    pub local_ts: i64,
+}
+
+impl AuditRecord {
+    /// Returns the record as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = String::with_capacity(256);
+        let _ = write!(
+            json,
+            "{{\"action\":\"{:?}\",\"order_id\":{},\"side\":\"{:?}\",\"price\":{},\"qty\":{},\
+             \"status\":\"{:?}\",\"reject_reason\":\"{:?}\",\"cancel_reason\":\"{:?}\",\
+             \"exch_ts\":{},\"local_ts\":{}}}",
+            self.action,
+            self.order_id,
+            self.side,
+            json_number(self.price),
+            json_number(self.qty),
+            self.status,
+            self.reject_reason,
+            self.cancel_reason,
+            self.exch_ts,
+            self.local_ts,
+        );
+        json
+    }
+}
+
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Records the submissions, acknowledgements, modifications, cancellations, fills, expiries, and
+/// rejections observed by the local as an audit trail, which is written to a file in JSON Lines
+/// format. The records are buffered up to the capacity and then appended to the file, and the
+/// remaining records are written by [`AuditRecorder::flush`], which is invoked when the backtest
+/// is closed.
+#[derive(Default)]
+pub struct AuditRecorder {
+    path: Option<PathBuf>,
+    capacity: usize,
+    records: Vec<AuditRecord>,
+    created: bool,
+}
+
+impl AuditRecorder {
+    /// Constructs an instance of `AuditRecorder` that writes the audit trail to `path`, buffering
+    /// up to `capacity` records in memory. The file is truncated on the first write.
+    pub fn new(path: impl Into<PathBuf>, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            path: Some(path.into()),
+            capacity,
+            records: Vec::with_capacity(capacity),
+            created: false,
+        }
+    }
+
+    /// Returns `true` if the recorder is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Returns the records that are not yet written to the file.
+    pub fn records(&self) -> &[AuditRecord] {
+        &self.records
+    }
+
+    /// Records the submission of the order.
+    pub fn on_submit(&mut self, order: &Order, timestamp: i64) -> Result<(), Error> {
+        self.push_request(order, AuditAction::Submitted, timestamp)
+    }
+
+    /// Records the modify request of the order.
+    pub fn on_modify(&mut self, order: &Order, timestamp: i64) -> Result<(), Error> {
+        self.push_request(order, AuditAction::ModifyRequested, timestamp)
+    }
+
+    /// Records the cancel request of the order.
+    pub fn on_cancel(&mut self, order: &Order, timestamp: i64) -> Result<(), Error> {
+        self.push_request(order, AuditAction::CancelRequested, timestamp)
+    }
+
+    /// Records the actions conveyed by the order response received at `local_ts`.
+    ///
+    /// * `resp` - The order response from the exchange.
+    /// * `prev_req` - The request status of the local order before the response is applied.
+    /// * `local_order` - The local order after the response is applied.
+    pub fn on_response(
+        &mut self,
+        resp: &Order,
+        prev_req: Status,
+        local_order: &Order,
+        local_ts: i64,
+    ) -> Result<(), Error> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        let record = |action: AuditAction, price: f64, qty: f64| AuditRecord {
+            action,
+            order_id: local_order.order_id,
+            side: local_order.side,
+            price,
+            qty,
+            status: local_order.status,
+            reject_reason: resp.reject_reason,
+            cancel_reason: resp.cancel_reason,
+            exch_ts: resp.exch_timestamp,
+            local_ts,
+        };
+        if resp.req == Status::Rejected {
+            return self.push(record(
+                AuditAction::Rejected,
+                local_order.price(),
+                local_order.leaves_qty,
+            ));
+        }
+        if prev_req == Status::New && resp.status != Status::Expired {
+            self.push(record(AuditAction::Acked, resp.price(), resp.qty))?;
+        }
+        // A fill that occurs before the modify request reaches the exchange carries the
+        // timestamp of the previous request.
+        if prev_req == Status::Replaced && resp.local_timestamp == local_order.local_timestamp {
+            self.push(record(AuditAction::Modified, resp.price(), resp.leaves_qty))?;
+        }
+        if resp.exec_qty > 0.0
+            && (resp.status == Status::Filled || resp.status == Status::PartiallyFilled)
+        {
+            self.push(record(AuditAction::Fill, resp.exec_price(), resp.exec_qty))?;
+        }
+        match local_order.status {
+            Status::Canceled => {
+                self.push(record(AuditAction::Canceled, resp.price(), resp.leaves_qty))
+            }
+            Status::Expired => {
+                self.push(record(AuditAction::Expired, resp.price(), resp.leaves_qty))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the buffered records to the file.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        if self.records.is_empty() && self.created {
+            return Ok(());
+        }
+        let file = if self.created {
+            OpenOptions::new().append(true).open(path)?
+        } else {
+            File::create(path)?
+        };
+        self.created = true;
+        let mut writer = BufWriter::new(file);
+        for record in self.records.drain(..) {
+            writeln!(writer, "{}", record.to_json())?;
+        }
+        writer.flush()
+    }
+
+    fn push_request(
+        &mut self,
+        order: &Order,
+        action: AuditAction,
+        timestamp: i64,
+    ) -> Result<(), Error> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        self.push(AuditRecord {
+            action,
+            order_id: order.order_id,
+            side: order.side,
+            price: order.price(),
+            qty: order.qty,
+            status: order.status,
+            reject_reason: RejectReason::None,
+            cancel_reason: CancelReason::None,
+            exch_ts: 0,
+            local_ts: timestamp,
+        })
+    }
+
+    fn push(&mut self, record: AuditRecord) -> Result<(), Error> {
+        self.records.push(record);
+        if self.records.len() >= self.capacity {
+            self.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, TimeInForce},
+        types::{ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, LOCAL_EVENT, SELL_EVENT},
+    };
+
+    #[test]
+    fn audit_log_records_each_action_in_order() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                105.0,
+                1.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+        let path = std::env::temp_dir().join("hftbacktest_audit_log.jsonl");
+
+        // A capacity smaller than the number of records streams them to the file in batches.
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .audit_log(&path, 4)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.modify(0, 10, 99.0, 2.0, true)?;
+        hbt.cancel(0, 10, true)?;
+        hbt.modify(0, 10, 98.0, 1.0, true)?;
+        hbt.submit_buy_order(0, 11, 105.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        hbt.submit_buy_order(0, 12, 99.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        hbt.close()?;
+
+        let audit = std::fs::read_to_string(&path)?;
+        let field = |line: &str, name: &str| -> String {
+            let start = line.find(&format!("\"{name}\":")).unwrap() + name.len() + 3;
+            line[start..]
+                .split([',', '}'])
+                .next()
+                .unwrap()
+                .trim_matches('"')
+                .to_string()
+        };
+        let actions = audit
+            .lines()
+            .map(|line| (field(line, "order_id"), field(line, "action")))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("10", "Submitted"),
+            ("10", "Acked"),
+            ("10", "ModifyRequested"),
+            ("10", "Modified"),
+            ("10", "CancelRequested"),
+            ("10", "Canceled"),
+            ("10", "ModifyRequested"),
+            ("10", "Rejected"),
+            ("11", "Submitted"),
+            ("11", "Acked"),
+            ("11", "Fill"),
+            ("12", "Submitted"),
+            ("12", "Expired"),
+        ]
+        .map(|(order_id, action)| (order_id.to_string(), action.to_string()));
+        assert_eq!(actions, expected);
+
+        let rejected = audit.lines().nth(7).unwrap();
+        assert_eq!(field(rejected, "reject_reason"), "UnknownOrder");
+        let fill = audit.lines().nth(10).unwrap();
+        assert_eq!(field(fill, "price"), "105");
+        assert_eq!(field(fill, "status"), "Filled");
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Error as IoError, ErrorKind},
+};
+
+use crate::{
+    backtest::data::{Data, DataSource, read_npy_file, read_npz_file},
+    types::{
+        ADD_ORDER_EVENT, AUCTION_UPDATE_EVENT, BUY_EVENT, CANCEL_ORDER_EVENT, DEPTH_BBO_EVENT,
+        DEPTH_CLEAR_EVENT, DEPTH_EVENT, DEPTH_SNAPSHOT_EVENT, EXCH_EVENT, Event, FILL_EVENT,
+        MODIFY_ORDER_EVENT, OrderId, SELL_EVENT, Side,
+    },
+};
+
+/// The mask to extract the event type, such as [`DEPTH_EVENT`] or [`ADD_ORDER_EVENT`], from
+/// [`Event::ev`].
+const EVENT_TYPE_MASK: u64 = 0xff;
+
+/// An interval between two consecutive exchange events that exceeds the gap threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataGap {
+    /// The row index of the event after the gap.
+    pub row: usize,
+    /// The exchange timestamp of the event before the gap.
+    pub from_ts: i64,
+    /// The exchange timestamp of the event after the gap.
+    pub to_ts: i64,
+}
+
+/// An occurrence of the order book becoming crossed, that is, the best bid is greater than or
+/// equal to the best ask.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrossedBook {
+    /// The row index of the event that crosses the order book.
+    pub row: usize,
+    pub exch_ts: i64,
+    pub best_bid: f64,
+    pub best_ask: f64,
+}
+
+/// The report of [`DataSource::inspect`].
+#[derive(Clone, Debug, Default)]
+pub struct DataReport {
+    /// The number of rows.
+    pub num_rows: usize,
+    /// The number of events by event type, such as [`DEPTH_EVENT`] or [`ADD_ORDER_EVENT`],
+    /// excluding the flags such as [`EXCH_EVENT`] and [`BUY_EVENT`].
+    pub event_counts: BTreeMap<u64, usize>,
+    /// The range of [`Event::exch_ts`]. `None` if there is no row.
+    pub exch_ts_range: Option<(i64, i64)>,
+    /// The range of [`Event::local_ts`]. `None` if there is no row.
+    pub local_ts_range: Option<(i64, i64)>,
+    /// The gaps between the consecutive exchange events.
+    pub gaps: Vec<DataGap>,
+    /// The occurrences of the order book becoming crossed. Consecutive events while the order
+    /// book stays crossed are reported once. Auction updates are not checked, since the order
+    /// book can be crossed during the auction.
+    pub crossed_books: Vec<CrossedBook>,
+}
+
+impl DataReport {
+    /// Returns the number of events of the given event type.
+    pub fn event_count(&self, event_type: u64) -> usize {
+        self.event_counts
+            .get(&(event_type & EVENT_TYPE_MASK))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// A minimal order book that can be crossed, unlike the market depth implementations, which
+/// resolve the crossing.
+struct InspectionBook {
+    tick_size: f64,
+    bid_depth: BTreeMap<i64, f64>,
+    ask_depth: BTreeMap<i64, f64>,
+    orders: HashMap<OrderId, (Side, i64, f64)>,
+}
+
+impl InspectionBook {
+    fn new(tick_size: f64) -> Self {
+        Self {
+            tick_size,
+            bid_depth: Default::default(),
+            ask_depth: Default::default(),
+            orders: Default::default(),
+        }
+    }
+
+    fn side_depth(&mut self, side: Side) -> &mut BTreeMap<i64, f64> {
+        if side == Side::Buy {
+            &mut self.bid_depth
+        } else {
+            &mut self.ask_depth
+        }
+    }
+
+    fn add_level_qty(&mut self, side: Side, price_tick: i64, qty: f64) {
+        let depth = self.side_depth(side);
+        let level_qty = depth.entry(price_tick).or_insert(0.0);
+        *level_qty += qty;
+        if *level_qty <= 0.0 {
+            depth.remove(&price_tick);
+        }
+    }
+
+    fn set_level_qty(&mut self, side: Side, price_tick: i64, qty: f64) {
+        let depth = self.side_depth(side);
+        if qty > 0.0 {
+            depth.insert(price_tick, qty);
+        } else {
+            depth.remove(&price_tick);
+        }
+    }
+
+    fn remove_order(&mut self, order_id: OrderId) -> Option<(Side, i64, f64)> {
+        let (side, price_tick, qty) = self.orders.remove(&order_id)?;
+        self.add_level_qty(side, price_tick, -qty);
+        Some((side, price_tick, qty))
+    }
+
+    fn process(&mut self, ev: &Event) {
+        let price_tick = (ev.px / self.tick_size).round() as i64;
+        let side = if ev.ev & BUY_EVENT == BUY_EVENT {
+            Side::Buy
+        } else if ev.ev & SELL_EVENT == SELL_EVENT {
+            Side::Sell
+        } else {
+            Side::None
+        };
+        match ev.ev & EVENT_TYPE_MASK {
+            DEPTH_EVENT | DEPTH_SNAPSHOT_EVENT | DEPTH_BBO_EVENT if side != Side::None => {
+                self.set_level_qty(side, price_tick, ev.qty);
+            }
+            DEPTH_CLEAR_EVENT => {
+                if side != Side::Sell {
+                    self.bid_depth.clear();
+                }
+                if side != Side::Buy {
+                    self.ask_depth.clear();
+                }
+                self.orders
+                    .retain(|_, (order_side, _, _)| side != Side::None && *order_side != side);
+            }
+            ADD_ORDER_EVENT if side != Side::None => {
+                self.remove_order(ev.order_id);
+                self.orders.insert(ev.order_id, (side, price_tick, ev.qty));
+                self.add_level_qty(side, price_tick, ev.qty);
+            }
+            MODIFY_ORDER_EVENT => {
+                if let Some((side, _, _)) = self.remove_order(ev.order_id) {
+                    self.orders.insert(ev.order_id, (side, price_tick, ev.qty));
+                    self.add_level_qty(side, price_tick, ev.qty);
+                }
+            }
+            CANCEL_ORDER_EVENT => {
+                self.remove_order(ev.order_id);
+            }
+            FILL_EVENT => {
+                if let Some((side, price_tick, qty)) = self.remove_order(ev.order_id) {
+                    let remaining_qty = qty - ev.qty;
+                    if remaining_qty > 0.0 {
+                        self.orders
+                            .insert(ev.order_id, (side, price_tick, remaining_qty));
+                        self.add_level_qty(side, price_tick, remaining_qty);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn crossed(&self) -> Option<(i64, i64)> {
+        let best_bid_tick = *self.bid_depth.keys().next_back()?;
+        let best_ask_tick = *self.ask_depth.keys().next()?;
+        (best_bid_tick >= best_ask_tick).then_some((best_bid_tick, best_ask_tick))
+    }
+}
+
+impl DataSource<Event> {
+    /// Scans the entire data upfront without running a backtest and reports the event type
+    /// distribution, the timestamp ranges, and anomalies such as gaps and crossed order books.
+    ///
+    /// * `tick_size` - The tick size of the asset, which is used to rebuild the order book.
+    /// * `gap_threshold` - The minimum interval between the exchange timestamps of two
+    ///   consecutive exchange events to be reported as a gap.
+    pub fn inspect(&self, tick_size: f64, gap_threshold: i64) -> Result<DataReport, IoError> {
+        let data = match self {
+            DataSource::File(filepath) if filepath.ends_with(".npy") => {
+                read_npy_file::<Event>(filepath)?
+            }
+            DataSource::File(filepath) if filepath.ends_with(".npz") => {
+                read_npz_file::<Event>(filepath, "data")?
+            }
+            DataSource::File(_) => {
+                return Err(IoError::new(
+                    ErrorKind::InvalidData,
+                    "unsupported data type",
+                ));
+            }
+            DataSource::Data(data) => data.clone(),
+        };
+        Ok(inspect_data(&data, tick_size, gap_threshold))
+    }
+}
+
+fn inspect_data(data: &Data<Event>, tick_size: f64, gap_threshold: i64) -> DataReport {
+    let mut report = DataReport {
+        num_rows: data.len(),
+        ..Default::default()
+    };
+    let mut book = InspectionBook::new(tick_size);
+    let mut prev_exch_ts: Option<i64> = None;
+    let mut crossed = false;
+    for row in 0..data.len() {
+        let ev = &data[row];
+        *report
+            .event_counts
+            .entry(ev.ev & EVENT_TYPE_MASK)
+            .or_insert(0) += 1;
+
+        let expand = |range: Option<(i64, i64)>, ts: i64| match range {
+            Some((min, max)) => Some((min.min(ts), max.max(ts))),
+            None => Some((ts, ts)),
+        };
+        report.exch_ts_range = expand(report.exch_ts_range, ev.exch_ts);
+        report.local_ts_range = expand(report.local_ts_range, ev.local_ts);
+
+        if ev.ev & EXCH_EVENT == EXCH_EVENT {
+            if let Some(prev_exch_ts) = prev_exch_ts {
+                if ev.exch_ts - prev_exch_ts > gap_threshold {
+                    report.gaps.push(DataGap {
+                        row,
+                        from_ts: prev_exch_ts,
+                        to_ts: ev.exch_ts,
+                    });
+                }
+            }
+            prev_exch_ts = Some(ev.exch_ts);
+        }
+
+        book.process(ev);
+        if ev.ev & AUCTION_UPDATE_EVENT == AUCTION_UPDATE_EVENT {
+            continue;
+        }
+        match book.crossed() {
+            Some((best_bid_tick, best_ask_tick)) => {
+                if !crossed {
+                    report.crossed_books.push(CrossedBook {
+                        row,
+                        exch_ts: ev.exch_ts,
+                        best_bid: best_bid_tick as f64 * tick_size,
+                        best_ask: best_ask_tick as f64 * tick_size,
+                    });
+                }
+                crossed = true;
+            }
+            None => {
+                crossed = false;
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use crate::{
+        backtest::data::{CrossedBook, DataGap, DataSource, write_npy},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, CANCEL_ORDER_EVENT, EXCH_EVENT, Event, LOCAL_EVENT,
+            SELL_EVENT,
+        },
+    };
+
+    fn event(ev: u64, ts: i64, px: f64, order_id: u64) -> Event {
+        Event {
+            ev: EXCH_EVENT | LOCAL_EVENT | ev,
+            exch_ts: ts,
+            local_ts: ts + 5,
+            px,
+            qty: 1.0,
+            order_id,
+            ival: 0,
+            fval: 0.0,
+        }
+    }
+
+    #[test]
+    fn inspect_file() {
+        let events = [
+            event(BUY_EVENT | ADD_ORDER_EVENT, 0, 100.0, 1),
+            event(SELL_EVENT | ADD_ORDER_EVENT, 10, 101.0, 2),
+            // Crosses the order book.
+            event(SELL_EVENT | ADD_ORDER_EVENT, 20, 99.0, 3),
+            event(BUY_EVENT | ADD_ORDER_EVENT, 30, 98.0, 4),
+            event(CANCEL_ORDER_EVENT, 1000, 0.0, 3),
+            event(CANCEL_ORDER_EVENT, 1010, 0.0, 1),
+        ];
+        let filepath = std::env::temp_dir().join("hftbacktest_inspect_file.npy");
+        write_npy(&mut File::create(&filepath).unwrap(), &events).unwrap();
+
+        let report = DataSource::<Event>::File(filepath.to_str().unwrap().to_string())
+            .inspect(1.0, 100)
+            .unwrap();
+        std::fs::remove_file(&filepath).unwrap();
+
+        assert_eq!(report.num_rows, 6);
+        assert_eq!(report.event_count(ADD_ORDER_EVENT), 4);
+        assert_eq!(report.event_count(CANCEL_ORDER_EVENT), 2);
+        assert_eq!(report.exch_ts_range, Some((0, 1010)));
+        assert_eq!(report.local_ts_range, Some((5, 1015)));
+        assert_eq!(
+            report.gaps,
+            vec![DataGap {
+                row: 4,
+                from_ts: 30,
+                to_ts: 1000
+            }]
+        );
+        assert_eq!(
+            report.crossed_books,
+            vec![CrossedBook {
+                row: 2,
+                exch_ts: 20,
+                best_bid: 100.0,
+                best_ask: 99.0
+            }]
+        );
+    }
+}
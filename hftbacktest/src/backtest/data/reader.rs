@@ -2,6 +2,7 @@ use std::{
     cell::RefCell,
     collections::HashMap,
     io::{Error as IoError, ErrorKind},
+    path::Path,
     rc::Rc,
     sync::{
         Arc,
@@ -21,7 +22,7 @@ use crate::{
             npy::{NpyDTyped, read_npy_file, read_npz_file},
         },
     },
-    types::Event,
+    types::{EXCH_EVENT, Event, LOCAL_EVENT},
 };
 
 /// Data source for the [`Reader`].
@@ -39,6 +40,108 @@ where
     Data(Data<D>),
 }
 
+impl DataSource<Event> {
+    /// Constructs a data source from the events built in memory, such as a synthetic order book
+    /// and trade sequence for a deterministic strategy test without data files.
+    ///
+    /// The events must be sorted by timestamp, that is, [`Event::exch_ts`] must not decrease
+    /// across the exchange events and [`Event::local_ts`] must not decrease across the local
+    /// events; otherwise, an error is returned.
+    pub fn from_events(events: Vec<Event>) -> Result<Self, IoError> {
+        let mut prev_exch_ts = i64::MIN;
+        let mut prev_local_ts = i64::MIN;
+        for (row, event) in events.iter().enumerate() {
+            if event.is(EXCH_EVENT) {
+                if event.exch_ts < prev_exch_ts {
+                    return Err(IoError::new(
+                        ErrorKind::InvalidData,
+                        format!("exch_ts is out of order at row {row}"),
+                    ));
+                }
+                prev_exch_ts = event.exch_ts;
+            }
+            if event.is(LOCAL_EVENT) {
+                if event.local_ts < prev_local_ts {
+                    return Err(IoError::new(
+                        ErrorKind::InvalidData,
+                        format!("local_ts is out of order at row {row}"),
+                    ));
+                }
+                prev_local_ts = event.local_ts;
+            }
+        }
+        Ok(DataSource::Data(Data::from_data(&events)))
+    }
+}
+
+impl<D> DataSource<D>
+where
+    D: POD + Clone,
+{
+    /// Constructs the file data sources for each date in the inclusive range from `start` to
+    /// `end`, in date order, by replacing `{date}` in the path template with the date formatted as
+    /// `YYYYMMDD`. The dates are also given as `YYYYMMDD`, for example, `20240501`.
+    ///
+    /// Since each file is read in turn, the files must hold consecutive periods so that the
+    /// sources are time-ordered. An error is returned if a date is invalid or if a local file is
+    /// not found.
+    pub fn date_range(template: &str, start: u32, end: u32) -> Result<Vec<Self>, IoError> {
+        let mut date = parse_date(start)?;
+        let end_date = parse_date(end)?;
+        if date > end_date {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                format!("start date {start} is after end date {end}"),
+            ));
+        }
+        let mut sources = Vec::new();
+        while date <= end_date {
+            let (year, month, day) = date;
+            let filepath = template.replace("{date}", &format!("{year:04}{month:02}{day:02}"));
+            if !filepath.starts_with("s3://") && !Path::new(&filepath).exists() {
+                return Err(IoError::new(
+                    ErrorKind::NotFound,
+                    format!("`{filepath}` is not found"),
+                ));
+            }
+            sources.push(DataSource::File(filepath));
+            date = next_date(date);
+        }
+        Ok(sources)
+    }
+}
+
+/// Parses the date given as `YYYYMMDD` into `(year, month, day)`.
+fn parse_date(date: u32) -> Result<(u32, u32, u32), IoError> {
+    let (year, month, day) = (date / 10000, date / 100 % 100, date % 100);
+    if month == 0 || month > 12 || day == 0 || day > days_in_month(year, month) {
+        return Err(IoError::new(
+            ErrorKind::InvalidInput,
+            format!("{date} is not a valid date"),
+        ));
+    }
+    Ok((year, month, day))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn next_date((year, month, day): (u32, u32, u32)) -> (u32, u32, u32) {
+    if day < days_in_month(year, month) {
+        (year, month, day + 1)
+    } else if month < 12 {
+        (year, month + 1, 1)
+    } else {
+        (year + 1, 1, 1)
+    }
+}
+
 #[derive(Debug)]
 struct CachedData<D>
 where
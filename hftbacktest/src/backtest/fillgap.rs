@@ -0,0 +1,426 @@
+use crate::{
+    depth::{INVALID_MAX, INVALID_MIN, MarketDepth},
+    types::{Order, OrderId, Side},
+};
+
+/// A fill compared against the mid price at the time of the fill, which is regarded as the
+/// theoretical fair price.
+#[derive(Clone, Debug)]
+pub struct FillGap {
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The executed price.
+    pub price: f64,
+    /// The executed quantity.
+    pub qty: f64,
+    /// The mid price in the exchange's market depth at the time of the fill.
+    pub mid: f64,
+    pub maker: bool,
+    /// The fee charged for the fill. A rebate is a negative fee.
+    pub fee: f64,
+    /// The time at which the fill occurs on the exchange.
+    pub exch_ts: i64,
+}
+
+impl FillGap {
+    /// Returns the gap between the mid price and the executed price per unit quantity. A positive
+    /// value means that the fill is favorable compared to the mid price, that is, buying below or
+    /// selling above the mid price.
+    pub fn gap(&self) -> f64 {
+        match self.side {
+            Side::Buy => self.mid - self.price,
+            Side::Sell => self.price - self.mid,
+            Side::None | Side::Unsupported => 0.0,
+        }
+    }
+
+    /// Returns the effective spread of the fill, `2 * |price - mid|`. For a maker fill, it is the
+    /// spread captured, and for a taker fill, the spread paid.
+    pub fn effective_spread(&self) -> f64 {
+        2.0 * (self.price - self.mid).abs()
+    }
+
+    /// Returns the profit and loss of the fill marked to the mid price, ignoring the fee.
+    pub fn gross_pnl(&self) -> f64 {
+        self.gap() * self.qty
+    }
+
+    /// Returns the profit and loss of the fill marked to the mid price, after the fee or rebate.
+    pub fn net_pnl(&self) -> f64 {
+        self.gross_pnl() - self.fee
+    }
+}
+
+/// The aggregate of the [`FillGap`]s over a run.
+#[derive(Clone, Debug, Default)]
+pub struct FillGapSummary {
+    pub num_fills: usize,
+    /// The total executed quantity.
+    pub qty: f64,
+    /// The quantity-weighted average gap of all fills.
+    pub avg_gap: f64,
+    /// The quantity-weighted average gap of the maker fills.
+    pub maker_avg_gap: f64,
+    /// The quantity-weighted average gap of the taker fills.
+    pub taker_avg_gap: f64,
+    /// The quantity-weighted average effective spread captured by the maker fills.
+    pub spread_captured: f64,
+    /// The quantity-weighted average effective spread paid by the taker fills.
+    pub spread_paid: f64,
+    /// The total profit and loss of the fills marked to the mid price, ignoring fees.
+    pub gross_pnl: f64,
+    /// The total fees of the fills. Rebates are counted as negative fees.
+    pub fee: f64,
+    /// The total profit and loss of the fills marked to the mid price, after fees and rebates.
+    pub net_pnl: f64,
+}
+
+impl FillGapSummary {
+    /// Aggregates the given fills.
+    pub fn new(fills: &[FillGap]) -> Self {
+        let mut maker = (0.0, 0.0);
+        let mut taker = (0.0, 0.0);
+        let mut spread_captured = 0.0;
+        let mut spread_paid = 0.0;
+        let mut fee = 0.0;
+        for fill in fills {
+            let (acc, spread) = if fill.maker {
+                (&mut maker, &mut spread_captured)
+            } else {
+                (&mut taker, &mut spread_paid)
+            };
+            acc.0 += fill.gross_pnl();
+            acc.1 += fill.qty;
+            *spread += fill.effective_spread() * fill.qty;
+            fee += fill.fee;
+        }
+        let gross_pnl = maker.0 + taker.0;
+        let avg = |(gap_qty, qty): (f64, f64)| if qty > 0.0 { gap_qty / qty } else { 0.0 };
+        Self {
+            num_fills: fills.len(),
+            qty: maker.1 + taker.1,
+            avg_gap: avg((maker.0 + taker.0, maker.1 + taker.1)),
+            maker_avg_gap: avg(maker),
+            taker_avg_gap: avg(taker),
+            spread_captured: avg((spread_captured, maker.1)),
+            spread_paid: avg((spread_paid, taker.1)),
+            gross_pnl,
+            fee,
+            net_pnl: gross_pnl - fee,
+        }
+    }
+}
+
+/// Records the mid price at the time of each fill in the exchange.
+#[derive(Default)]
+pub struct FillGapRecorder {
+    fills: Vec<FillGap>,
+}
+
+impl FillGapRecorder {
+    /// Constructs an instance of `FillGapRecorder`. The recorder is enabled only if `capacity` is
+    /// greater than zero.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            fills: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Records the fill of the order with the fee charged for it. The fill is not recorded if
+    /// either side of the market depth is empty, since the mid price is not available.
+    pub fn record<MD: MarketDepth>(&mut self, order: &Order, depth: &MD, fee: f64) {
+        if self.fills.capacity() == 0
+            || depth.best_bid_tick() == INVALID_MIN
+            || depth.best_ask_tick() == INVALID_MAX
+        {
+            return;
+        }
+        self.fills.push(FillGap {
+            order_id: order.order_id,
+            side: order.side,
+            price: order.exec_price(),
+            qty: order.exec_qty,
+            mid: (depth.best_bid() + depth.best_ask()) / 2.0,
+            maker: order.maker,
+            fee,
+            exch_ts: order.exch_timestamp,
+        });
+    }
+
+    /// Returns the recorded fills.
+    pub fn fills(&self) -> &[FillGap] {
+        self.fills.as_slice()
+    }
+
+    /// Clears the recorded fills.
+    pub fn clear(&mut self) {
+        self.fills.clear();
+    }
+}
+
+/// The fills whose executed prices are at the same distance from the mid price at the time of
+/// the fill.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FillPriceBucket {
+    /// The distance of the executed price from the mid price in ticks, rounded half away from
+    /// zero. As with [`FillGap::gap`], a positive value means that the fill is favorable compared
+    /// to the mid price.
+    pub ticks: i64,
+    pub num_fills: usize,
+    /// The total executed quantity.
+    pub qty: f64,
+}
+
+/// Accumulates the executed quantity of the fills in the exchange by the distance from the mid
+/// price at the time of the fill, as a histogram of the effective fill prices over the run.
+#[derive(Default)]
+pub struct FillPriceHistogram {
+    enabled: bool,
+    buckets: Vec<FillPriceBucket>,
+}
+
+impl FillPriceHistogram {
+    /// Constructs an instance of `FillPriceHistogram`.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            buckets: Vec::new(),
+        }
+    }
+
+    /// Adds the fill of the order to the bucket at its distance from the mid price. The fill is
+    /// not added if either side of the market depth is empty, since the mid price is not
+    /// available.
+    pub fn record<MD: MarketDepth>(&mut self, order: &Order, depth: &MD) {
+        if !self.enabled
+            || depth.best_bid_tick() == INVALID_MIN
+            || depth.best_ask_tick() == INVALID_MAX
+        {
+            return;
+        }
+        // The gap from the mid price in half ticks keeps it an integer.
+        let mid_x2 = depth.best_bid_tick() + depth.best_ask_tick();
+        let gap_x2 = match order.side {
+            Side::Buy => mid_x2 - 2 * order.exec_price_tick,
+            Side::Sell => 2 * order.exec_price_tick - mid_x2,
+            Side::None | Side::Unsupported => return,
+        };
+        let ticks = (gap_x2 + gap_x2.signum()) / 2;
+        match self
+            .buckets
+            .binary_search_by_key(&ticks, |bucket| bucket.ticks)
+        {
+            Ok(i) => {
+                let bucket = &mut self.buckets[i];
+                bucket.num_fills += 1;
+                bucket.qty += order.exec_qty;
+            }
+            Err(i) => self.buckets.insert(
+                i,
+                FillPriceBucket {
+                    ticks,
+                    num_fills: 1,
+                    qty: order.exec_qty,
+                },
+            ),
+        }
+    }
+
+    /// Returns the buckets in ascending order of the distance from the mid price. Only the
+    /// buckets with at least one fill are present.
+    pub fn buckets(&self) -> &[FillPriceBucket] {
+        self.buckets.as_slice()
+    }
+
+    /// Clears the histogram.
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            fillgap::{FillGap, FillGapSummary},
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, EXCH_FILL_EVENT, LOCAL_EVENT, SELL_EVENT, Side,
+        },
+    };
+
+    fn fill(side: Side, price: f64, qty: f64, mid: f64, maker: bool) -> FillGap {
+        FillGap {
+            order_id: 0,
+            side,
+            price,
+            qty,
+            mid,
+            maker,
+            fee: 0.0,
+            exch_ts: 0,
+        }
+    }
+
+    #[test]
+    fn test_effective_spread() {
+        let fills = [
+            fill(Side::Buy, 99.5, 1.0, 100.0, true),
+            fill(Side::Sell, 101.0, 3.0, 100.0, true),
+            fill(Side::Buy, 100.5, 2.0, 100.0, false),
+            fill(Side::Sell, 99.75, 2.0, 100.0, false),
+        ];
+        assert_eq!(fills[0].effective_spread(), 1.0);
+        assert_eq!(fills[1].effective_spread(), 2.0);
+
+        let summary = FillGapSummary::new(&fills);
+        assert!((summary.spread_captured - (1.0 * 1.0 + 2.0 * 3.0) / 4.0).abs() < 1e-9);
+        assert!((summary.spread_paid - (1.0 * 2.0 + 0.5 * 2.0) / 4.0).abs() < 1e-9);
+
+        let summary = FillGapSummary::new(&fills[..2]);
+        assert_eq!(summary.spread_paid, 0.0);
+    }
+
+    #[test]
+    fn fill_gap_against_mid() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                20,
+                100.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_FILL_EVENT | BUY_EVENT, 50, 100.0, 1.0, 3),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 200, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_gap_capacity(16)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        // Buys passively at the best bid, below the mid.
+        hbt.submit_buy_order(0, 100, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(99)?;
+        // Buys aggressively at the best ask, above the mid.
+        hbt.submit_buy_order(0, 101, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(100)?;
+
+        let fills = hbt.fill_gaps(0);
+        assert_eq!(fills.len(), 2);
+
+        assert_eq!(fills[0].order_id, 100);
+        assert!(fills[0].maker);
+        assert_eq!(fills[0].mid, 100.5);
+        assert_eq!(fills[0].gap(), 0.5);
+
+        assert_eq!(fills[1].order_id, 101);
+        assert!(!fills[1].maker);
+        assert_eq!(fills[1].mid, 100.5);
+        assert_eq!(fills[1].gap(), -0.5);
+
+        let summary = hbt.fill_gap_summary(0);
+        assert_eq!(summary.num_fills, 2);
+        assert_eq!(summary.qty, 2.0);
+        assert_eq!(summary.avg_gap, 0.0);
+        assert_eq!(summary.maker_avg_gap, 0.5);
+        assert_eq!(summary.taker_avg_gap, -0.5);
+
+        hbt.clear_fill_gaps(None);
+        assert!(hbt.fill_gaps(0).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn fill_price_histogram_by_ticks_from_mid() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                5.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                20,
+                100.0,
+                1.0,
+                3,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                20,
+                98.0,
+                1.0,
+                4,
+            ),
+            l3_event(EXCH_FILL_EVENT | BUY_EVENT, 50, 100.0, 1.0, 3),
+            l3_event(EXCH_FILL_EVENT | BUY_EVENT, 50, 98.0, 1.0, 4),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_price_histogram(true)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        // Buys passively at the best bid, half a tick below the mid, and two and a half ticks
+        // below the mid.
+        hbt.submit_buy_order(0, 100, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.submit_buy_order(0, 101, 98.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(99)?;
+        // Buys and sells aggressively at the touch, half a tick on the unfavorable side of the
+        // mid.
+        hbt.submit_buy_order(0, 102, 101.0, 2.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        hbt.submit_sell_order(0, 103, 100.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+
+        let histogram = hbt
+            .fill_price_histogram(0)
+            .iter()
+            .map(|bucket| (bucket.ticks, bucket.num_fills, bucket.qty))
+            .collect::<Vec<_>>();
+        assert_eq!(histogram, vec![(-1, 2, 3.0), (1, 1, 1.0), (3, 1, 1.0)]);
+
+        hbt.clear_fill_price_histogram(None);
+        assert!(hbt.fill_price_histogram(0).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use crate::types::{Order, OrderId, Side};
+
+/// How the order was filled.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum FillKind {
+    /// The order crossed the market depth, either by taking the opposite side on arrival or by
+    /// the opposite side moving through its price while it rests.
+    Crossing,
+    /// The resting order was filled at its price by a market trade reaching its queue position.
+    Touch,
+    /// The order was filled in the uncross of an auction, including a periodic batch auction.
+    Auction,
+}
+
+/// A fill of an order in the exchange.
+#[derive(Clone, Debug)]
+pub struct FillRecord {
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The executed price.
+    pub price: f64,
+    /// The executed quantity.
+    pub qty: f64,
+    pub maker: bool,
+    /// The fee charged for the fill. A rebate is a negative fee.
+    pub fee: f64,
+    pub kind: FillKind,
+    /// The time at which the fill occurs on the exchange.
+    pub exch_ts: i64,
+}
+
+/// Records every fill in the exchange as a trade log for post-analysis.
+#[derive(Default)]
+pub struct FillRecorder {
+    enabled: bool,
+    fills: Vec<FillRecord>,
+}
+
+impl FillRecorder {
+    /// Constructs an instance of `FillRecorder`.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            fills: Vec::new(),
+        }
+    }
+
+    /// Records the fill of the order with the fee charged for it.
+    pub fn record(&mut self, order: &Order, fee: f64, kind: FillKind) {
+        if !self.enabled {
+            return;
+        }
+        self.fills.push(FillRecord {
+            order_id: order.order_id,
+            side: order.side,
+            price: order.exec_price(),
+            qty: order.exec_qty,
+            maker: order.maker,
+            fee,
+            kind,
+            exch_ts: order.exch_timestamp,
+        });
+    }
+
+    /// Returns the recorded fills in the order in which they occur.
+    pub fn fills(&self) -> &[FillRecord] {
+        self.fills.as_slice()
+    }
+
+    /// Clears the recorded fills.
+    pub fn clear(&mut self) {
+        self.fills.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest, DataSource,
+            ExchangeKind::PartialFillExchange,
+            L3AssetBuilder,
+            assettype::LinearAsset,
+            data::Data,
+            fillrecord::FillKind,
+            models::{CommonFees, ConstantLatency, L3FIFOQueueModel, TradingValueFeeModel},
+            testutil::l3_event,
+        },
+        depth::{HashMapMarketDepth, MarketDepth},
+        prelude::{Bot, OrdType, Side, TimeInForce},
+        types::{ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, FILL_EVENT, LOCAL_EVENT, SELL_EVENT},
+    };
+
+    #[test]
+    fn fill_records_by_kind() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                5.0,
+                2,
+            ),
+            // Joins the queue behind the backtest order at 100.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                100,
+                100.0,
+                1.0,
+                3,
+            ),
+            // Fills the feed order, and the backtest order ahead of it in the queue.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                300,
+                100.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                L3AssetBuilder::default()
+                    .data(vec![DataSource::Data(data)])
+                    .latency_model(ConstantLatency::new(10, 20))
+                    .fill_counterparty(false)
+                    .asset_type(LinearAsset::new(1.0))
+                    .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0001, 0.0005)))
+                    .queue_model(L3FIFOQueueModel::new())
+                    .exchange(PartialFillExchange)
+                    .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                    .fill_records(true)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        // Buys at the best bid and holds.
+        let best_bid = hbt.depth(0).best_bid();
+        hbt.submit_buy_order(0, 10, best_bid, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(500)?;
+        assert_eq!(hbt.position(0), 1.0);
+        // Adds to the position by taking the best ask.
+        hbt.submit_buy_order(0, 11, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        hbt.close()?;
+
+        let fills = hbt.fill_records(0);
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].order_id, 10);
+        assert_eq!(fills[0].side, Side::Buy);
+        assert_eq!(fills[0].kind, FillKind::Touch);
+        assert!(fills[0].maker);
+        assert_eq!((fills[0].price, fills[0].qty), (100.0, 1.0));
+        assert!((fills[0].fee - 0.0001 * 100.0).abs() < 1e-12);
+        assert_eq!(fills[0].exch_ts, 300);
+        assert_eq!(fills[1].order_id, 11);
+        assert_eq!(fills[1].kind, FillKind::Crossing);
+        assert!(!fills[1].maker);
+        assert_eq!((fills[1].price, fills[1].qty), (101.0, 1.0));
+        assert!((fills[1].fee - 0.0005 * 101.0).abs() < 1e-12);
+
+        hbt.clear_fill_records(None);
+        assert!(hbt.fill_records(0).is_empty());
+        Ok(())
+    }
+}
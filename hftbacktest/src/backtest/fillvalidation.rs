@@ -0,0 +1,303 @@
+use crate::{
+    depth::MarketDepth,
+    types::{Bot, ElapseResult, OrderId, OrderRequest, Status},
+};
+
+/// An order recorded in live trading along with its actual outcome, to be replayed by
+/// [`validate_fills`].
+pub struct RecordedOrder {
+    /// The time at which the order was submitted.
+    pub submit_ts: i64,
+    pub order: OrderRequest,
+    /// The time at which the order was canceled in live trading. `None` if it wasn't canceled.
+    pub cancel_ts: Option<i64>,
+    /// The time at which the order was fully filled in live trading. `None` if it wasn't filled.
+    pub actual_fill_ts: Option<i64>,
+}
+
+/// The simulated fill of a recorded order compared against its actual fill.
+#[derive(Clone, Debug)]
+pub struct FillValidationRecord {
+    pub order_id: OrderId,
+    /// The time at which the order was fully filled in live trading. `None` if it wasn't filled.
+    pub actual_fill_ts: Option<i64>,
+    /// The exchange timestamp at which the order is fully filled in the simulation. `None` if it
+    /// isn't filled.
+    pub simulated_fill_ts: Option<i64>,
+}
+
+impl FillValidationRecord {
+    /// Returns the simulated fill time minus the actual fill time. A positive value means that
+    /// the simulation fills the order later than live trading. `None` unless the order is filled
+    /// both in the simulation and in live trading.
+    pub fn error(&self) -> Option<i64> {
+        Some(self.simulated_fill_ts? - self.actual_fill_ts?)
+    }
+}
+
+/// Aggregate statistics of the [`FillValidationRecord`]s.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FillValidationSummary {
+    pub num_orders: usize,
+    /// The number of orders filled both in the simulation and in live trading.
+    pub num_matched: usize,
+    /// The number of orders filled only in the simulation.
+    pub num_false_fills: usize,
+    /// The number of orders filled only in live trading.
+    pub num_missed_fills: usize,
+    /// The mean of the fill time errors of the matched orders, which is positive if the
+    /// simulation tends to fill later than live trading. `NaN` if no order is matched.
+    pub bias: f64,
+    /// The mean of the absolute fill time errors of the matched orders. `NaN` if no order is
+    /// matched.
+    pub mean_abs_error: f64,
+    /// The largest absolute fill time error of the matched orders. `0` if no order is matched.
+    pub max_abs_error: i64,
+}
+
+/// The result of [`validate_fills`].
+#[derive(Clone, Debug, Default)]
+pub struct FillValidation {
+    /// The records in the order of the submission.
+    pub records: Vec<FillValidationRecord>,
+}
+
+impl FillValidation {
+    /// Returns the aggregate statistics of the records.
+    pub fn summary(&self) -> FillValidationSummary {
+        let errors: Vec<i64> = self
+            .records
+            .iter()
+            .filter_map(FillValidationRecord::error)
+            .collect();
+        let count = |filled: fn(&FillValidationRecord) -> bool| {
+            self.records.iter().filter(|record| filled(record)).count()
+        };
+        let mean = |sum: i64| {
+            if errors.is_empty() {
+                f64::NAN
+            } else {
+                sum as f64 / errors.len() as f64
+            }
+        };
+        FillValidationSummary {
+            num_orders: self.records.len(),
+            num_matched: errors.len(),
+            num_false_fills: count(|record| {
+                record.simulated_fill_ts.is_some() && record.actual_fill_ts.is_none()
+            }),
+            num_missed_fills: count(|record| {
+                record.simulated_fill_ts.is_none() && record.actual_fill_ts.is_some()
+            }),
+            bias: mean(errors.iter().sum()),
+            mean_abs_error: mean(errors.iter().map(|error| error.abs()).sum()),
+            max_abs_error: errors.iter().map(|error| error.abs()).max().unwrap_or(0),
+        }
+    }
+}
+
+/// Validates the queue model against the fills recorded in live trading. The recorded orders are
+/// replayed on the asset, each being submitted at its submission time and canceled at its
+/// cancellation time, and the backtest then elapses until `end_ts`. The simulated fill time of
+/// each order is compared against its actual fill time.
+///
+/// Only the orders filled in full count as filled, and the replayed orders are left in the
+/// backtest. The order IDs must not collide with the orders already submitted to the asset.
+pub fn validate_fills<MD, I>(
+    hbt: &mut I,
+    asset_no: usize,
+    mut recorded: Vec<RecordedOrder>,
+    end_ts: i64,
+) -> Result<FillValidation, I::Error>
+where
+    MD: MarketDepth,
+    I: Bot<MD>,
+{
+    recorded.sort_by_key(|recorded| recorded.submit_ts);
+    let mut actions: Vec<(i64, Option<usize>, OrderId)> = Vec::new();
+    for (i, recorded) in recorded.iter().enumerate() {
+        actions.push((recorded.submit_ts, Some(i), recorded.order.order_id));
+        if let Some(cancel_ts) = recorded.cancel_ts {
+            actions.push((cancel_ts, None, recorded.order.order_id));
+        }
+    }
+    // The stable sort keeps the submission ahead of the cancellation at the same time.
+    actions.sort_by_key(|(timestamp, _, _)| *timestamp);
+
+    let mut records = Vec::with_capacity(recorded.len());
+    let mut requests: Vec<Option<OrderRequest>> = Vec::with_capacity(recorded.len());
+    for recorded in recorded {
+        records.push(FillValidationRecord {
+            order_id: recorded.order.order_id,
+            actual_fill_ts: recorded.actual_fill_ts,
+            simulated_fill_ts: None,
+        });
+        requests.push(Some(recorded.order));
+    }
+
+    // The backtest begins at the first event once it elapses.
+    if hbt.current_timestamp() == i64::MAX && hbt.elapse(0)? == ElapseResult::EndOfData {
+        return Ok(FillValidation { records });
+    }
+    for (timestamp, submit, order_id) in actions {
+        if timestamp > hbt.current_timestamp()
+            && hbt.elapse(timestamp - hbt.current_timestamp())? == ElapseResult::EndOfData
+        {
+            break;
+        }
+        match submit {
+            Some(i) => {
+                let order = requests[i].take().unwrap();
+                hbt.submit_order(asset_no, order, false)?;
+            }
+            None => {
+                let cancellable = hbt
+                    .orders(asset_no)
+                    .get(&order_id)
+                    .is_some_and(|order| order.cancellable());
+                if cancellable {
+                    hbt.cancel(asset_no, order_id, false)?;
+                }
+            }
+        }
+    }
+    if end_ts > hbt.current_timestamp() {
+        hbt.elapse(end_ts - hbt.current_timestamp())?;
+    }
+
+    for record in records.iter_mut() {
+        record.simulated_fill_ts = hbt
+            .orders(asset_no)
+            .get(&record.order_id)
+            .filter(|order| order.status == Status::Filled)
+            .map(|order| order.exch_timestamp);
+    }
+    Ok(FillValidation { records })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            fillvalidation::{RecordedOrder, validate_fills},
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, OrderId, OrderRequest, Side, Status, TimeInForce},
+        types::{ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, FILL_EVENT, LOCAL_EVENT, SELL_EVENT},
+    };
+
+    #[test]
+    fn validate_fills_against_recorded_fill_times() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                5.0,
+                2,
+            ),
+            // Joins the queue behind the backtest order at 100.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                50,
+                100.0,
+                1.0,
+                3,
+            ),
+            // Fills the feed order, and the backtest order ahead of it in the queue.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                300,
+                100.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_counterparty(false)
+                    .build()?,
+            )
+            .build()?;
+
+        let recorded = |submit_ts: i64,
+                        order_id: OrderId,
+                        price: f64,
+                        time_in_force: TimeInForce,
+                        cancel_ts: Option<i64>,
+                        actual_fill_ts: Option<i64>| RecordedOrder {
+            submit_ts,
+            order: OrderRequest {
+                order_id,
+                price,
+                qty: 1.0,
+                side: Side::Buy,
+                time_in_force,
+                order_type: OrdType::Limit,
+                max_lifetime: 0,
+                opening_auction: false,
+            },
+            cancel_ts,
+            actual_fill_ts,
+        };
+        let validation = validate_fills(
+            &mut hbt,
+            0,
+            vec![
+                // Filled at 300 in the simulation, later than in live trading.
+                recorded(10, 10, 100.0, TimeInForce::GTC, None, Some(250)),
+                // Taken at 20 on arrival, earlier than in live trading.
+                recorded(10, 11, 101.0, TimeInForce::IOC, None, Some(25)),
+                // Never filled in the simulation.
+                recorded(10, 12, 99.0, TimeInForce::GTC, None, Some(400)),
+                // Canceled both in the simulation and in live trading.
+                recorded(10, 13, 99.0, TimeInForce::GTC, Some(100), None),
+                // Filled only in the simulation.
+                recorded(500, 14, 101.0, TimeInForce::GTC, None, None),
+            ],
+            900,
+        )?;
+
+        let errors: Vec<_> = validation
+            .records
+            .iter()
+            .map(|record| (record.order_id, record.simulated_fill_ts, record.error()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (10, Some(300), Some(50)),
+                (11, Some(20), Some(-5)),
+                (12, None, None),
+                (13, None, None),
+                (14, Some(510), None),
+            ]
+        );
+        assert_eq!(hbt.orders(0).get(&13).unwrap().status, Status::Canceled);
+
+        let summary = validation.summary();
+        assert_eq!(summary.num_orders, 5);
+        assert_eq!(summary.num_matched, 2);
+        assert_eq!(summary.num_false_fills, 1);
+        assert_eq!(summary.num_missed_fills, 1);
+        assert_eq!(summary.bias, 22.5);
+        assert_eq!(summary.mean_abs_error, 27.5);
+        assert_eq!(summary.max_abs_error, 50);
+        Ok(())
+    }
+}
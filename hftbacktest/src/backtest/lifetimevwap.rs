@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use crate::types::{Order, OrderId, Side, Status};
+
+/// The market VWAP over an order's active lifetime, from submission to the terminal response,
+/// compared against the order's own fills.
+#[derive(Clone, Debug)]
+pub struct LifetimeVwap {
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The total executed quantity of the order.
+    pub fill_qty: f64,
+    /// The quantity-weighted average executed price of the order. `NaN` if the order has no fill.
+    pub fill_price: f64,
+    /// The VWAP of the market prints during the order's lifetime. `NaN` if there is no print.
+    pub vwap: f64,
+    /// The time at which the order is submitted.
+    pub submit_ts: i64,
+    /// The time at which the local receives the terminal response of the order.
+    pub terminal_ts: i64,
+}
+
+impl LifetimeVwap {
+    /// Returns the slippage of the order's average fill price relative to the lifetime VWAP per
+    /// unit quantity. A positive value means that the fill is worse than the VWAP, that is, buying
+    /// above or selling below it. `NaN` if either the order has no fill or there is no print
+    /// during the order's lifetime.
+    pub fn slippage(&self) -> f64 {
+        match self.side {
+            Side::Buy => self.fill_price - self.vwap,
+            Side::Sell => self.vwap - self.fill_price,
+            Side::None | Side::Unsupported => f64::NAN,
+        }
+    }
+}
+
+struct ActiveOrder {
+    side: Side,
+    submit_ts: i64,
+    fill_amount: f64,
+    fill_qty: f64,
+    print_amount: f64,
+    print_qty: f64,
+}
+
+/// Accumulates the market prints over the lifetime of each order observed by the local.
+#[derive(Default)]
+pub struct LifetimeVwapRecorder {
+    active: HashMap<OrderId, ActiveOrder>,
+    records: Vec<LifetimeVwap>,
+}
+
+impl LifetimeVwapRecorder {
+    /// Constructs an instance of `LifetimeVwapRecorder`. The recorder is enabled only if
+    /// `capacity` is greater than zero.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            active: Default::default(),
+            records: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns `true` if the recorder is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.records.capacity() > 0
+    }
+
+    /// Starts the lifetime of the submitted order.
+    pub fn on_submit(&mut self, order: &Order, timestamp: i64) {
+        if !self.enabled() {
+            return;
+        }
+        self.active.insert(
+            order.order_id,
+            ActiveOrder {
+                side: order.side,
+                submit_ts: timestamp,
+                fill_amount: 0.0,
+                fill_qty: 0.0,
+                print_amount: 0.0,
+                print_qty: 0.0,
+            },
+        );
+    }
+
+    /// Accumulates the market print into all active orders.
+    pub fn on_print(&mut self, px: f64, qty: f64) {
+        for active in self.active.values_mut() {
+            active.print_amount += px * qty;
+            active.print_qty += qty;
+        }
+    }
+
+    /// Accumulates the fill conveyed by the order response. If the resulting local order is in a
+    /// terminal state, its lifetime ends at `timestamp`. An order rejected before the exchange
+    /// accepts it has no lifetime and is not recorded.
+    ///
+    /// * `resp` - The order response from the exchange.
+    /// * `local_status` - The status of the local order after the response is applied.
+    pub fn on_response(&mut self, resp: &Order, local_status: Status, timestamp: i64) {
+        if resp.req == Status::Rejected && resp.status == Status::None {
+            self.active.remove(&resp.order_id);
+            return;
+        }
+        let Some(active) = self.active.get_mut(&resp.order_id) else {
+            return;
+        };
+        if resp.req != Status::Rejected
+            && resp.exec_qty > 0.0
+            && (resp.status == Status::Filled || resp.status == Status::PartiallyFilled)
+        {
+            active.fill_amount += resp.exec_price() * resp.exec_qty;
+            active.fill_qty += resp.exec_qty;
+        }
+        if local_status == Status::Filled
+            || local_status == Status::Canceled
+            || local_status == Status::Expired
+        {
+            let active = self.active.remove(&resp.order_id).unwrap();
+            let avg = |amount: f64, qty: f64| if qty > 0.0 { amount / qty } else { f64::NAN };
+            self.records.push(LifetimeVwap {
+                order_id: resp.order_id,
+                side: active.side,
+                fill_qty: active.fill_qty,
+                fill_price: avg(active.fill_amount, active.fill_qty),
+                vwap: avg(active.print_amount, active.print_qty),
+                submit_ts: active.submit_ts,
+                terminal_ts: timestamp,
+            });
+        }
+    }
+
+    /// Returns the records of the orders whose lifetime has ended.
+    pub fn records(&self) -> &[LifetimeVwap] {
+        self.records.as_slice()
+    }
+
+    /// Clears the records of the orders whose lifetime has ended.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, Status, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, FILL_EVENT, HALT_EVENT, LOCAL_EVENT,
+            RejectReason, SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn lifetime_vwap_slippage() -> Result<(), Box<dyn Error>> {
+        let print = EXCH_EVENT | LOCAL_EVENT | FILL_EVENT;
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                102.0,
+                5.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                103.0,
+                5.0,
+                3,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                50,
+                100.0,
+                1.0,
+                4,
+            ),
+            l3_event(print | SELL_EVENT, 100, 102.0, 1.0, 2),
+            l3_event(print | SELL_EVENT, 200, 103.0, 3.0, 3),
+            // Trades the bid behind the backtest order, which fills the backtest order.
+            l3_event(print | BUY_EVENT, 300, 100.0, 1.0, 4),
+            l3_event(EXCH_EVENT | HALT_EVENT, 600, 0.0, 0.0, 0),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_counterparty(false)
+                    .lifetime_vwap_capacity(16)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(400)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::Filled);
+
+        // No print occurs during the lifetime of this order.
+        hbt.submit_buy_order(0, 11, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.cancel(0, 11, true)?;
+
+        // The order rejected while trading is halted has no lifetime.
+        hbt.elapse(200)?;
+        hbt.submit_buy_order(0, 12, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        assert_eq!(
+            hbt.orders(0).get(&12).unwrap().reject_reason,
+            RejectReason::TradingHalted
+        );
+
+        let records = hbt.lifetime_vwaps(0);
+        assert_eq!(records.len(), 2);
+
+        let vwap = (102.0 * 1.0 + 103.0 * 3.0 + 100.0 * 1.0) / 5.0;
+        assert_eq!(records[0].order_id, 10);
+        assert_eq!(records[0].fill_qty, 1.0);
+        assert_eq!(records[0].fill_price, 100.0);
+        assert_eq!(records[0].vwap, vwap);
+        assert_eq!(records[0].slippage(), 100.0 - vwap);
+
+        assert_eq!(records[1].order_id, 11);
+        assert_eq!(records[1].fill_qty, 0.0);
+        assert!(records[1].vwap.is_nan());
+        assert!(records[1].slippage().is_nan());
+
+        hbt.clear_lifetime_vwaps(None);
+        assert!(hbt.lifetime_vwaps(0).is_empty());
+        Ok(())
+    }
+}
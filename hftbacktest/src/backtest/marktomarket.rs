@@ -0,0 +1,165 @@
+/// Determines how often the local re-marks the held position to the mid price, which drives the
+/// unrealized profit and loss and the equity seen by the state samples and the drawdown tracking.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum MarkFrequency {
+    /// Re-marks on every change of the best bid or the best ask.
+    #[default]
+    BboChange,
+    /// Re-marks on the first market depth update at or after each interval in nanoseconds
+    /// elapses since the last mark.
+    Interval(i64),
+    /// Re-marks only when an order is filled.
+    OnFill,
+}
+
+/// Decides when the held position is re-marked according to the [`MarkFrequency`], and tracks the
+/// maximum drawdown of the marked equity. Regardless of the frequency, the position is first
+/// marked once both sides of the market depth are populated.
+#[derive(Default)]
+pub struct MarkToMarket {
+    frequency: MarkFrequency,
+    next_ts: Option<i64>,
+    peak_equity: f64,
+    max_drawdown: f64,
+}
+
+impl MarkToMarket {
+    /// Constructs an instance of `MarkToMarket`.
+    pub fn new(frequency: MarkFrequency) -> Self {
+        Self {
+            frequency,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the frequency at which the held position is re-marked.
+    #[inline]
+    pub fn frequency(&self) -> MarkFrequency {
+        self.frequency
+    }
+
+    /// Returns `true` if the held position is due to be re-marked on the market depth update at
+    /// `timestamp`. This must only be invoked while both sides of the market depth are populated.
+    pub fn on_depth_update(&mut self, timestamp: i64) -> bool {
+        let due = match self.frequency {
+            MarkFrequency::BboChange => true,
+            MarkFrequency::Interval(_) | MarkFrequency::OnFill => {
+                self.next_ts.is_none_or(|next_ts| timestamp >= next_ts)
+            }
+        };
+        if due {
+            self.next_ts = Some(match self.frequency {
+                MarkFrequency::Interval(interval) => timestamp + interval,
+                MarkFrequency::BboChange | MarkFrequency::OnFill => i64::MAX,
+            });
+        }
+        due
+    }
+
+    /// Returns `true` if the held position is due to be re-marked on a fill.
+    #[inline]
+    pub fn on_fill(&self) -> bool {
+        self.frequency == MarkFrequency::OnFill
+    }
+
+    /// Updates the maximum drawdown with the marked equity, measured from the highest marked
+    /// equity so far, starting from zero. A non-finite equity is ignored.
+    pub fn update_drawdown(&mut self, equity: f64) {
+        if !equity.is_finite() {
+            return;
+        }
+        self.peak_equity = self.peak_equity.max(equity);
+        self.max_drawdown = self.max_drawdown.max(self.peak_equity - equity);
+    }
+
+    /// Returns the maximum drawdown of the marked equity.
+    #[inline]
+    pub fn max_drawdown(&self) -> f64 {
+        self.max_drawdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            MarkFrequency,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, CANCEL_ORDER_EVENT, EXCH_EVENT, LOCAL_EVENT, SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn mark_frequency_affects_max_drawdown() -> Result<(), Box<dyn Error>> {
+        let run = |mark_frequency: MarkFrequency| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    5.0,
+                    2,
+                ),
+                // The best bid drops to 95 and then recovers to 100 with no fill in between.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | CANCEL_ORDER_EVENT,
+                    300,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    300,
+                    95.0,
+                    1.0,
+                    3,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    500,
+                    100.0,
+                    1.0,
+                    4,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .mark_frequency(mark_frequency)
+                        .build()?,
+                )
+                .state_record_interval(100)
+                .build()?;
+
+            hbt.elapse(1)?;
+            // Buys at 101 while the mid price is 100.5.
+            hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+            hbt.elapse(1000)?;
+            Ok((hbt.max_drawdown(0).unwrap(), hbt.state_samples(0)[3].equity))
+        };
+
+        // The mid price of 98 between 300 and 500 is marked on every BBO change.
+        assert_eq!(run(MarkFrequency::BboChange)?, (3.0, -3.0));
+        // Only the mid price at the fill is marked.
+        assert_eq!(run(MarkFrequency::OnFill)?, (0.5, -0.5));
+        Ok(())
+    }
+}
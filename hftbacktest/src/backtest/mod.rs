@@ -3,6 +3,7 @@ use std::{
     io::Error as IoError,
     marker::PhantomData,
     ops::{Deref, DerefMut},
+    path::PathBuf,
 };
 
 pub use data::DataSource;
@@ -11,22 +12,46 @@ use models::FeeModel;
 use thiserror::Error;
 
 pub use crate::backtest::{
+    marktomarket::MarkFrequency,
     models::L3QueueModel,
-    proc::{L3Local, L3NoPartialFillExchange, L3PartialFillExchange},
+    proc::{
+        AuctionAlgorithm, AuctionFallbackPolicy, ExchangeOrderView, FillResponseMode, HaltPolicy,
+        L3Local, L3NoPartialFillExchange, L3PartialFillExchange, LockedMarketPolicy,
+        MissingOrderPolicy, ModifyMode, OutOfRoiPolicy, PriorityResetPolicy, SelfTradePrevention,
+        StopTrigger, SubLotResidualPolicy,
+    },
 };
 use crate::{
     backtest::{
+        adverseselection::{AdverseSelection, AdverseSelectionSummary},
         assettype::AssetType,
+        auction::AuctionResult,
         data::{Data, FeedLatencyAdjustment, NpyDTyped},
         evs::{EventIntentKind, EventSet},
-        models::{LatencyModel, QueueModel},
-        order::order_bus,
-        proc::{Local, LocalProcessor, NoPartialFillExchange, PartialFillExchange, Processor},
-        state::State,
+        fillgap::{FillGap, FillGapSummary, FillPriceBucket},
+        fillrecord::FillRecord,
+        lifetimevwap::LifetimeVwap,
+        models::{LatencyFloor, LatencyModel, QueueModel},
+        opportunitycost::{OpportunityCost, OpportunityCostSummary},
+        order::{order_bus, order_bus_by_receive_time},
+        orderlog::OrderLogEntry,
+        printdivergence::{PrintDivergence, PrintDivergenceSummary},
+        proc::{
+            Local, LocalProcessor, NoPartialFillExchange, OrderOptions, PartialFillExchange,
+            Processor,
+        },
+        resilience::{ResilienceSummary, SweepRecovery},
+        shadowfill::ShadowFill,
+        state::{PnlSummary, State},
+        staterecord::{StateRecorder, StateSample},
+        timeline::OrderTimelineEntry,
+        touchdistance::TouchDistanceStats,
+    },
+    depth::{
+        HashMapMarketDepth, INVALID_MAX, INVALID_MIN, L2MarketDepth, L3MarketDepth, MarketDepth,
     },
-    depth::{HashMapMarketDepth, L2MarketDepth, L3MarketDepth, MarketDepth},
     prelude::{
-        Bot, OrdType, Order, OrderId, OrderRequest, Side, StateValues, TimeInForce,
+        AssetParams, Bot, OrdType, Order, OrderId, OrderRequest, Side, StateValues, TimeInForce,
         UNTIL_END_OF_DATA, WaitOrderResponse,
     },
     types::{BuildError, ElapseResult, Event},
@@ -46,13 +71,64 @@ pub mod proc;
 /// Trading state.
 pub mod state;
 
+/// Local order event log.
+pub mod orderlog;
+
+/// Fill price comparison against the mid price.
+pub mod fillgap;
+
+/// Per-order attribution of the auction uncross.
+pub mod auction;
+
+/// Fill price comparison against the market VWAP over the order's lifetime.
+pub mod lifetimevwap;
+
+/// Per-order lifecycle timeline.
+pub mod timeline;
+
+/// Order outcomes by the distance from the touch.
+pub mod touchdistance;
+
+/// Fill comparison against the nearest market print.
+pub mod printdivergence;
+
+/// Mid price move after the unfilled orders end.
+pub mod opportunitycost;
+
+/// Counterfactual taker fills under an alternative order latency.
+pub mod shadowfill;
+
+/// Audit trail of the order actions.
+pub mod audit;
+
+/// Queue model validation against the fills recorded in live trading.
+pub mod fillvalidation;
+
+/// Per-fill trade log.
+pub mod fillrecord;
+
+/// Equity curve sampled at a fixed interval.
+pub mod staterecord;
+
+/// Mid price move against the maker fills.
+pub mod adverseselection;
+
+/// Re-marking of the held position to market and the drawdown of the marked equity.
+pub mod marktomarket;
+
+/// Recovery of the price levels swept by takers, the resilience of the order book.
+pub mod resilience;
+
 /// Recorder for a bot's trading statistics.
 pub mod recorder;
 
 pub mod data;
 mod evs;
+#[cfg(test)]
+mod testutil;
 
 /// Errors that can occur during backtesting.
+#[non_exhaustive]
 #[derive(Error, Debug)]
 pub enum BacktestError {
     #[error("Order related to a given order id already exists")]
@@ -67,6 +143,18 @@ pub enum BacktestError {
     InvalidOrderStatus,
     #[error("end of data")]
     EndOfData,
+    #[error("the total notional cap is reached")]
+    NotionalLimitReached,
+    #[error("the order crosses the best price on the opposite side outside an auction")]
+    CrossingOrder,
+    #[error("the order price is outside the range of interest of the market depth")]
+    OutOfRoi,
+    #[error("venue {venue} is not found for asset {asset_no}")]
+    VenueNotFound { asset_no: usize, venue: usize },
+    #[error("the ordering timestamp goes backward from {prev} to {ts}")]
+    EventOutOfOrder { prev: i64, ts: i64 },
+    #[error("an asset is built without a processor factory and cannot be reset")]
+    NotResettable,
     #[error("data error: {0:?}")]
     DataError(#[from] IoError),
 }
@@ -76,6 +164,44 @@ pub struct Asset<L: ?Sized, E: ?Sized, D: NpyDTyped + Clone /* todo: ugly bounds
     pub local: Box<L>,
     pub exch: Box<E>,
     pub reader: Reader<D>,
+    factory: Option<ProcessorFactory<L, E>>,
+}
+
+/// Builds the local processor and the exchange processor of an asset in their initial state. See
+/// [`Backtest::reset`].
+pub type ProcessorFactory<L, E> = Box<dyn Fn() -> (Box<L>, Box<E>)>;
+
+/// The local processor and the exchange processor of an asset.
+type Processors<MD> = (Box<dyn LocalProcessor<MD>>, Box<dyn Processor>);
+
+/// Builds the [`Processors`] of an asset in their initial state.
+type AssetProcessorFactory<MD> = ProcessorFactory<dyn LocalProcessor<MD>, dyn Processor>;
+
+impl<L: ?Sized, E: ?Sized, D: NpyDTyped + Clone> Asset<L, E, D> {
+    /// Constructs an instance of `Asset` from the boxed local processor and exchange processor,
+    /// which can be trait objects. The asset isn't resettable unless its factory is set by
+    /// [`Asset::factory`].
+    pub fn from_boxed(local: Box<L>, exch: Box<E>, reader: Reader<D>) -> Self {
+        Self {
+            local,
+            exch,
+            reader,
+            factory: None,
+        }
+    }
+
+    /// Sets the factory that rebuilds the processors of this asset in their initial state, which
+    /// makes the asset resettable by [`Backtest::reset`]. The asset builders set it in
+    /// `build_resettable`.
+    pub fn factory<F>(self, factory: F) -> Self
+    where
+        F: Fn() -> (Box<L>, Box<E>) + 'static,
+    {
+        Self {
+            factory: Some(Box::new(factory)),
+            ..self
+        }
+    }
 }
 
 impl<L, E, D: NpyDTyped + Clone> Asset<L, E, D> {
@@ -86,6 +212,7 @@ impl<L, E, D: NpyDTyped + Clone> Asset<L, E, D> {
             local: Box::new(local),
             exch: Box::new(exch),
             reader,
+            factory: None,
         }
     }
 
@@ -123,6 +250,35 @@ pub enum ExchangeKind {
     PartialFillExchange,
 }
 
+/// The timestamp field used to order the events across the processors.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum OrderingTimestamp {
+    /// Each processor uses the timestamp at which it sees the event: the exchange processor uses
+    /// [`Event::exch_ts`] and the local processor uses [`Event::local_ts`].
+    #[default]
+    Native,
+    /// All processors use [`Event::exch_ts`]. The local processor sees the feed without the feed
+    /// latency.
+    Exchange,
+    /// All processors use [`Event::local_ts`]. The exchange processor sees the feed only when the
+    /// local processor does.
+    Local,
+}
+
+/// The best bid and ask, including the strategy's own open orders, with whether the top of each
+/// side contains an own order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BestBidAsk {
+    /// The best bid price. `NaN` if there is neither a market bid nor an own bid order.
+    pub best_bid: f64,
+    /// The best ask price. `NaN` if there is neither a market ask nor an own ask order.
+    pub best_ask: f64,
+    /// Whether an own order is at the best bid.
+    pub own_bid: bool,
+    /// Whether an own order is at the best ask.
+    pub own_ask: bool,
+}
+
 /// A level-2 asset builder.
 pub struct L2AssetBuilder<LM, AT, QM, MD, FM> {
     latency_model: Option<LM>,
@@ -130,9 +286,12 @@ pub struct L2AssetBuilder<LM, AT, QM, MD, FM> {
     data: Vec<DataSource<Event>>,
     parallel_load: bool,
     latency_offset: i64,
+    latency_floor: bool,
     fee_model: Option<FM>,
     exch_kind: ExchangeKind,
     last_trades_cap: usize,
+    mid_history_cap: usize,
+    max_total_notional: f64,
     queue_model: Option<QM>,
     depth_builder: Option<Box<dyn Fn() -> MD>>,
 }
@@ -153,9 +312,12 @@ where
             data: vec![],
             parallel_load: false,
             latency_offset: 0,
+            latency_floor: false,
             fee_model: None,
             exch_kind: ExchangeKind::NoPartialFillExchange,
             last_trades_cap: 0,
+            mid_history_cap: 0,
+            max_total_notional: f64::INFINITY,
             queue_model: None,
             depth_builder: None,
         }
@@ -166,6 +328,16 @@ where
         Self { data, ..self }
     }
 
+    /// Sets the feed data to the files for each date in the inclusive range from `start` to `end`,
+    /// given as `YYYYMMDD`, whose paths are made by replacing `{date}` in the path template. See
+    /// [`DataSource::date_range`]. An error is returned if a date is invalid or a file is not
+    /// found.
+    pub fn data_date_range(self, template: &str, start: u32, end: u32) -> Result<Self, BuildError> {
+        let data = DataSource::date_range(template, start, end)
+            .map_err(|err| BuildError::Error(err.into()))?;
+        Ok(Self { data, ..self })
+    }
+
     /// Sets whether to load the next data in parallel with backtesting. This can speed up the
     /// backtest by reducing data loading time, but it also increases memory usage.
     /// The default value is `true`.
@@ -194,6 +366,17 @@ where
         }
     }
 
+    /// Sets whether to enforce a minimum order entry and response latency of one time unit, so
+    /// that an order is never processed, nor its response received, in the same step it is sent
+    /// even if the latency model yields zero latency. This prevents unrealistic same-step fills
+    /// with a zero-latency model. The default value is `false`.
+    pub fn latency_floor(self, latency_floor: bool) -> Self {
+        Self {
+            latency_floor,
+            ..self
+        }
+    }
+
     /// Sets an asset type.
     pub fn asset_type(self, asset_type: AT) -> Self {
         Self {
@@ -224,6 +407,26 @@ where
         }
     }
 
+    /// Sets the number of the most recent mid prices kept by the local, which are sampled on each
+    /// change of the best bid or the best ask. See [`Bot::mid_history`].
+    /// The default value is `0`, indicating that no mid price is kept.
+    pub fn mid_history_capacity(self, capacity: usize) -> Self {
+        Self {
+            mid_history_cap: capacity,
+            ..self
+        }
+    }
+
+    /// Sets the cap on the total notional traded per run. Once the cumulative trading value
+    /// reaches the cap, further order submissions are rejected with
+    /// [`BacktestError::NotionalLimitReached`]. The default value is infinity, indicating no cap.
+    pub fn max_total_notional(self, max_total_notional: f64) -> Self {
+        Self {
+            max_total_notional,
+            ..self
+        }
+    }
+
     /// Sets a queue model.
     pub fn queue_model(self, queue_model: QM) -> Self {
         Self {
@@ -244,22 +447,36 @@ where
     }
 
     /// Builds an `Asset`.
-    pub fn build(self) -> Result<Asset<dyn LocalProcessor<MD>, dyn Processor, Event>, BuildError> {
-        let reader = if self.latency_offset == 0 {
-            Reader::builder()
-                .parallel_load(self.parallel_load)
-                .data(self.data)
-                .build()
-                .map_err(|err| BuildError::Error(err.into()))?
+    pub fn build(
+        mut self,
+    ) -> Result<Asset<dyn LocalProcessor<MD>, dyn Processor, Event>, BuildError> {
+        let reader = self.reader()?;
+        let queue_model = self
+            .queue_model
+            .take()
+            .ok_or(BuildError::BuilderIncomplete("queue_model"))?;
+        let (local, exch) = self.processors(queue_model)?;
+        Ok(Asset {
+            local,
+            exch,
+            reader,
+            factory: None,
+        })
+    }
+
+    fn reader(&mut self) -> Result<Reader<Event>, BuildError> {
+        let builder = Reader::builder()
+            .parallel_load(self.parallel_load)
+            .data(std::mem::take(&mut self.data));
+        let builder = if self.latency_offset == 0 {
+            builder
         } else {
-            Reader::builder()
-                .parallel_load(self.parallel_load)
-                .data(self.data)
-                .preprocessor(FeedLatencyAdjustment::new(self.latency_offset))
-                .build()
-                .map_err(|err| BuildError::Error(err.into()))?
+            builder.preprocessor(FeedLatencyAdjustment::new(self.latency_offset))
         };
+        builder.build().map_err(|err| BuildError::Error(err.into()))
+    }
 
+    fn processors(&self, queue_model: QM) -> Result<Processors<MD>, BuildError> {
         let create_depth = self
             .depth_builder
             .as_ref()
@@ -277,18 +494,19 @@ where
             .clone()
             .ok_or(BuildError::BuilderIncomplete("fee_model"))?;
 
-        let (order_e2l, order_l2e) = order_bus(order_latency);
+        let (order_e2l, order_l2e) = order_bus(LatencyFloor::new(
+            order_latency,
+            if self.latency_floor { 1 } else { 0 },
+        ));
 
         let local = Local::new(
             create_depth(),
-            State::new(asset_type, fee_model),
+            State::new(asset_type, fee_model).max_total_notional(self.max_total_notional),
             self.last_trades_cap,
             order_l2e,
-        );
+        )
+        .mid_history_capacity(self.mid_history_cap);
 
-        let queue_model = self
-            .queue_model
-            .ok_or(BuildError::BuilderIncomplete("queue_model"))?;
         let asset_type = self
             .asset_type
             .clone()
@@ -307,11 +525,7 @@ where
                     order_e2l,
                 );
 
-                Ok(Asset {
-                    local: Box::new(local),
-                    exch: Box::new(exch),
-                    reader,
-                })
+                Ok((Box::new(local), Box::new(exch)))
             }
             ExchangeKind::PartialFillExchange => {
                 let exch = PartialFillExchange::new(
@@ -321,16 +535,44 @@ where
                     order_e2l,
                 );
 
-                Ok(Asset {
-                    local: Box::new(local),
-                    exch: Box::new(exch),
-                    reader,
-                })
+                Ok((Box::new(local), Box::new(exch)))
             }
         }
     }
 }
 
+impl<LM, AT, QM, MD, FM> L2AssetBuilder<LM, AT, QM, MD, FM>
+where
+    AT: AssetType + Clone + 'static,
+    MD: MarketDepth + L2MarketDepth + 'static,
+    QM: QueueModel<MD> + Clone + 'static,
+    LM: LatencyModel + Clone + 'static,
+    FM: FeeModel + Clone + 'static,
+{
+    /// Builds an `Asset` whose processors can be rebuilt from this configuration, so that
+    /// [`Backtest::reset`] can restore them to their initial state while keeping the loaded data.
+    pub fn build_resettable(
+        mut self,
+    ) -> Result<Asset<dyn LocalProcessor<MD>, dyn Processor, Event>, BuildError> {
+        let reader = self.reader()?;
+        let queue_model = self
+            .queue_model
+            .take()
+            .ok_or(BuildError::BuilderIncomplete("queue_model"))?;
+        let (local, exch) = self.processors(queue_model.clone())?;
+        Ok(Asset {
+            local,
+            exch,
+            reader,
+            factory: None,
+        }
+        .factory(move || {
+            // The configuration has already been validated by the first build.
+            self.processors(queue_model.clone()).unwrap()
+        }))
+    }
+}
+
 impl<LM, AT, QM, MD, FM> Default for L2AssetBuilder<LM, AT, QM, MD, FM>
 where
     AT: AssetType + Clone + 'static,
@@ -351,9 +593,47 @@ pub struct L3AssetBuilder<LM, AT, QM, MD, FM> {
     data: Vec<DataSource<Event>>,
     parallel_load: bool,
     latency_offset: i64,
+    latency_floor: bool,
+    strict_receive_order: bool,
     fee_model: Option<FM>,
     exch_kind: ExchangeKind,
     last_trades_cap: usize,
+    mid_history_cap: usize,
+    max_total_notional: f64,
+    order_log_cap: usize,
+    lifetime_vwap_cap: usize,
+    opportunity_cost_horizon: i64,
+    adverse_selection_horizon: i64,
+    mark_frequency: MarkFrequency,
+    resilience_recovery_fraction: f64,
+    order_timeline_cap: usize,
+    audit_log: Option<(PathBuf, usize)>,
+    touch_distance_stats: bool,
+    fill_gap_cap: usize,
+    fill_price_histogram: bool,
+    fill_records: bool,
+    print_divergence_window: i64,
+    shadow_latency_model: Option<LM>,
+    missing_order_policy: MissingOrderPolicy,
+    out_of_roi_policy: OutOfRoiPolicy,
+    fill_counterparty: bool,
+    feed_order_id_offset: u64,
+    fill_dedup_window: i64,
+    stop_trigger: StopTrigger,
+    self_trade_prevention: SelfTradePrevention,
+    two_phase_ack: bool,
+    priority_reset_policy: PriorityResetPolicy,
+    modify_mode: ModifyMode,
+    halt_policy: HaltPolicy,
+    sub_lot_residual_policy: SubLotResidualPolicy,
+    fill_response_mode: FillResponseMode,
+    locked_market_policy: LockedMarketPolicy,
+    warmup: bool,
+    min_price_improvement: i64,
+    session_open: i64,
+    auction_fallback_policy: AuctionFallbackPolicy,
+    auction_algorithm: AuctionAlgorithm,
+    batch_interval: i64,
     queue_model: Option<QM>,
     depth_builder: Option<Box<dyn Fn() -> MD>>,
 }
@@ -375,9 +655,47 @@ where
             data: vec![],
             parallel_load: false,
             latency_offset: 0,
+            latency_floor: false,
+            strict_receive_order: false,
             fee_model: None,
             exch_kind: ExchangeKind::NoPartialFillExchange,
             last_trades_cap: 0,
+            mid_history_cap: 0,
+            max_total_notional: f64::INFINITY,
+            order_log_cap: 0,
+            lifetime_vwap_cap: 0,
+            opportunity_cost_horizon: 0,
+            adverse_selection_horizon: 0,
+            mark_frequency: MarkFrequency::BboChange,
+            resilience_recovery_fraction: 0.0,
+            order_timeline_cap: 0,
+            audit_log: None,
+            touch_distance_stats: false,
+            fill_gap_cap: 0,
+            fill_price_histogram: false,
+            fill_records: false,
+            print_divergence_window: 0,
+            shadow_latency_model: None,
+            missing_order_policy: MissingOrderPolicy::Error,
+            out_of_roi_policy: OutOfRoiPolicy::Reject,
+            fill_counterparty: true,
+            feed_order_id_offset: 0,
+            fill_dedup_window: 0,
+            stop_trigger: StopTrigger::LastTrade,
+            self_trade_prevention: SelfTradePrevention::None,
+            two_phase_ack: false,
+            priority_reset_policy: PriorityResetPolicy::KeepOnSizeDown,
+            modify_mode: ModifyMode::Amend,
+            halt_policy: HaltPolicy::KeepOrders,
+            sub_lot_residual_policy: SubLotResidualPolicy::Keep,
+            fill_response_mode: FillResponseMode::PerFill,
+            locked_market_policy: LockedMarketPolicy::Match,
+            warmup: false,
+            min_price_improvement: 0,
+            session_open: i64::MIN,
+            auction_fallback_policy: AuctionFallbackPolicy::None,
+            auction_algorithm: AuctionAlgorithm::FeedPrice,
+            batch_interval: 0,
             queue_model: None,
             depth_builder: None,
         }
@@ -388,6 +706,16 @@ where
         Self { data, ..self }
     }
 
+    /// Sets the feed data to the files for each date in the inclusive range from `start` to `end`,
+    /// given as `YYYYMMDD`, whose paths are made by replacing `{date}` in the path template. See
+    /// [`DataSource::date_range`]. An error is returned if a date is invalid or a file is not
+    /// found.
+    pub fn data_date_range(self, template: &str, start: u32, end: u32) -> Result<Self, BuildError> {
+        let data = DataSource::date_range(template, start, end)
+            .map_err(|err| BuildError::Error(err.into()))?;
+        Ok(Self { data, ..self })
+    }
+
     /// Sets whether to load the next data in parallel with backtesting. This can speed up the
     /// backtest by reducing data loading time, but it also increases memory usage.
     /// The default value is `true`.
@@ -416,6 +744,28 @@ where
         }
     }
 
+    /// Sets whether to enforce a minimum order entry and response latency of one time unit, so
+    /// that an order is never processed, nor its response received, in the same step it is sent
+    /// even if the latency model yields zero latency. This prevents unrealistic same-step fills
+    /// with a zero-latency model. The default value is `false`.
+    pub fn latency_floor(self, latency_floor: bool) -> Self {
+        Self {
+            latency_floor,
+            ..self
+        }
+    }
+
+    /// Sets whether the exchange processes the order requests strictly in the order of their
+    /// receipt timestamps, so that a request sent later but with a shorter entry latency, as with
+    /// a jittered latency model, is processed first. Otherwise, the requests are processed in the
+    /// order in which they are sent. The default value is `false`.
+    pub fn strict_receive_order(self, strict_receive_order: bool) -> Self {
+        Self {
+            strict_receive_order,
+            ..self
+        }
+    }
+
     /// Sets an asset type.
     pub fn asset_type(self, asset_type: AT) -> Self {
         Self {
@@ -446,803 +796,1007 @@ where
         }
     }
 
-    /// Sets a queue model.
-    pub fn queue_model(self, queue_model: QM) -> Self {
+    /// Sets the number of the most recent mid prices kept by the local, which are sampled on each
+    /// change of the best bid or the best ask. See [`Bot::mid_history`].
+    /// The default value is `0`, indicating that no mid price is kept.
+    pub fn mid_history_capacity(self, capacity: usize) -> Self {
         Self {
-            queue_model: Some(queue_model),
+            mid_history_cap: capacity,
             ..self
         }
     }
 
-    /// Sets a market depth builder.
-    pub fn depth<Builder>(self, builder: Builder) -> Self
-    where
-        Builder: Fn() -> MD + 'static,
-    {
+    /// Sets the cap on the total notional traded per run. Once the cumulative trading value
+    /// reaches the cap, further order submissions are rejected with
+    /// [`BacktestError::NotionalLimitReached`]. The default value is infinity, indicating no cap.
+    pub fn max_total_notional(self, max_total_notional: f64) -> Self {
         Self {
-            depth_builder: Some(Box::new(builder)),
+            max_total_notional,
             ..self
         }
     }
 
-    /// Builds an `Asset`.
-    pub fn build(self) -> Result<Asset<dyn LocalProcessor<MD>, dyn Processor, Event>, BuildError> {
-        let reader = if self.latency_offset == 0 {
-            Reader::builder()
-                .parallel_load(self.parallel_load)
-                .data(self.data)
-                .build()
-                .map_err(|err| BuildError::Error(err.into()))?
-        } else {
-            Reader::builder()
-                .parallel_load(self.parallel_load)
-                .data(self.data)
-                .preprocessor(FeedLatencyAdjustment::new(self.latency_offset))
-                .build()
-                .map_err(|err| BuildError::Error(err.into()))?
-        };
+    /// Sets the initial capacity of the local order log, which records when the orders rest in
+    /// the order book and when they are filled.
+    /// The default value is `0`, indicating that no order log is stored.
+    pub fn order_log_capacity(self, capacity: usize) -> Self {
+        Self {
+            order_log_cap: capacity,
+            ..self
+        }
+    }
 
-        let create_depth = self
-            .depth_builder
-            .as_ref()
-            .ok_or(BuildError::BuilderIncomplete("depth"))?;
-        let order_latency = self
-            .latency_model
-            .clone()
-            .ok_or(BuildError::BuilderIncomplete("order_latency"))?;
-        let asset_type = self
-            .asset_type
-            .clone()
-            .ok_or(BuildError::BuilderIncomplete("asset_type"))?;
-        let fee_model = self
-            .fee_model
-            .clone()
-            .ok_or(BuildError::BuilderIncomplete("fee_model"))?;
+    /// Sets the initial capacity of the lifetime VWAP records in the local, which compare each
+    /// order's fills against the market VWAP over its lifetime. See [`Backtest::lifetime_vwaps`].
+    /// The default value is `0`, indicating that the lifetime VWAP is not recorded.
+    pub fn lifetime_vwap_capacity(self, capacity: usize) -> Self {
+        Self {
+            lifetime_vwap_cap: capacity,
+            ..self
+        }
+    }
 
-        let (order_e2l, order_l2e) = order_bus(order_latency);
+    /// Sets the horizon in nanoseconds over which the local tracks the mid price after each order
+    /// ends canceled or expired with an unfilled quantity. See [`Backtest::opportunity_costs`].
+    /// The default value is `0`, indicating that the opportunity cost is not recorded.
+    pub fn opportunity_cost_horizon(self, horizon: i64) -> Self {
+        Self {
+            opportunity_cost_horizon: horizon,
+            ..self
+        }
+    }
 
-        let local = L3Local::new(
-            create_depth(),
-            State::new(asset_type, fee_model),
-            self.last_trades_cap,
-            order_l2e,
-        );
+    /// Sets the horizon in nanoseconds over which the local tracks the mid price after each maker
+    /// fill. See [`Backtest::adverse_selections`].
+    /// The default value is `0`, indicating that the adverse selection is not recorded.
+    pub fn adverse_selection_horizon(self, horizon: i64) -> Self {
+        Self {
+            adverse_selection_horizon: horizon,
+            ..self
+        }
+    }
 
-        let queue_model = self
-            .queue_model
-            .ok_or(BuildError::BuilderIncomplete("queue_model"))?;
-        let asset_type = self
-            .asset_type
-            .clone()
-            .ok_or(BuildError::BuilderIncomplete("asset_type"))?;
-        let fee_model = self
-            .fee_model
-            .clone()
-            .ok_or(BuildError::BuilderIncomplete("fee_model"))?;
+    /// Sets how often the local re-marks the held position to the mid price, which determines the
+    /// equity in the state samples and [`Backtest::max_drawdown`].
+    /// The default value is [`MarkFrequency::BboChange`].
+    pub fn mark_frequency(self, mark_frequency: MarkFrequency) -> Self {
+        Self {
+            mark_frequency,
+            ..self
+        }
+    }
 
-        match self.exch_kind {
-            ExchangeKind::NoPartialFillExchange => {
-                println!("Using NoPartialFillExchange");
-                let exch = L3NoPartialFillExchange::new(
-                    create_depth(),
-                    State::new(asset_type, fee_model),
-                    queue_model,
-                    order_e2l,
-                );
+    /// Sets the fraction of the quantity before a sweep that a price level emptied by the market
+    /// feed fills must recover to. See [`Backtest::sweep_recoveries`].
+    /// The default value is `0`, indicating that the resilience is not recorded.
+    pub fn resilience_recovery_fraction(self, recovery_fraction: f64) -> Self {
+        Self {
+            resilience_recovery_fraction: recovery_fraction,
+            ..self
+        }
+    }
 
-                Ok(Asset {
-                    local: Box::new(local),
-                    exch: Box::new(exch),
-                    reader,
-                })
-            }
-            ExchangeKind::PartialFillExchange => {
-                println!("Using PartialFillExchange");
-                let exch = L3PartialFillExchange::new(
-                    create_depth(),
-                    State::new(asset_type, fee_model),
-                    queue_model,
-                    order_e2l,
-                );
+    /// Sets the number of the most recently submitted orders whose lifecycle timelines are kept
+    /// in the local. See [`Backtest::order_timeline`].
+    /// The default value is `0`, indicating that no timeline is recorded.
+    pub fn order_timeline_capacity(self, capacity: usize) -> Self {
+        Self {
+            order_timeline_cap: capacity,
+            ..self
+        }
+    }
 
-                Ok(Asset {
-                    local: Box::new(local),
-                    exch: Box::new(exch),
-                    reader,
-                })
-            }
+    /// Enables the audit trail of the submissions, acknowledgements, modifications,
+    /// cancellations, fills, expiries, and rejections observed by the local, which is written to
+    /// `path` in JSON Lines format. Up to `capacity` records are buffered before being appended to
+    /// the file, and the remaining records are written when the backtest is closed. See
+    /// [`AuditRecorder`](audit::AuditRecorder).
+    /// By default, no audit trail is recorded.
+    pub fn audit_log(self, path: impl Into<PathBuf>, capacity: usize) -> Self {
+        Self {
+            audit_log: Some((path.into(), capacity)),
+            ..self
         }
     }
-}
 
-impl<LM, AT, QM, MD, FM> Default for L3AssetBuilder<LM, AT, QM, MD, FM>
-where
-    AT: AssetType + Clone + 'static,
-    MD: MarketDepth + L3MarketDepth + 'static,
-    QM: L3QueueModel<MD> + 'static,
-    LM: LatencyModel + Clone + 'static,
-    FM: FeeModel + Clone + 'static,
-    BacktestError: From<<MD as L3MarketDepth>::Error>,
-{
-    fn default() -> Self {
-        Self::new()
+    /// Sets whether the local counts the outcomes of the orders by the distance from the touch at
+    /// which each order is submitted. See [`Backtest::touch_distance_stats`].
+    /// The default value is `false`.
+    pub fn touch_distance_stats(self, touch_distance_stats: bool) -> Self {
+        Self {
+            touch_distance_stats,
+            ..self
+        }
     }
-}
 
-/// [`Backtest`] builder.
-pub struct BacktestBuilder<MD> {
-    local: Vec<BacktestProcessorState<Box<dyn LocalProcessor<MD>>>>,
-    exch: Vec<BacktestProcessorState<Box<dyn Processor>>>,
-}
+    /// Sets the initial capacity of the fill gap recorder in the exchange, which records the mid
+    /// price at the time of each fill. See [`Backtest::fill_gaps`].
+    /// The default value is `0`, indicating that no fill gap is recorded.
+    pub fn fill_gap_capacity(self, capacity: usize) -> Self {
+        Self {
+            fill_gap_cap: capacity,
+            ..self
+        }
+    }
 
-impl<MD> BacktestBuilder<MD> {
-    /// Adds [`Asset`], which will undergo simulation within the backtester.
-    pub fn add_asset(self, asset: Asset<dyn LocalProcessor<MD>, dyn Processor, Event>) -> Self {
-        let mut self_ = Self { ..self };
-        self_.local.push(BacktestProcessorState::new(
-            asset.local,
-            asset.reader.clone(),
-        ));
-        self_
-            .exch
-            .push(BacktestProcessorState::new(asset.exch, asset.reader));
-        self_
+    /// Sets whether the exchange accumulates the executed quantity of the fills by the distance
+    /// from the mid price at the time of each fill. See [`Backtest::fill_price_histogram`].
+    /// The default value is `false`.
+    pub fn fill_price_histogram(self, fill_price_histogram: bool) -> Self {
+        Self {
+            fill_price_histogram,
+            ..self
+        }
     }
 
-    /// Builds [`Backtest`].
-    pub fn build(self) -> Result<Backtest<MD>, BuildError> {
-        let num_assets = self.local.len();
-        if self.local.len() != num_assets || self.exch.len() != num_assets {
-            panic!();
+    /// Sets whether the exchange records every fill as a trade log. See
+    /// [`Backtest::fill_records`]. The default value is `false`.
+    pub fn fill_records(self, fill_records: bool) -> Self {
+        Self {
+            fill_records,
+            ..self
         }
-        Ok(Backtest {
-            cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
-            local: self.local,
-            exch: self.exch,
-        })
     }
-}
 
-/// This backtester provides multi-asset and multi-exchange model backtesting, allowing you to
-/// configure different setups such as queue models or asset types for each asset. However, this may
-/// result in slightly slower performance compared to [`Backtest`].
-pub struct Backtest<MD> {
-    cur_ts: i64,
-    evs: EventSet,
-    local: Vec<BacktestProcessorState<Box<dyn LocalProcessor<MD>>>>,
-    exch: Vec<BacktestProcessorState<Box<dyn Processor>>>,
-}
+    /// Sets the window in nanoseconds around each fill within which the exchange looks for the
+    /// nearest market print in the feed, to compare the backtest fills with the actual trades.
+    /// See [`Backtest::print_divergences`].
+    /// The default value is `0`, indicating that no fill is compared.
+    pub fn print_divergence_window(self, window: i64) -> Self {
+        Self {
+            print_divergence_window: window,
+            ..self
+        }
+    }
 
-impl<P: Processor> Deref for BacktestProcessorState<P> {
-    type Target = P;
+    /// Sets the alternative order latency model under which the exchange replays each new order,
+    /// to compare the liquidity it would have taken on arrival with the liquidity it actually
+    /// takes, without rerunning the backtest. See [`Backtest::shadow_fills`]. It is only supported
+    /// by [`ExchangeKind::PartialFillExchange`], and building the asset with
+    /// [`ExchangeKind::NoPartialFillExchange`] fails if it is set.
+    pub fn shadow_latency_model(self, latency_model: LM) -> Self {
+        Self {
+            shadow_latency_model: Some(latency_model),
+            ..self
+        }
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.processor
+    /// Sets how the local handles a modify event for a market-feed order that is not in its order
+    /// book. The default value is [`MissingOrderPolicy::Error`].
+    pub fn missing_order_policy(self, missing_order_policy: MissingOrderPolicy) -> Self {
+        Self {
+            missing_order_policy,
+            ..self
+        }
     }
-}
 
-impl<P: Processor> DerefMut for BacktestProcessorState<P> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.processor
+    /// Sets how the local handles an order priced outside the range of interest of the market
+    /// depth, such as [`ROIVectorMarketDepth`](crate::depth::ROIVectorMarketDepth), on submission.
+    /// The default value is [`OutOfRoiPolicy::Reject`].
+    pub fn out_of_roi_policy(self, out_of_roi_policy: OutOfRoiPolicy) -> Self {
+        Self {
+            out_of_roi_policy,
+            ..self
+        }
     }
-}
 
-/// Per asset backtesting state used internally to advance event buffers.
-pub struct BacktestProcessorState<P: Processor> {
-    data: Data<Event>,
-    processor: P,
-    reader: Reader<Event>,
-    row: Option<usize>,
-}
+    /// Sets whether the fill events in the feed data provide the counterparty order ID in
+    /// [`Event::ival`]. Disable it if the dataset doesn't populate the field, so that only the
+    /// order identified by [`Event::order_id`] is reduced by the fill.
+    /// The default value is `true`.
+    pub fn fill_counterparty(self, fill_counterparty: bool) -> Self {
+        Self {
+            fill_counterparty,
+            ..self
+        }
+    }
 
-impl<P: Processor> BacktestProcessorState<P> {
-    fn new(processor: P, reader: Reader<Event>) -> BacktestProcessorState<P> {
+    /// Sets the offset added to the order IDs of the market feed events in both the local and the
+    /// exchange, so that the market feed order IDs can't collide with the backtest order IDs.
+    /// The default value is `0`, indicating that the order IDs are used as they are.
+    pub fn feed_order_id_offset(self, feed_order_id_offset: u64) -> Self {
         Self {
-            data: Data::empty(),
-            processor,
-            reader,
-            row: None,
+            feed_order_id_offset,
+            ..self
         }
     }
 
-    /// Get the index of the next available row, only advancing the reader if there's no
-    /// row currently available.
-    fn next_row(&mut self) -> Result<usize, BacktestError> {
-        if self.row.is_none() {
-            let _ = self.advance()?;
+    /// Sets the window in nanoseconds within which the local and the exchange ignore a market feed
+    /// fill event that repeats a previous fill, so that a fill emitted twice by the dataset neither
+    /// reduces the local order book twice nor fills the backtest orders twice. See
+    /// [`L3PartialFillExchange::fill_dedup_window`].
+    /// The default value is `0`, indicating no deduplication.
+    pub fn fill_dedup_window(self, fill_dedup_window: i64) -> Self {
+        Self {
+            fill_dedup_window,
+            ..self
         }
+    }
 
-        self.row.ok_or(BacktestError::EndOfData)
+    /// Sets the reference price that triggers a [`OrdType::StopMarket`] order, as venues differ
+    /// in whether a stop is activated by trades or by the quote. The default value is
+    /// [`StopTrigger::LastTrade`].
+    pub fn stop_trigger(self, stop_trigger: StopTrigger) -> Self {
+        Self {
+            stop_trigger,
+            ..self
+        }
     }
 
-    /// Advance the state of this processor to the next available event and return the
-    /// timestamp it occurred at, if any.
-    fn advance(&mut self) -> Result<i64, BacktestError> {
-        loop {
-            let start = self.row.map(|rn| rn + 1).unwrap_or(0);
+    /// Sets the self-trade prevention mode of the exchange. It is only supported by
+    /// [`ExchangeKind::PartialFillExchange`], and building the asset with
+    /// [`ExchangeKind::NoPartialFillExchange`] fails if it is set.
+    /// The default value is [`SelfTradePrevention::None`].
+    pub fn self_trade_prevention(self, self_trade_prevention: SelfTradePrevention) -> Self {
+        Self {
+            self_trade_prevention,
+            ..self
+        }
+    }
 
-            for rn in start..self.data.len() {
-                if let Some(ts) = self.processor.event_seen_timestamp(&self.data[rn]) {
-                    self.row = Some(rn);
-                    return Ok(ts);
-                }
-            }
+    /// Sets whether the exchange acknowledges a new order before matching it, so that the local
+    /// observes the order as [`Status::New`](crate::types::Status::New) before an immediate fill
+    /// at the same timestamp. The default value is `false`.
+    pub fn two_phase_ack(self, two_phase_ack: bool) -> Self {
+        Self {
+            two_phase_ack,
+            ..self
+        }
+    }
 
-            let next = self.reader.next_data()?;
+    /// Sets whether the exchange resets the queue priority of a replaced order.
+    /// The default value is [`PriorityResetPolicy::KeepOnSizeDown`].
+    pub fn priority_reset_policy(self, priority_reset_policy: PriorityResetPolicy) -> Self {
+        Self {
+            priority_reset_policy,
+            ..self
+        }
+    }
 
-            self.reader.release(std::mem::replace(&mut self.data, next));
-            self.row = None;
+    /// Sets whether the exchange amends a replaced order in place or processes the modify as a
+    /// cancel followed by a new order, which always loses the queue priority and gets a new
+    /// exchange order ID. The default value is [`ModifyMode::Amend`].
+    pub fn modify_mode(self, modify_mode: ModifyMode) -> Self {
+        Self {
+            modify_mode,
+            ..self
         }
     }
-}
 
-impl<MD> Backtest<MD>
-where
-    MD: MarketDepth,
-{
-    pub fn builder() -> BacktestBuilder<MD> {
-        BacktestBuilder {
-            local: vec![],
-            exch: vec![],
+    /// Sets whether the exchange cancels the resting orders when trading halts. While halted, new
+    /// orders and modifications are rejected regardless of the policy.
+    /// The default value is [`HaltPolicy::KeepOrders`].
+    pub fn halt_policy(self, halt_policy: HaltPolicy) -> Self {
+        Self {
+            halt_policy,
+            ..self
         }
     }
 
-    pub fn new(
-        local: Vec<Box<dyn LocalProcessor<MD>>>,
-        exch: Vec<Box<dyn Processor>>,
-        reader: Vec<Reader<Event>>,
-    ) -> Self {
-        let num_assets = local.len();
-        if local.len() != num_assets || exch.len() != num_assets || reader.len() != num_assets {
-            panic!();
+    /// Sets whether the exchange cancels the residual of a partially filled order that falls
+    /// below the lot size. It is only supported by [`ExchangeKind::PartialFillExchange`], and
+    /// building the asset with [`ExchangeKind::NoPartialFillExchange`] fails if it is set.
+    /// The default value is [`SubLotResidualPolicy::Keep`].
+    pub fn sub_lot_residual_policy(self, sub_lot_residual_policy: SubLotResidualPolicy) -> Self {
+        Self {
+            sub_lot_residual_policy,
+            ..self
         }
+    }
 
-        let local = local
-            .into_iter()
-            .zip(reader.iter())
-            .map(|(proc, reader)| BacktestProcessorState::new(proc, reader.clone()))
-            .collect();
-        let exch = exch
-            .into_iter()
-            .zip(reader.iter())
-            .map(|(proc, reader)| BacktestProcessorState::new(proc, reader.clone()))
-            .collect();
+    /// Sets whether the fills of an order sweeping multiple price levels are responded one per
+    /// level or aggregated into a single response. It is only supported by
+    /// [`ExchangeKind::PartialFillExchange`], and building the asset with
+    /// [`ExchangeKind::NoPartialFillExchange`] fails if it is set. The default value is
+    /// [`FillResponseMode::PerFill`].
+    pub fn fill_response_mode(self, fill_response_mode: FillResponseMode) -> Self {
+        Self {
+            fill_response_mode,
+            ..self
+        }
+    }
 
+    /// Sets whether the exchange rejects a new limit order meant to rest that would lock or cross
+    /// the market. The default value is [`LockedMarketPolicy::Match`].
+    pub fn locked_market_policy(self, locked_market_policy: LockedMarketPolicy) -> Self {
         Self {
-            local,
-            exch,
-            cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
+            locked_market_policy,
+            ..self
         }
     }
 
-    fn initialize_evs(&mut self) -> Result<(), BacktestError> {
-        for (asset_no, local) in self.local.iter_mut().enumerate() {
-            match local.advance() {
-                Ok(ts) => self.evs.update_local_data(asset_no, ts),
-                Err(BacktestError::EndOfData) => {
-                    self.evs.invalidate_local_data(asset_no);
-                }
-                Err(e) => {
-                    return Err(e);
-                }
-            }
+    /// Sets whether the exchange rejects new orders until both sides of the order book have at
+    /// least one level. See [`Backtest::book_established_timestamp`] for when the strategy sees the
+    /// order book established. The default value is `false`.
+    pub fn warmup(self, warmup: bool) -> Self {
+        Self { warmup, ..self }
+    }
+
+    /// Sets the minimum price improvement in ticks that a modify request must make when it
+    /// improves the order price. A modify that improves the price by less than this is rejected.
+    /// The default value is `0`, indicating no requirement.
+    pub fn min_price_improvement(self, min_price_improvement: i64) -> Self {
+        Self {
+            min_price_improvement,
+            ..self
         }
-        for (asset_no, exch) in self.exch.iter_mut().enumerate() {
-            match exch.advance() {
-                Ok(ts) => self.evs.update_exch_data(asset_no, ts),
-                Err(BacktestError::EndOfData) => {
-                    self.evs.invalidate_exch_data(asset_no);
-                }
-                Err(e) => {
-                    return Err(e);
-                }
-            }
+    }
+
+    /// Sets the time at which the session opens. Before the open, only the orders entered to
+    /// participate in the opening auction are accepted. It is only supported by
+    /// [`ExchangeKind::PartialFillExchange`], and building the asset with
+    /// [`ExchangeKind::NoPartialFillExchange`] fails if it is set. See
+    /// [`L3PartialFillExchange::session_open`]. The default value is [`i64::MIN`], indicating that
+    /// orders are accepted at any time.
+    pub fn session_open(self, session_open: i64) -> Self {
+        Self {
+            session_open,
+            ..self
         }
-        Ok(())
     }
 
-    pub fn goto_end(&mut self) -> Result<ElapseResult, BacktestError> {
-        if self.cur_ts == i64::MAX {
-            self.initialize_evs()?;
-            match self.evs.next() {
-                Some(ev) => {
-                    self.cur_ts = ev.timestamp;
-                }
-                None => {
-                    return Ok(ElapseResult::EndOfData);
-                }
-            }
+    /// Sets what the exchange does when the session opens without an opening auction price
+    /// having been delivered. It is only supported by [`ExchangeKind::PartialFillExchange`], and
+    /// building the asset with [`ExchangeKind::NoPartialFillExchange`] fails if it is set.
+    /// The default value is [`AuctionFallbackPolicy::None`].
+    pub fn auction_fallback_policy(self, auction_fallback_policy: AuctionFallbackPolicy) -> Self {
+        Self {
+            auction_fallback_policy,
+            ..self
         }
-        self.goto::<false>(UNTIL_END_OF_DATA, WaitOrderResponse::None)
     }
 
-    fn goto<const WAIT_NEXT_FEED: bool>(
-        &mut self,
-        timestamp: i64,
-        wait_order_response: WaitOrderResponse,
-    ) -> Result<ElapseResult, BacktestError> {
-        let mut result = ElapseResult::Ok;
-        let mut timestamp = timestamp;
-        for (asset_no, local) in self.local.iter().enumerate() {
-            self.evs
-                .update_exch_order(asset_no, local.earliest_send_order_timestamp());
-            self.evs
-                .update_local_order(asset_no, local.earliest_recv_order_timestamp());
+    /// Sets the uncross rule by which the auction price is chosen. It is only supported by
+    /// [`ExchangeKind::PartialFillExchange`], and building the asset with
+    /// [`ExchangeKind::NoPartialFillExchange`] fails if it is set. The default value is
+    /// [`AuctionAlgorithm::FeedPrice`].
+    pub fn auction_algorithm(self, auction_algorithm: AuctionAlgorithm) -> Self {
+        Self {
+            auction_algorithm,
+            ..self
         }
-        loop {
-            match self.evs.next() {
-                Some(ev) => {
-                    if ev.timestamp > timestamp {
-                        self.cur_ts = timestamp;
-                        return Ok(result);
-                    }
-                    match ev.kind {
-                        EventIntentKind::LocalData => {
-                            let local = unsafe { self.local.get_unchecked_mut(ev.asset_no) };
-                            let next = local.next_row().and_then(|row| {
-                                local.processor.process(&local.data[row])?;
-                                local.advance()
-                            });
+    }
 
-                            match next {
-                                Ok(next_ts) => {
-                                    self.evs.update_local_data(ev.asset_no, next_ts);
-                                }
-                                Err(BacktestError::EndOfData) => {
-                                    self.evs.invalidate_local_data(ev.asset_no);
-                                }
-                                Err(e) => {
-                                    return Err(e);
-                                }
-                            }
-                            if WAIT_NEXT_FEED {
-                                timestamp = ev.timestamp;
-                                result = ElapseResult::MarketFeed;
-                            }
-                        }
-                        EventIntentKind::LocalOrder => {
-                            let local = unsafe { self.local.get_unchecked_mut(ev.asset_no) };
-                            let wait_order_resp_id = match wait_order_response {
-                                WaitOrderResponse::Specified {
-                                    asset_no: wait_order_asset_no,
-                                    order_id: wait_order_id,
-                                } if ev.asset_no == wait_order_asset_no => Some(wait_order_id),
-                                _ => None,
-                            };
-                            if local.process_recv_order(ev.timestamp, wait_order_resp_id)?
-                                || wait_order_response == WaitOrderResponse::Any
-                            {
-                                timestamp = ev.timestamp;
-                                if WAIT_NEXT_FEED {
-                                    result = ElapseResult::OrderResponse;
-                                }
-                            }
-                            self.evs.update_local_order(
-                                ev.asset_no,
-                                local.earliest_recv_order_timestamp(),
-                            );
-                        }
-                        EventIntentKind::ExchData => {
-                            let exch = unsafe { self.exch.get_unchecked_mut(ev.asset_no) };
-                            let next = exch.next_row().and_then(|row| {
-                                exch.processor.process(&exch.data[row])?;
-                                exch.advance()
-                            });
+    /// Sets the matching cycle interval of the batch matching, in which the orders taking
+    /// liquidity are queued and matched together at each cycle boundary. It is only supported by
+    /// [`ExchangeKind::PartialFillExchange`], and building the asset with
+    /// [`ExchangeKind::NoPartialFillExchange`] fails if it is set.
+    /// The default value is `0`, indicating continuous matching.
+    pub fn batch_interval(self, batch_interval: i64) -> Self {
+        Self {
+            batch_interval,
+            ..self
+        }
+    }
 
-                            match next {
-                                Ok(next_ts) => {
-                                    self.evs.update_exch_data(ev.asset_no, next_ts);
-                                }
-                                Err(BacktestError::EndOfData) => {
-                                    self.evs.invalidate_exch_data(ev.asset_no);
-                                }
-                                Err(e) => {
-                                    return Err(e);
-                                }
-                            }
-                            self.evs.update_local_order(
-                                ev.asset_no,
-                                exch.earliest_send_order_timestamp(),
-                            );
-                        }
-                        EventIntentKind::ExchOrder => {
-                            let exch = unsafe { self.exch.get_unchecked_mut(ev.asset_no) };
-                            let _ = exch.process_recv_order(ev.timestamp, None)?;
-                            self.evs.update_exch_order(
-                                ev.asset_no,
-                                exch.earliest_recv_order_timestamp(),
-                            );
-                            self.evs.update_local_order(
-                                ev.asset_no,
-                                exch.earliest_send_order_timestamp(),
-                            );
-                        }
-                    }
-                }
-                None => {
-                    return Ok(ElapseResult::EndOfData);
-                }
-            }
+    /// Sets a queue model.
+    pub fn queue_model(self, queue_model: QM) -> Self {
+        Self {
+            queue_model: Some(queue_model),
+            ..self
         }
     }
-}
 
-impl<MD> Bot<MD> for Backtest<MD>
-where
-    MD: MarketDepth,
-{
-    type Error = BacktestError;
-
-    #[inline]
-    fn current_timestamp(&self) -> i64 {
-        self.cur_ts
+    /// Sets a market depth builder.
+    pub fn depth<Builder>(self, builder: Builder) -> Self
+    where
+        Builder: Fn() -> MD + 'static,
+    {
+        Self {
+            depth_builder: Some(Box::new(builder)),
+            ..self
+        }
     }
 
-    #[inline]
-    fn num_assets(&self) -> usize {
-        self.local.len()
+    /// Builds an `Asset`.
+    pub fn build(
+        mut self,
+    ) -> Result<Asset<dyn LocalProcessor<MD>, dyn Processor, Event>, BuildError> {
+        let reader = self.reader()?;
+        let queue_model = self
+            .queue_model
+            .take()
+            .ok_or(BuildError::BuilderIncomplete("queue_model"))?;
+        let (local, exch) = self.processors(queue_model)?;
+        Ok(Asset {
+            local,
+            exch,
+            reader,
+            factory: None,
+        })
     }
 
-    #[inline]
-    fn position(&self, asset_no: usize) -> f64 {
-        self.local.get(asset_no).unwrap().position()
+    fn reader(&mut self) -> Result<Reader<Event>, BuildError> {
+        let builder = Reader::builder()
+            .parallel_load(self.parallel_load)
+            .data(std::mem::take(&mut self.data));
+        let builder = if self.latency_offset == 0 {
+            builder
+        } else {
+            builder.preprocessor(FeedLatencyAdjustment::new(self.latency_offset))
+        };
+        builder.build().map_err(|err| BuildError::Error(err.into()))
     }
 
-    #[inline]
-    fn state_values(&self, asset_no: usize) -> &StateValues {
-        self.local.get(asset_no).unwrap().state_values()
+    /// Fails if any option that only [`ExchangeKind::PartialFillExchange`] supports is set, rather
+    /// than building an exchange that would silently ignore it.
+    fn check_no_partial_fill_options(&self) -> Result<(), BuildError> {
+        let unsupported = [
+            (
+                self.shadow_latency_model.is_some(),
+                "shadow_latency_model requires PartialFillExchange",
+            ),
+            (
+                self.self_trade_prevention != SelfTradePrevention::None,
+                "self_trade_prevention requires PartialFillExchange",
+            ),
+            (
+                self.sub_lot_residual_policy != SubLotResidualPolicy::Keep,
+                "sub_lot_residual_policy requires PartialFillExchange",
+            ),
+            (
+                self.fill_response_mode != FillResponseMode::PerFill,
+                "fill_response_mode requires PartialFillExchange",
+            ),
+            (
+                self.session_open != i64::MIN,
+                "session_open requires PartialFillExchange",
+            ),
+            (
+                self.auction_fallback_policy != AuctionFallbackPolicy::None,
+                "auction_fallback_policy requires PartialFillExchange",
+            ),
+            (
+                self.auction_algorithm != AuctionAlgorithm::FeedPrice,
+                "auction_algorithm requires PartialFillExchange",
+            ),
+            (
+                self.batch_interval != 0,
+                "batch_interval requires PartialFillExchange",
+            ),
+        ];
+        match unsupported.into_iter().find(|(set, _)| *set) {
+            Some((_, msg)) => Err(BuildError::InvalidArgument(msg)),
+            None => Ok(()),
+        }
     }
 
-    fn depth(&self, asset_no: usize) -> &MD {
-        self.local.get(asset_no).unwrap().depth()
-    }
+    fn processors(&self, queue_model: QM) -> Result<Processors<MD>, BuildError> {
+        let create_depth = self
+            .depth_builder
+            .as_ref()
+            .ok_or(BuildError::BuilderIncomplete("depth"))?;
+        let order_latency = self
+            .latency_model
+            .clone()
+            .ok_or(BuildError::BuilderIncomplete("order_latency"))?;
+        let asset_type = self
+            .asset_type
+            .clone()
+            .ok_or(BuildError::BuilderIncomplete("asset_type"))?;
+        let fee_model = self
+            .fee_model
+            .clone()
+            .ok_or(BuildError::BuilderIncomplete("fee_model"))?;
 
-    fn last_trades(&self, asset_no: usize) -> &[Event] {
-        self.local.get(asset_no).unwrap().last_trades()
-    }
+        let min_latency = if self.latency_floor { 1 } else { 0 };
+        let shadow_latency = self
+            .shadow_latency_model
+            .clone()
+            .map(|latency_model| LatencyFloor::new(latency_model, min_latency));
+        let order_latency = LatencyFloor::new(order_latency, min_latency);
+        let (order_e2l, order_l2e) = if self.strict_receive_order {
+            order_bus_by_receive_time(order_latency)
+        } else {
+            order_bus(order_latency)
+        };
 
-    #[inline]
-    fn clear_last_trades(&mut self, asset_no: Option<usize>) {
-        match asset_no {
-            Some(an) => {
-                let local = self.local.get_mut(an).unwrap();
-                local.clear_last_trades();
+        let local = L3Local::new(
+            create_depth(),
+            State::new(asset_type, fee_model).max_total_notional(self.max_total_notional),
+            self.last_trades_cap,
+            order_l2e,
+        )
+        .order_log_capacity(self.order_log_cap)
+        .mid_history_capacity(self.mid_history_cap)
+        .lifetime_vwap_capacity(self.lifetime_vwap_cap)
+        .opportunity_cost_horizon(self.opportunity_cost_horizon)
+        .adverse_selection_horizon(self.adverse_selection_horizon)
+        .mark_frequency(self.mark_frequency)
+        .resilience_recovery_fraction(self.resilience_recovery_fraction)
+        .order_timeline_capacity(self.order_timeline_cap)
+        .touch_distance_stats(self.touch_distance_stats)
+        .missing_order_policy(self.missing_order_policy)
+        .out_of_roi_policy(self.out_of_roi_policy)
+        .fill_counterparty(self.fill_counterparty)
+        .fill_dedup_window(self.fill_dedup_window)
+        .feed_order_id_offset(self.feed_order_id_offset);
+        let local = match self.audit_log.clone() {
+            Some((path, capacity)) => local.audit_log(path, capacity),
+            None => local,
+        };
+
+        let asset_type = self
+            .asset_type
+            .clone()
+            .ok_or(BuildError::BuilderIncomplete("asset_type"))?;
+        let fee_model = self
+            .fee_model
+            .clone()
+            .ok_or(BuildError::BuilderIncomplete("fee_model"))?;
+
+        match self.exch_kind {
+            ExchangeKind::NoPartialFillExchange => {
+                self.check_no_partial_fill_options()?;
+                println!("Using NoPartialFillExchange");
+                let exch = L3NoPartialFillExchange::new(
+                    create_depth(),
+                    State::new(asset_type, fee_model),
+                    queue_model,
+                    order_e2l,
+                )
+                .fill_gap_capacity(self.fill_gap_cap)
+                .fill_price_histogram(self.fill_price_histogram)
+                .fill_records(self.fill_records)
+                .print_divergence_window(self.print_divergence_window)
+                .two_phase_ack(self.two_phase_ack)
+                .priority_reset_policy(self.priority_reset_policy)
+                .modify_mode(self.modify_mode)
+                .halt_policy(self.halt_policy)
+                .locked_market_policy(self.locked_market_policy)
+                .warmup(self.warmup)
+                .min_price_improvement(self.min_price_improvement)
+                .feed_order_id_offset(self.feed_order_id_offset)
+                .fill_dedup_window(self.fill_dedup_window)
+                .stop_trigger(self.stop_trigger);
+
+                Ok((Box::new(local), Box::new(exch)))
             }
-            None => {
-                for local in self.local.iter_mut() {
-                    local.clear_last_trades();
-                }
+            ExchangeKind::PartialFillExchange => {
+                println!("Using PartialFillExchange");
+                let exch = L3PartialFillExchange::new(
+                    create_depth(),
+                    State::new(asset_type, fee_model),
+                    queue_model,
+                    order_e2l,
+                )
+                .fill_gap_capacity(self.fill_gap_cap)
+                .fill_price_histogram(self.fill_price_histogram)
+                .fill_records(self.fill_records)
+                .print_divergence_window(self.print_divergence_window)
+                .shadow_latency_model(shadow_latency)
+                .self_trade_prevention(self.self_trade_prevention)
+                .two_phase_ack(self.two_phase_ack)
+                .priority_reset_policy(self.priority_reset_policy)
+                .modify_mode(self.modify_mode)
+                .halt_policy(self.halt_policy)
+                .sub_lot_residual_policy(self.sub_lot_residual_policy)
+                .fill_response_mode(self.fill_response_mode)
+                .locked_market_policy(self.locked_market_policy)
+                .warmup(self.warmup)
+                .min_price_improvement(self.min_price_improvement)
+                .session_open(self.session_open)
+                .auction_fallback_policy(self.auction_fallback_policy)
+                .auction_algorithm(self.auction_algorithm)
+                .batch_interval(self.batch_interval)
+                .feed_order_id_offset(self.feed_order_id_offset)
+                .fill_dedup_window(self.fill_dedup_window)
+                .stop_trigger(self.stop_trigger);
+
+                Ok((Box::new(local), Box::new(exch)))
             }
         }
     }
+}
 
-    #[inline]
-    fn orders(&self, asset_no: usize) -> &HashMap<u64, Order> {
-        self.local.get(asset_no).unwrap().orders()
+impl<LM, AT, QM, MD, FM> L3AssetBuilder<LM, AT, QM, MD, FM>
+where
+    AT: AssetType + Clone + 'static,
+    MD: MarketDepth + L3MarketDepth + 'static,
+    QM: L3QueueModel<MD> + Clone + 'static,
+    LM: LatencyModel + Clone + 'static,
+    FM: FeeModel + Clone + 'static,
+    BacktestError: From<<MD as L3MarketDepth>::Error>,
+{
+    /// Builds an `Asset` whose processors can be rebuilt from this configuration, so that
+    /// [`Backtest::reset`] can restore them to their initial state while keeping the loaded data.
+    pub fn build_resettable(
+        mut self,
+    ) -> Result<Asset<dyn LocalProcessor<MD>, dyn Processor, Event>, BuildError> {
+        let reader = self.reader()?;
+        let queue_model = self
+            .queue_model
+            .take()
+            .ok_or(BuildError::BuilderIncomplete("queue_model"))?;
+        let (local, exch) = self.processors(queue_model.clone())?;
+        Ok(Asset {
+            local,
+            exch,
+            reader,
+            factory: None,
+        }
+        .factory(move || {
+            // The configuration has already been validated by the first build.
+            self.processors(queue_model.clone()).unwrap()
+        }))
     }
+}
 
-    #[inline]
-    fn submit_buy_order(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        price: f64,
-        qty: f64,
-        time_in_force: TimeInForce,
-        order_type: OrdType,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.submit_order(
-            order_id,
-            Side::Buy,
-            price,
-            qty,
-            order_type,
-            time_in_force,
-            self.cur_ts,
-        )?;
+impl<LM, AT, QM, MD, FM> Default for L3AssetBuilder<LM, AT, QM, MD, FM>
+where
+    AT: AssetType + Clone + 'static,
+    MD: MarketDepth + L3MarketDepth + 'static,
+    QM: L3QueueModel<MD> + 'static,
+    LM: LatencyModel + Clone + 'static,
+    FM: FeeModel + Clone + 'static,
+    BacktestError: From<<MD as L3MarketDepth>::Error>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified { asset_no, order_id },
-            );
+/// [`Backtest`] builder.
+pub struct BacktestBuilder<MD> {
+    local: Vec<BacktestProcessorState<Box<dyn LocalProcessor<MD>>>>,
+    exch: Vec<BacktestProcessorState<Box<dyn Processor>>>,
+    venues: Vec<Vec<usize>>,
+    factories: Vec<Option<AssetProcessorFactory<MD>>>,
+    ordering_timestamp: OrderingTimestamp,
+    state_record_interval: i64,
+}
+
+impl<MD> BacktestBuilder<MD> {
+    /// Adds [`Asset`], which will undergo simulation within the backtester.
+    pub fn add_asset(self, asset: Asset<dyn LocalProcessor<MD>, dyn Processor, Event>) -> Self {
+        let mut self_ = Self { ..self };
+        let slot = self_.push(asset);
+        self_.venues.push(vec![slot]);
+        self_
+    }
+
+    /// Adds another venue to the asset most recently added by [`add_asset`](Self::add_asset), so
+    /// that the same instrument is traded on multiple exchanges, each with its own data, market
+    /// depth, latency, and queue model. The venues are numbered in the order they are added,
+    /// starting from `1`, since venue `0` is the asset itself. Orders are routed to a venue by
+    /// [`Bot::submit_venue_order`].
+    ///
+    /// Returns [`BuildError::BuilderIncomplete`] if no asset has been added yet.
+    pub fn add_venue(
+        self,
+        asset: Asset<dyn LocalProcessor<MD>, dyn Processor, Event>,
+    ) -> Result<Self, BuildError> {
+        if self.venues.is_empty() {
+            return Err(BuildError::BuilderIncomplete("asset"));
         }
-        Ok(ElapseResult::Ok)
+        let mut self_ = Self { ..self };
+        let slot = self_.push(asset);
+        self_.venues.last_mut().unwrap().push(slot);
+        Ok(self_)
     }
 
-    #[inline]
-    fn submit_sell_order(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        price: f64,
-        qty: f64,
-        time_in_force: TimeInForce,
-        order_type: OrdType,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.submit_order(
-            order_id,
-            Side::Sell,
-            price,
-            qty,
-            order_type,
-            time_in_force,
-            self.cur_ts,
-        )?;
+    fn push(&mut self, asset: Asset<dyn LocalProcessor<MD>, dyn Processor, Event>) -> usize {
+        let mut local = BacktestProcessorState::new(asset.local, asset.reader.clone());
+        let mut exch = BacktestProcessorState::new(asset.exch, asset.reader);
+        if asset.factory.is_some() {
+            local.retain_data();
+            exch.retain_data();
+        }
+        self.local.push(local);
+        self.exch.push(exch);
+        self.factories.push(asset.factory);
+        self.local.len() - 1
+    }
 
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified { asset_no, order_id },
-            );
+    /// Sets the timestamp field used to order the events across all processors. Use it for
+    /// datasets where one of the timestamp fields is synthetic. Unless it is
+    /// [`OrderingTimestamp::Native`], the backtest fails with [`BacktestError::EventOutOfOrder`]
+    /// if the chosen field goes backward in the events seen by a processor.
+    /// The default value is [`OrderingTimestamp::Native`].
+    pub fn ordering_timestamp(self, ordering_timestamp: OrderingTimestamp) -> Self {
+        Self {
+            ordering_timestamp,
+            ..self
         }
-        Ok(ElapseResult::Ok)
     }
 
-    fn submit_order(
-        &mut self,
-        asset_no: usize,
-        order: OrderRequest,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.submit_order(
-            order.order_id,
-            Side::Sell,
-            order.price,
-            order.qty,
-            order.order_type,
-            order.time_in_force,
-            self.cur_ts,
-        )?;
+    /// Sets the interval at which the state of each asset is sampled as the backtest elapses,
+    /// without the strategy recording it. See [`Backtest::state_samples`].
+    /// The default value is `0`, indicating that no state is sampled.
+    pub fn state_record_interval(self, state_record_interval: i64) -> Self {
+        Self {
+            state_record_interval,
+            ..self
+        }
+    }
 
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified {
-                    asset_no,
-                    order_id: order.order_id,
-                },
-            );
+    /// Builds [`Backtest`].
+    pub fn build(mut self) -> Result<Backtest<MD>, BuildError> {
+        let num_assets = self.local.len();
+        if self.local.len() != num_assets || self.exch.len() != num_assets {
+            panic!();
         }
-        Ok(ElapseResult::Ok)
+        for local in self.local.iter_mut() {
+            local.ordering = self.ordering_timestamp;
+        }
+        for exch in self.exch.iter_mut() {
+            exch.ordering = self.ordering_timestamp;
+        }
+        Ok(Backtest {
+            cur_ts: i64::MAX,
+            evs: EventSet::new(num_assets),
+            state_recorder: StateRecorder::new(self.state_record_interval, self.venues.len()),
+            local: self.local,
+            exch: self.exch,
+            venues: self.venues,
+            factories: self.factories,
+        })
     }
+}
 
-    #[inline]
-    fn modify(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        price: f64,
-        qty: f64,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.modify(order_id, price, qty, self.cur_ts)?;
+/// This backtester provides multi-asset and multi-exchange model backtesting, allowing you to
+/// configure different setups such as queue models or asset types for each asset. However, this may
+/// result in slightly slower performance compared to [`Backtest`].
+pub struct Backtest<MD> {
+    cur_ts: i64,
+    evs: EventSet,
+    local: Vec<BacktestProcessorState<Box<dyn LocalProcessor<MD>>>>,
+    exch: Vec<BacktestProcessorState<Box<dyn Processor>>>,
+    // The processor slots of each asset's venues, with the primary venue first.
+    venues: Vec<Vec<usize>>,
+    factories: Vec<Option<AssetProcessorFactory<MD>>>,
+    state_recorder: StateRecorder,
+}
 
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified { asset_no, order_id },
-            );
+impl<P: Processor> Deref for BacktestProcessorState<P> {
+    type Target = P;
+
+    fn deref(&self) -> &Self::Target {
+        &self.processor
+    }
+}
+
+impl<P: Processor> DerefMut for BacktestProcessorState<P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.processor
+    }
+}
+
+/// Per asset backtesting state used internally to advance event buffers.
+pub struct BacktestProcessorState<P: Processor> {
+    data: Data<Event>,
+    processor: P,
+    reader: Reader<Event>,
+    row: Option<usize>,
+    ordering: OrderingTimestamp,
+    last_ts: i64,
+    // The data loaded so far, kept to be replayed after a reset, and the index of the next data to
+    // be read from it.
+    retained: Option<Vec<Data<Event>>>,
+    next_data: usize,
+}
+
+impl<P: Processor> BacktestProcessorState<P> {
+    fn new(processor: P, reader: Reader<Event>) -> BacktestProcessorState<P> {
+        Self {
+            data: Data::empty(),
+            processor,
+            reader,
+            row: None,
+            ordering: OrderingTimestamp::Native,
+            last_ts: i64::MIN,
+            retained: None,
+            next_data: 0,
         }
-        Ok(ElapseResult::Ok)
     }
 
-    #[inline]
-    fn cancel(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.cancel(order_id, self.cur_ts)?;
+    /// Keeps the loaded data instead of releasing it, so that it can be replayed after
+    /// [`reset`](Self::reset).
+    fn retain_data(&mut self) {
+        self.retained = Some(Vec::new());
+    }
 
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified { asset_no, order_id },
-            );
+    /// Replaces the processor and rewinds to the beginning of the retained data.
+    fn reset(&mut self, processor: P) {
+        self.processor = processor;
+        self.data = Data::empty();
+        self.row = None;
+        self.last_ts = i64::MIN;
+        self.next_data = 0;
+    }
+
+    /// Get the index of the next available row, only advancing the reader if there's no
+    /// row currently available.
+    fn next_row(&mut self) -> Result<usize, BacktestError> {
+        if self.row.is_none() {
+            let _ = self.advance()?;
         }
-        Ok(ElapseResult::Ok)
+
+        self.row.ok_or(BacktestError::EndOfData)
     }
 
-    #[inline]
-    fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
-        match asset_no {
-            Some(asset_no) => {
-                self.local
-                    .get_mut(asset_no)
-                    .unwrap()
-                    .clear_inactive_orders();
+    /// Advance the state of this processor to the next available event and return the
+    /// timestamp it occurred at, if any.
+    fn advance(&mut self) -> Result<i64, BacktestError> {
+        loop {
+            let start = self.row.map(|rn| rn + 1).unwrap_or(0);
+
+            for rn in start..self.data.len() {
+                let ev = &self.data[rn];
+                if let Some(ts) = self.processor.event_seen_timestamp(ev) {
+                    let ts = match self.ordering {
+                        OrderingTimestamp::Native => ts,
+                        OrderingTimestamp::Exchange => ev.exch_ts,
+                        OrderingTimestamp::Local => ev.local_ts,
+                    };
+                    if self.ordering != OrderingTimestamp::Native && ts < self.last_ts {
+                        return Err(BacktestError::EventOutOfOrder {
+                            prev: self.last_ts,
+                            ts,
+                        });
+                    }
+                    self.last_ts = ts;
+                    self.row = Some(rn);
+                    return Ok(ts);
+                }
             }
-            None => {
-                for local in self.local.iter_mut() {
-                    local.clear_inactive_orders();
+
+            match &mut self.retained {
+                Some(retained) => {
+                    if self.next_data == retained.len() {
+                        retained.push(self.reader.next_data()?);
+                    }
+                    self.data = retained[self.next_data].clone();
+                    self.next_data += 1;
+                }
+                None => {
+                    let next = self.reader.next_data()?;
+                    self.reader.release(std::mem::replace(&mut self.data, next));
                 }
             }
+            self.row = None;
         }
     }
+}
 
-    #[inline]
-    fn wait_order_response(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        timeout: i64,
-    ) -> Result<ElapseResult, BacktestError> {
-        self.goto::<false>(
-            self.cur_ts + timeout,
-            WaitOrderResponse::Specified { asset_no, order_id },
-        )
+impl<MD> Backtest<MD>
+where
+    MD: MarketDepth,
+{
+    pub fn builder() -> BacktestBuilder<MD> {
+        BacktestBuilder {
+            local: vec![],
+            exch: vec![],
+            venues: vec![],
+            factories: vec![],
+            ordering_timestamp: OrderingTimestamp::Native,
+            state_record_interval: 0,
+        }
     }
 
-    #[inline]
-    fn wait_next_feed(
-        &mut self,
-        include_order_resp: bool,
-        timeout: i64,
-    ) -> Result<ElapseResult, Self::Error> {
-        if self.cur_ts == i64::MAX {
-            self.initialize_evs()?;
-            match self.evs.next() {
-                Some(ev) => {
-                    self.cur_ts = ev.timestamp;
-                }
-                None => {
-                    return Ok(ElapseResult::EndOfData);
-                }
-            }
+    pub fn new(
+        local: Vec<Box<dyn LocalProcessor<MD>>>,
+        exch: Vec<Box<dyn Processor>>,
+        reader: Vec<Reader<Event>>,
+    ) -> Self {
+        let num_assets = local.len();
+        if local.len() != num_assets || exch.len() != num_assets || reader.len() != num_assets {
+            panic!();
         }
-        if include_order_resp {
-            self.goto::<true>(self.cur_ts + timeout, WaitOrderResponse::Any)
-        } else {
-            self.goto::<true>(self.cur_ts + timeout, WaitOrderResponse::None)
+
+        let local = local
+            .into_iter()
+            .zip(reader.iter())
+            .map(|(proc, reader)| BacktestProcessorState::new(proc, reader.clone()))
+            .collect();
+        let exch = exch
+            .into_iter()
+            .zip(reader.iter())
+            .map(|(proc, reader)| BacktestProcessorState::new(proc, reader.clone()))
+            .collect();
+
+        Self {
+            local,
+            exch,
+            venues: (0..num_assets).map(|slot| vec![slot]).collect(),
+            factories: (0..num_assets).map(|_| None).collect(),
+            cur_ts: i64::MAX,
+            evs: EventSet::new(num_assets),
+            state_recorder: Default::default(),
         }
     }
 
-    #[inline]
-    fn elapse(&mut self, duration: i64) -> Result<ElapseResult, Self::Error> {
-        if self.cur_ts == i64::MAX {
-            self.initialize_evs()?;
-            match self.evs.next() {
-                Some(ev) => {
-                    self.cur_ts = ev.timestamp;
-                }
-                None => {
-                    return Ok(ElapseResult::EndOfData);
-                }
-            }
+    /// Resets the backtest to the beginning of the data so that it can be run again, for example
+    /// with different strategy parameters, without reloading the data. The processors of all
+    /// assets are rebuilt in their initial state, which clears the market depth, the queue, the
+    /// orders, the state, and the order latency queues, while the data loaded so far is retained
+    /// and replayed.
+    ///
+    /// Every asset must be built by `build_resettable` or have a factory set by
+    /// [`Asset::factory`]; otherwise, [`BacktestError::NotResettable`] is returned and the
+    /// backtest is left unchanged.
+    pub fn reset(&mut self) -> Result<(), BacktestError> {
+        if self.factories.iter().any(Option::is_none) {
+            return Err(BacktestError::NotResettable);
         }
-        self.goto::<false>(self.cur_ts + duration, WaitOrderResponse::None)
+        for (slot, factory) in self.factories.iter().flatten().enumerate() {
+            let (local, exch) = factory();
+            self.local[slot].reset(local);
+            self.exch[slot].reset(exch);
+        }
+        self.cur_ts = i64::MAX;
+        self.evs = EventSet::new(self.local.len());
+        self.state_recorder.reset();
+        Ok(())
     }
 
+    /// Returns the processor slot of the primary venue of the asset.
     #[inline]
-    fn elapse_bt(&mut self, duration: i64) -> Result<ElapseResult, Self::Error> {
-        self.elapse(duration)
+    fn primary(&self, asset_no: usize) -> usize {
+        self.venues.get(asset_no).unwrap()[0]
     }
 
-    #[inline]
-    fn close(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+    /// Returns the processor slot of the venue of the asset on which the order is placed, or the
+    /// primary venue if no venue has the order.
+    fn order_venue(&self, asset_no: usize, order_id: OrderId) -> usize {
+        let venues = self.venues.get(asset_no).unwrap();
+        venues
+            .iter()
+            .copied()
+            .find(|&slot| self.local[slot].orders().contains_key(&order_id))
+            .unwrap_or(venues[0])
     }
 
-    #[inline]
-    fn feed_latency(&self, asset_no: usize) -> Option<(i64, i64)> {
-        self.local.get(asset_no).unwrap().feed_latency()
+    /// Returns the orders placed on the given venue of the asset. Venue `0` is the primary venue.
+    /// See [`BacktestBuilder::add_venue`].
+    pub fn venue_orders(&self, asset_no: usize, venue: usize) -> &HashMap<OrderId, Order> {
+        let slot = self.venues.get(asset_no).unwrap()[venue];
+        self.local[slot].orders()
     }
 
-    #[inline]
-    fn order_latency(&self, asset_no: usize) -> Option<(i64, i64, i64)> {
-        self.local.get(asset_no).unwrap().order_latency()
+    /// Returns the position held on the given venue of the asset. [`Bot::position`] returns the
+    /// position consolidated across all venues of the asset.
+    pub fn venue_position(&self, asset_no: usize, venue: usize) -> f64 {
+        let slot = self.venues.get(asset_no).unwrap()[venue];
+        self.local[slot].position()
     }
-}
 
-/// `MultiAssetSingleExchangeBacktest` builder.
-pub struct MultiAssetSingleExchangeBacktestBuilder<Local: Processor, Exchange: Processor> {
-    local: Vec<BacktestProcessorState<Local>>,
-    exch: Vec<BacktestProcessorState<Exchange>>,
-}
+    /// Returns the state values on the given venue of the asset. [`Bot::state_values`] returns
+    /// those on the primary venue.
+    pub fn venue_state_values(&self, asset_no: usize, venue: usize) -> &StateValues {
+        let slot = self.venues.get(asset_no).unwrap()[venue];
+        self.local[slot].state_values()
+    }
 
-impl<Local, Exchange> MultiAssetSingleExchangeBacktestBuilder<Local, Exchange>
-where
-    Local: LocalProcessor<HashMapMarketDepth> + 'static,
-    Exchange: Processor + 'static,
-{
-    /// Adds [`Asset`], which will undergo simulation within the backtester.
-    pub fn add_asset(self, asset: Asset<Local, Exchange, Event>) -> Self {
-        let mut self_ = Self { ..self };
-        self_.local.push(BacktestProcessorState::new(
-            *asset.local,
-            asset.reader.clone(),
-        ));
-        self_.exch.push(BacktestProcessorState::new(
-            *asset.exch,
-            asset.reader.clone(),
-        ));
-        self_
+    /// Returns the market depth of the given venue of the asset. [`Bot::depth`] returns that of
+    /// the primary venue.
+    pub fn venue_depth(&self, asset_no: usize, venue: usize) -> &MD {
+        let slot = self.venues.get(asset_no).unwrap()[venue];
+        self.local[slot].depth()
     }
 
-    /// Builds [`MultiAssetSingleExchangeBacktest`].
-    pub fn build(
-        self,
-    ) -> Result<MultiAssetSingleExchangeBacktest<HashMapMarketDepth, Local, Exchange>, BuildError>
-    {
-        let num_assets = self.local.len();
-        if self.local.len() != num_assets || self.exch.len() != num_assets {
-            panic!();
-        }
-        Ok(MultiAssetSingleExchangeBacktest {
-            cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
-            local: self.local,
-            exch: self.exch,
-            _md_marker: Default::default(),
-        })
+    /// Returns the last market trades on the given venue of the asset. [`Bot::last_trades`]
+    /// returns those on the primary venue.
+    pub fn venue_last_trades(&self, asset_no: usize, venue: usize) -> &[Event] {
+        let slot = self.venues.get(asset_no).unwrap()[venue];
+        self.local[slot].last_trades()
     }
-}
-
-/// This backtester provides multi-asset and single-exchange model backtesting, meaning all assets
-/// have the same setups for models such as asset type or queue model. However, this can be slightly
-/// faster than [`Backtest`]. If you need to configure different models for each asset, use
-/// [`Backtest`].
-pub struct MultiAssetSingleExchangeBacktest<MD, Local, Exchange>
-where
-    MD: MarketDepth,
-    Local: LocalProcessor<MD>,
-    Exchange: Processor,
-{
-    cur_ts: i64,
-    evs: EventSet,
-    local: Vec<BacktestProcessorState<Local>>,
-    exch: Vec<BacktestProcessorState<Exchange>>,
-    _md_marker: PhantomData<MD>,
-}
 
-impl<MD, Local, Exchange> MultiAssetSingleExchangeBacktest<MD, Local, Exchange>
-where
-    MD: MarketDepth,
-    Local: LocalProcessor<MD>,
-    Exchange: Processor,
-{
-    pub fn builder() -> MultiAssetSingleExchangeBacktestBuilder<Local, Exchange> {
-        MultiAssetSingleExchangeBacktestBuilder {
-            local: vec![],
-            exch: vec![],
-        }
+    /// Returns the profit and loss on the given venue of the asset. See [`pnl_summary`] for the
+    /// primary venue.
+    ///
+    /// [`pnl_summary`]: Self::pnl_summary
+    pub fn venue_pnl_summary(&self, asset_no: usize, venue: usize) -> PnlSummary {
+        let slot = self.venues.get(asset_no).unwrap()[venue];
+        self.local[slot].pnl(self.cur_ts)
     }
 
-    pub fn new(local: Vec<Local>, exch: Vec<Exchange>, reader: Vec<Reader<Event>>) -> Self {
-        let num_assets = local.len();
-        if local.len() != num_assets || exch.len() != num_assets || reader.len() != num_assets {
-            panic!();
-        }
-
-        let local = local
-            .into_iter()
-            .zip(reader.iter())
-            .map(|(proc, reader)| BacktestProcessorState::new(proc, reader.clone()))
-            .collect();
-        let exch = exch
-            .into_iter()
-            .zip(reader.iter())
-            .map(|(proc, reader)| BacktestProcessorState::new(proc, reader.clone()))
-            .collect();
-
-        Self {
-            local,
-            exch,
-            cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
-            _md_marker: Default::default(),
-        }
+    /// Returns the local timestamp at which both sides of the asset's market depth first have at
+    /// least one level, or `None` if the order book hasn't been established yet. Until then, the
+    /// best bid or the best ask is invalid, and the exchange rejects new orders if the warmup is
+    /// enabled. See [`L3AssetBuilder::warmup`].
+    pub fn book_established_timestamp(&self, asset_no: usize) -> Option<i64> {
+        self.local[self.primary(asset_no)].book_established_timestamp()
     }
 
     fn initialize_evs(&mut self) -> Result<(), BacktestError> {
@@ -1271,16 +1825,444 @@ where
         Ok(())
     }
 
-    pub fn goto<const WAIT_NEXT_FEED: bool>(
+    /// Returns the order log of the asset. See [`LocalProcessor::order_log`].
+    pub fn order_log(&self, asset_no: usize) -> &[OrderLogEntry] {
+        self.local[self.primary(asset_no)].order_log()
+    }
+
+    /// Clears the order log. If `asset_no` is `None`, the order logs of all assets are cleared.
+    pub fn clear_order_log(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.local[slot].clear_order_log();
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_order_log();
+                }
+            }
+        }
+    }
+
+    /// Returns the market VWAP over the lifetime of each order of the asset whose lifetime has
+    /// ended, compared against the order's fills. See [`L3AssetBuilder::lifetime_vwap_capacity`].
+    pub fn lifetime_vwaps(&self, asset_no: usize) -> &[LifetimeVwap] {
+        self.local[self.primary(asset_no)].lifetime_vwaps()
+    }
+
+    /// Clears the lifetime VWAP records. If `asset_no` is `None`, the records of all assets are
+    /// cleared.
+    pub fn clear_lifetime_vwaps(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.local[slot].clear_lifetime_vwaps();
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_lifetime_vwaps();
+                }
+            }
+        }
+    }
+
+    /// Returns the mid price move over the horizon after each order of the asset that ended
+    /// canceled or expired with an unfilled quantity, whose horizon has ended.
+    /// See [`L3AssetBuilder::opportunity_cost_horizon`].
+    pub fn opportunity_costs(&self, asset_no: usize) -> &[OpportunityCost] {
+        self.local[self.primary(asset_no)].opportunity_costs()
+    }
+
+    /// Returns the aggregate of the opportunity costs of the asset.
+    pub fn opportunity_cost_summary(&self, asset_no: usize) -> OpportunityCostSummary {
+        OpportunityCostSummary::new(self.opportunity_costs(asset_no))
+    }
+
+    /// Clears the opportunity cost records. If `asset_no` is `None`, the records of all assets
+    /// are cleared.
+    pub fn clear_opportunity_costs(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.local[slot].clear_opportunity_costs();
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_opportunity_costs();
+                }
+            }
+        }
+    }
+
+    /// Returns the mid price move over the horizon after each maker fill of the asset, whose
+    /// horizon has ended. See [`L3AssetBuilder::adverse_selection_horizon`].
+    pub fn adverse_selections(&self, asset_no: usize) -> &[AdverseSelection] {
+        self.local[self.primary(asset_no)].adverse_selections()
+    }
+
+    /// Returns the aggregate of the adverse selection of the asset, whose volume-weighted mid
+    /// price move is negative if the maker fills are picked off on average.
+    pub fn adverse_selection_summary(&self, asset_no: usize) -> AdverseSelectionSummary {
+        AdverseSelectionSummary::new(self.adverse_selections(asset_no))
+    }
+
+    /// Clears the adverse selection records. If `asset_no` is `None`, the records of all assets
+    /// are cleared.
+    pub fn clear_adverse_selections(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.local[slot].clear_adverse_selections();
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_adverse_selections();
+                }
+            }
+        }
+    }
+
+    /// Returns the price levels of the asset emptied by the market feed fills, with the time
+    /// taken for each to recover. See [`L3AssetBuilder::resilience_recovery_fraction`].
+    pub fn sweep_recoveries(&self, asset_no: usize) -> &[SweepRecovery] {
+        self.local[self.primary(asset_no)].sweep_recoveries()
+    }
+
+    /// Returns the aggregate of the recovery times of the asset's swept price levels, the
+    /// resilience of the order book.
+    pub fn resilience_summary(&self, asset_no: usize) -> ResilienceSummary {
+        ResilienceSummary::new(self.sweep_recoveries(asset_no))
+    }
+
+    /// Clears the sweep recovery records. If `asset_no` is `None`, the records of all assets are
+    /// cleared.
+    pub fn clear_sweep_recoveries(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.local[slot].clear_sweep_recoveries();
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_sweep_recoveries();
+                }
+            }
+        }
+    }
+
+    /// Returns the maximum drawdown of the asset's equity, net of fees, as marked to market at the
+    /// frequency set by [`L3AssetBuilder::mark_frequency`]. `None` unless the local model
+    /// supports it.
+    pub fn max_drawdown(&self, asset_no: usize) -> Option<f64> {
+        self.local[self.primary(asset_no)].max_drawdown()
+    }
+
+    /// Returns the lifecycle timeline of the order of the asset, from the submission to the
+    /// terminal response. See [`L3AssetBuilder::order_timeline_capacity`].
+    pub fn order_timeline(&self, asset_no: usize, order_id: OrderId) -> &[OrderTimelineEntry] {
+        self.local[self.primary(asset_no)].order_timeline(order_id)
+    }
+
+    /// Returns the outcomes of the orders of the asset, fully filled, canceled, or expired, counted
+    /// by the distance from the touch at which each order is submitted, in ascending order of the
+    /// distance. See [`L3AssetBuilder::touch_distance_stats`].
+    pub fn touch_distance_stats(&self, asset_no: usize) -> &[TouchDistanceStats] {
+        self.local[self.primary(asset_no)].touch_distance_stats()
+    }
+
+    /// Clears the counts of the order outcomes by the distance from the touch. If `asset_no` is
+    /// `None`, the counts of all assets are cleared.
+    pub fn clear_touch_distance_stats(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.local[slot].clear_touch_distance_stats();
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_touch_distance_stats();
+                }
+            }
+        }
+    }
+
+    /// Returns the best bid and ask of the asset, including the own open orders, with whether the
+    /// top of each side contains an own order. The market depth only reflects the market feed, so
+    /// this tells whether the strategy is itself at the top, to avoid quoting against itself. The
+    /// own orders are those acknowledged by the exchange as of the local's current view.
+    pub fn best_bid_ask(&self, asset_no: usize) -> BestBidAsk {
+        let local = &self.local[self.primary(asset_no)];
+        let depth = local.depth();
+        let mut own_bid_tick = INVALID_MIN;
+        let mut own_ask_tick = INVALID_MAX;
+        for order in local.orders().values() {
+            if order.leaves_qty <= 0.0 || !order.active() {
+                continue;
+            }
+            match order.side {
+                Side::Buy => own_bid_tick = own_bid_tick.max(order.price_tick),
+                Side::Sell => own_ask_tick = own_ask_tick.min(order.price_tick),
+                Side::None | Side::Unsupported => {}
+            }
+        }
+        let best_bid_tick = depth.best_bid_tick().max(own_bid_tick);
+        let best_ask_tick = depth.best_ask_tick().min(own_ask_tick);
+        BestBidAsk {
+            best_bid: if best_bid_tick == INVALID_MIN {
+                f64::NAN
+            } else {
+                best_bid_tick as f64 * depth.tick_size()
+            },
+            best_ask: if best_ask_tick == INVALID_MAX {
+                f64::NAN
+            } else {
+                best_ask_tick as f64 * depth.tick_size()
+            },
+            own_bid: own_bid_tick != INVALID_MIN && own_bid_tick == best_bid_tick,
+            own_ask: own_ask_tick != INVALID_MAX && own_ask_tick == best_ask_tick,
+        }
+    }
+
+    /// Returns the fills of the asset compared against the mid price at the time of the fill in
+    /// the exchange. See [`L3AssetBuilder::fill_gap_capacity`].
+    pub fn fill_gaps(&self, asset_no: usize) -> &[FillGap] {
+        self.exch[self.primary(asset_no)].fill_gaps()
+    }
+
+    /// Returns the state samples of the asset, taken at the interval set by
+    /// [`BacktestBuilder::state_record_interval`], from the oldest. The equity and the position
+    /// are consolidated across all venues of the asset.
+    pub fn state_samples(&self, asset_no: usize) -> &[StateSample] {
+        self.state_recorder.samples(asset_no)
+    }
+
+    /// Clears the state samples. If `asset_no` is `None`, the samples of all assets are cleared.
+    pub fn clear_state_samples(&mut self, asset_no: Option<usize>) {
+        self.state_recorder.clear(asset_no);
+    }
+
+    /// Records the state samples of all assets due at or before `until`.
+    fn record_states(&mut self, until: i64) {
+        while let Some(timestamp) = self.state_recorder.next_due(until) {
+            for (asset_no, slots) in self.venues.iter().enumerate() {
+                let mut sample = StateSample {
+                    timestamp,
+                    equity: 0.0,
+                    position: 0.0,
+                    num_fills: 0,
+                };
+                for &slot in slots {
+                    let local = &self.local[slot];
+                    sample.equity += local.marked_pnl(timestamp).net_pnl;
+                    sample.position += local.position();
+                    sample.num_fills += local.state_values().num_trades;
+                }
+                self.state_recorder.push(asset_no, sample);
+            }
+        }
+    }
+
+    /// Returns every fill of the asset in the exchange, with the price, quantity, fee, and how
+    /// the order was filled. The fills remain available after [`Bot::close`]. See
+    /// [`L3AssetBuilder::fill_records`].
+    pub fn fill_records(&self, asset_no: usize) -> &[FillRecord] {
+        self.exch[self.primary(asset_no)].fill_records()
+    }
+
+    /// Clears the recorded fills. If `asset_no` is `None`, the fills of all assets are cleared.
+    pub fn clear_fill_records(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.exch[slot].clear_fill_records();
+            }
+            None => {
+                for exch in self.exch.iter_mut() {
+                    exch.clear_fill_records();
+                }
+            }
+        }
+    }
+
+    /// Returns the executed quantity of the fills of the asset by the distance in ticks from the
+    /// mid price at the time of the fill in the exchange, in ascending order of the distance. See
+    /// [`L3AssetBuilder::fill_price_histogram`].
+    pub fn fill_price_histogram(&self, asset_no: usize) -> &[FillPriceBucket] {
+        self.exch[self.primary(asset_no)].fill_price_histogram()
+    }
+
+    /// Clears the fill price histogram. If `asset_no` is `None`, the histograms of all assets
+    /// are cleared.
+    pub fn clear_fill_price_histogram(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.exch[slot].clear_fill_price_histogram();
+            }
+            None => {
+                for exch in self.exch.iter_mut() {
+                    exch.clear_fill_price_histogram();
+                }
+            }
+        }
+    }
+
+    /// Returns the profit and loss of the asset marked to the current mid price in the local, both
+    /// gross and net of fees, with the gross profit and loss decomposed into spread capture and
+    /// inventory. The average holding time marks the held position to the current timestamp.
+    /// Calling this after [`Bot::close`] gives the final summary of the run. Only the primary
+    /// venue of the asset is covered; see [`venue_pnl_summary`](Self::venue_pnl_summary).
+    pub fn pnl_summary(&self, asset_no: usize) -> PnlSummary {
+        self.local[self.primary(asset_no)].pnl(self.cur_ts)
+    }
+
+    /// Returns the aggregate of the fill gaps of the asset.
+    pub fn fill_gap_summary(&self, asset_no: usize) -> FillGapSummary {
+        FillGapSummary::new(self.fill_gaps(asset_no))
+    }
+
+    /// Clears the recorded fill gaps. If `asset_no` is `None`, the fill gaps of all assets are
+    /// cleared.
+    pub fn clear_fill_gaps(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.exch[slot].clear_fill_gaps();
+            }
+            None => {
+                for exch in self.exch.iter_mut() {
+                    exch.clear_fill_gaps();
+                }
+            }
+        }
+    }
+
+    /// Returns the fills of the asset compared against the nearest market print in the feed.
+    /// A fill is compared once the prints up to the end of its window have been processed, and
+    /// the remaining fills are compared at [`Bot::close`].
+    /// See [`L3AssetBuilder::print_divergence_window`].
+    pub fn print_divergences(&self, asset_no: usize) -> &[PrintDivergence] {
+        self.exch[self.primary(asset_no)].print_divergences()
+    }
+
+    /// Returns the aggregate of the print divergences of the asset.
+    pub fn print_divergence_summary(&self, asset_no: usize) -> PrintDivergenceSummary {
+        PrintDivergenceSummary::new(self.print_divergences(asset_no))
+    }
+
+    /// Clears the recorded print divergences. If `asset_no` is `None`, the print divergences of
+    /// all assets are cleared.
+    pub fn clear_print_divergences(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.exch[slot].clear_print_divergences();
+            }
+            None => {
+                for exch in self.exch.iter_mut() {
+                    exch.clear_print_divergences();
+                }
+            }
+        }
+    }
+
+    /// Returns the liquidity that each new order of the asset took on arrival at the exchange,
+    /// compared against what it would have taken under the alternative order latency. An order is
+    /// recorded once both its actual and its shadow arrival have occurred.
+    /// See [`L3AssetBuilder::shadow_latency_model`].
+    pub fn shadow_fills(&self, asset_no: usize) -> &[ShadowFill] {
+        self.exch[self.primary(asset_no)].shadow_fills()
+    }
+
+    /// Returns the total gross profit and loss that the recorded orders of the asset would have
+    /// made under the alternative order latency relative to the actual latency, marked to the
+    /// current mid price in the local. See [`ShadowFill::pnl_delta`]. Returns `None` if either
+    /// side of the market depth is empty, as there is no mid price to mark to.
+    pub fn shadow_pnl_delta(&self, asset_no: usize) -> Option<f64> {
+        let depth = self.local[self.primary(asset_no)].depth();
+        match depth.mid_price() {
+            Some((mid, false)) => Some(
+                self.shadow_fills(asset_no)
+                    .iter()
+                    .map(|fill| fill.pnl_delta(mid))
+                    .sum(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Clears the recorded shadow fills. If `asset_no` is `None`, the shadow fills of all assets
+    /// are cleared.
+    pub fn clear_shadow_fills(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let slot = self.primary(an);
+                self.exch[slot].clear_shadow_fills();
+            }
+            None => {
+                for exch in self.exch.iter_mut() {
+                    exch.clear_shadow_fills();
+                }
+            }
+        }
+    }
+
+    /// Returns the result of the last auction uncrossed in the exchange of the asset, with the
+    /// matched quantity of each backtest order. Returns `None` if no auction has occurred or the
+    /// exchange doesn't support auctions.
+    pub fn last_auction(&self, asset_no: usize) -> Option<&AuctionResult> {
+        self.exch[self.primary(asset_no)].last_auction()
+    }
+
+    /// Returns the result of the last auction of the asset as observed by the local from the
+    /// auction responses, which reach the local after the order response latency. The matched
+    /// quantity is estimated from the local market depth.
+    pub fn local_last_auction(&self, asset_no: usize) -> Option<&AuctionResult> {
+        self.local[self.primary(asset_no)].last_auction()
+    }
+
+    /// Returns the exchange's view of the backtest order of the asset, which reflects the fills
+    /// and the queue position at the exchange before the local receives the responses due to the
+    /// order latency. This is useful for reconciling the local order state against the exchange.
+    /// Returns `None` if the exchange neither holds the order nor has a response for it pending
+    /// to the local, or if the exchange doesn't support it.
+    pub fn exchange_order(&self, asset_no: usize, order_id: OrderId) -> Option<ExchangeOrderView> {
+        self.exch[self.order_venue(asset_no, order_id)].exchange_order(order_id)
+    }
+
+    pub fn goto_end(&mut self) -> Result<ElapseResult, BacktestError> {
+        if self.cur_ts == i64::MAX {
+            self.initialize_evs()?;
+            match self.evs.next() {
+                Some(ev) => {
+                    self.cur_ts = ev.timestamp;
+                }
+                None => {
+                    return Ok(ElapseResult::EndOfData);
+                }
+            }
+        }
+        self.goto::<false>(UNTIL_END_OF_DATA, WaitOrderResponse::None)
+    }
+
+    fn goto<const WAIT_NEXT_FEED: bool>(
         &mut self,
         timestamp: i64,
         wait_order_response: WaitOrderResponse,
     ) -> Result<ElapseResult, BacktestError> {
         let mut result = ElapseResult::Ok;
         let mut timestamp = timestamp;
-        for (asset_no, local) in self.local.iter().enumerate() {
-            self.evs
-                .update_exch_order(asset_no, local.earliest_send_order_timestamp());
+        self.state_recorder.start(self.cur_ts);
+        for (asset_no, (local, exch)) in self.local.iter().zip(self.exch.iter()).enumerate() {
+            // The exchange may also have to process the orders at their lifetime's deadline, which
+            // the local can't know.
+            self.evs.update_exch_order(
+                asset_no,
+                local
+                    .earliest_send_order_timestamp()
+                    .min(exch.earliest_recv_order_timestamp()),
+            );
             self.evs
                 .update_local_order(asset_no, local.earliest_recv_order_timestamp());
         }
@@ -1288,9 +2270,11 @@ where
             match self.evs.next() {
                 Some(ev) => {
                     if ev.timestamp > timestamp {
+                        self.record_states(timestamp);
                         self.cur_ts = timestamp;
                         return Ok(result);
                     }
+                    self.record_states(ev.timestamp - 1);
                     match ev.kind {
                         EventIntentKind::LocalData => {
                             let local = unsafe { self.local.get_unchecked_mut(ev.asset_no) };
@@ -1382,376 +2366,2377 @@ where
     }
 }
 
-impl<MD, Local, Exchange> Bot<MD> for MultiAssetSingleExchangeBacktest<MD, Local, Exchange>
-where
-    MD: MarketDepth,
-    Local: LocalProcessor<MD>,
-    Exchange: Processor,
-{
-    type Error = BacktestError;
+impl<MD> Bot<MD> for Backtest<MD>
+where
+    MD: MarketDepth,
+{
+    type Error = BacktestError;
+
+    #[inline]
+    fn current_timestamp(&self) -> i64 {
+        self.cur_ts
+    }
+
+    #[inline]
+    fn num_assets(&self) -> usize {
+        self.venues.len()
+    }
+
+    #[inline]
+    fn num_venues(&self, asset_no: usize) -> usize {
+        self.venues.get(asset_no).unwrap().len()
+    }
+
+    #[inline]
+    fn position(&self, asset_no: usize) -> f64 {
+        self.venues
+            .get(asset_no)
+            .unwrap()
+            .iter()
+            .map(|&slot| self.local[slot].position())
+            .sum()
+    }
+
+    #[inline]
+    fn time_since_last_fill(&self, asset_no: usize, now: i64) -> Option<i64> {
+        self.venues
+            .get(asset_no)
+            .unwrap()
+            .iter()
+            .filter_map(|&slot| self.local[slot].last_fill_timestamp())
+            .max()
+            .map(|last_fill_ts| now - last_fill_ts)
+    }
+
+    #[inline]
+    fn state_values(&self, asset_no: usize) -> &StateValues {
+        self.local[self.primary(asset_no)].state_values()
+    }
+
+    fn asset_params(&self, asset_no: usize) -> AssetParams {
+        self.local[self.primary(asset_no)].asset_params()
+    }
+
+    fn depth(&self, asset_no: usize) -> &MD {
+        self.local[self.primary(asset_no)].depth()
+    }
+
+    fn last_trades(&self, asset_no: usize) -> &[Event] {
+        self.local[self.primary(asset_no)].last_trades()
+    }
+
+    fn mid_history(&self, asset_no: usize, window: i64) -> Vec<(i64, f64)> {
+        self.local[self.primary(asset_no)].mid_history(self.cur_ts.saturating_sub(window))
+    }
+
+    #[inline]
+    fn clear_last_trades(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                for &slot in self.venues.get(an).unwrap() {
+                    self.local[slot].clear_last_trades();
+                }
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_last_trades();
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn orders(&self, asset_no: usize) -> &HashMap<u64, Order> {
+        self.local[self.primary(asset_no)].orders()
+    }
+
+    #[inline]
+    fn submit_buy_order(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        time_in_force: TimeInForce,
+        order_type: OrdType,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        self.submit_venue_order(
+            asset_no,
+            0,
+            OrderRequest {
+                order_id,
+                price,
+                qty,
+                side: Side::Buy,
+                time_in_force,
+                order_type,
+                max_lifetime: 0,
+                opening_auction: false,
+            },
+            wait,
+        )
+    }
+
+    #[inline]
+    fn submit_sell_order(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        time_in_force: TimeInForce,
+        order_type: OrdType,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        self.submit_venue_order(
+            asset_no,
+            0,
+            OrderRequest {
+                order_id,
+                price,
+                qty,
+                side: Side::Sell,
+                time_in_force,
+                order_type,
+                max_lifetime: 0,
+                opening_auction: false,
+            },
+            wait,
+        )
+    }
+
+    fn submit_order(
+        &mut self,
+        asset_no: usize,
+        order: OrderRequest,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        self.submit_venue_order(asset_no, 0, order, wait)
+    }
+
+    fn submit_venue_order(
+        &mut self,
+        asset_no: usize,
+        venue: usize,
+        order: OrderRequest,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        let venues = self.venues.get(asset_no).unwrap();
+        let slot = *venues
+            .get(venue)
+            .ok_or(BacktestError::VenueNotFound { asset_no, venue })?;
+        // The order ID identifies the order across all venues of the asset, as modify and cancel
+        // requests are routed by it.
+        if venues
+            .iter()
+            .any(|&other| other != slot && self.local[other].orders().contains_key(&order.order_id))
+        {
+            return Err(BacktestError::OrderIdExist);
+        }
+        let local = &mut self.local[slot];
+        local.submit_order(
+            order.order_id,
+            order.side,
+            order.price,
+            order.qty,
+            order.order_type,
+            order.time_in_force,
+            OrderOptions::from(&order),
+            self.cur_ts,
+        )?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified {
+                    asset_no: slot,
+                    order_id: order.order_id,
+                },
+            );
+        }
+        Ok(ElapseResult::Ok)
+    }
+
+    #[inline]
+    fn modify(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        let slot = self.order_venue(asset_no, order_id);
+        let local = &mut self.local[slot];
+        local.modify(order_id, price, qty, self.cur_ts)?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified {
+                    asset_no: slot,
+                    order_id,
+                },
+            );
+        }
+        Ok(ElapseResult::Ok)
+    }
+
+    #[inline]
+    fn cancel(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        let slot = self.order_venue(asset_no, order_id);
+        let local = &mut self.local[slot];
+        local.cancel(order_id, self.cur_ts)?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified {
+                    asset_no: slot,
+                    order_id,
+                },
+            );
+        }
+        Ok(ElapseResult::Ok)
+    }
+
+    #[inline]
+    fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(asset_no) => {
+                for &slot in self.venues.get(asset_no).unwrap() {
+                    self.local[slot].clear_inactive_orders();
+                }
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_inactive_orders();
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn wait_order_response(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        timeout: i64,
+    ) -> Result<ElapseResult, BacktestError> {
+        let slot = self.order_venue(asset_no, order_id);
+        self.goto::<false>(
+            self.cur_ts + timeout,
+            WaitOrderResponse::Specified {
+                asset_no: slot,
+                order_id,
+            },
+        )
+    }
+
+    #[inline]
+    fn wait_next_feed(
+        &mut self,
+        include_order_resp: bool,
+        timeout: i64,
+    ) -> Result<ElapseResult, Self::Error> {
+        if self.cur_ts == i64::MAX {
+            self.initialize_evs()?;
+            match self.evs.next() {
+                Some(ev) => {
+                    self.cur_ts = ev.timestamp;
+                }
+                None => {
+                    return Ok(ElapseResult::EndOfData);
+                }
+            }
+        }
+        if include_order_resp {
+            self.goto::<true>(self.cur_ts + timeout, WaitOrderResponse::Any)
+        } else {
+            self.goto::<true>(self.cur_ts + timeout, WaitOrderResponse::None)
+        }
+    }
+
+    #[inline]
+    fn elapse(&mut self, duration: i64) -> Result<ElapseResult, Self::Error> {
+        if self.cur_ts == i64::MAX {
+            self.initialize_evs()?;
+            match self.evs.next() {
+                Some(ev) => {
+                    self.cur_ts = ev.timestamp;
+                }
+                None => {
+                    return Ok(ElapseResult::EndOfData);
+                }
+            }
+        }
+        self.goto::<false>(self.cur_ts + duration, WaitOrderResponse::None)
+    }
+
+    #[inline]
+    fn elapse_bt(&mut self, duration: i64) -> Result<ElapseResult, Self::Error> {
+        self.elapse(duration)
+    }
+
+    fn close(&mut self) -> Result<(), Self::Error> {
+        for exch in self.exch.iter_mut() {
+            exch.flush_print_divergences();
+        }
+        for local in self.local.iter_mut() {
+            local.flush_audit_log()?;
+        }
+        for asset_no in 0..self.venues.len() {
+            if self.print_divergences(asset_no).is_empty() {
+                continue;
+            }
+            let summary = self.print_divergence_summary(asset_no);
+            tracing::info!(asset_no, ?summary, "fill divergence from the market prints");
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn feed_latency(&self, asset_no: usize) -> Option<(i64, i64)> {
+        self.local[self.primary(asset_no)].feed_latency()
+    }
+
+    #[inline]
+    fn order_latency(&self, asset_no: usize) -> Option<(i64, i64, i64)> {
+        self.local[self.primary(asset_no)].order_latency()
+    }
+}
+
+/// `MultiAssetSingleExchangeBacktest` builder.
+pub struct MultiAssetSingleExchangeBacktestBuilder<Local: Processor, Exchange: Processor> {
+    local: Vec<BacktestProcessorState<Local>>,
+    exch: Vec<BacktestProcessorState<Exchange>>,
+    ordering_timestamp: OrderingTimestamp,
+}
+
+impl<Local, Exchange> MultiAssetSingleExchangeBacktestBuilder<Local, Exchange>
+where
+    Local: LocalProcessor<HashMapMarketDepth> + 'static,
+    Exchange: Processor + 'static,
+{
+    /// Adds [`Asset`], which will undergo simulation within the backtester.
+    pub fn add_asset(self, asset: Asset<Local, Exchange, Event>) -> Self {
+        let mut self_ = Self { ..self };
+        self_.local.push(BacktestProcessorState::new(
+            *asset.local,
+            asset.reader.clone(),
+        ));
+        self_.exch.push(BacktestProcessorState::new(
+            *asset.exch,
+            asset.reader.clone(),
+        ));
+        self_
+    }
+
+    /// Sets the timestamp field used to order the events across all processors.
+    /// See [`BacktestBuilder::ordering_timestamp`].
+    pub fn ordering_timestamp(self, ordering_timestamp: OrderingTimestamp) -> Self {
+        Self {
+            ordering_timestamp,
+            ..self
+        }
+    }
+
+    /// Builds [`MultiAssetSingleExchangeBacktest`].
+    pub fn build(
+        mut self,
+    ) -> Result<MultiAssetSingleExchangeBacktest<HashMapMarketDepth, Local, Exchange>, BuildError>
+    {
+        let num_assets = self.local.len();
+        if self.local.len() != num_assets || self.exch.len() != num_assets {
+            panic!();
+        }
+        for local in self.local.iter_mut() {
+            local.ordering = self.ordering_timestamp;
+        }
+        for exch in self.exch.iter_mut() {
+            exch.ordering = self.ordering_timestamp;
+        }
+        Ok(MultiAssetSingleExchangeBacktest {
+            cur_ts: i64::MAX,
+            evs: EventSet::new(num_assets),
+            local: self.local,
+            exch: self.exch,
+            _md_marker: Default::default(),
+        })
+    }
+}
+
+/// This backtester provides multi-asset and single-exchange model backtesting, meaning all assets
+/// have the same setups for models such as asset type or queue model. However, this can be slightly
+/// faster than [`Backtest`]. If you need to configure different models for each asset, use
+/// [`Backtest`].
+pub struct MultiAssetSingleExchangeBacktest<MD, Local, Exchange>
+where
+    MD: MarketDepth,
+    Local: LocalProcessor<MD>,
+    Exchange: Processor,
+{
+    cur_ts: i64,
+    evs: EventSet,
+    local: Vec<BacktestProcessorState<Local>>,
+    exch: Vec<BacktestProcessorState<Exchange>>,
+    _md_marker: PhantomData<MD>,
+}
+
+impl<MD, Local, Exchange> MultiAssetSingleExchangeBacktest<MD, Local, Exchange>
+where
+    MD: MarketDepth,
+    Local: LocalProcessor<MD>,
+    Exchange: Processor,
+{
+    pub fn builder() -> MultiAssetSingleExchangeBacktestBuilder<Local, Exchange> {
+        MultiAssetSingleExchangeBacktestBuilder {
+            local: vec![],
+            exch: vec![],
+            ordering_timestamp: OrderingTimestamp::Native,
+        }
+    }
+
+    pub fn new(local: Vec<Local>, exch: Vec<Exchange>, reader: Vec<Reader<Event>>) -> Self {
+        let num_assets = local.len();
+        if local.len() != num_assets || exch.len() != num_assets || reader.len() != num_assets {
+            panic!();
+        }
+
+        let local = local
+            .into_iter()
+            .zip(reader.iter())
+            .map(|(proc, reader)| BacktestProcessorState::new(proc, reader.clone()))
+            .collect();
+        let exch = exch
+            .into_iter()
+            .zip(reader.iter())
+            .map(|(proc, reader)| BacktestProcessorState::new(proc, reader.clone()))
+            .collect();
+
+        Self {
+            local,
+            exch,
+            cur_ts: i64::MAX,
+            evs: EventSet::new(num_assets),
+            _md_marker: Default::default(),
+        }
+    }
+
+    fn initialize_evs(&mut self) -> Result<(), BacktestError> {
+        for (asset_no, local) in self.local.iter_mut().enumerate() {
+            match local.advance() {
+                Ok(ts) => self.evs.update_local_data(asset_no, ts),
+                Err(BacktestError::EndOfData) => {
+                    self.evs.invalidate_local_data(asset_no);
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+        for (asset_no, exch) in self.exch.iter_mut().enumerate() {
+            match exch.advance() {
+                Ok(ts) => self.evs.update_exch_data(asset_no, ts),
+                Err(BacktestError::EndOfData) => {
+                    self.evs.invalidate_exch_data(asset_no);
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn goto<const WAIT_NEXT_FEED: bool>(
+        &mut self,
+        timestamp: i64,
+        wait_order_response: WaitOrderResponse,
+    ) -> Result<ElapseResult, BacktestError> {
+        let mut result = ElapseResult::Ok;
+        let mut timestamp = timestamp;
+        for (asset_no, (local, exch)) in self.local.iter().zip(self.exch.iter()).enumerate() {
+            // The exchange may also have to process the orders at their lifetime's deadline, which
+            // the local can't know.
+            self.evs.update_exch_order(
+                asset_no,
+                local
+                    .earliest_send_order_timestamp()
+                    .min(exch.earliest_recv_order_timestamp()),
+            );
+            self.evs
+                .update_local_order(asset_no, local.earliest_recv_order_timestamp());
+        }
+        loop {
+            match self.evs.next() {
+                Some(ev) => {
+                    if ev.timestamp > timestamp {
+                        self.cur_ts = timestamp;
+                        return Ok(result);
+                    }
+                    match ev.kind {
+                        EventIntentKind::LocalData => {
+                            let local = unsafe { self.local.get_unchecked_mut(ev.asset_no) };
+                            let next = local.next_row().and_then(|row| {
+                                local.processor.process(&local.data[row])?;
+                                local.advance()
+                            });
+
+                            match next {
+                                Ok(next_ts) => {
+                                    self.evs.update_local_data(ev.asset_no, next_ts);
+                                }
+                                Err(BacktestError::EndOfData) => {
+                                    self.evs.invalidate_local_data(ev.asset_no);
+                                }
+                                Err(e) => {
+                                    return Err(e);
+                                }
+                            }
+                            if WAIT_NEXT_FEED {
+                                timestamp = ev.timestamp;
+                                result = ElapseResult::MarketFeed;
+                            }
+                        }
+                        EventIntentKind::LocalOrder => {
+                            let local = unsafe { self.local.get_unchecked_mut(ev.asset_no) };
+                            let wait_order_resp_id = match wait_order_response {
+                                WaitOrderResponse::Specified {
+                                    asset_no: wait_order_asset_no,
+                                    order_id: wait_order_id,
+                                } if ev.asset_no == wait_order_asset_no => Some(wait_order_id),
+                                _ => None,
+                            };
+                            if local.process_recv_order(ev.timestamp, wait_order_resp_id)?
+                                || wait_order_response == WaitOrderResponse::Any
+                            {
+                                timestamp = ev.timestamp;
+                                if WAIT_NEXT_FEED {
+                                    result = ElapseResult::OrderResponse;
+                                }
+                            }
+                            self.evs.update_local_order(
+                                ev.asset_no,
+                                local.earliest_recv_order_timestamp(),
+                            );
+                        }
+                        EventIntentKind::ExchData => {
+                            let exch = unsafe { self.exch.get_unchecked_mut(ev.asset_no) };
+                            let next = exch.next_row().and_then(|row| {
+                                exch.processor.process(&exch.data[row])?;
+                                exch.advance()
+                            });
+
+                            match next {
+                                Ok(next_ts) => {
+                                    self.evs.update_exch_data(ev.asset_no, next_ts);
+                                }
+                                Err(BacktestError::EndOfData) => {
+                                    self.evs.invalidate_exch_data(ev.asset_no);
+                                }
+                                Err(e) => {
+                                    return Err(e);
+                                }
+                            }
+                            self.evs.update_local_order(
+                                ev.asset_no,
+                                exch.earliest_send_order_timestamp(),
+                            );
+                        }
+                        EventIntentKind::ExchOrder => {
+                            let exch = unsafe { self.exch.get_unchecked_mut(ev.asset_no) };
+                            let _ = exch.process_recv_order(ev.timestamp, None)?;
+                            self.evs.update_exch_order(
+                                ev.asset_no,
+                                exch.earliest_recv_order_timestamp(),
+                            );
+                            self.evs.update_local_order(
+                                ev.asset_no,
+                                exch.earliest_send_order_timestamp(),
+                            );
+                        }
+                    }
+                }
+                None => {
+                    return Ok(ElapseResult::EndOfData);
+                }
+            }
+        }
+    }
+}
+
+impl<MD, Local, Exchange> Bot<MD> for MultiAssetSingleExchangeBacktest<MD, Local, Exchange>
+where
+    MD: MarketDepth,
+    Local: LocalProcessor<MD>,
+    Exchange: Processor,
+{
+    type Error = BacktestError;
+
+    #[inline]
+    fn current_timestamp(&self) -> i64 {
+        self.cur_ts
+    }
+
+    #[inline]
+    fn num_assets(&self) -> usize {
+        self.local.len()
+    }
+
+    #[inline]
+    fn position(&self, asset_no: usize) -> f64 {
+        self.local.get(asset_no).unwrap().position()
+    }
+
+    #[inline]
+    fn time_since_last_fill(&self, asset_no: usize, now: i64) -> Option<i64> {
+        self.local
+            .get(asset_no)
+            .unwrap()
+            .last_fill_timestamp()
+            .map(|last_fill_ts| now - last_fill_ts)
+    }
+
+    #[inline]
+    fn state_values(&self, asset_no: usize) -> &StateValues {
+        self.local.get(asset_no).unwrap().state_values()
+    }
+
+    fn asset_params(&self, asset_no: usize) -> AssetParams {
+        self.local.get(asset_no).unwrap().asset_params()
+    }
+
+    fn depth(&self, asset_no: usize) -> &MD {
+        self.local.get(asset_no).unwrap().depth()
+    }
+
+    fn last_trades(&self, asset_no: usize) -> &[Event] {
+        self.local.get(asset_no).unwrap().last_trades()
+    }
+
+    fn mid_history(&self, asset_no: usize, window: i64) -> Vec<(i64, f64)> {
+        self.local
+            .get(asset_no)
+            .unwrap()
+            .mid_history(self.cur_ts.saturating_sub(window))
+    }
+
+    #[inline]
+    fn clear_last_trades(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(an) => {
+                let local = self.local.get_mut(an).unwrap();
+                local.clear_last_trades();
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_last_trades();
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn orders(&self, asset_no: usize) -> &HashMap<OrderId, Order> {
+        self.local.get(asset_no).unwrap().orders()
+    }
+
+    #[inline]
+    fn submit_buy_order(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        time_in_force: TimeInForce,
+        order_type: OrdType,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.submit_order(
+            order_id,
+            Side::Buy,
+            price,
+            qty,
+            order_type,
+            time_in_force,
+            OrderOptions::default(),
+            self.cur_ts,
+        )?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified { asset_no, order_id },
+            );
+        }
+        Ok(ElapseResult::Ok)
+    }
+
+    #[inline]
+    fn submit_sell_order(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        time_in_force: TimeInForce,
+        order_type: OrdType,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.submit_order(
+            order_id,
+            Side::Sell,
+            price,
+            qty,
+            order_type,
+            time_in_force,
+            OrderOptions::default(),
+            self.cur_ts,
+        )?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified { asset_no, order_id },
+            );
+        }
+        Ok(ElapseResult::Ok)
+    }
+
+    fn submit_order(
+        &mut self,
+        asset_no: usize,
+        order: OrderRequest,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.submit_order(
+            order.order_id,
+            Side::Sell,
+            order.price,
+            order.qty,
+            order.order_type,
+            order.time_in_force,
+            OrderOptions::from(&order),
+            self.cur_ts,
+        )?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified {
+                    asset_no,
+                    order_id: order.order_id,
+                },
+            );
+        }
+        Ok(ElapseResult::Ok)
+    }
+
+    fn submit_venue_order(
+        &mut self,
+        asset_no: usize,
+        venue: usize,
+        order: OrderRequest,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        if venue != 0 {
+            return Err(BacktestError::VenueNotFound { asset_no, venue });
+        }
+        self.submit_order(asset_no, order, wait)
+    }
+
+    #[inline]
+    fn modify(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        price: f64,
+        qty: f64,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.modify(order_id, price, qty, self.cur_ts)?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified { asset_no, order_id },
+            );
+        }
+        Ok(ElapseResult::Ok)
+    }
+
+    #[inline]
+    fn cancel(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.cancel(order_id, self.cur_ts)?;
+
+        if wait {
+            return self.goto::<false>(
+                UNTIL_END_OF_DATA,
+                WaitOrderResponse::Specified { asset_no, order_id },
+            );
+        }
+        Ok(ElapseResult::Ok)
+    }
+
+    #[inline]
+    fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(asset_no) => {
+                self.local
+                    .get_mut(asset_no)
+                    .unwrap()
+                    .clear_inactive_orders();
+            }
+            None => {
+                for local in self.local.iter_mut() {
+                    local.clear_inactive_orders();
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn wait_order_response(
+        &mut self,
+        asset_no: usize,
+        order_id: OrderId,
+        timeout: i64,
+    ) -> Result<ElapseResult, BacktestError> {
+        self.goto::<false>(
+            self.cur_ts + timeout,
+            WaitOrderResponse::Specified { asset_no, order_id },
+        )
+    }
+
+    fn wait_next_feed(
+        &mut self,
+        include_order_resp: bool,
+        timeout: i64,
+    ) -> Result<ElapseResult, Self::Error> {
+        if self.cur_ts == i64::MAX {
+            self.initialize_evs()?;
+            match self.evs.next() {
+                Some(ev) => {
+                    self.cur_ts = ev.timestamp;
+                }
+                None => {
+                    return Ok(ElapseResult::EndOfData);
+                }
+            }
+        }
+        if include_order_resp {
+            self.goto::<true>(self.cur_ts + timeout, WaitOrderResponse::Any)
+        } else {
+            self.goto::<true>(self.cur_ts + timeout, WaitOrderResponse::None)
+        }
+    }
+
+    #[inline]
+    fn elapse(&mut self, duration: i64) -> Result<ElapseResult, Self::Error> {
+        if self.cur_ts == i64::MAX {
+            self.initialize_evs()?;
+            match self.evs.next() {
+                Some(ev) => {
+                    self.cur_ts = ev.timestamp;
+                }
+                None => {
+                    return Ok(ElapseResult::EndOfData);
+                }
+            }
+        }
+        self.goto::<false>(self.cur_ts + duration, WaitOrderResponse::None)
+    }
+
+    #[inline]
+    fn elapse_bt(&mut self, duration: i64) -> Result<ElapseResult, Self::Error> {
+        self.elapse(duration)
+    }
+
+    #[inline]
+    fn close(&mut self) -> Result<(), Self::Error> {
+        for local in self.local.iter_mut() {
+            local.flush_audit_log()?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn feed_latency(&self, asset_no: usize) -> Option<(i64, i64)> {
+        self.local.get(asset_no).unwrap().feed_latency()
+    }
+
+    #[inline]
+    fn order_latency(&self, asset_no: usize) -> Option<(i64, i64, i64)> {
+        self.local.get(asset_no).unwrap().order_latency()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            AuctionAlgorithm, AuctionFallbackPolicy, Backtest, BacktestError, DataSource,
+            ExchangeKind::{NoPartialFillExchange, PartialFillExchange},
+            FillResponseMode, L2AssetBuilder, L3AssetBuilder, OrderingTimestamp,
+            SelfTradePrevention, SubLotResidualPolicy,
+            assettype::{AssetType, InverseAsset, LinearAsset, QuantoAsset},
+            data::{Data, write_npy},
+            models::{
+                CommonFees, ConstantLatency, L3FIFOQueueModel, LatencyModel, PowerProbQueueFunc3,
+                ProbQueueModel, TradingValueFeeModel,
+            },
+            state::State,
+            testutil::{l3_asset, l3_event},
+        },
+        depth::{HashMapMarketDepth, INVALID_MAX, MarketDepth, ROIVectorMarketDepth},
+        prelude::{
+            AssetParams, Bot, Event, OrdType, Order, OrderId, OrderRequest, Side, Status,
+            TimeInForce,
+        },
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, BuildError, CANCEL_ORDER_EVENT, CancelReason, DEPTH_EVENT,
+            EXCH_EVENT, EXCH_FILL_EVENT, LOCAL_EVENT, SELL_EVENT, TRADE_EVENT,
+        },
+    };
+
+    #[test]
+    fn skips_unseen_events() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            Event {
+                ev: EXCH_EVENT | LOCAL_EVENT,
+                exch_ts: 0,
+                local_ts: 0,
+                px: 0.0,
+                qty: 0.0,
+                order_id: 0,
+                ival: 0,
+                fval: 0.0,
+            },
+            Event {
+                ev: LOCAL_EVENT | EXCH_EVENT,
+                exch_ts: 1,
+                local_ts: 1,
+                px: 0.0,
+                qty: 0.0,
+                order_id: 0,
+                ival: 0,
+                fval: 0.0,
+            },
+            Event {
+                ev: EXCH_EVENT,
+                exch_ts: 3,
+                local_ts: 4,
+                px: 0.0,
+                qty: 0.0,
+                order_id: 0,
+                ival: 0,
+                fval: 0.0,
+            },
+            Event {
+                ev: LOCAL_EVENT,
+                exch_ts: 3,
+                local_ts: 4,
+                px: 0.0,
+                qty: 0.0,
+                order_id: 0,
+                ival: 0,
+                fval: 0.0,
+            },
+        ]);
+
+        let mut backtester = Backtest::builder()
+            .add_asset(
+                L2AssetBuilder::default()
+                    .data(vec![DataSource::Data(data)])
+                    .latency_model(ConstantLatency::new(50, 50))
+                    .asset_type(LinearAsset::new(1.0))
+                    .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                    .queue_model(ProbQueueModel::new(PowerProbQueueFunc3::new(3.0)))
+                    .exchange(NoPartialFillExchange)
+                    .depth(|| HashMapMarketDepth::new(0.01, 1.0))
+                    .build()?,
+            )
+            .build()?;
+
+        // Process first events and advance a single timestep
+        backtester.elapse_bt(1)?;
+        assert_eq!(1, backtester.cur_ts);
+
+        // Check that we correctly skip past events that aren't seen by a given processor
+        backtester.elapse_bt(1)?;
+        assert_eq!(2, backtester.cur_ts);
+        assert_eq!(Some(3), backtester.local[0].row);
+        assert_eq!(Some(2), backtester.exch[0].row);
 
-    #[inline]
-    fn current_timestamp(&self) -> i64 {
-        self.cur_ts
-    }
+        backtester.elapse_bt(1)?;
+        assert_eq!(3, backtester.cur_ts);
 
-    #[inline]
-    fn num_assets(&self) -> usize {
-        self.local.len()
+        Ok(())
     }
 
-    #[inline]
-    fn position(&self, asset_no: usize) -> f64 {
-        self.local.get(asset_no).unwrap().position()
+    #[test]
+    fn asset_params_reflect_configuration() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[l3_event(EXCH_EVENT | LOCAL_EVENT, 0, 0.0, 0.0, 0)]);
+
+        let backtester = Backtest::builder()
+            .add_asset(
+                L2AssetBuilder::default()
+                    .data(vec![DataSource::Data(data)])
+                    .latency_model(ConstantLatency::new(50, 50))
+                    .asset_type(LinearAsset::new(5.0))
+                    .fee_model(TradingValueFeeModel::new(CommonFees::new(-0.00005, 0.0007)))
+                    .queue_model(ProbQueueModel::new(PowerProbQueueFunc3::new(3.0)))
+                    .exchange(NoPartialFillExchange)
+                    .depth(|| HashMapMarketDepth::new(0.25, 0.001))
+                    .build()?,
+            )
+            .build()?;
+
+        assert_eq!(
+            backtester.asset_params(0),
+            AssetParams {
+                tick_size: 0.25,
+                lot_size: 0.001,
+                contract_size: 5.0,
+                maker_fee: -0.00005,
+                taker_fee: 0.0007,
+            }
+        );
+        Ok(())
     }
 
-    #[inline]
-    fn state_values(&self, asset_no: usize) -> &StateValues {
-        self.local.get(asset_no).unwrap().state_values()
+    #[test]
+    fn inventory_skew_clamped_to_unit_range() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                10.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        assert_eq!(hbt.inventory_skew(0, 0.0, 2.0), 0.0);
+        assert_eq!(hbt.inventory_skew(0, 1.0, 2.0), -0.5);
+
+        for order_id in 10..13 {
+            hbt.submit_buy_order(
+                0,
+                order_id,
+                101.0,
+                1.0,
+                TimeInForce::IOC,
+                OrdType::Limit,
+                true,
+            )?;
+            let position = hbt.position(0);
+            assert_eq!(position, (order_id - 9) as f64);
+            assert_eq!(hbt.inventory_skew(0, 0.0, 2.0), (position / 2.0).min(1.0));
+        }
+        assert_eq!(hbt.inventory_skew(0, 0.0, 2.0), 1.0);
+        assert_eq!(hbt.inventory_skew(0, 2.0, 2.0), 0.5);
+        assert_eq!(hbt.inventory_skew(0, 4.0, 2.0), -0.5);
+        assert_eq!(hbt.inventory_skew(0, 10.0, 2.0), -1.0);
+        // A maximum position that isn't positive doesn't skew.
+        assert_eq!(hbt.inventory_skew(0, 0.0, 0.0), 0.0);
+        assert_eq!(hbt.inventory_skew(0, 0.0, -2.0), 0.0);
+        Ok(())
     }
 
-    fn depth(&self, asset_no: usize) -> &MD {
-        self.local.get(asset_no).unwrap().depth()
+    #[test]
+    fn round_trip_pnl_by_asset_type() {
+        fn round_trip<AT: AssetType>(asset_type: AT) -> (f64, f64) {
+            let mut state = State::new(
+                asset_type,
+                TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)),
+            );
+            let fill = |side: Side, price_tick: i64| {
+                let mut order = Order::new(
+                    1,
+                    price_tick,
+                    1.0,
+                    2.0,
+                    side,
+                    OrdType::Limit,
+                    TimeInForce::GTC,
+                );
+                order.exec_price_tick = price_tick;
+                order.exec_qty = 2.0;
+                order.leaves_qty = 0.0;
+                order
+            };
+            state.apply_fill(&fill(Side::Buy, 100));
+            let unrealized = state.pnl(110.0, 0).gross_pnl;
+            state.apply_fill(&fill(Side::Sell, 110));
+            assert_eq!(state.values().position, 0.0);
+            (unrealized, state.pnl(120.0, 0).gross_pnl)
+        }
+
+        let close = |(unrealized, realized): (f64, f64), expected: f64| {
+            assert!((unrealized - expected).abs() < 1e-9);
+            assert!((realized - expected).abs() < 1e-9);
+        };
+        // Buys 2 at 100 and sells 2 at 110.
+        close(round_trip(LinearAsset::new(10.0)), 10.0 * 2.0 * 10.0);
+        close(
+            round_trip(InverseAsset::new(10.0)),
+            10.0 * 2.0 * (1.0 / 100.0 - 1.0 / 110.0),
+        );
+        close(
+            round_trip(QuantoAsset::new(10.0, 0.5)),
+            0.5 * 10.0 * 2.0 * 10.0,
+        );
     }
 
-    fn last_trades(&self, asset_no: usize) -> &[Event] {
-        self.local.get(asset_no).unwrap().last_trades()
+    #[test]
+    fn negative_prices_across_zero() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                -2.0,
+                2.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                -1.5,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                -0.5,
+                1.0,
+                3,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                0.5,
+                5.0,
+                4,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                L3AssetBuilder::default()
+                    .data(vec![DataSource::Data(data)])
+                    .latency_model(ConstantLatency::new(10, 20))
+                    .asset_type(LinearAsset::new(1.0))
+                    .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                    .queue_model(L3FIFOQueueModel::new())
+                    .exchange(PartialFillExchange)
+                    .depth(|| ROIVectorMarketDepth::new(0.5, 1.0, -5.0, 5.0))
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        assert_eq!(hbt.depth(0).best_bid_tick(), -3);
+        assert_eq!(hbt.depth(0).best_bid(), -1.5);
+        assert_eq!(hbt.depth(0).best_ask_tick(), -1);
+        assert_eq!(hbt.depth(0).best_ask(), -0.5);
+
+        // Rests between the best bid and ask.
+        hbt.submit_sell_order(0, 10, -1.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::New);
+        assert_eq!(hbt.orders(0).get(&10).unwrap().price_tick, -2);
+        hbt.cancel(0, 10, true)?;
+
+        hbt.submit_buy_order(0, 11, -0.5, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&11).unwrap();
+        assert_eq!(order.status, Status::Filled);
+        assert_eq!(order.exec_price(), -0.5);
+
+        hbt.submit_sell_order(0, 12, -2.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&12).unwrap();
+        assert_eq!(order.status, Status::Filled);
+        assert_eq!(order.exec_price(), -1.5);
+        assert_eq!(hbt.position(0), 0.0);
+        // Bought at -0.5 and sold at -1.5.
+        assert_eq!(hbt.state_values(0).balance, -1.0);
+        Ok(())
     }
 
-    #[inline]
-    fn clear_last_trades(&mut self, asset_no: Option<usize>) {
-        match asset_no {
-            Some(an) => {
-                let local = self.local.get_mut(an).unwrap();
-                local.clear_last_trades();
-            }
-            None => {
-                for local in self.local.iter_mut() {
-                    local.clear_last_trades();
-                }
-            }
-        }
+    #[test]
+    fn realized_and_unrealized_pnl() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                5.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                10.0,
+                2,
+            ),
+            // Moves the best ask, and thus the mid, up.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                200,
+                101.0,
+                0.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                200,
+                103.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 101.0, 2.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        let values = hbt.state_values(0);
+        assert_eq!(values.position, 2.0);
+        assert_eq!(values.avg_entry_price, 101.0);
+        assert_eq!(values.realized_pnl, 0.0);
+        // Marked at the mid of 100.5.
+        assert_eq!(values.unrealized_pnl, -1.0);
+
+        hbt.elapse(300)?;
+        let values = hbt.state_values(0);
+        assert_eq!(values.realized_pnl, 0.0);
+        // Marked at the mid of 101.5.
+        assert_eq!(values.unrealized_pnl, 1.0);
+
+        hbt.submit_sell_order(0, 11, 100.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        let values = hbt.state_values(0);
+        assert_eq!(values.position, 1.0);
+        assert_eq!(values.avg_entry_price, 101.0);
+        assert_eq!(values.realized_pnl, -1.0);
+        assert_eq!(values.unrealized_pnl, 0.5);
+
+        hbt.submit_sell_order(0, 12, 100.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        let values = hbt.state_values(0);
+        assert_eq!(values.position, 0.0);
+        assert_eq!(values.avg_entry_price, 0.0);
+        assert_eq!(values.realized_pnl, -2.0);
+        assert_eq!(values.unrealized_pnl, 0.0);
+        assert_eq!(values.fee, 0.0);
+        Ok(())
     }
 
-    #[inline]
-    fn orders(&self, asset_no: usize) -> &HashMap<OrderId, Order> {
-        self.local.get(asset_no).unwrap().orders()
+    #[test]
+    fn time_since_last_fill_resets_on_fill() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                5.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        let since = |hbt: &Backtest<HashMapMarketDepth>| {
+            hbt.time_since_last_fill(0, hbt.current_timestamp())
+        };
+
+        hbt.elapse(1)?;
+        assert_eq!(since(&hbt), None);
+
+        // The fill is received at 31.
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        assert_eq!(since(&hbt), Some(0));
+        hbt.elapse(100)?;
+        assert_eq!(since(&hbt), Some(100));
+        hbt.elapse(100)?;
+        assert_eq!(since(&hbt), Some(200));
+
+        hbt.submit_buy_order(0, 11, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        assert_eq!(since(&hbt), Some(0));
+        Ok(())
     }
 
-    #[inline]
-    fn submit_buy_order(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        price: f64,
-        qty: f64,
-        time_in_force: TimeInForce,
-        order_type: OrdType,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.submit_order(
-            order_id,
-            Side::Buy,
-            price,
+    #[test]
+    fn synthetic_events() -> Result<(), Box<dyn Error>> {
+        let event = |ev: u64, ts: i64, px: f64, qty: f64| Event {
+            ev: EXCH_EVENT | LOCAL_EVENT | ev,
+            exch_ts: ts,
+            local_ts: ts + 5,
+            px,
             qty,
-            order_type,
-            time_in_force,
-            self.cur_ts,
-        )?;
+            order_id: 0,
+            ival: 0,
+            fval: 0.0,
+        };
+        let events = vec![
+            event(BUY_EVENT | DEPTH_EVENT, 0, 100.0, 5.0),
+            event(SELL_EVENT | DEPTH_EVENT, 0, 101.0, 5.0),
+            event(SELL_EVENT | TRADE_EVENT, 100, 99.0, 1.0),
+            event(0, 1000, 0.0, 0.0),
+        ];
+
+        let mut unsorted = events.clone();
+        unsorted.swap(2, 3);
+        assert!(DataSource::from_events(unsorted).is_err());
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                L2AssetBuilder::default()
+                    .data(vec![DataSource::from_events(events)?])
+                    .latency_model(ConstantLatency::new(10, 20))
+                    .asset_type(LinearAsset::new(1.0))
+                    .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                    .queue_model(ProbQueueModel::new(PowerProbQueueFunc3::new(3.0)))
+                    .exchange(NoPartialFillExchange)
+                    .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                    .build()?,
+            )
+            .build()?;
 
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified { asset_no, order_id },
-            );
-        }
-        Ok(ElapseResult::Ok)
+        hbt.elapse(10)?;
+        assert_eq!(hbt.depth(0).best_bid(), 100.0);
+        assert_eq!(hbt.depth(0).best_ask(), 101.0);
+
+        hbt.submit_buy_order(0, 1, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        // The sell trade below the order price fills the order.
+        hbt.elapse(500)?;
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::Filled);
+        assert_eq!(hbt.position(0), 1.0);
+        Ok(())
     }
 
-    #[inline]
-    fn submit_sell_order(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        price: f64,
-        qty: f64,
-        time_in_force: TimeInForce,
-        order_type: OrdType,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.submit_order(
-            order_id,
-            Side::Sell,
-            price,
+    #[test]
+    fn l2_max_lifetime_cancels_order_after_resting() -> Result<(), Box<dyn Error>> {
+        let event = |ev: u64, ts: i64, px: f64, qty: f64| Event {
+            ev: EXCH_EVENT | LOCAL_EVENT | ev,
+            exch_ts: ts,
+            local_ts: ts,
+            px,
             qty,
-            order_type,
-            time_in_force,
-            self.cur_ts,
-        )?;
-
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified { asset_no, order_id },
-            );
+            order_id: 0,
+            ival: 0,
+            fval: 0.0,
+        };
+        for exchange in [NoPartialFillExchange, PartialFillExchange] {
+            let events = vec![
+                event(BUY_EVENT | DEPTH_EVENT, 0, 100.0, 5.0),
+                event(SELL_EVENT | DEPTH_EVENT, 0, 101.0, 5.0),
+                event(0, 1000, 0.0, 0.0),
+            ];
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    L2AssetBuilder::default()
+                        .data(vec![DataSource::from_events(events)?])
+                        .latency_model(ConstantLatency::new(10, 20))
+                        .asset_type(LinearAsset::new(1.0))
+                        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                        .queue_model(ProbQueueModel::new(PowerProbQueueFunc3::new(3.0)))
+                        .exchange(exchange)
+                        .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(10)?;
+            hbt.submit_order(
+                0,
+                OrderRequest {
+                    order_id: 1,
+                    price: 99.0,
+                    qty: 1.0,
+                    side: Side::Buy,
+                    time_in_force: TimeInForce::GTC,
+                    order_type: OrdType::Limit,
+                    max_lifetime: 100,
+                    opening_auction: false,
+                },
+                true,
+            )?;
+            assert_eq!(hbt.orders(0).get(&1).unwrap().exch_timestamp, 20);
+
+            // The order rests at 20 and is canceled at 120 without any market feed event.
+            hbt.elapse(500)?;
+            let order = hbt.orders(0).get(&1).unwrap();
+            assert_eq!(order.status, Status::Canceled);
+            assert_eq!(order.cancel_reason, CancelReason::MaxLifetime);
+            assert_eq!(order.canceled_qty, 1.0);
+            assert_eq!(order.exch_timestamp, 120);
         }
-        Ok(ElapseResult::Ok)
+        Ok(())
     }
 
-    fn submit_order(
-        &mut self,
-        asset_no: usize,
-        order: OrderRequest,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.submit_order(
-            order.order_id,
-            Side::Sell,
-            order.price,
-            order.qty,
-            order.order_type,
-            order.time_in_force,
-            self.cur_ts,
-        )?;
+    #[test]
+    fn l2_rejects_opening_auction_order() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[Event {
+            ev: EXCH_EVENT | LOCAL_EVENT,
+            exch_ts: 1000,
+            local_ts: 1000,
+            px: 0.0,
+            qty: 0.0,
+            order_id: 0,
+            ival: 0,
+            fval: 0.0,
+        }]);
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                L2AssetBuilder::default()
+                    .data(vec![DataSource::Data(data)])
+                    .latency_model(ConstantLatency::new(10, 20))
+                    .asset_type(LinearAsset::new(1.0))
+                    .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                    .queue_model(ProbQueueModel::new(PowerProbQueueFunc3::new(3.0)))
+                    .exchange(PartialFillExchange)
+                    .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                    .build()?,
+            )
+            .build()?;
 
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified {
-                    asset_no,
-                    order_id: order.order_id,
-                },
-            );
+        let result = hbt.submit_order(
+            0,
+            OrderRequest {
+                order_id: 1,
+                price: 100.0,
+                qty: 1.0,
+                side: Side::Buy,
+                time_in_force: TimeInForce::GTC,
+                order_type: OrdType::Limit,
+                max_lifetime: 0,
+                opening_auction: true,
+            },
+            false,
+        );
+        assert!(matches!(result, Err(BacktestError::InvalidOrderRequest)));
+        assert!(hbt.orders(0).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn no_partial_fill_exchange_rejects_partial_fill_only_options() {
+        let asset = || {
+            l3_asset(
+                Data::from_data(&[l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0)]),
+                NoPartialFillExchange,
+            )
+        };
+        assert!(asset().build().is_ok());
+        for builder in [
+            asset().shadow_latency_model(ConstantLatency::new(50, 20)),
+            asset().self_trade_prevention(SelfTradePrevention::CancelResting),
+            asset().sub_lot_residual_policy(SubLotResidualPolicy::Cancel),
+            asset().fill_response_mode(FillResponseMode::Batched),
+            asset().session_open(200),
+            asset().auction_fallback_policy(AuctionFallbackPolicy::ClearPreOpen),
+            asset().auction_algorithm(AuctionAlgorithm::MaxVolume),
+            asset().batch_interval(100),
+        ] {
+            assert!(matches!(
+                builder.build(),
+                Err(BuildError::InvalidArgument(_))
+            ));
         }
-        Ok(ElapseResult::Ok)
     }
 
-    #[inline]
-    fn modify(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        price: f64,
-        qty: f64,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.modify(order_id, price, qty, self.cur_ts)?;
+    fn ordered_backtest(
+        ordering: OrderingTimestamp,
+        events: &[Event],
+    ) -> Result<Backtest<HashMapMarketDepth>, Box<dyn Error>> {
+        Ok(Backtest::builder()
+            .add_asset(l3_asset(Data::from_data(events), PartialFillExchange).build()?)
+            .ordering_timestamp(ordering)
+            .build()?)
+    }
 
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified { asset_no, order_id },
-            );
-        }
-        Ok(ElapseResult::Ok)
+    #[test]
+    fn ordering_timestamp() -> Result<(), Box<dyn Error>> {
+        // The feed latency of the ask is much larger than the order latency.
+        let mut ask = l3_event(
+            EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+            50,
+            101.0,
+            1.0,
+            1,
+        );
+        ask.local_ts = 500;
+        let events = [
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 0, 0.0, 0.0, 0),
+            ask,
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ];
+
+        // Native: the exchange sees the ask at 50, but the local sees it at 500.
+        let mut hbt = ordered_backtest(OrderingTimestamp::Native, &events)?;
+        hbt.elapse(100)?;
+        assert_eq!(hbt.depth(0).best_ask_tick(), INVALID_MAX);
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::Filled);
+
+        // Exchange: the local also sees the ask at 50.
+        let mut hbt = ordered_backtest(OrderingTimestamp::Exchange, &events)?;
+        hbt.elapse(100)?;
+        assert_eq!(hbt.depth(0).best_ask_tick(), 101);
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::Filled);
+
+        // Local: the exchange also sees the ask at 500, so the order arriving at 110 doesn't fill.
+        let mut hbt = ordered_backtest(OrderingTimestamp::Local, &events)?;
+        hbt.elapse(100)?;
+        assert_eq!(hbt.depth(0).best_ask_tick(), INVALID_MAX);
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::Expired);
+        Ok(())
     }
 
-    #[inline]
-    fn cancel(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        wait: bool,
-    ) -> Result<ElapseResult, Self::Error> {
-        let local = self.local.get_mut(asset_no).unwrap();
-        local.cancel(order_id, self.cur_ts)?;
+    #[test]
+    fn ordering_timestamp_out_of_order() -> Result<(), Box<dyn Error>> {
+        let mut ask = l3_event(
+            EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+            50,
+            101.0,
+            1.0,
+            1,
+        );
+        ask.local_ts = 500;
+        let events = [ask, l3_event(EXCH_EVENT | LOCAL_EVENT, 100, 0.0, 0.0, 0)];
 
-        if wait {
-            return self.goto::<false>(
-                UNTIL_END_OF_DATA,
-                WaitOrderResponse::Specified { asset_no, order_id },
-            );
-        }
-        Ok(ElapseResult::Ok)
+        let mut hbt = ordered_backtest(OrderingTimestamp::Exchange, &events)?;
+        hbt.elapse(1000)?;
+
+        let mut hbt = ordered_backtest(OrderingTimestamp::Local, &events)?;
+        let result = hbt.elapse(1000);
+        assert!(matches!(
+            result,
+            Err(BacktestError::EventOutOfOrder { prev: 500, ts: 100 })
+        ));
+        Ok(())
     }
 
-    #[inline]
-    fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
-        match asset_no {
-            Some(asset_no) => {
-                self.local
-                    .get_mut(asset_no)
-                    .unwrap()
-                    .clear_inactive_orders();
+    #[test]
+    fn latency_floor_defers_zero_latency_fill() -> Result<(), Box<dyn Error>> {
+        let run = |latency_floor: bool| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    L3AssetBuilder::default()
+                        .data(vec![DataSource::Data(data)])
+                        .latency_model(ConstantLatency::new(0, 0))
+                        .asset_type(LinearAsset::new(1.0))
+                        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                        .queue_model(L3FIFOQueueModel::new())
+                        .exchange(PartialFillExchange)
+                        .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                        .latency_floor(latency_floor)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+            let order = hbt.orders(0).get(&10).unwrap();
+            assert_eq!(order.status, Status::Filled);
+            Ok((order.exch_timestamp, hbt.current_timestamp()))
+        };
+
+        // Without the floor, the order is filled and the response is received in the same step.
+        assert_eq!(run(false)?, (1, 1));
+        // With the floor, the exchange fills the order in the next step and the response is
+        // received in the step after.
+        assert_eq!(run(true)?, (2, 3));
+        Ok(())
+    }
+
+    #[test]
+    fn strict_receive_order_with_jittered_latency() -> Result<(), Box<dyn Error>> {
+        // The order 10 takes longer to reach the exchange than the order 11 sent after it.
+        #[derive(Clone)]
+        struct JitteredLatency;
+
+        impl LatencyModel for JitteredLatency {
+            fn entry(&mut self, _timestamp: i64, order: &Order) -> i64 {
+                if order.order_id == 10 { 100 } else { 10 }
             }
-            None => {
-                for local in self.local.iter_mut() {
-                    local.clear_inactive_orders();
-                }
+
+            fn response(&mut self, _timestamp: i64, _order: &Order) -> i64 {
+                20
             }
         }
-    }
 
-    #[inline]
-    fn wait_order_response(
-        &mut self,
-        asset_no: usize,
-        order_id: OrderId,
-        timeout: i64,
-    ) -> Result<ElapseResult, BacktestError> {
-        self.goto::<false>(
-            self.cur_ts + timeout,
-            WaitOrderResponse::Specified { asset_no, order_id },
-        )
+        let run = |strict_receive_order: bool| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    50,
+                    100.0,
+                    1.0,
+                    3,
+                ),
+                l3_event(EXCH_FILL_EVENT | BUY_EVENT, 150, 100.0, 1.0, 3),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 300, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    L3AssetBuilder::default()
+                        .data(vec![DataSource::Data(data)])
+                        .latency_model(JitteredLatency)
+                        .asset_type(LinearAsset::new(1.0))
+                        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                        .queue_model(L3FIFOQueueModel::new())
+                        .exchange(PartialFillExchange)
+                        .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                        .strict_receive_order(strict_receive_order)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 11, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+            hbt.elapse(298)?;
+            let status = |order_id: OrderId| hbt.orders(0).get(&order_id).unwrap().status;
+            Ok((status(10), status(11)))
+        };
+
+        // The order 11 reaches the exchange at 12, ahead of the market order 3 added at 50, and is
+        // filled by the fill of the order 3, whereas the order 10 reaching at 101 is behind it.
+        assert_eq!(run(true)?, (Status::New, Status::Filled));
+        // Processed in the order sent, the order 11 waits for the order 10 and is behind the
+        // order 3 as well.
+        assert_eq!(run(false)?, (Status::New, Status::New));
+        Ok(())
     }
 
-    fn wait_next_feed(
-        &mut self,
-        include_order_resp: bool,
-        timeout: i64,
-    ) -> Result<ElapseResult, Self::Error> {
-        if self.cur_ts == i64::MAX {
-            self.initialize_evs()?;
-            match self.evs.next() {
-                Some(ev) => {
-                    self.cur_ts = ev.timestamp;
-                }
-                None => {
-                    return Ok(ElapseResult::EndOfData);
-                }
-            }
-        }
-        if include_order_resp {
-            self.goto::<true>(self.cur_ts + timeout, WaitOrderResponse::Any)
-        } else {
-            self.goto::<true>(self.cur_ts + timeout, WaitOrderResponse::None)
-        }
+    #[test]
+    fn best_bid_ask_with_own_orders() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                102.0,
+                1.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        let bbo = hbt.best_bid_ask(0);
+        assert_eq!((bbo.best_bid, bbo.best_ask), (100.0, 102.0));
+        assert!(!bbo.own_bid && !bbo.own_ask);
+
+        // Alone at the best bid.
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        // Joins the market ask.
+        hbt.submit_sell_order(0, 11, 102.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        let bbo = hbt.best_bid_ask(0);
+        assert_eq!((bbo.best_bid, bbo.best_ask), (101.0, 102.0));
+        assert!(bbo.own_bid);
+        assert!(bbo.own_ask);
+        assert_eq!(hbt.depth(0).best_bid(), 100.0);
+        Ok(())
     }
 
-    #[inline]
-    fn elapse(&mut self, duration: i64) -> Result<ElapseResult, Self::Error> {
-        if self.cur_ts == i64::MAX {
-            self.initialize_evs()?;
-            match self.evs.next() {
-                Some(ev) => {
-                    self.cur_ts = ev.timestamp;
-                }
-                None => {
-                    return Ok(ElapseResult::EndOfData);
-                }
-            }
+    #[test]
+    fn pnl_gross_and_net_of_fees() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            // The feed ask taken by the backtest order leaves the book.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                400,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                400,
+                103.0,
+                1.0,
+                3,
+            ),
+            // Crosses the backtest ask.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                500,
+                102.0,
+                1.0,
+                4,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                L3AssetBuilder::default()
+                    .data(vec![DataSource::Data(data)])
+                    .latency_model(ConstantLatency::new(10, 20))
+                    .asset_type(LinearAsset::new(1.0))
+                    .fee_model(TradingValueFeeModel::new(CommonFees::new(-0.0001, 0.001)))
+                    .queue_model(L3FIFOQueueModel::new())
+                    .exchange(PartialFillExchange)
+                    .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                    .fill_gap_capacity(8)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_sell_order(0, 11, 102.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(1000)?;
+        hbt.close()?;
+
+        // The taker buy pays 0.001 * 101 and the maker sell receives 0.0001 * 102 as a rebate.
+        let total_fee = 0.101 - 0.0102;
+        let pnl = hbt.pnl_summary(0);
+        assert!((pnl.gross_pnl - 1.0).abs() < 1e-9);
+        assert!((pnl.fee - total_fee).abs() < 1e-9);
+        assert!((pnl.gross_pnl - pnl.net_pnl - total_fee).abs() < 1e-9);
+
+        let fills = hbt.fill_gaps(0);
+        assert_eq!(fills.len(), 2);
+        assert!((fills[0].fee - 0.101).abs() < 1e-9);
+        assert!((fills[1].fee + 0.0102).abs() < 1e-9);
+        for fill in fills {
+            assert!((fill.gross_pnl() - fill.net_pnl() - fill.fee).abs() < 1e-9);
         }
-        self.goto::<false>(self.cur_ts + duration, WaitOrderResponse::None)
+        let summary = hbt.fill_gap_summary(0);
+        assert!((summary.fee - total_fee).abs() < 1e-9);
+        assert!((summary.gross_pnl - summary.net_pnl - total_fee).abs() < 1e-9);
+        Ok(())
     }
 
-    #[inline]
-    fn elapse_bt(&mut self, duration: i64) -> Result<ElapseResult, Self::Error> {
-        self.elapse(duration)
+    #[test]
+    fn maker_taker_volume_split() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                3.0,
+                2,
+            ),
+            // Crosses the backtest ask.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                500,
+                102.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                L3AssetBuilder::default()
+                    .data(vec![DataSource::Data(data)])
+                    .latency_model(ConstantLatency::new(10, 20))
+                    .asset_type(LinearAsset::new(1.0))
+                    .fee_model(TradingValueFeeModel::new(CommonFees::new(-0.0001, 0.001)))
+                    .queue_model(L3FIFOQueueModel::new())
+                    .exchange(PartialFillExchange)
+                    .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 101.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_sell_order(0, 11, 102.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(1000)?;
+        hbt.close()?;
+
+        let pnl = hbt.pnl_summary(0);
+        assert_eq!(pnl.taker_volume, 2.0);
+        assert_eq!(pnl.maker_volume, 1.0);
+        // The taker buy pays 0.001 * 202 and the maker sell receives 0.0001 * 102 as a rebate
+        // over the trading value of 304.
+        let blended_fee_rate = (0.202 - 0.0102) / 304.0;
+        assert!((pnl.blended_fee_rate - blended_fee_rate).abs() < 1e-12);
+        Ok(())
     }
 
-    #[inline]
-    fn close(&mut self) -> Result<(), Self::Error> {
+    #[test]
+    fn pnl_decomposed_into_spread_and_inventory() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                2.0,
+                2,
+            ),
+            // The feed ask taken by the backtest order leaves the book.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                400,
+                101.0,
+                2.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                400,
+                105.0,
+                1.0,
+                3,
+            ),
+            // Crosses the backtest ask.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                500,
+                102.0,
+                1.0,
+                4,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 101.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_sell_order(0, 11, 102.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(1000)?;
+        hbt.close()?;
+
+        assert_eq!(hbt.position(0), 1.0);
+        let depth = hbt.depth(0);
+        let mid = (depth.best_bid() + depth.best_ask()) / 2.0;
+        let pnl = hbt.pnl_summary(0);
+        // Buys 2 at 101 and sells 1 of them at 102.
+        assert!((pnl.spread_pnl - 1.0).abs() < 1e-9);
+        // The remaining 1 is held at the entry price of 101.
+        assert!((pnl.inventory_pnl - (mid - 101.0)).abs() < 1e-9);
+        assert!((pnl.spread_pnl + pnl.inventory_pnl - pnl.gross_pnl).abs() < 1e-9);
         Ok(())
     }
 
-    #[inline]
-    fn feed_latency(&self, asset_no: usize) -> Option<(i64, i64)> {
-        self.local.get(asset_no).unwrap().feed_latency()
+    #[test]
+    fn avg_holding_time_marks_open_position_to_run_end() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                2.0,
+                2,
+            ),
+            // The feed ask taken by the backtest order leaves the book.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                400,
+                101.0,
+                2.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                400,
+                105.0,
+                1.0,
+                3,
+            ),
+            // Crosses the backtest ask.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                500,
+                102.0,
+                1.0,
+                4,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 10_000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        assert_eq!(hbt.pnl_summary(0).avg_holding_time, 0.0);
+
+        hbt.elapse(1)?;
+        // Buys 2 at 11 and sells 1 of them at 500 in the exchange.
+        hbt.submit_buy_order(0, 10, 101.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_sell_order(0, 11, 102.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(940)?;
+        assert_eq!(hbt.current_timestamp(), 1001);
+        assert_eq!(hbt.position(0), 1.0);
+
+        // The remaining 1 is held until the end of the run at 1001.
+        let position_time = 2.0 * (500 - 11) as f64 + 1.0 * (1001 - 500) as f64;
+        let pnl = hbt.pnl_summary(0);
+        assert!((pnl.avg_holding_time - position_time / 3.0).abs() < 1e-9);
+        Ok(())
     }
 
-    #[inline]
-    fn order_latency(&self, asset_no: usize) -> Option<(i64, i64, i64)> {
-        self.local.get(asset_no).unwrap().order_latency()
+    #[test]
+    fn mid_history_samples_bbo_changes() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            // No mid price while the ask side is empty.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                100,
+                103.0,
+                1.0,
+                3,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                200,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                300,
+                102.0,
+                1.0,
+                4,
+            ),
+            // Doesn't change the BBO.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                320,
+                99.0,
+                1.0,
+                5,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | CANCEL_ORDER_EVENT,
+                350,
+                102.0,
+                1.0,
+                4,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .mid_history_capacity(3)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        assert_eq!(hbt.mid_history(0, 1000), vec![(0, 100.5)]);
+
+        hbt.elapse(399)?;
+        assert_eq!(hbt.current_timestamp(), 400);
+        // The oldest sample is evicted by the capacity.
+        assert_eq!(
+            hbt.mid_history(0, 1000),
+            vec![(200, 101.5), (300, 102.5), (350, 101.5)]
+        );
+        assert_eq!(hbt.mid_history(0, 100), vec![(300, 102.5), (350, 101.5)]);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use std::error::Error;
+    #[test]
+    fn routes_orders_to_venues_of_same_asset() -> Result<(), Box<dyn Error>> {
+        let venue =
+            |data: Vec<Event>| l3_asset(Data::from_data(&data), PartialFillExchange).build();
+        let book = |ask: f64| {
+            vec![
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    ask,
+                    1.0,
+                    2,
+                ),
+            ]
+        };
 
-    use crate::{
-        backtest::{
-            Backtest, DataSource,
-            ExchangeKind::NoPartialFillExchange,
-            L2AssetBuilder,
-            assettype::LinearAsset,
-            data::Data,
-            models::{
-                CommonFees, ConstantLatency, PowerProbQueueFunc3, ProbQueueModel,
-                TradingValueFeeModel,
-            },
-        },
-        depth::HashMapMarketDepth,
-        prelude::{Bot, Event},
-        types::{EXCH_EVENT, LOCAL_EVENT},
-    };
+        let mut primary = book(101.0);
+        primary.push(l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0));
+        // The secondary venue's ask is away from the backtest bid until a seller crosses it.
+        let mut secondary = book(103.0);
+        secondary.push(l3_event(
+            EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+            500,
+            101.0,
+            2.0,
+            3,
+        ));
+        secondary.push(l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0));
+
+        let mut hbt = Backtest::builder()
+            .add_asset(venue(primary)?)
+            .add_venue(venue(secondary)?)?
+            .build()?;
+        assert_eq!(hbt.num_assets(), 1);
+        assert_eq!(hbt.num_venues(0), 2);
+
+        hbt.elapse(1)?;
+        let buy = |order_id: OrderId, qty: f64| OrderRequest {
+            order_id,
+            price: 101.0,
+            qty,
+            side: Side::Buy,
+            time_in_force: TimeInForce::GTC,
+            order_type: OrdType::Limit,
+            max_lifetime: 0,
+            opening_auction: false,
+        };
+        hbt.submit_venue_order(0, 0, buy(10, 1.0), true)?;
+        hbt.submit_venue_order(0, 1, buy(11, 2.0), true)?;
+        assert!(matches!(
+            hbt.submit_venue_order(0, 1, buy(10, 1.0), false),
+            Err(BacktestError::OrderIdExist)
+        ));
+        assert!(matches!(
+            hbt.submit_venue_order(0, 2, buy(12, 1.0), false),
+            Err(BacktestError::VenueNotFound {
+                asset_no: 0,
+                venue: 2
+            })
+        ));
+
+        // Only the primary venue's order is filled so far.
+        assert_eq!(
+            hbt.venue_orders(0, 0).get(&10).unwrap().status,
+            Status::Filled
+        );
+        assert_eq!(hbt.venue_orders(0, 1).get(&11).unwrap().status, Status::New);
+        assert!(!hbt.venue_orders(0, 0).contains_key(&11));
+        assert_eq!(hbt.venue_position(0, 0), 1.0);
+        assert_eq!(hbt.venue_position(0, 1), 0.0);
+        assert_eq!(hbt.position(0), 1.0);
+
+        hbt.elapse(1000)?;
+        assert_eq!(
+            hbt.venue_orders(0, 1).get(&11).unwrap().status,
+            Status::Filled
+        );
+        assert_eq!(hbt.venue_position(0, 1), 2.0);
+        assert_eq!(hbt.position(0), 3.0);
+        // The views without a venue selector cover the primary venue only.
+        assert_eq!(hbt.state_values(0).position, 1.0);
+        assert_eq!(hbt.venue_state_values(0, 1).position, 2.0);
+        assert_eq!(hbt.depth(0).ask_qty_at_tick(101), 1.0);
+        assert_eq!(hbt.venue_depth(0, 1).ask_qty_at_tick(101), 2.0);
+        assert!(!hbt.orders(0).contains_key(&11));
+
+        assert!(matches!(
+            Backtest::builder().add_venue(venue(book(101.0))?),
+            Err(BuildError::BuilderIncomplete(_))
+        ));
+        Ok(())
+    }
 
     #[test]
-    fn skips_unseen_events() -> Result<(), Box<dyn Error>> {
-        let data = Data::from_data(&[
-            Event {
-                ev: EXCH_EVENT | LOCAL_EVENT,
-                exch_ts: 0,
-                local_ts: 0,
-                px: 0.0,
-                qty: 0.0,
-                order_id: 0,
-                ival: 0,
-                fval: 0.0,
-            },
-            Event {
-                ev: LOCAL_EVENT | EXCH_EVENT,
-                exch_ts: 1,
-                local_ts: 1,
-                px: 0.0,
-                qty: 0.0,
-                order_id: 0,
-                ival: 0,
-                fval: 0.0,
-            },
-            Event {
-                ev: EXCH_EVENT,
-                exch_ts: 3,
-                local_ts: 4,
-                px: 0.0,
-                qty: 0.0,
-                order_id: 0,
-                ival: 0,
-                fval: 0.0,
-            },
-            Event {
-                ev: LOCAL_EVENT,
-                exch_ts: 3,
-                local_ts: 4,
-                px: 0.0,
-                qty: 0.0,
-                order_id: 0,
-                ival: 0,
-                fval: 0.0,
-            },
+    fn reset_replays_retained_data() -> Result<(), Box<dyn Error>> {
+        let book = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
         ]);
+        let trades = Data::from_data(&[
+            // Crosses the backtest ask.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                500,
+                102.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+        let asset = || {
+            L3AssetBuilder::default()
+                .data(vec![
+                    DataSource::Data(book.clone()),
+                    DataSource::Data(trades.clone()),
+                ])
+                .latency_model(ConstantLatency::new(10, 20))
+                .asset_type(LinearAsset::new(1.0))
+                .fee_model(TradingValueFeeModel::new(CommonFees::new(-0.0001, 0.001)))
+                .queue_model(L3FIFOQueueModel::new())
+                .exchange(PartialFillExchange)
+                .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+        };
 
-        let mut backtester = Backtest::builder()
+        let run = |hbt: &mut Backtest<HashMapMarketDepth>| -> Result<_, Box<dyn Error>> {
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 101.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+            hbt.submit_sell_order(0, 11, 102.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+            hbt.elapse(1000)?;
+            let orders = [10, 11].map(|order_id| {
+                let order = hbt.orders(0).get(&order_id).unwrap();
+                (order.status, order.leaves_qty)
+            });
+            Ok((hbt.state_values(0).clone(), orders))
+        };
+
+        let mut hbt = Backtest::builder()
+            .add_asset(asset().build_resettable()?)
+            .build()?;
+        let first = run(&mut hbt)?;
+        hbt.reset()?;
+        assert!(hbt.orders(0).is_empty());
+        assert_eq!(hbt.position(0), 0.0);
+        let second = run(&mut hbt)?;
+        assert_eq!(
+            first.1,
+            [(Status::PartiallyFilled, 1.0), (Status::Filled, 0.0)]
+        );
+        assert_eq!(first, second);
+
+        // An asset built without a processor factory cannot be reset.
+        let mut hbt = Backtest::builder().add_asset(asset().build()?).build()?;
+        assert!(matches!(hbt.reset(), Err(BacktestError::NotResettable)));
+        Ok(())
+    }
+
+    #[test]
+    fn data_date_range_merges_files_chronologically() -> Result<(), Box<dyn Error>> {
+        // Removes the directory even if the test fails.
+        struct TempDir(std::path::PathBuf);
+        impl Drop for TempDir {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_dir_all(&self.0);
+            }
+        }
+
+        let dir = TempDir(std::env::temp_dir().join(format!(
+            "hftbacktest_date_range_{}_{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_nanos()
+        )));
+        std::fs::create_dir_all(&dir.0)?;
+        let template = dir.0.join("{date}.npy").to_string_lossy().to_string();
+        let write = |date: &str, events: &[Event]| -> Result<(), Box<dyn Error>> {
+            let path = template.replace("{date}", date);
+            let mut file = std::fs::File::create(path)?;
+            write_npy(&mut file, events)?;
+            Ok(())
+        };
+        let event = |ev: u64, ts: i64, px: f64, qty: f64| Event {
+            ev: EXCH_EVENT | LOCAL_EVENT | ev,
+            exch_ts: ts,
+            local_ts: ts,
+            px,
+            qty,
+            order_id: 0,
+            ival: 0,
+            fval: 0.0,
+        };
+        write(
+            "20240131",
+            &[
+                event(BUY_EVENT | DEPTH_EVENT, 0, 100.0, 5.0),
+                event(SELL_EVENT | DEPTH_EVENT, 0, 101.0, 5.0),
+            ],
+        )?;
+        write(
+            "20240201",
+            &[
+                event(BUY_EVENT | DEPTH_EVENT, 1000, 102.0, 5.0),
+                event(SELL_EVENT | DEPTH_EVENT, 1000, 103.0, 5.0),
+            ],
+        )?;
+
+        assert_eq!(
+            DataSource::<Event>::date_range(&template, 20240131, 20240201)?.len(),
+            2
+        );
+        assert!(DataSource::<Event>::date_range(&template, 20240131, 20240202).is_err());
+        assert!(DataSource::<Event>::date_range(&template, 20240201, 20240131).is_err());
+
+        let mut hbt = Backtest::builder()
             .add_asset(
                 L2AssetBuilder::default()
-                    .data(vec![DataSource::Data(data)])
-                    .latency_model(ConstantLatency::new(50, 50))
+                    .data_date_range(&template, 20240131, 20240201)?
+                    .latency_model(ConstantLatency::new(10, 20))
                     .asset_type(LinearAsset::new(1.0))
                     .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
                     .queue_model(ProbQueueModel::new(PowerProbQueueFunc3::new(3.0)))
                     .exchange(NoPartialFillExchange)
-                    .depth(|| HashMapMarketDepth::new(0.01, 1.0))
+                    .depth(|| HashMapMarketDepth::new(1.0, 1.0))
                     .build()?,
             )
             .build()?;
 
-        // Process first events and advance a single timestep
-        backtester.elapse_bt(1)?;
-        assert_eq!(1, backtester.cur_ts);
-
-        // Check that we correctly skip past events that aren't seen by a given processor
-        backtester.elapse_bt(1)?;
-        assert_eq!(2, backtester.cur_ts);
-        assert_eq!(Some(3), backtester.local[0].row);
-        assert_eq!(Some(2), backtester.exch[0].row);
-
-        backtester.elapse_bt(1)?;
-        assert_eq!(3, backtester.cur_ts);
+        hbt.elapse(10)?;
+        assert_eq!(hbt.depth(0).best_bid(), 100.0);
+        assert_eq!(hbt.depth(0).best_ask(), 101.0);
 
+        // The second day's data follows the first day's.
+        hbt.elapse(1000)?;
+        assert_eq!(hbt.depth(0).best_bid(), 102.0);
+        assert_eq!(hbt.depth(0).best_ask(), 103.0);
         Ok(())
     }
 }
@@ -1,4 +1,9 @@
-use crate::{prelude::Side, types::Order};
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    prelude::Side,
+    types::{Order, OrderId},
+};
 
 /// Common transaction fees
 /// Fee calculation is determined by the fee model.
@@ -47,6 +52,22 @@ impl DirectionalFees {
 pub trait FeeModel {
     /// Calculates the fee amount.
     fn amount(&self, order: &Order, amount: f64) -> f64;
+
+    /// Returns the configured fee rate for adding liquidity. The default implementation returns
+    /// `0.0`, for models that aren't charged at a single rate.
+    fn maker_fee(&self) -> f64 {
+        0.0
+    }
+
+    /// Returns the configured fee rate for removing liquidity. The default implementation returns
+    /// `0.0`, for models that aren't charged at a single rate.
+    fn taker_fee(&self) -> f64 {
+        0.0
+    }
+
+    /// Updates the model with the fill of the order, whose transaction value is `amount`. This is
+    /// invoked after the fee of the fill is calculated. The default implementation does nothing.
+    fn on_fill(&mut self, _order: &Order, _amount: f64) {}
 }
 
 /// Fee based on the transaction value,
@@ -71,6 +92,14 @@ impl FeeModel for TradingValueFeeModel<CommonFees> {
             self.fees.taker_fee * amount
         }
     }
+
+    fn maker_fee(&self) -> f64 {
+        self.fees.maker_fee
+    }
+
+    fn taker_fee(&self) -> f64 {
+        self.fees.taker_fee
+    }
 }
 
 impl FeeModel for TradingValueFeeModel<DirectionalFees> {
@@ -85,6 +114,14 @@ impl FeeModel for TradingValueFeeModel<DirectionalFees> {
             _ => unreachable!(),
         }
     }
+
+    fn maker_fee(&self) -> f64 {
+        self.fees.common_fees.maker_fee
+    }
+
+    fn taker_fee(&self) -> f64 {
+        self.fees.common_fees.taker_fee
+    }
 }
 
 /// Fee based on the transaction quantity,
@@ -108,6 +145,14 @@ impl FeeModel for TradingQtyFeeModel<CommonFees> {
             self.fees.taker_fee * order.exec_qty
         }
     }
+
+    fn maker_fee(&self) -> f64 {
+        self.fees.maker_fee
+    }
+
+    fn taker_fee(&self) -> f64 {
+        self.fees.taker_fee
+    }
 }
 
 impl FeeModel for TradingQtyFeeModel<DirectionalFees> {
@@ -128,6 +173,14 @@ impl FeeModel for TradingQtyFeeModel<DirectionalFees> {
             _ => unreachable!(),
         }
     }
+
+    fn maker_fee(&self) -> f64 {
+        self.fees.common_fees.maker_fee
+    }
+
+    fn taker_fee(&self) -> f64 {
+        self.fees.common_fees.taker_fee
+    }
 }
 
 /// Flat fee per trade
@@ -150,4 +203,328 @@ impl FeeModel for FlatPerTradeFeeModel<CommonFees> {
             self.fees.taker_fee
         }
     }
+
+    fn maker_fee(&self) -> f64 {
+        self.fees.maker_fee
+    }
+
+    fn taker_fee(&self) -> f64 {
+        self.fees.taker_fee
+    }
+}
+
+/// Fee based on the transaction value, with the maker and taker rates given by the tier that the
+/// traded notional over a rolling window reaches. The traded notional is tracked by the model
+/// itself from the fills, and the fee of a fill is determined by the notional traded before it.
+/// A negative maker rate is a rebate.
+///
+/// **Example**
+/// ```
+/// use hftbacktest::backtest::models::{CommonFees, TieredFeeModel};
+///
+/// // Over the rolling 30 days, a maker rebate applies from 1,000,000 in notional.
+/// let fee_model = TieredFeeModel::new(vec![
+///     (0.0, CommonFees::new(0.0002, 0.0005)),
+///     (1_000_000.0, CommonFees::new(-0.0001, 0.0004)),
+/// ]);
+/// ```
+#[derive(Clone)]
+pub struct TieredFeeModel {
+    tiers: Vec<(f64, CommonFees)>,
+    window: i64,
+    fills: VecDeque<(i64, f64)>,
+    notional: f64,
+}
+
+impl TieredFeeModel {
+    /// Constructs `TieredFeeModel` with the given tiers of `(min_notional, fees)`, which are
+    /// sorted by the minimum traded notional. While the traded notional is below the lowest
+    /// minimum, the lowest tier applies. The default window is 30 days in nanoseconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tiers` is empty.
+    pub fn new(mut tiers: Vec<(f64, CommonFees)>) -> Self {
+        assert!(!tiers.is_empty(), "at least one tier is required");
+        tiers.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Self {
+            tiers,
+            window: 30 * 24 * 60 * 60 * 1_000_000_000,
+            fills: VecDeque::new(),
+            notional: 0.0,
+        }
+    }
+
+    /// Sets the length of the rolling window over which the traded notional is summed. It should
+    /// match the time unit of the data's timestamps.
+    pub fn window(self, window: i64) -> Self {
+        Self { window, ..self }
+    }
+
+    /// Returns the traded notional within the window ending at `timestamp`.
+    pub fn traded_notional(&self, timestamp: i64) -> f64 {
+        let expired: f64 = self
+            .fills
+            .iter()
+            .take_while(|(fill_ts, _)| *fill_ts <= timestamp - self.window)
+            .map(|(_, amount)| *amount)
+            .sum();
+        self.notional - expired
+    }
+
+    fn fees(&self, notional: f64) -> &CommonFees {
+        let i = self
+            .tiers
+            .partition_point(|(min_notional, _)| *min_notional <= notional);
+        &self.tiers[i.saturating_sub(1)].1
+    }
+}
+
+impl FeeModel for TieredFeeModel {
+    fn amount(&self, order: &Order, amount: f64) -> f64 {
+        let fees = self.fees(self.traded_notional(order.exch_timestamp));
+        if order.maker {
+            fees.maker_fee * amount
+        } else {
+            fees.taker_fee * amount
+        }
+    }
+
+    fn maker_fee(&self) -> f64 {
+        self.fees(self.notional).maker_fee
+    }
+
+    fn taker_fee(&self) -> f64 {
+        self.fees(self.notional).taker_fee
+    }
+
+    fn on_fill(&mut self, order: &Order, amount: f64) {
+        let timestamp = order.exch_timestamp;
+        while let Some((fill_ts, fill_amount)) = self.fills.front() {
+            if *fill_ts > timestamp - self.window {
+                break;
+            }
+            self.notional -= *fill_amount;
+            self.fills.pop_front();
+        }
+        self.fills.push_back((timestamp, amount));
+        self.notional += amount;
+    }
+}
+
+/// Determines how often the fixed fee of [`FixedPlusBpsFeeModel`] is charged.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum FixedFeeMode {
+    /// Every fill is charged the fixed fee, so an order filled in several slices pays it several
+    /// times.
+    #[default]
+    PerFill,
+    /// Only the first fill of an order is charged the fixed fee.
+    PerOrder,
+}
+
+/// Fixed fee per execution plus a fee in basis points of the transaction value, with the basis
+/// points depending on whether the order is a maker or taker. A negative maker rate is a rebate.
+///
+/// **Example**
+/// ```
+/// use hftbacktest::backtest::models::{FixedFeeMode, FixedPlusBpsFeeModel};
+///
+/// // 0.05 per order plus 0.2bp for maker and 0.5bp for taker.
+/// let fee_model =
+///     FixedPlusBpsFeeModel::new(0.05, 0.2, 0.5).fixed_fee_mode(FixedFeeMode::PerOrder);
+/// ```
+#[derive(Clone)]
+pub struct FixedPlusBpsFeeModel {
+    fixed_fee: f64,
+    maker_bps: f64,
+    taker_bps: f64,
+    fixed_fee_mode: FixedFeeMode,
+    charged: HashSet<OrderId>,
+}
+
+impl FixedPlusBpsFeeModel {
+    /// Constructs `FixedPlusBpsFeeModel` with the fixed fee and the maker and taker fees in basis
+    /// points. The fixed fee is charged on every fill by default.
+    pub fn new(fixed_fee: f64, maker_bps: f64, taker_bps: f64) -> Self {
+        Self {
+            fixed_fee,
+            maker_bps,
+            taker_bps,
+            fixed_fee_mode: FixedFeeMode::PerFill,
+            charged: HashSet::new(),
+        }
+    }
+
+    /// Sets how often the fixed fee is charged. The default value is [`FixedFeeMode::PerFill`].
+    pub fn fixed_fee_mode(self, fixed_fee_mode: FixedFeeMode) -> Self {
+        Self {
+            fixed_fee_mode,
+            ..self
+        }
+    }
+}
+
+impl FeeModel for FixedPlusBpsFeeModel {
+    fn amount(&self, order: &Order, amount: f64) -> f64 {
+        let bps = if order.maker {
+            self.maker_bps
+        } else {
+            self.taker_bps
+        };
+        let fixed_fee = match self.fixed_fee_mode {
+            FixedFeeMode::PerFill => self.fixed_fee,
+            FixedFeeMode::PerOrder if self.charged.contains(&order.order_id) => 0.0,
+            FixedFeeMode::PerOrder => self.fixed_fee,
+        };
+        fixed_fee + bps * 0.0001 * amount
+    }
+
+    fn maker_fee(&self) -> f64 {
+        self.maker_bps * 0.0001
+    }
+
+    fn taker_fee(&self) -> f64 {
+        self.taker_bps * 0.0001
+    }
+
+    fn on_fill(&mut self, order: &Order, _amount: f64) {
+        if self.fixed_fee_mode == FixedFeeMode::PerOrder {
+            // A fully filled order has no further fills to exempt.
+            if order.leaves_qty > 0.0 {
+                self.charged.insert(order.order_id);
+            } else {
+                self.charged.remove(&order.order_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest, DataSource,
+            ExchangeKind::PartialFillExchange,
+            L3AssetBuilder,
+            assettype::LinearAsset,
+            data::Data,
+            models::{
+                CommonFees, ConstantLatency, FixedFeeMode, FixedPlusBpsFeeModel, L3FIFOQueueModel,
+                TieredFeeModel,
+            },
+            state::State,
+            testutil::l3_event,
+        },
+        depth::HashMapMarketDepth,
+        prelude::{Bot, OrdType, Order, OrderId, Side, Status, TimeInForce},
+        types::{ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, LOCAL_EVENT, SELL_EVENT},
+    };
+
+    #[test]
+    fn tiered_fee_rate_drops_past_volume_threshold() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                10.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 5000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                L3AssetBuilder::default()
+                    .data(vec![DataSource::Data(data)])
+                    .latency_model(ConstantLatency::new(10, 20))
+                    .asset_type(LinearAsset::new(1.0))
+                    .fee_model(
+                        TieredFeeModel::new(vec![
+                            (250.0, CommonFees::new(-0.0001, 0.0003)),
+                            (0.0, CommonFees::new(0.0002, 0.0005)),
+                        ])
+                        .window(1000),
+                    )
+                    .queue_model(L3FIFOQueueModel::new())
+                    .exchange(PartialFillExchange)
+                    .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                    .build()?,
+            )
+            .build()?;
+
+        let take = |hbt: &mut Backtest<HashMapMarketDepth>, order_id: OrderId, qty: f64| {
+            let prev_fee = hbt.state_values(0).fee;
+            hbt.submit_buy_order(
+                0,
+                order_id,
+                101.0,
+                qty,
+                TimeInForce::IOC,
+                OrdType::Limit,
+                true,
+            )
+            .unwrap();
+            assert_eq!(hbt.orders(0).get(&order_id).unwrap().status, Status::Filled);
+            hbt.state_values(0).fee - prev_fee
+        };
+
+        hbt.elapse(1)?;
+        // The traded notional of 202 is below the threshold.
+        assert!((take(&mut hbt, 10, 2.0) - 0.0005 * 202.0).abs() < 1e-9);
+        assert!((take(&mut hbt, 11, 1.0) - 0.0005 * 101.0).abs() < 1e-9);
+        // The traded notional of 303 reaches the threshold.
+        assert!((take(&mut hbt, 12, 1.0) - 0.0003 * 101.0).abs() < 1e-9);
+        assert_eq!(hbt.asset_params(0).maker_fee, -0.0001);
+        assert_eq!(hbt.asset_params(0).taker_fee, 0.0003);
+
+        // The earlier fills fall out of the window.
+        hbt.elapse(1000)?;
+        assert!((take(&mut hbt, 13, 1.0) - 0.0005 * 101.0).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_plus_bps_fee_per_fill_and_per_order() {
+        let fee = |fixed_fee_mode: FixedFeeMode| {
+            let mut state = State::new(
+                LinearAsset::new(1.0),
+                FixedPlusBpsFeeModel::new(0.05, 2.0, 5.0).fixed_fee_mode(fixed_fee_mode),
+            );
+            let mut order = Order::new(
+                10,
+                100,
+                1.0,
+                3.0,
+                Side::Buy,
+                OrdType::Limit,
+                TimeInForce::GTC,
+            );
+            order.maker = true;
+            order.exec_price_tick = 100;
+            // The order is filled in three slices.
+            for ts in [100, 200, 300] {
+                order.exec_qty = 1.0;
+                order.leaves_qty -= 1.0;
+                order.exch_timestamp = ts;
+                state.apply_fill(&order);
+            }
+            assert_eq!(state.values().num_trades, 3);
+            state.values().fee
+        };
+
+        // The maker fee of 2bp on the notional of 300 is 0.06.
+        assert!((fee(FixedFeeMode::PerFill) - (3.0 * 0.05 + 0.06)).abs() < 1e-9);
+        assert!((fee(FixedFeeMode::PerOrder) - (0.05 + 0.06)).abs() < 1e-9);
+    }
 }
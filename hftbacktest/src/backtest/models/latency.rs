@@ -1,4 +1,4 @@
-use std::{io::Error as IoError, mem};
+use std::{cell::RefCell, collections::HashMap, io::Error as IoError, mem, rc::Rc};
 
 use hftbacktest_derive::NpyDTyped;
 
@@ -7,7 +7,7 @@ use crate::{
         BacktestError,
         data::{Data, DataPreprocess, DataSource, POD, Reader},
     },
-    types::Order,
+    types::{Order, OrderId},
 };
 
 /// Provides the order entry latency and the order response latency.
@@ -54,6 +54,326 @@ impl LatencyModel for ConstantLatency {
     }
 }
 
+/// Enforces a minimum order entry and response latency on top of the wrapped latency model, so
+/// that the exchange processes an order, and the local receives its response, at least `floor`
+/// after the request or the processing even if the wrapped model yields zero latency.
+///
+/// A negative entry latency, which indicates an order rejection, is passed through unchanged.
+#[derive(Clone)]
+pub struct LatencyFloor<LM> {
+    latency_model: LM,
+    floor: i64,
+}
+
+impl<LM> LatencyFloor<LM> {
+    /// Constructs an instance of `LatencyFloor`. `floor` should be non-negative; `0` disables the
+    /// floor.
+    pub fn new(latency_model: LM, floor: i64) -> Self {
+        Self {
+            latency_model,
+            floor,
+        }
+    }
+}
+
+impl<LM> LatencyModel for LatencyFloor<LM>
+where
+    LM: LatencyModel,
+{
+    fn entry(&mut self, timestamp: i64, order: &Order) -> i64 {
+        let latency = self.latency_model.entry(timestamp, order);
+        if latency < 0 {
+            latency
+        } else {
+            latency.max(self.floor)
+        }
+    }
+
+    fn response(&mut self, timestamp: i64, order: &Order) -> i64 {
+        self.latency_model
+            .response(timestamp, order)
+            .max(self.floor)
+    }
+}
+
+/// Determines how [`PiecewiseLatency`] takes the latency between two breakpoints.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum PiecewiseInterpolation {
+    /// The latency of the last breakpoint at or before the timestamp applies until the next
+    /// breakpoint.
+    #[default]
+    Step,
+    /// The latency is linearly interpolated between the breakpoints around the timestamp.
+    Linear,
+}
+
+/// Provides order latency that varies over time, such as the spikes around the open and scheduled
+/// events, given by the breakpoints of `(timestamp, entry_latency, response_latency)`. The entry
+/// latency is taken at the request timestamp, and the response latency at the timestamp at which
+/// the exchange processes the order. Before the first breakpoint and after the last one, the
+/// latency of the first and the last breakpoint applies, respectively.
+///
+/// As with [`ConstantLatency`], a negative latency indicates an order rejection by the exchange.
+///
+/// **Example**
+/// ```
+/// use hftbacktest::backtest::models::{PiecewiseInterpolation, PiecewiseLatency};
+///
+/// // The latency ramps up to 5ms at 09:30 UTC and settles back to 1ms by 09:35 UTC.
+/// let latency_model = PiecewiseLatency::new(vec![
+///     (1_700_040_600_000_000_000 - 60_000_000_000, 1_000_000, 1_000_000),
+///     (1_700_040_600_000_000_000, 5_000_000, 5_000_000),
+///     (1_700_040_600_000_000_000 + 300_000_000_000, 1_000_000, 1_000_000),
+/// ])
+/// .interpolation(PiecewiseInterpolation::Linear);
+/// ```
+#[derive(Clone)]
+pub struct PiecewiseLatency {
+    breakpoints: Vec<(i64, i64, i64)>,
+    interpolation: PiecewiseInterpolation,
+}
+
+impl PiecewiseLatency {
+    /// Constructs an instance of `PiecewiseLatency` with the given breakpoints of
+    /// `(timestamp, entry_latency, response_latency)`, which are sorted by timestamp.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `breakpoints` is empty.
+    pub fn new(mut breakpoints: Vec<(i64, i64, i64)>) -> Self {
+        assert!(
+            !breakpoints.is_empty(),
+            "at least one breakpoint is required"
+        );
+        breakpoints.sort_by_key(|(timestamp, _, _)| *timestamp);
+        Self {
+            breakpoints,
+            interpolation: Default::default(),
+        }
+    }
+
+    /// Sets how the latency between two breakpoints is taken. The default value is
+    /// [`PiecewiseInterpolation::Step`].
+    pub fn interpolation(self, interpolation: PiecewiseInterpolation) -> Self {
+        Self {
+            interpolation,
+            ..self
+        }
+    }
+
+    fn latency_at<F>(&self, timestamp: i64, latency: F) -> i64
+    where
+        F: Fn(&(i64, i64, i64)) -> i64,
+    {
+        // The number of the breakpoints at or before the timestamp.
+        let i = self
+            .breakpoints
+            .partition_point(|(breakpoint_ts, _, _)| *breakpoint_ts <= timestamp);
+        if i == 0 {
+            return latency(&self.breakpoints[0]);
+        }
+        let prev = &self.breakpoints[i - 1];
+        match (self.interpolation, self.breakpoints.get(i)) {
+            (PiecewiseInterpolation::Linear, Some(next)) => {
+                let (y1, y2) = (latency(prev), latency(next));
+                let ratio = (timestamp - prev.0) as f64 / (next.0 - prev.0) as f64;
+                y1 + ((y2 - y1) as f64 * ratio).round() as i64
+            }
+            _ => latency(prev),
+        }
+    }
+}
+
+impl LatencyModel for PiecewiseLatency {
+    fn entry(&mut self, timestamp: i64, _order: &Order) -> i64 {
+        self.latency_at(timestamp, |(_, entry_latency, _)| *entry_latency)
+    }
+
+    fn response(&mut self, timestamp: i64, _order: &Order) -> i64 {
+        self.latency_at(timestamp, |(_, _, response_latency)| *response_latency)
+    }
+}
+
+/// A small seedable pseudo-random number generator, SplitMix64, from which the samplers of
+/// [`DistributionLatency`] draw. It is not suitable for cryptographic use.
+#[derive(Clone, Debug)]
+pub struct LatencyRng {
+    state: u64,
+}
+
+impl LatencyRng {
+    /// Constructs an instance of `LatencyRng` with the given seed.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the next 64-bit random value.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a sample from the uniform distribution over `[0, 1)`.
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a sample from the standard normal distribution, using the Box-Muller transform.
+    pub fn standard_normal(&mut self) -> f64 {
+        // Takes it from (0, 1] to avoid the logarithm of zero.
+        let u1 = 1.0 - self.uniform();
+        let u2 = self.uniform();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+/// Provides order latency drawn from user-supplied distributions, to study how robust a strategy
+/// is to the latency variation. Each sampler is a closure that draws a latency from the given
+/// [`LatencyRng`], which is seeded for reproducibility; the same seed and samplers yield the same
+/// latency sequence.
+///
+/// A negative sample is clamped to zero, so unlike [`ConstantLatency`], this model never rejects
+/// an order. A sample is rounded to the nearest integer.
+///
+/// **Example**
+/// ```
+/// use hftbacktest::backtest::models::DistributionLatency;
+///
+/// // Entry latency of 1ms plus normally distributed jitter, and exponentially distributed
+/// // response latency with a mean of 0.5ms.
+/// let latency = DistributionLatency::new(
+///     42,
+///     |rng| 1_000_000.0 + 100_000.0 * rng.standard_normal(),
+///     |rng| -500_000.0 * (1.0 - rng.uniform()).ln(),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct DistributionLatency<E, R> {
+    rng: LatencyRng,
+    entry_sampler: E,
+    response_sampler: R,
+    last_entry_latency: Option<i64>,
+    last_response_latency: Option<i64>,
+}
+
+impl<E, R> DistributionLatency<E, R>
+where
+    E: FnMut(&mut LatencyRng) -> f64,
+    R: FnMut(&mut LatencyRng) -> f64,
+{
+    /// Constructs an instance of `DistributionLatency` sampling the order entry latency from
+    /// `entry_sampler` and the order response latency from `response_sampler`, with the random
+    /// number generator seeded by `seed`.
+    ///
+    /// The samples should match the time unit of the data's timestamps.
+    pub fn new(seed: u64, entry_sampler: E, response_sampler: R) -> Self {
+        Self {
+            rng: LatencyRng::new(seed),
+            entry_sampler,
+            response_sampler,
+            last_entry_latency: None,
+            last_response_latency: None,
+        }
+    }
+
+    /// Returns the last sampled order entry latency and order response latency. Each is `None`
+    /// until it is first sampled.
+    pub fn last_latency(&self) -> (Option<i64>, Option<i64>) {
+        (self.last_entry_latency, self.last_response_latency)
+    }
+}
+
+impl<E, R> LatencyModel for DistributionLatency<E, R>
+where
+    E: FnMut(&mut LatencyRng) -> f64,
+    R: FnMut(&mut LatencyRng) -> f64,
+{
+    fn entry(&mut self, _timestamp: i64, _order: &Order) -> i64 {
+        let latency = (self.entry_sampler)(&mut self.rng).round().max(0.0) as i64;
+        self.last_entry_latency = Some(latency);
+        latency
+    }
+
+    fn response(&mut self, _timestamp: i64, _order: &Order) -> i64 {
+        let latency = (self.response_sampler)(&mut self.rng).round().max(0.0) as i64;
+        self.last_response_latency = Some(latency);
+        latency
+    }
+}
+
+/// Provides order latency that grows with the backlog of order requests, as exchanges slow down
+/// when a strategy floods them with orders. The response latency of a request is the base
+/// response latency plus `penalty` for each request sent after the last response and before this
+/// request, which is the backlog the request joins. The responses not tied to a request, such as
+/// fills of a resting order, have the base response latency. The order entry latency is constant.
+///
+/// The backlog is shared among the clones of this model, so that the request sent through
+/// [`LocalToExch`](crate::backtest::order::LocalToExch) is tracked until its response is sent
+/// through [`ExchToLocal`](crate::backtest::order::ExchToLocal).
+///
+/// **Example**
+/// ```
+/// use hftbacktest::backtest::models::BacklogLatency;
+///
+/// // Each request in the backlog adds 50us to the 1ms response latency.
+/// let latency_model = BacklogLatency::new(1_000_000, 1_000_000, 50_000);
+/// ```
+#[derive(Clone)]
+pub struct BacklogLatency {
+    entry_latency: i64,
+    response_latency: i64,
+    penalty: i64,
+    backlog: Rc<RefCell<Backlog>>,
+}
+
+#[derive(Default)]
+struct Backlog {
+    // The number of requests sent since the last response.
+    since_response: i64,
+    // The backlog observed by each outstanding request.
+    outstanding: HashMap<OrderId, i64>,
+}
+
+impl BacklogLatency {
+    /// Constructs an instance of `BacklogLatency`.
+    ///
+    /// `entry_latency`, `response_latency`, and `penalty` should match the time unit of the data's
+    /// timestamps.
+    pub fn new(entry_latency: i64, response_latency: i64, penalty: i64) -> Self {
+        Self {
+            entry_latency,
+            response_latency,
+            penalty,
+            backlog: Default::default(),
+        }
+    }
+
+    /// Returns the number of requests sent since the last response.
+    pub fn backlog(&self) -> i64 {
+        self.backlog.borrow().since_response
+    }
+}
+
+impl LatencyModel for BacklogLatency {
+    fn entry(&mut self, _timestamp: i64, order: &Order) -> i64 {
+        let mut backlog = self.backlog.borrow_mut();
+        let ahead = backlog.since_response;
+        backlog.outstanding.insert(order.order_id, ahead);
+        backlog.since_response += 1;
+        self.entry_latency
+    }
+
+    fn response(&mut self, _timestamp: i64, order: &Order) -> i64 {
+        let mut backlog = self.backlog.borrow_mut();
+        backlog.since_response = 0;
+        let ahead = backlog.outstanding.remove(&order.order_id).unwrap_or(0);
+        self.response_latency + self.penalty * ahead
+    }
+}
+
 /// The historical order latency data
 #[repr(C, align(32))]
 #[derive(Clone, Debug, NpyDTyped)]
@@ -293,3 +613,168 @@ impl DataPreprocess<OrderLatencyRow> for OrderLatencyAdjustment {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backtest::{
+            models::{
+                BacklogLatency, DistributionLatency, LatencyModel, PiecewiseInterpolation,
+                PiecewiseLatency,
+            },
+            order::order_bus,
+        },
+        types::{OrdType, Order, Side, TimeInForce},
+    };
+
+    fn sample<LM: LatencyModel>(latency: &mut LM) -> Vec<(i64, i64)> {
+        let order = Order::new(
+            1,
+            100,
+            1.0,
+            1.0,
+            Side::Buy,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        (0..100)
+            .map(|i| (latency.entry(i, &order), latency.response(i, &order)))
+            .collect()
+    }
+
+    #[test]
+    fn test_distribution_latency_is_deterministic_by_seed() {
+        let new = |seed| {
+            DistributionLatency::new(
+                seed,
+                |rng| 1000.0 + 100.0 * rng.standard_normal(),
+                |rng| -500.0 * (1.0 - rng.uniform()).ln(),
+            )
+        };
+
+        let mut latency = new(42);
+        assert_eq!(latency.last_latency(), (None, None));
+        let run1 = sample(&mut latency);
+        assert_eq!(latency.last_latency(), (Some(run1[99].0), Some(run1[99].1)));
+        let run2 = sample(&mut new(42));
+        assert_eq!(run1, run2);
+        // The latency varies, and another seed yields another sequence.
+        assert!(run1.iter().any(|&sampled| sampled != run1[0]));
+        assert_ne!(run1, sample(&mut new(7)));
+    }
+
+    #[test]
+    fn test_distribution_latency_clamps_negative_samples() {
+        let mut latency =
+            DistributionLatency::new(1, |rng| rng.uniform() * 100.0 - 50.0, |_| -10.0);
+        let sampled = sample(&mut latency);
+        assert!(sampled.iter().all(|&(entry, _)| entry >= 0));
+        assert!(sampled.iter().any(|&(entry, _)| entry == 0));
+        assert!(sampled.iter().any(|&(entry, _)| entry > 0));
+        assert!(sampled.iter().all(|&(_, response)| response == 0));
+    }
+
+    #[test]
+    fn test_piecewise_latency() {
+        let order = Order::new(
+            1,
+            100,
+            1.0,
+            1.0,
+            Side::Buy,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        // The latency spikes at 1000 and settles back by 2000. The breakpoints needn't be sorted.
+        let breakpoints = vec![(1000, 500, 250), (0, 100, 50), (2000, 100, 50)];
+        let applied = |latency: &mut PiecewiseLatency| {
+            [-10, 0, 500, 1000, 1500, 2000, 3000].map(|timestamp| {
+                (
+                    latency.entry(timestamp, &order),
+                    latency.response(timestamp, &order),
+                )
+            })
+        };
+
+        let mut step = PiecewiseLatency::new(breakpoints.clone());
+        assert_eq!(
+            applied(&mut step),
+            [
+                (100, 50),
+                (100, 50),
+                (100, 50),
+                (500, 250),
+                (500, 250),
+                (100, 50),
+                (100, 50)
+            ]
+        );
+
+        let mut linear =
+            PiecewiseLatency::new(breakpoints).interpolation(PiecewiseInterpolation::Linear);
+        assert_eq!(
+            applied(&mut linear),
+            [
+                (100, 50),
+                (100, 50),
+                (300, 150),
+                (500, 250),
+                (300, 150),
+                (100, 50),
+                (100, 50)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backlog_latency_grows_with_burst() {
+        let (mut exch, mut local) = order_bus(BacklogLatency::new(10, 20, 5));
+        for order_id in 1..=10 {
+            let mut order = Order::new(
+                order_id,
+                100,
+                1.0,
+                1.0,
+                Side::Buy,
+                OrdType::Limit,
+                TimeInForce::GTC,
+            );
+            order.local_timestamp = 0;
+            local.request(order, |_| {});
+        }
+        for _ in 0..10 {
+            let mut order = exch.receive(10).unwrap();
+            order.exch_timestamp = 10;
+            exch.respond(order);
+        }
+
+        let mut response_latencies = Vec::new();
+        while let Some(timestamp) = local.earliest_recv_order_timestamp() {
+            let order = local.receive(timestamp).unwrap();
+            response_latencies.push((order.order_id, timestamp - order.exch_timestamp));
+        }
+        assert_eq!(response_latencies.len(), 10);
+        assert_eq!(response_latencies[0], (1, 20));
+        assert_eq!(response_latencies[9], (10, 65));
+        assert!(response_latencies[9].1 > response_latencies[0].1);
+
+        // The response resets the backlog, and the response not tied to a request has the base
+        // latency.
+        let mut latency = BacklogLatency::new(10, 20, 5);
+        let order = Order::new(
+            11,
+            100,
+            1.0,
+            1.0,
+            Side::Buy,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        assert_eq!(latency.backlog(), 0);
+        latency.entry(0, &order);
+        assert_eq!(latency.backlog(), 1);
+        assert_eq!(latency.response(10, &order), 20);
+        assert_eq!(latency.backlog(), 0);
+        assert_eq!(latency.response(20, &order), 20);
+    }
+}
@@ -11,14 +11,31 @@ pub use fee::{
     CommonFees,
     DirectionalFees,
     FeeModel,
+    FixedFeeMode,
+    FixedPlusBpsFeeModel,
     FlatPerTradeFeeModel,
+    TieredFeeModel,
     TradingQtyFeeModel,
     TradingValueFeeModel,
 };
-pub use latency::{ConstantLatency, IntpOrderLatency, LatencyModel, OrderLatencyRow};
+pub use latency::{
+    BacklogLatency,
+    ConstantLatency,
+    DistributionLatency,
+    IntpOrderLatency,
+    LatencyFloor,
+    LatencyModel,
+    LatencyRng,
+    OrderLatencyRow,
+    PiecewiseInterpolation,
+    PiecewiseLatency,
+};
 pub use queue::{
     L3FIFOQueueModel,
+    L3ProRataQueueModel,
     L3QueueModel,
+    L3TopPlusProRataQueueModel,
+    L3TouchQueueModel,
     LogProbQueueFunc,
     LogProbQueueFunc2,
     PowerProbQueueFunc,
@@ -29,4 +46,5 @@ pub use queue::{
     QueueModel,
     QueuePos,
     RiskAdverseQueueModel,
+    TiePriority,
 };
@@ -1,14 +1,16 @@
 use std::{
     any::Any,
+    cmp::Reverse,
     collections::{HashMap, HashSet, VecDeque, hash_map::Entry},
     marker::PhantomData,
 };
 
 use crate::{
     backtest::{BacktestError, order},
-    depth::{INVALID_MAX, INVALID_MIN, MarketDepth},
+    depth::{INVALID_MAX, INVALID_MIN, L3MarketDepth, MarketDepth},
     types::{
-        AnyClone, BUY_EVENT, Event, OrdType, Order, OrderId, SELL_EVENT, Side, Status, TimeInForce,
+        AnyClone, BUY_EVENT, CancelReason, Event, OrdType, Order, OrderId, RejectReason,
+        SELL_EVENT, Side, Status, TimeInForce,
     },
 };
 
@@ -34,6 +36,12 @@ where
 /// when trades occur at the same price level.
 pub struct RiskAdverseQueueModel<MD>(PhantomData<MD>);
 
+impl<MD> Clone for RiskAdverseQueueModel<MD> {
+    fn clone(&self) -> Self {
+        Self(PhantomData)
+    }
+}
+
 impl AnyClone for f64 {
     fn as_any(&self) -> &dyn Any {
         self
@@ -133,6 +141,18 @@ where
     _md_marker: PhantomData<MD>,
 }
 
+impl<P, MD> Clone for ProbQueueModel<P, MD>
+where
+    P: Probability + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            prob: self.prob.clone(),
+            _md_marker: PhantomData,
+        }
+    }
+}
+
 impl<P, MD> ProbQueueModel<P, MD>
 where
     P: Probability,
@@ -205,6 +225,7 @@ where
 
 /// This probability model uses a power function `f(x) = x ** n` to adjust the probability which is
 /// calculated as `f(back) / (f(back) + f(front))`.
+#[derive(Clone)]
 pub struct PowerProbQueueFunc {
     n: f64,
 }
@@ -228,7 +249,7 @@ impl Probability for PowerProbQueueFunc {
 
 /// This probability model uses a logarithmic function `f(x) = log(1 + x)` to adjust the
 /// probability which is calculated as `f(back) / (f(back) + f(front))`.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct LogProbQueueFunc(());
 
 impl LogProbQueueFunc {
@@ -250,7 +271,7 @@ impl Probability for LogProbQueueFunc {
 
 /// This probability model uses a logarithmic function `f(x) = log(1 + x)` to adjust the
 /// probability which is calculated as `f(back) / f(back + front)`.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct LogProbQueueFunc2(());
 
 impl LogProbQueueFunc2 {
@@ -272,6 +293,7 @@ impl Probability for LogProbQueueFunc2 {
 
 /// This probability model uses a power function `f(x) = x ** n` to adjust the probability which is
 /// calculated as `f(back) / f(back + front)`.
+#[derive(Clone)]
 pub struct PowerProbQueueFunc2 {
     n: f64,
 }
@@ -295,6 +317,7 @@ impl Probability for PowerProbQueueFunc2 {
 
 /// This probability model uses a power function `f(x) = x ** n` to adjust the probability which is
 /// calculated as `1 - f(front / (front + back))`.
+#[derive(Clone)]
 pub struct PowerProbQueueFunc3 {
     n: f64,
 }
@@ -463,16 +486,136 @@ pub trait L3QueueModel<MD> {
     fn get_all_bid_orders(&self) -> Vec<Order>;
 
     fn get_all_ask_orders(&self) -> Vec<Order>;
+
+    /// Returns the estimated probability, bounded to `[0, 1]`, that the backtest order is fully
+    /// filled within `horizon` nanoseconds, based on the recent trade arrival rate at the order's
+    /// price and the quantity queued ahead of the order. Returns `0` if the order is not found or
+    /// no trade has recently occurred at the order's price. By default, the fill probability is
+    /// not modeled and `0` is returned.
+    fn fill_probability(&self, order_id: OrderId, horizon: i64) -> f64 {
+        let _ = (order_id, horizon);
+        0.0
+    }
+
+    /// Returns the backtest order resting in the queue for the order ID.
+    fn backtest_order(&self, order_id: OrderId) -> Option<&Order>;
+
+    /// Returns the quantity queued ahead of the backtest order. Returns `None` if the order is not
+    /// found or its queue position is not modeled.
+    fn qty_ahead(&self, order_id: OrderId) -> Option<f64>;
+
+    /// Returns the quantity allocated to the backtest order returned by the last
+    /// [`fill_market_feed_order`](L3QueueModel::fill_market_feed_order), given the quantity of the
+    /// market-feed fill. By default, the order is filled up to the fill quantity.
+    fn allocated_qty(&self, order: &Order, fill_qty: f64) -> f64 {
+        fill_qty.min(order.leaves_qty)
+    }
+}
+
+/// The default lookback window to measure the trade arrival rate, which is 60 seconds.
+const DEFAULT_TRADE_WINDOW: i64 = 60_000_000_000;
+
+/// Tracks the recent market-feed fills by price level to estimate the trade arrival rate.
+#[derive(Clone)]
+struct TradeIntensity {
+    window: i64,
+    last_ts: i64,
+    trades: HashMap<(Side, i64), VecDeque<(i64, f64)>>,
+}
+
+impl Default for TradeIntensity {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_TRADE_WINDOW,
+            last_ts: 0,
+            trades: Default::default(),
+        }
+    }
+}
+
+impl TradeIntensity {
+    fn record(&mut self, side: Side, price_tick: i64, timestamp: i64, qty: f64) {
+        self.last_ts = self.last_ts.max(timestamp);
+        let trades = self.trades.entry((side, price_tick)).or_default();
+        trades.push_back((timestamp, qty));
+        while trades
+            .front()
+            .is_some_and(|(ts, _)| *ts < self.last_ts - self.window)
+        {
+            trades.pop_front();
+        }
+    }
+
+    /// Models the number of trades at the price level within the horizon as a Poisson process
+    /// and returns the probability that enough trades, of the average recent size, arrive to
+    /// consume `qty`.
+    fn fill_probability(&self, side: Side, price_tick: i64, qty: f64, horizon: i64) -> f64 {
+        let Some(trades) = self.trades.get(&(side, price_tick)) else {
+            return 0.0;
+        };
+        let (count, traded_qty) = trades
+            .iter()
+            .filter(|(ts, _)| *ts >= self.last_ts - self.window)
+            .fold((0usize, 0.0), |(count, traded_qty), (_, trade_qty)| {
+                (count + 1, traded_qty + trade_qty)
+            });
+        if count == 0 || traded_qty <= 0.0 || self.window <= 0 {
+            return 0.0;
+        }
+        let mean = count as f64 / self.window as f64 * horizon.max(0) as f64;
+        let required = (qty / (traded_qty / count as f64)).ceil().max(1.0) as usize;
+        // P(N >= required) = 1 - P(N < required)
+        let mut term = (-mean).exp();
+        let mut cdf = 0.0;
+        for k in 0..required {
+            if k > 0 {
+                term *= mean / k as f64;
+            }
+            cdf += term;
+        }
+        (1.0 - cdf).clamp(0.0, 1.0)
+    }
+}
+
+/// Determines the queue priority between a backtest order and a market feed order that join the
+/// same price level at the same exchange timestamp.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum TiePriority {
+    /// The order processed first ranks ahead, so the processing order of the events decides.
+    #[default]
+    Arrival,
+    /// The market feed order ranks ahead of the backtest order, which is the conservative choice
+    /// as it doesn't let the backtest order jump ahead of the actual orders.
+    MarketFeedFirst,
+    /// The backtest order ranks ahead of the market feed order.
+    BacktestFirst,
+}
+
+/// Adds the order at the back of the queue. If `ahead_of_ties` is `true`, the order is placed
+/// ahead of the orders from the other source that joined the queue at the same timestamp.
+fn enqueue(queue: &mut VecDeque<Order>, order: Order, ahead_of_ties: bool) {
+    let mut i = queue.len();
+    if ahead_of_ties {
+        while i > 0
+            && queue[i - 1].exch_timestamp == order.exch_timestamp
+            && queue[i - 1].is_backtest_order() != order.is_backtest_order()
+        {
+            i -= 1;
+        }
+    }
+    queue.insert(i, order);
 }
 
 /// This provides a Level 3 Market-By-Order queue model for backtesting in a FIFO manner. This means
 /// that all orders, including backtest orders, are managed in a FIFO queue based on price-time
 /// priority and executed in the FIFO order. Backtest orders are assumed to be executed in the queue
-/// when the market order, order from the market feed, behind the backtest order is executed.
+/// when the market order, order from the market feed, behind the backtest order is executed. The
+/// fill quantity is absorbed by the backtest orders ahead of it in the queue order, and a backtest
+/// order larger than the fill keeps its position with the residual until it is fully filled.
 /// Exchanges may have different matching algorithms, such as Pro-Rata, and may have exotic order
 /// types that aren't executed in a FIFO manner. Therefore, you should carefully choose the queue
 /// model, even when dealing with a Level 3 Market-By-Order feed.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct L3FIFOQueueModel {
     // Stores the location of the queue that holds the order by (side, price in ticks).
     pub backtest_orders: HashMap<OrderId, (Side, i64)>,
@@ -481,6 +624,13 @@ pub struct L3FIFOQueueModel {
     // linked list, so it is better to use a vector.
     pub bid_queue: HashMap<i64, VecDeque<Order>>,
     pub ask_queue: HashMap<i64, VecDeque<Order>>,
+    trade_intensity: TradeIntensity,
+    hidden_volume_ratio: f64,
+    // The estimated hidden quantity still queued ahead of the backtest order, by order ID.
+    hidden_ahead: HashMap<OrderId, f64>,
+    tie_priority: TiePriority,
+    // The quantities allocated by the last market-feed fill, by order ID.
+    allocations: HashMap<OrderId, f64>,
 }
 
 impl L3FIFOQueueModel {
@@ -489,6 +639,53 @@ impl L3FIFOQueueModel {
         Default::default()
     }
 
+    /// Sets the lookback window in nanoseconds to measure the trade arrival rate used by
+    /// [`fill_probability`](L3QueueModel::fill_probability). The default value is 60 seconds.
+    pub fn trade_window(mut self, window: i64) -> Self {
+        self.trade_intensity.window = window;
+        self
+    }
+
+    /// Sets the estimated hidden quantity, such as the undisplayed part of iceberg orders, as a
+    /// multiple of the displayed quantity. A backtest order placed at the back of the queue is
+    /// also queued behind the hidden quantity of this multiple of the displayed quantity ahead of
+    /// it, which the fills of the market feed orders behind it consume before the backtest order
+    /// is filled. The default value is `0`, indicating no hidden quantity.
+    pub fn hidden_volume_ratio(mut self, ratio: f64) -> Self {
+        self.hidden_volume_ratio = ratio;
+        self
+    }
+
+    /// Sets the queue priority between a backtest order and a market feed order that join the same
+    /// price level at the same timestamp. The default value is [`TiePriority::Arrival`].
+    pub fn tie_priority(mut self, tie_priority: TiePriority) -> Self {
+        self.tie_priority = tie_priority;
+        self
+    }
+
+    /// Estimates the hidden quantity ahead of the backtest order just placed at the back of the
+    /// queue, as the multiple of the displayed quantity of the market feed orders ahead of it.
+    fn queue_behind_hidden(&mut self, order_id: OrderId, side: Side, price_tick: i64) {
+        let queue = match side {
+            Side::Buy => self.bid_queue.get(&price_tick),
+            _ => self.ask_queue.get(&price_tick),
+        };
+        let displayed_qty: f64 = queue.map_or(0.0, |queue| {
+            queue
+                .iter()
+                .take_while(|order| !(order.is_backtest_order() && order.order_id == order_id))
+                .filter(|order| order.is_market_feed_order())
+                .map(|order| order.leaves_qty)
+                .sum()
+        });
+        let hidden_qty = displayed_qty * self.hidden_volume_ratio;
+        if hidden_qty > 0.0 {
+            self.hidden_ahead.insert(order_id, hidden_qty);
+        } else {
+            self.hidden_ahead.remove(&order_id);
+        }
+    }
+
     fn fill_bid_between<const INVALID_FROM: bool>(
         &mut self,
         from_tick: i64,
@@ -629,12 +826,17 @@ where
             Side::None | Side::Unsupported => unreachable!(),
         };
 
-        queue.push_back(order);
+        enqueue(
+            queue,
+            order,
+            self.tie_priority == TiePriority::BacktestFirst,
+        );
 
         match self.backtest_orders.entry(order_id) {
             Entry::Occupied(_) => Err(BacktestError::OrderIdExist),
             Entry::Vacant(entry) => {
                 entry.insert((side, order_price_tick));
+                self.queue_behind_hidden(order_id, side, order_price_tick);
                 Ok(())
             }
         }
@@ -656,7 +858,7 @@ where
             unreachable!()
         };
 
-        queue.push_back(Order {
+        let feed_order = Order {
             qty: order.qty,
             leaves_qty: order.qty,
             price_tick: order_price_tick,
@@ -667,6 +869,9 @@ where
             side,
             // The information below is invalid.
             exec_qty: 0.0,
+            cum_exec_qty: 0.0,
+            cum_exec_notional: 0.0,
+            modify_count: 0,
             exec_price_tick: 0,
             local_timestamp: 0,
             maker: false,
@@ -675,7 +880,18 @@ where
             status: Status::None,
             time_in_force: TimeInForce::GTC,
             is_auction: false,
-        });
+            cancel_reason: CancelReason::None,
+            canceled_qty: 0.0,
+            reject_reason: RejectReason::None,
+            exch_order_id: 0,
+            max_lifetime: 0,
+            opening_auction: false,
+        };
+        enqueue(
+            queue,
+            feed_order,
+            self.tie_priority == TiePriority::MarketFeedFirst,
+        );
 
         match self.mkt_feed_orders.entry(order_id) {
             Entry::Occupied(_) => Err(BacktestError::OrderIdExist),
@@ -695,6 +911,7 @@ where
             .backtest_orders
             .remove(&order_id)
             .ok_or(BacktestError::OrderNotFound)?;
+        self.hidden_ahead.remove(&order_id);
 
         match side {
             Side::Buy => {
@@ -781,6 +998,7 @@ where
             .get_mut(&order_id)
             .ok_or(BacktestError::OrderNotFound)?;
 
+        let mut requeued = false;
         match side {
             Side::Buy => {
                 let queue = self.bid_queue.get_mut(order_price_tick).unwrap();
@@ -793,6 +1011,7 @@ where
                         {
                             let mut prev_order = queue.remove(i).unwrap();
                             let prev_order_price_tick = prev_order.price_tick;
+                            requeued = true;
                             prev_order.update(order);
                             // if queue.len() == 0 {
                             //     self.bid_queue.remove(&order_price_tick);
@@ -808,6 +1027,10 @@ where
                         } else {
                             order_in_q.leaves_qty = order.leaves_qty;
                             order_in_q.qty = order.qty;
+                            order_in_q.cum_exec_qty = order.cum_exec_qty;
+                            order_in_q.cum_exec_notional = order.cum_exec_notional;
+                            order_in_q.modify_count = order.modify_count;
+                            order_in_q.status = order.status;
                             order_in_q.exch_timestamp = order.exch_timestamp;
                         }
                         processed = true;
@@ -829,6 +1052,7 @@ where
                         {
                             let mut prev_order = queue.remove(i).unwrap();
                             let prev_order_price_tick = prev_order.price_tick;
+                            requeued = true;
                             prev_order.update(order);
                             // if queue.len() == 0 {
                             //     self.bid_queue.remove(&order_price_tick);
@@ -844,6 +1068,10 @@ where
                         } else {
                             order_in_q.leaves_qty = order.leaves_qty;
                             order_in_q.qty = order.qty;
+                            order_in_q.cum_exec_qty = order.cum_exec_qty;
+                            order_in_q.cum_exec_notional = order.cum_exec_notional;
+                            order_in_q.modify_count = order.modify_count;
+                            order_in_q.status = order.status;
                             order_in_q.exch_timestamp = order.exch_timestamp;
                         }
                         processed = true;
@@ -857,6 +1085,9 @@ where
             Side::None | Side::Unsupported => unreachable!(),
         }
 
+        if requeued {
+            self.queue_behind_hidden(order_id, order.side, order.price_tick);
+        }
         Ok(())
     }
 
@@ -974,92 +1205,95 @@ where
                 .ok_or(BacktestError::OrderNotFound)?
         };
         let exec_price_tick = (order.px / depth.tick_size()).round() as i64;
+        self.trade_intensity
+            .record(side, order_price_tick, order.exch_ts, order.qty);
 
-        match side {
-            Side::Buy => {
-                let mut filled = Vec::new();
+        self.allocations.clear();
 
+        let mut filled = match side {
+            Side::Buy => {
                 // The backtest bid orders above the price of the filled market-feed bid order are
                 // filled.
                 // The fill event should occur before the cancel event which may update the best
                 // price.
                 if exec_price_tick < depth.best_bid_tick() {
-                    let mut f =
-                        self.fill_bid_between::<false>(depth.best_bid_tick(), exec_price_tick + 1);
-                    filled.append(&mut f);
-                }
-
-                // The backtest bid orders in the queue, placed before the filled market-feed bid
-                // order, are filled.
-                let queue = self.bid_queue.get_mut(&order_price_tick).unwrap();
-
-                let mut i = 0;
-                while i < queue.len() {
-                    let order_in_q = queue.get(i).unwrap();
-                    match order_in_q.order_source() {
-                        L3OrderSource::MarketFeed if order_in_q.order_id == order_id => {
-                            if DELETE {
-                                queue.remove(i);
-                            }
-                            break;
-                        }
-                        L3OrderSource::MarketFeed => {
-                            i += 1;
-                        }
-                        L3OrderSource::Backtest => {
-                            let order = queue.remove(i).unwrap();
-                            filled.push(order);
-                        }
-                    }
-                }
-                for order in &filled {
-                    self.backtest_orders.remove(&order.order_id);
+                    self.fill_bid_between::<false>(depth.best_bid_tick(), exec_price_tick + 1)
+                } else {
+                    Vec::new()
                 }
-                Ok(filled)
             }
             Side::Sell => {
-                let mut filled = Vec::new();
-
                 // The backtest ask orders below the price of the filled market-feed ask order are
                 // filled.
                 // The fill event should occur before the cancel event which may update the best
                 // price.
                 if exec_price_tick > depth.best_ask_tick() {
-                    let mut f =
-                        self.fill_ask_between::<false>(depth.best_ask_tick(), exec_price_tick - 1);
-                    filled.append(&mut f);
+                    self.fill_ask_between::<false>(depth.best_ask_tick(), exec_price_tick - 1)
+                } else {
+                    Vec::new()
                 }
+            }
+            Side::None | Side::Unsupported => unreachable!(),
+        };
+        // They would have traded first, so they are filled in full.
+        for bt_order in &filled {
+            self.hidden_ahead.remove(&bt_order.order_id);
+            self.allocations
+                .insert(bt_order.order_id, bt_order.leaves_qty);
+        }
 
-                // The backtest ask orders in the queue, placed before the filled market-feed ask
-                // order, are filled.
-                let queue = self.ask_queue.get_mut(&order_price_tick).unwrap();
-
-                let mut i = 0;
-                while i < queue.len() {
-                    let order_in_q = queue.get(i).unwrap();
-                    match order_in_q.order_source() {
-                        L3OrderSource::MarketFeed if order_in_q.order_id == order_id => {
-                            if DELETE {
-                                queue.remove(i);
-                            }
-                            break;
-                        }
-                        L3OrderSource::MarketFeed => {
+        // The backtest orders in the queue, placed before the filled market-feed order, absorb the
+        // fill quantity in the queue order. The front order keeps absorbing the subsequent fills
+        // with its residual until it is fully filled, before the fill quantity reaches the orders
+        // behind it.
+        let queue = match side {
+            Side::Buy => self.bid_queue.get_mut(&order_price_tick).unwrap(),
+            _ => self.ask_queue.get_mut(&order_price_tick).unwrap(),
+        };
+        let mut remaining = order.qty;
+        let mut i = 0;
+        while i < queue.len() {
+            let order_in_q = queue.get_mut(i).unwrap();
+            match order_in_q.order_source() {
+                L3OrderSource::MarketFeed if order_in_q.order_id == order_id => {
+                    if DELETE {
+                        queue.remove(i);
+                    }
+                    break;
+                }
+                L3OrderSource::MarketFeed => {
+                    i += 1;
+                }
+                L3OrderSource::Backtest => {
+                    // The fill consumes the hidden quantity ahead of the backtest order first.
+                    if let Some(hidden_qty) = self.hidden_ahead.get_mut(&order_in_q.order_id) {
+                        *hidden_qty -= order.qty;
+                        if *hidden_qty > 0.0 {
                             i += 1;
+                            continue;
                         }
-                        L3OrderSource::Backtest => {
-                            let order = queue.remove(i).unwrap();
-                            filled.push(order);
-                        }
+                        self.hidden_ahead.remove(&order_in_q.order_id);
+                    }
+                    if remaining <= 0.0 {
+                        break;
+                    }
+                    let alloc = remaining.min(order_in_q.leaves_qty);
+                    remaining -= alloc;
+                    self.allocations.insert(order_in_q.order_id, alloc);
+                    if alloc >= order_in_q.leaves_qty - 1e-9 {
+                        let bt_order = queue.remove(i).unwrap();
+                        self.backtest_orders.remove(&bt_order.order_id);
+                        filled.push(bt_order);
+                    } else {
+                        // The residual keeps its position at the front of the queue.
+                        filled.push(order_in_q.clone());
+                        order_in_q.leaves_qty -= alloc;
+                        i += 1;
                     }
                 }
-                for order in &filled {
-                    self.backtest_orders.remove(&order.order_id);
-                }
-                Ok(filled)
             }
-            Side::None | Side::Unsupported => unreachable!(),
         }
+        Ok(filled)
     }
 
     fn clear_orders(&mut self, side: Side) -> Vec<Order> {
@@ -1197,137 +1431,1478 @@ where
         all_ask_orders.sort_by(|a, b| a.price_tick.cmp(&b.price_tick));
         all_ask_orders
     }
-}
-
-#[cfg(test)]
-mod l3_tests {
-    use crate::{
-        backtest::{L3QueueModel, models::L3FIFOQueueModel},
-        prelude::{
-            Event, HashMapMarketDepth, L3MarketDepth, OrdType, Order, Side, Status, TimeInForce,
-        },
-        types::{ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, FILL_EVENT, SELL_EVENT},
-    };
-
-    #[test]
-    fn fill_by_crossing() {
-        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
-        let mut qm = L3FIFOQueueModel::new();
 
-        let ev = Event {
-            ev: EXCH_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
-            exch_ts: 0,
-            local_ts: 0,
-            px: 100.0,
-            qty: 1.0,
-            order_id: 1,
-            ival: 0,
-            fval: 0.0,
+    fn fill_probability(&self, order_id: OrderId, horizon: i64) -> f64 {
+        let Some((side, price_tick)) = self.backtest_orders.get(&order_id) else {
+            return 0.0;
+        };
+        let queue = match side {
+            Side::Buy => self.bid_queue.get(price_tick),
+            _ => self.ask_queue.get(price_tick),
+        };
+        let Some(queue) = queue else {
+            return 0.0;
         };
+        // The quantity to be traded includes the orders ahead, including the hidden quantity, and
+        // the order itself.
+        let mut qty = self.hidden_ahead.get(&order_id).copied().unwrap_or(0.0);
+        for order_in_q in queue {
+            qty += order_in_q.leaves_qty;
+            if order_in_q.is_backtest_order() && order_in_q.order_id == order_id {
+                break;
+            }
+        }
+        self.trade_intensity
+            .fill_probability(*side, *price_tick, qty, horizon)
+    }
 
-        depth
-            .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
-            .unwrap();
-        qm.add_market_feed_order(&ev, &depth).unwrap();
+    fn backtest_order(&self, order_id: OrderId) -> Option<&Order> {
+        let (side, price_tick) = self.backtest_orders.get(&order_id)?;
+        let queue = match side {
+            Side::Buy => self.bid_queue.get(price_tick)?,
+            _ => self.ask_queue.get(price_tick)?,
+        };
+        queue
+            .iter()
+            .find(|order| order.is_backtest_order() && order.order_id == order_id)
+    }
 
-        let ev = Event {
-            ev: EXCH_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
-            exch_ts: 0,
-            local_ts: 0,
-            px: 101.0,
-            qty: 1.0,
-            order_id: 2,
-            ival: 0,
-            fval: 0.0,
+    fn qty_ahead(&self, order_id: OrderId) -> Option<f64> {
+        let (side, price_tick) = self.backtest_orders.get(&order_id)?;
+        let queue = match side {
+            Side::Buy => self.bid_queue.get(price_tick)?,
+            _ => self.ask_queue.get(price_tick)?,
         };
+        let mut qty = self.hidden_ahead.get(&order_id).copied().unwrap_or(0.0);
+        for order_in_q in queue {
+            if order_in_q.is_backtest_order() && order_in_q.order_id == order_id {
+                return Some(qty);
+            }
+            qty += order_in_q.leaves_qty;
+        }
+        None
+    }
 
-        depth
-            .add_sell_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
-            .unwrap();
-        qm.add_market_feed_order(&ev, &depth).unwrap();
+    fn allocated_qty(&self, order: &Order, _fill_qty: f64) -> f64 {
+        self.allocations
+            .get(&order.order_id)
+            .map_or(0.0, |alloc| alloc.min(order.leaves_qty))
+    }
+}
 
-        qm.add_backtest_order(
-            Order {
-                qty: 1.0,
-                leaves_qty: 0.0,
-                exec_qty: 0.0,
-                exec_price_tick: 0,
-                price_tick: 100,
-                tick_size: 1.0,
-                exch_timestamp: 0,
-                local_timestamp: 0,
-                order_id: 1,
-                q: Box::new(()),
-                maker: false,
-                order_type: OrdType::Limit,
-                req: Status::None,
-                status: Status::None,
-                side: Side::Buy,
-                time_in_force: TimeInForce::GTC,
-                is_auction: false,
-            },
-            &depth,
-        )
-        .unwrap();
+/// Holds a backtest order in the [`L3TouchQueueModel`].
+#[derive(Clone)]
+struct TouchOrder {
+    order: Order,
+    // The quantities of the market-feed orders ahead of the backtest order in the queue, by order
+    // ID. `None` if the order was placed behind the touch, so its queue position is not modeled.
+    ahead: Option<HashMap<OrderId, f64>>,
+}
 
-        let filled = <L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::on_best_ask_update(
-            &mut qm, 101, 100,
-        )
-        .unwrap();
-        assert_eq!(filled.len(), 1);
-        assert!(
-            !<L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(
-                &qm, 1
-            )
-        );
+/// This provides a lightweight Level 3 Market-By-Order queue model for strategies that only quote
+/// at the touch, the best bid and ask. Unlike [`L3FIFOQueueModel`], it doesn't maintain the queue
+/// of the entire order book; the market-feed orders are not tracked at all. Instead, when a
+/// backtest order is placed at or through the touch, the model takes the market-feed orders
+/// resting at the same price as the orders ahead of it in the queue. The backtest order is filled
+/// when a market-feed order behind it, which is not one of them, is filled.
+///
+/// A backtest order placed behind the touch is regarded as a deep order and never fills
+/// passively, even if the touch moves to its price later. It is filled only when the market
+/// trades through its price or the opposite side crosses it. A modification that loses the
+/// queue priority, such as a price change or a quantity increase, re-evaluates whether the order
+/// is at the touch.
+///
+/// Since the market-feed orders are not tracked, [`get_all_bid_orders`] and
+/// [`get_all_ask_orders`] only return the backtest orders.
+///
+/// [`get_all_bid_orders`]: L3QueueModel::get_all_bid_orders
+/// [`get_all_ask_orders`]: L3QueueModel::get_all_ask_orders
+#[derive(Clone, Default)]
+pub struct L3TouchQueueModel {
+    backtest_orders: HashMap<OrderId, TouchOrder>,
+    trade_intensity: TradeIntensity,
+}
 
-        qm.add_backtest_order(
-            Order {
-                qty: 1.0,
-                leaves_qty: 0.0,
-                exec_qty: 0.0,
-                exec_price_tick: 0,
-                price_tick: 101,
-                tick_size: 1.0,
-                exch_timestamp: 0,
-                local_timestamp: 0,
-                order_id: 1,
-                q: Box::new(()),
-                maker: false,
-                order_type: OrdType::Limit,
-                req: Status::None,
-                status: Status::None,
-                side: Side::Sell,
-                time_in_force: TimeInForce::GTC,
-                is_auction: false,
-            },
-            &depth,
-        )
-        .unwrap();
+impl L3TouchQueueModel {
+    /// Constructs an instance of `L3TouchQueueModel`.
+    pub fn new() -> Self {
+        Default::default()
+    }
 
-        let filled = <L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::on_best_bid_update(
-            &mut qm, 100, 101,
-        )
-        .unwrap();
-        assert_eq!(filled.len(), 1);
-        assert!(
-            !<L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(
-                &qm, 1
-            )
-        );
+    /// Sets the lookback window in nanoseconds to measure the trade arrival rate used by
+    /// [`fill_probability`](L3QueueModel::fill_probability). The default value is 60 seconds.
+    pub fn trade_window(mut self, window: i64) -> Self {
+        self.trade_intensity.window = window;
+        self
     }
 
-    #[test]
-    fn fill_in_queue() {
-        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
-        let mut qm = L3FIFOQueueModel::new();
+    fn orders_ahead<MD>(order: &Order, depth: &MD) -> Option<HashMap<OrderId, f64>>
+    where
+        MD: L3MarketDepth,
+    {
+        let at_touch = match order.side {
+            Side::Buy => order.price_tick >= depth.best_bid_tick(),
+            Side::Sell => order.price_tick <= depth.best_ask_tick(),
+            Side::None | Side::Unsupported => unreachable!(),
+        };
+        at_touch.then(|| {
+            depth
+                .orders()
+                .values()
+                .filter(|o| o.side == order.side && o.price_tick == order.price_tick)
+                .map(|o| (o.order_id, o.qty))
+                .collect()
+        })
+    }
 
-        let ev = Event {
-            ev: EXCH_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
-            exch_ts: 0,
-            local_ts: 0,
-            px: 100.0,
-            qty: 1.0,
+    fn take_filled<F>(&mut self, mut is_filled: F) -> Vec<Order>
+    where
+        F: FnMut(&TouchOrder) -> bool,
+    {
+        let mut filled = Vec::new();
+        self.backtest_orders.retain(|_, touch_order| {
+            if is_filled(touch_order) {
+                filled.push(touch_order.order.clone());
+                false
+            } else {
+                true
+            }
+        });
+        filled
+    }
+
+    fn sorted_orders(&self, side: Side) -> Vec<Order> {
+        let mut orders: Vec<_> = self
+            .backtest_orders
+            .values()
+            .filter(|touch_order| touch_order.order.side == side)
+            .map(|touch_order| touch_order.order.clone())
+            .collect();
+        match side {
+            Side::Buy => orders.sort_by_key(|order| Reverse(order.price_tick)),
+            _ => orders.sort_by_key(|order| order.price_tick),
+        }
+        orders
+    }
+}
+
+impl<MD> L3QueueModel<MD> for L3TouchQueueModel
+where
+    MD: L3MarketDepth,
+{
+    fn contains_backtest_order(&self, order_id: OrderId) -> bool {
+        self.backtest_orders.contains_key(&order_id)
+    }
+
+    fn on_best_bid_update(
+        &mut self,
+        _prev_best_tick: i64,
+        new_best_tick: i64,
+    ) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(|touch_order| {
+            touch_order.order.side == Side::Sell && touch_order.order.price_tick <= new_best_tick
+        }))
+    }
+
+    fn on_best_ask_update(
+        &mut self,
+        _prev_best_tick: i64,
+        new_best_tick: i64,
+    ) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(|touch_order| {
+            touch_order.order.side == Side::Buy && touch_order.order.price_tick >= new_best_tick
+        }))
+    }
+
+    fn add_backtest_order(&mut self, order: Order, depth: &MD) -> Result<(), BacktestError> {
+        match self.backtest_orders.entry(order.order_id) {
+            Entry::Occupied(_) => Err(BacktestError::OrderIdExist),
+            Entry::Vacant(entry) => {
+                let ahead = Self::orders_ahead(&order, depth);
+                entry.insert(TouchOrder { order, ahead });
+                Ok(())
+            }
+        }
+    }
+
+    fn add_market_feed_order(&mut self, _order: &Event, _depth: &MD) -> Result<(), BacktestError> {
+        // A newly added market-feed order is always behind the backtest orders.
+        Ok(())
+    }
+
+    fn cancel_backtest_order(
+        &mut self,
+        order_id: OrderId,
+        _depth: &MD,
+    ) -> Result<Order, BacktestError> {
+        self.backtest_orders
+            .remove(&order_id)
+            .map(|touch_order| touch_order.order)
+            .ok_or(BacktestError::OrderNotFound)
+    }
+
+    fn cancel_market_feed_order(
+        &mut self,
+        order_id: OrderId,
+        _depth: &MD,
+    ) -> Result<(), BacktestError> {
+        for touch_order in self.backtest_orders.values_mut() {
+            if let Some(ahead) = touch_order.ahead.as_mut() {
+                ahead.remove(&order_id);
+            }
+        }
+        Ok(())
+    }
+
+    fn modify_backtest_order(
+        &mut self,
+        order_id: OrderId,
+        order: &mut Order,
+        depth: &MD,
+    ) -> Result<(), BacktestError> {
+        let touch_order = self
+            .backtest_orders
+            .get_mut(&order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+        if (touch_order.order.price_tick != order.price_tick)
+            || (touch_order.order.leaves_qty < order.leaves_qty)
+        {
+            touch_order.order.update(order);
+            touch_order.ahead = Self::orders_ahead(&touch_order.order, depth);
+        } else {
+            touch_order.order.leaves_qty = order.leaves_qty;
+            touch_order.order.qty = order.qty;
+            touch_order.order.cum_exec_qty = order.cum_exec_qty;
+            touch_order.order.cum_exec_notional = order.cum_exec_notional;
+            touch_order.order.modify_count = order.modify_count;
+            touch_order.order.status = order.status;
+            touch_order.order.exch_timestamp = order.exch_timestamp;
+        }
+        Ok(())
+    }
+
+    fn modify_market_feed_order(
+        &mut self,
+        order_id: OrderId,
+        order: &Event,
+        depth: &MD,
+    ) -> Result<(), BacktestError> {
+        let new_price_tick = (order.px / depth.tick_size()).round() as i64;
+        for touch_order in self.backtest_orders.values_mut() {
+            let price_tick = touch_order.order.price_tick;
+            if let Some(ahead) = touch_order.ahead.as_mut() {
+                if let Entry::Occupied(mut entry) = ahead.entry(order_id) {
+                    // The market-feed order loses its priority if the price is changed or the
+                    // quantity is increased.
+                    if new_price_tick != price_tick || order.qty > *entry.get() {
+                        entry.remove();
+                    } else {
+                        *entry.get_mut() = order.qty;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_market_feed_order<const DELETE: bool>(
+        &mut self,
+        order_id: OrderId,
+        order: &Event,
+        depth: &MD,
+    ) -> Result<Vec<Order>, BacktestError> {
+        let exec_price_tick = (order.px / depth.tick_size()).round() as i64;
+        let side = if order.is(BUY_EVENT) {
+            Side::Buy
+        } else if order.is(SELL_EVENT) {
+            Side::Sell
+        } else {
+            return Err(BacktestError::InvalidOrderRequest);
+        };
+        self.trade_intensity
+            .record(side, exec_price_tick, order.exch_ts, order.qty);
+
+        // The backtest orders priced better than the filled market-feed order are filled, and the
+        // backtest orders at the touch are filled if the filled market-feed order is behind them.
+        let filled = self.take_filled(|touch_order| {
+            let bt_order = &touch_order.order;
+            if bt_order.side != side {
+                return false;
+            }
+            let better = match side {
+                Side::Buy => bt_order.price_tick > exec_price_tick,
+                _ => bt_order.price_tick < exec_price_tick,
+            };
+            better
+                || (bt_order.price_tick == exec_price_tick
+                    && touch_order
+                        .ahead
+                        .as_ref()
+                        .is_some_and(|ahead| !ahead.contains_key(&order_id)))
+        });
+
+        if DELETE {
+            for touch_order in self.backtest_orders.values_mut() {
+                if let Some(ahead) = touch_order.ahead.as_mut() {
+                    ahead.remove(&order_id);
+                }
+            }
+        }
+        Ok(filled)
+    }
+
+    fn clear_orders(&mut self, side: Side) -> Vec<Order> {
+        match side {
+            Side::Buy | Side::Sell => {
+                self.take_filled(|touch_order| touch_order.order.side == side)
+            }
+            Side::None => self.take_filled(|_| true),
+            Side::Unsupported => {
+                unreachable!()
+            }
+        }
+    }
+
+    fn fill_auction_bids(&mut self, auction_price_tick: i64) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(|touch_order| {
+            touch_order.order.side == Side::Buy
+                && touch_order.order.price_tick >= auction_price_tick
+        }))
+    }
+
+    fn fill_auction_asks(&mut self, auction_price_tick: i64) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(|touch_order| {
+            touch_order.order.side == Side::Sell
+                && touch_order.order.price_tick <= auction_price_tick
+        }))
+    }
+
+    fn get_all_bid_orders(&self) -> Vec<Order> {
+        self.sorted_orders(Side::Buy)
+    }
+
+    fn get_all_ask_orders(&self) -> Vec<Order> {
+        self.sorted_orders(Side::Sell)
+    }
+
+    fn fill_probability(&self, order_id: OrderId, horizon: i64) -> f64 {
+        let Some(touch_order) = self.backtest_orders.get(&order_id) else {
+            return 0.0;
+        };
+        // A deep order never fills passively.
+        let Some(ahead) = touch_order.ahead.as_ref() else {
+            return 0.0;
+        };
+        let order = &touch_order.order;
+        let qty = ahead.values().sum::<f64>() + order.leaves_qty;
+        self.trade_intensity
+            .fill_probability(order.side, order.price_tick, qty, horizon)
+    }
+
+    fn backtest_order(&self, order_id: OrderId) -> Option<&Order> {
+        self.backtest_orders
+            .get(&order_id)
+            .map(|touch_order| &touch_order.order)
+    }
+
+    fn qty_ahead(&self, order_id: OrderId) -> Option<f64> {
+        let ahead = self.backtest_orders.get(&order_id)?.ahead.as_ref()?;
+        Some(ahead.values().sum())
+    }
+}
+
+/// Holds a backtest order in the [`L3ProRataQueueModel`].
+#[derive(Clone)]
+struct ProRataOrder {
+    order: Order,
+    // The arrival sequence, by which the ties in the allocation are broken in time priority.
+    seq: u64,
+    // The market-feed quantity at the order's price as last seen.
+    book_qty: f64,
+}
+
+/// Allocates `fill_qty` across the orders of the given sizes in proportion to them. Each
+/// allocation is rounded down to a multiple of `lot`, and the leftover is then handed out a lot at
+/// a time to the orders with the largest remainders, with ties going to the earlier order, so that
+/// the allocations add up to the fill quantity. The last piece of the leftover can be smaller than
+/// a lot. No order is allocated more than its size.
+fn allocate_pro_rata(sizes: &[f64], fill_qty: f64, lot: f64) -> Vec<f64> {
+    let total: f64 = sizes.iter().sum();
+    if total <= 0.0 {
+        return vec![0.0; sizes.len()];
+    }
+    let fill_qty = fill_qty.min(total);
+    let shares: Vec<f64> = sizes.iter().map(|size| fill_qty * size / total).collect();
+    let mut allocs: Vec<f64> = shares
+        .iter()
+        .map(|share| {
+            if lot > 0.0 {
+                (share / lot + 1e-9).floor() * lot
+            } else {
+                *share
+            }
+        })
+        .collect();
+
+    // The sort is stable, so the ties keep the given order.
+    let mut by_remainder: Vec<usize> = (0..sizes.len()).collect();
+    by_remainder.sort_by(|&a, &b| (shares[b] - allocs[b]).total_cmp(&(shares[a] - allocs[a])));
+    let mut leftover = fill_qty - allocs.iter().sum::<f64>();
+    while leftover > 1e-9 {
+        let mut allocated = false;
+        for &i in &by_remainder {
+            let qty = leftover.min(lot).min(sizes[i] - allocs[i]);
+            if qty > 1e-9 {
+                allocs[i] += qty;
+                leftover -= qty;
+                allocated = true;
+                if leftover <= 1e-9 {
+                    break;
+                }
+            }
+        }
+        if !allocated {
+            break;
+        }
+    }
+    allocs
+}
+
+/// This provides a Level 3 Market-By-Order queue model for venues that allocate fills pro rata
+/// across the resting orders at a price, such as certain futures markets, rather than in time
+/// priority. When a market-feed order is filled, the fill quantity is distributed over the
+/// backtest orders and the market-feed quantity resting at the traded price in proportion to
+/// their sizes. A backtest order priced better than the traded price is filled in full, as it
+/// would have traded first.
+///
+/// Each allocation is rounded down to a multiple of the minimum allocation lot, and the leftover
+/// is handed out a lot at a time to the largest rounded-off remainders, so that the allocations
+/// add up to the fill quantity. The ties go to the market-feed quantity first and then to the
+/// backtest orders in time priority. A backtest order allocated less than its quantity keeps
+/// resting with the rest.
+///
+/// Since the queue position doesn't matter to the allocation, the market-feed orders are not
+/// tracked; the market-feed quantity at a price is taken from the market depth. Therefore,
+/// [`get_all_bid_orders`] and [`get_all_ask_orders`] only return the backtest orders, and
+/// [`qty_ahead`](L3QueueModel::qty_ahead) always returns `None`.
+///
+/// [`get_all_bid_orders`]: L3QueueModel::get_all_bid_orders
+/// [`get_all_ask_orders`]: L3QueueModel::get_all_ask_orders
+#[derive(Clone, Default)]
+pub struct L3ProRataQueueModel {
+    backtest_orders: HashMap<OrderId, ProRataOrder>,
+    // The quantities allocated by the last market-feed fill, by order ID.
+    allocations: HashMap<OrderId, f64>,
+    min_allocation: f64,
+    next_seq: u64,
+    trade_intensity: TradeIntensity,
+}
+
+impl L3ProRataQueueModel {
+    /// Constructs an instance of `L3ProRataQueueModel`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the minimum allocation lot, of which each allocation is a multiple except for the
+    /// last piece of the leftover. The default value is `0`, indicating the lot size of the
+    /// market depth.
+    pub fn min_allocation(self, min_allocation: f64) -> Self {
+        Self {
+            min_allocation,
+            ..self
+        }
+    }
+
+    /// Sets the lookback window in nanoseconds to measure the trade arrival rate used by
+    /// [`fill_probability`](L3QueueModel::fill_probability). The default value is 60 seconds.
+    pub fn trade_window(mut self, window: i64) -> Self {
+        self.trade_intensity.window = window;
+        self
+    }
+
+    fn book_qty<MD>(side: Side, price_tick: i64, depth: &MD) -> f64
+    where
+        MD: MarketDepth,
+    {
+        match side {
+            Side::Buy => depth.bid_qty_at_tick(price_tick),
+            _ => depth.ask_qty_at_tick(price_tick),
+        }
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.next_seq += 1;
+        self.next_seq
+    }
+
+    fn take_filled<F>(&mut self, mut is_filled: F) -> Vec<Order>
+    where
+        F: FnMut(&Order) -> bool,
+    {
+        let mut filled = Vec::new();
+        self.backtest_orders.retain(|_, pr_order| {
+            if is_filled(&pr_order.order) {
+                filled.push((pr_order.seq, pr_order.order.clone()));
+                false
+            } else {
+                true
+            }
+        });
+        filled.sort_by_key(|(seq, _)| *seq);
+        filled.into_iter().map(|(_, order)| order).collect()
+    }
+
+    fn sorted_orders(&self, side: Side) -> Vec<Order> {
+        let mut orders: Vec<_> = self
+            .backtest_orders
+            .values()
+            .filter(|pr_order| pr_order.order.side == side)
+            .collect();
+        match side {
+            Side::Buy => {
+                orders.sort_by_key(|pr_order| (Reverse(pr_order.order.price_tick), pr_order.seq))
+            }
+            _ => orders.sort_by_key(|pr_order| (pr_order.order.price_tick, pr_order.seq)),
+        }
+        orders
+            .into_iter()
+            .map(|pr_order| pr_order.order.clone())
+            .collect()
+    }
+}
+
+impl<MD> L3QueueModel<MD> for L3ProRataQueueModel
+where
+    MD: L3MarketDepth,
+{
+    fn contains_backtest_order(&self, order_id: OrderId) -> bool {
+        self.backtest_orders.contains_key(&order_id)
+    }
+
+    fn on_best_bid_update(
+        &mut self,
+        _prev_best_tick: i64,
+        new_best_tick: i64,
+    ) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(|order| order.side == Side::Sell && order.price_tick <= new_best_tick))
+    }
+
+    fn on_best_ask_update(
+        &mut self,
+        _prev_best_tick: i64,
+        new_best_tick: i64,
+    ) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(|order| order.side == Side::Buy && order.price_tick >= new_best_tick))
+    }
+
+    fn add_backtest_order(&mut self, order: Order, depth: &MD) -> Result<(), BacktestError> {
+        if self.backtest_orders.contains_key(&order.order_id) {
+            return Err(BacktestError::OrderIdExist);
+        }
+        let seq = self.next_seq();
+        let book_qty = Self::book_qty(order.side, order.price_tick, depth);
+        self.backtest_orders.insert(
+            order.order_id,
+            ProRataOrder {
+                order,
+                seq,
+                book_qty,
+            },
+        );
+        Ok(())
+    }
+
+    fn add_market_feed_order(&mut self, _order: &Event, _depth: &MD) -> Result<(), BacktestError> {
+        Ok(())
+    }
+
+    fn cancel_backtest_order(
+        &mut self,
+        order_id: OrderId,
+        _depth: &MD,
+    ) -> Result<Order, BacktestError> {
+        self.backtest_orders
+            .remove(&order_id)
+            .map(|pr_order| pr_order.order)
+            .ok_or(BacktestError::OrderNotFound)
+    }
+
+    fn cancel_market_feed_order(
+        &mut self,
+        _order_id: OrderId,
+        _depth: &MD,
+    ) -> Result<(), BacktestError> {
+        Ok(())
+    }
+
+    fn modify_backtest_order(
+        &mut self,
+        order_id: OrderId,
+        order: &mut Order,
+        depth: &MD,
+    ) -> Result<(), BacktestError> {
+        let seq = self.next_seq();
+        let pr_order = self
+            .backtest_orders
+            .get_mut(&order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+        // Only the time priority, which breaks the ties, is lost.
+        if (pr_order.order.price_tick != order.price_tick)
+            || (pr_order.order.leaves_qty < order.leaves_qty)
+        {
+            pr_order.order.update(order);
+            pr_order.seq = seq;
+            pr_order.book_qty = Self::book_qty(order.side, order.price_tick, depth);
+        } else {
+            pr_order.order.leaves_qty = order.leaves_qty;
+            pr_order.order.qty = order.qty;
+            pr_order.order.cum_exec_qty = order.cum_exec_qty;
+            pr_order.order.cum_exec_notional = order.cum_exec_notional;
+            pr_order.order.modify_count = order.modify_count;
+            pr_order.order.status = order.status;
+            pr_order.order.exch_timestamp = order.exch_timestamp;
+        }
+        Ok(())
+    }
+
+    fn modify_market_feed_order(
+        &mut self,
+        _order_id: OrderId,
+        _order: &Event,
+        _depth: &MD,
+    ) -> Result<(), BacktestError> {
+        Ok(())
+    }
+
+    fn fill_market_feed_order<const DELETE: bool>(
+        &mut self,
+        _order_id: OrderId,
+        order: &Event,
+        depth: &MD,
+    ) -> Result<Vec<Order>, BacktestError> {
+        let exec_price_tick = (order.px / depth.tick_size()).round() as i64;
+        let side = if order.is(BUY_EVENT) {
+            Side::Buy
+        } else if order.is(SELL_EVENT) {
+            Side::Sell
+        } else {
+            return Err(BacktestError::InvalidOrderRequest);
+        };
+        self.trade_intensity
+            .record(side, exec_price_tick, order.exch_ts, order.qty);
+        self.allocations.clear();
+
+        // The backtest orders priced better than the traded price are filled in full.
+        let mut filled = self.take_filled(|bt_order| {
+            bt_order.side == side
+                && match side {
+                    Side::Buy => bt_order.price_tick > exec_price_tick,
+                    _ => bt_order.price_tick < exec_price_tick,
+                }
+        });
+        for bt_order in &filled {
+            self.allocations
+                .insert(bt_order.order_id, bt_order.leaves_qty);
+        }
+
+        // The backtest orders at the traded price share the fill with the market-feed quantity.
+        let mut at_price: Vec<(u64, OrderId)> = self
+            .backtest_orders
+            .values()
+            .filter(|pr_order| {
+                pr_order.order.side == side && pr_order.order.price_tick == exec_price_tick
+            })
+            .map(|pr_order| (pr_order.seq, pr_order.order.order_id))
+            .collect();
+        if at_price.is_empty() {
+            return Ok(filled);
+        }
+        at_price.sort_unstable();
+        let book_qty = Self::book_qty(side, exec_price_tick, depth);
+        let mut sizes = vec![book_qty];
+        sizes.extend(
+            at_price
+                .iter()
+                .map(|(_, order_id)| self.backtest_orders[order_id].order.leaves_qty),
+        );
+        let lot = if self.min_allocation > 0.0 {
+            self.min_allocation
+        } else {
+            depth.lot_size()
+        };
+        let allocs = allocate_pro_rata(&sizes, order.qty, lot);
+        for ((_, order_id), &alloc) in at_price.iter().zip(&allocs[1..]) {
+            let pr_order = self.backtest_orders.get_mut(order_id).unwrap();
+            pr_order.book_qty = book_qty;
+            if alloc <= 0.0 {
+                continue;
+            }
+            filled.push(pr_order.order.clone());
+            self.allocations.insert(*order_id, alloc);
+            if alloc >= pr_order.order.leaves_qty - 1e-9 {
+                self.backtest_orders.remove(order_id);
+            } else {
+                pr_order.order.leaves_qty -= alloc;
+            }
+        }
+        Ok(filled)
+    }
+
+    fn clear_orders(&mut self, side: Side) -> Vec<Order> {
+        match side {
+            Side::Buy | Side::Sell => self.take_filled(|order| order.side == side),
+            Side::None => self.take_filled(|_| true),
+            Side::Unsupported => {
+                unreachable!()
+            }
+        }
+    }
+
+    fn fill_auction_bids(&mut self, auction_price_tick: i64) -> Result<Vec<Order>, BacktestError> {
+        Ok(self
+            .take_filled(|order| order.side == Side::Buy && order.price_tick >= auction_price_tick))
+    }
+
+    fn fill_auction_asks(&mut self, auction_price_tick: i64) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(|order| {
+            order.side == Side::Sell && order.price_tick <= auction_price_tick
+        }))
+    }
+
+    fn get_all_bid_orders(&self) -> Vec<Order> {
+        self.sorted_orders(Side::Buy)
+    }
+
+    fn get_all_ask_orders(&self) -> Vec<Order> {
+        self.sorted_orders(Side::Sell)
+    }
+
+    fn fill_probability(&self, order_id: OrderId, horizon: i64) -> f64 {
+        let Some(pr_order) = self.backtest_orders.get(&order_id) else {
+            return 0.0;
+        };
+        // The order is filled in full once the traded quantity at its price reaches the entire
+        // quantity resting there.
+        let order = &pr_order.order;
+        let qty = pr_order.book_qty
+            + self
+                .backtest_orders
+                .values()
+                .filter(|other| {
+                    other.order.side == order.side && other.order.price_tick == order.price_tick
+                })
+                .map(|other| other.order.leaves_qty)
+                .sum::<f64>();
+        self.trade_intensity
+            .fill_probability(order.side, order.price_tick, qty, horizon)
+    }
+
+    fn backtest_order(&self, order_id: OrderId) -> Option<&Order> {
+        self.backtest_orders
+            .get(&order_id)
+            .map(|pr_order| &pr_order.order)
+    }
+
+    fn qty_ahead(&self, _order_id: OrderId) -> Option<f64> {
+        None
+    }
+
+    fn allocated_qty(&self, order: &Order, _fill_qty: f64) -> f64 {
+        self.allocations
+            .get(&order.order_id)
+            .map_or(0.0, |alloc| alloc.min(order.leaves_qty))
+    }
+}
+
+/// This provides a Level 3 Market-By-Order queue model for venues that give the first order at a
+/// price a guaranteed top-priority portion of each fill and allocate the rest pro rata, such as
+/// certain interest rate futures markets. All orders, including backtest orders, are kept in a
+/// queue per price in time priority as in [`L3FIFOQueueModel`]. When a market-feed order is
+/// filled, the earliest order at the traded price first gets up to `top_fraction` of the fill
+/// quantity, and the remainder is allocated over all orders at the price, including the top order
+/// with its remaining quantity, in proportion to their sizes. A backtest order priced better than
+/// the traded price is filled in full, as it would have traded first.
+///
+/// The top portion is rounded down to a multiple of the minimum allocation lot, and the remainder
+/// is allocated in the same way as [`L3ProRataQueueModel`]. A backtest order allocated less than
+/// its quantity keeps resting with the rest, keeping its time priority.
+#[derive(Clone, Default)]
+pub struct L3TopPlusProRataQueueModel {
+    // Stores the location of the queue that holds the order by (side, price in ticks).
+    backtest_orders: HashMap<OrderId, (Side, i64)>,
+    mkt_feed_orders: HashMap<OrderId, (Side, i64)>,
+    bid_queue: HashMap<i64, VecDeque<Order>>,
+    ask_queue: HashMap<i64, VecDeque<Order>>,
+    // The quantities allocated by the last market-feed fill, by order ID.
+    allocations: HashMap<OrderId, f64>,
+    top_fraction: f64,
+    min_allocation: f64,
+    tie_priority: TiePriority,
+    trade_intensity: TradeIntensity,
+}
+
+impl L3TopPlusProRataQueueModel {
+    /// Constructs an instance of `L3TopPlusProRataQueueModel` giving the top order up to
+    /// `top_fraction`, bounded to `[0, 1]`, of each fill at its price. A fraction of `1` makes it
+    /// behave like a FIFO queue for the top order, and a fraction of `0` like a pure pro-rata
+    /// allocation.
+    pub fn new(top_fraction: f64) -> Self {
+        Self {
+            top_fraction: top_fraction.clamp(0.0, 1.0),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the minimum allocation lot, of which each allocation is a multiple except for the
+    /// last piece of the leftover. The default value is `0`, indicating the lot size of the
+    /// market depth.
+    pub fn min_allocation(self, min_allocation: f64) -> Self {
+        Self {
+            min_allocation,
+            ..self
+        }
+    }
+
+    /// Sets the lookback window in nanoseconds to measure the trade arrival rate used by
+    /// [`fill_probability`](L3QueueModel::fill_probability). The default value is 60 seconds.
+    pub fn trade_window(mut self, window: i64) -> Self {
+        self.trade_intensity.window = window;
+        self
+    }
+
+    /// Sets the queue priority between a backtest order and a market feed order that join the same
+    /// price level at the same timestamp, which decides the top order. The default value is
+    /// [`TiePriority::Arrival`].
+    pub fn tie_priority(self, tie_priority: TiePriority) -> Self {
+        Self {
+            tie_priority,
+            ..self
+        }
+    }
+
+    fn queue(&self, side: Side, price_tick: i64) -> Option<&VecDeque<Order>> {
+        match side {
+            Side::Buy => self.bid_queue.get(&price_tick),
+            _ => self.ask_queue.get(&price_tick),
+        }
+    }
+
+    fn queue_mut(&mut self, side: Side, price_tick: i64) -> &mut VecDeque<Order> {
+        match side {
+            Side::Buy => self.bid_queue.entry(price_tick).or_default(),
+            Side::Sell => self.ask_queue.entry(price_tick).or_default(),
+            Side::None | Side::Unsupported => unreachable!(),
+        }
+    }
+
+    /// Inserts the order into the queue at its price in the order of the exchange timestamp,
+    /// breaking the ties between the sources by the tie priority.
+    fn enqueue(&mut self, order: Order) {
+        let ahead_of_ties = match self.tie_priority {
+            TiePriority::MarketFeedFirst => order.is_market_feed_order(),
+            TiePriority::BacktestFirst => order.is_backtest_order(),
+            TiePriority::Arrival => false,
+        };
+        let queue = self.queue_mut(order.side, order.price_tick);
+        let mut i = queue.len();
+        while i > 0
+            && (queue[i - 1].exch_timestamp > order.exch_timestamp
+                || (ahead_of_ties
+                    && queue[i - 1].exch_timestamp == order.exch_timestamp
+                    && queue[i - 1].is_backtest_order() != order.is_backtest_order()))
+        {
+            i -= 1;
+        }
+        queue.insert(i, order);
+    }
+
+    fn remove_from_queue(
+        &mut self,
+        side: Side,
+        price_tick: i64,
+        order_id: OrderId,
+        source: L3OrderSource,
+    ) -> Option<Order> {
+        let queue = self.queue_mut(side, price_tick);
+        let i = queue
+            .iter()
+            .position(|order| order.order_source() == source && order.order_id == order_id)?;
+        queue.remove(i)
+    }
+
+    /// Removes the backtest orders on the side at the prices satisfying `is_filled` from the
+    /// queues, in price-time priority.
+    fn take_filled<F>(&mut self, side: Side, mut is_filled: F) -> Vec<Order>
+    where
+        F: FnMut(i64) -> bool,
+    {
+        let mut ticks: Vec<i64> = self
+            .backtest_orders
+            .values()
+            .filter(|(order_side, price_tick)| *order_side == side && is_filled(*price_tick))
+            .map(|(_, price_tick)| *price_tick)
+            .collect();
+        ticks.sort_unstable();
+        ticks.dedup();
+        if side == Side::Buy {
+            ticks.reverse();
+        }
+        let mut filled = Vec::new();
+        for price_tick in ticks {
+            self.queue_mut(side, price_tick).retain(|order| {
+                if order.is_backtest_order() {
+                    filled.push(order.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        for order in &filled {
+            self.backtest_orders.remove(&order.order_id);
+        }
+        filled
+    }
+
+    fn sorted_orders(&self, side: Side) -> Vec<Order> {
+        let queues = match side {
+            Side::Buy => &self.bid_queue,
+            _ => &self.ask_queue,
+        };
+        let mut ticks: Vec<i64> = queues.keys().copied().collect();
+        match side {
+            Side::Buy => ticks.sort_unstable_by_key(|price_tick| Reverse(*price_tick)),
+            _ => ticks.sort_unstable(),
+        }
+        ticks
+            .iter()
+            .flat_map(|price_tick| queues[price_tick].iter())
+            .filter(|order| order.is_backtest_order())
+            .cloned()
+            .collect()
+    }
+
+    fn lot<MD: MarketDepth>(&self, depth: &MD) -> f64 {
+        if self.min_allocation > 0.0 {
+            self.min_allocation
+        } else {
+            depth.lot_size()
+        }
+    }
+}
+
+impl<MD> L3QueueModel<MD> for L3TopPlusProRataQueueModel
+where
+    MD: MarketDepth,
+{
+    fn contains_backtest_order(&self, order_id: OrderId) -> bool {
+        self.backtest_orders.contains_key(&order_id)
+    }
+
+    fn on_best_bid_update(
+        &mut self,
+        _prev_best_tick: i64,
+        new_best_tick: i64,
+    ) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(Side::Sell, |price_tick| price_tick <= new_best_tick))
+    }
+
+    fn on_best_ask_update(
+        &mut self,
+        _prev_best_tick: i64,
+        new_best_tick: i64,
+    ) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(Side::Buy, |price_tick| price_tick >= new_best_tick))
+    }
+
+    fn add_backtest_order(&mut self, mut order: Order, _depth: &MD) -> Result<(), BacktestError> {
+        match self.backtest_orders.entry(order.order_id) {
+            Entry::Occupied(_) => return Err(BacktestError::OrderIdExist),
+            Entry::Vacant(entry) => {
+                entry.insert((order.side, order.price_tick));
+            }
+        }
+        order.q = Box::new(L3OrderSource::Backtest);
+        self.enqueue(order);
+        Ok(())
+    }
+
+    fn add_market_feed_order(&mut self, order: &Event, depth: &MD) -> Result<(), BacktestError> {
+        let tick_size = depth.tick_size();
+        let order_price_tick = (order.px / tick_size).round() as i64;
+        let side = if order.is(BUY_EVENT) {
+            Side::Buy
+        } else if order.is(SELL_EVENT) {
+            Side::Sell
+        } else {
+            unreachable!()
+        };
+        match self.mkt_feed_orders.entry(order.order_id) {
+            Entry::Occupied(_) => return Err(BacktestError::OrderIdExist),
+            Entry::Vacant(entry) => {
+                entry.insert((side, order_price_tick));
+            }
+        }
+
+        let feed_order = Order {
+            qty: order.qty,
+            leaves_qty: order.qty,
+            price_tick: order_price_tick,
+            exch_timestamp: order.exch_ts,
+            q: Box::new(L3OrderSource::MarketFeed),
+            tick_size,
+            order_id: order.order_id,
+            side,
+            // The information below is invalid.
+            exec_qty: 0.0,
+            cum_exec_qty: 0.0,
+            cum_exec_notional: 0.0,
+            modify_count: 0,
+            exec_price_tick: 0,
+            local_timestamp: 0,
+            maker: false,
+            order_type: OrdType::Limit,
+            req: Status::None,
+            status: Status::None,
+            time_in_force: TimeInForce::GTC,
+            is_auction: false,
+            cancel_reason: CancelReason::None,
+            canceled_qty: 0.0,
+            reject_reason: RejectReason::None,
+            exch_order_id: 0,
+            max_lifetime: 0,
+            opening_auction: false,
+        };
+        self.enqueue(feed_order);
+        Ok(())
+    }
+
+    fn cancel_backtest_order(
+        &mut self,
+        order_id: OrderId,
+        _depth: &MD,
+    ) -> Result<Order, BacktestError> {
+        let (side, order_price_tick) = self
+            .backtest_orders
+            .remove(&order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+        self.remove_from_queue(side, order_price_tick, order_id, L3OrderSource::Backtest)
+            .ok_or(BacktestError::OrderNotFound)
+    }
+
+    fn cancel_market_feed_order(
+        &mut self,
+        order_id: OrderId,
+        _depth: &MD,
+    ) -> Result<(), BacktestError> {
+        let (side, order_price_tick) = self
+            .mkt_feed_orders
+            .remove(&order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+        self.remove_from_queue(side, order_price_tick, order_id, L3OrderSource::MarketFeed)
+            .ok_or(BacktestError::OrderNotFound)?;
+        Ok(())
+    }
+
+    fn modify_backtest_order(
+        &mut self,
+        order_id: OrderId,
+        order: &mut Order,
+        _depth: &MD,
+    ) -> Result<(), BacktestError> {
+        order.q = Box::new(L3OrderSource::Backtest);
+
+        let (side, order_price_tick) = *self
+            .backtest_orders
+            .get(&order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+        let order_in_q = self
+            .queue_mut(side, order_price_tick)
+            .iter_mut()
+            .find(|order_in_q| order_in_q.is_backtest_order() && order_in_q.order_id == order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+        if (order_in_q.price_tick != order.price_tick) || (order_in_q.leaves_qty < order.leaves_qty)
+        {
+            // The order loses its time priority.
+            let mut prev_order = self
+                .remove_from_queue(side, order_price_tick, order_id, L3OrderSource::Backtest)
+                .unwrap();
+            prev_order.update(order);
+            self.backtest_orders
+                .insert(order_id, (side, prev_order.price_tick));
+            self.enqueue(prev_order);
+        } else {
+            order_in_q.leaves_qty = order.leaves_qty;
+            order_in_q.qty = order.qty;
+            order_in_q.cum_exec_qty = order.cum_exec_qty;
+            order_in_q.cum_exec_notional = order.cum_exec_notional;
+            order_in_q.modify_count = order.modify_count;
+            order_in_q.status = order.status;
+            order_in_q.exch_timestamp = order.exch_timestamp;
+        }
+        Ok(())
+    }
+
+    fn modify_market_feed_order(
+        &mut self,
+        order_id: OrderId,
+        order: &Event,
+        depth: &MD,
+    ) -> Result<(), BacktestError> {
+        let (side, order_price_tick) = *self
+            .mkt_feed_orders
+            .get(&order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+        let new_price_tick = (order.px / depth.tick_size()).round() as i64;
+        let order_in_q = self
+            .queue_mut(side, order_price_tick)
+            .iter_mut()
+            .find(|order_in_q| order_in_q.is_market_feed_order() && order_in_q.order_id == order_id)
+            .ok_or(BacktestError::OrderNotFound)?;
+        if (order_in_q.price_tick != new_price_tick) || (order_in_q.leaves_qty < order.qty) {
+            // The order loses its time priority.
+            let mut prev_order = self
+                .remove_from_queue(side, order_price_tick, order_id, L3OrderSource::MarketFeed)
+                .unwrap();
+            prev_order.price_tick = new_price_tick;
+            prev_order.leaves_qty = order.qty;
+            prev_order.qty = order.qty;
+            prev_order.exch_timestamp = order.exch_ts;
+            self.mkt_feed_orders
+                .insert(order_id, (side, new_price_tick));
+            self.enqueue(prev_order);
+        } else {
+            order_in_q.leaves_qty = order.qty;
+            order_in_q.qty = order.qty;
+            order_in_q.exch_timestamp = order.exch_ts;
+        }
+        Ok(())
+    }
+
+    fn fill_market_feed_order<const DELETE: bool>(
+        &mut self,
+        order_id: OrderId,
+        order: &Event,
+        depth: &MD,
+    ) -> Result<Vec<Order>, BacktestError> {
+        let (side, order_price_tick) = if DELETE {
+            self.mkt_feed_orders
+                .remove(&order_id)
+                .ok_or(BacktestError::OrderNotFound)?
+        } else {
+            *self
+                .mkt_feed_orders
+                .get(&order_id)
+                .ok_or(BacktestError::OrderNotFound)?
+        };
+        self.trade_intensity
+            .record(side, order_price_tick, order.exch_ts, order.qty);
+        self.allocations.clear();
+
+        // The backtest orders priced better than the filled market-feed order are filled in full.
+        let mut filled = self.take_filled(side, |price_tick| match side {
+            Side::Buy => price_tick > order_price_tick,
+            _ => price_tick < order_price_tick,
+        });
+        for bt_order in &filled {
+            self.allocations
+                .insert(bt_order.order_id, bt_order.leaves_qty);
+        }
+
+        // The top order takes its portion first, and then all orders at the price, including the
+        // filled market-feed order, share the remainder.
+        let lot = self.lot(depth);
+        let top_fraction = self.top_fraction;
+        let queue = self.queue_mut(side, order_price_tick);
+        let mut sizes: Vec<f64> = queue
+            .iter()
+            .map(|order_in_q| order_in_q.leaves_qty)
+            .collect();
+        let mut allocs = vec![0.0; sizes.len()];
+        let mut fill_qty = order.qty;
+        if let Some(top_size) = sizes.first_mut() {
+            let top_qty = fill_qty * top_fraction;
+            let top_qty = if lot > 0.0 {
+                (top_qty / lot + 1e-9).floor() * lot
+            } else {
+                top_qty
+            };
+            let top_qty = top_qty.min(*top_size);
+            allocs[0] = top_qty;
+            *top_size -= top_qty;
+            fill_qty -= top_qty;
+        }
+        for (alloc, pro_rata) in allocs
+            .iter_mut()
+            .zip(allocate_pro_rata(&sizes, fill_qty, lot))
+        {
+            *alloc += pro_rata;
+        }
+
+        let mut allocs = allocs.into_iter();
+        let mut allocated = Vec::new();
+        queue.retain_mut(|order_in_q| {
+            let alloc = allocs.next().unwrap();
+            if !order_in_q.is_backtest_order() || alloc <= 0.0 {
+                return true;
+            }
+            filled.push(order_in_q.clone());
+            let resting = alloc < order_in_q.leaves_qty - 1e-9;
+            allocated.push((order_in_q.order_id, alloc, resting));
+            if resting {
+                order_in_q.leaves_qty -= alloc;
+            }
+            resting
+        });
+        if DELETE {
+            self.remove_from_queue(side, order_price_tick, order_id, L3OrderSource::MarketFeed);
+        }
+        for (bt_order_id, alloc, resting) in allocated {
+            if !resting {
+                self.backtest_orders.remove(&bt_order_id);
+            }
+            self.allocations.insert(bt_order_id, alloc);
+        }
+        Ok(filled)
+    }
+
+    fn clear_orders(&mut self, side: Side) -> Vec<Order> {
+        let sides = match side {
+            Side::Buy | Side::Sell => vec![side],
+            Side::None => vec![Side::Buy, Side::Sell],
+            Side::Unsupported => {
+                unreachable!()
+            }
+        };
+        let mut expired = Vec::new();
+        for side in sides {
+            self.mkt_feed_orders
+                .retain(|_, (order_side, _)| *order_side != side);
+            expired.append(&mut self.take_filled(side, |_| true));
+            match side {
+                Side::Buy => self.bid_queue.clear(),
+                _ => self.ask_queue.clear(),
+            }
+        }
+        expired
+    }
+
+    fn fill_auction_bids(&mut self, auction_price_tick: i64) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(Side::Buy, |price_tick| price_tick >= auction_price_tick))
+    }
+
+    fn fill_auction_asks(&mut self, auction_price_tick: i64) -> Result<Vec<Order>, BacktestError> {
+        Ok(self.take_filled(Side::Sell, |price_tick| price_tick <= auction_price_tick))
+    }
+
+    fn get_all_bid_orders(&self) -> Vec<Order> {
+        self.sorted_orders(Side::Buy)
+    }
+
+    fn get_all_ask_orders(&self) -> Vec<Order> {
+        self.sorted_orders(Side::Sell)
+    }
+
+    fn fill_probability(&self, order_id: OrderId, horizon: i64) -> f64 {
+        let Some(order) = L3QueueModel::<MD>::backtest_order(self, order_id) else {
+            return 0.0;
+        };
+        // The order is filled in full once the traded quantity at its price reaches the entire
+        // quantity resting there, as the pro-rata allocation gives it only its share.
+        let qty: f64 = self
+            .queue(order.side, order.price_tick)
+            .map_or(0.0, |queue| {
+                queue.iter().map(|order_in_q| order_in_q.leaves_qty).sum()
+            });
+        self.trade_intensity
+            .fill_probability(order.side, order.price_tick, qty, horizon)
+    }
+
+    fn backtest_order(&self, order_id: OrderId) -> Option<&Order> {
+        let (side, order_price_tick) = self.backtest_orders.get(&order_id)?;
+        self.queue(*side, *order_price_tick)?
+            .iter()
+            .find(|order| order.is_backtest_order() && order.order_id == order_id)
+    }
+
+    /// Returns the quantity queued ahead of the backtest order in time priority, which decides
+    /// only whether the order is the top order.
+    fn qty_ahead(&self, order_id: OrderId) -> Option<f64> {
+        let (side, order_price_tick) = self.backtest_orders.get(&order_id)?;
+        let queue = self.queue(*side, *order_price_tick)?;
+        Some(
+            queue
+                .iter()
+                .take_while(|order| !(order.is_backtest_order() && order.order_id == order_id))
+                .map(|order| order.leaves_qty)
+                .sum(),
+        )
+    }
+
+    fn allocated_qty(&self, order: &Order, _fill_qty: f64) -> f64 {
+        self.allocations
+            .get(&order.order_id)
+            .map_or(0.0, |alloc| alloc.min(order.leaves_qty))
+    }
+}
+
+#[cfg(test)]
+mod l3_tests {
+    use crate::{
+        backtest::{
+            L3QueueModel,
+            models::{
+                L3FIFOQueueModel, L3ProRataQueueModel, L3TopPlusProRataQueueModel,
+                L3TouchQueueModel, TiePriority,
+            },
+        },
+        prelude::{
+            Event, HashMapMarketDepth, L3MarketDepth, OrdType, Order, Side, Status, TimeInForce,
+        },
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, CancelReason, EXCH_EVENT, FILL_EVENT, RejectReason,
+            SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn fill_by_crossing() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let mut qm = L3FIFOQueueModel::new();
+
+        let ev = Event {
+            ev: EXCH_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+            exch_ts: 0,
+            local_ts: 0,
+            px: 100.0,
+            qty: 1.0,
+            order_id: 1,
+            ival: 0,
+            fval: 0.0,
+        };
+
+        depth
+            .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+            .unwrap();
+        qm.add_market_feed_order(&ev, &depth).unwrap();
+
+        let ev = Event {
+            ev: EXCH_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+            exch_ts: 0,
+            local_ts: 0,
+            px: 101.0,
+            qty: 1.0,
+            order_id: 2,
+            ival: 0,
+            fval: 0.0,
+        };
+
+        depth
+            .add_sell_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+            .unwrap();
+        qm.add_market_feed_order(&ev, &depth).unwrap();
+
+        qm.add_backtest_order(
+            Order {
+                qty: 1.0,
+                leaves_qty: 0.0,
+                exec_qty: 0.0,
+                cum_exec_qty: 0.0,
+                cum_exec_notional: 0.0,
+                modify_count: 0,
+                exec_price_tick: 0,
+                price_tick: 100,
+                tick_size: 1.0,
+                exch_timestamp: 0,
+                local_timestamp: 0,
+                order_id: 1,
+                q: Box::new(()),
+                maker: false,
+                order_type: OrdType::Limit,
+                req: Status::None,
+                status: Status::None,
+                side: Side::Buy,
+                time_in_force: TimeInForce::GTC,
+                is_auction: false,
+                cancel_reason: CancelReason::None,
+                canceled_qty: 0.0,
+                reject_reason: RejectReason::None,
+                exch_order_id: 0,
+                max_lifetime: 0,
+                opening_auction: false,
+            },
+            &depth,
+        )
+        .unwrap();
+
+        let filled = <L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::on_best_ask_update(
+            &mut qm, 101, 100,
+        )
+        .unwrap();
+        assert_eq!(filled.len(), 1);
+        assert!(
+            !<L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(
+                &qm, 1
+            )
+        );
+
+        qm.add_backtest_order(
+            Order {
+                qty: 1.0,
+                leaves_qty: 0.0,
+                exec_qty: 0.0,
+                cum_exec_qty: 0.0,
+                cum_exec_notional: 0.0,
+                modify_count: 0,
+                exec_price_tick: 0,
+                price_tick: 101,
+                tick_size: 1.0,
+                exch_timestamp: 0,
+                local_timestamp: 0,
+                order_id: 1,
+                q: Box::new(()),
+                maker: false,
+                order_type: OrdType::Limit,
+                req: Status::None,
+                status: Status::None,
+                side: Side::Sell,
+                time_in_force: TimeInForce::GTC,
+                is_auction: false,
+                cancel_reason: CancelReason::None,
+                canceled_qty: 0.0,
+                reject_reason: RejectReason::None,
+                exch_order_id: 0,
+                max_lifetime: 0,
+                opening_auction: false,
+            },
+            &depth,
+        )
+        .unwrap();
+
+        let filled = <L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::on_best_bid_update(
+            &mut qm, 100, 101,
+        )
+        .unwrap();
+        assert_eq!(filled.len(), 1);
+        assert!(
+            !<L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(
+                &qm, 1
+            )
+        );
+    }
+
+    #[test]
+    fn fill_in_queue() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let mut qm = L3FIFOQueueModel::new();
+
+        let ev = Event {
+            ev: EXCH_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+            exch_ts: 0,
+            local_ts: 0,
+            px: 100.0,
+            qty: 1.0,
             order_id: 1,
             ival: 0,
             fval: 0.0,
@@ -1343,6 +2918,9 @@ mod l3_tests {
                 qty: 1.0,
                 leaves_qty: 0.0,
                 exec_qty: 0.0,
+                cum_exec_qty: 0.0,
+                cum_exec_notional: 0.0,
+                modify_count: 0,
                 exec_price_tick: 0,
                 price_tick: 100,
                 tick_size: 1.0,
@@ -1357,6 +2935,12 @@ mod l3_tests {
                 side: Side::Buy,
                 time_in_force: TimeInForce::GTC,
                 is_auction: false,
+                cancel_reason: CancelReason::None,
+                canceled_qty: 0.0,
+                reject_reason: RejectReason::None,
+                exch_order_id: 0,
+                max_lifetime: 0,
+                opening_auction: false,
             },
             &depth,
         )
@@ -1431,4 +3015,545 @@ mod l3_tests {
             )
         );
     }
+
+    #[test]
+    fn touch_queue_model_fills_only_touch_order_passively() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let mut qm = L3TouchQueueModel::new();
+
+        let bid = |order_id, px| Event {
+            ev: EXCH_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+            exch_ts: 0,
+            local_ts: 0,
+            px,
+            qty: 1.0,
+            order_id,
+            ival: 0,
+            fval: 0.0,
+        };
+        let fill = |order_id, px| Event {
+            ev: EXCH_EVENT | BUY_EVENT | FILL_EVENT,
+            ..bid(order_id, px)
+        };
+
+        for ev in [bid(1, 100.0), bid(2, 99.0)] {
+            depth
+                .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+                .unwrap();
+            qm.add_market_feed_order(&ev, &depth).unwrap();
+        }
+
+        // The order 10 is at the touch, behind the market-feed order 1, while the order 11 is
+        // behind the touch.
+        let touch = Order::new(
+            10,
+            100,
+            1.0,
+            1.0,
+            Side::Buy,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        let deep = Order::new(
+            11,
+            99,
+            1.0,
+            1.0,
+            Side::Buy,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        qm.add_backtest_order(touch, &depth).unwrap();
+        qm.add_backtest_order(deep, &depth).unwrap();
+
+        for ev in [bid(3, 100.0), bid(4, 99.0)] {
+            depth
+                .add_buy_order(ev.order_id, ev.px, ev.qty, ev.exch_ts)
+                .unwrap();
+            qm.add_market_feed_order(&ev, &depth).unwrap();
+        }
+
+        // The market-feed order ahead is filled.
+        let filled = qm
+            .fill_market_feed_order::<true>(1, &fill(1, 100.0), &depth)
+            .unwrap();
+        assert!(filled.is_empty());
+
+        // The market-feed order behind is filled.
+        let filled = qm
+            .fill_market_feed_order::<true>(3, &fill(3, 100.0), &depth)
+            .unwrap();
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].order_id, 10);
+
+        // Even though the market-feed orders both ahead and behind the deep order are filled, the
+        // deep order is never filled passively.
+        for order_id in [2, 4] {
+            let filled = qm
+                .fill_market_feed_order::<true>(order_id, &fill(order_id, 99.0), &depth)
+                .unwrap();
+            assert!(filled.is_empty());
+        }
+        assert!(
+            <L3TouchQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(
+                &qm, 11
+            )
+        );
+
+        // It is filled only when the market trades through its price.
+        let filled = qm
+            .fill_market_feed_order::<true>(5, &fill(5, 98.0), &depth)
+            .unwrap();
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].order_id, 11);
+    }
+
+    #[test]
+    fn pro_rata_queue_model_allocates_fill_by_size() {
+        let depth = HashMapMarketDepth::new(1.0, 1.0);
+        let fill = |qty| Event {
+            ev: EXCH_EVENT | BUY_EVENT | FILL_EVENT,
+            exch_ts: 0,
+            local_ts: 0,
+            px: 100.0,
+            qty,
+            order_id: 1,
+            ival: 0,
+            fval: 0.0,
+        };
+        let new_model = |min_allocation| {
+            let mut qm = L3ProRataQueueModel::new().min_allocation(min_allocation);
+            for (order_id, qty) in [(10, 1.0), (11, 2.0), (12, 7.0)] {
+                let order = Order::new(
+                    order_id,
+                    100,
+                    1.0,
+                    qty,
+                    Side::Buy,
+                    OrdType::Limit,
+                    TimeInForce::GTC,
+                );
+                qm.add_backtest_order(order, &depth).unwrap();
+            }
+            qm
+        };
+        let allocate = |qm: &mut L3ProRataQueueModel, qty| {
+            qm.fill_market_feed_order::<false>(1, &fill(qty), &depth)
+                .unwrap()
+                .iter()
+                .map(|order| {
+                    let alloc = L3QueueModel::<HashMapMarketDepth>::allocated_qty(qm, order, qty);
+                    (order.order_id, alloc)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The shares of 0.5, 1 and 3.5 are rounded down to 0, 1 and 3, and the leftover lot goes
+        // to the earlier of the two orders with the largest remainder.
+        let mut qm = new_model(1.0);
+        assert_eq!(
+            allocate(&mut qm, 5.0),
+            vec![(10, 1.0), (11, 1.0), (12, 3.0)]
+        );
+        assert!(
+            !<L3ProRataQueueModel as L3QueueModel<HashMapMarketDepth>>::contains_backtest_order(
+                &qm, 10
+            )
+        );
+        assert_eq!(
+            <L3ProRataQueueModel as L3QueueModel<HashMapMarketDepth>>::backtest_order(&qm, 12)
+                .unwrap()
+                .leaves_qty,
+            4.0
+        );
+
+        // With a lot of 2, only the order of 7 gets a whole lot, and the leftover of 3 goes a lot
+        // to the largest remainder and then the rest below a lot to the next.
+        let mut qm = new_model(2.0);
+        assert_eq!(allocate(&mut qm, 5.0), vec![(11, 1.0), (12, 4.0)]);
+    }
+
+    #[test]
+    fn top_plus_pro_rata_top_advantage_shrinks_with_top_fraction() {
+        let depth = HashMapMarketDepth::new(1.0, 1.0);
+        let feed = |ev, exch_ts| Event {
+            ev: EXCH_EVENT | BUY_EVENT | ev,
+            exch_ts,
+            local_ts: exch_ts,
+            px: 100.0,
+            qty: 4.0,
+            order_id: 1,
+            ival: 0,
+            fval: 0.0,
+        };
+        // Both the backtest order and the market-feed order rest 4 at 100, and the market-feed
+        // order is filled 4.
+        let allocate = |mut qm: L3TopPlusProRataQueueModel, feed_ts| {
+            let order = Order::new(
+                10,
+                100,
+                1.0,
+                4.0,
+                Side::Buy,
+                OrdType::Limit,
+                TimeInForce::GTC,
+            );
+            qm.add_backtest_order(order, &depth).unwrap();
+            qm.add_market_feed_order(&feed(ADD_ORDER_EVENT, feed_ts), &depth)
+                .unwrap();
+            let qty_ahead = L3QueueModel::<HashMapMarketDepth>::qty_ahead(&qm, 10).unwrap();
+            let filled = qm
+                .fill_market_feed_order::<true>(1, &feed(FILL_EVENT, feed_ts), &depth)
+                .unwrap();
+            let alloc = filled.first().map_or(0.0, |order| {
+                L3QueueModel::<HashMapMarketDepth>::allocated_qty(&qm, order, 4.0)
+            });
+            (qty_ahead, alloc)
+        };
+
+        // As the top order, the backtest order takes its top portion of 4, 2 and 0 first, and
+        // then its pro-rata share of the remainder, 0 of 0, 1 of 2 with the leftover lot and 2 of
+        // 4.
+        let allocs: Vec<_> = [1.0, 0.5, 0.0]
+            .into_iter()
+            .map(|top_fraction| allocate(L3TopPlusProRataQueueModel::new(top_fraction), 1).1)
+            .collect();
+        assert_eq!(allocs, vec![4.0, 3.0, 2.0]);
+
+        // The market-feed order joining at the same timestamp ranks behind the backtest order
+        // that arrives first by default, but ranks ahead with the market-feed-first priority,
+        // taking the entire fill as the top order.
+        assert_eq!(
+            allocate(L3TopPlusProRataQueueModel::new(1.0), 0),
+            (0.0, 4.0)
+        );
+        assert_eq!(
+            allocate(
+                L3TopPlusProRataQueueModel::new(1.0).tie_priority(TiePriority::MarketFeedFirst),
+                0
+            ),
+            (4.0, 0.0)
+        );
+        assert_eq!(
+            allocate(
+                L3TopPlusProRataQueueModel::new(1.0).tie_priority(TiePriority::BacktestFirst),
+                0
+            ),
+            (0.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn fill_probability_by_trade_intensity_and_queue_position() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        let mut qm = L3FIFOQueueModel::new();
+
+        let add = |ev, order_id, px| Event {
+            ev: EXCH_EVENT | ev | ADD_ORDER_EVENT,
+            exch_ts: 0,
+            local_ts: 0,
+            px,
+            qty: 1.0,
+            order_id,
+            ival: 0,
+            fval: 0.0,
+        };
+        let fill = |ev, order_id, px, exch_ts| Event {
+            ev: EXCH_EVENT | ev | FILL_EVENT,
+            exch_ts,
+            ..add(ev, order_id, px)
+        };
+
+        // The busy bid level has 5 market-feed orders, and the quiet ask level has 20.
+        for order_id in 1..=5 {
+            let ev = add(BUY_EVENT, order_id, 100.0);
+            depth.add_buy_order(order_id, ev.px, ev.qty, 0).unwrap();
+            qm.add_market_feed_order(&ev, &depth).unwrap();
+        }
+        for order_id in 11..=30 {
+            let ev = add(SELL_EVENT, order_id, 105.0);
+            depth.add_sell_order(order_id, ev.px, ev.qty, 0).unwrap();
+            qm.add_market_feed_order(&ev, &depth).unwrap();
+        }
+        let front = Order::new(
+            100,
+            100,
+            1.0,
+            1.0,
+            Side::Buy,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        let deep = Order::new(
+            101,
+            105,
+            1.0,
+            1.0,
+            Side::Sell,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        qm.add_backtest_order(front, &depth).unwrap();
+        qm.add_backtest_order(deep, &depth).unwrap();
+
+        let mut trades = vec![fill(SELL_EVENT, 11, 105.0, 500_000_000)];
+        for order_id in 1..=4 {
+            trades.push(fill(
+                BUY_EVENT,
+                order_id,
+                100.0,
+                order_id as i64 * 1_000_000_000,
+            ));
+        }
+        for ev in trades {
+            let filled = qm
+                .fill_market_feed_order::<true>(ev.order_id, &ev, &depth)
+                .unwrap();
+            assert!(filled.is_empty());
+            depth.delete_order(ev.order_id, ev.exch_ts).unwrap();
+        }
+
+        let fill_probability = |order_id| {
+            <L3FIFOQueueModel as L3QueueModel<HashMapMarketDepth>>::fill_probability(
+                &qm,
+                order_id,
+                10_000_000_000,
+            )
+        };
+        let front = fill_probability(100);
+        let deep = fill_probability(101);
+        assert!((0.0..=1.0).contains(&front));
+        assert!((0.0..=1.0).contains(&deep));
+        assert!(front > deep);
+        assert_eq!(fill_probability(999), 0.0);
+    }
+}
+
+#[cfg(test)]
+mod backtest_tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest, DataSource,
+            ExchangeKind::{NoPartialFillExchange, PartialFillExchange},
+            L3AssetBuilder,
+            assettype::LinearAsset,
+            data::Data,
+            models::{
+                CommonFees, ConstantLatency, L3FIFOQueueModel, TiePriority, TradingValueFeeModel,
+            },
+            testutil::{l3_asset, l3_event},
+        },
+        depth::HashMapMarketDepth,
+        prelude::{Bot, OrdType, Status, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, EXCH_FILL_EVENT, FILL_EVENT, LOCAL_EVENT,
+            SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn front_backtest_order_absorbs_successive_prints() -> Result<(), Box<dyn Error>> {
+        let print = |ts: i64| {
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                ts,
+                100.0,
+                1.0,
+                3,
+            )
+        };
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            // Joins the queue behind both backtest orders.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                100,
+                100.0,
+                5.0,
+                3,
+            ),
+            print(200),
+            print(300),
+            print(400),
+            print(500),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_counterparty(false)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 3.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_buy_order(0, 11, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+
+        hbt.elapse(149)?;
+
+        // Each print is absorbed by the front order until it is fully filled.
+        for expected in [1.0, 2.0, 3.0] {
+            hbt.elapse(100)?;
+            assert_eq!(hbt.orders(0).get(&10).unwrap().cum_exec_qty, expected);
+            assert_eq!(hbt.orders(0).get(&11).unwrap().cum_exec_qty, 0.0);
+        }
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::Filled);
+
+        // Only then does the next print reach the order behind it.
+        hbt.elapse(100)?;
+        assert_eq!(hbt.orders(0).get(&11).unwrap().cum_exec_qty, 1.0);
+        assert_eq!(hbt.orders(0).get(&11).unwrap().status, Status::Filled);
+        Ok(())
+    }
+
+    #[test]
+    fn hidden_volume_delays_queue_position() -> Result<(), Box<dyn Error>> {
+        let run = |hidden_volume_ratio: f64| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    100,
+                    100.0,
+                    1.0,
+                    3,
+                ),
+                // The feed order queued behind the backtest order is filled in two parts.
+                l3_event(EXCH_FILL_EVENT | BUY_EVENT, 200, 100.0, 0.5, 3),
+                l3_event(EXCH_FILL_EVENT | BUY_EVENT, 300, 100.0, 0.5, 3),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    L3AssetBuilder::default()
+                        .data(vec![DataSource::Data(data)])
+                        .latency_model(ConstantLatency::new(10, 20))
+                        .asset_type(LinearAsset::new(1.0))
+                        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                        .queue_model(
+                            L3FIFOQueueModel::new().hidden_volume_ratio(hidden_volume_ratio),
+                        )
+                        .exchange(NoPartialFillExchange)
+                        .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+            hbt.elapse(15)?;
+            let initial_qty_ahead = hbt.exchange_order(0, 10).unwrap().qty_ahead;
+            hbt.elapse(234)?;
+            let status_after_first_fill = hbt.orders(0).get(&10).unwrap().status;
+            hbt.elapse(100)?;
+            let status_after_second_fill = hbt.orders(0).get(&10).unwrap().status;
+            Ok((
+                initial_qty_ahead,
+                status_after_first_fill,
+                status_after_second_fill,
+            ))
+        };
+
+        // Only the displayed quantity of the order 1 is ahead, so the first fill behind the
+        // backtest order fills it.
+        assert_eq!(run(0.0)?, (Some(1.0), Status::Filled, Status::Filled));
+        // The hidden quantity as much as the displayed quantity is also ahead, which takes both
+        // fills to consume.
+        assert_eq!(run(1.0)?, (Some(2.0), Status::New, Status::Filled));
+        Ok(())
+    }
+
+    #[test]
+    fn tie_priority_between_feed_and_backtest_orders() -> Result<(), Box<dyn Error>> {
+        let run = |tie_priority: TiePriority| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                // Joins the price level at the same timestamp as the backtest order.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    11,
+                    100.0,
+                    1.0,
+                    3,
+                ),
+                l3_event(EXCH_FILL_EVENT | BUY_EVENT, 100, 100.0, 1.0, 3),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    L3AssetBuilder::default()
+                        .data(vec![DataSource::Data(data)])
+                        .latency_model(ConstantLatency::new(10, 20))
+                        .asset_type(LinearAsset::new(1.0))
+                        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                        .queue_model(L3FIFOQueueModel::new().tie_priority(tie_priority))
+                        .exchange(NoPartialFillExchange)
+                        .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+            hbt.elapse(15)?;
+            let qty_ahead = hbt.exchange_order(0, 10).unwrap().qty_ahead;
+            hbt.elapse(200)?;
+            let status = hbt.orders(0).get(&10).unwrap().status;
+            Ok((qty_ahead, status))
+        };
+
+        // The feed order ranks ahead, so its fill doesn't reach the backtest order.
+        assert_eq!(run(TiePriority::MarketFeedFirst)?, (Some(2.0), Status::New));
+        // The backtest order ranks ahead, so it is filled before the feed order.
+        assert_eq!(
+            run(TiePriority::BacktestFirst)?,
+            (Some(1.0), Status::Filled)
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+
+use crate::{
+    depth::{INVALID_MAX, INVALID_MIN, MarketDepth},
+    types::{Order, OrderId, Side, Status},
+};
+
+/// The mid price move over the horizon after an order ends without being fully filled, measuring
+/// the profit and loss missed by not filling the unfilled quantity.
+#[derive(Clone, Debug)]
+pub struct OpportunityCost {
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The price of the order.
+    pub price: f64,
+    /// The quantity left unfilled when the order ends.
+    pub unfilled_qty: f64,
+    /// The terminal status of the order, either [`Status::Canceled`] or [`Status::Expired`].
+    pub status: Status,
+    /// The time at which the local receives the terminal response of the order.
+    pub terminal_ts: i64,
+    /// The mid price in the local's market depth at the end of the order. `NaN` if either side of
+    /// the market depth is empty.
+    pub terminal_mid: f64,
+    /// The mid price in the local's market depth at the end of the horizon. `NaN` if either side
+    /// of the market depth is empty.
+    pub horizon_mid: f64,
+}
+
+impl OpportunityCost {
+    /// Returns the mid price move over the horizon in the order's favor. A positive value means
+    /// that the mid price rises after a buy order ends or falls after a sell order ends.
+    pub fn mid_move(&self) -> f64 {
+        match self.side {
+            Side::Buy => self.horizon_mid - self.terminal_mid,
+            Side::Sell => self.terminal_mid - self.horizon_mid,
+            Side::None | Side::Unsupported => f64::NAN,
+        }
+    }
+
+    /// Returns the profit and loss that the unfilled quantity would have made if it had been
+    /// filled at the order's price, marked to the mid price at the end of the horizon. A positive
+    /// value is the missed profit, and a negative value is the avoided loss.
+    pub fn cost(&self) -> f64 {
+        match self.side {
+            Side::Buy => (self.horizon_mid - self.price) * self.unfilled_qty,
+            Side::Sell => (self.price - self.horizon_mid) * self.unfilled_qty,
+            Side::None | Side::Unsupported => f64::NAN,
+        }
+    }
+}
+
+/// The aggregate of the [`OpportunityCost`]s over a run. The orders without a valid mid price at
+/// the end of the order or the horizon are counted but excluded from the aggregate.
+#[derive(Clone, Debug, Default)]
+pub struct OpportunityCostSummary {
+    pub num_orders: usize,
+    /// The total unfilled quantity of the aggregated orders.
+    pub unfilled_qty: f64,
+    /// The total opportunity cost. See [`OpportunityCost::cost`].
+    pub total_cost: f64,
+    /// The average mid price move in the orders' favor. See [`OpportunityCost::mid_move`].
+    pub avg_mid_move: f64,
+}
+
+impl OpportunityCostSummary {
+    /// Aggregates the given opportunity costs.
+    pub fn new(costs: &[OpportunityCost]) -> Self {
+        let mut summary = Self {
+            num_orders: costs.len(),
+            ..Default::default()
+        };
+        let mut num_valid = 0;
+        let mut mid_move = 0.0;
+        for cost in costs {
+            if !cost.mid_move().is_finite() || !cost.cost().is_finite() {
+                continue;
+            }
+            num_valid += 1;
+            summary.unfilled_qty += cost.unfilled_qty;
+            summary.total_cost += cost.cost();
+            mid_move += cost.mid_move();
+        }
+        if num_valid > 0 {
+            summary.avg_mid_move = mid_move / num_valid as f64;
+        }
+        summary
+    }
+}
+
+pub(crate) fn mid<MD: MarketDepth>(depth: &MD) -> f64 {
+    let best_bid_tick = depth.best_bid_tick();
+    let best_ask_tick = depth.best_ask_tick();
+    if best_bid_tick == INVALID_MIN || best_ask_tick == INVALID_MAX {
+        f64::NAN
+    } else {
+        (best_bid_tick + best_ask_tick) as f64 * depth.tick_size() / 2.0
+    }
+}
+
+/// Tracks the mid price over the horizon after each order observed by the local ends canceled or
+/// expired with an unfilled quantity. An order is recorded once the local processes an event past
+/// the end of its horizon, so the orders whose horizon extends beyond the data are not recorded.
+#[derive(Default)]
+pub struct OpportunityCostRecorder {
+    horizon: i64,
+    pending: VecDeque<OpportunityCost>,
+    records: Vec<OpportunityCost>,
+}
+
+impl OpportunityCostRecorder {
+    /// Constructs an instance of `OpportunityCostRecorder` tracking the mid price over `horizon`
+    /// nanoseconds after each order ends. The recorder is enabled only if `horizon` is greater
+    /// than zero.
+    pub fn new(horizon: i64) -> Self {
+        Self {
+            horizon,
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if the recorder is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.horizon > 0
+    }
+
+    /// Starts the horizon of the order if the response ends it canceled or expired with an
+    /// unfilled quantity.
+    ///
+    /// * `prev_status` - The status of the local order before the response is applied, or `None`
+    ///   if the local has no such order.
+    /// * `local_order` - The local order after the response is applied.
+    pub fn on_response<MD: MarketDepth>(
+        &mut self,
+        prev_status: Option<Status>,
+        local_order: &Order,
+        depth: &MD,
+        timestamp: i64,
+    ) {
+        if !self.enabled()
+            || prev_status == Some(local_order.status)
+            || (local_order.status != Status::Canceled && local_order.status != Status::Expired)
+            || local_order.leaves_qty <= 0.0
+        {
+            return;
+        }
+        self.pending.push_back(OpportunityCost {
+            order_id: local_order.order_id,
+            side: local_order.side,
+            price: local_order.price(),
+            unfilled_qty: local_order.leaves_qty,
+            status: local_order.status,
+            terminal_ts: timestamp,
+            terminal_mid: mid(depth),
+            horizon_mid: f64::NAN,
+        });
+    }
+
+    /// Records the orders whose horizon ends before `timestamp`, with the mid price of the market
+    /// depth as of the end of the horizon. This must be invoked before the market depth is updated
+    /// by the event at `timestamp`.
+    pub fn on_time<MD: MarketDepth>(&mut self, depth: &MD, timestamp: i64) {
+        while let Some(cost) = self.pending.front() {
+            if cost.terminal_ts + self.horizon >= timestamp {
+                break;
+            }
+            let mut cost = self.pending.pop_front().unwrap();
+            cost.horizon_mid = mid(depth);
+            self.records.push(cost);
+        }
+    }
+
+    /// Returns the orders whose horizon has ended.
+    pub fn records(&self) -> &[OpportunityCost] {
+        self.records.as_slice()
+    }
+
+    /// Clears the orders whose horizon has ended.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, Status, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, CANCEL_ORDER_EVENT, EXCH_EVENT, LOCAL_EVENT, SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn opportunity_cost_of_canceled_order() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            // The market moves up after the backtest order is canceled.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                200,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                200,
+                105.0,
+                1.0,
+                3,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                250,
+                104.0,
+                1.0,
+                4,
+            ),
+            // It moves down again after the end of the horizon.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | CANCEL_ORDER_EVENT,
+                700,
+                104.0,
+                1.0,
+                4,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .opportunity_cost_horizon(500)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 99.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(69)?;
+        hbt.cancel(0, 10, true)?;
+        assert_eq!(hbt.current_timestamp(), 130);
+        // The horizon hasn't ended yet.
+        hbt.elapse(500)?;
+        assert!(hbt.opportunity_costs(0).is_empty());
+        hbt.elapse(370)?;
+
+        let costs = hbt.opportunity_costs(0);
+        assert_eq!(costs.len(), 1);
+        assert_eq!(costs[0].order_id, 10);
+        assert_eq!(costs[0].status, Status::Canceled);
+        assert_eq!(costs[0].unfilled_qty, 2.0);
+        assert_eq!(costs[0].terminal_ts, 130);
+        assert_eq!(costs[0].terminal_mid, 100.5);
+        // The mid price as of the end of the horizon at 630.
+        assert_eq!(costs[0].horizon_mid, 104.5);
+        assert_eq!(costs[0].mid_move(), 4.0);
+        // Buying 2 at 99 would have been marked at 104.5.
+        assert_eq!(costs[0].cost(), 11.0);
+
+        let summary = hbt.opportunity_cost_summary(0);
+        assert_eq!(summary.num_orders, 1);
+        assert_eq!(summary.unfilled_qty, 2.0);
+        assert_eq!(summary.total_cost, 11.0);
+        assert_eq!(summary.avg_mid_move, 4.0);
+
+        hbt.clear_opportunity_costs(None);
+        assert!(hbt.opportunity_costs(0).is_empty());
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
 use std::{cell::UnsafeCell, collections::VecDeque, rc::Rc};
 
-use crate::{backtest::models::LatencyModel, types::Order};
+use crate::{
+    backtest::models::LatencyModel,
+    types::{Order, OrderId},
+};
 
 /// Provides a bus for transporting backtesting orders between the exchange and the local model
 /// based on the given timestamp.
 #[derive(Clone, Debug, Default)]
 pub struct OrderBus {
     order_list: Rc<UnsafeCell<VecDeque<(Order, i64)>>>,
+    by_timestamp: bool,
 }
 
 impl OrderBus {
@@ -15,6 +19,15 @@ impl OrderBus {
         Default::default()
     }
 
+    /// Constructs an instance of ``OrderBus`` that delivers the orders strictly in the order of
+    /// their timestamps rather than in the order in which they are appended.
+    pub fn by_timestamp() -> Self {
+        Self {
+            by_timestamp: true,
+            ..Default::default()
+        }
+    }
+
     /// Returns the timestamp of the earliest order in the bus.
     pub fn earliest_timestamp(&self) -> Option<i64> {
         unsafe { &*self.order_list.get() }
@@ -30,8 +43,15 @@ impl OrderBus {
     /// In crypto exchanges that use REST APIs, it may be still possible for order requests sent
     /// later to reach the matching engine before order requests sent earlier. However, for the
     /// purpose of simplifying the backtesting process, all requests and responses are assumed to be
-    /// in order.
+    /// in order, unless the bus is constructed by [`OrderBus::by_timestamp`]. In that case, the
+    /// order is inserted at its timestamp, after the orders with the same timestamp.
     pub fn append(&mut self, order: Order, timestamp: i64) {
+        if self.by_timestamp {
+            let order_list = unsafe { &mut *self.order_list.get() };
+            let index = order_list.partition_point(|(_, ts)| *ts <= timestamp);
+            order_list.insert(index, (order, timestamp));
+            return;
+        }
         let latest_timestamp = {
             let order_list = unsafe { &*self.order_list.get() };
             let len = order_list.len();
@@ -65,6 +85,22 @@ impl OrderBus {
     pub fn pop_front(&mut self) -> Option<(Order, i64)> {
         unsafe { &mut *self.order_list.get() }.pop_front()
     }
+
+    /// Returns an iterator over the orders in the bus, from the earliest.
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        unsafe { &*self.order_list.get() }
+            .iter()
+            .map(|(order, _)| order)
+    }
+
+    /// Returns the latest order in the bus for the order ID, or ``None`` if there is none.
+    pub fn latest(&self, order_id: OrderId) -> Option<Order> {
+        unsafe { &*self.order_list.get() }
+            .iter()
+            .rev()
+            .find(|(order, _)| order.order_id == order_id)
+            .map(|(order, _)| order.clone())
+    }
 }
 
 /// Provides a bidirectional order bus connecting the exchange to the local.
@@ -95,6 +131,17 @@ where
         self.to_local.append(order, local_recv_timestamp);
     }
 
+    /// Returns the latest response for the order that has not yet been received by the local.
+    pub fn pending_response(&self, order_id: OrderId) -> Option<Order> {
+        self.to_local.latest(order_id)
+    }
+
+    /// Returns the order requests sent by the local that have not yet been received by the
+    /// exchange, from the earliest.
+    pub fn pending_requests(&self) -> impl Iterator<Item = &Order> {
+        self.to_exch.iter()
+    }
+
     /// Receives the order request from the local, which is expected to be received at
     /// `receipt_timestamp`.
     pub fn receive(&mut self, receipt_timestamp: i64) -> Option<Order> {
@@ -176,8 +223,28 @@ pub fn order_bus<LM>(order_latency: LM) -> (ExchToLocal<LM>, LocalToExch<LM>)
 where
     LM: LatencyModel + Clone,
 {
-    let to_exch = OrderBus::new();
-    let to_local = OrderBus::new();
+    connect(OrderBus::new(), OrderBus::new(), order_latency)
+}
+
+/// Creates bidirectional order buses with the order latency model, where the exchange receives
+/// the order requests strictly in the order of their receipt timestamps. A request sent later can
+/// reach the exchange first if its entry latency is shorter, as with a jittered latency model,
+/// whereas [`order_bus`] keeps the requests in the order in which they are sent.
+pub fn order_bus_by_receive_time<LM>(order_latency: LM) -> (ExchToLocal<LM>, LocalToExch<LM>)
+where
+    LM: LatencyModel + Clone,
+{
+    connect(OrderBus::by_timestamp(), OrderBus::new(), order_latency)
+}
+
+fn connect<LM>(
+    to_exch: OrderBus,
+    to_local: OrderBus,
+    order_latency: LM,
+) -> (ExchToLocal<LM>, LocalToExch<LM>)
+where
+    LM: LatencyModel + Clone,
+{
     (
         ExchToLocal {
             to_exch: to_exch.clone(),
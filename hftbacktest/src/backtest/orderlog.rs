@@ -0,0 +1,207 @@
+use crate::types::{OrdType, Order, OrderId, Side, Status, TimeInForce};
+
+/// Kind of an [`OrderLogEntry`].
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum OrderLogKind {
+    /// The order became passively resting in the order book.
+    Rested,
+    /// The order was executed, either fully or partially.
+    Fill,
+}
+
+/// An entry of the local order log.
+#[derive(Clone, Debug)]
+pub struct OrderLogEntry {
+    pub kind: OrderLogKind,
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The order price for [`OrderLogKind::Rested`], or the executed price for
+    /// [`OrderLogKind::Fill`].
+    pub price: f64,
+    /// The resting quantity for [`OrderLogKind::Rested`], or the executed quantity for
+    /// [`OrderLogKind::Fill`].
+    pub qty: f64,
+    /// Whether the order is executed as a maker, only meaningful for [`OrderLogKind::Fill`].
+    pub maker: bool,
+    /// The time at which the exchange processes the event.
+    pub exch_ts: i64,
+    /// The time at which the local receives the event.
+    pub local_ts: i64,
+}
+
+/// Records the order events, such as resting and fills, observed by the local from the order
+/// responses.
+#[derive(Default)]
+pub struct OrderLog {
+    entries: Vec<OrderLogEntry>,
+}
+
+impl OrderLog {
+    /// Constructs an instance of `OrderLog`. The order log is enabled only if `capacity` is
+    /// greater than zero.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns `true` if the order log is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.entries.capacity() > 0
+    }
+
+    /// Records the events conveyed by the order response received at `local_ts`.
+    ///
+    /// * `resp` - The order response from the exchange.
+    /// * `prev_req` - The request status of the local order before the response is applied.
+    pub fn on_response(&mut self, resp: &Order, mut prev_req: Status, local_ts: i64) {
+        if !self.enabled() {
+            return;
+        }
+        // With the two-phase acknowledgement, the acknowledgement of a new order is followed at
+        // the same exchange timestamp by the result of matching it, unless the order simply
+        // rests. An order filled as a taker, expired, or rejected on arrival has never rested, so
+        // the result supersedes the resting recorded from the acknowledgement.
+        if (resp.req == Status::Rejected
+            || resp.status == Status::Expired
+            || (resp.exec_qty > 0.0 && !resp.maker))
+            && self.entries.last().is_some_and(|last| {
+                last.kind == OrderLogKind::Rested
+                    && last.order_id == resp.order_id
+                    && last.exch_ts == resp.exch_timestamp
+            })
+        {
+            self.entries.pop();
+            prev_req = Status::New;
+        }
+        if resp.req == Status::Rejected {
+            return;
+        }
+        if resp.exec_qty > 0.0
+            && (resp.status == Status::Filled || resp.status == Status::PartiallyFilled)
+        {
+            self.entries.push(OrderLogEntry {
+                kind: OrderLogKind::Fill,
+                order_id: resp.order_id,
+                side: resp.side,
+                price: resp.exec_price(),
+                qty: resp.exec_qty,
+                maker: resp.maker,
+                exch_ts: resp.exch_timestamp,
+                local_ts,
+            });
+        }
+        // Only the response to a new order request can make the order rest for the first time, and
+        // only a GTC limit order rests after being partially filled on arrival. Otherwise, the
+        // partial fill is followed by the expiry of the remainder.
+        if prev_req == Status::New
+            && resp.leaves_qty > 0.0
+            && (resp.status == Status::New
+                || (resp.status == Status::PartiallyFilled
+                    && resp.order_type == OrdType::Limit
+                    && resp.time_in_force == TimeInForce::GTC))
+        {
+            self.entries.push(OrderLogEntry {
+                kind: OrderLogKind::Rested,
+                order_id: resp.order_id,
+                side: resp.side,
+                price: resp.price(),
+                qty: resp.leaves_qty,
+                maker: false,
+                exch_ts: resp.exch_timestamp,
+                local_ts,
+            });
+        }
+    }
+
+    /// Returns the recorded entries.
+    pub fn entries(&self) -> &[OrderLogEntry] {
+        self.entries.as_slice()
+    }
+
+    /// Clears the recorded entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            orderlog::OrderLogKind,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, TimeInForce},
+        types::{ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, EXCH_FILL_EVENT, LOCAL_EVENT, SELL_EVENT},
+    };
+
+    #[test]
+    fn order_log_records_rested_before_fill() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                20,
+                100.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_FILL_EVENT | BUY_EVENT, 50, 100.0, 1.0, 3),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 100, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .order_log_capacity(16)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        // The order rests behind the market-feed order 1, but ahead of the order 3.
+        hbt.submit_buy_order(0, 100, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(100)?;
+
+        let log = hbt.order_log(0);
+        assert_eq!(log.len(), 2);
+
+        let rested = &log[0];
+        assert_eq!(rested.kind, OrderLogKind::Rested);
+        assert_eq!(rested.order_id, 100);
+        assert_eq!(rested.exch_ts, 1 + 10);
+        assert_eq!(rested.local_ts, 1 + 10 + 20);
+
+        let fill = &log[1];
+        assert_eq!(fill.kind, OrderLogKind::Fill);
+        assert_eq!(fill.order_id, 100);
+        assert_eq!(fill.qty, 1.0);
+        assert_eq!(fill.exch_ts, 50);
+        assert_eq!(fill.local_ts, 50 + 20);
+        assert_eq!(fill.exch_ts - rested.exch_ts, 50 - (1 + 10));
+
+        hbt.clear_order_log(None);
+        assert!(hbt.order_log(0).is_empty());
+        Ok(())
+    }
+}
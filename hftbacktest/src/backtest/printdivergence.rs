@@ -0,0 +1,335 @@
+use std::collections::VecDeque;
+
+use crate::types::{Order, OrderId, Side};
+
+/// A fill compared against the nearest market print in the dataset, to validate the simulated
+/// fills against the actual trades.
+#[derive(Clone, Debug)]
+pub struct PrintDivergence {
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The executed price.
+    pub price: f64,
+    /// The executed quantity.
+    pub qty: f64,
+    /// The time at which the fill occurs on the exchange.
+    pub exch_ts: i64,
+    /// The price of the nearest market print. `NaN` if there is no print within the window.
+    pub print_price: f64,
+    /// The exchange timestamp of the nearest market print. `None` if there is no print within the
+    /// window.
+    pub print_ts: Option<i64>,
+}
+
+impl PrintDivergence {
+    /// Returns the time of the print relative to the fill. A positive value means that the print
+    /// occurs after the fill. `None` if there is no print within the window.
+    pub fn time_diff(&self) -> Option<i64> {
+        self.print_ts.map(|print_ts| print_ts - self.exch_ts)
+    }
+
+    /// Returns the price of the print relative to the executed price. `NaN` if there is no print
+    /// within the window.
+    pub fn price_diff(&self) -> f64 {
+        self.print_price - self.price
+    }
+}
+
+/// The aggregate of the [`PrintDivergence`]s over a run.
+#[derive(Clone, Debug, Default)]
+pub struct PrintDivergenceSummary {
+    pub num_fills: usize,
+    /// The number of the fills that have a print within the window.
+    pub num_matched: usize,
+    /// The number of the fills that have a print at the executed price within the window.
+    pub num_at_price: usize,
+    /// The average time of the prints relative to the matched fills.
+    pub avg_time_diff: f64,
+    /// The average absolute time difference between the matched fills and their prints.
+    pub avg_abs_time_diff: f64,
+    /// The largest absolute time difference between a matched fill and its print.
+    pub max_abs_time_diff: i64,
+    /// The average absolute price difference between the matched fills and their prints.
+    pub avg_abs_price_diff: f64,
+}
+
+impl PrintDivergenceSummary {
+    /// Aggregates the given divergences.
+    pub fn new(divergences: &[PrintDivergence]) -> Self {
+        let mut summary = Self {
+            num_fills: divergences.len(),
+            ..Default::default()
+        };
+        let mut time_diff = 0.0;
+        let mut abs_time_diff = 0.0;
+        let mut abs_price_diff = 0.0;
+        for divergence in divergences {
+            let Some(diff) = divergence.time_diff() else {
+                continue;
+            };
+            summary.num_matched += 1;
+            if divergence.price_diff().abs() < 1e-9 {
+                summary.num_at_price += 1;
+            }
+            time_diff += diff as f64;
+            abs_time_diff += diff.abs() as f64;
+            summary.max_abs_time_diff = summary.max_abs_time_diff.max(diff.abs());
+            abs_price_diff += divergence.price_diff().abs();
+        }
+        if summary.num_matched > 0 {
+            let n = summary.num_matched as f64;
+            summary.avg_time_diff = time_diff / n;
+            summary.avg_abs_time_diff = abs_time_diff / n;
+            summary.avg_abs_price_diff = abs_price_diff / n;
+        }
+        summary
+    }
+}
+
+/// Matches each fill in the exchange with the nearest market print within the window around the
+/// fill. The print at the executed price closest in time is preferred, and if there is none, the
+/// print closest in price is taken. A fill is matched once the prints up to the end of its window
+/// have been seen, or when the recorder is flushed.
+#[derive(Default)]
+pub struct PrintDivergenceRecorder {
+    window: i64,
+    prints: VecDeque<(i64, f64)>,
+    pending: VecDeque<(PrintDivergence, f64)>,
+    records: Vec<PrintDivergence>,
+}
+
+impl PrintDivergenceRecorder {
+    /// Constructs an instance of `PrintDivergenceRecorder` matching the prints within `window`
+    /// nanoseconds of each fill. The recorder is enabled only if `window` is greater than zero.
+    pub fn new(window: i64) -> Self {
+        Self {
+            window,
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if the recorder is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.window > 0
+    }
+
+    /// Records the fill of the order, which waits for the prints up to the end of its window.
+    pub fn on_fill(&mut self, order: &Order) {
+        if !self.enabled() {
+            return;
+        }
+        self.pending.push_back((
+            PrintDivergence {
+                order_id: order.order_id,
+                side: order.side,
+                price: order.exec_price(),
+                qty: order.exec_qty,
+                exch_ts: order.exch_timestamp,
+                print_price: f64::NAN,
+                print_ts: None,
+            },
+            order.tick_size,
+        ));
+    }
+
+    /// Records the market print, matching the fills whose window has ended before it.
+    pub fn on_print(&mut self, exch_ts: i64, price: f64) {
+        if !self.enabled() {
+            return;
+        }
+        while let Some((fill, _)) = self.pending.front() {
+            if fill.exch_ts + self.window >= exch_ts {
+                break;
+            }
+            let (fill, tick_size) = self.pending.pop_front().unwrap();
+            self.resolve(fill, tick_size);
+        }
+        self.prints.push_back((exch_ts, price));
+
+        // Keeps only the prints that can be within the window of a pending or a future fill.
+        let oldest_ts = self
+            .pending
+            .front()
+            .map_or(exch_ts, |(fill, _)| fill.exch_ts.min(exch_ts));
+        while let Some(&(print_ts, _)) = self.prints.front() {
+            if print_ts >= oldest_ts - self.window {
+                break;
+            }
+            self.prints.pop_front();
+        }
+    }
+
+    fn resolve(&mut self, mut fill: PrintDivergence, tick_size: f64) {
+        let nearest = self
+            .prints
+            .iter()
+            .filter(|(print_ts, _)| (print_ts - fill.exch_ts).abs() <= self.window)
+            .min_by_key(|(print_ts, print_price)| {
+                (
+                    ((print_price - fill.price) / tick_size).round().abs() as i64,
+                    (print_ts - fill.exch_ts).abs(),
+                )
+            })
+            .copied();
+        if let Some((print_ts, print_price)) = nearest {
+            fill.print_ts = Some(print_ts);
+            fill.print_price = print_price;
+        }
+        self.records.push(fill);
+    }
+
+    /// Matches the fills still waiting for the prints with the prints seen so far, as at the end
+    /// of the data.
+    pub fn flush(&mut self) {
+        while let Some((fill, tick_size)) = self.pending.pop_front() {
+            self.resolve(fill, tick_size);
+        }
+    }
+
+    /// Returns the matched fills.
+    pub fn records(&self) -> &[PrintDivergence] {
+        self.records.as_slice()
+    }
+
+    /// Clears the matched fills.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            printdivergence::{PrintDivergence, PrintDivergenceSummary},
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, EXCH_FILL_EVENT, LOCAL_EVENT, SELL_EVENT, Side,
+            TRADE_EVENT,
+        },
+    };
+
+    fn divergence(price: f64, exch_ts: i64, print: Option<(i64, f64)>) -> PrintDivergence {
+        PrintDivergence {
+            order_id: 0,
+            side: Side::Buy,
+            price,
+            qty: 1.0,
+            exch_ts,
+            print_price: print.map_or(f64::NAN, |(_, price)| price),
+            print_ts: print.map(|(print_ts, _)| print_ts),
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let divergences = [
+            divergence(100.0, 100, Some((130, 100.0))),
+            divergence(100.0, 200, Some((150, 101.0))),
+            divergence(100.0, 300, None),
+        ];
+        let summary = PrintDivergenceSummary::new(&divergences);
+        assert_eq!(summary.num_fills, 3);
+        assert_eq!(summary.num_matched, 2);
+        assert_eq!(summary.num_at_price, 1);
+        assert_eq!(summary.avg_time_diff, -10.0);
+        assert_eq!(summary.avg_abs_time_diff, 40.0);
+        assert_eq!(summary.max_abs_time_diff, 50);
+        assert_eq!(summary.avg_abs_price_diff, 0.5);
+    }
+
+    #[test]
+    fn print_divergence_against_market_prints() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                20,
+                100.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_FILL_EVENT | BUY_EVENT, 50, 100.0, 1.0, 3),
+            l3_event(EXCH_EVENT | TRADE_EVENT | BUY_EVENT, 60, 100.0, 1.0, 0),
+            l3_event(EXCH_EVENT | TRADE_EVENT | SELL_EVENT, 120, 100.0, 1.0, 0),
+            l3_event(EXCH_EVENT | TRADE_EVENT | BUY_EVENT, 135, 101.0, 1.0, 0),
+            l3_event(EXCH_EVENT | TRADE_EVENT | SELL_EVENT, 230, 99.0, 1.0, 0),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 300, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .print_divergence_window(30)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        // Filled passively at 50 by the market fill of the order behind it.
+        hbt.submit_buy_order(0, 100, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(99)?;
+        // Filled aggressively at 110, while the print at the price comes at 135.
+        hbt.submit_buy_order(0, 101, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(100)?;
+        // Filled aggressively at 210, while the only print in the window is a tick away.
+        hbt.submit_sell_order(0, 102, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(100)?;
+
+        // The last fill is still waiting for the prints until the end of its window.
+        assert_eq!(hbt.print_divergences(0).len(), 2);
+        hbt.close()?;
+
+        let divergences = hbt.print_divergences(0);
+        assert_eq!(divergences.len(), 3);
+
+        assert_eq!(divergences[0].order_id, 100);
+        assert_eq!(divergences[0].exch_ts, 50);
+        assert_eq!(divergences[0].print_ts, Some(50));
+        assert_eq!(divergences[0].price_diff(), 0.0);
+
+        assert_eq!(divergences[1].order_id, 101);
+        assert_eq!(divergences[1].exch_ts, 110);
+        assert_eq!(divergences[1].time_diff(), Some(25));
+        assert_eq!(divergences[1].price_diff(), 0.0);
+
+        assert_eq!(divergences[2].order_id, 102);
+        assert_eq!(divergences[2].exch_ts, 210);
+        assert_eq!(divergences[2].time_diff(), Some(20));
+        assert_eq!(divergences[2].price_diff(), -1.0);
+
+        let summary = hbt.print_divergence_summary(0);
+        assert_eq!(summary.num_fills, 3);
+        assert_eq!(summary.num_matched, 3);
+        assert_eq!(summary.num_at_price, 2);
+        assert_eq!(summary.avg_time_diff, 15.0);
+        assert_eq!(summary.avg_abs_time_diff, 15.0);
+        assert_eq!(summary.max_abs_time_diff, 25);
+        assert_eq!(summary.avg_abs_price_diff, 1.0 / 3.0);
+
+        hbt.clear_print_divergences(None);
+        assert!(hbt.print_divergences(0).is_empty());
+        Ok(())
+    }
+}
@@ -1,26 +1,68 @@
-use std::collections::{HashMap, hash_map::Entry};
+use std::{
+    collections::{HashMap, HashSet, hash_map::Entry},
+    io::Error as IoError,
+    path::PathBuf,
+};
 
 use uuid::timestamp;
 
 use crate::{
     backtest::{
         BacktestError,
+        adverseselection::{AdverseSelection, AdverseSelectionRecorder},
         assettype::AssetType,
+        auction::{AuctionFill, AuctionResult},
+        audit::AuditRecorder,
+        lifetimevwap::{LifetimeVwap, LifetimeVwapRecorder},
+        marktomarket::{MarkFrequency, MarkToMarket},
         models::{FeeModel, LatencyModel},
+        opportunitycost::{OpportunityCost, OpportunityCostRecorder},
         order::LocalToExch,
-        proc::{LocalProcessor, Processor},
-        state::State,
+        orderlog::{OrderLog, OrderLogEntry},
+        proc::{
+            FeedFillDedup, LocalProcessor, OrderOptions, Processor, allocate_auction_fills,
+            has_both_sides, offset_feed_order_id,
+        },
+        resilience::{ResilienceRecorder, SweepRecovery, level_qty},
+        state::{PnlSummary, State},
+        timeline::{OrderTimelineEntry, OrderTimelineRecorder},
+        touchdistance::{TouchDistanceRecorder, TouchDistanceStats},
     },
-    depth::{L3MarketDepth, L3Order},
+    depth::{L3MarketDepth, L3Order, MidHistory},
     types::{
-        AUCTION_UPDATE_EVENT, DEPTH_CLEAR_EVENT, Event, LOCAL_ASK_ADD_ORDER_EVENT,
-        LOCAL_ASK_DEPTH_CLEAR_EVENT, LOCAL_BID_ADD_ORDER_EVENT, LOCAL_BID_DEPTH_CLEAR_EVENT,
-        LOCAL_CANCEL_ORDER_EVENT, LOCAL_DEPTH_CLEAR_EVENT, LOCAL_EVENT, LOCAL_FILL_EVENT,
-        LOCAL_MODIFY_ORDER_EVENT, LOCAL_TRADE_EVENT, OrdType, Order, OrderId, Side, StateValues,
-        Status, TimeInForce,
+        AUCTION_UPDATE_EVENT, AssetParams, BUY_EVENT, DEPTH_CLEAR_EVENT, Event,
+        LOCAL_ASK_ADD_ORDER_EVENT, LOCAL_ASK_DEPTH_CLEAR_EVENT, LOCAL_BID_ADD_ORDER_EVENT,
+        LOCAL_BID_DEPTH_CLEAR_EVENT, LOCAL_CANCEL_ORDER_EVENT, LOCAL_DEPTH_CLEAR_EVENT,
+        LOCAL_EVENT, LOCAL_FILL_EVENT, LOCAL_MODIFY_ORDER_EVENT, LOCAL_TRADE_EVENT, OrdType, Order,
+        OrderId, SELL_EVENT, Side, StateValues, Status, TimeInForce,
     },
 };
 
+/// Determines how a modify event is handled when it targets a market-feed order that is not in
+/// the order book, for example, because the order was outside the range of interest.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum MissingOrderPolicy {
+    /// Returns [`BacktestError::OrderNotFound`], aborting the backtest.
+    #[default]
+    Error,
+    /// Ignores the modify event.
+    Ignore,
+    /// Adds the order to the order book as a new order. The side is determined by
+    /// [`BUY_EVENT`] or [`SELL_EVENT`] flag of the event; if neither is set, the event is ignored.
+    TreatAsAdd,
+}
+
+/// Determines how an order is handled when its price is outside the range of interest of the
+/// market depth, where the market depth can't track the queue at the price.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum OutOfRoiPolicy {
+    /// Rejects the order on submission with [`BacktestError::OutOfRoi`].
+    #[default]
+    Reject,
+    /// Submits the order as it is.
+    Allow,
+}
+
 /// The Level3 Market-By-Order local model.
 pub struct L3Local<AT, LM, MD, FM>
 where
@@ -36,6 +78,32 @@ where
     trades: Vec<Event>,
     last_feed_latency: Option<(i64, i64)>,
     last_order_latency: Option<(i64, i64, i64)>,
+    order_log: OrderLog,
+    lifetime_vwap: LifetimeVwapRecorder,
+    opportunity_cost: OpportunityCostRecorder,
+    adverse_selection: AdverseSelectionRecorder,
+    resilience: ResilienceRecorder,
+    order_timeline: OrderTimelineRecorder,
+    audit: AuditRecorder,
+    touch_distance: TouchDistanceRecorder,
+    mid_history: MidHistory,
+    mark_to_market: MarkToMarket,
+    book_established_ts: Option<i64>,
+    last_fill_ts: Option<i64>,
+    missing_order_policy: MissingOrderPolicy,
+    out_of_roi_policy: OutOfRoiPolicy,
+    fill_counterparty: bool,
+    fill_dedup: FeedFillDedup,
+    // The market feed orders deleted by the fills that fully filled them, pending their delete
+    // events.
+    filled_orders: HashSet<OrderId>,
+    feed_order_id_offset: u64,
+    // The exchange timestamp and the price in ticks of the last auction reconciled with the
+    // market depth.
+    last_auction: Option<(i64, i64)>,
+    auction: Option<AuctionResult>,
+    // The quantities matched in the last auction on the bid side and the ask side.
+    auction_matched_qty: (f64, f64),
 }
 
 impl<AT, LM, MD, FM> L3Local<AT, LM, MD, FM>
@@ -60,8 +128,277 @@ where
             trades: Vec::with_capacity(trade_len),
             last_feed_latency: None,
             last_order_latency: None,
+            order_log: Default::default(),
+            lifetime_vwap: Default::default(),
+            opportunity_cost: Default::default(),
+            adverse_selection: Default::default(),
+            resilience: Default::default(),
+            order_timeline: Default::default(),
+            audit: Default::default(),
+            touch_distance: Default::default(),
+            mid_history: Default::default(),
+            mark_to_market: Default::default(),
+            book_established_ts: None,
+            last_fill_ts: None,
+            missing_order_policy: Default::default(),
+            out_of_roi_policy: Default::default(),
+            fill_counterparty: true,
+            fill_dedup: Default::default(),
+            filled_orders: Default::default(),
+            feed_order_id_offset: 0,
+            last_auction: None,
+            auction: None,
+            auction_matched_qty: (0.0, 0.0),
+        }
+    }
+
+    /// Sets the initial capacity of the order log. The default value is `0`, indicating that no
+    /// order log is stored.
+    pub fn order_log_capacity(self, capacity: usize) -> Self {
+        Self {
+            order_log: OrderLog::new(capacity),
+            ..self
+        }
+    }
+
+    /// Sets the number of the most recently submitted orders whose lifecycle timelines are kept.
+    /// The default value is `0`, indicating that no timeline is recorded.
+    pub fn order_timeline_capacity(self, capacity: usize) -> Self {
+        Self {
+            order_timeline: OrderTimelineRecorder::new(capacity),
+            ..self
+        }
+    }
+
+    /// Enables the audit trail of the order actions, which is written to `path` in JSON Lines
+    /// format, buffering up to `capacity` records. See [`AuditRecorder`].
+    pub fn audit_log(self, path: impl Into<PathBuf>, capacity: usize) -> Self {
+        Self {
+            audit: AuditRecorder::new(path, capacity),
+            ..self
+        }
+    }
+
+    /// Sets the number of the most recent mid prices kept, which are sampled on each change of the
+    /// best bid or the best ask. The default value is `0`, indicating that no mid price is kept.
+    pub fn mid_history_capacity(self, capacity: usize) -> Self {
+        Self {
+            mid_history: MidHistory::new(capacity),
+            ..self
         }
     }
+
+    /// Sets whether the outcomes of the orders are counted by the distance from the touch at which
+    /// each order is submitted. The default value is `false`.
+    pub fn touch_distance_stats(self, enabled: bool) -> Self {
+        Self {
+            touch_distance: TouchDistanceRecorder::new(enabled),
+            ..self
+        }
+    }
+
+    /// Sets the initial capacity of the lifetime VWAP records, which compare each order's fills
+    /// against the market VWAP over its lifetime. The default value is `0`, indicating that the
+    /// lifetime VWAP is not recorded.
+    pub fn lifetime_vwap_capacity(self, capacity: usize) -> Self {
+        Self {
+            lifetime_vwap: LifetimeVwapRecorder::new(capacity),
+            ..self
+        }
+    }
+
+    /// Sets the horizon in nanoseconds over which the mid price is tracked after each order ends
+    /// canceled or expired with an unfilled quantity, to measure the opportunity cost of not
+    /// filling it. The default value is `0`, indicating that the opportunity cost is not recorded.
+    pub fn opportunity_cost_horizon(self, horizon: i64) -> Self {
+        Self {
+            opportunity_cost: OpportunityCostRecorder::new(horizon),
+            ..self
+        }
+    }
+
+    /// Sets the horizon in nanoseconds over which the mid price is tracked after each maker fill,
+    /// to measure how adversely the fills are selected. The default value is `0`, indicating that
+    /// the adverse selection is not recorded.
+    pub fn adverse_selection_horizon(self, horizon: i64) -> Self {
+        Self {
+            adverse_selection: AdverseSelectionRecorder::new(horizon),
+            ..self
+        }
+    }
+
+    /// Sets the fraction of the quantity before a sweep that a price level emptied by the market
+    /// feed fills must recover to, to measure the resilience of the order book. The default value
+    /// is `0`, indicating that the resilience is not recorded.
+    pub fn resilience_recovery_fraction(self, recovery_fraction: f64) -> Self {
+        Self {
+            resilience: ResilienceRecorder::new(recovery_fraction),
+            ..self
+        }
+    }
+
+    /// Sets how often the held position is re-marked to the mid price, which determines the
+    /// equity in the state samples and the maximum drawdown. The default value is
+    /// [`MarkFrequency::BboChange`].
+    pub fn mark_frequency(self, frequency: MarkFrequency) -> Self {
+        Self {
+            mark_to_market: MarkToMarket::new(frequency),
+            ..self
+        }
+    }
+
+    /// Sets how a modify event for a market-feed order that is not in the order book is handled.
+    /// The default value is [`MissingOrderPolicy::Error`].
+    pub fn missing_order_policy(self, missing_order_policy: MissingOrderPolicy) -> Self {
+        Self {
+            missing_order_policy,
+            ..self
+        }
+    }
+
+    /// Sets how an order priced outside the range of interest of the market depth is handled on
+    /// submission. A market order is not checked. The default value is [`OutOfRoiPolicy::Reject`].
+    pub fn out_of_roi_policy(self, out_of_roi_policy: OutOfRoiPolicy) -> Self {
+        Self {
+            out_of_roi_policy,
+            ..self
+        }
+    }
+
+    /// Sets whether the fill events in the feed data provide the counterparty order ID in
+    /// [`Event::ival`]. If enabled, the quantity of the counterparty order is also reduced by the
+    /// fill; otherwise, only the order identified by [`Event::order_id`] is reduced.
+    /// The default value is `true`.
+    pub fn fill_counterparty(self, fill_counterparty: bool) -> Self {
+        Self {
+            fill_counterparty,
+            ..self
+        }
+    }
+
+    /// Sets the window in nanoseconds of exchange time within which a market feed fill event
+    /// repeating a previous fill's exchange timestamp, price, quantity, and order ID is ignored as
+    /// a duplicate. It should be the same for the local and the exchange of the asset. The default
+    /// value is `0`, indicating that every fill event is processed.
+    pub fn fill_dedup_window(self, fill_dedup_window: i64) -> Self {
+        Self {
+            fill_dedup: FeedFillDedup::new(fill_dedup_window),
+            ..self
+        }
+    }
+
+    /// Sets the offset added to the order IDs of the market feed events at ingestion, so that the
+    /// market feed order IDs can't collide with the backtest order IDs. It should be the same for
+    /// the local and the exchange of the asset. The default value is `0`, indicating that the
+    /// order IDs are used as they are.
+    pub fn feed_order_id_offset(self, feed_order_id_offset: u64) -> Self {
+        Self {
+            feed_order_id_offset,
+            ..self
+        }
+    }
+
+    fn process_modify_order(&mut self, ev: &Event) -> Result<(), BacktestError>
+    where
+        BacktestError: From<<MD as L3MarketDepth>::Error>,
+    {
+        if self.depth.orders().contains_key(&ev.order_id) {
+            self.depth
+                .modify_order(ev.order_id, ev.px, ev.qty, ev.local_ts)?;
+            return Ok(());
+        }
+        match self.missing_order_policy {
+            MissingOrderPolicy::Error => return Err(BacktestError::OrderNotFound),
+            MissingOrderPolicy::Ignore => {}
+            MissingOrderPolicy::TreatAsAdd => {
+                if ev.is(BUY_EVENT) {
+                    self.depth
+                        .add_buy_order(ev.order_id, ev.px, ev.qty, ev.local_ts)?;
+                } else if ev.is(SELL_EVENT) {
+                    self.depth
+                        .add_sell_order(ev.order_id, ev.px, ev.qty, ev.local_ts)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reduces the market feed order by the fill quantity, which deletes the order once it is
+    /// fully filled. Returns the side, the price in ticks, and the quantity at the price before the
+    /// fill, or `None` if the order has already been deleted by a previous fill, in which case the
+    /// fill is ignored.
+    fn fill_feed_order(
+        &mut self,
+        order_id: OrderId,
+        qty: f64,
+        timestamp: i64,
+    ) -> Result<Option<(Side, i64, f64)>, BacktestError>
+    where
+        BacktestError: From<<MD as L3MarketDepth>::Error>,
+    {
+        let Some(order) = self.depth.orders().get(&order_id) else {
+            return if self.filled_orders.contains(&order_id) {
+                Ok(None)
+            } else {
+                Err(BacktestError::OrderNotFound)
+            };
+        };
+        let (side, price_tick) = (order.side, order.price_tick);
+        let prev_level_qty = level_qty(&self.depth, side, price_tick);
+        let remaining_qty = order.qty - qty;
+        self.depth.modify_order(
+            order_id,
+            price_tick as f64 * self.depth.tick_size(),
+            remaining_qty,
+            timestamp,
+        )?;
+        if !self.depth.orders().contains_key(&order_id) {
+            self.filled_orders.insert(order_id);
+        }
+        Ok(Some((side, price_tick, prev_level_qty)))
+    }
+}
+
+impl<AT, LM, MD, FM> L3Local<AT, LM, MD, FM>
+where
+    AT: AssetType,
+    LM: LatencyModel,
+    MD: L3MarketDepth,
+    FM: FeeModel,
+{
+    /// Adds the quantity matched on the side to the auction uncrossed at `price_tick` at
+    /// `exch_ts`, starting a new auction result if it differs from the last auction. Since the
+    /// market depth lacks the backtest orders and may diverge from the exchange's, the matched
+    /// quantity of the auction is the larger of the quantities matched on either side.
+    fn add_auction_matched_qty(
+        &mut self,
+        exch_ts: i64,
+        price_tick: i64,
+        side: Side,
+        qty: f64,
+    ) -> &mut AuctionResult {
+        let price = price_tick as f64 * self.depth.tick_size();
+        if self
+            .auction
+            .as_ref()
+            .is_none_or(|auction| auction.exch_ts != exch_ts || auction.price != price)
+        {
+            self.auction = Some(AuctionResult {
+                price,
+                exch_ts,
+                matched_qty: 0.0,
+                fills: Vec::new(),
+            });
+            self.auction_matched_qty = (0.0, 0.0);
+        }
+        match side {
+            Side::Buy => self.auction_matched_qty.0 += qty,
+            _ => self.auction_matched_qty.1 += qty,
+        }
+        let auction = self.auction.as_mut().unwrap();
+        auction.matched_qty = self.auction_matched_qty.0.max(self.auction_matched_qty.1);
+        auction
+    }
 }
 
 impl<AT, LM, MD, FM> LocalProcessor<MD> for L3Local<AT, LM, MD, FM>
@@ -80,13 +417,24 @@ where
         qty: f64,
         order_type: OrdType,
         time_in_force: TimeInForce,
+        options: OrderOptions,
         current_timestamp: i64,
     ) -> Result<(), BacktestError> {
         if self.orders.contains_key(&order_id) {
             return Err(BacktestError::OrderIdExist);
         }
+        if self.state.notional_limit_reached {
+            return Err(BacktestError::NotionalLimitReached);
+        }
 
         let price_tick = (price / self.depth.tick_size()).round() as i64;
+        if self.out_of_roi_policy == OutOfRoiPolicy::Reject && order_type != OrdType::Market {
+            if let Some((roi_lb, roi_ub)) = self.depth.roi_tick_range() {
+                if price_tick < roi_lb || price_tick > roi_ub {
+                    return Err(BacktestError::OutOfRoi);
+                }
+            }
+        }
         let mut order = Order::new(
             order_id,
             price_tick,
@@ -98,6 +446,12 @@ where
         );
         order.req = Status::New;
         order.local_timestamp = current_timestamp;
+        order.max_lifetime = options.max_lifetime;
+        order.opening_auction = options.opening_auction;
+        self.lifetime_vwap.on_submit(&order, current_timestamp);
+        self.order_timeline.on_submit(&order, current_timestamp);
+        self.audit.on_submit(&order, current_timestamp)?;
+        self.touch_distance.on_submit(&order, &self.depth);
         self.orders.insert(order.order_id, order.clone());
 
         self.order_l2e.request(order, |order| {
@@ -132,6 +486,8 @@ where
 
         order.req = Status::Replaced;
         order.local_timestamp = current_timestamp;
+        self.order_timeline.on_modify(order, current_timestamp);
+        self.audit.on_modify(order, current_timestamp)?;
 
         self.order_l2e.request(order.clone(), |order| {
             order.req = Status::Rejected;
@@ -154,6 +510,8 @@ where
 
         order.req = Status::Canceled;
         order.local_timestamp = current_timestamp;
+        self.order_timeline.on_cancel(order, current_timestamp);
+        self.audit.on_cancel(order, current_timestamp)?;
 
         self.order_l2e.request(order.clone(), |order| {
             order.req = Status::Rejected;
@@ -178,6 +536,31 @@ where
         self.state.values()
     }
 
+    fn asset_params(&self) -> AssetParams {
+        self.state
+            .asset_params(self.depth.tick_size(), self.depth.lot_size())
+    }
+
+    fn pnl(&self, timestamp: i64) -> PnlSummary {
+        self.state.pnl(
+            (self.depth.best_bid() + self.depth.best_ask()) / 2.0,
+            timestamp,
+        )
+    }
+
+    fn marked_pnl(&self, timestamp: i64) -> PnlSummary {
+        match self.mark_to_market.frequency() {
+            MarkFrequency::BboChange => self.pnl(timestamp),
+            MarkFrequency::Interval(_) | MarkFrequency::OnFill => {
+                self.state.pnl(self.state.mark_price, timestamp)
+            }
+        }
+    }
+
+    fn max_drawdown(&self) -> Option<f64> {
+        Some(self.mark_to_market.max_drawdown())
+    }
+
     fn depth(&self) -> &MD {
         &self.depth
     }
@@ -201,6 +584,74 @@ where
     fn order_latency(&self) -> Option<(i64, i64, i64)> {
         self.last_order_latency
     }
+
+    fn order_log(&self) -> &[OrderLogEntry] {
+        self.order_log.entries()
+    }
+
+    fn clear_order_log(&mut self) {
+        self.order_log.clear();
+    }
+
+    fn lifetime_vwaps(&self) -> &[LifetimeVwap] {
+        self.lifetime_vwap.records()
+    }
+
+    fn clear_lifetime_vwaps(&mut self) {
+        self.lifetime_vwap.clear();
+    }
+
+    fn opportunity_costs(&self) -> &[OpportunityCost] {
+        self.opportunity_cost.records()
+    }
+
+    fn clear_opportunity_costs(&mut self) {
+        self.opportunity_cost.clear();
+    }
+
+    fn adverse_selections(&self) -> &[AdverseSelection] {
+        self.adverse_selection.records()
+    }
+
+    fn clear_adverse_selections(&mut self) {
+        self.adverse_selection.clear();
+    }
+
+    fn sweep_recoveries(&self) -> &[SweepRecovery] {
+        self.resilience.records()
+    }
+
+    fn clear_sweep_recoveries(&mut self) {
+        self.resilience.clear();
+    }
+
+    fn order_timeline(&self, order_id: OrderId) -> &[OrderTimelineEntry] {
+        self.order_timeline.timeline(order_id)
+    }
+
+    fn flush_audit_log(&mut self) -> Result<(), IoError> {
+        self.audit.flush()
+    }
+
+    fn touch_distance_stats(&self) -> &[TouchDistanceStats] {
+        self.touch_distance.stats()
+    }
+
+    fn clear_touch_distance_stats(&mut self) {
+        self.touch_distance.clear();
+    }
+
+    fn mid_history(&self, since: i64) -> Vec<(i64, f64)> {
+        self.mid_history.since(since)
+    }
+
+    fn book_established_timestamp(&self) -> Option<i64> {
+        self.book_established_ts
+    }
+
+    fn last_fill_timestamp(&self) -> Option<i64> {
+        self.last_fill_ts
+    }
 }
 
 impl<AT, LM, MD, FM> Processor for L3Local<AT, LM, MD, FM>
@@ -216,12 +667,18 @@ where
     }
 
     fn process(&mut self, ev: &Event) -> Result<(), BacktestError> {
+        let ev = offset_feed_order_id(ev, self.feed_order_id_offset);
+        let ev = &*ev;
+
         if !ev.is(AUCTION_UPDATE_EVENT) {
             self.depth.set_allow_price_cross(false);
         } else if ev.is(AUCTION_UPDATE_EVENT) {
             self.depth.set_allow_price_cross(true);
         }
 
+        self.opportunity_cost.on_time(&self.depth, ev.local_ts);
+        self.adverse_selection.on_time(&self.depth, ev.local_ts);
+
         // Processes a depth event
         if ev.is(LOCAL_BID_DEPTH_CLEAR_EVENT) {
             self.depth.clear_orders(Side::Buy);
@@ -236,53 +693,55 @@ where
             self.depth
                 .add_sell_order(ev.order_id, ev.px, ev.qty, ev.local_ts)?;
         } else if ev.is(LOCAL_MODIFY_ORDER_EVENT) {
-            self.depth
-                .modify_order(ev.order_id, ev.px, ev.qty, ev.local_ts)?;
+            self.process_modify_order(ev)?;
         } else if ev.is(LOCAL_CANCEL_ORDER_EVENT) {
             // println!("DELETE {:?}", ev);
-            self.depth.delete_order(ev.order_id, ev.local_ts)?;
-        } else if !ev.is(AUCTION_UPDATE_EVENT) && ev.is(LOCAL_FILL_EVENT) {
+            // The market feed may send the delete after the fill that has already deleted the
+            // order.
+            if self.depth.orders().contains_key(&ev.order_id)
+                || !self.filled_orders.remove(&ev.order_id)
+            {
+                self.depth.delete_order(ev.order_id, ev.local_ts)?;
+            }
+        } else if !ev.is(AUCTION_UPDATE_EVENT)
+            && ev.is(LOCAL_FILL_EVENT)
+            && !self.fill_dedup.is_duplicate(ev)
+        {
             // println!("FILL {:?}", ev);
-            let order1 = self
-                .depth
-                .orders()
-                .get(&ev.order_id)
-                .ok_or(BacktestError::OrderNotFound)?;
-
-            // println!("order1 found {:?}", order1);
-
-            let remaining_qty = order1.qty - ev.qty;
-            // println!("remaining qty {:?}", remaining_qty);
-            // println!("curr price {:?}", order1.price_tick as f64 * self.depth.tick_size());
-            self.depth.modify_order(
-                ev.order_id,
-                order1.price_tick as f64 * self.depth.tick_size(),
-                remaining_qty,
-                ev.local_ts,
-            )?;
-
-            let ival_u64 = ev.ival as u64;
-            let order2 = self
-                .depth
-                .orders()
-                .get(&ival_u64)
-                .ok_or(BacktestError::OrderNotFound)?;
-
-            // println!("order2 found {:?}", order2);
-
-            let remaining_qty_2 = order2.qty - ev.qty;
-            // println!("remaining qty 2 {:?}", remaining_qty_2);
-            self.depth.modify_order(
-                order2.order_id,
-                order2.price_tick as f64 * self.depth.tick_size(),
-                remaining_qty_2,
-                ev.local_ts,
-            )?;
+            self.lifetime_vwap.on_print(ev.px, ev.qty);
+            if let Some((side, price_tick, prev_level_qty)) =
+                self.fill_feed_order(ev.order_id, ev.qty, ev.local_ts)?
+            {
+                self.resilience
+                    .on_fill(side, price_tick, prev_level_qty, &self.depth, ev.local_ts);
+            }
 
+            // The counterparty order ID is provided only if the dataset populates it.
+            if self.fill_counterparty {
+                let ival_u64 = (ev.ival as u64).wrapping_add(self.feed_order_id_offset);
+                self.fill_feed_order(ival_u64, ev.qty, ev.local_ts)?;
+            }
         }
         // Processes a trade event
-        else if ev.is(LOCAL_TRADE_EVENT) && self.trades.capacity() > 0 {
-            self.trades.push(ev.clone());
+        else if ev.is(LOCAL_TRADE_EVENT) {
+            self.lifetime_vwap.on_print(ev.px, ev.qty);
+            if self.trades.capacity() > 0 {
+                self.trades.push(ev.clone());
+            }
+        }
+
+        self.mid_history.update(&self.depth, ev.local_ts);
+        self.resilience.on_depth(&self.depth, ev.local_ts);
+        if has_both_sides(&self.depth) {
+            if self.mark_to_market.on_depth_update(ev.local_ts) {
+                self.state
+                    .mark_to_market((self.depth.best_bid() + self.depth.best_ask()) / 2.0);
+                self.mark_to_market
+                    .update_drawdown(self.state.marked_equity());
+            }
+            if self.book_established_ts.is_none() {
+                self.book_established_ts = Some(ev.local_ts);
+            }
         }
 
         // Stores the current feed latency
@@ -299,12 +758,34 @@ where
         // Processes the order part.
         let mut wait_resp_order_received = false;
         while let Some(order) = self.order_l2e.receive(timestamp) {
+            // The auction fill of a backtest order is attributed to the auction and then applied
+            // like any other fill.
+            if order.is_auction && self.orders.contains_key(&order.order_id) {
+                let auction = self.add_auction_matched_qty(
+                    order.exch_timestamp,
+                    order.exec_price_tick,
+                    order.side,
+                    order.exec_qty,
+                );
+                auction.fills.push(AuctionFill {
+                    order_id: order.order_id,
+                    side: order.side,
+                    matched_qty: order.exec_qty,
+                    leaves_qty: order.leaves_qty,
+                    status: order.status,
+                });
+            }
             // 收到 is_auction order 更新 depth
             // qty < 0 ask 剩余，qty > 0 bid 剩余
-            if order.is_auction {
-                println!("=============================");
-                println!("local auction price: {}", order.exec_price());
-                println!("local auction qty: {}", order.qty);
+            // The exchange responds for every market feed order matched in the auction, but the
+            // market depth is reconciled only once per auction. The response only conveys the
+            // auction to the local and is not an order response.
+            else if order.is_auction {
+                let auction = (order.exch_timestamp, order.exec_price_tick);
+                if self.last_auction == Some(auction) {
+                    continue;
+                }
+                self.last_auction = Some(auction);
 
                 let auction_price = order.exec_price();
                 let auction_price_tick = (auction_price / self.depth.tick_size()).round() as i64;
@@ -313,6 +794,7 @@ where
 
                 // 1. 全部删除订单
                 let mut orders_to_delete = Vec::new();
+                let mut matched_qty = (0.0, 0.0);
 
                 for (order_id, order_info) in self.depth.orders() {
                     let should_delete = match order_info.side {
@@ -345,6 +827,10 @@ where
 
                     if should_delete {
                         orders_to_delete.push(*order_id);
+                        match order_info.side {
+                            Side::Buy => matched_qty.0 += order_info.qty,
+                            _ => matched_qty.1 += order_info.qty,
+                        }
                     }
                 }
 
@@ -378,38 +864,62 @@ where
                     total_qty += l3order.qty;
                 }
                 let need_to_fill = total_qty - auction_qty.abs();
-                let mut already_filled = 0.0;
+                let qtys = at_auction_price
+                    .iter()
+                    .map(|(_, l3order)| l3order.qty)
+                    .collect::<Vec<_>>();
+                // 这个订单需要成交的量
+                let fill_qtys = allocate_auction_fills(&qtys, need_to_fill, self.depth.lot_size());
 
-                let mut order_to_modify: Option<(OrderId, f64, f64)> = None;
+                let mut orders_to_readd = Vec::new();
                 let mut orders_to_delete = Vec::new();
 
-                for (id, l3order) in at_auction_price {
-                    if already_filled >= need_to_fill {
-                        break;
+                for ((id, l3order), order_fill_qty) in at_auction_price.into_iter().zip(fill_qtys) {
+                    match side {
+                        Side::Buy => matched_qty.0 += order_fill_qty.clamp(0.0, l3order.qty),
+                        _ => matched_qty.1 += order_fill_qty.clamp(0.0, l3order.qty),
                     }
-                    // 这个订单需要成交的量
-                    let order_fill_qty = (need_to_fill - already_filled).min(order.leaves_qty);
-                    already_filled += order_fill_qty;
-
                     if order_fill_qty >= l3order.qty {
                         orders_to_delete.push(id);
-                    } else if order_fill_qty > 0.0 {
-                        let remaining_qty = l3order.qty - order_fill_qty;
-                        order_to_modify = Some((id, auction_price, remaining_qty));
+                    } else {
+                        let remaining_qty = l3order.qty - order_fill_qty.max(0.0);
+                        orders_to_readd.push((id, remaining_qty, l3order.timestamp));
                     }
                 }
 
-                if let Some((id, price, qty)) = order_to_modify {
-                    // println!("at auction left {}, {}, {}", id, price, qty);
-                    self.depth.modify_order(id, price, qty, timestamp)?;
-                }
-
                 for order_id in orders_to_delete {
                     self.depth.delete_order(order_id, timestamp)?;
                 }
 
+                // The best price on the remaining side was pushed past the auction price while the
+                // order book was crossed, so the orders left at the auction price are added back
+                // with their original timestamps to restore it.
+                for (id, qty, order_timestamp) in orders_to_readd {
+                    self.depth.delete_order(id, timestamp)?;
+                    match side {
+                        Side::Buy => {
+                            self.depth
+                                .add_buy_order(id, auction_price, qty, order_timestamp)?;
+                        }
+                        _ => {
+                            self.depth
+                                .add_sell_order(id, auction_price, qty, order_timestamp)?;
+                        }
+                    }
+                }
+
                 // println!("best ask {:?}", self.depth.best_ask());
                 // println!("best bid {:?}", self.depth.best_bid());                
+
+                let exch_ts = order.exch_timestamp;
+                self.add_auction_matched_qty(exch_ts, auction_price_tick, Side::Buy, matched_qty.0);
+                self.add_auction_matched_qty(
+                    exch_ts,
+                    auction_price_tick,
+                    Side::Sell,
+                    matched_qty.1,
+                );
+                continue;
             }
             // Updates the order latency only if it has a valid exchange timestamp. When the
             // order is rejected before it reaches the matching engine, it has no exchange
@@ -425,16 +935,39 @@ where
                 }
             }
 
-            // Processes receiving order response.
-            if order.status == Status::Filled {
-                self.state.apply_fill(&order);
+            // Processes receiving order response. A response can carry several fills, or none
+            // beyond those already applied, so only the executions since the last update of the
+            // local order are applied.
+            if order.req != Status::Rejected {
+                let (cum_exec_qty, cum_exec_notional) = self
+                    .orders
+                    .get(&order.order_id)
+                    .map_or((0.0, 0.0), |local_order| {
+                        (local_order.cum_exec_qty, local_order.cum_exec_notional)
+                    });
+                if let Some(fill) = order.fill_since(cum_exec_qty, cum_exec_notional) {
+                    self.state.apply_fill(&fill);
+                    if self.mark_to_market.on_fill() && has_both_sides(&self.depth) {
+                        self.state
+                            .mark_to_market((self.depth.best_bid() + self.depth.best_ask()) / 2.0);
+                    }
+                    self.mark_to_market
+                        .update_drawdown(self.state.marked_equity());
+                    self.last_fill_ts = Some(timestamp);
+                    self.adverse_selection
+                        .on_fill(&fill, &self.depth, timestamp);
+                }
             }
             // Applies the received order response to the local orders.
+            let prev_status = self.orders.get(&order.order_id).map(|order| order.status);
             match self.orders.entry(order.order_id) {
                 Entry::Occupied(mut entry) => {
                     let local_order = entry.get_mut();
+                    let prev_req = local_order.req;
+                    self.order_log.on_response(&order, prev_req, timestamp);
                     if order.req == Status::Rejected {
                         if order.local_timestamp == local_order.local_timestamp {
+                            local_order.reject_reason = order.reject_reason;
                             if local_order.req == Status::New {
                                 local_order.req = Status::None;
                                 local_order.status = Status::Expired;
@@ -445,13 +978,25 @@ where
                     } else {
                         local_order.update(&order);
                     }
+                    self.order_timeline
+                        .on_response(&order, prev_req, local_order, timestamp);
+                    self.audit
+                        .on_response(&order, prev_req, local_order, timestamp)?;
                 }
                 Entry::Vacant(entry) => {
                     if order.req != Status::Rejected {
-                        entry.insert(order);
+                        self.order_log.on_response(&order, Status::None, timestamp);
+                        entry.insert(order.clone());
                     }
                 }
             }
+            if let Some(local_order) = self.orders.get(&order.order_id) {
+                self.lifetime_vwap
+                    .on_response(&order, local_order.status, timestamp);
+                self.touch_distance.on_response(&order, local_order.status);
+                self.opportunity_cost
+                    .on_response(prev_status, local_order, &self.depth, timestamp);
+            }
         }
         Ok(wait_resp_order_received)
     }
@@ -467,4 +1012,406 @@ where
             .earliest_send_order_timestamp()
             .unwrap_or(i64::MAX)
     }
+
+    fn last_auction(&self) -> Option<&AuctionResult> {
+        self.auction.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest, BacktestError, DataSource,
+            ExchangeKind::PartialFillExchange,
+            L3AssetBuilder, MissingOrderPolicy, OutOfRoiPolicy,
+            assettype::LinearAsset,
+            data::Data,
+            models::{CommonFees, ConstantLatency, L3FIFOQueueModel, TradingValueFeeModel},
+            testutil::{l3_asset, l3_event},
+        },
+        depth::{HashMapMarketDepth, L3MarketDepth, MarketDepth, ROIVectorMarketDepth},
+        prelude::{Bot, Event, OrdType, Side, Status, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, AUCTION_UPDATE_EVENT, BUY_EVENT, DEPTH_CLEAR_EVENT, EXCH_EVENT,
+            FILL_EVENT, LOCAL_EVENT, LOCAL_FILL_EVENT, MODIFY_ORDER_EVENT, SELL_EVENT,
+        },
+    };
+
+    fn modify_untracked_order(
+        policy: MissingOrderPolicy,
+    ) -> Result<Backtest<HashMapMarketDepth>, Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            // The order 2 has never been added, for example, because it was outside the range of
+            // interest.
+            l3_event(
+                LOCAL_EVENT | BUY_EVENT | MODIFY_ORDER_EVENT,
+                10,
+                99.0,
+                2.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 100, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .missing_order_policy(policy)
+                    .build()?,
+            )
+            .build()?;
+        hbt.elapse(100)?;
+        Ok(hbt)
+    }
+
+    #[test]
+    fn modify_untracked_order_error() {
+        assert!(modify_untracked_order(MissingOrderPolicy::Error).is_err());
+    }
+
+    #[test]
+    fn modify_untracked_order_ignore() -> Result<(), Box<dyn Error>> {
+        let hbt = modify_untracked_order(MissingOrderPolicy::Ignore)?;
+        let depth = hbt.depth(0);
+        assert!(!depth.orders().contains_key(&2));
+        assert_eq!(depth.best_bid_tick(), 100);
+        assert_eq!(depth.bid_qty_at_tick(99), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn modify_untracked_order_treat_as_add() -> Result<(), Box<dyn Error>> {
+        let hbt = modify_untracked_order(MissingOrderPolicy::TreatAsAdd)?;
+        let depth = hbt.depth(0);
+        let order = depth.orders().get(&2).unwrap();
+        assert_eq!(order.price_tick, 99);
+        assert_eq!(order.qty, 2.0);
+        assert_eq!(depth.best_bid_tick(), 100);
+        assert_eq!(depth.bid_qty_at_tick(99), 2.0);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_order_outside_roi() -> Result<(), Box<dyn Error>> {
+        let build = |policy: OutOfRoiPolicy| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 100, 0.0, 0.0, 0),
+            ]);
+            Ok(Backtest::builder()
+                .add_asset(
+                    L3AssetBuilder::default()
+                        .data(vec![DataSource::Data(data)])
+                        .latency_model(ConstantLatency::new(10, 20))
+                        .asset_type(LinearAsset::new(1.0))
+                        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                        .queue_model(L3FIFOQueueModel::new())
+                        .exchange(PartialFillExchange)
+                        .depth(|| ROIVectorMarketDepth::new(1.0, 1.0, 90.0, 110.0))
+                        .out_of_roi_policy(policy)
+                        .build()?,
+                )
+                .build()?)
+        };
+
+        let mut hbt = build(OutOfRoiPolicy::Reject)?;
+        hbt.elapse(1)?;
+        let result = hbt.submit_buy_order(0, 1, 80.0, 1.0, TimeInForce::GTC, OrdType::Limit, false);
+        assert!(matches!(result, Err(BacktestError::OutOfRoi)));
+        assert!(hbt.orders(0).get(&1).is_none());
+        // The order within the range of interest is accepted.
+        hbt.submit_buy_order(0, 2, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(50)?;
+        assert_eq!(hbt.orders(0).get(&2).unwrap().status, Status::New);
+
+        let mut hbt = build(OutOfRoiPolicy::Allow)?;
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 1, 80.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        assert!(hbt.orders(0).get(&1).is_some());
+        Ok(())
+    }
+
+    fn local_fill(
+        fill_counterparty: bool,
+        ival: i64,
+    ) -> Result<Backtest<HashMapMarketDepth>, Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                3.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                3.0,
+                2,
+            ),
+            Event {
+                ival,
+                ..l3_event(LOCAL_FILL_EVENT | BUY_EVENT, 10, 100.0, 1.0, 1)
+            },
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 100, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_counterparty(fill_counterparty)
+                    .build()?,
+            )
+            .build()?;
+        hbt.elapse(100)?;
+        Ok(hbt)
+    }
+
+    #[test]
+    fn local_fill_with_counterparty() -> Result<(), Box<dyn Error>> {
+        let hbt = local_fill(true, 2)?;
+        let depth = hbt.depth(0);
+        assert_eq!(depth.orders().get(&1).unwrap().qty, 2.0);
+        assert_eq!(depth.orders().get(&2).unwrap().qty, 2.0);
+        Ok(())
+    }
+
+    #[test]
+    fn local_fill_without_counterparty() -> Result<(), Box<dyn Error>> {
+        // The dataset doesn't populate the counterparty order ID.
+        assert!(local_fill(true, 0).is_err());
+
+        let hbt = local_fill(false, 0)?;
+        let depth = hbt.depth(0);
+        assert_eq!(depth.orders().get(&1).unwrap().qty, 2.0);
+        assert_eq!(depth.orders().get(&2).unwrap().qty, 3.0);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_orders_after_notional_cap() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                5.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                5.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .max_total_notional(150.0)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 1, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        assert_eq!(hbt.state_values(0).trading_value, 101.0);
+
+        // The cumulative notional crosses the cap by this fill.
+        hbt.submit_buy_order(0, 2, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        assert_eq!(hbt.state_values(0).trading_value, 202.0);
+
+        let result = hbt.submit_buy_order(0, 3, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true);
+        assert!(matches!(result, Err(BacktestError::NotionalLimitReached)));
+        assert!(!hbt.orders(0).contains_key(&3));
+        Ok(())
+    }
+
+    #[test]
+    fn auction_reconciles_local_depth_once() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                50,
+                100.0,
+                3.0,
+                4,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                100,
+                100.0,
+                3.0,
+                3,
+            ),
+            // Uncrosses at 100, where the bids of 4 are matched against the ask of 3. Both feed
+            // bids are matched, so the exchange sends an auction response for each of them.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | FILL_EVENT | AUCTION_UPDATE_EVENT,
+                200,
+                100.0,
+                0.0,
+                0,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(500)?;
+        assert_eq!(hbt.last_auction(0).unwrap().matched_qty, 3.0);
+
+        // The first bid is fully matched and the second is left with 1.
+        let depth = hbt.depth(0);
+        assert!(!depth.orders().contains_key(&1));
+        assert!(!depth.orders().contains_key(&3));
+        assert_eq!(depth.orders().get(&4).unwrap().qty, 1.0);
+        assert_eq!(depth.best_bid(), 100.0);
+        assert_eq!(depth.bid_qty_at_tick(100), 1.0);
+        assert_eq!(depth.best_ask(), 101.0);
+        assert_eq!(depth.ask_qty_at_tick(101), 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn feed_order_id_offset_separates_feed_and_backtest_orders() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            // The feed order has the same ID as the backtest order.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                100,
+                100.0,
+                3.0,
+                10,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | FILL_EVENT | AUCTION_UPDATE_EVENT,
+                200,
+                100.0,
+                0.0,
+                0,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .feed_order_id_offset(1 << 32)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 5.0, TimeInForce::GTC, OrdType::Limit, true)?;
+
+        hbt.elapse(500)?;
+        let auction = hbt.last_auction(0).unwrap();
+        assert_eq!(auction.matched_qty, 3.0);
+        let fill = auction.fill(10).unwrap();
+        assert_eq!(fill.matched_qty, 2.0);
+        assert_eq!(fill.leaves_qty, 3.0);
+
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.side, Side::Buy);
+        assert_eq!(order.status, Status::PartiallyFilled);
+        assert_eq!(order.leaves_qty, 3.0);
+        // The feed order IDs are shifted in the local's market depth as well.
+        assert!(hbt.depth(0).orders().contains_key(&((1 << 32) + 2)));
+        assert!(!hbt.depth(0).orders().contains_key(&2));
+        Ok(())
+    }
+
+    #[test]
+    fn expiry_arrives_after_response_latency() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                105.0,
+                1.0,
+                2,
+            ),
+            // The exchange clears its book, which expires the resting orders.
+            l3_event(EXCH_EVENT | DEPTH_CLEAR_EVENT, 500, 0.0, 0.0, 0),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::New);
+
+        // The order expires on the exchange at 500, but the local doesn't see it until the
+        // response latency of 20 has passed.
+        hbt.elapse(519 - hbt.current_timestamp())?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::New);
+
+        hbt.elapse(1)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.leaves_qty, 0.0);
+        assert_eq!(order.exch_timestamp, 500);
+        Ok(())
+    }
 }
@@ -2,15 +2,23 @@ use crate::{
     backtest::{
         BacktestError,
         assettype::AssetType,
+        fillgap::{FillGap, FillGapRecorder, FillPriceBucket, FillPriceHistogram},
+        fillrecord::{FillKind, FillRecord, FillRecorder},
         models::{FeeModel, L3QueueModel, LatencyModel},
         order::ExchToLocal,
-        proc::Processor,
+        printdivergence::{PrintDivergence, PrintDivergenceRecorder},
+        proc::{
+            ExchangeOrderView, FeedFillDedup, HaltPolicy, LockedMarketPolicy, MaxLifetimes,
+            ModifyMode, PriorityResetPolicy, Processor, RecentFills, StopOrders, StopTrigger,
+            has_both_sides, lacks_price_improvement, offset_feed_order_id, sort_by_fill_sequence,
+        },
         state::State,
     },
     depth::L3MarketDepth,
     prelude::OrdType,
     types::{
         BUY_EVENT,
+        CancelReason,
         EXCH_ASK_ADD_ORDER_EVENT,
         EXCH_ASK_DEPTH_CLEAR_EVENT,
         EXCH_BID_ADD_ORDER_EVENT,
@@ -19,10 +27,14 @@ use crate::{
         EXCH_DEPTH_CLEAR_EVENT,
         EXCH_EVENT,
         EXCH_FILL_EVENT,
+        EXCH_HALT_EVENT,
         EXCH_MODIFY_ORDER_EVENT,
+        EXCH_RESUME_EVENT,
+        EXCH_TRADE_EVENT,
         Event,
         Order,
         OrderId,
+        RejectReason,
         SELL_EVENT,
         Side,
         Status,
@@ -32,7 +44,9 @@ use crate::{
 
 /// The exchange model without partial fills.
 ///
-/// Support order types: [OrdType::Limit](crate::types::OrdType::Limit)
+/// Support order types: [OrdType::Limit](crate::types::OrdType::Limit),
+/// [OrdType::Market](crate::types::OrdType::Market),
+/// [OrdType::StopMarket](crate::types::OrdType::StopMarket)
 /// Support time-in-force: [`TimeInForce::GTC`], [`TimeInForce::GTX`]
 ///
 /// **Conditions for Full Execution**
@@ -67,6 +81,24 @@ where
     state: State<AT, FM>,
     queue_model: QM,
     order_e2l: ExchToLocal<LM>,
+    fill_gaps: FillGapRecorder,
+    fill_records: FillRecorder,
+    fill_price_histogram: FillPriceHistogram,
+    print_divergence: PrintDivergenceRecorder,
+    two_phase_ack: bool,
+    priority_reset_policy: PriorityResetPolicy,
+    modify_mode: ModifyMode,
+    halt_policy: HaltPolicy,
+    locked_market_policy: LockedMarketPolicy,
+    halted: bool,
+    warmup: bool,
+    min_price_improvement: i64,
+    feed_order_id_offset: u64,
+    fill_dedup: FeedFillDedup,
+    recent_fills: RecentFills,
+    stop_orders: StopOrders,
+    max_lifetimes: MaxLifetimes,
+    last_exch_order_id: u64,
 }
 
 impl<AT, LM, QM, MD, FM> L3NoPartialFillExchange<AT, LM, QM, MD, FM>
@@ -90,16 +122,197 @@ where
             state,
             queue_model,
             order_e2l,
+            fill_gaps: Default::default(),
+            fill_records: Default::default(),
+            fill_price_histogram: Default::default(),
+            print_divergence: Default::default(),
+            two_phase_ack: false,
+            priority_reset_policy: Default::default(),
+            modify_mode: Default::default(),
+            halt_policy: Default::default(),
+            locked_market_policy: Default::default(),
+            halted: false,
+            warmup: false,
+            min_price_improvement: 0,
+            feed_order_id_offset: 0,
+            fill_dedup: Default::default(),
+            recent_fills: Default::default(),
+            stop_orders: Default::default(),
+            max_lifetimes: Default::default(),
+            last_exch_order_id: 0,
         }
     }
 
+    /// Sets the initial capacity of the fill gap recorder, which records the mid price at the
+    /// time of each fill. The default value is `0`, indicating that no fill gap is recorded.
+    pub fn fill_gap_capacity(self, capacity: usize) -> Self {
+        Self {
+            fill_gaps: FillGapRecorder::new(capacity),
+            ..self
+        }
+    }
+
+    /// Sets whether every fill is recorded as a trade log. The default value is `false`.
+    pub fn fill_records(self, enabled: bool) -> Self {
+        Self {
+            fill_records: FillRecorder::new(enabled),
+            ..self
+        }
+    }
+
+    /// Sets whether the executed quantity of the fills is accumulated by the distance from the
+    /// mid price at the time of each fill. The default value is `false`.
+    pub fn fill_price_histogram(self, enabled: bool) -> Self {
+        Self {
+            fill_price_histogram: FillPriceHistogram::new(enabled),
+            ..self
+        }
+    }
+
+    /// Sets the window in nanoseconds around each fill within which the nearest market print is
+    /// looked for, to compare the fills with the actual trades in the feed. The default value is
+    /// `0`, indicating that no fill is compared.
+    pub fn print_divergence_window(self, window: i64) -> Self {
+        Self {
+            print_divergence: PrintDivergenceRecorder::new(window),
+            ..self
+        }
+    }
+
+    /// Sets whether a new order is acknowledged before it is matched. If enabled, the exchange
+    /// always responds with [`Status::New`] first, and then responds with the matching result,
+    /// such as a fill, at the same timestamp. The second response is omitted if the order simply
+    /// rests in the order book. The default value is `false`.
+    pub fn two_phase_ack(self, two_phase_ack: bool) -> Self {
+        Self {
+            two_phase_ack,
+            ..self
+        }
+    }
+
+    /// Sets whether a replaced order loses its queue priority. The default value is
+    /// [`PriorityResetPolicy::KeepOnSizeDown`].
+    pub fn priority_reset_policy(self, priority_reset_policy: PriorityResetPolicy) -> Self {
+        Self {
+            priority_reset_policy,
+            ..self
+        }
+    }
+
+    /// Sets how a modify request is processed. The default value is [`ModifyMode::Amend`].
+    pub fn modify_mode(self, modify_mode: ModifyMode) -> Self {
+        Self {
+            modify_mode,
+            ..self
+        }
+    }
+
+    /// Sets how the resting backtest orders are handled when trading halts by
+    /// [`HALT_EVENT`](crate::types::HALT_EVENT). Regardless of the policy, new orders and
+    /// modifications are rejected with [`RejectReason::TradingHalted`] until trading resumes by
+    /// [`RESUME_EVENT`](crate::types::RESUME_EVENT). The default value is
+    /// [`HaltPolicy::KeepOrders`].
+    pub fn halt_policy(self, halt_policy: HaltPolicy) -> Self {
+        Self {
+            halt_policy,
+            ..self
+        }
+    }
+
+    /// Sets whether a new [`TimeInForce::GTC`] limit order that would lock or cross the market is
+    /// matched or rejected with [`RejectReason::WouldLockMarket`]. The default value is
+    /// [`LockedMarketPolicy::Match`].
+    pub fn locked_market_policy(self, locked_market_policy: LockedMarketPolicy) -> Self {
+        Self {
+            locked_market_policy,
+            ..self
+        }
+    }
+
+    /// Sets whether the exchange starts in the warmup, during which new orders are rejected with
+    /// [`RejectReason::BookNotEstablished`] so that nothing is matched against an order book that
+    /// is still empty or one-sided. The warmup ends once both sides of the market depth have at
+    /// least one level. The default value is `false`.
+    pub fn warmup(self, warmup: bool) -> Self {
+        Self { warmup, ..self }
+    }
+
+    /// Ends the warmup once both sides of the market depth have at least one level.
+    fn update_warmup(&mut self) {
+        if self.warmup && has_both_sides(&self.depth) {
+            self.warmup = false;
+        }
+    }
+
+    /// Sets the minimum price improvement in ticks that a modify request must make when it
+    /// improves the order price, as on venues that don't let an order jump the queue by a marginal
+    /// reprice. A modify that improves the price by less than this is rejected with
+    /// [`RejectReason::InsufficientPriceImprovement`]. The default value is `0`, indicating no
+    /// requirement.
+    pub fn min_price_improvement(self, min_price_improvement: i64) -> Self {
+        Self {
+            min_price_improvement,
+            ..self
+        }
+    }
+
+    /// Sets the offset added to the order IDs of the market feed events at ingestion, so that the
+    /// market feed order IDs can't collide with the backtest order IDs. It should be the same for
+    /// the local and the exchange of the asset. The default value is `0`, indicating that the
+    /// order IDs are used as they are.
+    pub fn feed_order_id_offset(self, feed_order_id_offset: u64) -> Self {
+        Self {
+            feed_order_id_offset,
+            ..self
+        }
+    }
+
+    /// Sets the window in nanoseconds of exchange time within which a market feed fill event
+    /// repeating a previous fill's exchange timestamp, price, quantity, and order ID is ignored as
+    /// a duplicate. The default value is `0`, indicating that every fill event is processed.
+    pub fn fill_dedup_window(self, fill_dedup_window: i64) -> Self {
+        Self {
+            fill_dedup: FeedFillDedup::new(fill_dedup_window),
+            ..self
+        }
+    }
+
+    /// Sets the reference price that triggers a stop order. The default value is
+    /// [`StopTrigger::LastTrade`].
+    pub fn stop_trigger(self, stop_trigger: StopTrigger) -> Self {
+        Self {
+            stop_orders: StopOrders::new(stop_trigger),
+            ..self
+        }
+    }
+
+    /// Returns the backtest order resting in the queue.
+    fn resting_order(&self, order: &Order) -> Option<Order> {
+        let orders = match order.side {
+            Side::Buy => self.queue_model.get_all_bid_orders(),
+            _ => self.queue_model.get_all_ask_orders(),
+        };
+        orders
+            .into_iter()
+            .find(|resting| resting.order_id == order.order_id)
+    }
+
+    fn ack_before_match(&mut self, order: &Order, timestamp: i64) {
+        let mut ack = order.clone();
+        ack.status = Status::New;
+        ack.exch_timestamp = timestamp;
+        self.respond(ack);
+    }
+
+    /// Expires the order at `timestamp`. Like any other response, the expiry reaches the local
+    /// after the order response latency.
     fn expired(&mut self, mut order: Order, timestamp: i64) -> Result<(), BacktestError> {
         order.exec_qty = 0.0;
         order.leaves_qty = 0.0;
         order.status = Status::Expired;
         order.exch_timestamp = timestamp;
 
-        self.order_e2l.respond(order);
+        self.respond(order);
         Ok(())
     }
 
@@ -109,6 +322,7 @@ where
         timestamp: i64,
         maker: bool,
         exec_price_tick: i64,
+        kind: FillKind,
     ) -> Result<(), BacktestError> {
         if order.status == Status::Expired
             || order.status == Status::Canceled
@@ -126,13 +340,20 @@ where
 
         order.exec_qty = order.leaves_qty;
         order.leaves_qty = 0.0;
+        order.cum_exec_qty += order.exec_qty;
+        order.cum_exec_notional += order.exec_price() * order.exec_qty;
         order.status = Status::Filled;
         order.exch_timestamp = timestamp;
+        self.recent_fills.insert(order.order_id);
 
-        self.state.apply_fill(order);
+        let fee = self.state.apply_fill(order);
+        self.fill_gaps.record(order, &self.depth, fee);
+        self.fill_records.record(order, fee, kind);
+        self.fill_price_histogram.record(order, &self.depth);
+        self.print_divergence.on_fill(order);
 
         if MAKE_RESPONSE {
-            self.order_e2l.respond(order.clone());
+            self.respond(order.clone());
         }
         Ok(())
     }
@@ -143,12 +364,13 @@ where
         new_best_tick: i64,
         timestamp: i64,
     ) -> Result<(), BacktestError> {
-        let filled = self
+        let mut filled = self
             .queue_model
             .on_best_bid_update(prev_best_tick, new_best_tick)?;
+        sort_by_fill_sequence(&mut filled);
         for mut order in filled {
             let price_tick = order.price_tick;
-            self.fill::<true>(&mut order, timestamp, true, price_tick)?;
+            self.fill::<true>(&mut order, timestamp, true, price_tick, FillKind::Crossing)?;
         }
         Ok(())
     }
@@ -159,20 +381,57 @@ where
         new_best_tick: i64,
         timestamp: i64,
     ) -> Result<(), BacktestError> {
-        let filled = self
+        let mut filled = self
             .queue_model
             .on_best_ask_update(prev_best_tick, new_best_tick)?;
+        sort_by_fill_sequence(&mut filled);
         for mut order in filled {
             let price_tick = order.price_tick;
-            self.fill::<true>(&mut order, timestamp, true, price_tick)?;
+            self.fill::<true>(&mut order, timestamp, true, price_tick, FillKind::Crossing)?;
         }
         Ok(())
     }
 
     fn ack_new(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
-        if self.queue_model.contains_backtest_order(order.order_id) {
+        if self.queue_model.contains_backtest_order(order.order_id)
+            || self.stop_orders.get(order.order_id).is_some()
+        {
             return Err(BacktestError::OrderIdExist);
         }
+        if self.halted {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::TradingHalted;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        self.update_warmup();
+        if self.warmup {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::BookNotEstablished;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        if self.locked_market_policy.rejects(
+            order,
+            self.depth.best_bid_tick(),
+            self.depth.best_ask_tick(),
+        ) {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::WouldLockMarket;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        order.exch_order_id = self.next_exch_order_id();
+
+        if order.order_type == OrdType::StopMarket
+            && !self.stop_orders.is_triggered(order, &self.depth)
+        {
+            // Waits for the trigger.
+            order.status = Status::New;
+            order.exch_timestamp = timestamp;
+            self.stop_orders.insert(order.clone());
+            return Ok(());
+        }
 
         if order.side == Side::Buy {
             match order.order_type {
@@ -194,6 +453,7 @@ where
                                     timestamp,
                                     false,
                                     self.depth.best_ask_tick(),
+                                    FillKind::Crossing,
                                 )
                             }
                             TimeInForce::Unsupported => Err(BacktestError::InvalidOrderRequest),
@@ -207,6 +467,7 @@ where
 
                                 self.queue_model
                                     .add_backtest_order(order.clone(), &self.depth)?;
+                                self.max_lifetimes.on_rest(order, timestamp);
                                 Ok(())
                             }
                             TimeInForce::FOK | TimeInForce::IOC => {
@@ -218,9 +479,15 @@ where
                         }
                     }
                 }
-                OrdType::Market => {
+                OrdType::Market | OrdType::StopMarket => {
                     // Takes the market.
-                    self.fill::<false>(order, timestamp, false, self.depth.best_ask_tick())
+                    self.fill::<false>(
+                        order,
+                        timestamp,
+                        false,
+                        self.depth.best_ask_tick(),
+                        FillKind::Crossing,
+                    )
                 }
                 OrdType::Unsupported => Err(BacktestError::InvalidOrderRequest),
             }
@@ -244,6 +511,7 @@ where
                                     timestamp,
                                     false,
                                     self.depth.best_bid_tick(),
+                                    FillKind::Crossing,
                                 )
                             }
                             TimeInForce::Unsupported => Err(BacktestError::InvalidOrderRequest),
@@ -257,6 +525,7 @@ where
 
                                 self.queue_model
                                     .add_backtest_order(order.clone(), &self.depth)?;
+                                self.max_lifetimes.on_rest(order, timestamp);
                                 Ok(())
                             }
                             TimeInForce::FOK | TimeInForce::IOC => {
@@ -268,9 +537,15 @@ where
                         }
                     }
                 }
-                OrdType::Market => {
+                OrdType::Market | OrdType::StopMarket => {
                     // Takes the market.
-                    self.fill::<false>(order, timestamp, false, self.depth.best_bid_tick())
+                    self.fill::<false>(
+                        order,
+                        timestamp,
+                        false,
+                        self.depth.best_bid_tick(),
+                        FillKind::Crossing,
+                    )
                 }
                 OrdType::Unsupported => Err(BacktestError::InvalidOrderRequest),
             }
@@ -278,6 +553,12 @@ where
     }
 
     fn ack_cancel(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
+        if let Some(stop_order) = self.stop_orders.remove(order.order_id) {
+            *order = stop_order;
+            order.status = Status::Canceled;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
         match self
             .queue_model
             .cancel_backtest_order(order.order_id, &self.depth)
@@ -291,6 +572,7 @@ where
             }
             Err(BacktestError::OrderNotFound) => {
                 order.req = Status::Rejected;
+                order.reject_reason = self.recent_fills.reject_reason(order.order_id);
                 order.exch_timestamp = timestamp;
                 Ok(())
             }
@@ -298,21 +580,159 @@ where
         }
     }
 
+    /// Replaces the order by re-adding it at the tail of its price level.
+    fn reset_queue_pos(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
+        self.queue_model
+            .cancel_backtest_order(order.order_id, &self.depth)?;
+        order.exch_timestamp = timestamp;
+        self.queue_model
+            .add_backtest_order(order.clone(), &self.depth)
+    }
+
+    /// Replaces the order by a cancel followed by a new order, which gets a new exchange order ID
+    /// and joins the tail of its price level.
+    fn cancel_replace(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
+        self.queue_model
+            .cancel_backtest_order(order.order_id, &self.depth)?;
+        order.exch_order_id = self.next_exch_order_id();
+        order.exch_timestamp = timestamp;
+        self.queue_model
+            .add_backtest_order(order.clone(), &self.depth)
+    }
+
+    /// Executes the stop orders triggered by the current reference price as market orders.
+    fn trigger_stop_orders(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        if self.halted {
+            return Ok(());
+        }
+        for mut order in self.stop_orders.take_triggered(&self.depth) {
+            self.ack_new(&mut order, timestamp)?;
+            self.respond(order);
+        }
+        Ok(())
+    }
+
+    /// Halts trading, canceling the resting backtest orders if the halt policy requires it.
+    fn halt(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        self.halted = true;
+        if self.halt_policy != HaltPolicy::CancelOrders {
+            return Ok(());
+        }
+        for mut order in self.stop_orders.drain() {
+            order.status = Status::Canceled;
+            order.cancel_reason = CancelReason::TradingHalt;
+            order.exch_timestamp = timestamp;
+            self.respond(order);
+        }
+        let mut resting_orders = self.queue_model.get_all_bid_orders();
+        resting_orders.append(&mut self.queue_model.get_all_ask_orders());
+        for resting_order in resting_orders {
+            if !self
+                .queue_model
+                .contains_backtest_order(resting_order.order_id)
+            {
+                continue;
+            }
+            let mut order = self
+                .queue_model
+                .cancel_backtest_order(resting_order.order_id, &self.depth)?;
+            order.canceled_qty = order.leaves_qty;
+            order.leaves_qty = 0.0;
+            order.exec_qty = 0.0;
+            order.status = Status::Canceled;
+            order.cancel_reason = CancelReason::TradingHalt;
+            order.exch_timestamp = timestamp;
+            self.respond(order);
+        }
+        Ok(())
+    }
+
+    /// Cancels the resting orders that have rested for their maximum lifetime by `timestamp`.
+    fn cancel_expired_lifetimes(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        for order_id in self.max_lifetimes.take_expired(timestamp) {
+            if !self.queue_model.contains_backtest_order(order_id) {
+                continue;
+            }
+            let mut order = self
+                .queue_model
+                .cancel_backtest_order(order_id, &self.depth)?;
+            order.canceled_qty = order.leaves_qty;
+            order.leaves_qty = 0.0;
+            order.exec_qty = 0.0;
+            order.status = Status::Canceled;
+            order.cancel_reason = CancelReason::MaxLifetime;
+            order.exch_timestamp = timestamp;
+            self.respond(order);
+        }
+        Ok(())
+    }
+
+    /// Sends the response to the local, ending the maximum lifetime of the order once it leaves
+    /// the order book.
+    fn respond(&mut self, order: Order) {
+        self.max_lifetimes.on_response(&order);
+        self.order_e2l.respond(order);
+    }
+
+    fn next_exch_order_id(&mut self) -> u64 {
+        self.last_exch_order_id += 1;
+        self.last_exch_order_id
+    }
+
     fn ack_modify<const RESET_QUEUE_POS: bool>(
         &mut self,
         order: &mut Order,
         timestamp: i64,
     ) -> Result<(), BacktestError> {
-        match self
-            .queue_model
-            .modify_backtest_order(order.order_id, order, &self.depth)
+        if self.halted {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::TradingHalted;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        let resting = self.resting_order(order);
+        if let Some(resting) = resting.as_ref() {
+            // Only applied to the order if the modification is accepted.
+            order.modify_count = resting.modify_count + 1;
+        }
+        if self.min_price_improvement > 1
+            && resting.as_ref().is_some_and(|resting| {
+                lacks_price_improvement(
+                    order.side,
+                    resting.price_tick,
+                    order.price_tick,
+                    self.min_price_improvement,
+                )
+            })
         {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::InsufficientPriceImprovement;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        let reset_queue_pos = RESET_QUEUE_POS
+            || resting.is_some_and(|resting| {
+                self.priority_reset_policy
+                    .resets(resting.price_tick, resting.qty, order)
+            });
+        let result = if self.modify_mode == ModifyMode::CancelReplace {
+            self.cancel_replace(order, timestamp)
+        } else if reset_queue_pos {
+            self.reset_queue_pos(order, timestamp)
+        } else {
+            self.queue_model
+                .modify_backtest_order(order.order_id, order, &self.depth)
+        };
+        match result {
             Ok(()) => {
+                // No execution takes place by the modification.
+                order.exec_qty = 0.0;
                 order.exch_timestamp = timestamp;
                 Ok(())
             }
             Err(BacktestError::OrderNotFound) => {
                 order.req = Status::Rejected;
+                order.reject_reason = self.recent_fills.reject_reason(order.order_id);
                 order.exch_timestamp = timestamp;
                 Ok(())
             }
@@ -335,7 +755,16 @@ where
     }
 
     fn process(&mut self, event: &Event) -> Result<(), BacktestError> {
-        if event.is(EXCH_BID_DEPTH_CLEAR_EVENT) {
+        let event = offset_feed_order_id(event, self.feed_order_id_offset);
+        let event = &*event;
+        self.cancel_expired_lifetimes(event.exch_ts)?;
+        self.update_warmup();
+
+        if event.is(EXCH_HALT_EVENT) {
+            self.halt(event.exch_ts)?;
+        } else if event.is(EXCH_RESUME_EVENT) {
+            self.halted = false;
+        } else if event.is(EXCH_BID_DEPTH_CLEAR_EVENT) {
             self.depth.clear_orders(Side::Buy);
             let expired = self.queue_model.clear_orders(Side::Buy);
             for order in expired {
@@ -390,20 +819,37 @@ where
         } else if event.is(EXCH_FILL_EVENT) {
             // todo: handle properly if no side is provided.
             if event.is(BUY_EVENT) || event.is(SELL_EVENT) {
-                let filled = self.queue_model.fill_market_feed_order::<false>(
+                if self.fill_dedup.is_duplicate(event) {
+                    return Ok(());
+                }
+                let mut filled = self.queue_model.fill_market_feed_order::<false>(
                     event.order_id,
                     event,
                     &self.depth,
                 )?;
+                sort_by_fill_sequence(&mut filled);
                 let timestamp = event.exch_ts;
                 for mut order in filled {
+                    // The order is filled in full even if the queue model allocates only a part of
+                    // the fill to it and keeps the rest resting.
+                    if self.queue_model.contains_backtest_order(order.order_id) {
+                        self.queue_model
+                            .cancel_backtest_order(order.order_id, &self.depth)?;
+                    }
                     let price_tick = order.price_tick;
-                    self.fill::<true>(&mut order, timestamp, true, price_tick)?;
+                    self.fill::<true>(&mut order, timestamp, true, price_tick, FillKind::Touch)?;
                 }
+                self.stop_orders
+                    .on_trade((event.px / self.depth.tick_size()).round() as i64);
+                self.print_divergence.on_print(event.exch_ts, event.px);
             }
+        } else if event.is(EXCH_TRADE_EVENT) {
+            self.stop_orders
+                .on_trade((event.px / self.depth.tick_size()).round() as i64);
+            self.print_divergence.on_print(event.exch_ts, event.px);
         }
 
-        Ok(())
+        self.trigger_stop_orders(event.exch_ts)
     }
 
     fn process_recv_order(
@@ -411,11 +857,21 @@ where
         timestamp: i64,
         _wait_resp_order_id: Option<OrderId>,
     ) -> Result<bool, BacktestError> {
+        self.cancel_expired_lifetimes(timestamp)?;
         while let Some(mut order) = self.order_e2l.receive(timestamp) {
             // Processes a new order.
             if order.req == Status::New {
                 order.req = Status::None;
-                self.ack_new(&mut order, timestamp)?;
+                if self.two_phase_ack && !self.halted {
+                    self.ack_before_match(&order, timestamp);
+                    self.ack_new(&mut order, timestamp)?;
+                    // The acknowledgement has already conveyed the resting order.
+                    if order.status == Status::New && order.req == Status::None {
+                        continue;
+                    }
+                } else {
+                    self.ack_new(&mut order, timestamp)?;
+                }
             }
             // Processes a cancel order.
             else if order.req == Status::Canceled {
@@ -430,15 +886,17 @@ where
                 return Err(BacktestError::InvalidOrderRequest);
             }
             // Makes the response.
-            self.order_e2l.respond(order);
+            self.respond(order);
         }
         Ok(false)
     }
 
     fn earliest_recv_order_timestamp(&self) -> i64 {
+        // Wakes up at the earliest lifetime deadline to cancel the order exactly when it expires.
         self.order_e2l
             .earliest_recv_order_timestamp()
             .unwrap_or(i64::MAX)
+            .min(self.max_lifetimes.earliest().unwrap_or(i64::MAX))
     }
 
     fn earliest_send_order_timestamp(&self) -> i64 {
@@ -446,4 +904,296 @@ where
             .earliest_send_order_timestamp()
             .unwrap_or(i64::MAX)
     }
+
+    fn fill_gaps(&self) -> &[FillGap] {
+        self.fill_gaps.fills()
+    }
+
+    fn clear_fill_gaps(&mut self) {
+        self.fill_gaps.clear();
+    }
+
+    fn fill_records(&self) -> &[FillRecord] {
+        self.fill_records.fills()
+    }
+
+    fn clear_fill_records(&mut self) {
+        self.fill_records.clear();
+    }
+
+    fn fill_price_histogram(&self) -> &[FillPriceBucket] {
+        self.fill_price_histogram.buckets()
+    }
+
+    fn clear_fill_price_histogram(&mut self) {
+        self.fill_price_histogram.clear();
+    }
+
+    fn print_divergences(&self) -> &[PrintDivergence] {
+        self.print_divergence.records()
+    }
+
+    fn clear_print_divergences(&mut self) {
+        self.print_divergence.clear();
+    }
+
+    fn flush_print_divergences(&mut self) {
+        self.print_divergence.flush();
+    }
+
+    fn exchange_order(&self, order_id: OrderId) -> Option<ExchangeOrderView> {
+        if let Some(order) = self.stop_orders.get(order_id) {
+            return Some(ExchangeOrderView::new(order, None));
+        }
+        if let Some(order) = self.queue_model.backtest_order(order_id) {
+            return Some(ExchangeOrderView::new(
+                order,
+                self.queue_model.qty_ahead(order_id),
+            ));
+        }
+        self.order_e2l
+            .pending_response(order_id)
+            .map(|order| ExchangeOrderView::new(&order, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::NoPartialFillExchange,
+            PriorityResetPolicy, StopTrigger,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, Status, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, CANCEL_ORDER_EVENT, EXCH_EVENT, FILL_EVENT, LOCAL_EVENT,
+            SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn modify_queue_position_by_priority_reset_policy() -> Result<(), Box<dyn Error>> {
+        let run = |policy: PriorityResetPolicy| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                // Joins the queue behind the backtest order.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    100,
+                    100.0,
+                    2.0,
+                    3,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    100,
+                    99.0,
+                    1.5,
+                    4,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, NoPartialFillExchange)
+                        .priority_reset_policy(policy)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 100.0, 2.0, TimeInForce::GTC, OrdType::Limit, false)?;
+            hbt.elapse(200)?;
+            let mut qty_ahead = vec![hbt.exchange_order(0, 10).unwrap().qty_ahead];
+            for (price, qty) in [(100.0, 1.0), (100.0, 3.0), (99.0, 3.0)] {
+                hbt.modify(0, 10, price, qty, true)?;
+                qty_ahead.push(hbt.exchange_order(0, 10).unwrap().qty_ahead);
+            }
+            Ok(qty_ahead)
+        };
+
+        // Reducing the quantity keeps the position ahead of the feed order that joined later,
+        // while increasing the quantity or changing the price moves the order to the tail.
+        assert_eq!(
+            run(PriorityResetPolicy::KeepOnSizeDown)?,
+            [Some(1.0), Some(1.0), Some(3.0), Some(1.5)]
+        );
+        assert_eq!(
+            run(PriorityResetPolicy::AlwaysReset)?,
+            [Some(1.0), Some(3.0), Some(3.0), Some(1.5)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stop_order_triggers_on_best_bid_without_trade() -> Result<(), Box<dyn Error>> {
+        let build = |stop_trigger: StopTrigger| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    99.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    3,
+                ),
+                // The best bid drops to 99 without a trade.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | CANCEL_ORDER_EVENT,
+                    500,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            Ok(Backtest::builder()
+                .add_asset(
+                    l3_asset(data, NoPartialFillExchange)
+                        .stop_trigger(stop_trigger)
+                        .build()?,
+                )
+                .build()?)
+        };
+
+        let mut hbt = build(StopTrigger::BestBid)?;
+        hbt.elapse(1)?;
+        hbt.submit_sell_order(
+            0,
+            10,
+            99.0,
+            1.0,
+            TimeInForce::GTC,
+            OrdType::StopMarket,
+            true,
+        )?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::New);
+        hbt.elapse(1000)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Filled);
+        assert_eq!(order.exec_price_tick, 99);
+        assert_eq!(hbt.position(0), -1.0);
+
+        // Without a trade, the stop keyed off the last trade stays pending.
+        let mut hbt = build(StopTrigger::LastTrade)?;
+        hbt.elapse(1)?;
+        hbt.submit_sell_order(
+            0,
+            10,
+            99.0,
+            1.0,
+            TimeInForce::GTC,
+            OrdType::StopMarket,
+            true,
+        )?;
+        hbt.elapse(1000)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(hbt.position(0), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn exchange_order_leads_local_order_by_latency() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                100,
+                100.0,
+                1.0,
+                3,
+            ),
+            // The feed order queued behind the backtest order is filled.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                200,
+                100.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, NoPartialFillExchange)
+                    .fill_counterparty(false)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        // The exchange has accepted the order, but the local hasn't received the response yet.
+        hbt.elapse(15)?;
+        let order = hbt.exchange_order(0, 10).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.leaves_qty, 1.0);
+        assert_eq!(order.qty_ahead, Some(1.0));
+        assert_eq!(hbt.orders(0).get(&10).unwrap().req, Status::New);
+
+        // The order is filled in the exchange at 200, which the local learns of at 220.
+        hbt.elapse(194)?;
+        let order = hbt.exchange_order(0, 10).unwrap();
+        assert_eq!(order.status, Status::Filled);
+        assert_eq!(order.leaves_qty, 0.0);
+        assert_eq!(order.qty_ahead, None);
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::New);
+        assert_eq!(hbt.position(0), 0.0);
+
+        hbt.elapse(20)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::Filled);
+        assert_eq!(hbt.position(0), 1.0);
+        // The local view is in sync once the response is received.
+        assert!(hbt.exchange_order(0, 10).is_none());
+        Ok(())
+    }
 }
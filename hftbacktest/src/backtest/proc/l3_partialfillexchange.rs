@@ -1,4 +1,5 @@
 use core::time;
+use std::{cmp::Reverse, collections::BTreeMap};
 
 use uuid::timestamp;
 
@@ -6,21 +7,87 @@ use crate::{
     backtest::{
         BacktestError,
         assettype::AssetType,
+        auction::{AuctionFill, AuctionResult},
+        fillgap::{FillGap, FillGapRecorder, FillPriceBucket, FillPriceHistogram},
+        fillrecord::{FillKind, FillRecord, FillRecorder},
         models::{FeeModel, L3QueueModel, LatencyModel},
         order::{self, ExchToLocal},
-        proc::Processor,
+        printdivergence::{PrintDivergence, PrintDivergenceRecorder},
+        proc::{
+            ExchangeOrderView, FeedFillDedup, FillResponseMode, HaltPolicy, LockedMarketPolicy,
+            MaxLifetimes, ModifyMode, PriorityResetPolicy, Processor, RecentFills, StopOrders,
+            StopTrigger, SubLotResidualPolicy, allocate_auction_fills, has_both_sides,
+            lacks_price_improvement, offset_feed_order_id, sort_by_fill_sequence,
+        },
+        shadowfill::{ShadowFill, ShadowFillRecorder},
         state::State,
     },
     depth::{INVALID_MAX, INVALID_MIN, L3MarketDepth},
-    prelude::OrdType,
+    prelude::{OrdType, format_price},
     types::{
-        AUCTION_UPDATE_EVENT, BUY_EVENT, DEPTH_CLEAR_EVENT, EXCH_ASK_ADD_ORDER_EVENT,
+        AUCTION_UPDATE_EVENT, BUY_EVENT, CancelReason, DEPTH_CLEAR_EVENT, EXCH_ASK_ADD_ORDER_EVENT,
         EXCH_ASK_DEPTH_CLEAR_EVENT, EXCH_BID_ADD_ORDER_EVENT, EXCH_BID_DEPTH_CLEAR_EVENT,
         EXCH_CANCEL_ORDER_EVENT, EXCH_DEPTH_CLEAR_EVENT, EXCH_EVENT, EXCH_FILL_EVENT,
-        EXCH_MODIFY_ORDER_EVENT, Event, Order, OrderId, SELL_EVENT, Side, Status, TimeInForce,
+        EXCH_HALT_EVENT, EXCH_MODIFY_ORDER_EVENT, EXCH_RESUME_EVENT, EXCH_TRADE_EVENT, Event,
+        Order, OrderId, RejectReason, SELL_EVENT, Side, Status, TimeInForce,
     },
 };
 
+/// Determines how the exchange prevents an incoming backtest order from trading against the
+/// resting backtest orders on the opposite side.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum SelfTradePrevention {
+    /// Self-trade prevention is disabled.
+    #[default]
+    None,
+    /// Cancels the quantity of the resting orders that the incoming order would trade against.
+    /// The strategy receives a response with [`CancelReason::SelfTradePrevention`] and the
+    /// canceled quantity for each affected resting order.
+    CancelResting,
+    /// Cancels the incoming order without matching, leaving the resting orders intact. The
+    /// strategy receives a response with [`CancelReason::SelfTradePrevention`] and the canceled
+    /// quantity for the incoming order.
+    CancelNewest,
+    /// Cancels both the quantity of the resting orders that the incoming order would trade
+    /// against and the incoming order itself.
+    CancelBoth,
+}
+
+/// Determines what the exchange does when the session opens without an opening auction price
+/// having been delivered by an [`AUCTION_UPDATE_EVENT`], in which case the orders accumulated
+/// before the open are never uncrossed and may leave the order book crossed.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum AuctionFallbackPolicy {
+    /// The accumulated orders stay in the order book as they are.
+    #[default]
+    None,
+    /// Uncrosses the accumulated orders at the theoretical opening price determined by the
+    /// [`AuctionAlgorithm`], choosing the lowest among the prices still tied. The orders are
+    /// matched as if the auction price had been delivered at the session open.
+    TheoreticalUncross,
+    /// Expires the backtest orders accumulated before the open.
+    ClearPreOpen,
+}
+
+/// Determines the uncross rule by which the auction price is chosen among the prices at which
+/// the accumulated orders cross. The matched quantity is the smaller of the bid quantity priced
+/// at or above the price and the ask quantity priced at or below it, and the imbalance is the
+/// difference between them.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum AuctionAlgorithm {
+    /// Uncrosses at the price delivered by the [`AUCTION_UPDATE_EVENT`]. The theoretical opening
+    /// price of [`AuctionFallbackPolicy::TheoreticalUncross`] follows
+    /// [`AuctionAlgorithm::MaxVolume`].
+    #[default]
+    FeedPrice,
+    /// Maximizes the matched quantity and then minimizes the imbalance, ignoring the price
+    /// delivered by the feed.
+    MaxVolume,
+    /// Minimizes the imbalance and then maximizes the matched quantity, ignoring the price
+    /// delivered by the feed.
+    MinImbalance,
+}
+
 pub struct L3PartialFillExchange<AT, LM, QM, MD, FM>
 where
     AT: AssetType,
@@ -35,6 +102,40 @@ where
     order_e2l: ExchToLocal<LM>,
 
     auction_processed: bool,
+    in_auction: bool,
+    last_auction: Option<AuctionResult>,
+    fill_gaps: FillGapRecorder,
+    fill_records: FillRecorder,
+    fill_price_histogram: FillPriceHistogram,
+    print_divergence: PrintDivergenceRecorder,
+    shadow_fills: ShadowFillRecorder<LM>,
+    self_trade_prevention: SelfTradePrevention,
+    two_phase_ack: bool,
+    priority_reset_policy: PriorityResetPolicy,
+    modify_mode: ModifyMode,
+    halt_policy: HaltPolicy,
+    sub_lot_residual_policy: SubLotResidualPolicy,
+    fill_response_mode: FillResponseMode,
+    locked_market_policy: LockedMarketPolicy,
+    halted: bool,
+    warmup: bool,
+    min_price_improvement: i64,
+    feed_order_id_offset: u64,
+    fill_dedup: FeedFillDedup,
+    session_open: i64,
+    auction_fallback_policy: AuctionFallbackPolicy,
+    auction_algorithm: AuctionAlgorithm,
+    // Whether the session open has been reached and handled.
+    session_opened: bool,
+    // Whether an opening auction price has been delivered.
+    opening_uncrossed: bool,
+    recent_fills: RecentFills,
+    stop_orders: StopOrders,
+    max_lifetimes: MaxLifetimes,
+    last_exch_order_id: u64,
+    batch_interval: i64,
+    next_cycle_ts: i64,
+    pending_batch: Vec<Order>,
 }
 
 impl<AT, LM, QM, MD, FM> L3PartialFillExchange<AT, LM, QM, MD, FM>
@@ -61,16 +162,327 @@ where
             order_e2l,
 
             auction_processed: false,
+            in_auction: false,
+            last_auction: None,
+            fill_gaps: Default::default(),
+            fill_records: Default::default(),
+            fill_price_histogram: Default::default(),
+            print_divergence: Default::default(),
+            shadow_fills: Default::default(),
+            self_trade_prevention: Default::default(),
+            two_phase_ack: false,
+            priority_reset_policy: Default::default(),
+            modify_mode: Default::default(),
+            halt_policy: Default::default(),
+            sub_lot_residual_policy: Default::default(),
+            fill_response_mode: Default::default(),
+            locked_market_policy: Default::default(),
+            halted: false,
+            warmup: false,
+            min_price_improvement: 0,
+            feed_order_id_offset: 0,
+            fill_dedup: Default::default(),
+            session_open: i64::MIN,
+            auction_fallback_policy: Default::default(),
+            auction_algorithm: Default::default(),
+            session_opened: false,
+            opening_uncrossed: false,
+            recent_fills: Default::default(),
+            stop_orders: Default::default(),
+            max_lifetimes: Default::default(),
+            last_exch_order_id: 0,
+            batch_interval: 0,
+            next_cycle_ts: 0,
+            pending_batch: Vec::new(),
+        }
+    }
+
+    /// Sets the initial capacity of the fill gap recorder, which records the mid price at the
+    /// time of each fill. The default value is `0`, indicating that no fill gap is recorded.
+    pub fn fill_gap_capacity(self, capacity: usize) -> Self {
+        Self {
+            fill_gaps: FillGapRecorder::new(capacity),
+            ..self
+        }
+    }
+
+    /// Sets whether every fill is recorded as a trade log. The default value is `false`.
+    pub fn fill_records(self, enabled: bool) -> Self {
+        Self {
+            fill_records: FillRecorder::new(enabled),
+            ..self
+        }
+    }
+
+    /// Sets whether the executed quantity of the fills is accumulated by the distance from the
+    /// mid price at the time of each fill. The default value is `false`.
+    pub fn fill_price_histogram(self, enabled: bool) -> Self {
+        Self {
+            fill_price_histogram: FillPriceHistogram::new(enabled),
+            ..self
+        }
+    }
+
+    /// Sets the window in nanoseconds around each fill within which the nearest market print is
+    /// looked for, to compare the fills with the actual trades in the feed. The default value is
+    /// `0`, indicating that no fill is compared.
+    pub fn print_divergence_window(self, window: i64) -> Self {
+        Self {
+            print_divergence: PrintDivergenceRecorder::new(window),
+            ..self
+        }
+    }
+
+    /// Sets the alternative order entry latency under which each new order is replayed as a
+    /// shadow order, to compare the liquidity it would have taken on arrival with the liquidity
+    /// it actually takes. See [`ShadowFillRecorder`]. The default value is `None`, indicating that
+    /// no order is replayed.
+    pub fn shadow_latency_model(self, latency_model: Option<LM>) -> Self {
+        Self {
+            shadow_fills: ShadowFillRecorder::new(latency_model),
+            ..self
+        }
+    }
+
+    /// Sets the self-trade prevention mode. The default value is [`SelfTradePrevention::None`].
+    pub fn self_trade_prevention(self, self_trade_prevention: SelfTradePrevention) -> Self {
+        Self {
+            self_trade_prevention,
+            ..self
+        }
+    }
+
+    /// Sets whether a new order is acknowledged before it is matched. If enabled, the exchange
+    /// always responds with [`Status::New`] first, and then responds with the matching result,
+    /// such as a fill, at the same timestamp. The second response is omitted if the order simply
+    /// rests in the order book. The default value is `false`.
+    pub fn two_phase_ack(self, two_phase_ack: bool) -> Self {
+        Self {
+            two_phase_ack,
+            ..self
+        }
+    }
+
+    /// Sets whether a replaced order loses its queue priority. The default value is
+    /// [`PriorityResetPolicy::KeepOnSizeDown`].
+    pub fn priority_reset_policy(self, priority_reset_policy: PriorityResetPolicy) -> Self {
+        Self {
+            priority_reset_policy,
+            ..self
+        }
+    }
+
+    /// Sets how a modify request is processed. The default value is [`ModifyMode::Amend`].
+    pub fn modify_mode(self, modify_mode: ModifyMode) -> Self {
+        Self {
+            modify_mode,
+            ..self
+        }
+    }
+
+    /// Sets how the resting backtest orders are handled when trading halts by
+    /// [`HALT_EVENT`](crate::types::HALT_EVENT). Regardless of the policy, new orders and
+    /// modifications are rejected with [`RejectReason::TradingHalted`] until trading resumes by
+    /// [`RESUME_EVENT`](crate::types::RESUME_EVENT). The default value is
+    /// [`HaltPolicy::KeepOrders`].
+    pub fn halt_policy(self, halt_policy: HaltPolicy) -> Self {
+        Self {
+            halt_policy,
+            ..self
+        }
+    }
+
+    /// Sets whether the residual of a partially filled backtest order that falls below the lot
+    /// size is left resting or canceled with [`CancelReason::SubLotResidual`]. The default value
+    /// is [`SubLotResidualPolicy::Keep`].
+    pub fn sub_lot_residual_policy(self, sub_lot_residual_policy: SubLotResidualPolicy) -> Self {
+        Self {
+            sub_lot_residual_policy,
+            ..self
+        }
+    }
+
+    /// Sets whether the fills of an order sweeping multiple price levels, whose remainder expires
+    /// after the sweep, are responded one per level or aggregated into a single response with the
+    /// total filled quantity and the average price. The default value is
+    /// [`FillResponseMode::PerFill`].
+    pub fn fill_response_mode(self, fill_response_mode: FillResponseMode) -> Self {
+        Self {
+            fill_response_mode,
+            ..self
+        }
+    }
+
+    /// Sets whether a new [`TimeInForce::GTC`] limit order that would lock or cross the market is
+    /// matched or rejected with [`RejectReason::WouldLockMarket`]. The check doesn't apply during
+    /// an auction, where the order book can be crossed. The default value is
+    /// [`LockedMarketPolicy::Match`].
+    pub fn locked_market_policy(self, locked_market_policy: LockedMarketPolicy) -> Self {
+        Self {
+            locked_market_policy,
+            ..self
+        }
+    }
+
+    /// Sets whether the exchange starts in the warmup, during which new orders are rejected with
+    /// [`RejectReason::BookNotEstablished`] so that nothing is matched against an order book that
+    /// is still empty or one-sided. The warmup ends once both sides of the market depth have at
+    /// least one level. The default value is `false`.
+    pub fn warmup(self, warmup: bool) -> Self {
+        Self { warmup, ..self }
+    }
+
+    /// Ends the warmup once both sides of the market depth have at least one level.
+    fn update_warmup(&mut self) {
+        if self.warmup && has_both_sides(&self.depth) {
+            self.warmup = false;
+        }
+    }
+
+    /// Sets the minimum price improvement in ticks that a modify request must make when it
+    /// improves the order price, as on venues that don't let an order jump the queue by a marginal
+    /// reprice. A modify that improves the price by less than this is rejected with
+    /// [`RejectReason::InsufficientPriceImprovement`]. The default value is `0`, indicating no
+    /// requirement.
+    pub fn min_price_improvement(self, min_price_improvement: i64) -> Self {
+        Self {
+            min_price_improvement,
+            ..self
+        }
+    }
+
+    /// Sets the offset added to the order IDs of the market feed events at ingestion, so that the
+    /// market feed order IDs can't collide with the backtest order IDs. It should be the same for
+    /// the local and the exchange of the asset. The default value is `0`, indicating that the
+    /// order IDs are used as they are.
+    pub fn feed_order_id_offset(self, feed_order_id_offset: u64) -> Self {
+        Self {
+            feed_order_id_offset,
+            ..self
+        }
+    }
+
+    /// Sets the window in nanoseconds of exchange time within which a market feed fill event
+    /// repeating a previous fill's exchange timestamp, price, quantity, and order ID is ignored as
+    /// a duplicate. The default value is `0`, indicating that every fill event is processed.
+    pub fn fill_dedup_window(self, fill_dedup_window: i64) -> Self {
+        Self {
+            fill_dedup: FeedFillDedup::new(fill_dedup_window),
+            ..self
+        }
+    }
+
+    /// Sets the reference price that triggers a stop order. The default value is
+    /// [`StopTrigger::LastTrade`].
+    pub fn stop_trigger(self, stop_trigger: StopTrigger) -> Self {
+        Self {
+            stop_orders: StopOrders::new(stop_trigger),
+            ..self
+        }
+    }
+
+    /// Sets the time at which the session opens. Before the open, only GTC limit orders entered to
+    /// participate in the opening auction, see [`Order::opening_auction`], are accepted, and they
+    /// rest in the queue without matching until the auction. Any other order is rejected with
+    /// [`RejectReason::OrderEntryNotAllowed`]. An IOC order never participates in an auction; one
+    /// that arrives while an auction is in progress expires without matching. The default value
+    /// is [`i64::MIN`], indicating that orders are accepted at any time.
+    pub fn session_open(self, session_open: i64) -> Self {
+        Self {
+            session_open,
+            ..self
+        }
+    }
+
+    /// Sets what the exchange does when the session opens without an opening auction price
+    /// having been delivered. The default value is [`AuctionFallbackPolicy::None`].
+    pub fn auction_fallback_policy(self, auction_fallback_policy: AuctionFallbackPolicy) -> Self {
+        Self {
+            auction_fallback_policy,
+            ..self
+        }
+    }
+
+    /// Sets the uncross rule by which the auction price is chosen. The default value is
+    /// [`AuctionAlgorithm::FeedPrice`].
+    pub fn auction_algorithm(self, auction_algorithm: AuctionAlgorithm) -> Self {
+        Self {
+            auction_algorithm,
+            ..self
+        }
+    }
+
+    /// Returns `true` if the order can be entered at the given time. No order can be entered while
+    /// trading is halted, and before the session opens, only the orders participating in the
+    /// opening auction can be entered.
+    fn accepts_order_entry(&self, order: &Order, timestamp: i64) -> bool {
+        !self.halted
+            && (timestamp >= self.session_open
+                || (order.opening_auction
+                    && order.order_type == OrdType::Limit
+                    && order.time_in_force == TimeInForce::GTC))
+    }
+
+    /// Returns the price levels in ticks of the market feed orders on the given side, from the best
+    /// price outward.
+    fn level_ticks(&self, side: Side) -> Vec<i64> {
+        let mut ticks: Vec<i64> = self
+            .depth
+            .orders()
+            .values()
+            .filter(|order| order.side == side)
+            .map(|order| order.price_tick)
+            .collect();
+        match side {
+            Side::Buy => ticks.sort_unstable_by_key(|&tick| Reverse(tick)),
+            _ => ticks.sort_unstable(),
         }
+        ticks.dedup();
+        ticks
     }
 
+    /// Returns the backtest order resting in the queue.
+    fn resting_order(&self, order: &Order) -> Option<Order> {
+        let orders = match order.side {
+            Side::Buy => self.queue_model.get_all_bid_orders(),
+            _ => self.queue_model.get_all_ask_orders(),
+        };
+        orders
+            .into_iter()
+            .find(|resting| resting.order_id == order.order_id)
+    }
+
+    /// Sets the matching cycle interval for the batch matching, as on a frequent batch auction
+    /// venue. If it is positive, an incoming order that would take liquidity is not matched
+    /// immediately but queued until the next cycle boundary, which is a multiple of the interval.
+    /// At the boundary, all queued orders are matched at a single uncross price in price-time
+    /// priority. The cycle runs when the exchange first processes an event or an order at or after
+    /// the boundary. An IOC order is never queued; it is matched immediately as in continuous
+    /// matching, and its remainder expires. The default value is `0`, indicating continuous
+    /// matching.
+    pub fn batch_interval(self, batch_interval: i64) -> Self {
+        Self {
+            batch_interval,
+            ..self
+        }
+    }
+
+    fn ack_before_match(&mut self, order: &Order, timestamp: i64) {
+        let mut ack = order.clone();
+        ack.status = Status::New;
+        ack.exch_timestamp = timestamp;
+        self.respond(ack);
+    }
+
+    /// Expires the order at `timestamp`. Like any other response, the expiry reaches the local
+    /// after the order response latency.
     fn expired(&mut self, mut order: Order, timestamp: i64) -> Result<(), BacktestError> {
         order.exec_qty = 0.0;
         order.leaves_qty = 0.0;
         order.status = Status::Expired;
         order.exch_timestamp = timestamp;
 
-        self.order_e2l.respond(order);
+        self.respond(order);
         Ok(())
     }
 
@@ -81,6 +493,7 @@ where
         maker: bool,
         exec_price_tick: i64,
         fill_qty: f64,
+        kind: FillKind,
     ) -> Result<(), BacktestError> {
         // println!("Partial fill: order_id={}, fill_qty={}, leaves_qty={}", order.order_id, fill_qty, order.leaves_qty);
         if order.status == Status::Expired
@@ -102,250 +515,1309 @@ where
 
         order.exec_qty = actual_fill_qty;
         order.leaves_qty -= actual_fill_qty;
+        order.cum_exec_qty += actual_fill_qty;
+        order.cum_exec_notional += order.exec_price() * actual_fill_qty;
 
         // Update status based on remaining quantity
         if order.leaves_qty <= 0.0 {
             order.status = Status::Filled;
+            self.recent_fills.insert(order.order_id);
         } else {
             order.status = Status::PartiallyFilled;
         }
 
         order.exch_timestamp = timestamp;
 
-        self.state.apply_fill(order);
+        let fee = self.state.apply_fill(order);
+        self.fill_gaps.record(order, &self.depth, fee);
+        self.fill_records.record(order, fee, kind);
+        self.fill_price_histogram.record(order, &self.depth);
+        self.print_divergence.on_fill(order);
 
         if MAKE_RESPONSE {
-            self.order_e2l.respond(order.clone());
+            self.respond(order.clone());
         }
         Ok(())
     }
 
-    fn fill_ask_orders_by_crossing(
-        &mut self,
-        prev_best_tick: i64,
-        new_best_tick: i64,
-        timestamp: i64,
-    ) -> Result<(), BacktestError> {
-        let filled = self
-            .queue_model
-            .on_best_bid_update(prev_best_tick, new_best_tick)?;
-        for mut order in filled {
-            let price_tick = order.price_tick;
-            // For crossing orders, we assume full fill at the order's limit price
-            let fill_qty = order.leaves_qty;
-            self.partial_fill::<true>(&mut order, timestamp, true, price_tick, fill_qty)?;
-        }
-        Ok(())
+    /// Returns `true` if the order is left with a residual below the lot size that must be
+    /// canceled under the [`SubLotResidualPolicy`].
+    fn is_sub_lot_residual(&self, order: &Order) -> bool {
+        self.sub_lot_residual_policy == SubLotResidualPolicy::Cancel
+            && order.leaves_qty > 0.0
+            && order.leaves_qty / self.depth.lot_size() + 1e-9 < 1.0
     }
 
-    fn fill_bid_orders_by_crossing(
-        &mut self,
-        prev_best_tick: i64,
-        new_best_tick: i64,
-        timestamp: i64,
-    ) -> Result<(), BacktestError> {
-        let filled = self
-            .queue_model
-            .on_best_ask_update(prev_best_tick, new_best_tick)?;
-        for mut order in filled {
-            let price_tick = order.price_tick;
-            // For crossing orders, we assume full fill at the order's limit price
-            let fill_qty = order.leaves_qty;
-            self.partial_fill::<true>(&mut order, timestamp, true, price_tick, fill_qty)?;
-        }
-        Ok(())
+    /// Cancels the sub-lot residual of the order. The order must already be out of the queue.
+    fn cancel_sub_lot_residual(&mut self, order: &mut Order, timestamp: i64) {
+        order.canceled_qty = order.leaves_qty;
+        order.leaves_qty = 0.0;
+        order.exec_qty = 0.0;
+        order.status = Status::Canceled;
+        order.cancel_reason = CancelReason::SubLotResidual;
+        order.exch_timestamp = timestamp;
     }
 
-    fn try_fill_at_touch(
-        &mut self,
-        order: &mut Order,
-        timestamp: i64,
-    ) -> Result<bool, BacktestError> {
-        if order.side == Side::Buy {
-            let best_ask_tick = self.depth.best_ask_tick();
-            if order.price_tick >= best_ask_tick {
-                // Get available quantity at best ask
-                let available_qty = self.depth.ask_qty_at_tick(best_ask_tick);
-                if available_qty > 0.0 {
-                    let fill_qty = available_qty.min(order.leaves_qty);
-                    self.partial_fill::<false>(order, timestamp, false, best_ask_tick, fill_qty)?;
-                    return Ok(true);
+    /// Applies the [`AuctionFallbackPolicy`] once the session opens by `timestamp`, if no opening
+    /// auction price has been delivered by then.
+    fn open_session_if_due(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        if self.session_opened || timestamp < self.session_open {
+            return Ok(());
+        }
+        self.session_opened = true;
+        if self.opening_uncrossed {
+            return Ok(());
+        }
+        match self.auction_fallback_policy {
+            AuctionFallbackPolicy::None => {}
+            AuctionFallbackPolicy::TheoreticalUncross => {
+                if let Some(price_tick) = self.theoretical_auction_price_tick() {
+                    self.opening_uncrossed = true;
+                    self.uncross_auction(price_tick as f64 * self.depth.tick_size(), timestamp)?;
                 }
             }
-        } else {
-            let best_bid_tick = self.depth.best_bid_tick();
-            if order.price_tick <= best_bid_tick {
-                // Get available quantity at best bid
-                let available_qty = self.depth.bid_qty_at_tick(best_bid_tick);
-                if available_qty > 0.0 {
-                    let fill_qty = available_qty.min(order.leaves_qty);
-                    self.partial_fill::<false>(order, timestamp, false, best_bid_tick, fill_qty)?;
-                    return Ok(true);
+            AuctionFallbackPolicy::ClearPreOpen => {
+                let mut resting_orders = self.queue_model.get_all_bid_orders();
+                resting_orders.append(&mut self.queue_model.get_all_ask_orders());
+                for resting_order in resting_orders {
+                    if !self
+                        .queue_model
+                        .contains_backtest_order(resting_order.order_id)
+                    {
+                        continue;
+                    }
+                    let order = self
+                        .queue_model
+                        .cancel_backtest_order(resting_order.order_id, &self.depth)?;
+                    self.expired(order, timestamp)?;
                 }
             }
         }
-        Ok(false)
+        Ok(())
     }
 
-    // TODO unchecked
-    fn ack_new(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
-        if self.queue_model.contains_backtest_order(order.order_id) {
-            return Err(BacktestError::OrderIdExist);
-        }
-
-        // Normal trading mode - with immediate matching
-        match order.order_type {
-            OrdType::Limit => {
-                match order.time_in_force {
-                    TimeInForce::GTC | TimeInForce::GTX => {
-                        // Try immediate execution first
-                        let filled = self.try_fill_at_touch(order, timestamp)?;
-
-                        if order.leaves_qty > 0.0 {
-                            // If not fully filled, add to book
-                            if order.time_in_force == TimeInForce::GTX && filled {
-                                // GTX order touched the market, expire remaining
-                                order.status = Status::Expired;
-                                order.exch_timestamp = timestamp;
-                            } else {
-                                // Add remaining quantity to book
-                                order.status = if filled {
-                                    Status::PartiallyFilled
-                                } else {
-                                    Status::New
-                                };
-                                order.exch_timestamp = timestamp;
-                                self.queue_model
-                                    .add_backtest_order(order.clone(), &self.depth)?;
-                            }
-                        }
-                        Ok(())
-                    }
-                    TimeInForce::IOC => {
-                        // Execute what we can and cancel the rest
-                        self.try_fill_at_touch(order, timestamp)?;
-                        if order.leaves_qty > 0.0 {
-                            order.status = Status::Expired;
-                            order.exch_timestamp = timestamp;
-                        }
-                        Ok(())
-                    }
-                    TimeInForce::FOK => {
-                        // Check if full quantity can be filled
-                        let can_fill_full = if order.side == Side::Buy {
-                            let best_ask_tick = self.depth.best_ask_tick();
-                            order.price_tick >= best_ask_tick
-                                && self.depth.ask_qty_at_tick(best_ask_tick) >= order.leaves_qty
-                        } else {
-                            let best_bid_tick = self.depth.best_bid_tick();
-                            order.price_tick <= best_bid_tick
-                                && self.depth.bid_qty_at_tick(best_bid_tick) >= order.leaves_qty
-                        };
+    /// Returns the price in ticks at which the accumulated orders would uncross under the
+    /// [`AuctionAlgorithm`], as described in [`AuctionFallbackPolicy::TheoreticalUncross`], or
+    /// `None` if they don't cross.
+    fn theoretical_auction_price_tick(&self) -> Option<i64> {
+        let bids = self.queue_model.get_all_bid_orders();
+        let asks = self.queue_model.get_all_ask_orders();
+        let mut price_ticks: Vec<i64> = bids
+            .iter()
+            .chain(asks.iter())
+            .map(|order| order.price_tick)
+            .collect();
+        price_ticks.sort_unstable();
+        price_ticks.dedup();
 
-                        if can_fill_full {
-                            self.try_fill_at_touch(order, timestamp)?;
-                        } else {
-                            order.status = Status::Expired;
-                            order.exch_timestamp = timestamp;
+        let mut uncross: Option<(i64, f64, f64)> = None;
+        for price_tick in price_ticks {
+            let demand: f64 = bids
+                .iter()
+                .filter(|order| order.price_tick >= price_tick)
+                .map(|order| order.leaves_qty)
+                .sum();
+            let supply: f64 = asks
+                .iter()
+                .filter(|order| order.price_tick <= price_tick)
+                .map(|order| order.leaves_qty)
+                .sum();
+            let volume = demand.min(supply);
+            let imbalance = (demand - supply).abs();
+            if volume > 0.0
+                && uncross.is_none_or(|(_, max_volume, min_imbalance)| {
+                    match self.auction_algorithm {
+                        AuctionAlgorithm::FeedPrice | AuctionAlgorithm::MaxVolume => {
+                            volume > max_volume
+                                || (volume == max_volume && imbalance < min_imbalance)
                         }
-                        Ok(())
-                    }
-                    TimeInForce::Unsupported => Err(BacktestError::InvalidOrderRequest),
-                }
-            }
-            OrdType::Market => {
-                // Market orders try to fill against available liquidity
-                if order.side == Side::Buy {
-                    let mut remaining_qty = order.leaves_qty;
-                    let mut tick = self.depth.best_ask_tick();
-
-                    while remaining_qty > 0.0 && tick < self.depth.best_ask_tick() {
-                        let available_qty = self.depth.ask_qty_at_tick(tick);
-                        if available_qty > 0.0 {
-                            let fill_qty = available_qty.min(remaining_qty);
-                            self.partial_fill::<false>(order, timestamp, false, tick, fill_qty)?;
-                            remaining_qty = order.leaves_qty;
-                        }
-                        tick += 1;
-                    }
-                } else {
-                    let mut remaining_qty = order.leaves_qty;
-                    let mut tick = self.depth.best_bid_tick();
-
-                    while remaining_qty > 0.0 && tick > self.depth.best_bid_tick() {
-                        let available_qty = self.depth.bid_qty_at_tick(tick);
-                        if available_qty > 0.0 {
-                            let fill_qty = available_qty.min(remaining_qty);
-                            self.partial_fill::<false>(order, timestamp, false, tick, fill_qty)?;
-                            remaining_qty = order.leaves_qty;
+                        AuctionAlgorithm::MinImbalance => {
+                            imbalance < min_imbalance
+                                || (imbalance == min_imbalance && volume > max_volume)
                         }
-                        tick -= 1;
                     }
-                }
-
-                // If market order couldn't be fully filled, expire remaining
-                if order.leaves_qty > 0.0 {
-                    order.status = Status::Expired;
-                    order.exch_timestamp = timestamp;
-                }
-                Ok(())
+                })
+            {
+                uncross = Some((price_tick, volume, imbalance));
             }
-            OrdType::Unsupported => Err(BacktestError::InvalidOrderRequest),
         }
+        uncross.map(|(price_tick, _, _)| price_tick)
     }
 
-    // TODO unchecked
-    fn ack_cancel(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
-        match self
-            .queue_model
-            .cancel_backtest_order(order.order_id, &self.depth)
-        {
-            Ok(exch_order) => {
-                let _ = std::mem::replace(order, exch_order);
+    /// Uncrosses the orders accumulated during the auction at `auction_price`. The orders priced
+    /// better than the auction price are fully matched, and the quantity at the auction price is
+    /// allocated to the side with the larger quantity.
+    fn uncross_auction(&mut self, auction_price: f64, timestamp: i64) -> Result<(), BacktestError> {
+        let auction_price_tick = (auction_price / self.depth.tick_size()).round() as i64;
+        self.last_auction = Some(AuctionResult {
+            price: auction_price,
+            exch_ts: timestamp,
+            matched_qty: 0.0,
+            fills: Vec::new(),
+        });
 
-                order.status = Status::Canceled;
-                order.exch_timestamp = timestamp;
-                Ok(())
-            }
-            Err(BacktestError::OrderNotFound) => {
-                order.req = Status::Rejected;
-                order.exch_timestamp = timestamp;
-                Ok(())
+        println!(
+            "[AUCTION] Processing auction at price: {} (tick: {})",
+            auction_price, auction_price_tick
+        );
+
+        // 1. 获取所有能成交的订单
+        // 买单：价格 >= 集合竞价价格
+        let mut filled_bids = Vec::new();
+        let mut bids_at_auction_price = Vec::new();
+        let mut total_bid_qty_ge_auction = 0.0;
+
+        let all_bid_orders = self.queue_model.get_all_bid_orders();
+        for order in all_bid_orders {
+            if order.price_tick > auction_price_tick {
+                total_bid_qty_ge_auction += order.leaves_qty;
+                filled_bids.push(order);
+            } else if order.price_tick == auction_price_tick {
+                total_bid_qty_ge_auction += order.leaves_qty;
+                bids_at_auction_price.push(order);
             }
-            Err(e) => Err(e),
         }
-    }
 
-    // TODO unchecked
-    fn ack_modify<const RESET_QUEUE_POS: bool>(
-        &mut self,
-        order: &mut Order,
-        timestamp: i64,
-    ) -> Result<(), BacktestError> {
-        match self
-            .queue_model
-            .modify_backtest_order(order.order_id, order, &self.depth)
-        {
-            Ok(()) => {
-                order.exch_timestamp = timestamp;
-                Ok(())
-            }
-            Err(BacktestError::OrderNotFound) => {
-                order.req = Status::Rejected;
-                order.exch_timestamp = timestamp;
-                Ok(())
+        // 卖单：价格 <= 集合竞价价格
+        let mut filled_asks = Vec::new();
+        let mut asks_at_auction_price = Vec::new();
+        let mut total_ask_qty_le_auction = 0.0;
+
+        let all_ask_orders = self.queue_model.get_all_ask_orders();
+        for order in all_ask_orders {
+            if order.price_tick < auction_price_tick {
+                total_ask_qty_le_auction += order.leaves_qty;
+                filled_asks.push(order);
+            } else if order.price_tick == auction_price_tick {
+                total_ask_qty_le_auction += order.leaves_qty;
+                asks_at_auction_price.push(order);
             }
-            Err(e) => Err(e),
         }
-    }
-}
 
-impl<AT, LM, QM, MD, FM> Processor for L3PartialFillExchange<AT, LM, QM, MD, FM>
-where
-    AT: AssetType,
-    LM: LatencyModel,
-    QM: L3QueueModel<MD>,
-    MD: L3MarketDepth,
+        println!(
+            "[AUCTION] Orders above/below auction price - Bids: {}, Asks: {}",
+            filled_bids.len(),
+            filled_asks.len()
+        );
+        println!(
+            "[AUCTION] Orders at auction price - Bids: {} (qty: {}), Asks: {} (qty: {})",
+            bids_at_auction_price.len(),
+            total_bid_qty_ge_auction,
+            asks_at_auction_price.len(),
+            total_ask_qty_le_auction
+        );
+
+        let matched_qty = total_bid_qty_ge_auction.min(total_ask_qty_le_auction);
+        if (matched_qty / self.depth.lot_size()).round() as i64 == 0 {
+            // Without a counterparty on the other side, nothing is matched, so the orders
+            // on the one side stay in the order book as they are.
+            return Ok(());
+        }
+        if let Some(auction) = self.last_auction.as_mut() {
+            auction.matched_qty = matched_qty;
+        }
+
+        // 2. 处理价格优于集合竞价价格的订单（全部成交）
+        for mut order in filled_bids {
+            let fill_qty = order.leaves_qty;
+            self.fill_in_auction(&mut order, fill_qty, auction_price_tick, timestamp)?;
+        }
+
+        for mut order in filled_asks {
+            let fill_qty = order.leaves_qty;
+            self.fill_in_auction(&mut order, fill_qty, auction_price_tick, timestamp)?;
+        }
+
+        // 3. 处理价格等于集合竞价价格的订单
+        if !bids_at_auction_price.is_empty() || !asks_at_auction_price.is_empty() {
+            if total_bid_qty_ge_auction <= total_ask_qty_le_auction {
+                // 买单数量少，买单全部成交
+                for mut order in bids_at_auction_price {
+                    let fill_qty = order.leaves_qty;
+                    self.fill_in_auction(&mut order, fill_qty, auction_price_tick, timestamp)?;
+                }
+
+                // left_qty 是开盘后剩余在卖1的数量
+                let left_qty = total_ask_qty_le_auction - total_bid_qty_ge_auction;
+                // 计算卖单的总量
+                let mut total_asks_qty = 0.0;
+                for order in &asks_at_auction_price {
+                    total_asks_qty += order.leaves_qty;
+                }
+                // 需要成交的总量
+                let need_to_fill = total_asks_qty - left_qty;
+                let qtys = asks_at_auction_price
+                    .iter()
+                    .map(|order| order.leaves_qty)
+                    .collect::<Vec<_>>();
+                let fill_qtys = allocate_auction_fills(&qtys, need_to_fill, self.depth.lot_size());
+
+                for (mut order, order_fill_qty) in asks_at_auction_price.into_iter().zip(fill_qtys)
+                {
+                    if order_fill_qty <= 0.0 {
+                        continue; // 剩余订单保留在订单簿中
+                    }
+
+                    let is_backtest_order =
+                        self.queue_model.contains_backtest_order(order.order_id);
+                    self.fill_in_auction(
+                        &mut order,
+                        order_fill_qty,
+                        auction_price_tick,
+                        timestamp,
+                    )?;
+                    if is_backtest_order {
+                        // The backtest order has been notified of its fill.
+                        continue;
+                    }
+
+                    // pass an order is_auction
+                    order.exec_price_tick = auction_price_tick;
+                    order.exch_timestamp = timestamp;
+                    order.qty = left_qty;
+                    order.is_auction = true;
+                    self.order_e2l.respond(order.clone());
+                }
+            } else {
+                // 卖单数量少，卖单全部成交
+                for mut order in asks_at_auction_price {
+                    let fill_qty = order.leaves_qty;
+                    self.fill_in_auction(&mut order, fill_qty, auction_price_tick, timestamp)?;
+                }
+
+                let left_qty = total_bid_qty_ge_auction - total_ask_qty_le_auction;
+
+                let mut total_bids_qty = 0.0;
+                for order in &bids_at_auction_price {
+                    total_bids_qty += order.leaves_qty;
+                }
+
+                let need_to_fill = total_bids_qty - left_qty;
+                let qtys = bids_at_auction_price
+                    .iter()
+                    .map(|order| order.leaves_qty)
+                    .collect::<Vec<_>>();
+                let fill_qtys = allocate_auction_fills(&qtys, need_to_fill, self.depth.lot_size());
+
+                for (mut order, order_fill_qty) in bids_at_auction_price.into_iter().zip(fill_qtys)
+                {
+                    if order_fill_qty <= 0.0 {
+                        continue;
+                    }
+
+                    let is_backtest_order =
+                        self.queue_model.contains_backtest_order(order.order_id);
+                    self.fill_in_auction(
+                        &mut order,
+                        order_fill_qty,
+                        auction_price_tick,
+                        timestamp,
+                    )?;
+                    if is_backtest_order {
+                        // The backtest order has been notified of its fill.
+                        continue;
+                    }
+                    // pass an order is_auction
+                    order.exec_price_tick = auction_price_tick;
+                    order.exch_timestamp = timestamp;
+                    order.qty = -left_qty;
+                    order.is_auction = true;
+                    self.order_e2l.respond(order.clone());
+                }
+            }
+
+            println!(
+                "[AUCTION] Auction completed. Opening price: {}",
+                auction_price
+            );
+
+            // 打印5档深度
+            println!("[AUCTION] Post-auction market depth (5 levels):");
+            println!("         Bid                    Ask");
+            println!("  Price      Qty        Price      Qty");
+            println!("---------- --------   ---------- --------");
+
+            // 获取5档深度
+            let tick_size = self.depth.tick_size();
+            let to_price = |(price_tick, qty): (i64, f64)| (price_tick as f64 * tick_size, qty);
+            let bid_levels: Vec<(f64, f64)> =
+                self.depth.bid_levels(5).into_iter().map(to_price).collect();
+            let ask_levels: Vec<(f64, f64)> =
+                self.depth.ask_levels(5).into_iter().map(to_price).collect();
+
+            // 打印深度表格
+            for i in 0..5 {
+                let bid_str = if i < bid_levels.len() {
+                    format!(
+                        "{:>10} {:8.0}",
+                        format_price(tick_size, bid_levels[i].0),
+                        bid_levels[i].1
+                    )
+                } else {
+                    format!("{:10} {:8}", "--", "--")
+                };
+
+                let ask_str = if i < ask_levels.len() {
+                    format!(
+                        "{:>10} {:8.0}",
+                        format_price(tick_size, ask_levels[i].0),
+                        ask_levels[i].1
+                    )
+                } else {
+                    format!("{:10} {:8}", "--", "--")
+                };
+
+                println!("{}   {}", bid_str, ask_str);
+            }
+
+            // 打印最优买卖价和价差
+            if self.depth.best_bid_tick() != INVALID_MIN
+                && self.depth.best_ask_tick() != INVALID_MAX
+            {
+                let best_bid = self.depth.best_bid();
+                let best_ask = self.depth.best_ask();
+                let spread = best_ask - best_bid;
+                let spread_ticks = self.depth.best_ask_tick() - self.depth.best_bid_tick();
+                let mid_price = (best_bid + best_ask) / 2.0;
+
+                println!();
+                println!("[AUCTION] Summary:");
+                println!(
+                    "  Best Bid: {} (qty: {:.0})",
+                    format_price(tick_size, best_bid),
+                    self.depth.bid_qty_at_tick(self.depth.best_bid_tick())
+                );
+                println!(
+                    "  Best Ask: {} (qty: {:.0})",
+                    format_price(tick_size, best_ask),
+                    self.depth.ask_qty_at_tick(self.depth.best_ask_tick())
+                );
+                println!("  Mid Price: {}", format_price(tick_size, mid_price));
+                println!(
+                    "  Spread: {} ({} ticks)",
+                    format_price(tick_size, spread),
+                    spread_ticks
+                );
+            } else if self.depth.best_bid_tick() != INVALID_MIN {
+                println!();
+                println!("[AUCTION] Only bid side has orders");
+                println!(
+                    "  Best Bid: {} (qty: {:.0})",
+                    format_price(tick_size, self.depth.best_bid()),
+                    self.depth.bid_qty_at_tick(self.depth.best_bid_tick())
+                );
+            } else if self.depth.best_ask_tick() != INVALID_MAX {
+                println!();
+                println!("[AUCTION] Only ask side has orders");
+                println!(
+                    "  Best Ask: {} (qty: {:.0})",
+                    format_price(tick_size, self.depth.best_ask()),
+                    self.depth.ask_qty_at_tick(self.depth.best_ask_tick())
+                );
+            } else {
+                println!();
+                println!("[AUCTION] No orders in the book");
+            }
+        }
+        Ok(())
+    }
+
+    /// Matches the order by `fill_qty` at the auction price. A backtest order is filled and its
+    /// matched quantity is attributed to the last auction result, while a market feed order is
+    /// removed from or reduced in the market depth.
+    fn fill_in_auction(
+        &mut self,
+        order: &mut Order,
+        fill_qty: f64,
+        auction_price_tick: i64,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        if self.queue_model.contains_backtest_order(order.order_id) {
+            if fill_qty >= order.leaves_qty {
+                *order = self
+                    .queue_model
+                    .cancel_backtest_order(order.order_id, &self.depth)?;
+            }
+            self.partial_fill::<false>(
+                order,
+                timestamp,
+                false,
+                auction_price_tick,
+                fill_qty,
+                FillKind::Auction,
+            )?;
+            let sub_lot_residual = self.is_sub_lot_residual(order);
+            if sub_lot_residual {
+                self.queue_model
+                    .cancel_backtest_order(order.order_id, &self.depth)?;
+            } else if order.leaves_qty > 0.0 {
+                // Reducing the quantity doesn't lose the queue priority.
+                self.queue_model
+                    .modify_backtest_order(order.order_id, order, &self.depth)?;
+            }
+            // Marks the fill as the auction fill for the local.
+            let mut resp = order.clone();
+            resp.is_auction = true;
+            self.respond(resp);
+
+            if let Some(auction) = self.last_auction.as_mut() {
+                auction.fills.push(AuctionFill {
+                    order_id: order.order_id,
+                    side: order.side,
+                    matched_qty: order.exec_qty,
+                    leaves_qty: order.leaves_qty,
+                    status: order.status,
+                });
+            }
+            if sub_lot_residual {
+                self.cancel_sub_lot_residual(order, timestamp);
+                self.respond(order.clone());
+            }
+        } else if fill_qty >= order.leaves_qty {
+            self.depth.delete_order(order.order_id, timestamp)?;
+            self.queue_model
+                .cancel_market_feed_order(order.order_id, &self.depth)?;
+        } else if fill_qty > 0.0 {
+            let remaining_qty = order.leaves_qty - fill_qty;
+            self.depth.modify_order(
+                order.order_id,
+                auction_price_tick as f64 * self.depth.tick_size(),
+                remaining_qty,
+                timestamp,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the order would take liquidity and thus should wait for the next matching
+    /// cycle.
+    fn is_batched(&self, order: &Order) -> bool {
+        // An IOC order never participates in the batch auction.
+        if self.batch_interval <= 0 || order.time_in_force == TimeInForce::IOC {
+            return false;
+        }
+        match order.order_type {
+            OrdType::Market | OrdType::StopMarket => true,
+            OrdType::Limit if order.time_in_force != TimeInForce::GTX => match order.side {
+                Side::Buy => order.price_tick >= self.depth.best_ask_tick(),
+                Side::Sell => order.price_tick <= self.depth.best_bid_tick(),
+                Side::None | Side::Unsupported => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Matches the queued orders if the matching cycle has ended by `timestamp`.
+    fn match_batch_if_due(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        if self.pending_batch.is_empty() || timestamp < self.next_cycle_ts {
+            return Ok(());
+        }
+        let orders = std::mem::take(&mut self.pending_batch);
+        self.uncross_batch(orders, timestamp)
+    }
+
+    /// Matches the queued orders of both sides, against each other and against the resting
+    /// orders, at a single price. The price maximizes the executed quantity, then minimizes the
+    /// imbalance, and then is the closest to the best bid and offer before the uncross. On each
+    /// side, the quantity is allocated in price priority and then in arrival order.
+    fn uncross_batch(
+        &mut self,
+        mut orders: Vec<Order>,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        let limit_tick = |order: &Order| match (order.order_type, order.side) {
+            (OrdType::Market | OrdType::StopMarket, Side::Buy) => i64::MAX,
+            (OrdType::Market | OrdType::StopMarket, _) => i64::MIN,
+            _ => order.price_tick,
+        };
+        let crosses = |order: &Order, price_tick: i64| match order.side {
+            Side::Buy => limit_tick(order) >= price_tick,
+            _ => limit_tick(order) <= price_tick,
+        };
+        // The sort is stable, so the arrival order is kept within the same limit price.
+        orders.sort_by_key(|order| match order.side {
+            Side::Buy => -limit_tick(order),
+            _ => limit_tick(order),
+        });
+
+        let mut resting_bids = BTreeMap::new();
+        let mut resting_asks = BTreeMap::new();
+        for resting_order in self.depth.orders().values() {
+            let levels = match resting_order.side {
+                Side::Buy => &mut resting_bids,
+                _ => &mut resting_asks,
+            };
+            *levels.entry(resting_order.price_tick).or_insert(0.0) += resting_order.qty;
+        }
+        let mut price_ticks: Vec<i64> = resting_bids
+            .keys()
+            .chain(resting_asks.keys())
+            .copied()
+            .chain(orders.iter().map(limit_tick))
+            .filter(|&price_tick| price_tick != i64::MAX && price_tick != i64::MIN)
+            .collect();
+        price_ticks.sort_unstable();
+        price_ticks.dedup();
+
+        let best_bid_tick = self.depth.best_bid_tick();
+        let best_ask_tick = self.depth.best_ask_tick();
+        // As `(price_tick, bid_volume, ask_volume, volume, imbalance, distance)`, where the bid and
+        // ask volumes are the quantities executed by the queued bids and asks.
+        let mut uncross: Option<(i64, f64, f64, f64, f64, i64)> = None;
+        for price_tick in price_ticks {
+            let queued_qty = |side: Side| -> f64 {
+                orders
+                    .iter()
+                    .filter(|order| order.side == side && crosses(order, price_tick))
+                    .map(|order| order.leaves_qty)
+                    .sum()
+            };
+            let queued_bid_qty = queued_qty(Side::Buy);
+            let queued_ask_qty = queued_qty(Side::Sell);
+            let resting_bid_qty: f64 = resting_bids.range(price_tick..).map(|(_, qty)| qty).sum();
+            let resting_ask_qty: f64 = resting_asks.range(..=price_tick).map(|(_, qty)| qty).sum();
+            let demand = queued_bid_qty + resting_bid_qty;
+            let supply = queued_ask_qty + resting_ask_qty;
+            let volume = demand.min(supply);
+            if volume <= 0.0 || (queued_bid_qty <= 0.0 && queued_ask_qty <= 0.0) {
+                continue;
+            }
+            let imbalance = (demand - supply).abs();
+            let distance = best_bid_tick
+                .saturating_sub(price_tick)
+                .max(price_tick.saturating_sub(best_ask_tick))
+                .max(0);
+            if uncross.is_none_or(|(_, _, _, max_volume, min_imbalance, min_distance)| {
+                volume > max_volume
+                    || (volume == max_volume
+                        && (imbalance < min_imbalance
+                            || (imbalance == min_imbalance && distance < min_distance)))
+            }) {
+                uncross = Some((
+                    price_tick,
+                    queued_bid_qty.min(supply),
+                    queued_ask_qty.min(demand),
+                    volume,
+                    imbalance,
+                    distance,
+                ));
+            }
+        }
+        let (price_tick, mut bid_volume, mut ask_volume, ..) =
+            uncross.unwrap_or((0, 0.0, 0.0, 0.0, 0.0, 0));
+
+        for mut order in orders {
+            let volume = match order.side {
+                Side::Buy => &mut bid_volume,
+                _ => &mut ask_volume,
+            };
+            let mut fill_qty = if crosses(&order, price_tick) {
+                order.leaves_qty.min(*volume)
+            } else {
+                0.0
+            };
+            if order.time_in_force == TimeInForce::FOK && fill_qty < order.leaves_qty {
+                fill_qty = 0.0;
+            }
+            if fill_qty > 0.0 {
+                *volume -= fill_qty;
+                self.partial_fill::<false>(
+                    &mut order,
+                    timestamp,
+                    false,
+                    price_tick,
+                    fill_qty,
+                    FillKind::Auction,
+                )?;
+            }
+            if fill_qty > 0.0 && self.is_sub_lot_residual(&order) {
+                // Responds the fill before the cancel of the residual.
+                self.respond(order.clone());
+                self.cancel_sub_lot_residual(&mut order, timestamp);
+            }
+            if order.leaves_qty > 0.0 {
+                order.exch_timestamp = timestamp;
+                if order.order_type == OrdType::Limit && order.time_in_force == TimeInForce::GTC {
+                    self.queue_model
+                        .add_backtest_order(order.clone(), &self.depth)?;
+                    self.max_lifetimes.on_rest(&order, timestamp);
+                    if fill_qty <= 0.0 {
+                        // The order has already been acknowledged as new.
+                        continue;
+                    }
+                } else {
+                    if fill_qty > 0.0 {
+                        // Responds the fill before the expiry of the remainder.
+                        self.respond(order.clone());
+                    }
+                    order.status = Status::Expired;
+                }
+            }
+            self.respond(order);
+        }
+        Ok(())
+    }
+
+    fn fill_ask_orders_by_crossing(
+        &mut self,
+        prev_best_tick: i64,
+        new_best_tick: i64,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        let mut filled = self
+            .queue_model
+            .on_best_bid_update(prev_best_tick, new_best_tick)?;
+        sort_by_fill_sequence(&mut filled);
+        for mut order in filled {
+            let price_tick = order.price_tick;
+            // For crossing orders, we assume full fill at the order's limit price
+            let fill_qty = order.leaves_qty;
+            self.partial_fill::<true>(
+                &mut order,
+                timestamp,
+                true,
+                price_tick,
+                fill_qty,
+                FillKind::Crossing,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn fill_bid_orders_by_crossing(
+        &mut self,
+        prev_best_tick: i64,
+        new_best_tick: i64,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        let mut filled = self
+            .queue_model
+            .on_best_ask_update(prev_best_tick, new_best_tick)?;
+        sort_by_fill_sequence(&mut filled);
+        for mut order in filled {
+            let price_tick = order.price_tick;
+            // For crossing orders, we assume full fill at the order's limit price
+            let fill_qty = order.leaves_qty;
+            self.partial_fill::<true>(
+                &mut order,
+                timestamp,
+                true,
+                price_tick,
+                fill_qty,
+                FillKind::Crossing,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns the price levels on the opposite side that the order can take, at or better than
+    /// `limit_tick` or across the whole side if it is `None`, as `(price_tick, qty)` from the best
+    /// price outward.
+    fn takeable_levels(&self, order: &Order, limit_tick: Option<i64>) -> Vec<(i64, f64)> {
+        let book_side = match order.side {
+            Side::Buy => Side::Sell,
+            _ => Side::Buy,
+        };
+        self.level_ticks(book_side)
+            .into_iter()
+            .take_while(|&tick| match (order.side, limit_tick) {
+                (_, None) => true,
+                (Side::Buy, Some(limit_tick)) => tick <= limit_tick,
+                (_, Some(limit_tick)) => tick >= limit_tick,
+            })
+            .map(|tick| match book_side {
+                Side::Sell => (tick, self.depth.ask_qty_at_tick(tick)),
+                _ => (tick, self.depth.bid_qty_at_tick(tick)),
+            })
+            .filter(|&(_, qty)| qty > 0.0)
+            .collect()
+    }
+
+    /// Returns `true` if the price levels that the order can take, at or better than
+    /// `limit_tick` or across the whole side if it is `None`, cover its leaves quantity.
+    fn can_fill_full(&self, order: &Order, limit_tick: Option<i64>) -> bool {
+        self.takeable_levels(order, limit_tick)
+            .iter()
+            .map(|&(_, qty)| qty)
+            .sum::<f64>()
+            >= order.leaves_qty
+    }
+
+    /// Sweeps the price levels of the opposite side from the best price outward, up to
+    /// `limit_tick` if given, with a separate fill at each level's price until the order is
+    /// filled, and returns the total filled quantity. The order then reports the fills as a single
+    /// execution at the average price, rounded to the nearest tick.
+    ///
+    /// If `respond_fills` is `true`, each fill is also responded as it occurs, which is for an
+    /// order whose remainder expires after the sweep. The terminal response then carries the
+    /// cumulative executed quantity. Under [`FillResponseMode::Batched`], the fills are instead
+    /// responded once as the aggregated execution.
+    fn sweep(
+        &mut self,
+        order: &mut Order,
+        timestamp: i64,
+        limit_tick: Option<i64>,
+        respond_fills: bool,
+    ) -> Result<f64, BacktestError> {
+        let mut filled_qty = 0.0;
+        let mut filled_tick_value = 0.0;
+        for (tick, available_qty) in self.takeable_levels(order, limit_tick) {
+            if order.leaves_qty <= 0.0 {
+                break;
+            }
+            let fill_qty = available_qty.min(order.leaves_qty);
+            self.partial_fill::<false>(
+                order,
+                timestamp,
+                false,
+                tick,
+                fill_qty,
+                FillKind::Crossing,
+            )?;
+            if respond_fills && self.fill_response_mode == FillResponseMode::PerFill {
+                self.respond(order.clone());
+            }
+            filled_qty += order.exec_qty;
+            filled_tick_value += order.exec_qty * tick as f64;
+        }
+        if filled_qty > 0.0 {
+            let avg_price = filled_tick_value / filled_qty * self.depth.tick_size();
+            self.shadow_fills.on_fill(order, filled_qty, avg_price);
+            order.exec_qty = filled_qty;
+            order.exec_price_tick = (filled_tick_value / filled_qty).round() as i64;
+            if respond_fills && self.fill_response_mode == FillResponseMode::Batched {
+                self.respond(order.clone());
+            }
+        }
+        Ok(filled_qty)
+    }
+
+    /// Matches the shadow orders that arrive at or before `timestamp` against the current market
+    /// depth, taking the liquidity as the actual order would on arrival.
+    fn update_shadow_fills(&mut self, timestamp: i64) {
+        if !self.shadow_fills.enabled() {
+            return;
+        }
+        for order in self.order_e2l.pending_requests() {
+            self.shadow_fills.on_request(order);
+        }
+        for order in self.shadow_fills.take_arrivals(timestamp) {
+            let limit_tick = (order.order_type == OrdType::Limit).then_some(order.price_tick);
+            let levels = self.takeable_levels(&order, limit_tick);
+            let mut qty = 0.0;
+            let mut tick_value = 0.0;
+            if order.time_in_force != TimeInForce::FOK || self.can_fill_full(&order, limit_tick) {
+                for (tick, available_qty) in levels {
+                    if qty >= order.leaves_qty {
+                        break;
+                    }
+                    let fill_qty = available_qty.min(order.leaves_qty - qty);
+                    qty += fill_qty;
+                    tick_value += fill_qty * tick as f64;
+                }
+            }
+            let price = if qty > 0.0 {
+                tick_value / qty * self.depth.tick_size()
+            } else {
+                f64::NAN
+            };
+            self.shadow_fills.on_shadow_fill(&order, qty, price);
+        }
+    }
+
+    /// Fills the order against the price levels between the touch and its limit price, and
+    /// returns the total filled quantity.
+    fn try_fill_at_touch(
+        &mut self,
+        order: &mut Order,
+        timestamp: i64,
+        respond_fills: bool,
+    ) -> Result<f64, BacktestError> {
+        let limit_tick = order.price_tick;
+        self.sweep(order, timestamp, Some(limit_tick), respond_fills)
+    }
+
+    /// Prevents the incoming order from trading against the resting backtest orders according to
+    /// the self-trade prevention mode, and returns `true` if the incoming order is canceled.
+    ///
+    /// Depending on the mode, the quantity of the resting backtest orders that the incoming order
+    /// would trade against is canceled from the most aggressive price, and the incoming order is
+    /// canceled as a whole, each with the canceled quantity.
+    fn cancel_self_trade(
+        &mut self,
+        order: &mut Order,
+        timestamp: i64,
+    ) -> Result<bool, BacktestError> {
+        if self.self_trade_prevention == SelfTradePrevention::None
+            || order.order_type != OrdType::Limit
+        {
+            return Ok(false);
+        }
+        let resting_orders = match order.side {
+            Side::Buy => self.queue_model.get_all_ask_orders(),
+            Side::Sell => self.queue_model.get_all_bid_orders(),
+            Side::None | Side::Unsupported => return Ok(false),
+        };
+        let crossed_orders: Vec<Order> = resting_orders
+            .into_iter()
+            .take_while(|resting_order| match order.side {
+                Side::Buy => resting_order.price_tick <= order.price_tick,
+                _ => resting_order.price_tick >= order.price_tick,
+            })
+            .filter(|resting_order| {
+                self.queue_model
+                    .contains_backtest_order(resting_order.order_id)
+            })
+            .collect();
+        if crossed_orders.is_empty() {
+            return Ok(false);
+        }
+
+        if self.self_trade_prevention != SelfTradePrevention::CancelNewest {
+            let mut remaining_qty = order.leaves_qty;
+            for mut resting_order in crossed_orders {
+                if remaining_qty <= 0.0 {
+                    break;
+                }
+                let canceled_qty = resting_order.leaves_qty.min(remaining_qty);
+                remaining_qty -= canceled_qty;
+                if canceled_qty < resting_order.leaves_qty {
+                    // Reducing the quantity doesn't lose the queue priority.
+                    resting_order.leaves_qty -= canceled_qty;
+                    resting_order.exch_timestamp = timestamp;
+                    self.queue_model.modify_backtest_order(
+                        resting_order.order_id,
+                        &mut resting_order,
+                        &self.depth,
+                    )?;
+                } else {
+                    resting_order = self
+                        .queue_model
+                        .cancel_backtest_order(resting_order.order_id, &self.depth)?;
+                    resting_order.leaves_qty = 0.0;
+                    resting_order.status = Status::Canceled;
+                    resting_order.exch_timestamp = timestamp;
+                }
+                resting_order.exec_qty = 0.0;
+                resting_order.cancel_reason = CancelReason::SelfTradePrevention;
+                resting_order.canceled_qty = canceled_qty;
+                self.respond(resting_order);
+            }
+        }
+
+        if self.self_trade_prevention == SelfTradePrevention::CancelResting {
+            return Ok(false);
+        }
+        order.canceled_qty = order.leaves_qty;
+        order.leaves_qty = 0.0;
+        order.exec_qty = 0.0;
+        order.status = Status::Canceled;
+        order.cancel_reason = CancelReason::SelfTradePrevention;
+        order.exch_timestamp = timestamp;
+        Ok(true)
+    }
+
+    // TODO unchecked
+    fn ack_new(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
+        if self.queue_model.contains_backtest_order(order.order_id)
+            || self.stop_orders.get(order.order_id).is_some()
+            || self
+                .pending_batch
+                .iter()
+                .any(|pending| pending.order_id == order.order_id)
+        {
+            return Err(BacktestError::OrderIdExist);
+        }
+
+        if self.halted {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::TradingHalted;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+
+        self.update_warmup();
+        if self.warmup {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::BookNotEstablished;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+
+        if !self.accepts_order_entry(order, timestamp) {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::OrderEntryNotAllowed;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+
+        if timestamp >= self.session_open
+            && !self.in_auction
+            && self.locked_market_policy.rejects(
+                order,
+                self.depth.best_bid_tick(),
+                self.depth.best_ask_tick(),
+            )
+        {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::WouldLockMarket;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        order.exch_order_id = self.next_exch_order_id();
+        if order.order_type == OrdType::StopMarket
+            && !self.stop_orders.is_triggered(order, &self.depth)
+        {
+            // Waits for the trigger.
+            order.status = Status::New;
+            order.exch_timestamp = timestamp;
+            self.stop_orders.insert(order.clone());
+            return Ok(());
+        }
+        if timestamp < self.session_open {
+            // Waits for the opening auction.
+            order.status = Status::New;
+            order.exch_timestamp = timestamp;
+            self.queue_model
+                .add_backtest_order(order.clone(), &self.depth)?;
+            self.max_lifetimes.on_rest(order, timestamp);
+            return Ok(());
+        }
+
+        if self.in_auction && order.time_in_force == TimeInForce::IOC {
+            // An IOC order never participates in the auction, so it expires without matching.
+            order.status = Status::Expired;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+
+        if self.cancel_self_trade(order, timestamp)? {
+            return Ok(());
+        }
+
+        if self.is_batched(order) {
+            // Waits for the next matching cycle.
+            order.status = Status::New;
+            order.exch_timestamp = timestamp;
+            if self.pending_batch.is_empty() {
+                self.next_cycle_ts = (timestamp / self.batch_interval + 1) * self.batch_interval;
+            }
+            self.pending_batch.push(order.clone());
+            return Ok(());
+        }
+
+        // Normal trading mode - with immediate matching
+        match order.order_type {
+            OrdType::Limit => {
+                match order.time_in_force {
+                    TimeInForce::GTC | TimeInForce::GTX => {
+                        // Try immediate execution first
+                        let respond_fills = order.time_in_force == TimeInForce::GTX
+                            && !self.can_fill_full(order, Some(order.price_tick));
+                        let filled = self.try_fill_at_touch(order, timestamp, respond_fills)?;
+
+                        if order.time_in_force == TimeInForce::GTC
+                            && filled > 0.0
+                            && self.is_sub_lot_residual(order)
+                        {
+                            // Responds the fill before the cancel of the residual.
+                            self.respond(order.clone());
+                            self.cancel_sub_lot_residual(order, timestamp);
+                        }
+                        if order.leaves_qty > 0.0 {
+                            // If not fully filled, add to book
+                            if order.time_in_force == TimeInForce::GTX && filled > 0.0 {
+                                // GTX order touched the market, expire remaining
+                                order.status = Status::Expired;
+                                order.exch_timestamp = timestamp;
+                            } else {
+                                // Add remaining quantity to book
+                                order.status = if filled > 0.0 {
+                                    Status::PartiallyFilled
+                                } else {
+                                    Status::New
+                                };
+                                order.exch_timestamp = timestamp;
+                                self.queue_model
+                                    .add_backtest_order(order.clone(), &self.depth)?;
+                                self.max_lifetimes.on_rest(order, timestamp);
+                            }
+                        }
+                        Ok(())
+                    }
+                    TimeInForce::IOC => {
+                        // Execute what we can and cancel the rest
+                        let respond_fills = !self.can_fill_full(order, Some(order.price_tick));
+                        self.try_fill_at_touch(order, timestamp, respond_fills)?;
+                        if order.leaves_qty > 0.0 {
+                            order.status = Status::Expired;
+                            order.exch_timestamp = timestamp;
+                        }
+                        Ok(())
+                    }
+                    TimeInForce::FOK => {
+                        // Check if full quantity can be filled across the levels within the
+                        // limit price
+                        if self.can_fill_full(order, Some(order.price_tick)) {
+                            self.try_fill_at_touch(order, timestamp, false)?;
+                        } else {
+                            order.status = Status::Expired;
+                            order.exch_timestamp = timestamp;
+                        }
+                        Ok(())
+                    }
+                    TimeInForce::Unsupported => Err(BacktestError::InvalidOrderRequest),
+                }
+            }
+            OrdType::Market | OrdType::StopMarket => {
+                // Market orders walk the book outward from the best price until they are filled
+                // or the opposite side is exhausted.
+                let respond_fills = !self.can_fill_full(order, None);
+                self.sweep(order, timestamp, None, respond_fills)?;
+
+                // If market order couldn't be fully filled, expire remaining
+                if order.leaves_qty > 0.0 {
+                    order.status = Status::Expired;
+                    order.exch_timestamp = timestamp;
+                }
+                Ok(())
+            }
+            OrdType::Unsupported => Err(BacktestError::InvalidOrderRequest),
+        }
+    }
+
+    // TODO unchecked
+    fn ack_cancel(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
+        if let Some(stop_order) = self.stop_orders.remove(order.order_id) {
+            *order = stop_order;
+            order.status = Status::Canceled;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        if let Some(i) = self
+            .pending_batch
+            .iter()
+            .position(|pending| pending.order_id == order.order_id)
+        {
+            *order = self.pending_batch.remove(i);
+            order.status = Status::Canceled;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        match self
+            .queue_model
+            .cancel_backtest_order(order.order_id, &self.depth)
+        {
+            Ok(exch_order) => {
+                let _ = std::mem::replace(order, exch_order);
+
+                order.status = Status::Canceled;
+                order.exch_timestamp = timestamp;
+                Ok(())
+            }
+            Err(BacktestError::OrderNotFound) => {
+                order.req = Status::Rejected;
+                order.reject_reason = self.recent_fills.reject_reason(order.order_id);
+                order.exch_timestamp = timestamp;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // TODO unchecked
+    /// Replaces the order by re-adding it at the tail of its price level.
+    fn reset_queue_pos(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
+        self.queue_model
+            .cancel_backtest_order(order.order_id, &self.depth)?;
+        order.exch_timestamp = timestamp;
+        self.queue_model
+            .add_backtest_order(order.clone(), &self.depth)
+    }
+
+    /// Replaces the order by a cancel followed by a new order, which gets a new exchange order ID
+    /// and joins the tail of its price level.
+    fn cancel_replace(&mut self, order: &mut Order, timestamp: i64) -> Result<(), BacktestError> {
+        self.queue_model
+            .cancel_backtest_order(order.order_id, &self.depth)?;
+        order.exch_order_id = self.next_exch_order_id();
+        order.exch_timestamp = timestamp;
+        self.queue_model
+            .add_backtest_order(order.clone(), &self.depth)
+    }
+
+    /// Executes the stop orders triggered by the current reference price as market orders.
+    fn trigger_stop_orders(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        if self.halted {
+            return Ok(());
+        }
+        for mut order in self.stop_orders.take_triggered(&self.depth) {
+            self.ack_new(&mut order, timestamp)?;
+            self.respond(order);
+        }
+        Ok(())
+    }
+
+    /// Halts trading, canceling the resting backtest orders if the halt policy requires it.
+    fn halt(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        self.halted = true;
+        if self.halt_policy != HaltPolicy::CancelOrders {
+            return Ok(());
+        }
+        for mut order in self.stop_orders.drain() {
+            order.status = Status::Canceled;
+            order.cancel_reason = CancelReason::TradingHalt;
+            order.exch_timestamp = timestamp;
+            self.respond(order);
+        }
+        let mut resting_orders = self.queue_model.get_all_bid_orders();
+        resting_orders.append(&mut self.queue_model.get_all_ask_orders());
+        for resting_order in resting_orders {
+            if !self
+                .queue_model
+                .contains_backtest_order(resting_order.order_id)
+            {
+                continue;
+            }
+            let mut order = self
+                .queue_model
+                .cancel_backtest_order(resting_order.order_id, &self.depth)?;
+            order.canceled_qty = order.leaves_qty;
+            order.leaves_qty = 0.0;
+            order.exec_qty = 0.0;
+            order.status = Status::Canceled;
+            order.cancel_reason = CancelReason::TradingHalt;
+            order.exch_timestamp = timestamp;
+            self.respond(order);
+        }
+        Ok(())
+    }
+
+    /// Cancels the resting orders that have rested for their maximum lifetime by `timestamp`.
+    fn cancel_expired_lifetimes(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        for order_id in self.max_lifetimes.take_expired(timestamp) {
+            if !self.queue_model.contains_backtest_order(order_id) {
+                continue;
+            }
+            let mut order = self
+                .queue_model
+                .cancel_backtest_order(order_id, &self.depth)?;
+            order.canceled_qty = order.leaves_qty;
+            order.leaves_qty = 0.0;
+            order.exec_qty = 0.0;
+            order.status = Status::Canceled;
+            order.cancel_reason = CancelReason::MaxLifetime;
+            order.exch_timestamp = timestamp;
+            self.respond(order);
+        }
+        Ok(())
+    }
+
+    /// Sends the response to the local, ending the maximum lifetime of the order once it leaves
+    /// the order book.
+    fn respond(&mut self, order: Order) {
+        self.max_lifetimes.on_response(&order);
+        self.order_e2l.respond(order);
+    }
+
+    fn next_exch_order_id(&mut self) -> u64 {
+        self.last_exch_order_id += 1;
+        self.last_exch_order_id
+    }
+
+    fn ack_modify<const RESET_QUEUE_POS: bool>(
+        &mut self,
+        order: &mut Order,
+        timestamp: i64,
+    ) -> Result<(), BacktestError> {
+        if self.halted {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::TradingHalted;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        if let Some(i) = self
+            .pending_batch
+            .iter()
+            .position(|pending| pending.order_id == order.order_id)
+        {
+            // The queued order hasn't been matched yet, so it is replaced as is, losing its arrival
+            // priority in the matching cycle.
+            self.pending_batch.remove(i);
+            order.status = Status::New;
+            order.leaves_qty = order.qty;
+            order.exec_qty = 0.0;
+            order.exch_timestamp = timestamp;
+            self.pending_batch.push(order.clone());
+            return Ok(());
+        }
+        let resting = self.resting_order(order);
+        if let Some(resting) = resting.as_ref() {
+            // The request carries the executions seen by the local, which may lag behind.
+            order.cum_exec_qty = resting.cum_exec_qty;
+            order.cum_exec_notional = resting.cum_exec_notional;
+            // Only applied to the order if the modification is accepted.
+            order.modify_count = resting.modify_count + 1;
+        }
+        // The new quantity cannot leave a negative quantity to be filled.
+        if resting.is_some() && (order.cum_exec_qty - order.qty) / self.depth.lot_size() > 1e-9 {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::QtyBelowFilled;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        if self.min_price_improvement > 1
+            && resting.as_ref().is_some_and(|resting| {
+                lacks_price_improvement(
+                    order.side,
+                    resting.price_tick,
+                    order.price_tick,
+                    self.min_price_improvement,
+                )
+            })
+        {
+            order.req = Status::Rejected;
+            order.reject_reason = RejectReason::InsufficientPriceImprovement;
+            order.exch_timestamp = timestamp;
+            return Ok(());
+        }
+        let reset_queue_pos = RESET_QUEUE_POS
+            || resting.is_some_and(|resting| {
+                self.priority_reset_policy
+                    .resets(resting.price_tick, resting.qty, order)
+            });
+        let result = if self.modify_mode == ModifyMode::CancelReplace {
+            self.cancel_replace(order, timestamp)
+        } else if reset_queue_pos {
+            self.reset_queue_pos(order, timestamp)
+        } else {
+            self.queue_model
+                .modify_backtest_order(order.order_id, order, &self.depth)
+        };
+        match result {
+            Ok(()) => {
+                // No execution takes place by the modification.
+                order.exec_qty = 0.0;
+                order.exch_timestamp = timestamp;
+                Ok(())
+            }
+            Err(BacktestError::OrderNotFound) => {
+                order.req = Status::Rejected;
+                order.reject_reason = self.recent_fills.reject_reason(order.order_id);
+                order.exch_timestamp = timestamp;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<AT, LM, QM, MD, FM> Processor for L3PartialFillExchange<AT, LM, QM, MD, FM>
+where
+    AT: AssetType,
+    LM: LatencyModel,
+    QM: L3QueueModel<MD>,
+    MD: L3MarketDepth,
     FM: FeeModel,
     BacktestError: From<<MD as L3MarketDepth>::Error>,
 {
@@ -353,456 +1825,2291 @@ where
         event.is(EXCH_EVENT).then_some(event.exch_ts)
     }
 
-    fn process(&mut self, event: &Event) -> Result<(), BacktestError> {
-        if !event.is(AUCTION_UPDATE_EVENT) {
-            self.depth.set_allow_price_cross(false);
-            self.auction_processed = false;
-        } else if event.is(AUCTION_UPDATE_EVENT) {
-            self.depth.set_allow_price_cross(true);
-        }
+    fn process(&mut self, event: &Event) -> Result<(), BacktestError> {
+        let event = offset_feed_order_id(event, self.feed_order_id_offset);
+        let event = &*event;
+
+        // The shadow orders arriving before this event see the market depth prior to it.
+        self.update_shadow_fills(event.exch_ts - 1);
+        self.cancel_expired_lifetimes(event.exch_ts)?;
+        self.update_warmup();
+        self.match_batch_if_due(event.exch_ts)?;
+
+        if !event.is(AUCTION_UPDATE_EVENT) {
+            // An auction event at the open may still deliver the opening auction price.
+            self.open_session_if_due(event.exch_ts)?;
+            self.depth.set_allow_price_cross(false);
+            self.auction_processed = false;
+            self.in_auction = false;
+        } else if event.is(AUCTION_UPDATE_EVENT) {
+            self.depth.set_allow_price_cross(true);
+            self.in_auction = true;
+        }
+
+        if event.is(EXCH_HALT_EVENT) {
+            self.halt(event.exch_ts)?;
+        } else if event.is(EXCH_RESUME_EVENT) {
+            self.halted = false;
+        } else if event.is(EXCH_BID_DEPTH_CLEAR_EVENT) {
+            self.depth.clear_orders(Side::Buy);
+            let expired = self.queue_model.clear_orders(Side::Buy);
+            for order in expired {
+                self.expired(order, event.exch_ts)?;
+            }
+        } else if event.is(EXCH_ASK_DEPTH_CLEAR_EVENT) {
+            self.depth.clear_orders(Side::Sell);
+            let expired = self.queue_model.clear_orders(Side::Sell);
+            for order in expired {
+                self.expired(order, event.exch_ts)?;
+            }
+        } else if event.is(EXCH_DEPTH_CLEAR_EVENT) {
+            // println!("[EXCHANGE] FULL DEPTH CLEAR");
+            self.depth.clear_orders(Side::None);
+            let expired = self.queue_model.clear_orders(Side::None);
+            for order in expired {
+                self.expired(order, event.exch_ts)?;
+            }
+        } else if event.is(EXCH_BID_ADD_ORDER_EVENT) {
+            // println!("exch");
+            let (prev_best_bid_tick, best_bid_tick) =
+                self.depth
+                    .add_buy_order(event.order_id, event.px, event.qty, event.exch_ts)?;
+            self.queue_model.add_market_feed_order(event, &self.depth)?;
+
+            // println!("[EXCHANGE] BID added: prev_best={}, new_best={}", prev_best_bid_tick, best_bid_tick);
+
+            if !event.is(AUCTION_UPDATE_EVENT) && best_bid_tick > prev_best_bid_tick {
+                // println!("ask partial fill crossing fill!");
+                self.fill_ask_orders_by_crossing(prev_best_bid_tick, best_bid_tick, event.exch_ts)?;
+            }
+        } else if event.is(EXCH_ASK_ADD_ORDER_EVENT) {
+            // println!("exch");
+            let (prev_best_ask_tick, best_ask_tick) =
+                self.depth
+                    .add_sell_order(event.order_id, event.px, event.qty, event.exch_ts)?;
+            self.queue_model.add_market_feed_order(event, &self.depth)?;
+
+            // println!("[EXCHANGE] ASK added: prev_best={}, new_best={}", prev_best_ask_tick, best_ask_tick);
+
+            if !event.is(AUCTION_UPDATE_EVENT) && best_ask_tick < prev_best_ask_tick {
+                // println!("bid partial fill crossing fill!");
+                self.fill_bid_orders_by_crossing(prev_best_ask_tick, best_ask_tick, event.exch_ts)?;
+            }
+        } else if event.is(EXCH_CANCEL_ORDER_EVENT) {
+            let order_id = event.order_id;
+            self.depth.delete_order(order_id, event.exch_ts)?;
+            self.queue_model
+                .cancel_market_feed_order(event.order_id, &self.depth)?;
+        } else if event.is(EXCH_FILL_EVENT) {
+            if event.is(BUY_EVENT) || event.is(SELL_EVENT) {
+                if self.fill_dedup.is_duplicate(event) {
+                    return Ok(());
+                }
+                // println!("[EXCHANGE] Processing FILL event for market feed order");
+                let mut filled = self.queue_model.fill_market_feed_order::<false>(
+                    event.order_id,
+                    event,
+                    &self.depth,
+                )?;
+                sort_by_fill_sequence(&mut filled);
+                let timestamp = event.exch_ts;
+                let fill_qty = event.qty; // The quantity from the market feed fill event
+                for mut order in filled {
+                    // Partial fill based on the quantity that the queue model allocates to the
+                    // order out of the market feed fill quantity
+                    let order_fill_qty = self.queue_model.allocated_qty(&order, fill_qty);
+                    let price_tick = order.price_tick;
+                    self.partial_fill::<true>(
+                        &mut order,
+                        timestamp,
+                        true,
+                        price_tick,
+                        order_fill_qty,
+                        FillKind::Touch,
+                    )?;
+                    let resting = self.queue_model.contains_backtest_order(order.order_id);
+                    if self.is_sub_lot_residual(&order) {
+                        if resting {
+                            self.queue_model
+                                .cancel_backtest_order(order.order_id, &self.depth)?;
+                        }
+                        self.cancel_sub_lot_residual(&mut order, timestamp);
+                        self.respond(order);
+                    } else if resting {
+                        // The queue model keeps the residual resting, which only needs to reflect
+                        // the fill.
+                        self.queue_model.modify_backtest_order(
+                            order.order_id,
+                            &mut order,
+                            &self.depth,
+                        )?;
+                    }
+                }
+                self.stop_orders
+                    .on_trade((event.px / self.depth.tick_size()).round() as i64);
+                self.print_divergence.on_print(event.exch_ts, event.px);
+            } else if event.is(AUCTION_UPDATE_EVENT) && !self.auction_processed {
+                self.auction_processed = true;
+                self.opening_uncrossed = true;
+                let auction_price = match self.auction_algorithm {
+                    AuctionAlgorithm::FeedPrice => event.px,
+                    // Falls back to the price delivered by the feed if the orders don't cross.
+                    AuctionAlgorithm::MaxVolume | AuctionAlgorithm::MinImbalance => self
+                        .theoretical_auction_price_tick()
+                        .map_or(event.px, |price_tick| {
+                            price_tick as f64 * self.depth.tick_size()
+                        }),
+                };
+                self.uncross_auction(auction_price, event.exch_ts)?;
+            }
+        } else if event.is(EXCH_TRADE_EVENT) {
+            self.stop_orders
+                .on_trade((event.px / self.depth.tick_size()).round() as i64);
+            self.print_divergence.on_print(event.exch_ts, event.px);
+        }
+
+        if event.is(AUCTION_UPDATE_EVENT) {
+            return Ok(());
+        }
+        self.trigger_stop_orders(event.exch_ts)
+    }
+
+    // TODO unchecked
+    fn process_recv_order(
+        &mut self,
+        timestamp: i64,
+        wait_resp_order_id: Option<OrderId>,
+    ) -> Result<bool, BacktestError> {
+        self.update_shadow_fills(timestamp);
+        self.cancel_expired_lifetimes(timestamp)?;
+        self.match_batch_if_due(timestamp)?;
+        self.open_session_if_due(timestamp)?;
+
+        while let Some(mut order) = self.order_e2l.receive(timestamp) {
+            // Processes a new order.
+            if order.req == Status::New {
+                order.req = Status::None;
+                if self.two_phase_ack && self.accepts_order_entry(&order, timestamp) {
+                    self.ack_before_match(&order, timestamp);
+                    self.ack_new(&mut order, timestamp)?;
+                    self.shadow_fills.on_arrival(&order, timestamp);
+                    // The acknowledgement has already conveyed the resting order.
+                    if order.status == Status::New && order.req == Status::None {
+                        continue;
+                    }
+                } else {
+                    self.ack_new(&mut order, timestamp)?;
+                    self.shadow_fills.on_arrival(&order, timestamp);
+                }
+            }
+            // Processes a cancel order.
+            else if order.req == Status::Canceled {
+                order.req = Status::None;
+                self.ack_cancel(&mut order, timestamp)?;
+            }
+            // Processes a modify order.
+            else if order.req == Status::Replaced {
+                order.req = Status::None;
+                self.ack_modify::<false>(&mut order, timestamp)?;
+            } else {
+                return Err(BacktestError::InvalidOrderRequest);
+            }
+            // Makes the response.
+            self.respond(order);
+        }
+        Ok(false)
+    }
+
+    fn earliest_recv_order_timestamp(&self) -> i64 {
+        // Wakes up at the earliest lifetime deadline to cancel the order exactly when it expires,
+        // at the session open to apply the auction fallback policy exactly when it opens, and at
+        // the earliest shadow arrival to complete its shadow fill even if no event follows it.
+        let session_open = if self.session_opened
+            || self.session_open == i64::MIN
+            || self.auction_fallback_policy == AuctionFallbackPolicy::None
+        {
+            i64::MAX
+        } else {
+            self.session_open
+        };
+        self.order_e2l
+            .earliest_recv_order_timestamp()
+            .unwrap_or(i64::MAX)
+            .min(self.max_lifetimes.earliest().unwrap_or(i64::MAX))
+            .min(session_open)
+            .min(self.shadow_fills.earliest_arrival().unwrap_or(i64::MAX))
+    }
+
+    fn earliest_send_order_timestamp(&self) -> i64 {
+        self.order_e2l
+            .earliest_send_order_timestamp()
+            .unwrap_or(i64::MAX)
+    }
+
+    fn fill_gaps(&self) -> &[FillGap] {
+        self.fill_gaps.fills()
+    }
+
+    fn clear_fill_gaps(&mut self) {
+        self.fill_gaps.clear();
+    }
+
+    fn fill_records(&self) -> &[FillRecord] {
+        self.fill_records.fills()
+    }
+
+    fn clear_fill_records(&mut self) {
+        self.fill_records.clear();
+    }
+
+    fn fill_price_histogram(&self) -> &[FillPriceBucket] {
+        self.fill_price_histogram.buckets()
+    }
+
+    fn clear_fill_price_histogram(&mut self) {
+        self.fill_price_histogram.clear();
+    }
+
+    fn print_divergences(&self) -> &[PrintDivergence] {
+        self.print_divergence.records()
+    }
+
+    fn clear_print_divergences(&mut self) {
+        self.print_divergence.clear();
+    }
+
+    fn shadow_fills(&self) -> &[ShadowFill] {
+        self.shadow_fills.records()
+    }
+
+    fn clear_shadow_fills(&mut self) {
+        self.shadow_fills.clear();
+    }
+
+    fn flush_print_divergences(&mut self) {
+        self.print_divergence.flush();
+    }
+
+    fn last_auction(&self) -> Option<&AuctionResult> {
+        self.last_auction.as_ref()
+    }
+
+    fn exchange_order(&self, order_id: OrderId) -> Option<ExchangeOrderView> {
+        if let Some(order) = self.stop_orders.get(order_id) {
+            return Some(ExchangeOrderView::new(order, None));
+        }
+        if let Some(order) = self.queue_model.backtest_order(order_id) {
+            return Some(ExchangeOrderView::new(
+                order,
+                self.queue_model.qty_ahead(order_id),
+            ));
+        }
+        self.order_e2l
+            .pending_response(order_id)
+            .map(|order| ExchangeOrderView::new(&order, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            AuctionAlgorithm, AuctionFallbackPolicy, Backtest, DataSource,
+            ExchangeKind::{NoPartialFillExchange, PartialFillExchange},
+            FillResponseMode, HaltPolicy, L3AssetBuilder, LockedMarketPolicy, ModifyMode,
+            PriorityResetPolicy, SelfTradePrevention, SubLotResidualPolicy,
+            assettype::LinearAsset,
+            data::Data,
+            models::{CommonFees, ConstantLatency, L3FIFOQueueModel, TradingValueFeeModel},
+            orderlog::OrderLogKind,
+            testutil::{l3_asset, l3_event},
+            timeline::OrderTimelineKind,
+        },
+        depth::{HashMapMarketDepth, INVALID_MAX, L3MarketDepth, MarketDepth},
+        prelude::{Bot, OrdType, OrderRequest, Side, Status, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, AUCTION_UPDATE_EVENT, BUY_EVENT, BuildError, CANCEL_ORDER_EVENT,
+            CancelReason, EXCH_EVENT, FILL_EVENT, HALT_EVENT, LOCAL_EVENT, RESUME_EVENT,
+            RejectReason, SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn auction_algorithm_selects_uncross_rule() -> Result<(), Box<dyn Error>> {
+        let build = |auction_algorithm: AuctionAlgorithm| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                // Crosses the order book during the auction.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                    100,
+                    99.0,
+                    4.0,
+                    3,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                    100,
+                    99.0,
+                    3.0,
+                    4,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | FILL_EVENT | AUCTION_UPDATE_EVENT,
+                    200,
+                    100.0,
+                    0.0,
+                    0,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .auction_algorithm(auction_algorithm)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 100.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+            hbt.elapse(500)?;
+            Ok(hbt)
+        };
+
+        // The bids of 3 at 100 and 3 at 99 against the ask of 4 at 99 match 4 with the imbalance
+        // of 2 at 99, and 3 with the imbalance of 1 at 100.
+        let hbt = build(AuctionAlgorithm::MaxVolume)?;
+        let auction = hbt.last_auction(0).unwrap();
+        assert_eq!((auction.price, auction.matched_qty), (99.0, 4.0));
+        assert_eq!(hbt.orders(0).get(&10).unwrap().exec_price(), 99.0);
+
+        let hbt = build(AuctionAlgorithm::MinImbalance)?;
+        let auction = hbt.last_auction(0).unwrap();
+        assert_eq!((auction.price, auction.matched_qty), (100.0, 3.0));
+        assert_eq!(hbt.orders(0).get(&10).unwrap().exec_price(), 100.0);
+
+        // Uncrosses at the price delivered by the feed regardless of the orders.
+        let hbt = build(AuctionAlgorithm::FeedPrice)?;
+        assert_eq!(hbt.last_auction(0).unwrap().price, 100.0);
+        Ok(())
+    }
+
+    #[test]
+    fn modify_below_filled_qty_is_rejected() -> Result<(), Box<dyn Error>> {
+        let print = |ts: i64| {
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                ts,
+                100.0,
+                1.0,
+                3,
+            )
+        };
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                100,
+                100.0,
+                5.0,
+                3,
+            ),
+            print(200),
+            print(300),
+            print(400),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_counterparty(false)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 3.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(349)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().cum_exec_qty, 2.0);
+
+        // The new quantity is below the 2.0 already filled.
+        hbt.modify(0, 10, 100.0, 1.0, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.req, Status::None);
+        assert_eq!(order.reject_reason, RejectReason::QtyBelowFilled);
+        let exch_order = hbt.exchange_order(0, 10).unwrap();
+        assert_eq!(exch_order.status, Status::PartiallyFilled);
+        assert_eq!(exch_order.leaves_qty, 1.0);
+
+        // The order keeps resting with its original quantity.
+        hbt.elapse(1000)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.cum_exec_qty, 3.0);
+        assert_eq!(order.status, Status::Filled);
+        Ok(())
+    }
+
+    #[test]
+    fn fill_response_mode_batches_sweep_fills() -> Result<(), Box<dyn Error>> {
+        let run = |fill_response_mode: FillResponseMode| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    102.0,
+                    1.0,
+                    3,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    103.0,
+                    1.0,
+                    4,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .order_timeline_capacity(1)
+                        .fill_response_mode(fill_response_mode)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            // Sweeps the three ask levels, and the remainder expires.
+            hbt.submit_buy_order(0, 10, 103.0, 5.0, TimeInForce::IOC, OrdType::Limit, true)?;
+            let order = hbt.orders(0).get(&10).unwrap();
+            assert_eq!(order.status, Status::Expired);
+            assert_eq!(order.cum_exec_qty, 3.0);
+            assert_eq!(hbt.position(0), 3.0);
+
+            let fills = hbt
+                .order_timeline(0, 10)
+                .iter()
+                .filter(|entry| entry.kind == OrderTimelineKind::Fill)
+                .map(|entry| (entry.price, entry.qty))
+                .collect::<Vec<_>>();
+            Ok(fills)
+        };
+
+        assert_eq!(
+            run(FillResponseMode::PerFill)?,
+            vec![(101.0, 1.0), (102.0, 1.0), (103.0, 1.0)]
+        );
+        assert_eq!(run(FillResponseMode::Batched)?, vec![(102.0, 3.0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn self_trade_prevention_cancels_resting_order() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                99.0,
+                5.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                102.0,
+                5.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .self_trade_prevention(SelfTradePrevention::CancelResting)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_sell_order(0, 10, 101.0, 3.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        // The incoming buy order would trade against the resting sell order.
+        hbt.submit_buy_order(0, 11, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.leaves_qty, 2.0);
+        assert_eq!(order.exec_qty, 0.0);
+        assert_eq!(order.cancel_reason, CancelReason::SelfTradePrevention);
+        assert_eq!(order.canceled_qty, 1.0);
+        assert_eq!(hbt.orders(0).get(&11).unwrap().status, Status::Expired);
+        assert_eq!(hbt.position(0), 0.0);
+
+        // A user-initiated cancel carries no cancel reason.
+        hbt.cancel(0, 10, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Canceled);
+        assert_eq!(order.cancel_reason, CancelReason::None);
+        Ok(())
+    }
+
+    #[test]
+    fn sub_lot_residual_policy() -> Result<(), Box<dyn Error>> {
+        let run = |sub_lot_residual_policy: SubLotResidualPolicy| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    99.0,
+                    5.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .sub_lot_residual_policy(sub_lot_residual_policy)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            // Takes the whole 1.0 lot at 101, leaving a 0.5-lot residual.
+            hbt.submit_buy_order(0, 10, 101.0, 1.5, TimeInForce::GTC, OrdType::Limit, true)?;
+            let order = hbt.orders(0).get(&10).unwrap().clone();
+            Ok(order)
+        };
+
+        let order = run(SubLotResidualPolicy::Cancel)?;
+        assert_eq!(order.status, Status::Canceled);
+        assert_eq!(order.cancel_reason, CancelReason::SubLotResidual);
+        assert_eq!(order.canceled_qty, 0.5);
+        assert_eq!(order.leaves_qty, 0.0);
+
+        let order = run(SubLotResidualPolicy::Keep)?;
+        assert_eq!(order.status, Status::PartiallyFilled);
+        assert_eq!(order.cancel_reason, CancelReason::None);
+        assert_eq!(order.leaves_qty, 0.5);
+        Ok(())
+    }
+
+    #[test]
+    fn locked_market_policy() -> Result<(), Box<dyn Error>> {
+        let build = |locked_market_policy: LockedMarketPolicy| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    99.0,
+                    5.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    5.0,
+                    2,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .locked_market_policy(locked_market_policy)
+                        .build()?,
+                )
+                .build()?;
+            hbt.elapse(1)?;
+            Ok(hbt)
+        };
+
+        let mut hbt = build(LockedMarketPolicy::Reject)?;
+        // A resting order at the best ask would lock the market.
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.reject_reason, RejectReason::WouldLockMarket);
+        assert_eq!(hbt.position(0), 0.0);
+
+        // An order that doesn't lock the market rests, and one meant to take liquidity takes it.
+        hbt.submit_buy_order(0, 11, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        assert_eq!(hbt.orders(0).get(&11).unwrap().status, Status::New);
+        hbt.submit_buy_order(0, 12, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        assert_eq!(hbt.orders(0).get(&12).unwrap().status, Status::Filled);
+
+        let mut hbt = build(LockedMarketPolicy::Match)?;
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Filled);
+        assert_eq!(order.reject_reason, RejectReason::None);
+        Ok(())
+    }
+
+    #[test]
+    fn self_trade_prevention_modes() -> Result<(), Box<dyn Error>> {
+        let run = |self_trade_prevention: SelfTradePrevention| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    99.0,
+                    5.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    102.0,
+                    5.0,
+                    2,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .self_trade_prevention(self_trade_prevention)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_sell_order(0, 10, 101.0, 3.0, TimeInForce::GTC, OrdType::Limit, true)?;
+            // The incoming buy order would trade against the resting sell order.
+            hbt.submit_buy_order(0, 11, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+
+            let resting = hbt.orders(0).get(&10).unwrap();
+            let incoming = hbt.orders(0).get(&11).unwrap();
+            Ok((
+                resting.leaves_qty,
+                resting.canceled_qty,
+                incoming.status,
+                incoming.canceled_qty,
+                incoming.cancel_reason,
+            ))
+        };
+
+        assert_eq!(
+            run(SelfTradePrevention::None)?,
+            (3.0, 0.0, Status::Expired, 0.0, CancelReason::None)
+        );
+        assert_eq!(
+            run(SelfTradePrevention::CancelResting)?,
+            (2.0, 1.0, Status::Expired, 0.0, CancelReason::None)
+        );
+        assert_eq!(
+            run(SelfTradePrevention::CancelNewest)?,
+            (
+                3.0,
+                0.0,
+                Status::Canceled,
+                1.0,
+                CancelReason::SelfTradePrevention
+            )
+        );
+        assert_eq!(
+            run(SelfTradePrevention::CancelBoth)?,
+            (
+                2.0,
+                1.0,
+                Status::Canceled,
+                1.0,
+                CancelReason::SelfTradePrevention
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn two_phase_ack_responds_new_before_fill() -> Result<(), Box<dyn Error>> {
+        let run = |two_phase_ack: bool, qty: f64| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .order_log_capacity(16)
+                        .two_phase_ack(two_phase_ack)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 101.0, qty, TimeInForce::GTC, OrdType::Limit, true)?;
+            assert_eq!(hbt.orders(0).get(&10).unwrap().leaves_qty, qty - 1.0);
+            Ok(hbt
+                .order_log(0)
+                .iter()
+                .map(|entry| (entry.kind, entry.exch_ts))
+                .collect::<Vec<_>>())
+        };
+
+        assert_eq!(run(false, 1.0)?, vec![(OrderLogKind::Fill, 11)]);
+        // The order filled on arrival never rests, even though it is acknowledged first.
+        assert_eq!(run(true, 1.0)?, vec![(OrderLogKind::Fill, 11)]);
+        // The remainder of the order partially filled on arrival rests after the fill.
+        let partially_filled = vec![(OrderLogKind::Fill, 11), (OrderLogKind::Rested, 11)];
+        assert_eq!(run(false, 2.0)?, partially_filled);
+        assert_eq!(run(true, 2.0)?, partially_filled);
+        Ok(())
+    }
+
+    #[test]
+    fn auction_fill_attribution() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            // Crosses the order book during the auction.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                100,
+                100.0,
+                3.0,
+                3,
+            ),
+            // Uncrosses at 100, where the bids of 6 are matched against the ask of 3.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | FILL_EVENT | AUCTION_UPDATE_EVENT,
+                200,
+                100.0,
+                0.0,
+                0,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 5.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        assert!(hbt.last_auction(0).is_none());
+
+        hbt.elapse(500)?;
+        let auction = hbt.last_auction(0).unwrap();
+        assert_eq!(auction.price, 100.0);
+        assert_eq!(auction.exch_ts, 200);
+        assert_eq!(auction.matched_qty, 3.0);
+        // The feed order ahead in the queue takes 1, leaving 2 for the backtest order.
+        assert_eq!(auction.fills.len(), 1);
+        let fill = auction.fill(10).unwrap();
+        assert_eq!(fill.matched_qty, 2.0);
+        assert_eq!(fill.leaves_qty, 3.0);
+        assert_eq!(fill.status, Status::PartiallyFilled);
+        assert!(!fill.is_fully_filled());
+
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::PartiallyFilled);
+        assert_eq!(order.leaves_qty, 3.0);
+        assert_eq!(order.exec_price(), 100.0);
+
+        // The local attributes the same fill from the auction responses.
+        let auction = hbt.local_last_auction(0).unwrap();
+        assert_eq!(auction.price, 100.0);
+        assert_eq!(auction.exch_ts, 200);
+        assert_eq!(auction.matched_qty, 3.0);
+        assert_eq!(auction.fills.len(), 1);
+        assert_eq!(auction.fill(10).unwrap().matched_qty, 2.0);
+        // The response for the market feed order only reconciles the local market depth.
+        assert!(!hbt.orders(0).contains_key(&1));
+        Ok(())
+    }
+
+    #[test]
+    fn ioc_order_never_joins_auction() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            // Crosses the order book during the auction.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                100,
+                100.0,
+                3.0,
+                3,
+            ),
+            // Uncrosses at 100, where the bid of 1 is matched against the ask of 3.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | FILL_EVENT | AUCTION_UPDATE_EVENT,
+                200,
+                100.0,
+                0.0,
+                0,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
 
-        if event.is(EXCH_BID_DEPTH_CLEAR_EVENT) {
-            self.depth.clear_orders(Side::Buy);
-            let expired = self.queue_model.clear_orders(Side::Buy);
-            for order in expired {
-                self.expired(order, event.exch_ts)?;
-            }
-        } else if event.is(EXCH_ASK_DEPTH_CLEAR_EVENT) {
-            self.depth.clear_orders(Side::Sell);
-            let expired = self.queue_model.clear_orders(Side::Sell);
-            for order in expired {
-                self.expired(order, event.exch_ts)?;
-            }
-        } else if event.is(EXCH_DEPTH_CLEAR_EVENT) {
-            // println!("[EXCHANGE] FULL DEPTH CLEAR");
-            self.depth.clear_orders(Side::None);
-            let expired = self.queue_model.clear_orders(Side::None);
-            for order in expired {
-                self.expired(order, event.exch_ts)?;
-            }
-        } else if event.is(EXCH_BID_ADD_ORDER_EVENT) {
-            // println!("exch");
-            let (prev_best_bid_tick, best_bid_tick) =
-                self.depth
-                    .add_buy_order(event.order_id, event.px, event.qty, event.exch_ts)?;
-            self.queue_model.add_market_feed_order(event, &self.depth)?;
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
 
-            // println!("[EXCHANGE] BID added: prev_best={}, new_best={}", prev_best_bid_tick, best_bid_tick);
+        // The IOC order reaches the exchange at 151, just before the auction uncrosses.
+        hbt.elapse(141)?;
+        hbt.submit_buy_order(0, 10, 101.0, 2.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.exec_qty, 0.0);
+        assert_eq!(order.exch_timestamp, 151);
 
-            if !event.is(AUCTION_UPDATE_EVENT) && best_bid_tick > prev_best_bid_tick {
-                // println!("ask partial fill crossing fill!");
-                self.fill_ask_orders_by_crossing(prev_best_bid_tick, best_bid_tick, event.exch_ts)?;
-            }
-        } else if event.is(EXCH_ASK_ADD_ORDER_EVENT) {
-            // println!("exch");
-            let (prev_best_ask_tick, best_ask_tick) =
-                self.depth
-                    .add_sell_order(event.order_id, event.px, event.qty, event.exch_ts)?;
-            self.queue_model.add_market_feed_order(event, &self.depth)?;
+        hbt.elapse(500)?;
+        let auction = hbt.last_auction(0).unwrap();
+        assert_eq!(auction.matched_qty, 1.0);
+        assert!(auction.fill(10).is_none());
+        assert_eq!(hbt.position(0), 0.0);
+        Ok(())
+    }
 
-            // println!("[EXCHANGE] ASK added: prev_best={}, new_best={}", prev_best_ask_tick, best_ask_tick);
+    #[test]
+    fn priority_reset_policy_on_size_down() -> Result<(), Box<dyn Error>> {
+        let run = |policy: PriorityResetPolicy| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                // Joins the queue behind the backtest order.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    100,
+                    100.0,
+                    2.0,
+                    3,
+                ),
+                // Fills the feed order, and the backtest orders ahead of it in the queue.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                    500,
+                    100.0,
+                    2.0,
+                    3,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
 
-            if !event.is(AUCTION_UPDATE_EVENT) && best_ask_tick < prev_best_ask_tick {
-                // println!("bid partial fill crossing fill!");
-                self.fill_bid_orders_by_crossing(prev_best_ask_tick, best_ask_tick, event.exch_ts)?;
-            }
-        } else if event.is(EXCH_CANCEL_ORDER_EVENT) {
-            let order_id = event.order_id;
-            self.depth.delete_order(order_id, event.exch_ts)?;
-            self.queue_model
-                .cancel_market_feed_order(event.order_id, &self.depth)?;
-        } else if event.is(EXCH_FILL_EVENT) {
-            if event.is(BUY_EVENT) || event.is(SELL_EVENT) {
-                // println!("[EXCHANGE] Processing FILL event for market feed order");
-                let filled = self.queue_model.fill_market_feed_order::<false>(
-                    event.order_id,
-                    event,
-                    &self.depth,
-                )?;
-                let timestamp = event.exch_ts;
-                let fill_qty = event.qty; // The quantity from the market feed fill event
-                for mut order in filled {
-                    // Partial fill based on the market feed fill quantity
-                    // This assumes FIFO - front orders get filled first
-                    let order_fill_qty = fill_qty.min(order.leaves_qty);
-                    let price_tick = order.price_tick;
-                    self.partial_fill::<true>(
-                        &mut order,
-                        timestamp,
-                        true,
-                        price_tick,
-                        order_fill_qty,
-                    )?;
-                }
-            } else if event.is(AUCTION_UPDATE_EVENT) && !self.auction_processed {
-                self.auction_processed = true;
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .fill_counterparty(false)
+                        .priority_reset_policy(policy)
+                        .build()?,
+                )
+                .build()?;
 
-                let auction_price = event.px;
-                let auction_price_tick = (auction_price / self.depth.tick_size()).round() as i64;
-                let timestamp = event.exch_ts;
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 100.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+            hbt.elapse(200)?;
+            hbt.modify(0, 10, 100.0, 1.0, true)?;
+            hbt.elapse(500)?;
+            Ok(hbt.orders(0).get(&10).unwrap().status)
+        };
 
-                println!(
-                    "[AUCTION] Processing auction at price: {} (tick: {})",
-                    auction_price, auction_price_tick
-                );
+        assert_eq!(run(PriorityResetPolicy::KeepOnSizeDown)?, Status::Filled);
+        // Even the size reduction moves the order behind the feed order that joined later.
+        assert_eq!(run(PriorityResetPolicy::AlwaysReset)?, Status::New);
+        Ok(())
+    }
 
-                // 1. 获取所有能成交的订单
-                // 买单：价格 >= 集合竞价价格
-                let mut filled_bids = Vec::new();
-                let mut bids_at_auction_price = Vec::new();
-                let mut total_bid_qty_ge_auction = 0.0;
-
-                let all_bid_orders = self.queue_model.get_all_bid_orders();
-                for order in all_bid_orders {
-                    if order.price_tick > auction_price_tick {
-                        total_bid_qty_ge_auction += order.leaves_qty;
-                        filled_bids.push(order);
-                    } else if order.price_tick == auction_price_tick {
-                        total_bid_qty_ge_auction += order.leaves_qty;
-                        bids_at_auction_price.push(order);
-                    }
-                }
+    #[test]
+    fn cancel_replace_modify_loses_priority() -> Result<(), Box<dyn Error>> {
+        let run = |modify_mode: ModifyMode| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                // Joins the queue behind the backtest order.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    100,
+                    100.0,
+                    2.0,
+                    3,
+                ),
+                // Fills the feed order, and the backtest orders ahead of it in the queue.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                    500,
+                    100.0,
+                    2.0,
+                    3,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
 
-                // 卖单：价格 <= 集合竞价价格
-                let mut filled_asks = Vec::new();
-                let mut asks_at_auction_price = Vec::new();
-                let mut total_ask_qty_le_auction = 0.0;
-
-                let all_ask_orders = self.queue_model.get_all_ask_orders();
-                for order in all_ask_orders {
-                    if order.price_tick < auction_price_tick {
-                        total_ask_qty_le_auction += order.leaves_qty;
-                        filled_asks.push(order);
-                    } else if order.price_tick == auction_price_tick {
-                        total_ask_qty_le_auction += order.leaves_qty;
-                        asks_at_auction_price.push(order);
-                    }
-                }
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .fill_counterparty(false)
+                        .priority_reset_policy(PriorityResetPolicy::KeepOnSizeDown)
+                        .modify_mode(modify_mode)
+                        .build()?,
+                )
+                .build()?;
 
-                println!(
-                    "[AUCTION] Orders above/below auction price - Bids: {}, Asks: {}",
-                    filled_bids.len(),
-                    filled_asks.len()
-                );
-                println!(
-                    "[AUCTION] Orders at auction price - Bids: {} (qty: {}), Asks: {} (qty: {})",
-                    bids_at_auction_price.len(),
-                    total_bid_qty_ge_auction,
-                    asks_at_auction_price.len(),
-                    total_ask_qty_le_auction
-                );
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 100.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+            hbt.elapse(200)?;
+            let exch_order_id = hbt.orders(0).get(&10).unwrap().exch_order_id;
+            assert_ne!(exch_order_id, 0);
+            hbt.modify(0, 10, 100.0, 1.0, true)?;
+            hbt.elapse(500)?;
+            let order = hbt.orders(0).get(&10).unwrap();
+            Ok((order.status, order.exch_order_id != exch_order_id))
+        };
 
-                // 2. 处理价格优于集合竞价价格的订单（全部成交）
-                for mut order in filled_bids {
-                    let order_id = order.order_id;
-                    let order_leaves_qty = order.leaves_qty;
+        // The size reduction keeps the priority when amended in place.
+        assert_eq!(run(ModifyMode::Amend)?, (Status::Filled, false));
+        // The replacement order joins the queue behind the feed order that joined later.
+        assert_eq!(run(ModifyMode::CancelReplace)?, (Status::New, true));
+        Ok(())
+    }
 
-                    self.depth.delete_order(order_id, timestamp)?;
-                    self.queue_model
-                        .cancel_market_feed_order(order_id, &self.depth)?;
-                }
+    #[test]
+    fn duplicate_feed_fill_is_ignored_within_dedup_window() -> Result<(), Box<dyn Error>> {
+        let run = |fill_dedup_window: i64| -> Result<_, Box<dyn Error>> {
+            let fill = l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                500,
+                100.0,
+                1.0,
+                3,
+            );
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                // Joins the queue behind the backtest order.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    100,
+                    100.0,
+                    2.0,
+                    3,
+                ),
+                // The dataset emits the same fill twice.
+                fill.clone(),
+                fill,
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
 
-                for mut order in filled_asks {
-                    let order_id = order.order_id;
-                    let order_leaves_qty = order.leaves_qty;
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .fill_counterparty(false)
+                        .fill_dedup_window(fill_dedup_window)
+                        .build()?,
+                )
+                .build()?;
 
-                    self.depth.delete_order(order_id, timestamp)?;
-                    self.queue_model
-                        .cancel_market_feed_order(order_id, &self.depth)?;
-                }
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 100.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+            hbt.elapse(1000)?;
+            let order = hbt.orders(0).get(&10).unwrap();
+            let feed_qty = hbt.depth(0).orders().get(&3).map(|order| order.qty);
+            Ok((order.status, order.leaves_qty, feed_qty))
+        };
 
-                // 3. 处理价格等于集合竞价价格的订单
-                if !bids_at_auction_price.is_empty() || !asks_at_auction_price.is_empty() {
-                    if total_bid_qty_ge_auction <= total_ask_qty_le_auction {
-                        // 买单数量少，买单全部成交
-                        for order in bids_at_auction_price {
-                            let order_id = order.order_id;
+        // The local order book applies the duplicate as well, deleting the feed order.
+        assert_eq!(run(0)?, (Status::Filled, 0.0, None));
+        assert_eq!(run(1_000)?, (Status::PartiallyFilled, 1.0, Some(1.0)));
+        Ok(())
+    }
 
-                            self.depth.delete_order(order_id, timestamp)?;
-                            self.queue_model
-                                .cancel_market_feed_order(order_id, &self.depth)?;
-                        }
+    #[test]
+    fn feed_events_after_full_fill_are_ignored() -> Result<(), Box<dyn Error>> {
+        let fill = l3_event(
+            EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+            200,
+            100.0,
+            2.0,
+            3,
+        );
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                100,
+                100.0,
+                2.0,
+                3,
+            ),
+            // The fill deletes the order from the local order book, after which the feed repeats
+            // the fill and then sends the delete.
+            fill.clone(),
+            fill,
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | CANCEL_ORDER_EVENT,
+                300,
+                100.0,
+                0.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
 
-                        // left_qty 是开盘后剩余在卖1的数量
-                        let left_qty = total_ask_qty_le_auction - total_bid_qty_ge_auction;
-                        // 计算卖单的总量
-                        let mut total_asks_qty = 0.0;
-                        for order in &asks_at_auction_price {
-                            total_asks_qty += order.leaves_qty;
-                        }
-                        // 需要成交的总量
-                        let need_to_fill = total_asks_qty - left_qty;
-                        let mut already_filled = 0.0;
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_counterparty(false)
+                    .build()?,
+            )
+            .build()?;
 
-                        for mut order in asks_at_auction_price {
-                            if already_filled >= need_to_fill {
-                                break; // 剩余订单保留在订单簿中
-                            }
+        hbt.elapse(1000)?;
+        let depth = hbt.depth(0);
+        assert!(!depth.orders().contains_key(&3));
+        assert_eq!(depth.best_bid(), 100.0);
+        assert_eq!(depth.bid_qty_at_tick(100), 1.0);
+        Ok(())
+    }
 
-                            // 这个订单需要成交的量
-                            let order_fill_qty =
-                                (need_to_fill - already_filled).min(order.leaves_qty);
-                            already_filled += order_fill_qty;
+    #[test]
+    fn cancel_after_fill_is_rejected_as_already_filled() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                100,
+                100.0,
+                1.0,
+                3,
+            ),
+            // Fills the backtest order ahead of the feed order.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                500,
+                100.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
 
-                            if order_fill_qty >= order.leaves_qty {
-                                // 订单完全成交
-                                self.depth.delete_order(order.order_id, timestamp)?;
-                                self.queue_model
-                                    .cancel_market_feed_order(order.order_id, &self.depth)?;
-                            } else if order_fill_qty > 0.0 {
-                                // 订单部分成交
-                                let remaining_qty = order.leaves_qty - order_fill_qty;
-
-                                // 更新深度中该订单的数量
-                                self.depth.modify_order(
-                                    order.order_id,
-                                    auction_price,
-                                    remaining_qty,
-                                    timestamp,
-                                )?;
-                            }
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_counterparty(false)
+                    .build()?,
+            )
+            .build()?;
 
-                            // pass an order is_auction
-                            order.exec_price_tick = auction_price_tick;
-                            order.qty = left_qty;
-                            order.is_auction = true;
-                            self.order_e2l.respond(order.clone());
-                        }
-                    } else {
-                        // 卖单数量少，卖单全部成交
-                        for order in asks_at_auction_price {
-                            let order_id = order.order_id;
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        // The cancel is sent before the fill response, which arrives at 520, is received.
+        hbt.elapse(504 - hbt.current_timestamp())?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::New);
+        hbt.cancel(0, 10, false)?;
+        hbt.elapse(100)?;
 
-                            self.depth.delete_order(order_id, timestamp)?;
-                            self.queue_model
-                                .cancel_market_feed_order(order_id, &self.depth)?;
-                        }
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Filled);
+        assert_eq!(order.req, Status::None);
+        assert_eq!(order.reject_reason, RejectReason::AlreadyFilled);
+        Ok(())
+    }
 
-                        let left_qty = total_bid_qty_ge_auction - total_ask_qty_le_auction;
+    #[test]
+    fn batch_matching_at_uncross_price() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                102.0,
+                2.0,
+                3,
+            ),
+            // The first event in the next matching cycle.
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 100, 0.0, 0.0, 0),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
 
-                        let mut total_bids_qty = 0.0;
-                        for order in &bids_at_auction_price {
-                            total_bids_qty += order.leaves_qty;
-                        }
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .batch_interval(100)
+                    .build()?,
+            )
+            .build()?;
 
-                        let need_to_fill = total_bids_qty - left_qty;
-                        let mut already_filled = 0.0;
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 102.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_buy_order(0, 11, 102.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        // Both orders wait for the cycle boundary.
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::New);
+        assert_eq!(hbt.orders(0).get(&11).unwrap().status, Status::New);
 
-                        for mut order in bids_at_auction_price {
-                            if already_filled >= need_to_fill {
-                                break;
-                            }
+        hbt.elapse(500)?;
+        // At 102, the bids of 4 are matched against the asks of 3. Though the first order could
+        // have been filled at 101 in continuous matching, both orders are filled at 102.
+        let first = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(first.status, Status::Filled);
+        assert_eq!(first.exec_price(), 102.0);
+        assert_eq!(first.exch_timestamp, 100);
+        // The quantity left for the later order is partially filled.
+        let second = hbt.orders(0).get(&11).unwrap();
+        assert_eq!(second.status, Status::PartiallyFilled);
+        assert_eq!(second.exec_qty, 1.0);
+        assert_eq!(second.leaves_qty, 1.0);
+        assert_eq!(second.exec_price(), 102.0);
+        Ok(())
+    }
 
-                            let order_fill_qty =
-                                (need_to_fill - already_filled).min(order.leaves_qty);
-                            already_filled += order_fill_qty;
+    #[test]
+    fn batch_matching_uncrosses_both_sides() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                102.0,
+                2.0,
+                3,
+            ),
+            // The first event in the next matching cycle.
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 100, 0.0, 0.0, 0),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
 
-                            if order_fill_qty >= order.leaves_qty {
-                                // 订单完全成交
-                                self.depth.delete_order(order.order_id, timestamp)?;
-                                self.queue_model
-                                    .cancel_market_feed_order(order.order_id, &self.depth)?;
-                            } else if order_fill_qty > 0.0 {
-                                // 订单部分成交
-                                let remaining_qty = order.leaves_qty - order_fill_qty;
-
-                                // 更新深度中该订单的数量
-                                self.depth.modify_order(
-                                    order.order_id,
-                                    auction_price,
-                                    remaining_qty,
-                                    timestamp,
-                                )?;
-                            }
-                            // pass an order is_auction
-                            order.exec_price_tick = auction_price_tick;
-                            order.qty = -left_qty;
-                            order.is_auction = true;
-                            self.order_e2l.respond(order.clone());
-                        }
-                    }
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .batch_interval(100)
+                    .build()?,
+            )
+            .build()?;
 
-                    println!(
-                        "[AUCTION] Auction completed. Opening price: {}",
-                        auction_price
-                    );
-
-                    // 打印5档深度
-                    println!("[AUCTION] Post-auction market depth (5 levels):");
-                    println!("         Bid                    Ask");
-                    println!("  Price      Qty        Price      Qty");
-                    println!("---------- --------   ---------- --------");
-
-                    // 获取5档深度
-                    let mut bid_levels = Vec::new();
-                    let mut ask_levels = Vec::new();
-
-                    // 获取买档 - 从最优买价开始向下查找
-                    if self.depth.best_bid_tick() != INVALID_MIN {
-                        let tick_size = self.depth.tick_size();
-                        let mut current_tick = self.depth.best_bid_tick();
-
-                        for _ in 0..5 {
-                            let qty = self.depth.bid_qty_at_tick(current_tick);
-                            if qty > 0.0 {
-                                let price = current_tick as f64 * tick_size;
-                                bid_levels.push((price, qty));
-                            }
+        hbt.elapse(1)?;
+        // The bids arrive in the reverse order of their price priority.
+        hbt.submit_sell_order(0, 12, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.submit_buy_order(0, 11, 102.0, 2.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.submit_sell_order(0, 13, 100.0, 5.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        hbt.elapse(30)?;
+        assert_eq!(hbt.orders(0).get(&13).unwrap().status, Status::New);
 
-                            // 向下查找下一个有效价格档位
-                            let mut found_next = false;
-                            for i in 1..=100 {
-                                // 最多查找100个tick
-                                let next_tick = current_tick - i;
-                                if self.depth.bid_qty_at_tick(next_tick) > 0.0 {
-                                    current_tick = next_tick;
-                                    found_next = true;
-                                    break;
-                                }
-                            }
+        // The queued orders can be modified and canceled until the cycle boundary.
+        hbt.modify(0, 10, 101.0, 2.0, false)?;
+        hbt.cancel(0, 13, false)?;
+        hbt.elapse(30)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().qty, 2.0);
+        assert_eq!(hbt.orders(0).get(&13).unwrap().status, Status::Canceled);
 
-                            if !found_next {
-                                break;
-                            }
-                        }
-                    }
+        hbt.elapse(500)?;
+        // At 101, the bids of 4 are matched against the queued ask and the resting ask, 2 in
+        // total, and the queued ask against the queued bids, at the single uncross price.
+        let ask = hbt.orders(0).get(&12).unwrap();
+        assert_eq!(ask.status, Status::Filled);
+        assert_eq!(ask.exec_price(), 101.0);
+        // The later bid at the better price is filled first.
+        let better = hbt.orders(0).get(&11).unwrap();
+        assert_eq!(better.status, Status::Filled);
+        assert_eq!(better.exec_price(), 101.0);
+        assert_eq!(better.exch_timestamp, 100);
+        // No quantity is left for the earlier bid, which rests at its limit price.
+        let worse = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(worse.status, Status::New);
+        assert_eq!(worse.leaves_qty, 2.0);
+        Ok(())
+    }
 
-                    // 获取卖档 - 从最优卖价开始向上查找
-                    if self.depth.best_ask_tick() != INVALID_MAX {
-                        let tick_size = self.depth.tick_size();
-                        let mut current_tick = self.depth.best_ask_tick();
+    #[test]
+    fn fill_sequence_is_price_then_time_then_order_id() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                105.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                200,
+                100.0,
+                5.0,
+                4,
+            ),
+            // Fills the backtest bids queued ahead of the feed order.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                300,
+                100.0,
+                4.0,
+                4,
+            ),
+            // Crosses all backtest asks.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                500,
+                103.0,
+                5.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
 
-                        for _ in 0..5 {
-                            let qty = self.depth.ask_qty_at_tick(current_tick);
-                            if qty > 0.0 {
-                                let price = current_tick as f64 * tick_size;
-                                ask_levels.push((price, qty));
-                            }
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .order_log_capacity(16)
+                    .fill_counterparty(false)
+                    .build()?,
+            )
+            .build()?;
 
-                            // 向上查找下一个有效价格档位
-                            let mut found_next = false;
-                            for i in 1..=100 {
-                                // 最多查找100个tick
-                                let next_tick = current_tick + i;
-                                if self.depth.ask_qty_at_tick(next_tick) > 0.0 {
-                                    current_tick = next_tick;
-                                    found_next = true;
-                                    break;
-                                }
-                            }
+        hbt.elapse(1)?;
+        hbt.submit_sell_order(0, 30, 102.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_sell_order(0, 20, 102.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_sell_order(0, 40, 101.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_buy_order(0, 60, 100.0, 3.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.submit_buy_order(0, 50, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(1000)?;
 
-                            if !found_next {
-                                break;
-                            }
-                        }
-                    }
+        let fills = hbt
+            .order_log(0)
+            .iter()
+            .filter(|entry| entry.kind == OrderLogKind::Fill)
+            .map(|entry| (entry.order_id, entry.price, entry.qty))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fills,
+            vec![
+                (60, 100.0, 3.0),
+                (50, 100.0, 1.0),
+                (40, 101.0, 1.0),
+                (30, 102.0, 1.0),
+                (20, 102.0, 1.0),
+            ]
+        );
+        assert_eq!(hbt.orders(0).get(&60).unwrap().status, Status::Filled);
+        assert_eq!(hbt.orders(0).get(&50).unwrap().status, Status::Filled);
+        Ok(())
+    }
 
-                    // 打印深度表格
-                    for i in 0..5 {
-                        let bid_str = if i < bid_levels.len() {
-                            format!("{:10.2} {:8.0}", bid_levels[i].0, bid_levels[i].1)
-                        } else {
-                            format!("{:10} {:8}", "--", "--")
-                        };
+    #[test]
+    fn market_order_walks_multiple_levels() -> Result<(), Box<dyn Error>> {
+        let run = |side: Side, qty: f64| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    97.0,
+                    2.0,
+                    2,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    3,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    103.0,
+                    1.0,
+                    4,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    104.0,
+                    2.0,
+                    5,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
 
-                        let ask_str = if i < ask_levels.len() {
-                            format!("{:10.2} {:8.0}", ask_levels[i].0, ask_levels[i].1)
-                        } else {
-                            format!("{:10} {:8}", "--", "--")
-                        };
+            let mut hbt = Backtest::builder()
+                .add_asset(l3_asset(data, PartialFillExchange).build()?)
+                .build()?;
 
-                        println!("{}   {}", bid_str, ask_str);
-                    }
+            hbt.elapse(1)?;
+            hbt.submit_order(
+                0,
+                OrderRequest {
+                    order_id: 10,
+                    price: 0.0,
+                    qty,
+                    side,
+                    time_in_force: TimeInForce::GTC,
+                    order_type: OrdType::Market,
+                    max_lifetime: 0,
+                    opening_auction: false,
+                },
+                true,
+            )?;
+            let order = hbt.orders(0).get(&10).unwrap();
+            Ok((order.status, order.exec_qty, order.exec_price_tick))
+        };
 
-                    // 打印最优买卖价和价差
-                    if self.depth.best_bid_tick() != INVALID_MIN
-                        && self.depth.best_ask_tick() != INVALID_MAX
-                    {
-                        let best_bid = self.depth.best_bid();
-                        let best_ask = self.depth.best_ask();
-                        let spread = best_ask - best_bid;
-                        let spread_ticks = self.depth.best_ask_tick() - self.depth.best_bid_tick();
-                        let mid_price = (best_bid + best_ask) / 2.0;
-
-                        println!();
-                        println!("[AUCTION] Summary:");
-                        println!(
-                            "  Best Bid: {:.2} (qty: {:.0})",
-                            best_bid,
-                            self.depth.bid_qty_at_tick(self.depth.best_bid_tick())
-                        );
-                        println!(
-                            "  Best Ask: {:.2} (qty: {:.0})",
-                            best_ask,
-                            self.depth.ask_qty_at_tick(self.depth.best_ask_tick())
-                        );
-                        println!("  Mid Price: {:.2}", mid_price);
-                        println!("  Spread: {:.2} ({} ticks)", spread, spread_ticks);
-                    } else if self.depth.best_bid_tick() != INVALID_MIN {
-                        println!();
-                        println!("[AUCTION] Only bid side has orders");
-                        println!(
-                            "  Best Bid: {:.2} (qty: {:.0})",
-                            self.depth.best_bid(),
-                            self.depth.bid_qty_at_tick(self.depth.best_bid_tick())
-                        );
-                    } else if self.depth.best_ask_tick() != INVALID_MAX {
-                        println!();
-                        println!("[AUCTION] Only ask side has orders");
-                        println!(
-                            "  Best Ask: {:.2} (qty: {:.0})",
-                            self.depth.best_ask(),
-                            self.depth.ask_qty_at_tick(self.depth.best_ask_tick())
-                        );
-                    } else {
-                        println!();
-                        println!("[AUCTION] No orders in the book");
-                    }
-                }
+        // Takes 1 at 101 and 1 at 103.
+        assert_eq!(run(Side::Buy, 2.0)?, (Status::Filled, 2.0, 102));
+        // Takes 1 at 101, 1 at 103, and 2 at 104.
+        assert_eq!(run(Side::Buy, 4.0)?, (Status::Filled, 4.0, 103));
+        // Takes 1 at 100 and 2 at 97.
+        assert_eq!(run(Side::Sell, 3.0)?, (Status::Filled, 3.0, 98));
+        // The book is exhausted before the order is filled.
+        assert_eq!(run(Side::Sell, 5.0)?, (Status::Expired, 3.0, 98));
+        Ok(())
+    }
+
+    #[test]
+    fn aggressive_limit_order_sweeps_levels() -> Result<(), Box<dyn Error>> {
+        let run = |time_in_force: TimeInForce, qty: f64| -> Result<_, Box<dyn Error>> {
+            let mut events = vec![l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            )];
+            for (order_id, px) in [(2, 101.0), (3, 102.0), (4, 103.0), (5, 105.0)] {
+                events.push(l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    px,
+                    1.0,
+                    order_id,
+                ));
             }
+            events.push(l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0));
+            let data = Data::from_data(&events);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .fill_gap_capacity(8)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            // Priced three ticks through the best ask.
+            hbt.submit_buy_order(0, 10, 104.0, qty, time_in_force, OrdType::Limit, true)?;
+            let order = hbt.orders(0).get(&10).unwrap();
+            let fill_prices: Vec<f64> = hbt.fill_gaps(0).iter().map(|fill| fill.price).collect();
+            Ok((
+                order.status,
+                order.exec_qty,
+                order.exec_price_tick,
+                fill_prices,
+            ))
+        };
+
+        // Fills 1 at each of 101, 102, and 103, but not at 105 beyond the limit price.
+        assert_eq!(
+            run(TimeInForce::IOC, 4.0)?,
+            (Status::Expired, 3.0, 102, vec![101.0, 102.0, 103.0])
+        );
+        assert_eq!(
+            run(TimeInForce::FOK, 3.0)?,
+            (Status::Filled, 3.0, 102, vec![101.0, 102.0, 103.0])
+        );
+        // The levels within the limit price can't fill the full quantity.
+        assert_eq!(
+            run(TimeInForce::FOK, 4.0)?,
+            (Status::Expired, 0.0, 0, vec![])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ioc_order_responds_each_fill_before_expiry() -> Result<(), Box<dyn Error>> {
+        let mut events = vec![l3_event(
+            EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+            0,
+            100.0,
+            1.0,
+            1,
+        )];
+        for (order_id, px, qty) in [(2, 101.0, 1.0), (3, 102.0, 2.0), (4, 105.0, 1.0)] {
+            events.push(l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                px,
+                qty,
+                order_id,
+            ));
         }
+        events.push(l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0));
+        let data = Data::from_data(&events);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .order_log_capacity(16)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        // Takes 1 at 101 and 2 at 102, but not at 105 beyond the limit price.
+        hbt.submit_buy_order(0, 10, 103.0, 5.0, TimeInForce::IOC, OrdType::Limit, true)?;
+
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.exec_qty, 3.0);
+        assert_eq!(order.leaves_qty, 2.0);
+
+        // Each fill is conveyed by its own response, and the expiry doesn't repeat them.
+        let log = hbt
+            .order_log(0)
+            .iter()
+            .map(|entry| (entry.kind, entry.price, entry.qty))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            log,
+            vec![
+                (OrderLogKind::Fill, 101.0, 1.0),
+                (OrderLogKind::Fill, 102.0, 2.0),
+            ]
+        );
         Ok(())
     }
 
-    // TODO unchecked
-    fn process_recv_order(
-        &mut self,
-        timestamp: i64,
-        wait_resp_order_id: Option<OrderId>,
-    ) -> Result<bool, BacktestError> {
-        while let Some(mut order) = self.order_e2l.receive(timestamp) {
-            // Processes a new order.
-            if order.req == Status::New {
-                order.req = Status::None;
-                self.ack_new(&mut order, timestamp)?;
-            }
-            // Processes a cancel order.
-            else if order.req == Status::Canceled {
-                order.req = Status::None;
-                self.ack_cancel(&mut order, timestamp)?;
-            }
-            // Processes a modify order.
-            else if order.req == Status::Replaced {
-                order.req = Status::None;
-                self.ack_modify::<false>(&mut order, timestamp)?;
-            } else {
-                return Err(BacktestError::InvalidOrderRequest);
-            }
-            // Makes the response.
-            self.order_e2l.respond(order);
+    #[test]
+    fn cumulative_fill_over_three_slices() -> Result<(), Box<dyn Error>> {
+        let mut events = vec![l3_event(
+            EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+            0,
+            100.0,
+            1.0,
+            1,
+        )];
+        for (order_id, px) in [(2, 101.0), (3, 102.0), (4, 103.0), (5, 105.0)] {
+            events.push(l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                px,
+                1.0,
+                order_id,
+            ));
         }
-        Ok(false)
+        events.push(l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0));
+        let data = Data::from_data(&events);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        // Takes 1 at each of 101, 102 and 103 in its own response, and the rest expires.
+        hbt.submit_buy_order(0, 10, 103.0, 4.0, TimeInForce::IOC, OrdType::Limit, true)?;
+
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.cum_exec_qty, 3.0);
+        assert_eq!(order.cum_exec_notional, 306.0);
+        assert_eq!(order.avg_exec_price(), 102.0);
+        assert_eq!(order.leaves_qty, order.qty - order.cum_exec_qty);
+
+        // Each slice is applied once, including the expiry carrying all of them.
+        assert_eq!(hbt.position(0), 3.0);
+        assert_eq!(hbt.state_values(0).trading_value, 306.0);
+        Ok(())
     }
 
-    fn earliest_recv_order_timestamp(&self) -> i64 {
-        self.order_e2l
-            .earliest_recv_order_timestamp()
-            .unwrap_or(i64::MAX)
+    #[test]
+    fn modify_below_min_price_improvement_is_rejected() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                105.0,
+                1.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .min_price_improvement(2)
+                    .order_timeline_capacity(1)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+
+        // A one-tick improvement doesn't meet the requirement.
+        hbt.modify(0, 10, 101.0, 1.0, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.req, Status::None);
+        assert_eq!(
+            order.reject_reason,
+            RejectReason::InsufficientPriceImprovement
+        );
+
+        // A two-tick improvement joins the queue at the new price.
+        hbt.modify(0, 10, 102.0, 1.0, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.req, Status::None);
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.price_tick, 102);
+        let kinds: Vec<_> = hbt
+            .order_timeline(0, 10)
+            .iter()
+            .map(|entry| entry.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                OrderTimelineKind::Submitted,
+                OrderTimelineKind::Acked,
+                OrderTimelineKind::ModifyRequested,
+                OrderTimelineKind::Rejected,
+                OrderTimelineKind::ModifyRequested,
+                OrderTimelineKind::Modified,
+            ]
+        );
+        Ok(())
     }
 
-    fn earliest_send_order_timestamp(&self) -> i64 {
-        self.order_e2l
-            .earliest_send_order_timestamp()
-            .unwrap_or(i64::MAX)
+    #[test]
+    fn modify_count() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                105.0,
+                1.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 100.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        assert_eq!(hbt.orders(0).get(&10).unwrap().modify_count, 0);
+
+        // Both the price change and the in-place quantity reduction count as a modification.
+        for (expected, price, qty) in [(1, 99.0, 2.0), (2, 99.0, 1.0), (3, 101.0, 1.0)] {
+            hbt.modify(0, 10, price, qty, true)?;
+            let order = hbt.orders(0).get(&10).unwrap();
+            assert_eq!(order.status, Status::New);
+            assert_eq!(order.modify_count, expected);
+        }
+
+        // A rejected modification isn't counted.
+        hbt.cancel(0, 10, true)?;
+        hbt.modify(0, 10, 102.0, 1.0, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Canceled);
+        assert_eq!(order.modify_count, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn one_sided_auction_matches_nothing() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                100,
+                101.0,
+                3.0,
+                2,
+            ),
+            // Only bids are present at the uncross.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | FILL_EVENT | AUCTION_UPDATE_EVENT,
+                200,
+                100.0,
+                0.0,
+                0,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 101.0, 2.0, TimeInForce::GTC, OrdType::Limit, true)?;
+
+        hbt.elapse(500)?;
+        let auction = hbt.last_auction(0).unwrap();
+        assert_eq!(auction.price, 100.0);
+        assert_eq!(auction.matched_qty, 0.0);
+        assert!(auction.fills.is_empty());
+
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.leaves_qty, 2.0);
+        assert_eq!(hbt.position(0), 0.0);
+
+        // The local market depth is left as it is.
+        let depth = hbt.depth(0);
+        assert_eq!(depth.best_bid_tick(), 101);
+        assert_eq!(depth.bid_qty_at_tick(101), 3.0);
+        assert_eq!(depth.bid_qty_at_tick(100), 1.0);
+        assert_eq!(depth.best_ask_tick(), INVALID_MAX);
+
+        // The backtest order still rests in the order book.
+        hbt.cancel(0, 10, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Canceled);
+        assert_eq!(order.req, Status::None);
+        Ok(())
+    }
+
+    #[test]
+    fn auction_allocation_respects_lot_size() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                2.5,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                10,
+                100.0,
+                1.5,
+                4,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                20,
+                100.0,
+                3.0,
+                5,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                100,
+                100.0,
+                4.0,
+                3,
+            ),
+            // Uncrosses at 100, where the bids of 7 are matched against the ask of 4.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | FILL_EVENT | AUCTION_UPDATE_EVENT,
+                200,
+                100.0,
+                0.0,
+                0,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .build()?;
+
+        hbt.elapse(500)?;
+        assert_eq!(hbt.last_auction(0).unwrap().matched_qty, 4.0);
+
+        // In time priority, the bids take 2.5 and 1.5, which are rounded down to 2 and 1. The lot
+        // lost to the rounding goes to the earliest bid that can take a whole lot.
+        let depth = hbt.depth(0);
+        assert_eq!(depth.orders().get(&1).unwrap().qty, 0.5);
+        assert_eq!(depth.orders().get(&4).unwrap().qty, 0.5);
+        assert_eq!(depth.orders().get(&5).unwrap().qty, 2.0);
+        assert!(!depth.orders().contains_key(&3));
+        assert_eq!(depth.bid_qty_at_tick(100), 3.0);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_continuous_orders_before_session_open() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                100,
+                100.0,
+                3.0,
+                3,
+            ),
+            // The opening auction uncrosses at 100.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | FILL_EVENT | AUCTION_UPDATE_EVENT,
+                200,
+                100.0,
+                0.0,
+                0,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .session_open(200)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        // It would take the ask at 101 if the session were open.
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.reject_reason, RejectReason::OrderEntryNotAllowed);
+        assert_eq!(order.exec_qty, 0.0);
+
+        // A GTC limit order not entered for the opening auction is rejected as well.
+        hbt.submit_buy_order(0, 12, 100.0, 5.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&12).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.reject_reason, RejectReason::OrderEntryNotAllowed);
+
+        hbt.submit_order(
+            0,
+            OrderRequest {
+                order_id: 11,
+                price: 100.0,
+                qty: 5.0,
+                side: Side::Buy,
+                time_in_force: TimeInForce::GTC,
+                order_type: OrdType::Limit,
+                max_lifetime: 0,
+                opening_auction: true,
+            },
+            true,
+        )?;
+        let order = hbt.orders(0).get(&11).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.reject_reason, RejectReason::None);
+
+        hbt.elapse(500)?;
+        // The feed order ahead in the queue takes 1, leaving 2 for the backtest order.
+        let fill = hbt.last_auction(0).unwrap().fill(11).unwrap();
+        assert_eq!(fill.matched_qty, 2.0);
+        let order = hbt.orders(0).get(&11).unwrap();
+        assert_eq!(order.status, Status::PartiallyFilled);
+        assert_eq!(order.leaves_qty, 3.0);
+
+        // The session open isn't supported without partial fills.
+        let asset = L3AssetBuilder::default()
+            .data(vec![DataSource::Data(Data::from_data(&[l3_event(
+                EXCH_EVENT | LOCAL_EVENT,
+                1000,
+                0.0,
+                0.0,
+                0,
+            )]))])
+            .latency_model(ConstantLatency::new(10, 20))
+            .asset_type(LinearAsset::new(1.0))
+            .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+            .queue_model(L3FIFOQueueModel::new())
+            .exchange(NoPartialFillExchange)
+            .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+            .session_open(200)
+            .build();
+        assert!(matches!(asset, Err(BuildError::InvalidArgument(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn auction_fallback_policy_without_uncross_event() -> Result<(), Box<dyn Error>> {
+        let build = |auction_fallback_policy: AuctionFallbackPolicy| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                // Crosses the order book before the open, but no auction price is delivered.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT | AUCTION_UPDATE_EVENT,
+                    100,
+                    99.0,
+                    3.0,
+                    3,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .session_open(200)
+                        .auction_fallback_policy(auction_fallback_policy)
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_order(
+                0,
+                OrderRequest {
+                    order_id: 10,
+                    price: 100.0,
+                    qty: 2.0,
+                    side: Side::Buy,
+                    time_in_force: TimeInForce::GTC,
+                    order_type: OrdType::Limit,
+                    max_lifetime: 0,
+                    opening_auction: true,
+                },
+                true,
+            )?;
+            hbt.elapse(299)?;
+            Ok(hbt)
+        };
+
+        // The pre-open orders stay as they are.
+        let hbt = build(AuctionFallbackPolicy::None)?;
+        assert!(hbt.last_auction(0).is_none());
+        assert_eq!(hbt.orders(0).get(&10).unwrap().status, Status::New);
+
+        // The bids of 3 at 100 and the ask of 3 at 99 match at 99 as well as at 100, where the
+        // lower price is chosen.
+        let hbt = build(AuctionFallbackPolicy::TheoreticalUncross)?;
+        let auction = hbt.last_auction(0).unwrap();
+        assert_eq!(auction.price, 99.0);
+        assert_eq!(auction.exch_ts, 200);
+        assert_eq!(auction.matched_qty, 3.0);
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Filled);
+        assert_eq!(order.exec_price(), 99.0);
+        assert_eq!(order.exch_timestamp, 200);
+
+        // The backtest order accumulated before the open expires at the open.
+        let hbt = build(AuctionFallbackPolicy::ClearPreOpen)?;
+        assert!(hbt.last_auction(0).is_none());
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.exch_timestamp, 200);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_orders_while_trading_halted() -> Result<(), Box<dyn Error>> {
+        let build = |halt_policy: HaltPolicy| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(EXCH_EVENT | HALT_EVENT, 200, 0.0, 0.0, 0),
+                l3_event(EXCH_EVENT | RESUME_EVENT, 600, 0.0, 0.0, 0),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            Ok(Backtest::builder()
+                .add_asset(
+                    l3_asset(data, PartialFillExchange)
+                        .halt_policy(halt_policy)
+                        .build()?,
+                )
+                .build()?)
+        };
+
+        let mut hbt = build(HaltPolicy::KeepOrders)?;
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(300)?;
+        hbt.submit_buy_order(0, 11, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&11).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.reject_reason, RejectReason::TradingHalted);
+        // The resting order stays in the order book, but can't be modified.
+        hbt.modify(0, 10, 98.0, 1.0, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.reject_reason, RejectReason::TradingHalted);
+
+        hbt.elapse(400)?;
+        hbt.submit_buy_order(0, 12, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&12).unwrap();
+        assert_eq!(order.status, Status::New);
+        assert_eq!(order.reject_reason, RejectReason::None);
+
+        let mut hbt = build(HaltPolicy::CancelOrders)?;
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(300)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Canceled);
+        assert_eq!(order.cancel_reason, CancelReason::TradingHalt);
+        assert_eq!(order.leaves_qty, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn warmup_rejects_orders_until_book_established() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            // Establishes the book with the first level on the ask side.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                100,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).warmup(true).build()?)
+            .build()?;
+
+        hbt.elapse(1)?;
+        assert_eq!(hbt.book_established_timestamp(0), None);
+        // It would take the bid at 100 if the book were established.
+        hbt.submit_sell_order(0, 10, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        let order = hbt.orders(0).get(&10).unwrap();
+        assert_eq!(order.status, Status::Expired);
+        assert_eq!(order.reject_reason, RejectReason::BookNotEstablished);
+        assert_eq!(order.exec_qty, 0.0);
+        assert_eq!(hbt.position(0), 0.0);
+
+        hbt.elapse(200)?;
+        assert_eq!(hbt.book_established_timestamp(0), Some(100));
+        hbt.submit_sell_order(0, 11, 100.0, 1.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        assert_eq!(hbt.orders(0).get(&11).unwrap().status, Status::Filled);
+        assert_eq!(hbt.position(0), -1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn max_lifetime_cancels_order_after_resting() -> Result<(), Box<dyn Error>> {
+        let run = |entry_latency: i64| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    L3AssetBuilder::default()
+                        .data(vec![DataSource::Data(data)])
+                        .latency_model(ConstantLatency::new(entry_latency, 20))
+                        .asset_type(LinearAsset::new(1.0))
+                        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                        .queue_model(L3FIFOQueueModel::new())
+                        .exchange(PartialFillExchange)
+                        .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_order(
+                0,
+                OrderRequest {
+                    order_id: 10,
+                    price: 99.0,
+                    qty: 1.0,
+                    side: Side::Buy,
+                    time_in_force: TimeInForce::GTC,
+                    order_type: OrdType::Limit,
+                    max_lifetime: 100,
+                    opening_auction: false,
+                },
+                true,
+            )?;
+            let rest_ts = hbt.orders(0).get(&10).unwrap().exch_timestamp;
+            // The order is still resting just before its lifetime elapses.
+            hbt.elapse(rest_ts + 99 - hbt.current_timestamp())?;
+            assert_eq!(
+                hbt.exchange_order(0, 10).map(|order| order.status),
+                Some(Status::New)
+            );
+            hbt.elapse(500)?;
+
+            let order = hbt.orders(0).get(&10).unwrap();
+            assert_eq!(order.status, Status::Canceled);
+            assert_eq!(order.cancel_reason, CancelReason::MaxLifetime);
+            assert_eq!(order.canceled_qty, 1.0);
+            Ok((rest_ts, order.exch_timestamp))
+        };
+
+        // The lifetime is measured from the time the order rests, regardless of how long it
+        // takes to reach the exchange, and no market feed event is needed to cancel it.
+        assert_eq!(run(10)?, (11, 111));
+        assert_eq!(run(50)?, (51, 151));
+        Ok(())
     }
 }
@@ -6,11 +6,12 @@ use crate::{
         assettype::AssetType,
         models::{FeeModel, LatencyModel},
         order::LocalToExch,
-        proc::{LocalProcessor, Processor},
-        state::State,
+        proc::{LocalProcessor, OrderOptions, Processor, has_both_sides},
+        state::{PnlSummary, State},
     },
-    depth::{L2MarketDepth, MarketDepth},
+    depth::{L2MarketDepth, MarketDepth, MidHistory},
     types::{
+        AssetParams,
         Event,
         LOCAL_ASK_DEPTH_CLEAR_EVENT,
         LOCAL_ASK_DEPTH_EVENT,
@@ -46,6 +47,9 @@ where
     trades: Vec<Event>,
     last_feed_latency: Option<(i64, i64)>,
     last_order_latency: Option<(i64, i64, i64)>,
+    mid_history: MidHistory,
+    book_established_ts: Option<i64>,
+    last_fill_ts: Option<i64>,
 }
 
 impl<AT, LM, MD, FM> Local<AT, LM, MD, FM>
@@ -70,6 +74,18 @@ where
             trades: Vec::with_capacity(last_trades_cap),
             last_feed_latency: None,
             last_order_latency: None,
+            mid_history: Default::default(),
+            book_established_ts: None,
+            last_fill_ts: None,
+        }
+    }
+
+    /// Sets the number of the most recent mid prices kept, which are sampled on each change of the
+    /// best bid or the best ask. The default value is `0`, indicating that no mid price is kept.
+    pub fn mid_history_capacity(self, capacity: usize) -> Self {
+        Self {
+            mid_history: MidHistory::new(capacity),
+            ..self
         }
     }
 
@@ -98,9 +114,20 @@ where
                 }
             }
 
-            // Processes receiving order response.
-            if order.status == Status::Filled {
-                self.state.apply_fill(&order);
+            // Processes receiving order response. A response can carry several fills, or none
+            // beyond those already applied, so only the executions since the last update of the
+            // local order are applied.
+            if order.req != Status::Rejected {
+                let (cum_exec_qty, cum_exec_notional) = self
+                    .orders
+                    .get(&order.order_id)
+                    .map_or((0.0, 0.0), |local_order| {
+                        (local_order.cum_exec_qty, local_order.cum_exec_notional)
+                    });
+                if let Some(fill) = order.fill_since(cum_exec_qty, cum_exec_notional) {
+                    self.state.apply_fill(&fill);
+                    self.last_fill_ts = Some(timestamp);
+                }
             }
             // Applies the received order response to the local orders.
             match self.orders.entry(order.order_id) {
@@ -108,6 +135,7 @@ where
                     let local_order = entry.get_mut();
                     if order.req == Status::Rejected {
                         if order.local_timestamp == local_order.local_timestamp {
+                            local_order.reject_reason = order.reject_reason;
                             if local_order.req == Status::New {
                                 local_order.req = Status::None;
                                 local_order.status = Status::Expired;
@@ -151,11 +179,19 @@ where
         qty: f64,
         order_type: OrdType,
         time_in_force: TimeInForce,
+        options: OrderOptions,
         current_timestamp: i64,
     ) -> Result<(), BacktestError> {
         if self.orders.contains_key(&order_id) {
             return Err(BacktestError::OrderIdExist);
         }
+        if self.state.notional_limit_reached {
+            return Err(BacktestError::NotionalLimitReached);
+        }
+        if options.opening_auction {
+            // Only the L3 exchange with partial fills runs the opening auction.
+            return Err(BacktestError::InvalidOrderRequest);
+        }
 
         let price_tick = (price / self.depth.tick_size()).round() as i64;
         let mut order = Order::new(
@@ -169,6 +205,8 @@ where
         );
         order.req = Status::New;
         order.local_timestamp = current_timestamp;
+        order.max_lifetime = options.max_lifetime;
+        order.opening_auction = options.opening_auction;
         self.orders.insert(order.order_id, order.clone());
 
         self.order_l2e.request(order, |order| {
@@ -249,6 +287,18 @@ where
         self.state.values()
     }
 
+    fn asset_params(&self) -> AssetParams {
+        self.state
+            .asset_params(self.depth.tick_size(), self.depth.lot_size())
+    }
+
+    fn pnl(&self, timestamp: i64) -> PnlSummary {
+        self.state.pnl(
+            (self.depth.best_bid() + self.depth.best_ask()) / 2.0,
+            timestamp,
+        )
+    }
+
     fn depth(&self) -> &MD {
         &self.depth
     }
@@ -272,6 +322,18 @@ where
     fn order_latency(&self) -> Option<(i64, i64, i64)> {
         self.last_order_latency
     }
+
+    fn mid_history(&self, since: i64) -> Vec<(i64, f64)> {
+        self.mid_history.since(since)
+    }
+
+    fn book_established_timestamp(&self) -> Option<i64> {
+        self.book_established_ts
+    }
+
+    fn last_fill_timestamp(&self) -> Option<i64> {
+        self.last_fill_ts
+    }
 }
 
 impl<AT, LM, MD, FM> Processor for Local<AT, LM, MD, FM>
@@ -303,6 +365,15 @@ where
             self.trades.push(ev.clone());
         }
 
+        self.mid_history.update(&self.depth, ev.local_ts);
+        if has_both_sides(&self.depth) {
+            self.state
+                .mark_to_market((self.depth.best_bid() + self.depth.best_ask()) / 2.0);
+            if self.book_established_ts.is_none() {
+                self.book_established_ts = Some(ev.local_ts);
+            }
+        }
+
         // Stores the current feed latency
         self.last_feed_latency = Some((ev.exch_ts, ev.local_ts));
 
@@ -2,7 +2,11 @@ mod local;
 mod nopartialfillexchange;
 mod partialfillexchange;
 
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    io::Error as IoError,
+};
 
 pub use local::Local;
 pub use nopartialfillexchange::NoPartialFillExchange;
@@ -13,16 +17,520 @@ mod l3_local;
 mod l3_nopartialfillexchange;
 mod l3_partialfillexchange;
 
-pub use l3_local::L3Local;
+pub use l3_local::{L3Local, MissingOrderPolicy, OutOfRoiPolicy};
 pub use l3_nopartialfillexchange::L3NoPartialFillExchange;
-pub use l3_partialfillexchange::L3PartialFillExchange;
+pub use l3_partialfillexchange::{
+    AuctionAlgorithm, AuctionFallbackPolicy, L3PartialFillExchange, SelfTradePrevention,
+};
 
 use crate::{
-    backtest::BacktestError,
-    depth::MarketDepth,
-    prelude::{Event, OrdType, Order, OrderId, Side, StateValues, TimeInForce},
+    backtest::{
+        BacktestError,
+        adverseselection::AdverseSelection,
+        auction::AuctionResult,
+        fillgap::{FillGap, FillPriceBucket},
+        fillrecord::FillRecord,
+        lifetimevwap::LifetimeVwap,
+        opportunitycost::OpportunityCost,
+        orderlog::OrderLogEntry,
+        printdivergence::PrintDivergence,
+        resilience::SweepRecovery,
+        shadowfill::ShadowFill,
+        state::PnlSummary,
+        timeline::OrderTimelineEntry,
+        touchdistance::TouchDistanceStats,
+    },
+    depth::{INVALID_MAX, INVALID_MIN, MarketDepth},
+    prelude::{
+        AssetParams, Event, OrdType, Order, OrderId, OrderRequest, Side, StateValues, Status,
+        TimeInForce,
+    },
+    types::RejectReason,
 };
 
+/// The number of the most recently filled order IDs that the exchange remembers.
+const RECENT_FILL_CAPACITY: usize = 1024;
+
+/// Remembers the IDs of the most recently filled orders, so that the exchange can tell a request
+/// for an order that has already been filled from one for an unknown order.
+pub(crate) struct RecentFills {
+    order_ids: HashSet<OrderId>,
+    fifo: VecDeque<OrderId>,
+}
+
+impl Default for RecentFills {
+    fn default() -> Self {
+        Self {
+            order_ids: HashSet::with_capacity(RECENT_FILL_CAPACITY),
+            fifo: VecDeque::with_capacity(RECENT_FILL_CAPACITY),
+        }
+    }
+}
+
+impl RecentFills {
+    /// Records the fully filled order.
+    pub fn insert(&mut self, order_id: OrderId) {
+        if !self.order_ids.insert(order_id) {
+            return;
+        }
+        if self.fifo.len() == RECENT_FILL_CAPACITY {
+            if let Some(oldest) = self.fifo.pop_front() {
+                self.order_ids.remove(&oldest);
+            }
+        }
+        self.fifo.push_back(order_id);
+    }
+
+    /// Returns the reason to reject a request for the order that is not found in the exchange.
+    pub fn reject_reason(&self, order_id: OrderId) -> RejectReason {
+        if self.order_ids.contains(&order_id) {
+            RejectReason::AlreadyFilled
+        } else {
+            RejectReason::UnknownOrder
+        }
+    }
+}
+
+/// The key that identifies a market feed fill: the exchange timestamp, the bits of the price and
+/// the quantity, and the order ID.
+type FeedFillKey = (i64, u64, u64, OrderId);
+
+/// Remembers the market feed fills seen within the window, so that the local and the exchange can
+/// ignore a fill event that a dataset emits twice for the same trade, as is common in merged files.
+#[derive(Default)]
+pub(crate) struct FeedFillDedup {
+    window: i64,
+    keys: HashSet<FeedFillKey>,
+    fifo: VecDeque<FeedFillKey>,
+}
+
+impl FeedFillDedup {
+    /// Constructs a `FeedFillDedup` remembering the fills for `window` nanoseconds of exchange
+    /// time. A `window` of `0` disables the deduplication.
+    pub fn new(window: i64) -> Self {
+        Self {
+            window,
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if the fill event repeats a fill seen within the window. Otherwise, records
+    /// the fill and returns `false`.
+    pub fn is_duplicate(&mut self, event: &Event) -> bool {
+        if self.window <= 0 {
+            return false;
+        }
+        while let Some(&key) = self.fifo.front() {
+            if key.0 >= event.exch_ts - self.window {
+                break;
+            }
+            self.fifo.pop_front();
+            self.keys.remove(&key);
+        }
+        let key = (
+            event.exch_ts,
+            event.px.to_bits(),
+            event.qty.to_bits(),
+            event.order_id,
+        );
+        if !self.keys.insert(key) {
+            return true;
+        }
+        self.fifo.push_back(key);
+        false
+    }
+}
+
+/// Determines whether a resting order loses its queue priority when it is replaced.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum PriorityResetPolicy {
+    /// Every replacement resets the queue priority, as on a pure price-time venue.
+    AlwaysReset,
+    /// Reducing only the quantity keeps the queue priority, while changing the price or increasing
+    /// the quantity resets it.
+    #[default]
+    KeepOnSizeDown,
+}
+
+impl PriorityResetPolicy {
+    /// Returns `true` if replacing the resting order at `prev_price_tick` with `prev_qty` by
+    /// `order` resets its queue priority.
+    pub fn resets(&self, prev_price_tick: i64, prev_qty: f64, order: &Order) -> bool {
+        match self {
+            PriorityResetPolicy::AlwaysReset => true,
+            PriorityResetPolicy::KeepOnSizeDown => {
+                order.price_tick != prev_price_tick || order.qty > prev_qty
+            }
+        }
+    }
+}
+
+/// Determines how the exchange handles the resting backtest orders when trading halts.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum HaltPolicy {
+    /// The resting orders stay in the order book through the halt.
+    #[default]
+    KeepOrders,
+    /// The resting orders are canceled when trading halts, with
+    /// [`CancelReason::TradingHalt`](crate::types::CancelReason::TradingHalt).
+    CancelOrders,
+}
+
+/// Determines how the exchange handles the residual quantity of a partially filled backtest order
+/// that falls below the lot size.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum SubLotResidualPolicy {
+    /// The residual stays in the order book.
+    #[default]
+    Keep,
+    /// The residual is canceled right after the fill, with
+    /// [`CancelReason::SubLotResidual`](crate::types::CancelReason::SubLotResidual), as on venues
+    /// that auto-cancel the dust.
+    Cancel,
+}
+
+/// Determines how the exchange responds to the consecutive fills of an order taking multiple
+/// price levels at once.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum FillResponseMode {
+    /// Each fill is responded separately, one per price level.
+    #[default]
+    PerFill,
+    /// The fills are aggregated into a single response carrying the total filled quantity and
+    /// the average price, rounded to the nearest tick.
+    Batched,
+}
+
+/// Determines how the exchange handles a new limit order meant to rest that is priced at or
+/// through the best price on the opposite side, which would lock or cross the market.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum LockedMarketPolicy {
+    /// The order takes the liquidity it crosses, as in the usual matching.
+    #[default]
+    Match,
+    /// The order is rejected with [`RejectReason::WouldLockMarket`], as on venues that don't let
+    /// a resting order lock or cross the market. The orders meant to take liquidity,
+    /// [`TimeInForce::IOC`] and [`TimeInForce::FOK`], and the post-only orders,
+    /// [`TimeInForce::GTX`], which expire by their own rule, are not affected.
+    Reject,
+}
+
+impl LockedMarketPolicy {
+    /// Returns `true` if the order is rejected for locking or crossing the market with the given
+    /// best prices.
+    pub fn rejects(&self, order: &Order, best_bid_tick: i64, best_ask_tick: i64) -> bool {
+        *self == LockedMarketPolicy::Reject
+            && order.order_type == OrdType::Limit
+            && order.time_in_force == TimeInForce::GTC
+            && match order.side {
+                Side::Buy => order.price_tick >= best_ask_tick,
+                Side::Sell => order.price_tick <= best_bid_tick,
+                Side::None | Side::Unsupported => false,
+            }
+    }
+}
+
+/// Determines how the exchange processes a modify request.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum ModifyMode {
+    /// The order is amended in place, keeping its exchange order ID. Whether it keeps its queue
+    /// priority is determined by the [`PriorityResetPolicy`].
+    #[default]
+    Amend,
+    /// The order is canceled and a new order is added in its place, as on venues that implement
+    /// a modify as a cancel-replace internally. The new order always joins the tail of its price
+    /// level and gets a new exchange order ID.
+    CancelReplace,
+}
+
+/// Determines the reference price that triggers a stop order.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum StopTrigger {
+    /// The stop order is triggered by the price of the last trade.
+    #[default]
+    LastTrade,
+    /// The stop order is triggered by the best bid, so that it can be activated by a quote move
+    /// even without a trade.
+    BestBid,
+    /// The stop order is triggered by the best ask.
+    BestAsk,
+    /// The stop order is triggered by the mid price.
+    Mid,
+}
+
+/// Holds the stop orders waiting for their trigger. A buy stop order is triggered when the
+/// reference price rises to or above its stop price, and a sell stop order is triggered when the
+/// reference price falls to or below its stop price. The order price is used as the stop price.
+#[derive(Default)]
+pub(crate) struct StopOrders {
+    trigger: StopTrigger,
+    orders: Vec<Order>,
+    last_trade_tick: Option<i64>,
+}
+
+impl StopOrders {
+    /// Constructs a `StopOrders` triggered by the given reference price.
+    pub fn new(trigger: StopTrigger) -> Self {
+        Self {
+            trigger,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the pending stop order.
+    pub fn get(&self, order_id: OrderId) -> Option<&Order> {
+        self.orders.iter().find(|order| order.order_id == order_id)
+    }
+
+    /// Adds the stop order, which waits for its trigger.
+    pub fn insert(&mut self, order: Order) {
+        self.orders.push(order);
+    }
+
+    /// Removes and returns the pending stop order.
+    pub fn remove(&mut self, order_id: OrderId) -> Option<Order> {
+        let i = self
+            .orders
+            .iter()
+            .position(|order| order.order_id == order_id)?;
+        Some(self.orders.remove(i))
+    }
+
+    /// Removes and returns all the pending stop orders.
+    pub fn drain(&mut self) -> Vec<Order> {
+        std::mem::take(&mut self.orders)
+    }
+
+    /// Records the price of the last trade.
+    pub fn on_trade(&mut self, price_tick: i64) {
+        self.last_trade_tick = Some(price_tick);
+    }
+
+    /// Returns the reference price in ticks, which can be a half tick for the mid price. `None` if
+    /// the reference price isn't available.
+    fn reference_tick<MD: MarketDepth>(&self, depth: &MD) -> Option<f64> {
+        let best_bid_tick = depth.best_bid_tick();
+        let best_ask_tick = depth.best_ask_tick();
+        match self.trigger {
+            StopTrigger::LastTrade => self.last_trade_tick.map(|tick| tick as f64),
+            StopTrigger::BestBid => (best_bid_tick != INVALID_MIN).then_some(best_bid_tick as f64),
+            StopTrigger::BestAsk => (best_ask_tick != INVALID_MAX).then_some(best_ask_tick as f64),
+            StopTrigger::Mid => (best_bid_tick != INVALID_MIN && best_ask_tick != INVALID_MAX)
+                .then_some((best_bid_tick + best_ask_tick) as f64 / 2.0),
+        }
+    }
+
+    /// Returns `true` if the stop order is triggered by the current reference price.
+    pub fn is_triggered<MD: MarketDepth>(&self, order: &Order, depth: &MD) -> bool {
+        match self.reference_tick(depth) {
+            Some(reference_tick) => match order.side {
+                Side::Buy => reference_tick >= order.price_tick as f64,
+                Side::Sell => reference_tick <= order.price_tick as f64,
+                Side::None | Side::Unsupported => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Removes and returns the stop orders triggered by the current reference price, in the order
+    /// they were submitted.
+    pub fn take_triggered<MD: MarketDepth>(&mut self, depth: &MD) -> Vec<Order> {
+        if self.orders.is_empty() {
+            return Vec::new();
+        }
+        let (triggered, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.orders)
+            .into_iter()
+            .partition(|order| self.is_triggered(order, depth));
+        self.orders = pending;
+        triggered
+    }
+}
+
+/// Tracks the deadlines of the resting orders that have a maximum lifetime, each measured from
+/// the time the order rests in the order book. The deadlines are kept ordered so that the earliest
+/// one is found without scanning every resting order, and a deadline is removed once the response
+/// shows that its order has left the order book.
+#[derive(Default)]
+pub(crate) struct MaxLifetimes {
+    deadlines: HashMap<OrderId, i64>,
+    by_deadline: BTreeSet<(i64, OrderId)>,
+}
+
+impl MaxLifetimes {
+    /// Starts the lifetime of the order resting at `timestamp`, replacing the deadline left by a
+    /// previous order with the same order ID.
+    pub fn on_rest(&mut self, order: &Order, timestamp: i64) {
+        self.remove(order.order_id);
+        if order.max_lifetime > 0 {
+            let deadline = timestamp + order.max_lifetime;
+            self.deadlines.insert(order.order_id, deadline);
+            self.by_deadline.insert((deadline, order.order_id));
+        }
+    }
+
+    /// Ends the lifetime of the order if the response shows that it has left the order book.
+    pub fn on_response(&mut self, order: &Order) {
+        if matches!(
+            order.status,
+            Status::Filled | Status::Canceled | Status::Expired
+        ) {
+            self.remove(order.order_id);
+        }
+    }
+
+    fn remove(&mut self, order_id: OrderId) {
+        if let Some(deadline) = self.deadlines.remove(&order_id) {
+            self.by_deadline.remove(&(deadline, order_id));
+        }
+    }
+
+    /// Returns the earliest deadline.
+    pub fn earliest(&self) -> Option<i64> {
+        self.by_deadline.first().map(|&(deadline, _)| deadline)
+    }
+
+    /// Removes and returns the IDs of the orders whose deadline is at or before `timestamp`, in
+    /// the order of their deadline.
+    pub fn take_expired(&mut self, timestamp: i64) -> Vec<OrderId> {
+        let mut expired = Vec::new();
+        while let Some(&(deadline, order_id)) = self.by_deadline.first() {
+            if deadline > timestamp {
+                break;
+            }
+            self.by_deadline.pop_first();
+            self.deadlines.remove(&order_id);
+            expired.push(order_id);
+        }
+        expired
+    }
+}
+
+/// The exchange's view of a backtest order, which the local sees only after the order latency.
+#[derive(Clone, Debug)]
+pub struct ExchangeOrderView {
+    /// The order status in the exchange.
+    pub status: Status,
+    /// The remaining quantity of the order in the exchange.
+    pub leaves_qty: f64,
+    /// The executed quantity of the order in the exchange.
+    pub exec_qty: f64,
+    /// The price of the order in ticks in the exchange.
+    pub price_tick: i64,
+    /// The quantity queued ahead of the order. `None` if the order isn't resting in the order
+    /// book or the queue model doesn't model its queue position.
+    pub qty_ahead: Option<f64>,
+}
+
+impl ExchangeOrderView {
+    pub(crate) fn new(order: &Order, qty_ahead: Option<f64>) -> Self {
+        Self {
+            status: order.status,
+            leaves_qty: order.leaves_qty,
+            exec_qty: order.exec_qty,
+            price_tick: order.price_tick,
+            qty_ahead,
+        }
+    }
+}
+
+/// Returns `true` if the replacement from `prev_price_tick` to `price_tick` improves the price of
+/// the order on the given side by less than `min_price_improvement` ticks. A replacement that
+/// doesn't improve the price is not subject to the requirement.
+pub(crate) fn lacks_price_improvement(
+    side: Side,
+    prev_price_tick: i64,
+    price_tick: i64,
+    min_price_improvement: i64,
+) -> bool {
+    let improvement = match side {
+        Side::Buy => price_tick - prev_price_tick,
+        Side::Sell => prev_price_tick - price_tick,
+        Side::None | Side::Unsupported => 0,
+    };
+    improvement > 0 && improvement < min_price_improvement
+}
+
+/// Allocates `need_to_fill` at the auction price to the orders with the given quantities, in time
+/// priority, in whole lots. Each order takes as much as it can in time priority, rounded down to
+/// the lot size, and the lots lost to the rounding are then given, one lot at a time, to the
+/// earliest orders that can still take a whole lot. Returns the fill quantity of each order.
+pub(crate) fn allocate_auction_fills(qtys: &[f64], need_to_fill: f64, lot_size: f64) -> Vec<f64> {
+    // Tolerates the floating-point error of the quantities that are whole lots.
+    let whole_lots = |qty: f64| (qty / lot_size + 1e-9).floor().max(0.0) as i64;
+    let mut remaining = need_to_fill;
+    let mut lots: Vec<i64> = qtys
+        .iter()
+        .map(|&qty| {
+            let fill_qty = remaining.min(qty).max(0.0);
+            remaining -= fill_qty;
+            whole_lots(fill_qty)
+        })
+        .collect();
+    let mut lost = whole_lots(need_to_fill) - lots.iter().sum::<i64>();
+    for (lot, &qty) in lots.iter_mut().zip(qtys) {
+        if lost <= 0 {
+            break;
+        }
+        let take = (whole_lots(qty) - *lot).clamp(0, lost);
+        *lot += take;
+        lost -= take;
+    }
+    lots.into_iter().map(|lot| lot as f64 * lot_size).collect()
+}
+
+/// Returns `true` if both sides of the market depth have at least one level.
+pub(crate) fn has_both_sides<MD: MarketDepth>(depth: &MD) -> bool {
+    depth.best_bid_tick() != INVALID_MIN && depth.best_ask_tick() != INVALID_MAX
+}
+
+/// Shifts the order ID of the market feed event by `offset`, so that the market feed order IDs
+/// live in a namespace separate from the backtest order IDs. The event is returned as it is if
+/// `offset` is zero.
+pub(crate) fn offset_feed_order_id(event: &Event, offset: u64) -> Cow<'_, Event> {
+    if offset == 0 {
+        Cow::Borrowed(event)
+    } else {
+        let mut event = event.clone();
+        event.order_id = event.order_id.wrapping_add(offset);
+        Cow::Owned(event)
+    }
+}
+
+/// Sorts the backtest orders filled by the same event into the fill sequence, which is price
+/// priority, then time priority, then the order ID, so that the fills are processed
+/// deterministically regardless of the order in which the queue model returns them.
+pub(crate) fn sort_by_fill_sequence(orders: &mut [Order]) {
+    orders.sort_by(|a, b| {
+        let price_priority = match a.side {
+            Side::Buy => b.price_tick.cmp(&a.price_tick),
+            _ => a.price_tick.cmp(&b.price_tick),
+        };
+        price_priority
+            .then(a.exch_timestamp.cmp(&b.exch_timestamp))
+            .then(a.order_id.cmp(&b.order_id))
+    });
+}
+
+/// Provides the options of a new order beyond its price, quantity, and type. The default has none
+/// of them set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OrderOptions {
+    /// The maximum time in nanoseconds that the order can rest in the order book, or `0` for no
+    /// limit. See [`Order::max_lifetime`].
+    pub max_lifetime: i64,
+    /// Whether the order participates in the opening auction. See [`Order::opening_auction`].
+    pub opening_auction: bool,
+}
+
+impl From<&OrderRequest> for OrderOptions {
+    fn from(order: &OrderRequest) -> Self {
+        Self {
+            max_lifetime: order.max_lifetime,
+            opening_auction: order.opening_auction,
+        }
+    }
+}
+
 /// Provides local-specific interaction.
 pub trait LocalProcessor<MD>: Processor
 where
@@ -38,6 +546,7 @@ where
     ///   the exchange model for details.
     /// * `time_in_force` - Available [`TimeInForce`] options vary depending on the exchange model.
     ///   See to the exchange model for details.
+    /// * `options` - The options of the order. See [`OrderOptions`].
     /// * `current_timestamp` - The current backtesting timestamp.
     #[allow(clippy::too_many_arguments)]
     fn submit_order(
@@ -48,6 +557,7 @@ where
         qty: f64,
         order_type: OrdType,
         time_in_force: TimeInForce,
+        options: OrderOptions,
         current_timestamp: i64,
     ) -> Result<(), BacktestError>;
 
@@ -82,6 +592,37 @@ where
     /// Returns the state's values such as balance, fee, and so on.
     fn state_values(&self) -> &StateValues;
 
+    /// Returns the configured parameters of the asset. The default implementation takes the tick
+    /// size and the lot size from the market depth, with a contract size of `1.0` and no fees.
+    fn asset_params(&self) -> AssetParams {
+        let depth = self.depth();
+        AssetParams {
+            tick_size: depth.tick_size(),
+            lot_size: depth.lot_size(),
+            contract_size: 1.0,
+            maker_fee: 0.0,
+            taker_fee: 0.0,
+        }
+    }
+
+    /// Returns the profit and loss marked to the current mid price, both gross and net of fees,
+    /// with the held position marked to `timestamp` for the average holding time. The profit and
+    /// loss values are `NaN` if either side of the market depth is empty.
+    fn pnl(&self, timestamp: i64) -> PnlSummary;
+
+    /// Returns the profit and loss with the held position valued at the price at which it is
+    /// marked to market, which depends on the local model's mark frequency. It is the same as
+    /// [`pnl`](LocalProcessor::pnl) unless the local model supports a different frequency.
+    fn marked_pnl(&self, timestamp: i64) -> PnlSummary {
+        self.pnl(timestamp)
+    }
+
+    /// Returns the maximum drawdown of the equity marked to market. `None` unless the local model
+    /// supports it.
+    fn max_drawdown(&self) -> Option<f64> {
+        None
+    }
+
     /// Returns the [`MarketDepth`].
     fn depth(&self) -> &MD;
 
@@ -100,6 +641,95 @@ where
     /// Returns the last order's request timestamp, exchange timestamp, and response receipt
     /// timestamp.
     fn order_latency(&self) -> Option<(i64, i64, i64)>;
+
+    /// Returns the order log, which records when the orders rest in the order book and when
+    /// they are filled. It is empty unless the local model supports and enables it.
+    fn order_log(&self) -> &[OrderLogEntry] {
+        &[]
+    }
+
+    /// Clears the order log.
+    fn clear_order_log(&mut self) {}
+
+    /// Returns the market VWAP over the lifetime of each order whose lifetime has ended,
+    /// compared against the order's fills. It is empty unless the local model supports and
+    /// enables it.
+    fn lifetime_vwaps(&self) -> &[LifetimeVwap] {
+        &[]
+    }
+
+    /// Clears the lifetime VWAP records.
+    fn clear_lifetime_vwaps(&mut self) {}
+
+    /// Returns the mid price move over the horizon after each order that ended canceled or
+    /// expired with an unfilled quantity, whose horizon has ended. It is empty unless the local
+    /// model supports and enables it.
+    fn opportunity_costs(&self) -> &[OpportunityCost] {
+        &[]
+    }
+
+    /// Clears the opportunity cost records.
+    fn clear_opportunity_costs(&mut self) {}
+
+    /// Returns the mid price move over the horizon after each maker fill, whose horizon has
+    /// ended. It is empty unless the local model supports and enables it.
+    fn adverse_selections(&self) -> &[AdverseSelection] {
+        &[]
+    }
+
+    /// Clears the adverse selection records.
+    fn clear_adverse_selections(&mut self) {}
+
+    /// Returns the price levels emptied by the market feed fills that have recovered. It is empty
+    /// unless the local model supports and enables it.
+    fn sweep_recoveries(&self) -> &[SweepRecovery] {
+        &[]
+    }
+
+    /// Clears the sweep recovery records.
+    fn clear_sweep_recoveries(&mut self) {}
+
+    /// Returns the lifecycle timeline of the order, from the submission to the terminal response.
+    /// It is empty unless the local model supports and enables it, or if the order is not
+    /// recorded.
+    fn order_timeline(&self, _order_id: OrderId) -> &[OrderTimelineEntry] {
+        &[]
+    }
+
+    /// Writes the buffered records of the audit trail to its file. This is invoked when the
+    /// backtest is closed. It does nothing unless the local model supports and enables it.
+    fn flush_audit_log(&mut self) -> Result<(), IoError> {
+        Ok(())
+    }
+
+    /// Returns the outcomes of the orders counted by the distance from the touch at which each
+    /// order is submitted. It is empty unless the local model supports and enables it.
+    fn touch_distance_stats(&self) -> &[TouchDistanceStats] {
+        &[]
+    }
+
+    /// Clears the counts of the outcomes by the distance from the touch.
+    fn clear_touch_distance_stats(&mut self) {}
+
+    /// Returns the mid prices sampled at or after `since` on each change of the best bid or the
+    /// best ask, as `(timestamp, mid)` from the oldest. It is empty unless the local model
+    /// supports and enables it.
+    fn mid_history(&self, _since: i64) -> Vec<(i64, f64)> {
+        Vec::new()
+    }
+
+    /// Returns the local timestamp at which both sides of the market depth first have at least
+    /// one level, or `None` if the order book hasn't been established yet. Until then, the best
+    /// bid or the best ask is invalid.
+    fn book_established_timestamp(&self) -> Option<i64> {
+        None
+    }
+
+    /// Returns the local timestamp at which the most recent fill was received, or `None` if no
+    /// order has been filled yet.
+    fn last_fill_timestamp(&self) -> Option<i64> {
+        None
+    }
 }
 
 impl<P: Processor + ?Sized> Processor for Box<P> {
@@ -126,6 +756,58 @@ impl<P: Processor + ?Sized> Processor for Box<P> {
     fn earliest_send_order_timestamp(&self) -> i64 {
         P::earliest_send_order_timestamp(self)
     }
+
+    fn fill_gaps(&self) -> &[FillGap] {
+        P::fill_gaps(self)
+    }
+
+    fn clear_fill_gaps(&mut self) {
+        P::clear_fill_gaps(self)
+    }
+
+    fn fill_records(&self) -> &[FillRecord] {
+        P::fill_records(self)
+    }
+
+    fn clear_fill_records(&mut self) {
+        P::clear_fill_records(self)
+    }
+
+    fn fill_price_histogram(&self) -> &[FillPriceBucket] {
+        P::fill_price_histogram(self)
+    }
+
+    fn clear_fill_price_histogram(&mut self) {
+        P::clear_fill_price_histogram(self)
+    }
+
+    fn print_divergences(&self) -> &[PrintDivergence] {
+        P::print_divergences(self)
+    }
+
+    fn clear_print_divergences(&mut self) {
+        P::clear_print_divergences(self)
+    }
+
+    fn flush_print_divergences(&mut self) {
+        P::flush_print_divergences(self)
+    }
+
+    fn shadow_fills(&self) -> &[ShadowFill] {
+        P::shadow_fills(self)
+    }
+
+    fn clear_shadow_fills(&mut self) {
+        P::clear_shadow_fills(self)
+    }
+
+    fn last_auction(&self) -> Option<&AuctionResult> {
+        P::last_auction(self)
+    }
+
+    fn exchange_order(&self, order_id: OrderId) -> Option<ExchangeOrderView> {
+        P::exchange_order(self, order_id)
+    }
 }
 /// Processes the historical feed data and the order interaction.
 pub trait Processor {
@@ -155,4 +837,114 @@ pub trait Processor {
     /// Returns the foremost timestamp at which an order sent by this processor is to be received by
     /// the corresponding processor.
     fn earliest_send_order_timestamp(&self) -> i64;
+
+    /// Returns the fills compared against the mid price at the time of the fill, recorded by the
+    /// exchange processor. Returns an empty slice if the processor doesn't record them.
+    fn fill_gaps(&self) -> &[FillGap] {
+        &[]
+    }
+
+    /// Clears the recorded fill gaps.
+    fn clear_fill_gaps(&mut self) {}
+
+    /// Returns every fill in the exchange, recorded by the exchange processor. Returns an empty
+    /// slice if the processor doesn't record them.
+    fn fill_records(&self) -> &[FillRecord] {
+        &[]
+    }
+
+    /// Clears the recorded fills.
+    fn clear_fill_records(&mut self) {}
+
+    /// Returns the executed quantity of the fills by the distance from the mid price at the time
+    /// of the fill, accumulated by the exchange processor. Returns an empty slice if the
+    /// processor doesn't accumulate it.
+    fn fill_price_histogram(&self) -> &[FillPriceBucket] {
+        &[]
+    }
+
+    /// Clears the fill price histogram.
+    fn clear_fill_price_histogram(&mut self) {}
+
+    /// Returns the fills compared against the nearest market print in the feed, recorded by the
+    /// exchange processor. Returns an empty slice if the processor doesn't record them.
+    fn print_divergences(&self) -> &[PrintDivergence] {
+        &[]
+    }
+
+    /// Clears the recorded print divergences.
+    fn clear_print_divergences(&mut self) {}
+
+    /// Compares the fills still waiting for the market prints with the prints processed so far.
+    /// This is invoked at the end of the backtest.
+    fn flush_print_divergences(&mut self) {}
+
+    /// Returns the liquidity taken by the new orders on arrival compared against a replay under
+    /// an alternative order entry latency, recorded by the exchange processor. Returns an empty
+    /// slice if the processor doesn't record them.
+    fn shadow_fills(&self) -> &[ShadowFill] {
+        &[]
+    }
+
+    /// Clears the recorded shadow fills.
+    fn clear_shadow_fills(&mut self) {}
+
+    /// Returns the result of the last auction uncrossed by the exchange processor, or as observed
+    /// by the local processor from the auction responses. Returns `None` if no auction has
+    /// occurred or the processor doesn't process auctions.
+    fn last_auction(&self) -> Option<&AuctionResult> {
+        None
+    }
+
+    /// Returns the exchange's view of the backtest order, which is authoritative regardless of
+    /// the order latency. Returns `None` if the exchange neither holds the order nor has a
+    /// response for it that is yet to be received by the local, or if the processor doesn't
+    /// support it.
+    fn exchange_order(&self, _order_id: OrderId) -> Option<ExchangeOrderView> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backtest::proc::MaxLifetimes,
+        types::{OrdType, Order, Side, Status, TimeInForce},
+    };
+
+    fn order(order_id: u64, max_lifetime: i64) -> Order {
+        let mut order = Order::new(
+            order_id,
+            100,
+            1.0,
+            1.0,
+            Side::Buy,
+            OrdType::Limit,
+            TimeInForce::GTC,
+        );
+        order.max_lifetime = max_lifetime;
+        order
+    }
+
+    #[test]
+    fn test_max_lifetimes() {
+        let mut max_lifetimes = MaxLifetimes::default();
+        max_lifetimes.on_rest(&order(1, 300), 0);
+        max_lifetimes.on_rest(&order(2, 100), 50);
+        max_lifetimes.on_rest(&order(3, 200), 0);
+        assert_eq!(max_lifetimes.earliest(), Some(150));
+
+        // The order that has left the order book no longer has a deadline.
+        let mut filled = order(2, 100);
+        filled.status = Status::Filled;
+        max_lifetimes.on_response(&filled);
+        assert_eq!(max_lifetimes.earliest(), Some(200));
+
+        // Resting again replaces the deadline.
+        max_lifetimes.on_rest(&order(1, 100), 50);
+        assert_eq!(max_lifetimes.earliest(), Some(150));
+
+        assert_eq!(max_lifetimes.take_expired(250), vec![1, 3]);
+        assert_eq!(max_lifetimes.earliest(), None);
+    }
 }
@@ -11,12 +11,13 @@ use crate::{
         assettype::AssetType,
         models::{FeeModel, LatencyModel, QueueModel},
         order::ExchToLocal,
-        proc::Processor,
+        proc::{MaxLifetimes, Processor},
         state::State,
     },
     depth::{INVALID_MAX, INVALID_MIN, L2MarketDepth, MarketDepth},
     prelude::OrdType,
     types::{
+        CancelReason,
         EXCH_ASK_DEPTH_CLEAR_EVENT,
         EXCH_ASK_DEPTH_EVENT,
         EXCH_ASK_DEPTH_SNAPSHOT_EVENT,
@@ -82,6 +83,7 @@ where
     queue_model: QM,
 
     filled_orders: Vec<OrderId>,
+    max_lifetimes: MaxLifetimes,
 }
 
 impl<AT, LM, QM, MD, FM> NoPartialFillExchange<AT, LM, QM, MD, FM>
@@ -108,6 +110,7 @@ where
             state,
             queue_model,
             filled_orders: Default::default(),
+            max_lifetimes: Default::default(),
         }
     }
 
@@ -184,13 +187,15 @@ where
 
         order.exec_qty = order.leaves_qty;
         order.leaves_qty = 0.0;
+        order.cum_exec_qty += order.exec_qty;
+        order.cum_exec_notional += order.exec_price() * order.exec_qty;
         order.status = Status::Filled;
         order.exch_timestamp = timestamp;
 
         self.state.apply_fill(order);
 
         if MAKE_RESPONSE {
-            self.order_e2l.respond(order.clone());
+            self.respond(order.clone());
         }
         Ok(())
     }
@@ -356,6 +361,7 @@ where
                                 self.orders
                                     .borrow_mut()
                                     .insert(order.order_id, order.clone());
+                                self.max_lifetimes.on_rest(order, timestamp);
                                 Ok(())
                             }
                             TimeInForce::FOK | TimeInForce::IOC => {
@@ -371,7 +377,9 @@ where
                     // Takes the market.
                     self.fill::<false>(order, timestamp, false, self.depth.best_ask_tick())
                 }
-                OrdType::Unsupported => Err(BacktestError::InvalidOrderRequest),
+                OrdType::StopMarket | OrdType::Unsupported => {
+                    Err(BacktestError::InvalidOrderRequest)
+                }
             }
         } else {
             match order.order_type {
@@ -413,6 +421,7 @@ where
                                 self.orders
                                     .borrow_mut()
                                     .insert(order.order_id, order.clone());
+                                self.max_lifetimes.on_rest(order, timestamp);
                                 Ok(())
                             }
                             TimeInForce::FOK | TimeInForce::IOC => {
@@ -428,7 +437,9 @@ where
                     // Takes the market.
                     self.fill::<false>(order, timestamp, false, self.depth.best_bid_tick())
                 }
-                OrdType::Unsupported => Err(BacktestError::InvalidOrderRequest),
+                OrdType::StopMarket | OrdType::Unsupported => {
+                    Err(BacktestError::InvalidOrderRequest)
+                }
             }
         }
     }
@@ -493,6 +504,7 @@ where
             || order.qty > prev_leaves_qty
         {
             self.ack_cancel(order, timestamp)?;
+            order.modify_count += 1;
             self.ack_new(order, timestamp)?;
         } else {
             let mut order_borrowed = self.orders.borrow_mut();
@@ -501,10 +513,35 @@ where
 
             exch_order.qty = order.qty;
             exch_order.exch_timestamp = timestamp;
+            exch_order.modify_count += 1;
+            order.modify_count = exch_order.modify_count;
             order.exch_timestamp = timestamp;
         }
         Ok(())
     }
+
+    /// Cancels the resting orders that have rested for their maximum lifetime by `timestamp`.
+    fn cancel_expired_lifetimes(&mut self, timestamp: i64) -> Result<(), BacktestError> {
+        for order_id in self.max_lifetimes.take_expired(timestamp) {
+            let Some(mut order) = self.orders.borrow().get(&order_id).cloned() else {
+                continue;
+            };
+            self.ack_cancel(&mut order, timestamp)?;
+            order.canceled_qty = order.leaves_qty;
+            order.leaves_qty = 0.0;
+            order.exec_qty = 0.0;
+            order.cancel_reason = CancelReason::MaxLifetime;
+            self.respond(order);
+        }
+        Ok(())
+    }
+
+    /// Sends the response to the local, ending the maximum lifetime of the order once it leaves
+    /// the order book.
+    fn respond(&mut self, order: Order) {
+        self.max_lifetimes.on_response(&order);
+        self.order_e2l.respond(order);
+    }
 }
 
 impl<AT, LM, QM, MD, FM> Processor for NoPartialFillExchange<AT, LM, QM, MD, FM>
@@ -604,6 +641,8 @@ where
         timestamp: i64,
         _wait_resp_order_id: Option<OrderId>,
     ) -> Result<bool, BacktestError> {
+        self.cancel_expired_lifetimes(timestamp)?;
+
         while let Some(mut order) = self.order_e2l.receive(timestamp) {
             // Processes a new order.
             if order.req == Status::New {
@@ -623,15 +662,17 @@ where
                 return Err(BacktestError::InvalidOrderRequest);
             }
             // Makes the response.
-            self.order_e2l.respond(order);
+            self.respond(order);
         }
         Ok(false)
     }
 
     fn earliest_recv_order_timestamp(&self) -> i64 {
+        // Wakes up at the earliest lifetime deadline to cancel the order exactly when it expires.
         self.order_e2l
             .earliest_recv_order_timestamp()
             .unwrap_or(i64::MAX)
+            .min(self.max_lifetimes.earliest().unwrap_or(i64::MAX))
     }
 
     fn earliest_send_order_timestamp(&self) -> i64 {
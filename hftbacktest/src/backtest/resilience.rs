@@ -0,0 +1,270 @@
+use crate::{depth::MarketDepth, types::Side};
+
+/// The recovery of a price level emptied by fills, measuring how fast the order book refills
+/// after a taker sweeps the level.
+#[derive(Clone, Debug)]
+pub struct SweepRecovery {
+    /// The side of the swept level.
+    pub side: Side,
+    /// The price of the swept level.
+    pub price: f64,
+    /// The quantity at the level before the fills that emptied it.
+    pub pre_sweep_qty: f64,
+    /// The time at which the level is emptied.
+    pub sweep_ts: i64,
+    /// The time at which the quantity at the level recovers to the recovery fraction of
+    /// `pre_sweep_qty`.
+    pub recovery_ts: i64,
+}
+
+impl SweepRecovery {
+    /// Returns the time in nanoseconds taken for the level to recover.
+    pub fn recovery_time(&self) -> i64 {
+        self.recovery_ts - self.sweep_ts
+    }
+}
+
+/// The aggregate of the [`SweepRecovery`]s over a run, the resilience of the order book.
+#[derive(Clone, Debug, Default)]
+pub struct ResilienceSummary {
+    /// The number of the swept levels that recovered.
+    pub num_sweeps: usize,
+    /// The average recovery time in nanoseconds. `0` if no swept level recovered.
+    pub avg_recovery_time: f64,
+    /// The longest recovery time in nanoseconds.
+    pub max_recovery_time: i64,
+}
+
+impl ResilienceSummary {
+    /// Aggregates the given sweep recovery records.
+    pub fn new(records: &[SweepRecovery]) -> Self {
+        let mut summary = Self {
+            num_sweeps: records.len(),
+            ..Default::default()
+        };
+        if records.is_empty() {
+            return summary;
+        }
+        let mut total_recovery_time = 0;
+        for record in records {
+            total_recovery_time += record.recovery_time();
+            summary.max_recovery_time = summary.max_recovery_time.max(record.recovery_time());
+        }
+        summary.avg_recovery_time = total_recovery_time as f64 / records.len() as f64;
+        summary
+    }
+}
+
+/// Returns the quantity at the price level of the given side.
+pub(crate) fn level_qty<MD: MarketDepth>(depth: &MD, side: Side, price_tick: i64) -> f64 {
+    match side {
+        Side::Buy => depth.bid_qty_at_tick(price_tick),
+        Side::Sell => depth.ask_qty_at_tick(price_tick),
+        Side::None | Side::Unsupported => 0.0,
+    }
+}
+
+/// Tracks the price levels emptied by the market feed fills received by the local until their
+/// quantity recovers to a fraction of the quantity before the sweep. A level swept again before
+/// it recovers starts over, and the levels that never recover are not recorded.
+#[derive(Default)]
+pub struct ResilienceRecorder {
+    recovery_fraction: f64,
+    // The level hit by the fills at the timestamp, with its quantity before the first of them,
+    // as `(side, price_tick, timestamp, qty)`.
+    hit: Option<(Side, i64, i64, f64)>,
+    pending: Vec<(i64, SweepRecovery)>,
+    records: Vec<SweepRecovery>,
+}
+
+impl ResilienceRecorder {
+    /// Constructs an instance of `ResilienceRecorder` that deems a swept level recovered once its
+    /// quantity reaches `recovery_fraction` of the quantity before the sweep. The recorder is
+    /// enabled only if `recovery_fraction` is greater than zero.
+    pub fn new(recovery_fraction: f64) -> Self {
+        Self {
+            recovery_fraction,
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if the recorder is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.recovery_fraction > 0.0
+    }
+
+    /// Starts tracking the level if the fill empties it. This must be invoked after the market
+    /// depth is updated by the fill.
+    ///
+    /// * `prev_level_qty` - The quantity at the level before the fill.
+    pub fn on_fill<MD: MarketDepth>(
+        &mut self,
+        side: Side,
+        price_tick: i64,
+        prev_level_qty: f64,
+        depth: &MD,
+        timestamp: i64,
+    ) {
+        if !self.enabled() {
+            return;
+        }
+        // The fills sweeping the level arrive as separate events at the same timestamp.
+        let pre_sweep_qty = match self.hit {
+            Some((hit_side, hit_price_tick, hit_ts, qty))
+                if hit_side == side && hit_price_tick == price_tick && hit_ts == timestamp =>
+            {
+                qty
+            }
+            _ => prev_level_qty,
+        };
+        self.hit = Some((side, price_tick, timestamp, pre_sweep_qty));
+        if level_qty(depth, side, price_tick) > 0.0 {
+            return;
+        }
+        self.pending.retain(|(pending_price_tick, sweep)| {
+            sweep.side != side || *pending_price_tick != price_tick
+        });
+        self.pending.push((
+            price_tick,
+            SweepRecovery {
+                side,
+                price: price_tick as f64 * depth.tick_size(),
+                pre_sweep_qty,
+                sweep_ts: timestamp,
+                recovery_ts: 0,
+            },
+        ));
+    }
+
+    /// Records the swept levels whose quantity has recovered as of `timestamp`. This must be
+    /// invoked after the market depth is updated by the event at `timestamp`.
+    pub fn on_depth<MD: MarketDepth>(&mut self, depth: &MD, timestamp: i64) {
+        let mut i = 0;
+        while i < self.pending.len() {
+            let (price_tick, sweep) = &self.pending[i];
+            if level_qty(depth, sweep.side, *price_tick)
+                >= self.recovery_fraction * sweep.pre_sweep_qty
+            {
+                let (_, mut sweep) = self.pending.remove(i);
+                sweep.recovery_ts = timestamp;
+                self.records.push(sweep);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Returns the swept levels that have recovered.
+    pub fn records(&self) -> &[SweepRecovery] {
+        self.records.as_slice()
+    }
+
+    /// Clears the swept levels that have recovered.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, Side},
+        types::{ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, FILL_EVENT, LOCAL_EVENT, SELL_EVENT},
+    };
+
+    #[test]
+    fn sweep_recovery_time() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                2.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                2.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                3,
+            ),
+            // A taker sweeps the 4.0 at 100.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                200,
+                100.0,
+                2.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                200,
+                100.0,
+                2.0,
+                2,
+            ),
+            // The level refills to 1.0 and then to 3.0, reaching half of 4.0.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                300,
+                100.0,
+                1.0,
+                4,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                450,
+                100.0,
+                2.0,
+                5,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .fill_counterparty(false)
+                    .resilience_recovery_fraction(0.5)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(400)?;
+        assert!(hbt.sweep_recoveries(0).is_empty());
+        hbt.elapse(600)?;
+
+        let records = hbt.sweep_recoveries(0);
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].side, records[0].price), (Side::Buy, 100.0));
+        assert_eq!(records[0].pre_sweep_qty, 4.0);
+        assert_eq!((records[0].sweep_ts, records[0].recovery_ts), (200, 450));
+        assert_eq!(records[0].recovery_time(), 250);
+
+        let summary = hbt.resilience_summary(0);
+        assert_eq!(summary.num_sweeps, 1);
+        assert_eq!(summary.avg_recovery_time, 250.0);
+        assert_eq!(summary.max_recovery_time, 250);
+
+        hbt.clear_sweep_recoveries(None);
+        assert!(hbt.sweep_recoveries(0).is_empty());
+        Ok(())
+    }
+}
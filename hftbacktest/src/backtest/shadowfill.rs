@@ -0,0 +1,319 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    backtest::models::LatencyModel,
+    types::{OrdType, Order, OrderId, Side, Status},
+};
+
+/// The liquidity that a new order takes on arrival at the exchange, compared against the
+/// liquidity that it would have taken if it had arrived under an alternative order entry latency.
+#[derive(Clone, Debug)]
+pub struct ShadowFill {
+    pub order_id: OrderId,
+    pub side: Side,
+    /// The quantity taken on arrival under the actual latency.
+    pub qty: f64,
+    /// The average price of the quantity taken under the actual latency. `NaN` if nothing is
+    /// taken.
+    pub price: f64,
+    /// The time at which the order arrives at the exchange under the actual latency.
+    pub exch_ts: i64,
+    /// The quantity that would have been taken on arrival under the alternative latency.
+    pub shadow_qty: f64,
+    /// The average price of the quantity that would have been taken under the alternative
+    /// latency. `NaN` if nothing would have been taken.
+    pub shadow_price: f64,
+    /// The time at which the order would have arrived at the exchange under the alternative
+    /// latency.
+    pub shadow_exch_ts: i64,
+}
+
+impl ShadowFill {
+    /// Returns the gross profit and loss that the order would have made under the alternative
+    /// latency relative to the actual one, with the taken quantities marked to `mark`. A positive
+    /// value means that the alternative latency would have been more profitable. The contract
+    /// size and the fees are not taken into account.
+    pub fn pnl_delta(&self, mark: f64) -> f64 {
+        let pnl = |qty: f64, price: f64| if qty > 0.0 { qty * (mark - price) } else { 0.0 };
+        let delta = pnl(self.shadow_qty, self.shadow_price) - pnl(self.qty, self.price);
+        match self.side {
+            Side::Buy => delta,
+            Side::Sell => -delta,
+            Side::None | Side::Unsupported => 0.0,
+        }
+    }
+}
+
+struct PendingShadowFill {
+    fill: ShadowFill,
+    taken_amount: f64,
+    arrived: bool,
+    shadow_arrived: bool,
+}
+
+/// Replays each new order request under an alternative order entry latency as a shadow order,
+/// which takes the liquidity of the market depth at the time it would have arrived at the
+/// exchange, and compares it with the liquidity the order actually takes on arrival. This is a
+/// counterfactual without a rerun: the shadow order neither rests in the queue nor affects the
+/// market depth or the strategy, and only the liquidity taken on arrival is compared, assuming
+/// continuous matching.
+pub struct ShadowFillRecorder<LM> {
+    latency: Option<LM>,
+    requested: HashSet<(OrderId, i64)>,
+    arrivals: Vec<(i64, Order)>,
+    pending: HashMap<(OrderId, i64), PendingShadowFill>,
+    records: Vec<ShadowFill>,
+}
+
+impl<LM> Default for ShadowFillRecorder<LM> {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl<LM> ShadowFillRecorder<LM> {
+    /// Constructs an instance of `ShadowFillRecorder` replaying the orders under the given
+    /// alternative latency model. The recorder is enabled only if `latency` is given.
+    pub fn new(latency: Option<LM>) -> Self {
+        Self {
+            latency,
+            requested: Default::default(),
+            arrivals: Default::default(),
+            pending: Default::default(),
+            records: Default::default(),
+        }
+    }
+
+    /// Returns `true` if the recorder is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.latency.is_some()
+    }
+
+    /// Returns the records of the orders whose shadow and actual arrivals have both occurred.
+    pub fn records(&self) -> &[ShadowFill] {
+        self.records.as_slice()
+    }
+
+    /// Clears the records.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Returns the earliest time at which a scheduled shadow order arrives at the exchange.
+    pub fn earliest_arrival(&self) -> Option<i64> {
+        self.arrivals
+            .iter()
+            .map(|(arrival_ts, _)| *arrival_ts)
+            .min()
+    }
+
+    /// Removes and returns the shadow orders arriving at or before `timestamp`, which must be
+    /// matched against the market depth as of their arrival.
+    pub fn take_arrivals(&mut self, timestamp: i64) -> Vec<Order> {
+        let (arrived, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.arrivals)
+            .into_iter()
+            .partition(|(arrival_ts, _)| *arrival_ts <= timestamp);
+        self.arrivals = waiting;
+        arrived.into_iter().map(|(_, order)| order).collect()
+    }
+
+    /// Records the liquidity that the shadow order takes on its arrival.
+    pub fn on_shadow_fill(&mut self, order: &Order, qty: f64, price: f64) {
+        let key = (order.order_id, order.local_timestamp);
+        if let Some(pending) = self.pending.get_mut(&key) {
+            pending.fill.shadow_qty = qty;
+            pending.fill.shadow_price = price;
+            pending.shadow_arrived = true;
+            self.complete(key);
+        }
+    }
+
+    /// Accumulates the liquidity that the order takes on its actual arrival.
+    pub fn on_fill(&mut self, order: &Order, qty: f64, price: f64) {
+        if let Some(pending) = self
+            .pending
+            .get_mut(&(order.order_id, order.local_timestamp))
+        {
+            pending.fill.qty += qty;
+            pending.taken_amount += qty * price;
+        }
+    }
+
+    /// Completes the actual arrival of the order at `timestamp`.
+    pub fn on_arrival(&mut self, order: &Order, timestamp: i64) {
+        let key = (order.order_id, order.local_timestamp);
+        let Some(pending) = self.pending.get_mut(&key) else {
+            self.requested.remove(&key);
+            return;
+        };
+        if pending.fill.qty > 0.0 {
+            pending.fill.price = pending.taken_amount / pending.fill.qty;
+        }
+        pending.fill.exch_ts = timestamp;
+        pending.arrived = true;
+        self.complete(key);
+    }
+
+    fn complete(&mut self, key: (OrderId, i64)) {
+        if self
+            .pending
+            .get(&key)
+            .is_some_and(|pending| pending.arrived && pending.shadow_arrived)
+        {
+            let pending = self.pending.remove(&key).unwrap();
+            self.requested.remove(&key);
+            self.records.push(pending.fill);
+        }
+    }
+}
+
+impl<LM> ShadowFillRecorder<LM>
+where
+    LM: LatencyModel,
+{
+    /// Schedules the shadow arrival of the new order request sent by the local, unless it has
+    /// already been scheduled. A stop order is not replayed, since it waits for its trigger.
+    pub fn on_request(&mut self, order: &Order) {
+        let Some(latency) = self.latency.as_mut() else {
+            return;
+        };
+        let key = (order.order_id, order.local_timestamp);
+        if order.req != Status::New
+            || order.order_type == OrdType::StopMarket
+            || !self.requested.insert(key)
+        {
+            return;
+        }
+        let entry_latency = latency.entry(order.local_timestamp, order);
+        // The order would have been rejected before reaching the exchange.
+        if entry_latency < 0 {
+            return;
+        }
+        let shadow_exch_ts = order.local_timestamp + entry_latency;
+        self.pending.insert(
+            key,
+            PendingShadowFill {
+                fill: ShadowFill {
+                    order_id: order.order_id,
+                    side: order.side,
+                    qty: 0.0,
+                    price: f64::NAN,
+                    exch_ts: 0,
+                    shadow_qty: 0.0,
+                    shadow_price: f64::NAN,
+                    shadow_exch_ts,
+                },
+                taken_amount: 0.0,
+                arrived: false,
+                shadow_arrived: false,
+            },
+        );
+        self.arrivals.push((shadow_exch_ts, order.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest, DataSource,
+            ExchangeKind::PartialFillExchange,
+            L3AssetBuilder,
+            assettype::LinearAsset,
+            data::Data,
+            models::{CommonFees, ConstantLatency, L3FIFOQueueModel, TradingValueFeeModel},
+            testutil::l3_event,
+        },
+        depth::HashMapMarketDepth,
+        prelude::{Bot, OrdType, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, CANCEL_ORDER_EVENT, EXCH_EVENT, LOCAL_EVENT, SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn shadow_fills_match_rerun_at_alternative_latency() -> Result<(), Box<dyn Error>> {
+        let run = |entry_latency: i64, shadow_entry_latency: i64| -> Result<_, Box<dyn Error>> {
+            let data = Data::from_data(&[
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    100.0,
+                    1.0,
+                    1,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                    0,
+                    103.0,
+                    1.0,
+                    3,
+                ),
+                // The best ask moves up between the two arrivals.
+                l3_event(
+                    EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                    50,
+                    101.0,
+                    1.0,
+                    2,
+                ),
+                l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+            ]);
+
+            let mut hbt = Backtest::builder()
+                .add_asset(
+                    L3AssetBuilder::default()
+                        .data(vec![DataSource::Data(data)])
+                        .latency_model(ConstantLatency::new(entry_latency, 20))
+                        .asset_type(LinearAsset::new(1.0))
+                        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+                        .queue_model(L3FIFOQueueModel::new())
+                        .exchange(PartialFillExchange)
+                        .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+                        .shadow_latency_model(ConstantLatency::new(shadow_entry_latency, 20))
+                        .build()?,
+                )
+                .build()?;
+
+            hbt.elapse(1)?;
+            hbt.submit_buy_order(0, 10, 0.0, 1.0, TimeInForce::GTC, OrdType::Market, false)?;
+            hbt.elapse(500)?;
+            Ok(hbt)
+        };
+
+        let hbt = run(10, 60)?;
+        let fills = hbt.shadow_fills(0);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].order_id, 10);
+        assert_eq!(
+            (fills[0].qty, fills[0].price, fills[0].exch_ts),
+            (1.0, 101.0, 11)
+        );
+        assert_eq!(
+            (
+                fills[0].shadow_qty,
+                fills[0].shadow_price,
+                fills[0].shadow_exch_ts
+            ),
+            (1.0, 103.0, 61)
+        );
+
+        // The counterfactual agrees with an actual rerun at the alternative latency.
+        let rerun = run(60, 10)?;
+        assert_eq!(rerun.state_values(0).position, 1.0);
+        let pnl_delta = rerun.pnl_summary(0).gross_pnl - hbt.pnl_summary(0).gross_pnl;
+        assert!((hbt.shadow_pnl_delta(0).unwrap() - pnl_delta).abs() < 1e-9);
+        assert_eq!(pnl_delta, -2.0);
+        Ok(())
+    }
+}
@@ -1,8 +1,36 @@
 use crate::{
     backtest::{assettype::AssetType, models::FeeModel},
-    types::{Order, StateValues},
+    types::{AssetParams, Order, StateValues},
 };
 
+/// The profit and loss of the trading state, valued at a given price, reported both gross and net
+/// of fees.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PnlSummary {
+    /// The profit and loss ignoring fees.
+    pub gross_pnl: f64,
+    /// The total fees paid. A rebate is counted as a negative fee.
+    pub fee: f64,
+    /// The profit and loss after fees and rebates, which is `gross_pnl - fee`.
+    pub net_pnl: f64,
+    /// The part of `gross_pnl` realized by round trips, the spread captured between the average
+    /// entry price of the position and the price at which it is closed.
+    pub spread_pnl: f64,
+    /// The part of `gross_pnl` from marking the held position against its average entry price,
+    /// which is `gross_pnl - spread_pnl`.
+    pub inventory_pnl: f64,
+    /// The position-weighted average holding time in nanoseconds, which is the absolute position
+    /// integrated over time divided by the trading volume. `0` if nothing has been traded.
+    pub avg_holding_time: f64,
+    /// The quantity filled as a maker.
+    pub maker_volume: f64,
+    /// The quantity filled as a taker.
+    pub taker_volume: f64,
+    /// The total fees paid per unit of trading value, blending the maker and taker fee rates by
+    /// the execution mix. `0` if nothing has been traded.
+    pub blended_fee_rate: f64,
+}
+
 #[derive(Debug)]
 pub struct State<AT, FM>
 where
@@ -12,6 +40,21 @@ where
     pub state_values: StateValues,
     pub asset_type: AT,
     pub fee_model: FM,
+    pub max_total_notional: f64,
+    pub notional_limit_reached: bool,
+    /// The part of the balance paid or received to open the held position, so that the rest of
+    /// the balance is realized by round trips.
+    pub open_balance: f64,
+    /// The absolute position integrated over time in nanoseconds, up to `position_ts`.
+    pub position_time: f64,
+    /// The exchange timestamp of the last fill, since which the position has been held.
+    pub position_ts: i64,
+    /// The quantity filled as a maker.
+    pub maker_volume: f64,
+    /// The quantity filled as a taker.
+    pub taker_volume: f64,
+    /// The price at which the held position is marked to market. `NaN` until it is marked.
+    pub mark_price: f64,
 }
 
 impl<AT, FM> State<AT, FM>
@@ -28,21 +71,141 @@ where
                 num_trades: 0,
                 trading_volume: 0.0,
                 trading_value: 0.0,
+                realized_pnl: 0.0,
+                unrealized_pnl: 0.0,
+                avg_entry_price: 0.0,
             },
             fee_model,
             asset_type,
+            max_total_notional: f64::INFINITY,
+            notional_limit_reached: false,
+            open_balance: 0.0,
+            position_time: 0.0,
+            position_ts: 0,
+            maker_volume: 0.0,
+            taker_volume: 0.0,
+            mark_price: f64::NAN,
         }
     }
 
+    /// Sets the cap on the total notional traded, the cumulative trading value. Once the cap is
+    /// reached, [`notional_limit_reached`](State::notional_limit_reached) is set.
+    pub fn max_total_notional(self, max_total_notional: f64) -> Self {
+        Self {
+            max_total_notional,
+            ..self
+        }
+    }
+
+    /// Applies the fill of the order and returns the fee charged for the fill, which is negative
+    /// for a rebate. The fill is the order's latest execution of
+    /// [`exec_qty`](Order::exec_qty) at [`exec_price`](Order::exec_price), not its cumulative
+    /// execution; see [`Order::fill_since`] for a response that carries several fills.
     #[inline]
-    pub fn apply_fill(&mut self, order: &Order) {
+    pub fn apply_fill(&mut self, order: &Order) -> f64 {
         let amount = self.asset_type.amount(order.exec_price(), order.exec_qty);
+        let fee = self.fee_model.amount(order, amount);
+        self.fee_model.on_fill(order, amount);
+        self.apply_open_balance(order, amount);
+        self.position_time = self.position_time_until(order.exch_timestamp);
+        self.position_ts = self.position_ts.max(order.exch_timestamp);
         self.state_values.position += order.exec_qty * AsRef::<f64>::as_ref(&order.side);
         self.state_values.balance -= amount * AsRef::<f64>::as_ref(&order.side);
-        self.state_values.fee += self.fee_model.amount(order, amount);
+        self.state_values.fee += fee;
         self.state_values.num_trades += 1;
         self.state_values.trading_volume += order.exec_qty;
         self.state_values.trading_value += amount;
+        if order.maker {
+            self.maker_volume += order.exec_qty;
+        } else {
+            self.taker_volume += order.exec_qty;
+        }
+        if self.state_values.trading_value >= self.max_total_notional {
+            self.notional_limit_reached = true;
+        }
+        self.state_values.realized_pnl = self.asset_type.equity(
+            order.exec_price(),
+            self.state_values.balance - self.open_balance,
+            0.0,
+            0.0,
+        );
+        // Until the position is marked to market, it is marked at the fill price.
+        let mark_price = if self.mark_price.is_finite() {
+            self.mark_price
+        } else {
+            order.exec_price()
+        };
+        self.mark_to_market(mark_price);
+        fee
+    }
+
+    /// Marks the held position to market at `price`, typically the mid price, which updates the
+    /// unrealized profit and loss. A non-finite price is ignored.
+    pub fn mark_to_market(&mut self, price: f64) {
+        if !price.is_finite() {
+            return;
+        }
+        self.mark_price = price;
+        self.state_values.unrealized_pnl = if self.state_values.position == 0.0 {
+            0.0
+        } else {
+            self.asset_type
+                .equity(price, self.open_balance, self.state_values.position, 0.0)
+        };
+    }
+
+    /// Returns the equity net of fees with the held position valued at the price at which it is
+    /// marked to market. `NaN` until the position is marked.
+    pub fn marked_equity(&self) -> f64 {
+        self.asset_type.equity(
+            self.mark_price,
+            self.state_values.balance,
+            self.state_values.position,
+            self.state_values.fee,
+        )
+    }
+
+    /// Attributes the balance change of the fill to the held position. The part that closes the
+    /// position releases its share of the open balance pro rata, and the rest opens a position,
+    /// whose average entry price is updated accordingly.
+    fn apply_open_balance(&mut self, order: &Order, amount: f64) {
+        let side = *AsRef::<f64>::as_ref(&order.side);
+        let position = self.state_values.position;
+        let flow = -amount * side;
+        let exec_price = order.exec_price();
+        if position == 0.0 || position.signum() == side {
+            self.open_balance += flow;
+            self.state_values.avg_entry_price =
+                (self.state_values.avg_entry_price * position.abs() + exec_price * order.exec_qty)
+                    / (position.abs() + order.exec_qty);
+        } else {
+            let closed_qty = order.exec_qty.min(position.abs());
+            self.open_balance -= self.open_balance * closed_qty / position.abs();
+            // The rest of the fill flips the position to the other side.
+            self.open_balance += flow * (order.exec_qty - closed_qty) / order.exec_qty;
+            if order.exec_qty > closed_qty {
+                self.state_values.avg_entry_price = exec_price;
+            } else if closed_qty == position.abs() {
+                self.state_values.avg_entry_price = 0.0;
+            }
+        }
+    }
+
+    /// Returns the absolute position integrated over time up to `timestamp`, marking the held
+    /// position since the last fill.
+    fn position_time_until(&self, timestamp: i64) -> f64 {
+        let elapsed = timestamp.saturating_sub(self.position_ts).max(0);
+        self.position_time + self.state_values.position.abs() * elapsed as f64
+    }
+
+    /// Returns the position-weighted average holding time in nanoseconds up to `timestamp`, which
+    /// is the absolute position integrated over time divided by the trading volume. The position
+    /// held at `timestamp` is marked to it. Returns `0` if nothing has been traded.
+    pub fn avg_holding_time(&self, timestamp: i64) -> f64 {
+        if self.state_values.trading_volume == 0.0 {
+            return 0.0;
+        }
+        self.position_time_until(timestamp) / self.state_values.trading_volume
     }
 
     #[inline]
@@ -55,8 +218,49 @@ where
         )
     }
 
+    /// Returns the profit and loss valued at `mid`, both gross and net of fees, with the gross
+    /// profit and loss decomposed into spread capture and inventory, along with the average
+    /// holding time up to `timestamp` and the maker and taker volume split.
+    pub fn pnl(&self, mid: f64, timestamp: i64) -> PnlSummary {
+        let gross_pnl = self.asset_type.equity(
+            mid,
+            self.state_values.balance,
+            self.state_values.position,
+            0.0,
+        );
+        let spread_pnl =
+            self.asset_type
+                .equity(mid, self.state_values.balance - self.open_balance, 0.0, 0.0);
+        PnlSummary {
+            gross_pnl,
+            fee: self.state_values.fee,
+            net_pnl: gross_pnl - self.state_values.fee,
+            spread_pnl,
+            inventory_pnl: gross_pnl - spread_pnl,
+            avg_holding_time: self.avg_holding_time(timestamp),
+            maker_volume: self.maker_volume,
+            taker_volume: self.taker_volume,
+            blended_fee_rate: if self.state_values.trading_value == 0.0 {
+                0.0
+            } else {
+                self.state_values.fee / self.state_values.trading_value
+            },
+        }
+    }
+
     #[inline]
     pub fn values(&self) -> &StateValues {
         &self.state_values
     }
+
+    /// Returns the asset parameters with the contract size and the fee rates of this state.
+    pub fn asset_params(&self, tick_size: f64, lot_size: f64) -> AssetParams {
+        AssetParams {
+            tick_size,
+            lot_size,
+            contract_size: self.asset_type.contract_size(),
+            maker_fee: self.fee_model.maker_fee(),
+            taker_fee: self.fee_model.taker_fee(),
+        }
+    }
 }
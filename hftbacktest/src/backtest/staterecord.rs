@@ -0,0 +1,155 @@
+/// A sample of an asset's state taken by the [`StateRecorder`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateSample {
+    /// The sampling time.
+    pub timestamp: i64,
+    /// The equity marked to the mid price as often as the local's
+    /// [`MarkFrequency`](crate::backtest::MarkFrequency), net of fees. With the default frequency,
+    /// `NaN` if either side of the market depth is empty.
+    pub equity: f64,
+    pub position: f64,
+    /// The cumulative number of fills.
+    pub num_fills: i64,
+}
+
+/// Samples the state of each asset at a fixed interval as the backtest elapses, to build the
+/// equity curve. The sampling times are the multiples of the interval after the time at which the
+/// backtest begins, and each sample reflects the events up to and including its sampling time.
+#[derive(Default)]
+pub struct StateRecorder {
+    interval: i64,
+    next_ts: Option<i64>,
+    samples: Vec<Vec<StateSample>>,
+}
+
+impl StateRecorder {
+    /// Constructs an instance of `StateRecorder` sampling every `interval` for `num_assets`
+    /// assets. A non-positive interval disables the sampling.
+    pub fn new(interval: i64, num_assets: usize) -> Self {
+        Self {
+            interval,
+            next_ts: None,
+            samples: vec![Vec::new(); num_assets],
+        }
+    }
+
+    /// Starts the sampling from `timestamp` unless it has already started.
+    #[inline]
+    pub fn start(&mut self, timestamp: i64) {
+        if self.interval > 0 && self.next_ts.is_none() {
+            self.next_ts = Some(timestamp + self.interval);
+        }
+    }
+
+    /// Returns the next sampling time if it is at or before `until`, and advances to the sampling
+    /// time after it.
+    #[inline]
+    pub fn next_due(&mut self, until: i64) -> Option<i64> {
+        let next_ts = self.next_ts.filter(|&next_ts| next_ts <= until)?;
+        self.next_ts = Some(next_ts + self.interval);
+        Some(next_ts)
+    }
+
+    /// Appends the sample of the asset.
+    pub fn push(&mut self, asset_no: usize, sample: StateSample) {
+        self.samples[asset_no].push(sample);
+    }
+
+    /// Returns the samples of the asset from the oldest.
+    pub fn samples(&self, asset_no: usize) -> &[StateSample] {
+        self.samples
+            .get(asset_no)
+            .map(|samples| samples.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Clears the samples of the asset, or of all assets if `asset_no` is `None`.
+    pub fn clear(&mut self, asset_no: Option<usize>) {
+        match asset_no {
+            Some(asset_no) => {
+                if let Some(samples) = self.samples.get_mut(asset_no) {
+                    samples.clear();
+                }
+            }
+            None => self.samples.iter_mut().for_each(Vec::clear),
+        }
+    }
+
+    /// Clears all samples so that the sampling starts over from the next [`start`](Self::start).
+    pub fn reset(&mut self) {
+        self.next_ts = None;
+        self.clear(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, TimeInForce},
+        types::{ADD_ORDER_EVENT, BUY_EVENT, EXCH_EVENT, LOCAL_EVENT, SELL_EVENT},
+    };
+
+    #[test]
+    fn state_samples_at_fixed_interval() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                5.0,
+                2,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 2000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(l3_asset(data, PartialFillExchange).build()?)
+            .state_record_interval(100)
+            .build()?;
+
+        hbt.elapse(250)?;
+        // The fill is received at 280.
+        hbt.submit_buy_order(0, 10, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        hbt.elapse(720)?;
+        assert_eq!(hbt.current_timestamp(), 1000);
+
+        let samples = hbt.state_samples(0);
+        assert_eq!(samples.len(), 1000 / 100);
+        assert_eq!(
+            samples
+                .iter()
+                .map(|sample| sample.timestamp)
+                .collect::<Vec<_>>(),
+            (1..=10).map(|i| i * 100).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (samples[1].equity, samples[1].position, samples[1].num_fills),
+            (0.0, 0.0, 0)
+        );
+        // Marked to the mid price of 100.5 after buying at 101.
+        assert_eq!(
+            (samples[2].equity, samples[2].position, samples[2].num_fills),
+            (-0.5, 1.0, 1)
+        );
+
+        hbt.clear_state_samples(None);
+        hbt.elapse(100)?;
+        assert_eq!(hbt.state_samples(0).len(), 1);
+        Ok(())
+    }
+}
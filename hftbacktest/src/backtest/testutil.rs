@@ -0,0 +1,47 @@
+use crate::{
+    backtest::{
+        DataSource, ExchangeKind, L3AssetBuilder,
+        assettype::LinearAsset,
+        data::Data,
+        models::{CommonFees, ConstantLatency, L3FIFOQueueModel, TradingValueFeeModel},
+    },
+    depth::HashMapMarketDepth,
+    types::Event,
+};
+
+/// The [`L3AssetBuilder`] returned by [`l3_asset`].
+pub type TestL3AssetBuilder = L3AssetBuilder<
+    ConstantLatency,
+    LinearAsset,
+    L3FIFOQueueModel,
+    HashMapMarketDepth,
+    TradingValueFeeModel<CommonFees>,
+>;
+
+/// Returns the market feed event seen by both the exchange and the local at `ts`.
+pub fn l3_event(ev: u64, ts: i64, px: f64, qty: f64, order_id: u64) -> Event {
+    Event {
+        ev,
+        exch_ts: ts,
+        local_ts: ts,
+        px,
+        qty,
+        order_id,
+        ival: 0,
+        fval: 0.0,
+    }
+}
+
+/// Returns the builder of an L3 asset replaying the given data on the exchange model, with an
+/// entry latency of 10 and a response latency of 20, a linear asset without fees, the FIFO queue
+/// model, and both the tick size and the lot size of 1.
+pub fn l3_asset(data: Data<Event>, exchange: ExchangeKind) -> TestL3AssetBuilder {
+    L3AssetBuilder::default()
+        .data(vec![DataSource::Data(data)])
+        .latency_model(ConstantLatency::new(10, 20))
+        .asset_type(LinearAsset::new(1.0))
+        .fee_model(TradingValueFeeModel::new(CommonFees::new(0.0, 0.0)))
+        .queue_model(L3FIFOQueueModel::new())
+        .exchange(exchange)
+        .depth(|| HashMapMarketDepth::new(1.0, 1.0))
+}
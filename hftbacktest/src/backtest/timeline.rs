@@ -0,0 +1,280 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::types::{Order, OrderId, Status};
+
+/// Kind of an [`OrderTimelineEntry`].
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum OrderTimelineKind {
+    /// The local submitted the order.
+    Submitted,
+    /// The local requested to modify the order.
+    ModifyRequested,
+    /// The local requested to cancel the order.
+    CancelRequested,
+    /// The exchange accepted the order.
+    Acked,
+    /// The order was executed, either fully or partially.
+    Fill,
+    /// The exchange accepted the modification of the order.
+    Modified,
+    /// The exchange rejected the request.
+    Rejected,
+    /// The order reached a terminal state, which is given by [`OrderTimelineEntry::status`].
+    Terminal,
+}
+
+/// An entry of an order's lifecycle timeline.
+#[derive(Clone, Debug)]
+pub struct OrderTimelineEntry {
+    pub kind: OrderTimelineKind,
+    /// The status of the local order after the event is applied.
+    pub status: Status,
+    /// The executed price for [`OrderTimelineKind::Fill`], or the order price otherwise.
+    pub price: f64,
+    /// The executed quantity for [`OrderTimelineKind::Fill`], or the order's leaves quantity
+    /// otherwise.
+    pub qty: f64,
+    /// The time at which the exchange processes the event. `0` for the requests made by the
+    /// local.
+    pub exch_ts: i64,
+    /// The time at which the local makes the request or receives the response.
+    pub local_ts: i64,
+}
+
+/// Records the lifecycle of each order observed by the local, from the submission to the terminal
+/// response, as an ordered timeline. Only the timelines of the most recently submitted orders,
+/// up to the capacity, are kept.
+#[derive(Default)]
+pub struct OrderTimelineRecorder {
+    capacity: usize,
+    timelines: HashMap<OrderId, Vec<OrderTimelineEntry>>,
+    fifo: VecDeque<OrderId>,
+}
+
+impl OrderTimelineRecorder {
+    /// Constructs an instance of `OrderTimelineRecorder` that keeps the timelines of up to
+    /// `capacity` orders. The recorder is enabled only if `capacity` is greater than zero.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            timelines: HashMap::with_capacity(capacity),
+            fifo: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns `true` if the recorder is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Starts the timeline of the submitted order, evicting the oldest timeline if the capacity is
+    /// reached.
+    pub fn on_submit(&mut self, order: &Order, timestamp: i64) {
+        if !self.enabled() {
+            return;
+        }
+        if self.timelines.remove(&order.order_id).is_some() {
+            self.fifo.retain(|order_id| *order_id != order.order_id);
+        }
+        if self.fifo.len() == self.capacity {
+            if let Some(oldest) = self.fifo.pop_front() {
+                self.timelines.remove(&oldest);
+            }
+        }
+        self.fifo.push_back(order.order_id);
+        self.timelines.insert(order.order_id, Vec::new());
+        self.push(order, OrderTimelineKind::Submitted, timestamp);
+    }
+
+    /// Records the modify request of the order.
+    pub fn on_modify(&mut self, order: &Order, timestamp: i64) {
+        self.push(order, OrderTimelineKind::ModifyRequested, timestamp);
+    }
+
+    /// Records the cancel request of the order.
+    pub fn on_cancel(&mut self, order: &Order, timestamp: i64) {
+        self.push(order, OrderTimelineKind::CancelRequested, timestamp);
+    }
+
+    /// Records the events conveyed by the order response received at `local_ts`.
+    ///
+    /// * `resp` - The order response from the exchange.
+    /// * `prev_req` - The request status of the local order before the response is applied.
+    /// * `local_order` - The local order after the response is applied.
+    pub fn on_response(
+        &mut self,
+        resp: &Order,
+        prev_req: Status,
+        local_order: &Order,
+        local_ts: i64,
+    ) {
+        let Some(timeline) = self.timelines.get_mut(&resp.order_id) else {
+            return;
+        };
+        let mut push = |kind: OrderTimelineKind, price: f64, qty: f64| {
+            timeline.push(OrderTimelineEntry {
+                kind,
+                status: local_order.status,
+                price,
+                qty,
+                exch_ts: resp.exch_timestamp,
+                local_ts,
+            });
+        };
+        if resp.req == Status::Rejected {
+            push(
+                OrderTimelineKind::Rejected,
+                local_order.price(),
+                local_order.leaves_qty,
+            );
+            return;
+        }
+        if prev_req == Status::New && resp.status != Status::Expired {
+            push(OrderTimelineKind::Acked, resp.price(), resp.qty);
+        }
+        // A fill that occurs before the modify request reaches the exchange carries the
+        // timestamp of the previous request.
+        if prev_req == Status::Replaced && resp.local_timestamp == local_order.local_timestamp {
+            push(OrderTimelineKind::Modified, resp.price(), resp.leaves_qty);
+        }
+        if resp.exec_qty > 0.0
+            && (resp.status == Status::Filled || resp.status == Status::PartiallyFilled)
+        {
+            push(OrderTimelineKind::Fill, resp.exec_price(), resp.exec_qty);
+        }
+        if local_order.status == Status::Filled
+            || local_order.status == Status::Canceled
+            || local_order.status == Status::Expired
+        {
+            push(OrderTimelineKind::Terminal, resp.price(), resp.leaves_qty);
+        }
+    }
+
+    /// Returns the timeline of the order. It is empty if the order is not recorded.
+    pub fn timeline(&self, order_id: OrderId) -> &[OrderTimelineEntry] {
+        self.timelines
+            .get(&order_id)
+            .map(|timeline| timeline.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn push(&mut self, order: &Order, kind: OrderTimelineKind, timestamp: i64) {
+        if let Some(timeline) = self.timelines.get_mut(&order.order_id) {
+            timeline.push(OrderTimelineEntry {
+                kind,
+                status: order.status,
+                price: order.price(),
+                qty: order.leaves_qty,
+                exch_ts: 0,
+                local_ts: timestamp,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+            timeline::OrderTimelineKind,
+        },
+        prelude::{Bot, OrdType, Status, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, CANCEL_ORDER_EVENT, EXCH_EVENT, FILL_EVENT, LOCAL_EVENT,
+            SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn order_timeline_of_partially_then_fully_filled_order() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                101.0,
+                1.0,
+                2,
+            ),
+            // The feed ask taken by the backtest order leaves the book.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | CANCEL_ORDER_EVENT,
+                50,
+                101.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                100,
+                101.0,
+                5.0,
+                3,
+            ),
+            // Fills the rest of the backtest order queued ahead of the feed order.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | FILL_EVENT,
+                200,
+                101.0,
+                5.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .order_timeline_capacity(1)
+                    .fill_counterparty(false)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        hbt.submit_buy_order(0, 10, 101.0, 3.0, TimeInForce::GTC, OrdType::Limit, true)?;
+        hbt.elapse(500)?;
+
+        let timeline = hbt
+            .order_timeline(0, 10)
+            .iter()
+            .map(|entry| (entry.kind, entry.qty, entry.exch_ts, entry.local_ts))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            timeline,
+            vec![
+                (OrderTimelineKind::Submitted, 3.0, 0, 1),
+                (OrderTimelineKind::Acked, 3.0, 11, 31),
+                (OrderTimelineKind::Fill, 1.0, 11, 31),
+                (OrderTimelineKind::Fill, 2.0, 200, 220),
+                (OrderTimelineKind::Terminal, 0.0, 200, 220),
+            ]
+        );
+        let statuses = hbt
+            .order_timeline(0, 10)
+            .iter()
+            .map(|entry| entry.status)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses[2], Status::PartiallyFilled);
+        assert_eq!(statuses[4], Status::Filled);
+
+        // Only the timeline of the most recently submitted order is kept.
+        hbt.submit_buy_order(0, 11, 99.0, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+        assert!(hbt.order_timeline(0, 10).is_empty());
+        assert_eq!(hbt.order_timeline(0, 11).len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use crate::{
+    depth::{INVALID_MAX, INVALID_MIN, MarketDepth},
+    types::{Order, OrderId, Side, Status},
+};
+
+/// The outcomes of the orders submitted at the same distance from the touch.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TouchDistanceStats {
+    /// The distance in ticks of the order price from the best price on the order's side at the
+    /// time of the submission. A positive value means that the order is placed behind the touch,
+    /// `0` at the touch, and a negative value inside the spread.
+    pub distance: i64,
+    /// The number of the orders fully filled.
+    pub fills: usize,
+    /// The number of the orders canceled, including those partially filled before the cancel.
+    pub cancels: usize,
+    /// The number of the orders expired, including those partially filled before the expiry.
+    pub expiries: usize,
+}
+
+impl TouchDistanceStats {
+    /// Returns the ratio of the filled orders to the canceled or expired orders. It is infinite if
+    /// no order is canceled or expired.
+    pub fn fill_to_cancel_ratio(&self) -> f64 {
+        self.fills as f64 / (self.cancels + self.expiries) as f64
+    }
+}
+
+/// Counts the outcomes of the orders observed by the local, bucketed by the distance from the
+/// touch at which each order is submitted.
+#[derive(Default)]
+pub struct TouchDistanceRecorder {
+    enabled: bool,
+    active: HashMap<OrderId, i64>,
+    stats: Vec<TouchDistanceStats>,
+}
+
+impl TouchDistanceRecorder {
+    /// Constructs an instance of `TouchDistanceRecorder`.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    /// Records the distance of the submitted order from the touch in the given market depth. The
+    /// order is not counted if its side of the market depth is empty.
+    pub fn on_submit<MD: MarketDepth>(&mut self, order: &Order, depth: &MD) {
+        if !self.enabled {
+            return;
+        }
+        let distance = match order.side {
+            Side::Buy if depth.best_bid_tick() != INVALID_MIN => {
+                depth.best_bid_tick() - order.price_tick
+            }
+            Side::Sell if depth.best_ask_tick() != INVALID_MAX => {
+                order.price_tick - depth.best_ask_tick()
+            }
+            _ => return,
+        };
+        self.active.insert(order.order_id, distance);
+    }
+
+    /// Counts the outcome of the order if the local order reaches a terminal state by the
+    /// response. An order rejected by the exchange is not counted.
+    ///
+    /// * `resp` - The order response from the exchange.
+    /// * `local_status` - The status of the local order after the response is applied.
+    pub fn on_response(&mut self, resp: &Order, local_status: Status) {
+        if local_status != Status::Filled
+            && local_status != Status::Canceled
+            && local_status != Status::Expired
+        {
+            return;
+        }
+        let Some(distance) = self.active.remove(&resp.order_id) else {
+            return;
+        };
+        if resp.req == Status::Rejected {
+            return;
+        }
+        let i = match self
+            .stats
+            .binary_search_by_key(&distance, |stats| stats.distance)
+        {
+            Ok(i) => i,
+            Err(i) => {
+                self.stats.insert(
+                    i,
+                    TouchDistanceStats {
+                        distance,
+                        ..Default::default()
+                    },
+                );
+                i
+            }
+        };
+        let stats = &mut self.stats[i];
+        match local_status {
+            Status::Filled => stats.fills += 1,
+            Status::Canceled => stats.cancels += 1,
+            _ => stats.expiries += 1,
+        }
+    }
+
+    /// Returns the counts of each distance, in ascending order of the distance.
+    pub fn stats(&self) -> &[TouchDistanceStats] {
+        self.stats.as_slice()
+    }
+
+    /// Clears the counts.
+    pub fn clear(&mut self) {
+        self.stats.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        backtest::{
+            Backtest,
+            ExchangeKind::PartialFillExchange,
+            data::Data,
+            testutil::{l3_asset, l3_event},
+        },
+        prelude::{Bot, OrdType, TimeInForce},
+        types::{
+            ADD_ORDER_EVENT, BUY_EVENT, CANCEL_ORDER_EVENT, EXCH_EVENT, LOCAL_EVENT, SELL_EVENT,
+        },
+    };
+
+    #[test]
+    fn touch_distance_stats() -> Result<(), Box<dyn Error>> {
+        let data = Data::from_data(&[
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | ADD_ORDER_EVENT,
+                0,
+                100.0,
+                1.0,
+                1,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                0,
+                105.0,
+                1.0,
+                2,
+            ),
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | BUY_EVENT | CANCEL_ORDER_EVENT,
+                200,
+                100.0,
+                1.0,
+                1,
+            ),
+            // Crosses the backtest bids at 100 and 99.
+            l3_event(
+                EXCH_EVENT | LOCAL_EVENT | SELL_EVENT | ADD_ORDER_EVENT,
+                300,
+                99.0,
+                1.0,
+                3,
+            ),
+            l3_event(EXCH_EVENT | LOCAL_EVENT, 1000, 0.0, 0.0, 0),
+        ]);
+
+        let mut hbt = Backtest::builder()
+            .add_asset(
+                l3_asset(data, PartialFillExchange)
+                    .touch_distance_stats(true)
+                    .build()?,
+            )
+            .build()?;
+
+        hbt.elapse(1)?;
+        for (order_id, price) in [(10, 100.0), (11, 99.0), (12, 98.0), (13, 97.0)] {
+            hbt.submit_buy_order(
+                0,
+                order_id,
+                price,
+                1.0,
+                TimeInForce::GTC,
+                OrdType::Limit,
+                true,
+            )?;
+        }
+        // Inside the spread, but nothing to take.
+        hbt.submit_buy_order(0, 14, 101.0, 1.0, TimeInForce::IOC, OrdType::Limit, true)?;
+        hbt.cancel(0, 12, true)?;
+
+        hbt.elapse(1000)?;
+        let stats = hbt.touch_distance_stats(0);
+        let counts: Vec<_> = stats
+            .iter()
+            .map(|stats| (stats.distance, stats.fills, stats.cancels, stats.expiries))
+            .collect();
+        // The order 13 is still open.
+        assert_eq!(
+            counts,
+            vec![(-1, 0, 0, 1), (0, 1, 0, 0), (1, 1, 0, 0), (2, 0, 1, 0)]
+        );
+        assert_eq!(stats[0].fill_to_cancel_ratio(), 0.0);
+        assert_eq!(stats[1].fill_to_cancel_ratio(), f64::INFINITY);
+
+        hbt.clear_touch_distance_stats(None);
+        assert!(hbt.touch_distance_stats(0).is_empty());
+        Ok(())
+    }
+}
@@ -2,12 +2,15 @@ use std::collections::{BTreeMap, HashMap, hash_map::Entry};
 
 use super::{
     ApplySnapshot,
+    CrossingAddPolicy,
+    CrossingAdds,
     INVALID_MAX,
     INVALID_MIN,
     L2MarketDepth,
     L3MarketDepth,
     L3Order,
     MarketDepth,
+    tick_at_cumulative_qty,
 };
 use crate::{
     backtest::{BacktestError, data::Data},
@@ -31,6 +34,7 @@ pub struct BTreeMarketDepth {
     pub best_ask_tick: i64,
     pub orders: HashMap<OrderId, L3Order>,
     pub allow_price_cross: bool,
+    crossing_adds: CrossingAdds,
 }
 
 impl BTreeMarketDepth {
@@ -47,10 +51,40 @@ impl BTreeMarketDepth {
             orders: Default::default(),
             
             allow_price_cross: true, // 默认允许价格交叉（集合竞价模式）
+            crossing_adds: Default::default(),
         }
     }
 
+    /// Sets how an add-order that crosses the best price on the opposite side outside an auction
+    /// is handled. The default value is [`CrossingAddPolicy::Apply`].
+    pub fn crossing_add_policy(self, policy: CrossingAddPolicy) -> Self {
+        Self {
+            crossing_adds: CrossingAdds::new(policy),
+            ..self
+        }
+    }
+
+    /// Adds the held crossing orders that no longer cross to the order book, once the order book
+    /// uncrosses. The ones that still cross remain held.
+    fn release_crossing_adds(&mut self) -> Result<(), BacktestError> {
+        if self.allow_price_cross || self.crossing_adds.is_empty() {
+            return Ok(());
+        }
+        for order in self.crossing_adds.take() {
+            let px = order.price_tick as f64 * self.tick_size;
+            if order.side == Side::Buy {
+                self.add_buy_order(order.order_id, px, order.qty, order.timestamp)?;
+            } else {
+                self.add_sell_order(order.order_id, px, order.qty, order.timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
     fn add(&mut self, order: L3Order) -> Result<(), BacktestError> {
+        if self.crossing_adds.contains(order.order_id) {
+            return Err(BacktestError::OrderIdExist);
+        }
         let order = match self.orders.entry(order.order_id) {
             Entry::Occupied(_) => return Err(BacktestError::OrderIdExist),
             Entry::Vacant(entry) => entry.insert(order),
@@ -212,6 +246,45 @@ impl MarketDepth for BTreeMarketDepth {
     fn ask_qty_at_tick(&self, price_tick: i64) -> f64 {
         *self.ask_depth.get(&price_tick).unwrap_or(&0.0)
     }
+
+    fn price_at_cumulative_qty(&self, side: Side, target_qty: f64) -> Option<f64> {
+        let price_tick = match side {
+            Side::Buy => tick_at_cumulative_qty(
+                self.bid_depth
+                    .range(..=self.best_bid_tick)
+                    .rev()
+                    .map(|(price_tick, qty)| (*price_tick, *qty)),
+                target_qty,
+            ),
+            Side::Sell => tick_at_cumulative_qty(
+                self.ask_depth
+                    .range(self.best_ask_tick..)
+                    .map(|(price_tick, qty)| (*price_tick, *qty)),
+                target_qty,
+            ),
+            Side::None | Side::Unsupported => None,
+        }?;
+        Some(price_tick as f64 * self.tick_size)
+    }
+
+    fn bid_levels(&self, max_levels: usize) -> Vec<(i64, f64)> {
+        self.bid_depth
+            .range(..=self.best_bid_tick)
+            .rev()
+            .filter(|(_, qty)| **qty > 0.0)
+            .take(max_levels)
+            .map(|(price_tick, qty)| (*price_tick, *qty))
+            .collect()
+    }
+
+    fn ask_levels(&self, max_levels: usize) -> Vec<(i64, f64)> {
+        self.ask_depth
+            .range(self.best_ask_tick..)
+            .filter(|(_, qty)| **qty > 0.0)
+            .take(max_levels)
+            .map(|(price_tick, qty)| (*price_tick, *qty))
+            .collect()
+    }
 }
 
 impl ApplySnapshot for BTreeMarketDepth {
@@ -253,6 +326,26 @@ impl L3MarketDepth for BTreeMarketDepth {
         timestamp: i64,
     ) -> Result<(i64, i64), Self::Error> {
         let price_tick = (px / self.tick_size).round() as i64;
+        if !self.allow_price_cross
+            && self.crossing_adds.intercepts(
+                Side::Buy,
+                price_tick,
+                self.best_bid_tick,
+                self.best_ask_tick,
+            )
+        {
+            self.crossing_adds.hold(
+                L3Order {
+                    order_id,
+                    side: Side::Buy,
+                    price_tick,
+                    qty,
+                    timestamp,
+                },
+                &self.orders,
+            )?;
+            return Ok((self.best_bid_tick, self.best_bid_tick));
+        }
         self.add(L3Order {
             order_id,
             side: Side::Buy,
@@ -275,6 +368,26 @@ impl L3MarketDepth for BTreeMarketDepth {
         timestamp: i64,
     ) -> Result<(i64, i64), Self::Error> {
         let price_tick = (px / self.tick_size).round() as i64;
+        if !self.allow_price_cross
+            && self.crossing_adds.intercepts(
+                Side::Sell,
+                price_tick,
+                self.best_bid_tick,
+                self.best_ask_tick,
+            )
+        {
+            self.crossing_adds.hold(
+                L3Order {
+                    order_id,
+                    side: Side::Sell,
+                    price_tick,
+                    qty,
+                    timestamp,
+                },
+                &self.orders,
+            )?;
+            return Ok((self.best_ask_tick, self.best_ask_tick));
+        }
         self.add(L3Order {
             order_id,
             side: Side::Sell,
@@ -294,11 +407,19 @@ impl L3MarketDepth for BTreeMarketDepth {
         order_id: OrderId,
         _timestamp: i64,
     ) -> Result<(Side, i64, i64), Self::Error> {
+        if let Some(order) = self.crossing_adds.remove(order_id) {
+            let best_tick = if order.side == Side::Buy {
+                self.best_bid_tick
+            } else {
+                self.best_ask_tick
+            };
+            return Ok((order.side, best_tick, best_tick));
+        }
         let order = self
             .orders
             .remove(&order_id)
             .ok_or(BacktestError::OrderNotFound)?;
-        if order.side == Side::Buy {
+        let result = if order.side == Side::Buy {
             let prev_best_tick = self.best_bid_tick;
 
             let depth_qty = self.bid_depth.get_mut(&order.price_tick).unwrap();
@@ -322,7 +443,9 @@ impl L3MarketDepth for BTreeMarketDepth {
                 }
             }
             Ok((Side::Sell, prev_best_tick, self.best_ask_tick))
-        }
+        };
+        self.release_crossing_adds()?;
+        result
     }
 
     fn modify_order(
@@ -332,11 +455,26 @@ impl L3MarketDepth for BTreeMarketDepth {
         qty: f64,
         timestamp: i64,
     ) -> Result<(Side, i64, i64), Self::Error> {
+        if (qty / self.lot_size).round() as i64 == 0 {
+            return self.delete_order(order_id, timestamp);
+        }
+        if let Some(order) = self.crossing_adds.remove(order_id) {
+            // The held order is added to the order book unless it still crosses.
+            return if order.side == Side::Buy {
+                let (prev_best_tick, best_tick) =
+                    self.add_buy_order(order_id, px, qty, timestamp)?;
+                Ok((Side::Buy, prev_best_tick, best_tick))
+            } else {
+                let (prev_best_tick, best_tick) =
+                    self.add_sell_order(order_id, px, qty, timestamp)?;
+                Ok((Side::Sell, prev_best_tick, best_tick))
+            };
+        }
         let order = self
             .orders
             .get_mut(&order_id)
             .ok_or(BacktestError::OrderNotFound)?;
-        if order.side == Side::Buy {
+        let result = if order.side == Side::Buy {
             let prev_best_tick = self.best_bid_tick;
             let price_tick = (px / self.tick_size).round() as i64;
             if price_tick != order.price_tick {
@@ -394,10 +532,13 @@ impl L3MarketDepth for BTreeMarketDepth {
                 order.qty = qty;
                 Ok((Side::Sell, self.best_ask_tick, self.best_ask_tick))
             }
-        }
+        };
+        self.release_crossing_adds()?;
+        result
     }
 
     fn clear_orders(&mut self, side: Side) {
+        self.crossing_adds.clear(side);
         match side {
             Side::Buy => {
                 L2MarketDepth::clear_depth(self, side, f64::NEG_INFINITY);
@@ -431,6 +572,9 @@ impl L3MarketDepth for BTreeMarketDepth {
                 unreachable!();
             }
         }
+        // Clearing a side uncrosses the order book. The held orders are taken out before they
+        // are added back, so adding them cannot fail.
+        self.release_crossing_adds().unwrap();
     }
 
     fn orders(&self) -> &HashMap<OrderId, L3Order> {
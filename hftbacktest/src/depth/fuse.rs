@@ -1,6 +1,9 @@
 use std::collections::{HashMap, hash_map::Entry};
 
-use super::{ApplySnapshot, INVALID_MAX, INVALID_MIN, L1MarketDepth, L3Order, MarketDepth};
+use super::{
+    ApplySnapshot, INVALID_MAX, INVALID_MIN, L1MarketDepth, L3Order, MarketDepth,
+    tick_at_cumulative_qty,
+};
 use crate::{
     backtest::{BacktestError, data::Data},
     prelude::{DEPTH_SNAPSHOT_EVENT, EXCH_EVENT, L2MarketDepth, LOCAL_EVENT, Side},
@@ -329,6 +332,54 @@ impl MarketDepth for FusedHashMapMarketDepth {
             .unwrap_or(&Default::default())
             .qty
     }
+
+    fn price_at_cumulative_qty(&self, side: Side, target_qty: f64) -> Option<f64> {
+        let mut levels: Vec<(i64, f64)> = match side {
+            Side::Buy => self
+                .bid_depth
+                .iter()
+                .filter(|(price_tick, _)| **price_tick <= self.best_bid_tick)
+                .map(|(price_tick, qty_ts)| (*price_tick, qty_ts.qty))
+                .collect(),
+            Side::Sell => self
+                .ask_depth
+                .iter()
+                .filter(|(price_tick, _)| **price_tick >= self.best_ask_tick)
+                .map(|(price_tick, qty_ts)| (*price_tick, qty_ts.qty))
+                .collect(),
+            Side::None | Side::Unsupported => return None,
+        };
+        match side {
+            Side::Buy => levels.sort_by(|a, b| b.0.cmp(&a.0)),
+            _ => levels.sort_by(|a, b| a.0.cmp(&b.0)),
+        }
+        tick_at_cumulative_qty(levels, target_qty)
+            .map(|price_tick| price_tick as f64 * self.tick_size)
+    }
+
+    fn bid_levels(&self, max_levels: usize) -> Vec<(i64, f64)> {
+        let mut levels: Vec<(i64, f64)> = self
+            .bid_depth
+            .iter()
+            .filter(|(price_tick, qty_ts)| **price_tick <= self.best_bid_tick && qty_ts.qty > 0.0)
+            .map(|(price_tick, qty_ts)| (*price_tick, qty_ts.qty))
+            .collect();
+        levels.sort_by(|a, b| b.0.cmp(&a.0));
+        levels.truncate(max_levels);
+        levels
+    }
+
+    fn ask_levels(&self, max_levels: usize) -> Vec<(i64, f64)> {
+        let mut levels: Vec<(i64, f64)> = self
+            .ask_depth
+            .iter()
+            .filter(|(price_tick, qty_ts)| **price_tick >= self.best_ask_tick && qty_ts.qty > 0.0)
+            .map(|(price_tick, qty_ts)| (*price_tick, qty_ts.qty))
+            .collect();
+        levels.sort_by(|a, b| a.0.cmp(&b.0));
+        levels.truncate(max_levels);
+        levels
+    }
 }
 
 impl ApplySnapshot for FusedHashMapMarketDepth {
@@ -1,6 +1,12 @@
-use std::collections::{HashMap, hash_map::Entry};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, hash_map::Entry},
+};
 
-use super::{ApplySnapshot, INVALID_MAX, INVALID_MIN, L3MarketDepth, L3Order, MarketDepth};
+use super::{
+    ApplySnapshot, CrossingAddPolicy, CrossingAdds, INVALID_MAX, INVALID_MIN, L3MarketDepth,
+    L3Order, MarketDepth, tick_at_cumulative_qty,
+};
 use crate::{
     backtest::{BacktestError, data::Data},
     prelude::{L2MarketDepth, OrderId, Side},
@@ -30,6 +36,7 @@ pub struct HashMapMarketDepth {
     pub high_ask_tick: i64,
     pub orders: HashMap<OrderId, L3Order>,
     pub allow_price_cross: bool,
+    crossing_adds: CrossingAdds,
 }
 
 #[inline(always)]
@@ -68,10 +75,40 @@ impl HashMapMarketDepth {
             orders: HashMap::new(),
 
             allow_price_cross: true, // 默认允许价格交叉（集合竞价模式）
+            crossing_adds: Default::default(),
         }
     }
 
+    /// Sets how an add-order that crosses the best price on the opposite side outside an auction
+    /// is handled. The default value is [`CrossingAddPolicy::Apply`].
+    pub fn crossing_add_policy(self, policy: CrossingAddPolicy) -> Self {
+        Self {
+            crossing_adds: CrossingAdds::new(policy),
+            ..self
+        }
+    }
+
+    /// Adds the held crossing orders that no longer cross to the order book, once the order book
+    /// uncrosses. The ones that still cross remain held.
+    fn release_crossing_adds(&mut self) -> Result<(), BacktestError> {
+        if self.allow_price_cross || self.crossing_adds.is_empty() {
+            return Ok(());
+        }
+        for order in self.crossing_adds.take() {
+            let px = order.price_tick as f64 * self.tick_size;
+            if order.side == Side::Buy {
+                self.add_buy_order(order.order_id, px, order.qty, order.timestamp)?;
+            } else {
+                self.add_sell_order(order.order_id, px, order.qty, order.timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
     fn add(&mut self, order: L3Order) -> Result<(), BacktestError> {
+        if self.crossing_adds.contains(order.order_id) {
+            return Err(BacktestError::OrderIdExist);
+        }
         let order = match self.orders.entry(order.order_id) {
             Entry::Occupied(_) => return Err(BacktestError::OrderIdExist),
             Entry::Vacant(entry) => entry.insert(order),
@@ -301,6 +338,54 @@ impl MarketDepth for HashMapMarketDepth {
     fn ask_qty_at_tick(&self, price_tick: i64) -> f64 {
         *self.ask_depth.get(&price_tick).unwrap_or(&0.0)
     }
+
+    fn price_at_cumulative_qty(&self, side: Side, target_qty: f64) -> Option<f64> {
+        let mut levels: Vec<(i64, f64)> = match side {
+            Side::Buy => self
+                .bid_depth
+                .iter()
+                .filter(|(price_tick, _)| **price_tick <= self.best_bid_tick)
+                .map(|(price_tick, qty)| (*price_tick, *qty))
+                .collect(),
+            Side::Sell => self
+                .ask_depth
+                .iter()
+                .filter(|(price_tick, _)| **price_tick >= self.best_ask_tick)
+                .map(|(price_tick, qty)| (*price_tick, *qty))
+                .collect(),
+            Side::None | Side::Unsupported => return None,
+        };
+        match side {
+            Side::Buy => levels.sort_unstable_by_key(|&(price_tick, _)| Reverse(price_tick)),
+            _ => levels.sort_unstable_by_key(|&(price_tick, _)| price_tick),
+        }
+        tick_at_cumulative_qty(levels, target_qty)
+            .map(|price_tick| price_tick as f64 * self.tick_size)
+    }
+
+    fn bid_levels(&self, max_levels: usize) -> Vec<(i64, f64)> {
+        let mut levels: Vec<(i64, f64)> = self
+            .bid_depth
+            .iter()
+            .filter(|(price_tick, qty)| **price_tick <= self.best_bid_tick && **qty > 0.0)
+            .map(|(price_tick, qty)| (*price_tick, *qty))
+            .collect();
+        levels.sort_unstable_by_key(|&(price_tick, _)| Reverse(price_tick));
+        levels.truncate(max_levels);
+        levels
+    }
+
+    fn ask_levels(&self, max_levels: usize) -> Vec<(i64, f64)> {
+        let mut levels: Vec<(i64, f64)> = self
+            .ask_depth
+            .iter()
+            .filter(|(price_tick, qty)| **price_tick >= self.best_ask_tick && **qty > 0.0)
+            .map(|(price_tick, qty)| (*price_tick, *qty))
+            .collect();
+        levels.sort_unstable_by_key(|&(price_tick, _)| price_tick);
+        levels.truncate(max_levels);
+        levels
+    }
 }
 
 impl ApplySnapshot for HashMapMarketDepth {
@@ -390,6 +475,26 @@ impl L3MarketDepth for HashMapMarketDepth {
         timestamp: i64,
     ) -> Result<(i64, i64), Self::Error> {
         let price_tick = (px / self.tick_size).round() as i64;
+        if !self.allow_price_cross
+            && self.crossing_adds.intercepts(
+                Side::Buy,
+                price_tick,
+                self.best_bid_tick,
+                self.best_ask_tick,
+            )
+        {
+            self.crossing_adds.hold(
+                L3Order {
+                    order_id,
+                    side: Side::Buy,
+                    price_tick,
+                    qty,
+                    timestamp,
+                },
+                &self.orders,
+            )?;
+            return Ok((self.best_bid_tick, self.best_bid_tick));
+        }
         self.add(L3Order {
             order_id,
             side: Side::Buy,
@@ -417,6 +522,26 @@ impl L3MarketDepth for HashMapMarketDepth {
         timestamp: i64,
     ) -> Result<(i64, i64), Self::Error> {
         let price_tick = (px / self.tick_size).round() as i64;
+        if !self.allow_price_cross
+            && self.crossing_adds.intercepts(
+                Side::Sell,
+                price_tick,
+                self.best_bid_tick,
+                self.best_ask_tick,
+            )
+        {
+            self.crossing_adds.hold(
+                L3Order {
+                    order_id,
+                    side: Side::Sell,
+                    price_tick,
+                    qty,
+                    timestamp,
+                },
+                &self.orders,
+            )?;
+            return Ok((self.best_ask_tick, self.best_ask_tick));
+        }
         self.add(L3Order {
             order_id,
             side: Side::Sell,
@@ -441,11 +566,19 @@ impl L3MarketDepth for HashMapMarketDepth {
         order_id: OrderId,
         _timestamp: i64,
     ) -> Result<(Side, i64, i64), Self::Error> {
+        if let Some(order) = self.crossing_adds.remove(order_id) {
+            let best_tick = if order.side == Side::Buy {
+                self.best_bid_tick
+            } else {
+                self.best_ask_tick
+            };
+            return Ok((order.side, best_tick, best_tick));
+        }
         let order = self
             .orders
             .remove(&order_id)
             .ok_or(BacktestError::OrderNotFound)?;
-        if order.side == Side::Buy {
+        let result = if order.side == Side::Buy {
             let prev_best_tick = self.best_bid_tick;
 
             let depth_qty = self.bid_depth.get_mut(&order.price_tick).unwrap();
@@ -477,7 +610,9 @@ impl L3MarketDepth for HashMapMarketDepth {
                 }
             }
             Ok((Side::Sell, prev_best_tick, self.best_ask_tick))
-        }
+        };
+        self.release_crossing_adds()?;
+        result
     }
 
     fn modify_order(
@@ -487,11 +622,26 @@ impl L3MarketDepth for HashMapMarketDepth {
         qty: f64,
         timestamp: i64,
     ) -> Result<(Side, i64, i64), Self::Error> {
+        if (qty / self.lot_size).round() as i64 == 0 {
+            return self.delete_order(order_id, timestamp);
+        }
+        if let Some(order) = self.crossing_adds.remove(order_id) {
+            // The held order is added to the order book unless it still crosses.
+            return if order.side == Side::Buy {
+                let (prev_best_tick, best_tick) =
+                    self.add_buy_order(order_id, px, qty, timestamp)?;
+                Ok((Side::Buy, prev_best_tick, best_tick))
+            } else {
+                let (prev_best_tick, best_tick) =
+                    self.add_sell_order(order_id, px, qty, timestamp)?;
+                Ok((Side::Sell, prev_best_tick, best_tick))
+            };
+        }
         let order = self
             .orders
             .get_mut(&order_id)
             .ok_or(BacktestError::OrderNotFound)?;
-        if order.side == Side::Buy {
+        let result = if order.side == Side::Buy {
             let prev_best_tick = self.best_bid_tick;
             let price_tick = (px / self.tick_size).round() as i64;
             if price_tick != order.price_tick {
@@ -567,10 +717,13 @@ impl L3MarketDepth for HashMapMarketDepth {
                 order.qty = qty;
                 Ok((Side::Sell, self.best_ask_tick, self.best_ask_tick))
             }
-        }
+        };
+        self.release_crossing_adds()?;
+        result
     }
 
     fn clear_orders(&mut self, side: Side) {
+        self.crossing_adds.clear(side);
         match side {
             Side::Buy => {
                 L2MarketDepth::clear_depth(self, side, f64::NEG_INFINITY);
@@ -604,6 +757,9 @@ impl L3MarketDepth for HashMapMarketDepth {
                 unreachable!();
             }
         }
+        // Clearing a side uncrosses the order book. The held orders are taken out before they
+        // are added back, so adding them cannot fail.
+        self.release_crossing_adds().unwrap();
     }
 
     fn orders(&self) -> &HashMap<OrderId, L3Order> {
@@ -614,7 +770,11 @@ impl L3MarketDepth for HashMapMarketDepth {
 #[cfg(test)]
 mod tests {
     use crate::{
-        depth::{HashMapMarketDepth, INVALID_MAX, INVALID_MIN, L3MarketDepth, MarketDepth},
+        backtest::BacktestError,
+        depth::{
+            CrossingAddPolicy, HashMapMarketDepth, INVALID_MAX, INVALID_MIN, L2MarketDepth,
+            L3MarketDepth, MarketDepth,
+        },
         types::Side,
     };
 
@@ -838,4 +998,235 @@ mod tests {
         assert_eq_qty!(depth.ask_qty_at_tick(4981), 0.0, lot_size);
         assert_eq_qty!(depth.ask_qty_at_tick(5002), 0.002, lot_size);
     }
+
+    #[test]
+    fn test_empty_side_has_no_best_price() {
+        let mut depth = HashMapMarketDepth::new(0.1, 0.001);
+        assert!(depth.best_bid().is_nan());
+        assert!(depth.best_ask().is_nan());
+        assert_eq!(depth.try_best_bid(), None);
+        assert_eq!(depth.try_best_ask(), None);
+
+        depth.add_buy_order(1, 500.1, 0.001, 0).unwrap();
+        assert_eq!(depth.try_best_bid(), Some(500.1));
+        assert_eq!(depth.try_best_ask(), None);
+
+        depth.delete_order(1, 0).unwrap();
+        assert_eq!(depth.try_best_bid(), None);
+    }
+
+    #[test]
+    fn test_best_qty() {
+        let lot_size = 0.001;
+        let mut depth = HashMapMarketDepth::new(0.1, lot_size);
+        assert_eq!(depth.best_bid_qty(), 0.0);
+        assert_eq!(depth.best_ask_qty(), 0.0);
+
+        depth.add_buy_order(1, 500.1, 0.001, 0).unwrap();
+        depth.add_buy_order(2, 500.1, 0.002, 0).unwrap();
+        depth.add_sell_order(3, 500.3, 0.004, 0).unwrap();
+        assert_eq_qty!(depth.best_bid_qty(), 0.003, lot_size);
+        assert_eq_qty!(depth.best_ask_qty(), 0.004, lot_size);
+
+        depth.delete_order(3, 0).unwrap();
+        assert_eq!(depth.best_ask_qty(), 0.0);
+    }
+
+    #[test]
+    fn test_levels() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        assert!(depth.bid_levels(5).is_empty());
+        assert!(depth.ask_levels(5).is_empty());
+
+        depth.update_bid_depth(100.0, 2.0, 0);
+        depth.update_bid_depth(99.0, 3.0, 0);
+        depth.update_bid_depth(95.0, 5.0, 0);
+        depth.update_ask_depth(101.0, 1.0, 0);
+        depth.update_ask_depth(104.0, 4.0, 0);
+
+        assert_eq!(depth.bid_levels(2), vec![(100, 2.0), (99, 3.0)]);
+        assert_eq!(depth.ask_levels(1), vec![(101, 1.0)]);
+        // Stops at the last populated level.
+        assert_eq!(depth.bid_levels(10), vec![(100, 2.0), (99, 3.0), (95, 5.0)]);
+        assert_eq!(depth.ask_levels(10), vec![(101, 1.0), (104, 4.0)]);
+
+        depth.update_bid_depth(99.0, 0.0, 0);
+        assert_eq!(depth.bid_levels(10), vec![(100, 2.0), (95, 5.0)]);
+    }
+
+    #[test]
+    fn test_mid_price_and_microprice() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        assert_eq!(depth.mid_price(), None);
+        assert_eq!(depth.microprice(), None);
+
+        // Only the available side's price is returned for a one-sided book.
+        depth.update_bid_depth(100.0, 3.0, 0);
+        assert_eq!(depth.mid_price(), Some((100.0, true)));
+        assert_eq!(depth.microprice(), Some((100.0, true)));
+
+        // The larger bid quantity pulls the microprice toward the best ask.
+        depth.update_ask_depth(102.0, 1.0, 0);
+        assert_eq!(depth.mid_price(), Some((101.0, false)));
+        assert_eq!(depth.microprice(), Some((101.5, false)));
+
+        // And the larger ask quantity pulls it toward the best bid.
+        depth.update_ask_depth(102.0, 7.0, 0);
+        assert_eq!(depth.microprice(), Some((100.6, false)));
+    }
+
+    #[test]
+    fn test_vwap_for_qty() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        assert_eq!(depth.vwap_for_qty(Side::Buy, 1.0), None);
+
+        depth.update_bid_depth(100.0, 2.0, 0);
+        depth.update_bid_depth(99.0, 1.0, 0);
+        depth.update_bid_depth(97.0, 3.0, 0);
+        depth.update_ask_depth(101.0, 1.0, 0);
+
+        // Spans three levels, consuming 2 at 100, 1 at 99, and 1 at 97.
+        assert_eq!(depth.vwap_for_qty(Side::Buy, 4.0), Some((99.0, 4.0)));
+        // Only the quantity that the side holds is consumed.
+        assert_eq!(depth.vwap_for_qty(Side::Sell, 3.0), Some((101.0, 1.0)));
+    }
+
+    #[test]
+    fn test_price_at_cumulative_qty() {
+        let mut depth = HashMapMarketDepth::new(1.0, 1.0);
+        assert_eq!(depth.price_at_cumulative_qty(Side::Buy, 1.0), None);
+
+        depth.update_bid_depth(100.0, 2.0, 0);
+        depth.update_bid_depth(99.0, 3.0, 0);
+        depth.update_bid_depth(97.0, 5.0, 0);
+        depth.update_ask_depth(101.0, 1.0, 0);
+        depth.update_ask_depth(103.0, 4.0, 0);
+
+        assert_eq!(depth.price_at_cumulative_qty(Side::Buy, 2.0), Some(100.0));
+        assert_eq!(depth.price_at_cumulative_qty(Side::Buy, 4.0), Some(99.0));
+        assert_eq!(depth.price_at_cumulative_qty(Side::Buy, 10.0), Some(97.0));
+        assert_eq!(depth.price_at_cumulative_qty(Side::Buy, 11.0), None);
+        assert_eq!(depth.price_at_cumulative_qty(Side::Sell, 3.0), Some(103.0));
+        assert_eq!(depth.price_at_cumulative_qty(Side::Sell, 6.0), None);
+    }
+
+    #[test]
+    fn test_modify_order_to_zero_qty() {
+        let lot_size = 0.001;
+        let mut depth = HashMapMarketDepth::new(0.1, lot_size);
+        depth.add_buy_order(1, 500.0, 0.002, 0).unwrap();
+        depth.add_buy_order(2, 499.9, 0.001, 0).unwrap();
+        depth.add_sell_order(3, 500.1, 0.001, 0).unwrap();
+        depth.add_sell_order(4, 500.3, 0.001, 0).unwrap();
+
+        // Partially filled.
+        let (side, prev_best, best) = depth.modify_order(1, 500.0, 0.001, 0).unwrap();
+        assert_eq!(side, Side::Buy);
+        assert_eq!(prev_best, 5000);
+        assert_eq!(best, 5000);
+
+        // Fully filled.
+        let (side, prev_best, best) = depth.modify_order(1, 500.0, 0.0, 0).unwrap();
+        assert_eq!(side, Side::Buy);
+        assert_eq!(prev_best, 5000);
+        assert_eq!(best, 4999);
+        assert!(!depth.orders().contains_key(&1));
+        assert_eq_qty!(depth.bid_qty_at_tick(5000), 0.0, lot_size);
+        assert_eq!(depth.best_bid_tick(), 4999);
+
+        let (side, prev_best, best) = depth.modify_order(3, 500.1, 0.0, 0).unwrap();
+        assert_eq!(side, Side::Sell);
+        assert_eq!(prev_best, 5001);
+        assert_eq!(best, 5003);
+        assert!(!depth.orders().contains_key(&3));
+        assert_eq!(depth.best_ask_tick(), 5003);
+    }
+
+    #[test]
+    fn test_crossing_add_policy() {
+        let lot_size = 0.001;
+
+        // Apply
+        let mut depth = HashMapMarketDepth::new(0.1, lot_size);
+        depth.add_buy_order(1, 500.0, 0.001, 0).unwrap();
+        depth.add_sell_order(2, 500.1, 0.001, 0).unwrap();
+        depth.set_allow_price_cross(false);
+
+        let (prev_best, best) = depth.add_buy_order(3, 500.2, 0.003, 0).unwrap();
+        assert_eq!(prev_best, 5000);
+        assert_eq!(best, 5002);
+        assert_eq!(depth.best_ask_tick(), INVALID_MAX);
+        assert_eq_qty!(depth.bid_qty_at_tick(5002), 0.003, lot_size);
+
+        // Hold
+        let mut depth =
+            HashMapMarketDepth::new(0.1, lot_size).crossing_add_policy(CrossingAddPolicy::Hold);
+        depth.add_buy_order(1, 500.0, 0.001, 0).unwrap();
+        depth.add_sell_order(2, 500.1, 0.001, 0).unwrap();
+        depth.set_allow_price_cross(false);
+
+        let (prev_best, best) = depth.add_buy_order(3, 500.2, 0.003, 0).unwrap();
+        assert_eq!(prev_best, 5000);
+        assert_eq!(best, 5000);
+        assert_eq!(depth.best_ask_tick(), 5001);
+        assert_eq_qty!(depth.bid_qty_at_tick(5002), 0.0, lot_size);
+        assert!(!depth.orders().contains_key(&3));
+        assert!(matches!(
+            depth.add_sell_order(3, 500.3, 0.001, 0),
+            Err(BacktestError::OrderIdExist)
+        ));
+
+        // The held order rests once the crossed ask is filled and the order book uncrosses.
+        let (side, prev_best, best) = depth.delete_order(2, 0).unwrap();
+        assert_eq!(side, Side::Sell);
+        assert_eq!(prev_best, 5001);
+        assert_eq!(best, INVALID_MAX);
+        assert_eq!(depth.best_bid_tick(), 5002);
+        assert_eq_qty!(depth.bid_qty_at_tick(5002), 0.003, lot_size);
+        assert!(depth.orders().contains_key(&3));
+
+        // A held order rests once a modify for it no longer crosses.
+        depth.add_sell_order(5, 500.3, 0.001, 0).unwrap();
+        depth.add_sell_order(6, 500.1, 0.001, 0).unwrap();
+        assert!(!depth.orders().contains_key(&6));
+        let (side, prev_best, best) = depth.modify_order(6, 500.4, 0.001, 0).unwrap();
+        assert_eq!(side, Side::Sell);
+        assert_eq!(prev_best, 5003);
+        assert_eq!(best, 5003);
+        assert_eq_qty!(depth.ask_qty_at_tick(5004), 0.001, lot_size);
+        depth.delete_order(5, 0).unwrap();
+        depth.delete_order(6, 0).unwrap();
+
+        // A held order is discarded by a delete.
+        depth.add_sell_order(4, 500.1, 0.001, 0).unwrap();
+        let (side, prev_best, best) = depth.delete_order(4, 0).unwrap();
+        assert_eq!(side, Side::Sell);
+        assert_eq!(prev_best, INVALID_MAX);
+        assert_eq!(best, INVALID_MAX);
+        assert!(depth.delete_order(4, 0).is_err());
+
+        // Reject
+        let mut depth =
+            HashMapMarketDepth::new(0.1, lot_size).crossing_add_policy(CrossingAddPolicy::Reject);
+        depth.add_buy_order(1, 500.0, 0.001, 0).unwrap();
+        depth.add_sell_order(2, 500.1, 0.001, 0).unwrap();
+        depth.set_allow_price_cross(false);
+
+        assert!(matches!(
+            depth.add_buy_order(3, 500.1, 0.001, 0),
+            Err(BacktestError::CrossingOrder)
+        ));
+        assert!(matches!(
+            depth.add_sell_order(4, 500.0, 0.001, 0),
+            Err(BacktestError::CrossingOrder)
+        ));
+        assert_eq!(depth.best_bid_tick(), 5000);
+        assert_eq!(depth.best_ask_tick(), 5001);
+        assert_eq!(depth.orders().len(), 2);
+
+        // Crossing is allowed during an auction.
+        depth.set_allow_price_cross(true);
+        let (_, best) = depth.add_buy_order(3, 500.1, 0.001, 0).unwrap();
+        assert_eq!(best, 5001);
+    }
 }
@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+
+use super::{INVALID_MAX, INVALID_MIN, MarketDepth};
+
+/// Records the mid price each time the best bid or the best ask changes, keeping up to the
+/// capacity of the most recent samples as `(timestamp, mid)`.
+#[derive(Clone, Debug)]
+pub struct MidHistory {
+    capacity: usize,
+    samples: VecDeque<(i64, f64)>,
+    best_bid_tick: i64,
+    best_ask_tick: i64,
+}
+
+impl Default for MidHistory {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl MidHistory {
+    /// Constructs an instance of `MidHistory` that keeps up to `capacity` samples. The history is
+    /// enabled only if `capacity` is greater than zero.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+            best_bid_tick: INVALID_MIN,
+            best_ask_tick: INVALID_MAX,
+        }
+    }
+
+    /// Returns `true` if the history is enabled.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Samples the mid price of the market depth at `timestamp` if the best bid or the best ask
+    /// has changed since the last update, evicting the oldest sample if the capacity is reached.
+    /// No sample is taken while either side of the market depth is empty.
+    pub fn update<MD: MarketDepth>(&mut self, depth: &MD, timestamp: i64) {
+        if !self.enabled() {
+            return;
+        }
+        let best_bid_tick = depth.best_bid_tick();
+        let best_ask_tick = depth.best_ask_tick();
+        if best_bid_tick == self.best_bid_tick && best_ask_tick == self.best_ask_tick {
+            return;
+        }
+        self.best_bid_tick = best_bid_tick;
+        self.best_ask_tick = best_ask_tick;
+        if best_bid_tick == INVALID_MIN || best_ask_tick == INVALID_MAX {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        let mid = (best_bid_tick + best_ask_tick) as f64 * depth.tick_size() / 2.0;
+        self.samples.push_back((timestamp, mid));
+    }
+
+    /// Returns the samples taken at or after `since`, from the oldest.
+    pub fn since(&self, since: i64) -> Vec<(i64, f64)> {
+        let start = self.samples.partition_point(|(timestamp, _)| *timestamp < since);
+        self.samples.range(start..).copied().collect()
+    }
+
+    /// Clears the samples.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
@@ -2,13 +2,17 @@ use std::collections::HashMap;
 
 pub use btreemarketdepth::BTreeMarketDepth;
 pub use hashmapmarketdepth::HashMapMarketDepth;
+pub use midhistory::MidHistory;
 pub use roivectormarketdepth::ROIVectorMarketDepth;
+pub use snapshot::{DepthSnapshot, LevelChange, LevelChangeKind};
 
 use crate::prelude::Side;
 
 mod btreemarketdepth;
 mod hashmapmarketdepth;
+mod midhistory;
 mod roivectormarketdepth;
+mod snapshot;
 
 #[cfg(any(feature = "unstable_fuse", doc))]
 mod fuse;
@@ -17,7 +21,7 @@ mod fuse;
 pub use fuse::FusedHashMapMarketDepth;
 
 use crate::{
-    backtest::data::Data,
+    backtest::{BacktestError, data::Data},
     types::{Event, OrderId},
 };
 
@@ -30,13 +34,29 @@ pub const INVALID_MAX: i64 = i64::MAX;
 /// Provides MarketDepth interface.
 pub trait MarketDepth {
     /// Returns the best bid price.
-    /// If there is no best bid, it returns [`f64::NAN`].
+    /// If there is no best bid, it returns [`f64::NAN`]. See
+    /// [`try_best_bid`](MarketDepth::try_best_bid) to handle the empty side explicitly.
     fn best_bid(&self) -> f64;
 
     /// Returns the best ask price.
-    /// If there is no best ask, it returns [`f64::NAN`].
+    /// If there is no best ask, it returns [`f64::NAN`]. See
+    /// [`try_best_ask`](MarketDepth::try_best_ask) to handle the empty side explicitly.
     fn best_ask(&self) -> f64;
 
+    /// Returns the best bid price, or `None` if there is no best bid. Prefer this over
+    /// [`best_bid`](MarketDepth::best_bid) so that a strategy cannot operate on a price derived
+    /// from an empty side.
+    fn try_best_bid(&self) -> Option<f64> {
+        (self.best_bid_tick() != INVALID_MIN).then(|| self.best_bid())
+    }
+
+    /// Returns the best ask price, or `None` if there is no best ask. Prefer this over
+    /// [`best_ask`](MarketDepth::best_ask) so that a strategy cannot operate on a price derived
+    /// from an empty side.
+    fn try_best_ask(&self) -> Option<f64> {
+        (self.best_ask_tick() != INVALID_MAX).then(|| self.best_ask())
+    }
+
     /// Returns the best bid price in ticks.
     /// If there is no best bid, it returns [`INVALID_MIN`].
     fn best_bid_tick(&self) -> i64;
@@ -56,6 +76,141 @@ pub trait MarketDepth {
 
     /// Returns the quantity at the ask market depth for a given price in ticks.
     fn ask_qty_at_tick(&self, price_tick: i64) -> f64;
+
+    /// Returns the quantity at the best bid.
+    /// If there is no best bid, it returns `0.0`.
+    fn best_bid_qty(&self) -> f64 {
+        let best_bid_tick = self.best_bid_tick();
+        if best_bid_tick == INVALID_MIN {
+            0.0
+        } else {
+            self.bid_qty_at_tick(best_bid_tick)
+        }
+    }
+
+    /// Returns the quantity at the best ask.
+    /// If there is no best ask, it returns `0.0`.
+    fn best_ask_qty(&self) -> f64 {
+        let best_ask_tick = self.best_ask_tick();
+        if best_ask_tick == INVALID_MAX {
+            0.0
+        } else {
+            self.ask_qty_at_tick(best_ask_tick)
+        }
+    }
+
+    /// Returns the mid price with a flag that is `true` if the market depth is one-sided, in
+    /// which case the price is the best price of the populated side. Returns `None` if both sides
+    /// are empty.
+    fn mid_price(&self) -> Option<(f64, bool)> {
+        match (self.try_best_bid(), self.try_best_ask()) {
+            (Some(_), Some(_)) => Some((
+                (self.best_bid_tick() + self.best_ask_tick()) as f64 * self.tick_size() / 2.0,
+                false,
+            )),
+            (Some(best_bid), None) => Some((best_bid, true)),
+            (None, Some(best_ask)) => Some((best_ask, true)),
+            (None, None) => None,
+        }
+    }
+
+    /// Returns the microprice, the average of the best bid and the best ask weighted by the
+    /// quantity on the opposite side, so that it leans toward the best ask when the best bid
+    /// holds more quantity and vice versa. The one-sided flag and the empty market depth are
+    /// handled as in [`mid_price`](MarketDepth::mid_price).
+    fn microprice(&self) -> Option<(f64, bool)> {
+        match (self.try_best_bid(), self.try_best_ask()) {
+            (Some(best_bid), Some(best_ask)) => {
+                let best_bid_qty = self.best_bid_qty();
+                let best_ask_qty = self.best_ask_qty();
+                let total_qty = best_bid_qty + best_ask_qty;
+                if total_qty > 0.0 {
+                    Some((
+                        (best_bid * best_ask_qty + best_ask * best_bid_qty) / total_qty,
+                        false,
+                    ))
+                } else {
+                    self.mid_price()
+                }
+            }
+            _ => self.mid_price(),
+        }
+    }
+
+    /// Returns the price of the level at which the cumulative quantity, accumulated level by level
+    /// from the best price on the given side, reaches `target_qty`, that is, the price needed to
+    /// clear `target_qty`. [`Side::Buy`] walks the bids and [`Side::Sell`] walks the asks.
+    /// Returns `None` if the side doesn't hold enough quantity.
+    fn price_at_cumulative_qty(&self, side: Side, target_qty: f64) -> Option<f64>;
+
+    /// Returns the volume-weighted average price of consuming `qty` level by level from the best
+    /// price on the given side, along with the quantity actually consumed, which is less than
+    /// `qty` if the side doesn't hold enough quantity. [`Side::Buy`] walks the bids and
+    /// [`Side::Sell`] walks the asks. Returns `None` if the side is empty.
+    fn vwap_for_qty(&self, side: Side, qty: f64) -> Option<(f64, f64)> {
+        let levels = match side {
+            Side::Buy => self.bid_levels(usize::MAX),
+            Side::Sell => self.ask_levels(usize::MAX),
+            Side::None | Side::Unsupported => return None,
+        };
+        vwap_tick_for_qty(levels, qty)
+            .map(|(vwap_tick, filled_qty)| (vwap_tick * self.tick_size(), filled_qty))
+    }
+
+    /// Returns up to `max_levels` populated bid levels as `(price_tick, qty)`, ordered from the
+    /// best bid downward. Fewer levels are returned if the bid side doesn't hold that many.
+    fn bid_levels(&self, max_levels: usize) -> Vec<(i64, f64)>;
+
+    /// Returns up to `max_levels` populated ask levels as `(price_tick, qty)`, ordered from the
+    /// best ask upward. Fewer levels are returned if the ask side doesn't hold that many.
+    fn ask_levels(&self, max_levels: usize) -> Vec<(i64, f64)>;
+
+    /// Returns the range of interest as `(lower bound, upper bound)` in ticks, both inclusive.
+    /// Returns `None` if the market depth covers every price.
+    fn roi_tick_range(&self) -> Option<(i64, i64)> {
+        None
+    }
+}
+
+/// Returns the price in ticks of the first level at which the cumulative quantity of the levels,
+/// given as `(price_tick, qty)` from the best price, reaches `target_qty`.
+fn tick_at_cumulative_qty<I>(levels: I, target_qty: f64) -> Option<i64>
+where
+    I: IntoIterator<Item = (i64, f64)>,
+{
+    let mut cum_qty = 0.0;
+    for (price_tick, qty) in levels {
+        if qty <= 0.0 {
+            continue;
+        }
+        cum_qty += qty;
+        if cum_qty >= target_qty {
+            return Some(price_tick);
+        }
+    }
+    None
+}
+
+/// Returns the volume-weighted average price in ticks and the quantity consumed by consuming
+/// `target_qty` from the levels, given as `(price_tick, qty)` from the best price.
+fn vwap_tick_for_qty<I>(levels: I, target_qty: f64) -> Option<(f64, f64)>
+where
+    I: IntoIterator<Item = (i64, f64)>,
+{
+    let mut filled_qty = 0.0;
+    let mut filled_tick_value = 0.0;
+    for (price_tick, qty) in levels {
+        if filled_qty >= target_qty {
+            break;
+        }
+        if qty <= 0.0 {
+            continue;
+        }
+        let fill_qty = qty.min(target_qty - filled_qty);
+        filled_qty += fill_qty;
+        filled_tick_value += price_tick as f64 * fill_qty;
+    }
+    (filled_qty > 0.0).then(|| (filled_tick_value / filled_qty, filled_qty))
 }
 
 /// Provides Level2-specific market depth functions.
@@ -111,12 +266,115 @@ pub struct L3Order {
     pub timestamp: i64,
 }
 
+/// Determines how an add-order event is handled when the order crosses the best price on the
+/// opposite side outside an auction, that is, when price crossing is not allowed.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum CrossingAddPolicy {
+    /// Adds the order to the order book as it is. The best price on the opposite side moves past
+    /// the order's price, while the crossed levels remain in the order book.
+    #[default]
+    Apply,
+    /// Treats the order as aggressive and holds it out of the order book, pending the events that
+    /// consume the crossed liquidity. The order is added to the order book once the book
+    /// uncrosses, that is, once a fill, delete, or modify event leaves it no longer crossing, or
+    /// once a modify event for the order itself no longer crosses. It is discarded by a delete
+    /// event.
+    Hold,
+    /// Rejects the order with [`BacktestError::CrossingOrder`].
+    Reject,
+}
+
+/// Holds the crossing add-orders according to the [`CrossingAddPolicy`].
+#[derive(Debug, Default)]
+pub(crate) struct CrossingAdds {
+    policy: CrossingAddPolicy,
+    held: HashMap<OrderId, L3Order>,
+}
+
+impl CrossingAdds {
+    pub fn new(policy: CrossingAddPolicy) -> Self {
+        Self {
+            policy,
+            held: Default::default(),
+        }
+    }
+
+    /// Returns `true` if the order on the given side at `price_tick` crosses the best price on the
+    /// opposite side and is to be held or rejected according to the policy.
+    pub fn intercepts(
+        &self,
+        side: Side,
+        price_tick: i64,
+        best_bid_tick: i64,
+        best_ask_tick: i64,
+    ) -> bool {
+        if self.policy == CrossingAddPolicy::Apply {
+            return false;
+        }
+        match side {
+            Side::Buy => price_tick >= best_ask_tick,
+            Side::Sell => price_tick <= best_bid_tick,
+            Side::None | Side::Unsupported => false,
+        }
+    }
+
+    /// Holds or rejects the crossing order according to the policy. `orders` are the orders in the
+    /// order book, used to check the order ID.
+    pub fn hold(
+        &mut self,
+        order: L3Order,
+        orders: &HashMap<OrderId, L3Order>,
+    ) -> Result<(), BacktestError> {
+        if orders.contains_key(&order.order_id) || self.held.contains_key(&order.order_id) {
+            return Err(BacktestError::OrderIdExist);
+        }
+        match self.policy {
+            CrossingAddPolicy::Hold => {
+                self.held.insert(order.order_id, order);
+                Ok(())
+            }
+            CrossingAddPolicy::Reject => Err(BacktestError::CrossingOrder),
+            CrossingAddPolicy::Apply => unreachable!(),
+        }
+    }
+
+    /// Takes all held orders in the order in which they arrive, so that the ones that no longer
+    /// cross can be added to the order book.
+    pub fn take(&mut self) -> Vec<L3Order> {
+        let mut orders: Vec<_> = self.held.drain().map(|(_, order)| order).collect();
+        orders.sort_by_key(|order| (order.timestamp, order.order_id));
+        orders
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    pub fn contains(&self, order_id: OrderId) -> bool {
+        self.held.contains_key(&order_id)
+    }
+
+    pub fn remove(&mut self, order_id: OrderId) -> Option<L3Order> {
+        self.held.remove(&order_id)
+    }
+
+    /// Discards the held orders on the given side. If the side is [Side::None], both sides are
+    /// discarded.
+    pub fn clear(&mut self, side: Side) {
+        match side {
+            Side::None => self.held.clear(),
+            side => self.held.retain(|_, order| order.side != side),
+        }
+    }
+}
+
 /// Provides Level3-specific market depth functions.
 pub trait L3MarketDepth: MarketDepth {
     type Error;
 
     /// Adds a buy order to the order book and returns a tuple containing (the previous best bid
-    /// in ticks, the current best bid in ticks).
+    /// in ticks, the current best bid in ticks). If price crossing is not allowed and the order
+    /// crosses the best ask, it is handled according to the [`CrossingAddPolicy`].
     fn add_buy_order(
         &mut self,
         order_id: OrderId,
@@ -126,7 +384,8 @@ pub trait L3MarketDepth: MarketDepth {
     ) -> Result<(i64, i64), Self::Error>;
 
     /// Adds a sell order to the order book and returns a tuple containing (the previous best ask
-    ///  in ticks, the current best ask in ticks).
+    ///  in ticks, the current best ask in ticks). If price crossing is not allowed and the order
+    /// crosses the best bid, it is handled according to the [`CrossingAddPolicy`].
     fn add_sell_order(
         &mut self,
         order_id: OrderId,
@@ -143,7 +402,11 @@ pub trait L3MarketDepth: MarketDepth {
     ) -> Result<(Side, i64, i64), Self::Error>;
 
     /// Modifies the order in the order book and returns a tuple containing (side, the previous best
-    /// in ticks, the current best in ticks).
+    /// in ticks, the current best in ticks). Modifying the quantity to zero, such as when the
+    /// order is fully filled, deletes the order instead of leaving an empty level that would stop
+    /// the best price scans. A subsequent delete or modify for the deleted order fails with
+    /// [`BacktestError::OrderNotFound`], so the caller must ignore the events that the market feed
+    /// sends for the order after the fill.
     fn modify_order(
         &mut self,
         order_id: OrderId,
@@ -1,6 +1,9 @@
 use std::collections::{HashMap, hash_map::Entry};
 
-use super::{ApplySnapshot, INVALID_MAX, INVALID_MIN, L3MarketDepth, L3Order, MarketDepth};
+use super::{
+    ApplySnapshot, CrossingAddPolicy, CrossingAdds, DepthSnapshot, INVALID_MAX, INVALID_MIN,
+    L3MarketDepth, L3Order, MarketDepth, tick_at_cumulative_qty, vwap_tick_for_qty,
+};
 use crate::{
     backtest::{BacktestError, data::Data},
     prelude::{L2MarketDepth, OrderId, Side},
@@ -27,6 +30,7 @@ pub struct ROIVectorMarketDepth {
     pub orders: HashMap<OrderId, L3Order>,
 
     pub allow_price_cross: bool,
+    crossing_adds: CrossingAdds,
 }
 
 #[inline(always)]
@@ -181,6 +185,16 @@ impl ROIVectorMarketDepth {
             roi_ub,
             orders: HashMap::new(),
             allow_price_cross: true, // 默认允许价格交叉（集合竞价模式）
+            crossing_adds: Default::default(),
+        }
+    }
+
+    /// Sets how an add-order that crosses the best price on the opposite side outside an auction
+    /// is handled. The default value is [`CrossingAddPolicy::Apply`].
+    pub fn crossing_add_policy(self, policy: CrossingAddPolicy) -> Self {
+        Self {
+            crossing_adds: CrossingAdds::new(policy),
+            ..self
         }
     }
 
@@ -188,7 +202,27 @@ impl ROIVectorMarketDepth {
         self.allow_price_cross = allow;
     }
 
+    /// Adds the held crossing orders that no longer cross to the order book, once the order book
+    /// uncrosses. The ones that still cross remain held.
+    fn release_crossing_adds(&mut self) -> Result<(), BacktestError> {
+        if self.allow_price_cross || self.crossing_adds.is_empty() {
+            return Ok(());
+        }
+        for order in self.crossing_adds.take() {
+            let px = order.price_tick as f64 * self.tick_size;
+            if order.side == Side::Buy {
+                self.add_buy_order(order.order_id, px, order.qty, order.timestamp)?;
+            } else {
+                self.add_sell_order(order.order_id, px, order.qty, order.timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
     fn add(&mut self, order: L3Order) -> Result<(), BacktestError> {
+        if self.crossing_adds.contains(order.order_id) {
+            return Err(BacktestError::OrderIdExist);
+        }
         let order = match self.orders.entry(order.order_id) {
             Entry::Occupied(_) => return Err(BacktestError::OrderIdExist),
             Entry::Vacant(entry) => entry.insert(order),
@@ -217,6 +251,81 @@ impl ROIVectorMarketDepth {
     pub fn ask_depth(&self) -> &[f64] {
         self.ask_depth.as_slice()
     }
+
+    /// Returns the number of Level-3 orders held in the market depth, including the orders
+    /// outside the range of interest.
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// Returns the number of price levels with a positive quantity within the range of interest
+    /// on the given side. [`Side::None`] counts both sides.
+    pub fn populated_level_count(&self, side: Side) -> usize {
+        let count = |depth: &[f64]| depth.iter().filter(|&&qty| qty > 0.0).count();
+        match side {
+            Side::Buy => count(&self.bid_depth),
+            Side::Sell => count(&self.ask_depth),
+            Side::None => count(&self.bid_depth) + count(&self.ask_depth),
+            Side::Unsupported => 0,
+        }
+    }
+
+    /// Returns the approximate heap and inline memory used by the market depth in bytes. The
+    /// depth vectors are sized by the range of interest, while the order map grows with the number
+    /// of Level-3 orders, so this helps to decide the range of interest or whether a sparse market
+    /// depth suits better.
+    pub fn approx_memory_bytes(&self) -> usize {
+        let depth_bytes =
+            (self.bid_depth.capacity() + self.ask_depth.capacity()) * size_of::<f64>();
+        // Each hash map bucket holds the key, the value, and a control byte.
+        let orders_bytes =
+            self.orders.capacity() * (size_of::<OrderId>() + size_of::<L3Order>() + 1);
+        size_of::<Self>() + depth_bytes + orders_bytes
+    }
+
+    /// Takes a snapshot of the price levels within the range of interest, which can be encoded
+    /// and restored later by [`restore_from_snapshot`](Self::restore_from_snapshot).
+    pub fn snapshot(&self) -> DepthSnapshot {
+        DepthSnapshot {
+            timestamp: self.timestamp,
+            tick_size: self.tick_size,
+            lot_size: self.lot_size,
+            bids: self.bid_levels(usize::MAX),
+            asks: self.ask_levels(usize::MAX),
+        }
+    }
+
+    /// Replaces the price levels with those of the snapshot to seed the market depth for replay.
+    /// The snapshot must have been taken with the same tick size, and the levels outside the range
+    /// of interest are ignored. The Level-3 orders are cleared since the snapshot only holds the
+    /// quantity of each level.
+    pub fn restore_from_snapshot(&mut self, snapshot: &DepthSnapshot) {
+        self.orders.clear();
+        self.crossing_adds.clear(Side::None);
+        self.timestamp = snapshot.timestamp;
+        self.best_bid_tick = INVALID_MIN;
+        self.best_ask_tick = INVALID_MAX;
+        self.low_bid_tick = INVALID_MAX;
+        self.high_ask_tick = INVALID_MIN;
+        self.bid_depth.fill(0.0);
+        self.ask_depth.fill(0.0);
+        for &(price_tick, qty) in &snapshot.bids {
+            if qty <= 0.0 || price_tick < self.roi_lb || price_tick > self.roi_ub {
+                continue;
+            }
+            self.best_bid_tick = self.best_bid_tick.max(price_tick);
+            self.low_bid_tick = self.low_bid_tick.min(price_tick);
+            self.bid_depth[(price_tick - self.roi_lb) as usize] = qty;
+        }
+        for &(price_tick, qty) in &snapshot.asks {
+            if qty <= 0.0 || price_tick < self.roi_lb || price_tick > self.roi_ub {
+                continue;
+            }
+            self.best_ask_tick = self.best_ask_tick.min(price_tick);
+            self.high_ask_tick = self.high_ask_tick.max(price_tick);
+            self.ask_depth[(price_tick - self.roi_lb) as usize] = qty;
+        }
+    }
 }
 
 impl L2MarketDepth for ROIVectorMarketDepth {
@@ -400,7 +509,7 @@ impl L2MarketDepth for ROIVectorMarketDepth {
                     let clear_upto = (clear_upto_price / self.tick_size).round() as i64;
                     if self.best_ask_tick != INVALID_MAX {
                         let from = self.best_ask_tick - self.roi_lb;
-                        let to = (clear_upto + 1 - self.roi_ub).min(self.ask_depth.len() as i64);
+                        let to = (clear_upto + 1 - self.roi_lb).min(self.ask_depth.len() as i64);
                         for t in from..to {
                             unsafe {
                                 *self.ask_depth.get_unchecked_mut(t as usize) = 0.0;
@@ -488,6 +597,11 @@ impl MarketDepth for ROIVectorMarketDepth {
         self.lot_size
     }
 
+    #[inline(always)]
+    fn roi_tick_range(&self) -> Option<(i64, i64)> {
+        Some((self.roi_lb, self.roi_ub))
+    }
+
     #[inline(always)]
     fn bid_qty_at_tick(&self, price_tick: i64) -> f64 {
         if price_tick < self.roi_lb || price_tick > self.roi_ub {
@@ -515,6 +629,117 @@ impl MarketDepth for ROIVectorMarketDepth {
             }
         }
     }
+
+    #[inline(always)]
+    fn best_bid_qty(&self) -> f64 {
+        // The best bid is always within the range of interest.
+        if self.best_bid_tick == INVALID_MIN {
+            0.0
+        } else {
+            self.bid_depth[(self.best_bid_tick - self.roi_lb) as usize]
+        }
+    }
+
+    #[inline(always)]
+    fn best_ask_qty(&self) -> f64 {
+        // The best ask is always within the range of interest.
+        if self.best_ask_tick == INVALID_MAX {
+            0.0
+        } else {
+            self.ask_depth[(self.best_ask_tick - self.roi_lb) as usize]
+        }
+    }
+
+    fn price_at_cumulative_qty(&self, side: Side, target_qty: f64) -> Option<f64> {
+        // Only the levels within the range of interest are accumulated.
+        let price_tick = match side {
+            Side::Buy if self.best_bid_tick >= self.roi_lb => tick_at_cumulative_qty(
+                (self.roi_lb..=self.best_bid_tick.min(self.roi_ub))
+                    .rev()
+                    .map(|price_tick| {
+                        (
+                            price_tick,
+                            self.bid_depth[(price_tick - self.roi_lb) as usize],
+                        )
+                    }),
+                target_qty,
+            ),
+            Side::Sell if self.best_ask_tick <= self.roi_ub => tick_at_cumulative_qty(
+                (self.best_ask_tick.max(self.roi_lb)..=self.roi_ub).map(|price_tick| {
+                    (
+                        price_tick,
+                        self.ask_depth[(price_tick - self.roi_lb) as usize],
+                    )
+                }),
+                target_qty,
+            ),
+            _ => None,
+        }?;
+        Some(price_tick as f64 * self.tick_size)
+    }
+
+    fn vwap_for_qty(&self, side: Side, qty: f64) -> Option<(f64, f64)> {
+        // Walks the contiguous levels within the range of interest from the best price without
+        // collecting them.
+        let vwap = match side {
+            Side::Buy if self.best_bid_tick >= self.roi_lb => {
+                let best = (self.best_bid_tick.min(self.roi_ub) - self.roi_lb) as usize;
+                vwap_tick_for_qty(
+                    self.bid_depth[..=best]
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(i, qty)| (self.roi_lb + i as i64, *qty)),
+                    qty,
+                )
+            }
+            Side::Sell if self.best_ask_tick <= self.roi_ub => {
+                let best = (self.best_ask_tick.max(self.roi_lb) - self.roi_lb) as usize;
+                vwap_tick_for_qty(
+                    self.ask_depth[best..]
+                        .iter()
+                        .enumerate()
+                        .map(|(i, qty)| (self.roi_lb + (best + i) as i64, *qty)),
+                    qty,
+                )
+            }
+            _ => None,
+        };
+        vwap.map(|(vwap_tick, filled_qty)| (vwap_tick * self.tick_size, filled_qty))
+    }
+
+    fn bid_levels(&self, max_levels: usize) -> Vec<(i64, f64)> {
+        // Only the levels within the range of interest are returned, skipping the empty slots
+        // while walking the vector.
+        if self.best_bid_tick < self.roi_lb {
+            return Vec::new();
+        }
+        let best = (self.best_bid_tick.min(self.roi_ub) - self.roi_lb) as usize;
+        self.bid_depth[..=best]
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, qty)| **qty > 0.0)
+            .take(max_levels)
+            .map(|(i, qty)| (self.roi_lb + i as i64, *qty))
+            .collect()
+    }
+
+    fn ask_levels(&self, max_levels: usize) -> Vec<(i64, f64)> {
+        // Only the levels within the range of interest are returned, skipping the empty slots
+        // while walking the vector.
+        if self.best_ask_tick > self.roi_ub {
+            return Vec::new();
+        }
+        let best = (self.best_ask_tick.max(self.roi_lb) - self.roi_lb) as usize;
+        self.ask_depth[best..]
+            .iter()
+            .enumerate()
+            .filter(|(_, qty)| **qty > 0.0)
+            .take(max_levels)
+            .map(|(i, qty)| (self.roi_lb + (best + i) as i64, *qty))
+            .collect()
+    }
 }
 
 impl ApplySnapshot for ROIVectorMarketDepth {
@@ -571,6 +796,26 @@ impl L3MarketDepth for ROIVectorMarketDepth {
         timestamp: i64,
     ) -> Result<(i64, i64), Self::Error> {
         let price_tick = (px / self.tick_size).round() as i64;
+        if !self.allow_price_cross
+            && self.crossing_adds.intercepts(
+                Side::Buy,
+                price_tick,
+                self.best_bid_tick,
+                self.best_ask_tick,
+            )
+        {
+            self.crossing_adds.hold(
+                L3Order {
+                    order_id,
+                    side: Side::Buy,
+                    price_tick,
+                    qty,
+                    timestamp,
+                },
+                &self.orders,
+            )?;
+            return Ok((self.best_bid_tick, self.best_bid_tick));
+        }
         self.add(L3Order {
             order_id,
             side: Side::Buy,
@@ -580,22 +825,7 @@ impl L3MarketDepth for ROIVectorMarketDepth {
         })?;
         let prev_best_tick = self.best_bid_tick;
         if price_tick > self.best_bid_tick {
-            if !self.allow_price_cross && price_tick >= self.best_ask_tick {
-                //// println!("add_buy_order crossing fill!");
-                self.best_ask_tick = depth_above_with_qty(
-                    &self.ask_depth,
-                    price_tick,
-                    self.best_ask_tick,
-                    self.roi_lb,
-                    self.roi_ub,
-                    qty,
-                );
-                if self.best_ask_tick > price_tick {
-                    self.best_bid_tick = price_tick;
-                }
-            } else {
-                self.best_bid_tick = price_tick;
-            }
+            self.best_bid_tick = price_tick;
         }
         self.low_bid_tick = self.low_bid_tick.min(price_tick);
         Ok((prev_best_tick, self.best_bid_tick))
@@ -609,6 +839,26 @@ impl L3MarketDepth for ROIVectorMarketDepth {
         timestamp: i64,
     ) -> Result<(i64, i64), Self::Error> {
         let price_tick = (px / self.tick_size).round() as i64;
+        if !self.allow_price_cross
+            && self.crossing_adds.intercepts(
+                Side::Sell,
+                price_tick,
+                self.best_bid_tick,
+                self.best_ask_tick,
+            )
+        {
+            self.crossing_adds.hold(
+                L3Order {
+                    order_id,
+                    side: Side::Sell,
+                    price_tick,
+                    qty,
+                    timestamp,
+                },
+                &self.orders,
+            )?;
+            return Ok((self.best_ask_tick, self.best_ask_tick));
+        }
         self.add(L3Order {
             order_id,
             side: Side::Sell,
@@ -618,22 +868,7 @@ impl L3MarketDepth for ROIVectorMarketDepth {
         })?;
         let prev_best_tick = self.best_ask_tick;
         if price_tick < self.best_ask_tick {
-            if !self.allow_price_cross && self.best_bid_tick >= price_tick {
-                // println!("add_sell_order crossing fill!");
-                self.best_bid_tick = depth_below_with_qty(
-                    &self.bid_depth,
-                    price_tick,
-                    self.best_bid_tick,
-                    self.roi_lb,
-                    self.roi_ub,
-                    qty,
-                );
-                if self.best_bid_tick < price_tick {
-                    self.best_ask_tick = price_tick;
-                }
-            } else {
-                self.best_ask_tick = price_tick;
-            }
+            self.best_ask_tick = price_tick;
         }
         self.high_ask_tick = self.high_ask_tick.max(price_tick);
         Ok((prev_best_tick, self.best_ask_tick))
@@ -644,11 +879,19 @@ impl L3MarketDepth for ROIVectorMarketDepth {
         order_id: OrderId,
         _timestamp: i64,
     ) -> Result<(Side, i64, i64), Self::Error> {
+        if let Some(order) = self.crossing_adds.remove(order_id) {
+            let best_tick = if order.side == Side::Buy {
+                self.best_bid_tick
+            } else {
+                self.best_ask_tick
+            };
+            return Ok((order.side, best_tick, best_tick));
+        }
         let order = self
             .orders
             .remove(&order_id)
             .ok_or(BacktestError::OrderNotFound)?;
-        if order.side == Side::Buy {
+        let result = if order.side == Side::Buy {
             let prev_best_tick = self.best_bid_tick;
 
             if !(order.price_tick < self.roi_lb || order.price_tick > self.roi_ub) {
@@ -696,7 +939,9 @@ impl L3MarketDepth for ROIVectorMarketDepth {
                 }
             }
             Ok((Side::Sell, prev_best_tick, self.best_ask_tick))
-        }
+        };
+        self.release_crossing_adds()?;
+        result
     }
 
     fn modify_order(
@@ -706,11 +951,26 @@ impl L3MarketDepth for ROIVectorMarketDepth {
         qty: f64,
         timestamp: i64,
     ) -> Result<(Side, i64, i64), Self::Error> {
+        if (qty / self.lot_size).round() as i64 == 0 {
+            return self.delete_order(order_id, timestamp);
+        }
+        if let Some(order) = self.crossing_adds.remove(order_id) {
+            // The held order is added to the order book unless it still crosses.
+            return if order.side == Side::Buy {
+                let (prev_best_tick, best_tick) =
+                    self.add_buy_order(order_id, px, qty, timestamp)?;
+                Ok((Side::Buy, prev_best_tick, best_tick))
+            } else {
+                let (prev_best_tick, best_tick) =
+                    self.add_sell_order(order_id, px, qty, timestamp)?;
+                Ok((Side::Sell, prev_best_tick, best_tick))
+            };
+        }
         let order = self
             .orders
             .get_mut(&order_id)
             .ok_or(BacktestError::OrderNotFound)?;
-        if order.side == Side::Buy {
+        let result = if order.side == Side::Buy {
             let prev_best_tick = self.best_bid_tick;
             let price_tick = (px / self.tick_size).round() as i64;
             if price_tick != order.price_tick {
@@ -836,10 +1096,13 @@ impl L3MarketDepth for ROIVectorMarketDepth {
                 order.qty = qty;
                 Ok((Side::Sell, self.best_ask_tick, self.best_ask_tick))
             }
-        }
+        };
+        self.release_crossing_adds()?;
+        result
     }
 
     fn clear_orders(&mut self, side: Side) {
+        self.crossing_adds.clear(side);
         match side {
             Side::Buy => {
                 L2MarketDepth::clear_depth(self, side, f64::NEG_INFINITY);
@@ -873,6 +1136,9 @@ impl L3MarketDepth for ROIVectorMarketDepth {
                 unreachable!();
             }
         }
+        // Clearing a side uncrosses the order book. The held orders are taken out before they
+        // are added back, so adding them cannot fail.
+        self.release_crossing_adds().unwrap();
     }
 
     fn orders(&self) -> &HashMap<OrderId, L3Order> {
@@ -886,8 +1152,13 @@ impl L3MarketDepth for ROIVectorMarketDepth {
 
 #[cfg(test)]
 mod tests {
+    use bincode::config;
+
     use crate::{
-        depth::{INVALID_MAX, INVALID_MIN, L3MarketDepth, MarketDepth, ROIVectorMarketDepth},
+        depth::{
+            DepthSnapshot, INVALID_MAX, INVALID_MIN, L2MarketDepth, L3MarketDepth, MarketDepth,
+            ROIVectorMarketDepth,
+        },
         types::Side,
     };
 
@@ -900,6 +1171,173 @@ mod tests {
         }};
     }
 
+    #[test]
+    fn test_order_count_and_memory() {
+        let mut depth = ROIVectorMarketDepth::new(0.1, 0.001, 0.0, 2000.0);
+        let empty_bytes = depth.approx_memory_bytes();
+        assert!(empty_bytes >= 2 * 20001 * size_of::<f64>());
+
+        depth.add_buy_order(1, 500.1, 0.001, 0).unwrap();
+        depth.add_buy_order(2, 500.1, 0.002, 0).unwrap();
+        depth.add_buy_order(3, 500.0, 0.001, 0).unwrap();
+        depth.add_sell_order(4, 500.3, 0.001, 0).unwrap();
+        // Outside the range of interest.
+        depth.add_sell_order(5, 3000.0, 0.001, 0).unwrap();
+
+        assert_eq!(depth.order_count(), 5);
+        assert_eq!(depth.populated_level_count(Side::Buy), 2);
+        assert_eq!(depth.populated_level_count(Side::Sell), 1);
+        assert_eq!(depth.populated_level_count(Side::None), 3);
+        assert!(depth.approx_memory_bytes() > empty_bytes);
+
+        depth.delete_order(3, 0).unwrap();
+        assert_eq!(depth.order_count(), 4);
+        assert_eq!(depth.populated_level_count(Side::Buy), 1);
+    }
+
+    #[test]
+    fn test_negative_prices() {
+        let lot_size = 1.0;
+        let mut depth = ROIVectorMarketDepth::new(0.5, lot_size, -5.0, 5.0);
+
+        depth.update_bid_depth(-1.0, 2.0, 0);
+        depth.update_bid_depth(-1.5, 1.0, 0);
+        depth.update_ask_depth(-0.5, 3.0, 0);
+        depth.update_ask_depth(0.5, 1.0, 0);
+        assert_eq!(depth.best_bid_tick(), -2);
+        assert_eq!(depth.best_bid(), -1.0);
+        assert_eq!(depth.best_ask_tick(), -1);
+        assert_eq!(depth.best_ask(), -0.5);
+        assert_eq_qty!(depth.bid_qty_at_tick(-3), 1.0, lot_size);
+        assert_eq_qty!(depth.ask_qty_at_tick(1), 1.0, lot_size);
+        // Outside the range of interest.
+        assert!(depth.bid_qty_at_tick(-11).is_nan());
+
+        depth.update_bid_depth(-1.0, 0.0, 0);
+        assert_eq!(depth.best_bid_tick(), -3);
+        depth.update_ask_depth(-0.5, 0.0, 0);
+        assert_eq!(depth.best_ask_tick(), 1);
+
+        depth.update_ask_depth(-0.5, 2.0, 0);
+        depth.clear_depth(Side::Sell, -0.5);
+        assert_eq!(depth.best_ask_tick(), 1);
+        assert_eq_qty!(depth.ask_qty_at_tick(-1), 0.0, lot_size);
+        depth.clear_depth(Side::Sell, 0.5);
+        assert_eq!(depth.best_ask_tick(), INVALID_MAX);
+        assert_eq_qty!(depth.ask_qty_at_tick(1), 0.0, lot_size);
+
+        depth.clear_depth(Side::Buy, -1.5);
+        assert_eq!(depth.best_bid_tick(), INVALID_MIN);
+        assert_eq_qty!(depth.bid_qty_at_tick(-3), 0.0, lot_size);
+    }
+
+    #[test]
+    fn test_best_qty() {
+        let lot_size = 0.001;
+        let mut depth = ROIVectorMarketDepth::new(0.1, lot_size, 0.0, 2000.0);
+        assert_eq!(depth.best_bid_qty(), 0.0);
+        assert_eq!(depth.best_ask_qty(), 0.0);
+
+        depth.update_bid_depth(500.1, 0.002, 0);
+        depth.update_bid_depth(500.0, 0.005, 0);
+        depth.update_ask_depth(500.3, 0.001, 0);
+        assert_eq_qty!(depth.best_bid_qty(), 0.002, lot_size);
+        assert_eq_qty!(depth.best_ask_qty(), 0.001, lot_size);
+
+        depth.update_bid_depth(500.1, 0.0, 0);
+        assert_eq_qty!(depth.best_bid_qty(), 0.005, lot_size);
+        depth.update_ask_depth(500.3, 0.0, 0);
+        assert_eq!(depth.best_ask_qty(), 0.0);
+    }
+
+    #[test]
+    fn test_levels() {
+        let mut depth = ROIVectorMarketDepth::new(1.0, 1.0, 0.0, 200.0);
+        assert!(depth.bid_levels(5).is_empty());
+        assert!(depth.ask_levels(5).is_empty());
+
+        depth.update_bid_depth(100.0, 2.0, 0);
+        depth.update_bid_depth(99.0, 3.0, 0);
+        depth.update_bid_depth(95.0, 5.0, 0);
+        depth.update_ask_depth(101.0, 1.0, 0);
+        depth.update_ask_depth(104.0, 4.0, 0);
+        depth.update_ask_depth(110.0, 6.0, 0);
+
+        assert_eq!(depth.bid_levels(2), vec![(100, 2.0), (99, 3.0)]);
+        assert_eq!(depth.ask_levels(2), vec![(101, 1.0), (104, 4.0)]);
+        // Stops at the last populated level.
+        assert_eq!(depth.bid_levels(10), vec![(100, 2.0), (99, 3.0), (95, 5.0)]);
+        assert_eq!(
+            depth.ask_levels(10),
+            vec![(101, 1.0), (104, 4.0), (110, 6.0)]
+        );
+
+        depth.update_bid_depth(99.0, 0.0, 0);
+        assert_eq!(depth.bid_levels(10), vec![(100, 2.0), (95, 5.0)]);
+        assert!(depth.ask_levels(0).is_empty());
+    }
+
+    #[test]
+    fn test_vwap_for_qty() {
+        let mut depth = ROIVectorMarketDepth::new(1.0, 1.0, 0.0, 200.0);
+        assert_eq!(depth.vwap_for_qty(Side::Sell, 1.0), None);
+
+        depth.update_bid_depth(100.0, 2.0, 0);
+        depth.update_bid_depth(98.0, 3.0, 0);
+        depth.update_ask_depth(101.0, 1.0, 0);
+        depth.update_ask_depth(102.0, 2.0, 0);
+        depth.update_ask_depth(105.0, 4.0, 0);
+
+        // Spans three levels, consuming 1 at 101, 2 at 102, and 2 at 105.
+        assert_eq!(
+            depth.vwap_for_qty(Side::Sell, 5.0),
+            Some(((101.0 + 204.0 + 210.0) / 5.0, 5.0))
+        );
+        assert_eq!(depth.vwap_for_qty(Side::Buy, 1.0), Some((100.0, 1.0)));
+
+        // Only the quantity that the side holds is consumed.
+        assert_eq!(
+            depth.vwap_for_qty(Side::Buy, 10.0),
+            Some(((200.0 + 294.0) / 5.0, 5.0))
+        );
+        assert_eq!(depth.vwap_for_qty(Side::None, 1.0), None);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let lot_size = 0.001;
+        let mut depth = ROIVectorMarketDepth::new(0.1, lot_size, 0.0, 2000.0);
+        depth.timestamp = 100;
+        depth.update_bid_depth(500.1, 0.002, 100);
+        depth.update_bid_depth(499.8, 0.005, 100);
+        depth.update_ask_depth(500.3, 0.001, 100);
+        depth.update_ask_depth(500.7, 0.004, 100);
+
+        let snapshot = depth.snapshot();
+        assert_eq!(snapshot.timestamp, 100);
+        assert_eq!(snapshot.bids, vec![(5001, 0.002), (4998, 0.005)]);
+        assert_eq!(snapshot.asks, vec![(5003, 0.001), (5007, 0.004)]);
+
+        let encoded = bincode::encode_to_vec(&snapshot, config::standard()).unwrap();
+        let (decoded, _): (DepthSnapshot, usize) =
+            bincode::decode_from_slice(&encoded, config::standard()).unwrap();
+
+        // Restores into a depth holding other levels.
+        let mut restored = ROIVectorMarketDepth::new(0.1, lot_size, 0.0, 2000.0);
+        restored.update_bid_depth(400.0, 1.0, 0);
+        restored.update_ask_depth(600.0, 1.0, 0);
+        restored.restore_from_snapshot(&decoded);
+        assert_eq!(restored.timestamp, 100);
+        assert_eq!(restored.best_bid_tick(), depth.best_bid_tick());
+        assert_eq!(restored.best_ask_tick(), depth.best_ask_tick());
+        assert_eq!(restored.bid_levels(10), depth.bid_levels(10));
+        assert_eq!(restored.ask_levels(10), depth.ask_levels(10));
+        assert_eq!(restored.bid_qty_at_tick(4000), 0.0);
+        assert_eq!(restored.ask_qty_at_tick(6000), 0.0);
+        assert_eq!(restored.low_bid_tick, 4998);
+        assert_eq!(restored.high_ask_tick, 5007);
+    }
+
     #[test]
     fn test_l3_add_delete_buy_order() {
         let lot_size = 0.001;
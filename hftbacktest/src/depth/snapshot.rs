@@ -0,0 +1,189 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use bincode::{Decode, Encode};
+
+use super::{ApplySnapshot, MarketDepth};
+use crate::types::{BUY_EVENT, SELL_EVENT, Side};
+
+/// Kind of a [`LevelChange`].
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum LevelChangeKind {
+    /// The price level didn't exist before.
+    Added,
+    /// The price level no longer exists.
+    Removed,
+    /// The quantity at the price level changed.
+    Changed,
+}
+
+/// A change of a price level between two [`DepthSnapshot`]s.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LevelChange {
+    pub kind: LevelChangeKind,
+    pub side: Side,
+    pub price_tick: i64,
+    /// The quantity at the price level in the earlier snapshot. `0` if the level is added.
+    pub prev_qty: f64,
+    /// The quantity at the price level in the later snapshot. `0` if the level is removed.
+    pub qty: f64,
+}
+
+/// A copy of the price levels of a market depth at a point in time, which can be compared against
+/// another snapshot to see how an operation changed the book, or encoded to be restored later for
+/// replay.
+#[derive(Clone, Debug, Decode, Encode)]
+pub struct DepthSnapshot {
+    /// The timestamp of the market depth at which the snapshot is taken.
+    pub timestamp: i64,
+    pub tick_size: f64,
+    pub lot_size: f64,
+    /// The bid levels as `(price_tick, qty)` from the best bid downward.
+    pub bids: Vec<(i64, f64)>,
+    /// The ask levels as `(price_tick, qty)` from the best ask upward.
+    pub asks: Vec<(i64, f64)>,
+}
+
+impl DepthSnapshot {
+    /// Takes a snapshot of the given market depth. [`MarketDepth`] doesn't expose the timestamp,
+    /// so it is set to `0`.
+    pub fn new<MD>(depth: &MD) -> Self
+    where
+        MD: MarketDepth + ApplySnapshot,
+    {
+        let mut bids = Vec::new();
+        let mut asks = Vec::new();
+        for ev in depth.snapshot() {
+            if ev.qty <= 0.0 {
+                continue;
+            }
+            let price_tick = (ev.px / depth.tick_size()).round() as i64;
+            if ev.ev & BUY_EVENT == BUY_EVENT {
+                bids.push((price_tick, ev.qty));
+            } else if ev.ev & SELL_EVENT == SELL_EVENT {
+                asks.push((price_tick, ev.qty));
+            }
+        }
+        bids.sort_by_key(|&(price_tick, _)| Reverse(price_tick));
+        asks.sort_by_key(|&(price_tick, _)| price_tick);
+        Self {
+            timestamp: 0,
+            tick_size: depth.tick_size(),
+            lot_size: depth.lot_size(),
+            bids,
+            asks,
+        }
+    }
+
+    /// Returns the quantity at the price level, or `0` if the level doesn't exist.
+    pub fn qty_at_tick(&self, side: Side, price_tick: i64) -> f64 {
+        let levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+            Side::None | Side::Unsupported => return 0.0,
+        };
+        levels
+            .iter()
+            .find(|(level_tick, _)| *level_tick == price_tick)
+            .map_or(0.0, |(_, qty)| *qty)
+    }
+
+    /// Returns the price level changes from this snapshot to `other`, bids first and then asks,
+    /// each in ascending price order. Quantities are compared in lots, so a change smaller than
+    /// half a lot is not reported.
+    pub fn diff(&self, other: &DepthSnapshot) -> Vec<LevelChange> {
+        let mut changes = Vec::new();
+        self.diff_side(Side::Buy, &self.bids, &other.bids, &mut changes);
+        self.diff_side(Side::Sell, &self.asks, &other.asks, &mut changes);
+        changes
+    }
+
+    fn diff_side(
+        &self,
+        side: Side,
+        prev: &[(i64, f64)],
+        curr: &[(i64, f64)],
+        changes: &mut Vec<LevelChange>,
+    ) {
+        let lots = |qty: f64| (qty / self.lot_size).round() as i64;
+        let prev: BTreeMap<i64, f64> = prev.iter().copied().collect();
+        let curr: BTreeMap<i64, f64> = curr.iter().copied().collect();
+        let mut price_ticks: Vec<i64> = prev.keys().chain(curr.keys()).copied().collect();
+        price_ticks.sort_unstable();
+        price_ticks.dedup();
+        for price_tick in price_ticks {
+            let prev_qty = prev.get(&price_tick).copied().unwrap_or(0.0);
+            let qty = curr.get(&price_tick).copied().unwrap_or(0.0);
+            let kind = match (lots(prev_qty), lots(qty)) {
+                (0, 0) => continue,
+                (0, _) => LevelChangeKind::Added,
+                (_, 0) => LevelChangeKind::Removed,
+                (prev_lots, lots) if prev_lots != lots => LevelChangeKind::Changed,
+                _ => continue,
+            };
+            changes.push(LevelChange {
+                kind,
+                side,
+                price_tick,
+                prev_qty,
+                qty,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        depth::{DepthSnapshot, HashMapMarketDepth, L3MarketDepth, LevelChange, LevelChangeKind},
+        types::Side,
+    };
+
+    #[test]
+    fn test_diff() {
+        let mut depth = HashMapMarketDepth::new(0.1, 0.001);
+        depth.add_buy_order(1, 500.0, 0.002, 0).unwrap();
+        depth.add_buy_order(2, 499.9, 0.001, 0).unwrap();
+        depth.add_sell_order(3, 500.1, 0.001, 0).unwrap();
+        depth.add_sell_order(4, 500.1, 0.003, 0).unwrap();
+
+        let before = DepthSnapshot::new(&depth);
+        assert!(before.diff(&before).is_empty());
+
+        depth.add_buy_order(5, 499.8, 0.004, 0).unwrap();
+        depth.delete_order(2, 0).unwrap();
+        depth.delete_order(3, 0).unwrap();
+        let after = DepthSnapshot::new(&depth);
+
+        assert_eq!(
+            before.diff(&after),
+            vec![
+                LevelChange {
+                    kind: LevelChangeKind::Added,
+                    side: Side::Buy,
+                    price_tick: 4998,
+                    prev_qty: 0.0,
+                    qty: 0.004,
+                },
+                LevelChange {
+                    kind: LevelChangeKind::Removed,
+                    side: Side::Buy,
+                    price_tick: 4999,
+                    prev_qty: 0.001,
+                    qty: 0.0,
+                },
+                LevelChange {
+                    kind: LevelChangeKind::Changed,
+                    side: Side::Sell,
+                    price_tick: 5001,
+                    prev_qty: 0.004,
+                    qty: after.qty_at_tick(Side::Sell, 5001),
+                },
+            ]
+        );
+        assert_eq!(
+            (after.qty_at_tick(Side::Sell, 5001) / 0.001).round() as i64,
+            3
+        );
+        assert_eq!(after.qty_at_tick(Side::Buy, 5000), 0.002);
+    }
+}
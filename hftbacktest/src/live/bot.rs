@@ -12,8 +12,10 @@ use crate::{
     depth::{L2MarketDepth, MarketDepth},
     live::{Instrument, ipc::Channel},
     types::{
+        AssetParams,
         Bot,
         BuildError,
+        CancelReason,
         ElapseResult,
         Event,
         LOCAL_ASK_DEPTH_EVENT,
@@ -27,6 +29,7 @@ use crate::{
         Order,
         OrderId,
         OrderRequest,
+        RejectReason,
         Side,
         StateValues,
         Status,
@@ -41,6 +44,8 @@ pub enum BotError {
     OrderIdExist,
     #[error("AssetNotFound")]
     InstrumentNotFound,
+    #[error("VenueNotFound")]
+    VenueNotFound,
     #[error("OrderNotFound")]
     OrderNotFound,
     #[error("InvalidOrderStatus")]
@@ -212,6 +217,7 @@ where
             LiveEvent::Feed { event, .. } => {
                 let instrument = unsafe { self.instruments.get_unchecked_mut(inst_no) };
                 instrument.last_feed_latency = Some((event.exch_ts, event.local_ts));
+                let local_ts = event.local_ts;
                 if event.is(LOCAL_BID_DEPTH_EVENT) {
                     instrument
                         .depth
@@ -225,6 +231,7 @@ where
                 {
                     instrument.last_trades.push(event);
                 }
+                instrument.mid_history.update(&instrument.depth, local_ts);
                 if WAIT_NEXT_FEED {
                     return Ok(ElapseResult::MarketFeed);
                 }
@@ -240,11 +247,14 @@ where
                     _ => false,
                 };
                 let instrument = unsafe { self.instruments.get_unchecked_mut(inst_no) };
-                instrument.last_order_latency = Some((
-                    order.local_timestamp,
-                    order.exch_timestamp,
-                    Utc::now().timestamp_nanos_opt().unwrap(),
-                ));
+                let recv_ts = Utc::now().timestamp_nanos_opt().unwrap();
+                instrument.last_order_latency =
+                    Some((order.local_timestamp, order.exch_timestamp, recv_ts));
+                if order.exec_qty > 0.0
+                    && (order.status == Status::Filled || order.status == Status::PartiallyFilled)
+                {
+                    instrument.last_fill_ts = Some(recv_ts);
+                }
                 match instrument.orders.entry(order.order_id) {
                     Entry::Occupied(mut entry) => {
                         let ex_order = entry.get_mut();
@@ -392,10 +402,19 @@ where
             exec_price_tick: 0,
             exch_timestamp: 0,
             exec_qty: 0.0,
+            cum_exec_qty: 0.0,
+            cum_exec_notional: 0.0,
+            modify_count: 0,
             // Invalid information
             q: Box::new(()),
             maker: false,
             is_auction: false,
+            cancel_reason: CancelReason::None,
+            canceled_qty: 0.0,
+            reject_reason: RejectReason::None,
+            exch_order_id: 0,
+            max_lifetime: 0,
+            opening_auction: false,
         };
         let order_id = order.order_id;
         instrument.orders.insert(order_id, order.clone());
@@ -433,6 +452,15 @@ where
         self.state_values(asset_no).position
     }
 
+    #[inline]
+    fn time_since_last_fill(&self, asset_no: usize, now: i64) -> Option<i64> {
+        self.instruments
+            .get(asset_no)
+            .unwrap()
+            .last_fill_ts
+            .map(|last_fill_ts| now - last_fill_ts)
+    }
+
     #[inline]
     fn state_values(&self, asset_no: usize) -> &StateValues {
         // todo: implement the missing fields. Trade values need to be changed to a rolling manner,
@@ -440,6 +468,17 @@ where
         &self.instruments.get(asset_no).unwrap().state
     }
 
+    fn asset_params(&self, asset_no: usize) -> AssetParams {
+        let instrument = self.instruments.get(asset_no).unwrap();
+        AssetParams {
+            tick_size: instrument.tick_size,
+            lot_size: instrument.lot_size,
+            contract_size: 1.0,
+            maker_fee: 0.0,
+            taker_fee: 0.0,
+        }
+    }
+
     #[inline]
     fn depth(&self, asset_no: usize) -> &MD {
         &self.instruments.get(asset_no).unwrap().depth
@@ -454,6 +493,15 @@ where
             .as_slice()
     }
 
+    #[inline]
+    fn mid_history(&self, asset_no: usize, window: i64) -> Vec<(i64, f64)> {
+        self.instruments
+            .get(asset_no)
+            .unwrap()
+            .mid_history
+            .since(self.current_timestamp().saturating_sub(window))
+    }
+
     fn clear_last_trades(&mut self, asset_no: Option<usize>) {
         match asset_no {
             Some(asset_no) => {
@@ -544,6 +592,19 @@ where
         )
     }
 
+    fn submit_venue_order(
+        &mut self,
+        asset_no: usize,
+        venue: usize,
+        order: OrderRequest,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error> {
+        if venue != 0 {
+            return Err(BotError::VenueNotFound);
+        }
+        Bot::submit_order(self, asset_no, order, wait)
+    }
+
     #[inline]
     fn modify(
         &mut self,
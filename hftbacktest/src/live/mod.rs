@@ -4,6 +4,7 @@ pub use bot::{BotError, LiveBot, LiveBotBuilder};
 pub use recorder::LoggingRecorder;
 
 use crate::{
+    depth::MidHistory,
     prelude::StateValues,
     types::{Event, Order, OrderId},
 };
@@ -20,9 +21,11 @@ pub struct Instrument<MD> {
     lot_size: f64,
     depth: MD,
     last_trades: Vec<Event>,
+    mid_history: MidHistory,
     orders: HashMap<OrderId, Order>,
     last_feed_latency: Option<(i64, i64)>,
     last_order_latency: Option<(i64, i64, i64)>,
+    last_fill_ts: Option<i64>,
     state: StateValues,
 }
 
@@ -49,10 +52,21 @@ impl<MD> Instrument<MD> {
             lot_size,
             depth,
             last_trades: Vec::with_capacity(last_trades_capacity),
+            mid_history: Default::default(),
             orders: Default::default(),
             last_feed_latency: None,
             last_order_latency: None,
+            last_fill_ts: None,
             state: Default::default(),
         }
     }
+
+    /// Sets the number of the most recent mid prices kept, which are sampled on each change of the
+    /// best bid or the best ask. The default value is `0`, indicating that no mid price is kept.
+    pub fn mid_history_capacity(self, capacity: usize) -> Self {
+        Self {
+            mid_history: MidHistory::new(capacity),
+            ..self
+        }
+    }
 }
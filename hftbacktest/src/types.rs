@@ -181,6 +181,12 @@ pub const MODIFY_ORDER_EVENT: u64 = 12;
 /// Indicates that an order in the order book has been filled.
 pub const FILL_EVENT: u64 = 13;
 
+/// Indicates that the venue halts trading, for example, by a circuit breaker.
+pub const HALT_EVENT: u64 = 14;
+
+/// Indicates that the venue resumes trading after a halt.
+pub const RESUME_EVENT: u64 = 15;
+
 /// Indicates that it is a valid event to be handled by the exchange processor at the exchange
 /// timestamp.
 pub const EXCH_EVENT: u64 = 1 << 31;
@@ -298,6 +304,12 @@ pub const EXCH_MODIFY_ORDER_EVENT: u64 = EXCH_EVENT | MODIFY_ORDER_EVENT;
 /// Represents a combination of [`EXCH_EVENT`] and [`FILL_EVENT`].
 pub const EXCH_FILL_EVENT: u64 = EXCH_EVENT | FILL_EVENT;
 
+/// Represents a combination of [`EXCH_EVENT`] and [`HALT_EVENT`].
+pub const EXCH_HALT_EVENT: u64 = EXCH_EVENT | HALT_EVENT;
+
+/// Represents a combination of [`EXCH_EVENT`] and [`RESUME_EVENT`].
+pub const EXCH_RESUME_EVENT: u64 = EXCH_EVENT | RESUME_EVENT;
+
 /// Indicates that one should continue until the end of the data.
 pub const UNTIL_END_OF_DATA: i64 = i64::MAX;
 
@@ -353,7 +365,7 @@ impl Event {
 
 /// Represents a side, which can refer to either the side of an order or the initiator's side in a
 /// trade event, with the meaning varying depending on the context.
-#[derive(Clone, Copy, Eq, PartialEq, Debug, Decode, Encode)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Decode, Encode)]
 #[repr(i8)]
 pub enum Side {
     /// In the market depth event, this indicates the bid side; in the market trade event, it
@@ -408,6 +420,55 @@ pub enum Status {
     Unsupported = 255,
 }
 
+/// The reason why the exchange canceled the order quantity without the user's request.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Decode, Encode)]
+#[repr(u8)]
+pub enum CancelReason {
+    /// The order quantity is not canceled by the exchange, or it is canceled by the user's
+    /// request.
+    None = 0,
+    /// The order quantity is canceled by the self-trade prevention.
+    SelfTradePrevention = 1,
+    /// The order is canceled by the exchange because trading is halted.
+    TradingHalt = 2,
+    /// The order is canceled by the exchange because it has rested in the order book for its
+    /// maximum lifetime. See [`Order::max_lifetime`].
+    MaxLifetime = 3,
+    /// The residual quantity of a partially filled order is canceled by the exchange because it
+    /// falls below the lot size.
+    SubLotResidual = 4,
+}
+
+/// The reason why the exchange rejected the request.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Decode, Encode)]
+#[repr(u8)]
+pub enum RejectReason {
+    /// The request is not rejected, or the exchange doesn't provide the reason.
+    None = 0,
+    /// The order to cancel or modify has already been filled.
+    AlreadyFilled = 1,
+    /// The order to cancel or modify is unknown to the exchange, for example, it has never existed
+    /// or it has already been canceled or expired.
+    UnknownOrder = 2,
+    /// The modify request improves the order price by less than the minimum price improvement
+    /// that the exchange requires.
+    InsufficientPriceImprovement = 3,
+    /// The exchange doesn't accept the order at this time, for example, a continuous-trading
+    /// order submitted before the session opens.
+    OrderEntryNotAllowed = 4,
+    /// The exchange doesn't accept new orders or modifications while trading is halted.
+    TradingHalted = 5,
+    /// The exchange doesn't accept new orders during the warmup, until both sides of the order
+    /// book have at least one level.
+    BookNotEstablished = 6,
+    /// The limit order meant to rest would lock or cross the market, which the exchange doesn't
+    /// allow.
+    WouldLockMarket = 7,
+    /// The modify request sets the order quantity below the quantity that has already been
+    /// filled.
+    QtyBelowFilled = 8,
+}
+
 /// Time In Force
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Decode, Encode)]
 #[repr(u8)]
@@ -443,6 +504,9 @@ impl AsRef<str> for TimeInForce {
 pub enum OrdType {
     Limit = 0,
     Market = 1,
+    /// A market order that rests in the exchange until its trigger reference price reaches the
+    /// order price, which is used as the stop price.
+    StopMarket = 2,
     Unsupported = 255,
 }
 
@@ -451,6 +515,7 @@ impl AsRef<str> for OrdType {
         match self {
             OrdType::Limit => "LIMIT",
             OrdType::Market => "MARKET",
+            OrdType::StopMarket => "STOP_MARKET",
             OrdType::Unsupported => panic!("OrdType::Unsupported"),
         }
     }
@@ -496,10 +561,10 @@ pub struct Order {
     /// The quantity of this order that has not yet been executed. It represents the remaining
     /// quantity that is still open or active in the market after any partial fills.
     pub leaves_qty: f64,
-    /// Executed quantity, only available when this order is executed.
+    /// Executed quantity of the latest fill, only available when this order is executed.
     pub exec_qty: f64,
-    /// Executed price in ticks (`executed_price / tick_size`), only available when this order is
-    /// executed.
+    /// Executed price in ticks (`executed_price / tick_size`) of the latest fill, only available
+    /// when this order is executed.
     pub exec_price_tick: i64,
     /// Order price in ticks (`price / tick_size`).
     pub price_tick: i64,
@@ -525,6 +590,36 @@ pub struct Order {
     pub side: Side,
     pub time_in_force: TimeInForce,
     pub is_auction: bool,
+    /// The reason why the exchange canceled the order quantity, only available when the response
+    /// is caused by the exchange-side cancellation.
+    pub cancel_reason: CancelReason,
+    /// The quantity canceled by the exchange, only available when
+    /// [`cancel_reason`](Order::cancel_reason) is not [`CancelReason::None`].
+    pub canceled_qty: f64,
+    /// The reason why the exchange rejected the request, only available when
+    /// [`req`](Order::req) is [`Status::Rejected`].
+    pub reject_reason: RejectReason,
+    /// The order ID assigned by the exchange, which changes when the exchange replaces the order
+    /// by a cancel and a new order. `0` if the exchange doesn't assign it.
+    pub exch_order_id: u64,
+    /// The maximum time in nanoseconds that the order can rest in the order book, measured from
+    /// the time it rests, after which the exchange cancels it with
+    /// [`CancelReason::MaxLifetime`]. Unlike an absolute expiry, it doesn't depend on when the
+    /// order is submitted or how long it takes to reach the exchange. `0` if the order has no
+    /// maximum lifetime. This is only available in backtesting.
+    pub max_lifetime: i64,
+    /// Whether the order is entered to participate in the opening auction. Before the session
+    /// opens, the exchange accepts only such orders, which must be GTC limit orders. This is only
+    /// available in backtesting with an L3 asset; the L2 local rejects an order with it set as an
+    /// [invalid request](crate::backtest::BacktestError::InvalidOrderRequest).
+    pub opening_auction: bool,
+    /// The total executed quantity over all fills of this order.
+    pub cum_exec_qty: f64,
+    /// The total executed notional, the sum of the executed price times the executed quantity,
+    /// over all fills of this order.
+    pub cum_exec_notional: f64,
+    /// The number of times the exchange has accepted a modification of this order.
+    pub modify_count: u32,
 }
 
 impl Order {
@@ -556,6 +651,15 @@ impl Order {
             maker: false,
             order_type,
             is_auction: false,
+            cancel_reason: CancelReason::None,
+            canceled_qty: 0.0,
+            reject_reason: RejectReason::None,
+            exch_order_id: 0,
+            max_lifetime: 0,
+            opening_auction: false,
+            cum_exec_qty: 0.0,
+            cum_exec_notional: 0.0,
+            modify_count: 0,
         }
     }
 
@@ -569,6 +673,33 @@ impl Order {
         self.exec_price_tick as f64 * self.tick_size
     }
 
+    /// Returns the average executed price over all fills of this order. `NaN` if nothing has been
+    /// executed.
+    pub fn avg_exec_price(&self) -> f64 {
+        if self.cum_exec_qty > 0.0 {
+            self.cum_exec_notional / self.cum_exec_qty
+        } else {
+            f64::NAN
+        }
+    }
+
+    /// Returns the fills executed since the given cumulative executed quantity and notional as a
+    /// single fill, whose executed quantity is the increment and whose executed price is the
+    /// average price of the increment rounded to the nearest tick. `None` if nothing has been
+    /// executed since. This allows a response to be applied exactly once even if it carries
+    /// several fills.
+    pub fn fill_since(&self, cum_exec_qty: f64, cum_exec_notional: f64) -> Option<Order> {
+        let exec_qty = self.cum_exec_qty - cum_exec_qty;
+        if exec_qty <= 0.0 {
+            return None;
+        }
+        let exec_price = (self.cum_exec_notional - cum_exec_notional) / exec_qty;
+        let mut fill = self.clone();
+        fill.exec_qty = exec_qty;
+        fill.exec_price_tick = (exec_price / self.tick_size).round() as i64;
+        Some(fill)
+    }
+
     /// Returns whether this order is cancelable.
     pub fn cancellable(&self) -> bool {
         (self.status == Status::New || self.status == Status::PartiallyFilled)
@@ -618,11 +749,18 @@ impl Order {
         self.req = order.req;
         self.exec_price_tick = order.exec_price_tick;
         self.exec_qty = order.exec_qty;
+        self.cum_exec_qty = order.cum_exec_qty;
+        self.cum_exec_notional = order.cum_exec_notional;
+        self.modify_count = order.modify_count;
         self.order_id = order.order_id;
         self.q = order.q.clone();
         self.maker = order.maker;
         self.order_type = order.order_type;
         self.is_auction = order.is_auction;
+        self.cancel_reason = order.cancel_reason;
+        self.canceled_qty = order.canceled_qty;
+        self.reject_reason = order.reject_reason;
+        self.exch_order_id = order.exch_order_id;
     }
 }
 
@@ -645,6 +783,15 @@ impl Debug for Order {
             .field("maker", &self.maker)
             .field("order_type", &self.order_type)
             .field("is_auction", &self.is_auction)
+            .field("cancel_reason", &self.cancel_reason)
+            .field("canceled_qty", &self.canceled_qty)
+            .field("reject_reason", &self.reject_reason)
+            .field("exch_order_id", &self.exch_order_id)
+            .field("max_lifetime", &self.max_lifetime)
+            .field("opening_auction", &self.opening_auction)
+            .field("cum_exec_qty", &self.cum_exec_qty)
+            .field("cum_exec_notional", &self.cum_exec_notional)
+            .field("modify_count", &self.modify_count)
             .finish()
     }
 }
@@ -670,6 +817,16 @@ impl<Context> Decode<Context> for Order {
             side: Decode::decode(decoder)?,
             time_in_force: Decode::decode(decoder)?,
             is_auction: Decode::decode(decoder)?,
+            cancel_reason: Decode::decode(decoder)?,
+            canceled_qty: Decode::decode(decoder)?,
+            reject_reason: Decode::decode(decoder)?,
+            exch_order_id: Decode::decode(decoder)?,
+            // In a live bot, max_lifetime and opening_auction aren't used.
+            max_lifetime: 0,
+            opening_auction: false,
+            cum_exec_qty: Decode::decode(decoder)?,
+            cum_exec_notional: Decode::decode(decoder)?,
+            modify_count: Decode::decode(decoder)?,
         })
     }
 }
@@ -695,6 +852,16 @@ impl<'de, Context> BorrowDecode<'de, Context> for Order {
             side: Decode::decode(decoder)?,
             time_in_force: Decode::decode(decoder)?,
             is_auction: Decode::decode(decoder)?,
+            cancel_reason: Decode::decode(decoder)?,
+            canceled_qty: Decode::decode(decoder)?,
+            reject_reason: Decode::decode(decoder)?,
+            exch_order_id: Decode::decode(decoder)?,
+            // In a live bot, max_lifetime and opening_auction aren't used.
+            max_lifetime: 0,
+            opening_auction: false,
+            cum_exec_qty: Decode::decode(decoder)?,
+            cum_exec_notional: Decode::decode(decoder)?,
+            modify_count: Decode::decode(decoder)?,
         })
     }
 }
@@ -718,6 +885,14 @@ impl Encode for Order {
         self.side.encode(encoder)?;
         self.time_in_force.encode(encoder)?;
         self.is_auction.encode(encoder)?;
+        self.cancel_reason.encode(encoder)?;
+        self.canceled_qty.encode(encoder)?;
+        self.reject_reason.encode(encoder)?;
+        self.exch_order_id.encode(encoder)?;
+        // In a live bot, max_lifetime and opening_auction aren't used.
+        self.cum_exec_qty.encode(encoder)?;
+        self.cum_exec_notional.encode(encoder)?;
+        self.modify_count.encode(encoder)?;
         Ok(())
     }
 }
@@ -755,6 +930,30 @@ pub struct StateValues {
     pub trading_volume: f64,
     /// Backtest only
     pub trading_value: f64,
+    /// The profit and loss realized by closing the position, excluding fees. Backtest only
+    pub realized_pnl: f64,
+    /// The profit and loss of the held position marked to the latest mid price, excluding fees.
+    /// Backtest only
+    pub unrealized_pnl: f64,
+    /// The quantity-weighted average price of the fills that opened the held position. `0` if no
+    /// position is held. Backtest only
+    pub avg_entry_price: f64,
+}
+
+/// Provides the configured parameters of an asset.
+///
+/// **Note:** In a live bot, only `tick_size` and `lot_size` are configured, and the other values
+/// are the defaults.
+#[derive(PartialEq, Clone, Debug)]
+pub struct AssetParams {
+    pub tick_size: f64,
+    pub lot_size: f64,
+    /// The contract size by which the value amount is multiplied. Backtest only
+    pub contract_size: f64,
+    /// The fee rate for adding liquidity. Backtest only
+    pub maker_fee: f64,
+    /// The fee rate for removing liquidity. Backtest only
+    pub taker_fee: f64,
 }
 
 /// Provides errors that can occur in builders.
@@ -781,6 +980,11 @@ pub struct OrderRequest {
     pub side: Side,
     pub time_in_force: TimeInForce,
     pub order_type: OrdType,
+    /// The maximum time in nanoseconds that the order can rest in the order book. `0` if the
+    /// order has no maximum lifetime. See [`Order::max_lifetime`].
+    pub max_lifetime: i64,
+    /// Whether the order participates in the opening auction. See [`Order::opening_auction`].
+    pub opening_auction: bool,
 }
 
 /// Provides a bot interface for backtesting and live trading.
@@ -797,31 +1001,103 @@ where
     /// Returns the number of assets.
     fn num_assets(&self) -> usize;
 
-    /// Returns the position you currently hold.
+    /// Returns the number of venues on which the asset is traded. Venue `0` is the primary venue,
+    /// on which the methods without a venue selector place orders.
+    ///
+    /// * `asset_no` - Asset number from which the number of venues will be retrieved.
+    fn num_venues(&self, asset_no: usize) -> usize {
+        let _ = asset_no;
+        1
+    }
+
+    /// Returns the position you currently hold, consolidated across all venues of the asset.
     ///
     /// * `asset_no` - Asset number from which the position will be retrieved.
     fn position(&self, asset_no: usize) -> f64;
 
-    /// Returns the state's values such as balance, fee, and so on.
+    /// Returns the inventory skew, which is the deviation of the position from the target
+    /// position normalized by the maximum position and clamped to `[-1, 1]`. A positive skew
+    /// means the position is long relative to the target, so that the quotes can be biased to
+    /// reduce it.
+    ///
+    /// * `asset_no` - Asset number from which the position will be retrieved.
+    /// * `target_position` - The position that the strategy aims to hold.
+    /// * `max_position` - The deviation from the target at which the skew saturates. If it is not
+    ///   positive, no deviation can be normalized and the skew is `0`.
+    fn inventory_skew(&self, asset_no: usize, target_position: f64, max_position: f64) -> f64 {
+        if max_position <= 0.0 {
+            return 0.0;
+        }
+        ((self.position(asset_no) - target_position) / max_position).clamp(-1.0, 1.0)
+    }
+
+    /// Returns the time elapsed from the receipt of the most recent fill to `now`, which can be
+    /// used to detect an inactive or stale position. Returns `None` if no order has been filled
+    /// yet.
+    ///
+    /// * `asset_no` - Asset number from which the last fill will be retrieved.
+    /// * `now` - The timestamp to measure to, usually [`Bot::current_timestamp`].
+    ///
+    /// The default implementation doesn't track fills and always returns `None`.
+    fn time_since_last_fill(&self, asset_no: usize, now: i64) -> Option<i64> {
+        let _ = (asset_no, now);
+        None
+    }
+
+    /// Returns the state's values such as balance, fee, and so on, on the primary venue of the
+    /// asset.
     fn state_values(&self, asset_no: usize) -> &StateValues;
 
-    /// Returns the [`MarketDepth`].
+    /// Returns the configured parameters of the asset, such as the tick size, the lot size, the
+    /// contract size, and the fee rates.
+    ///
+    /// * `asset_no` - Asset number from which the parameters will be retrieved.
+    ///
+    /// The default implementation takes the tick size and the lot size from the market depth,
+    /// with a contract size of `1.0` and no fees.
+    fn asset_params(&self, asset_no: usize) -> AssetParams {
+        let depth = self.depth(asset_no);
+        AssetParams {
+            tick_size: depth.tick_size(),
+            lot_size: depth.lot_size(),
+            contract_size: 1.0,
+            maker_fee: 0.0,
+            taker_fee: 0.0,
+        }
+    }
+
+    /// Returns the [`MarketDepth`] of the primary venue of the asset.
     ///
     /// * `asset_no` - Asset number from which the market depth will be retrieved.
     fn depth(&self, asset_no: usize) -> &MD;
 
-    /// Returns the last market trades.
+    /// Returns the last market trades on the primary venue of the asset.
     ///
     /// * `asset_no` - Asset number from which the last market trades will be retrieved.
     fn last_trades(&self, asset_no: usize) -> &[Event];
 
-    /// Clears the last market trades from the buffer.
+    /// Clears the last market trades from the buffer, on all venues of the asset.
     ///
     /// * `asset_no` - Asset number at which this command will be executed. If `None`, all last
     ///   trades in any assets will be cleared.
     fn clear_last_trades(&mut self, asset_no: Option<usize>);
 
-    /// Returns a hash map of order IDs and their corresponding [`Order`]s.
+    /// Returns the mid prices sampled on each change of the best bid or the best ask within the
+    /// last `window` nanoseconds up to the current timestamp, as `(timestamp, mid)` from the
+    /// oldest. Only up to the configured capacity of the most recent samples are kept.
+    ///
+    /// * `asset_no` - Asset number from which the mid price history will be retrieved.
+    /// * `window` - Lookback window. Nanoseconds is the default unit. However, unit should be the
+    ///   same as the data's timestamp unit.
+    ///
+    /// The default implementation doesn't record the history and returns an empty vector.
+    fn mid_history(&self, asset_no: usize, window: i64) -> Vec<(i64, f64)> {
+        let _ = (asset_no, window);
+        Vec::new()
+    }
+
+    /// Returns a hash map of order IDs and their corresponding [`Order`]s placed on the primary
+    /// venue of the asset.
     ///
     /// * `asset_no` - Asset number from which orders will be retrieved.
     fn orders(&self, asset_no: usize) -> &HashMap<OrderId, Order>;
@@ -886,7 +1162,26 @@ where
         wait: bool,
     ) -> Result<ElapseResult, Self::Error>;
 
-    /// Modifies an open order.
+    /// Places an order on the given venue of the asset, to route orders across the venues on
+    /// which the same instrument is traded. The order ID must be unique across all venues of the
+    /// asset.
+    ///
+    /// * `asset_no` - Asset number at which this command will be executed.
+    /// * `venue` - Venue number at which the order will be placed.
+    /// * `order` - The order to place.
+    /// * `wait` - If true, wait until the order placement response is received.
+    ///
+    /// An implementation that supports only the primary venue should return an error if `venue`
+    /// is not `0`.
+    fn submit_venue_order(
+        &mut self,
+        asset_no: usize,
+        venue: usize,
+        order: OrderRequest,
+        wait: bool,
+    ) -> Result<ElapseResult, Self::Error>;
+
+    /// Modifies an open order, on the venue on which the order is placed.
     ///
     /// * `asset_no` - Asset number at which this command will be executed.
     /// * `order_id` - Order ID to modify.
@@ -902,7 +1197,7 @@ where
         wait: bool,
     ) -> Result<ElapseResult, Self::Error>;
 
-    /// Cancels an open order.
+    /// Cancels an open order, on the venue on which the order is placed.
     ///
     /// * `asset_no` - Asset number at which this command will be executed.
     /// * `order_id` - Order ID to cancel.
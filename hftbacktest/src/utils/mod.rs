@@ -16,3 +16,26 @@ pub fn get_precision(tick_size: f64) -> usize {
     }
     prec
 }
+
+/// Formats the price with the number of decimals that the tick size needs, for example, two
+/// decimals for a tick size of `0.01` and three for `0.005`. See [`get_precision`].
+pub fn format_price(tick_size: f64, price: f64) -> String {
+    format!("{:.*}", get_precision(tick_size), price)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::format_price;
+
+    #[test]
+    fn test_format_price() {
+        assert_eq!(format_price(1.0, 101.0), "101");
+        assert_eq!(format_price(0.5, 101.5), "101.5");
+        assert_eq!(format_price(0.01, 343.8), "343.80");
+        assert_eq!(format_price(0.005, 12.345), "12.345");
+        assert_eq!(format_price(0.005, 12.35), "12.350");
+        assert_eq!(format_price(0.0001, 1.2345), "1.2345");
+        assert_eq!(format_price(0.0001, 1.1), "1.1000");
+        assert_eq!(format_price(25.0, 4150.0), "4150");
+    }
+}
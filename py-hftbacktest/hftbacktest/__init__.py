@@ -29,6 +29,7 @@ from .order import (
     GTX,
     LIMIT,
     MARKET,
+    STOP_MARKET,
 )
 from .recorder import Recorder
 from .types import (
@@ -108,6 +109,7 @@ __all__ = (
 
     'LIMIT',
     'MARKET',
+    'STOP_MARKET',
     
     'Recorder'
 )
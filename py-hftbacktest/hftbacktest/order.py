@@ -59,6 +59,9 @@ LIMIT = 0
 #: MARKET
 MARKET = 1
 
+#: STOP_MARKET
+STOP_MARKET = 2
+
 
 class Order:
     arr: from_dtype(order_dtype)[:]
@@ -55,5 +55,27 @@ class StateValues:
         """
         return self.arr[0].trading_value
 
+    @property
+    def realized_pnl(self) -> float64:
+        """
+        Returns the profit and loss realized by closing the position, excluding fees.
+        """
+        return self.arr[0].realized_pnl
+
+    @property
+    def unrealized_pnl(self) -> float64:
+        """
+        Returns the profit and loss of the open position marked to the latest mid price, excluding
+        fees.
+        """
+        return self.arr[0].unrealized_pnl
+
+    @property
+    def avg_entry_price(self) -> float64:
+        """
+        Returns the average entry price of the open position, or 0 if no position is open.
+        """
+        return self.arr[0].avg_entry_price
+
 
 StateValues_ = jitclass(StateValues)
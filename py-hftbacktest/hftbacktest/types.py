@@ -66,7 +66,10 @@ state_values_dtype = np.dtype(
         ('fee', 'f8'),
         ('num_trades', 'i8'),
         ('trading_volume', 'f8'),
-        ('trading_value', 'f8')
+        ('trading_value', 'f8'),
+        ('realized_pnl', 'f8'),
+        ('unrealized_pnl', 'f8'),
+        ('avg_entry_price', 'f8')
     ],
     align=True
 )
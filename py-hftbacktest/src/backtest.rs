@@ -28,6 +28,10 @@ fn handle_result(result: Result<ElapseResult, BacktestError>) -> i64 {
             println!("BacktestError::DataError: {error:?}");
             100
         },
+        Err(error) => {
+            println!("BacktestError: {error}");
+            16
+        },
     }
 }
 
@@ -82,6 +86,7 @@ pub extern "C" fn hashmapbt_close(hbt_ptr: *mut HashMapMarketDepthBacktest) -> i
         Err(BacktestError::InvalidOrderStatus) => 14,
         Err(BacktestError::EndOfData) => 15,
         Err(BacktestError::DataError(_)) => 100,
+        Err(_) => 16,
     }
 }
 
@@ -341,6 +346,7 @@ pub extern "C" fn roivecbt_close(hbt_ptr: *mut ROIVectorMarketDepthBacktest) ->
         Err(BacktestError::InvalidOrderStatus) => 14,
         Err(BacktestError::EndOfData) => 15,
         Err(BacktestError::DataError(_)) => 100,
+        Err(_) => 16,
     }
 }
 